@@ -0,0 +1,55 @@
+//! Compares the heuristic token estimate with the BPE tokenizers on prose, code and JSON, and
+//! measures how long counting takes with each of them.
+//!
+//! Run with `cargo bench -p cli --features bpe-tokenizer --bench token_counter`.
+
+use std::hint::black_box;
+
+use cli::cli::chat::token_counter::Tokenizer;
+use criterion::{
+    Criterion,
+    criterion_group,
+    criterion_main,
+};
+
+const SAMPLES: &[(&str, &str)] = &[
+    ("prose", include_str!("../../../README.md")),
+    ("code", include_str!("../src/cli/chat/conversation_state.rs")),
+    ("json", include_str!("../telemetry_definitions.json")),
+];
+
+const ENCODINGS: [Tokenizer; 2] = [Tokenizer::Cl100k, Tokenizer::O200k];
+
+/// Prints the error of the heuristic estimate relative to the tokens counted by each encoding.
+fn report_estimate_error() {
+    println!(
+        "{:<8} {:<10} {:>10} {:>10} {:>8}",
+        "sample", "encoding", "tokens", "estimate", "error"
+    );
+    for (name, text) in SAMPLES {
+        let estimate = Tokenizer::Heuristic.count(text);
+        for tokenizer in ENCODINGS {
+            let tokens = tokenizer.count(text);
+            let error = (estimate as f64 - tokens as f64) / tokens as f64 * 100.0;
+            let tokenizer = format!("{tokenizer:?}");
+            println!("{name:<8} {tokenizer:<10} {tokens:>10} {estimate:>10} {error:>+7.1}%");
+        }
+    }
+}
+
+fn bench_count(c: &mut Criterion) {
+    report_estimate_error();
+
+    let mut group = c.benchmark_group("count");
+    for (name, text) in SAMPLES {
+        for tokenizer in [Tokenizer::Heuristic].into_iter().chain(ENCODINGS) {
+            group.bench_function(format!("{name}/{tokenizer:?}"), |b| {
+                b.iter(|| tokenizer.count(black_box(text)));
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_count);
+criterion_main!(benches);
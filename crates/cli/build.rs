@@ -41,6 +41,14 @@ struct Def {
 
 fn main() {
     println!("cargo:rerun-if-changed=def.json");
+    println!("cargo:rerun-if-changed=proto");
+
+    // The gRPC interface of `q server`
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+    tonic_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/chat.proto"], &["proto"])
+        .unwrap();
 
     let outdir = std::env::var("OUT_DIR").unwrap();
 
@@ -0,0 +1,419 @@
+//! Provider adapters of [`OpenAiBackend`](super::OpenAiBackend), which normalize how the models
+//! behind OpenAI-compatible endpoints use tools.
+//!
+//! Most providers take the tools of a request natively and answer with `tool_calls`. The
+//! [PromptedTools] adapter covers the others: the tools are described in a system message asking
+//! the model to answer with `<tool_call>` blocks, which [InlineToolCalls] turns back into tool
+//! uses, and the tool uses and results of the history are sent as text, so the agent loop works the
+//! same with either.
+//!
+//! Local models which ignore the tools of the request also tend to write their calls in the
+//! response, as fenced JSON or in the tags of their chat template, so once `chat.toolCallPatterns`
+//! is set, its patterns are looked for in the responses of the other providers too.
+
+use serde_json::{
+    Value,
+    json,
+};
+use tracing::warn;
+
+use crate::api_client::model::ToolSpecification;
+use crate::cli::chat::model_capabilities::ModelCapabilities;
+use crate::cli::chat::openai_config::OpenAiConfig;
+use crate::database::Database;
+use crate::database::settings::Setting;
+
+/// The patterns of `chat.toolCallPatterns` for [PromptedTools] providers when it isn't set.
+const DEFAULT_TOOL_CALL_PATTERNS: &[&str] = &["<tool_call>", "<function_call>", "```json"];
+
+pub const TOOL_CALL_START: &str = "<tool_call>";
+pub const TOOL_CALL_END: &str = "</tool_call>";
+
+pub trait ProviderAdapter: std::fmt::Debug + Send + Sync {
+    /// Provider specific guidance, sent as a system message before the conversation.
+    fn system_guidance(&self, _tools: &[ToolSpecification]) -> Option<String> {
+        None
+    }
+
+    /// Whether the tools are sent in the `tools` field of the request.
+    fn native_tools(&self) -> bool {
+        true
+    }
+
+    /// Whether tool uses are written as `<tool_call>` blocks in the text of the messages.
+    fn inline_tool_calls(&self) -> bool {
+        false
+    }
+}
+
+/// Providers with native tool support.
+#[derive(Debug)]
+pub struct NativeTools;
+
+impl ProviderAdapter for NativeTools {}
+
+/// Providers without tool support, or whose tool support doesn't work with the requests of the
+/// chat, which are prompted to write their tool calls in the response.
+#[derive(Debug)]
+pub struct PromptedTools;
+
+impl ProviderAdapter for PromptedTools {
+    fn system_guidance(&self, tools: &[ToolSpecification]) -> Option<String> {
+        if tools.is_empty() {
+            return None;
+        }
+        let mut guidance = format!(
+            "You can use the tools below. To use one, answer with a block like\n\
+             {TOOL_CALL_START}{{\"name\": \"<tool name>\", \"arguments\": {{<arguments matching the schema of the \
+             tool>}}}}{TOOL_CALL_END}\n\
+             and stop there: the result comes back in a <tool_result> block of the next message. Use several blocks \
+             to use several tools at once, and answer without a block once no tool is needed. Never describe a \
+             tool call instead of making it.\n\nTools:"
+        );
+        for tool in tools {
+            let schema = tool
+                .input_schema
+                .json
+                .as_ref()
+                .and_then(|schema| serde_json::to_string(schema).ok())
+                .unwrap_or_else(|| "{}".to_string());
+            guidance.push_str(&format!(
+                "\n\n## {}\n{}\nSchema: {}",
+                tool.name,
+                tool.description.trim(),
+                schema
+            ));
+        }
+        Some(guidance)
+    }
+
+    fn native_tools(&self) -> bool {
+        false
+    }
+
+    fn inline_tool_calls(&self) -> bool {
+        true
+    }
+}
+
+/// The adapter for the provider and model of `config`.
+pub fn adapter_for(config: &OpenAiConfig, capabilities: &ModelCapabilities) -> Box<dyn ProviderAdapter> {
+    // Kimi-based APIs require a tool_choice with tools, which the other providers don't accept.
+    if !capabilities.supports_tools || config.base_url.contains("xiaomi.srv") {
+        Box::new(PromptedTools)
+    } else {
+        Box::new(NativeTools)
+    }
+}
+
+/// A tool use written as a block, for the history sent to [PromptedTools] providers.
+pub fn tool_call_block(name: &str, arguments: &Value) -> String {
+    format!(
+        "{TOOL_CALL_START}{{\"name\":{},\"arguments\":{arguments}}}{TOOL_CALL_END}",
+        json!(name)
+    )
+}
+
+/// The result of the tool use `id` written as a block.
+pub fn tool_result_block(id: &str, content: &str) -> String {
+    format!("<tool_result id=\"{id}\">\n{content}\n</tool_result>")
+}
+
+/// A way models write tool calls in the text of their responses, from `chat.toolCallPatterns`:
+/// a block between `<tag>` and `</tag>`, or a fenced code block like ```` ```json ````.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolCallPattern {
+    start: String,
+    end: String,
+}
+
+impl ToolCallPattern {
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let pattern = pattern.trim();
+        if pattern.starts_with("```") {
+            return Some(Self {
+                start: pattern.to_string(),
+                end: "```".to_string(),
+            });
+        }
+        let tag = pattern
+            .strip_prefix('<')?
+            .strip_suffix('>')
+            .filter(|tag| !tag.is_empty() && !tag.contains(['<', '>', '/']) && !tag.contains(char::is_whitespace))?;
+        Some(Self {
+            start: format!("<{tag}>"),
+            end: format!("</{tag}>"),
+        })
+    }
+
+    /// The patterns of `chat.toolCallPatterns`, if it is set.
+    pub fn from_database(database: &Database) -> Option<Vec<Self>> {
+        let patterns = database.settings.get_string_list(Setting::ChatToolCallPatterns)?;
+        Some(
+            patterns
+                .iter()
+                .filter_map(|pattern| {
+                    let parsed = Self::parse(pattern);
+                    if parsed.is_none() {
+                        warn!(
+                            "Ignoring invalid tool call pattern '{}' of chat.toolCallPatterns",
+                            pattern
+                        );
+                    }
+                    parsed
+                })
+                .collect(),
+        )
+    }
+
+    pub fn defaults() -> Vec<Self> {
+        DEFAULT_TOOL_CALL_PATTERNS
+            .iter()
+            .filter_map(|pattern| Self::parse(pattern))
+            .collect()
+    }
+
+    /// The blocks [PromptedTools] providers are asked to write.
+    pub fn tool_call() -> Self {
+        Self {
+            start: TOOL_CALL_START.to_string(),
+            end: TOOL_CALL_END.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Text(String),
+    ToolCall { name: String, arguments: String },
+}
+
+/// Splits the streamed text of a response into text and the tool calls written in it, for models
+/// without function calling.
+///
+/// Text which may be the start of a block split between chunks is held back until the next chunk.
+/// Blocks which aren't a call of one of the tools of the request, e.g. a JSON example in a fence,
+/// are kept as text.
+#[derive(Debug)]
+pub struct InlineToolCalls {
+    patterns: Vec<ToolCallPattern>,
+    tool_names: Vec<String>,
+    buffer: String,
+    /// The pattern of the block being read.
+    current: Option<usize>,
+}
+
+impl InlineToolCalls {
+    pub fn new(patterns: Vec<ToolCallPattern>, tool_names: Vec<String>) -> Self {
+        Self {
+            patterns,
+            tool_names,
+            buffer: String::new(),
+            current: None,
+        }
+    }
+
+    pub fn push(&mut self, text: &str) -> Vec<Segment> {
+        self.buffer.push_str(text);
+        let mut segments = Vec::new();
+        loop {
+            match self.current {
+                Some(index) => {
+                    let pattern = self.patterns[index].clone();
+                    let Some(end) = self.buffer.find(&pattern.end) else {
+                        return segments;
+                    };
+                    let content = self.buffer.drain(..end).collect::<String>();
+                    self.buffer.drain(..pattern.end.len());
+                    segments.push(
+                        self.tool_call(&content)
+                            .unwrap_or_else(|| Segment::Text(format!("{}{content}{}", pattern.start, pattern.end))),
+                    );
+                    self.current = None;
+                },
+                None => {
+                    let next = self
+                        .patterns
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(index, pattern)| Some((self.buffer.find(&pattern.start)?, index)))
+                        .min();
+                    let Some((start, index)) = next else {
+                        let keep = self
+                            .patterns
+                            .iter()
+                            .map(|pattern| partial_marker_len(&self.buffer, &pattern.start))
+                            .max()
+                            .unwrap_or_default();
+                        let text = self.buffer.drain(..self.buffer.len() - keep).collect::<String>();
+                        if !text.is_empty() {
+                            segments.push(Segment::Text(text));
+                        }
+                        return segments;
+                    };
+                    let text = self.buffer.drain(..start).collect::<String>();
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(text));
+                    }
+                    self.buffer.drain(..self.patterns[index].start.len());
+                    self.current = Some(index);
+                },
+            }
+        }
+    }
+
+    /// The rest of the response, once it ended. A block the model didn't close is still a tool
+    /// call when it's complete.
+    pub fn finish(&mut self) -> Vec<Segment> {
+        let rest = std::mem::take(&mut self.buffer);
+        if let Some(index) = self.current.take() {
+            let start = &self.patterns[index].start;
+            return vec![
+                self.tool_call(&rest)
+                    .unwrap_or_else(|| Segment::Text(format!("{start}{rest}"))),
+            ];
+        }
+        match rest.is_empty() {
+            true => Vec::new(),
+            false => vec![Segment::Text(rest)],
+        }
+    }
+
+    /// Parses the content of a block, which models sometimes wrap in a code fence or give the
+    /// arguments of as a string.
+    fn tool_call(&self, content: &str) -> Option<Segment> {
+        let content = content.trim();
+        let content = content
+            .strip_prefix("```json")
+            .or_else(|| content.strip_prefix("```"))
+            .map_or(content, |content| content.trim_end_matches('`'));
+        let call = serde_json::from_str::<Value>(content.trim()).ok()?;
+        let name = call.get("name")?.as_str()?.to_string();
+        if !self.tool_names.contains(&name) {
+            return None;
+        }
+        let arguments = match call.get("arguments").or_else(|| call.get("parameters")) {
+            Some(Value::String(arguments)) => arguments.clone(),
+            Some(arguments) => arguments.to_string(),
+            None => "{}".to_string(),
+        };
+        Some(Segment::ToolCall { name, arguments })
+    }
+}
+
+/// Length of the longest start of `marker` that `text` ends with.
+fn partial_marker_len(text: &str, marker: &str) -> usize {
+    (1..marker.len())
+        .rev()
+        .filter(|len| marker.is_char_boundary(*len))
+        .find(|len| text.ends_with(&marker[..*len]))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_client::model::ToolInputSchema;
+
+    fn push_all(chunks: &[&str]) -> Vec<Segment> {
+        let tool_names = vec!["fs_read".to_string(), "execute_bash".to_string()];
+        let mut parser = InlineToolCalls::new(ToolCallPattern::defaults(), tool_names);
+        let mut segments = chunks.iter().flat_map(|chunk| parser.push(chunk)).collect::<Vec<_>>();
+        segments.extend(parser.finish());
+        segments
+    }
+
+    fn text(text: &str) -> Segment {
+        Segment::Text(text.to_string())
+    }
+
+    fn call(name: &str, arguments: &str) -> Segment {
+        Segment::ToolCall {
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_inline_tool_calls() {
+        assert_eq!(
+            push_all(&[
+                "Reading it.\n<tool",
+                "_call>{\"name\": \"fs_read\", \"argu",
+                "ments\": {\"path\": \"a\"}}</tool_call>"
+            ]),
+            vec![text("Reading it.\n"), call("fs_read", "{\"path\":\"a\"}")]
+        );
+        assert_eq!(
+            push_all(&[
+                "<tool_call>\n```json\n{\"name\": \"execute_bash\", \"arguments\": \"{\\\"command\\\": \\\"ls\\\"}\"}\n```\n</tool_call>"
+            ]),
+            vec![call("execute_bash", "{\"command\": \"ls\"}")]
+        );
+        // Unclosed at the end of the response.
+        assert_eq!(
+            push_all(&["<tool_call>{\"name\": \"fs_read\", \"arguments\": {}}"]),
+            vec![call("fs_read", "{}")]
+        );
+    }
+
+    #[test]
+    fn test_inline_text_is_kept() {
+        assert_eq!(push_all(&["a < b", " and <tool"]), vec![
+            text("a < b"),
+            text(" and "),
+            text("<tool")
+        ]);
+        assert_eq!(push_all(&["<tool_call>not json</tool_call> done"]), vec![
+            text("<tool_call>not json</tool_call>"),
+            text(" done")
+        ]);
+        // Not one of the tools.
+        assert_eq!(
+            push_all(&["```json\n{\"name\": \"Ann\", \"arguments\": []}\n```"]),
+            vec![text("```json\n{\"name\": \"Ann\", \"arguments\": []}\n```")]
+        );
+    }
+
+    #[test]
+    fn test_fallback_patterns() {
+        assert_eq!(
+            push_all(&[
+                "Let me check.\n```js",
+                "on\n{\"name\": \"execute_bash\", \"parameters\": {\"command\": \"ls\"}}\n``",
+                "`\nThen <function_call>{\"name\": \"fs_read\"}</function_call>"
+            ]),
+            vec![
+                text("Let me check.\n"),
+                call("execute_bash", "{\"command\":\"ls\"}"),
+                text("\nThen "),
+                call("fs_read", "{}")
+            ]
+        );
+        assert_eq!(
+            ToolCallPattern::parse(" <invoke> "),
+            Some(ToolCallPattern {
+                start: "<invoke>".to_string(),
+                end: "</invoke>".to_string(),
+            })
+        );
+        assert_eq!(ToolCallPattern::parse("<a b>"), None);
+        assert_eq!(ToolCallPattern::parse("json"), None);
+    }
+
+    #[test]
+    fn test_system_guidance() {
+        let tool = ToolSpecification {
+            name: "fs_read".to_string(),
+            description: "Reads a file.".to_string(),
+            input_schema: ToolInputSchema {
+                json: Some(serde_json::from_value(json!({"type": "object"})).unwrap()),
+            },
+        };
+        let guidance = PromptedTools.system_guidance(&[tool]).unwrap();
+        assert!(guidance.contains("<tool_call>{\"name\": \"<tool name>\""));
+        assert!(guidance.contains("## fs_read\nReads a file.\nSchema: {\"type\":\"object\"}"));
+        assert_eq!(PromptedTools.system_guidance(&[]), None);
+        assert_eq!(NativeTools.system_guidance(&[]), None);
+    }
+}
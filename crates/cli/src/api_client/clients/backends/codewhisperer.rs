@@ -0,0 +1,115 @@
+use amzn_codewhisperer_streaming_client::Client as CodewhispererStreamingClient;
+use amzn_codewhisperer_streaming_client::types::{
+    ChatMessage as CodewhispererChatMessage,
+    ChatTriggerType,
+    ConversationState as CodewhispererConversationState,
+};
+use tracing::{
+    debug,
+    warn,
+};
+
+use super::ChatProviderBackend;
+use crate::api_client::ApiClientError;
+use crate::api_client::clients::SendMessageOutput;
+use crate::api_client::model::ConversationState;
+use crate::auth::builder_id::BuilderIdToken;
+use crate::database::{
+    AuthProfile,
+    Database,
+};
+
+#[derive(Debug)]
+pub struct CodewhispererBackend {
+    client: CodewhispererStreamingClient,
+    profile: Option<AuthProfile>,
+}
+
+impl CodewhispererBackend {
+    pub fn new(client: CodewhispererStreamingClient, profile: Option<AuthProfile>) -> Self {
+        Self { client, profile }
+    }
+}
+
+#[async_trait::async_trait]
+impl ChatProviderBackend for CodewhispererBackend {
+    async fn send(&self, conversation_state: ConversationState) -> Result<SendMessageOutput, ApiClientError> {
+        let ConversationState {
+            conversation_id,
+            user_input_message,
+            history,
+            ..
+        } = conversation_state;
+
+        let conversation_state = CodewhispererConversationState::builder()
+            .set_conversation_id(conversation_id)
+            .current_message(CodewhispererChatMessage::UserInputMessage(user_input_message.into()))
+            .chat_trigger_type(ChatTriggerType::Manual)
+            .set_history(
+                history
+                    .map(|v| v.into_iter().map(|i| i.try_into()).collect::<Result<Vec<_>, _>>())
+                    .transpose()?,
+            )
+            .build()
+            .expect("building conversation_state should not fail");
+        let send = |conversation_state: CodewhispererConversationState| {
+            self.client
+                .generate_assistant_response()
+                .conversation_state(conversation_state)
+                .set_profile_arn(self.profile.as_ref().map(|p| p.arn.clone()))
+                .send()
+        };
+
+        let mut response = send(conversation_state.clone()).await;
+        // The token can be rejected before it expires, e.g. when it was revoked or the clock is
+        // off. Refresh it and try again once, the bearer resolver picks up the new token.
+        let is_auth_error = response
+            .as_ref()
+            .err()
+            .and_then(|e| e.raw_response())
+            .is_some_and(|resp| matches!(resp.status().as_u16(), 401 | 403));
+        if is_auth_error && refresh_token().await {
+            response = send(conversation_state).await;
+        }
+
+        match response {
+            Ok(resp) => Ok(SendMessageOutput::Codewhisperer(resp)),
+            Err(e) => {
+                let is_quota_breach = e.raw_response().is_some_and(|resp| resp.status().as_u16() == 429);
+                let is_context_window_overflow = e.as_service_error().is_some_and(|err| {
+                    matches!(err, err if err.meta().code() == Some("ValidationException")
+                        && err.meta().message() == Some("Input is too long."))
+                });
+
+                if is_quota_breach {
+                    Err(ApiClientError::QuotaBreach("quota has reached its limit"))
+                } else if is_context_window_overflow {
+                    Err(ApiClientError::ContextWindowOverflow)
+                } else {
+                    Err(e.into())
+                }
+            },
+        }
+    }
+}
+
+/// Refreshes the stored bearer token, returning whether there is a new token to retry with.
+async fn refresh_token() -> bool {
+    let database = match Database::new().await {
+        Ok(database) => database,
+        Err(err) => {
+            warn!(?err, "Failed to open the database to refresh the token");
+            return false;
+        },
+    };
+    match BuilderIdToken::force_refresh(&database).await {
+        Ok(token) => {
+            debug!(refreshed = token.is_some(), "Refreshed the rejected token");
+            token.is_some()
+        },
+        Err(err) => {
+            warn!(?err, "Failed to refresh the rejected token");
+            false
+        },
+    }
+}
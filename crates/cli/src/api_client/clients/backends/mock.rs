@@ -0,0 +1,32 @@
+use std::sync::Mutex;
+
+use super::ChatProviderBackend;
+use crate::api_client::ApiClientError;
+use crate::api_client::clients::SendMessageOutput;
+use crate::api_client::model::{
+    ChatResponseStream,
+    ConversationState,
+};
+
+/// Replays a fixed set of responses, one per request, for tests.
+#[derive(Debug)]
+pub struct MockBackend {
+    events: Mutex<std::vec::IntoIter<Vec<ChatResponseStream>>>,
+}
+
+impl MockBackend {
+    pub fn new(events: Vec<Vec<ChatResponseStream>>) -> Self {
+        Self {
+            events: Mutex::new(events.into_iter()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ChatProviderBackend for MockBackend {
+    async fn send(&self, _conversation_state: ConversationState) -> Result<SendMessageOutput, ApiClientError> {
+        let mut new_events = self.events.lock().unwrap().next().unwrap_or_default();
+        new_events.reverse();
+        Ok(SendMessageOutput::Mock(new_events))
+    }
+}
@@ -0,0 +1,31 @@
+//! Chat provider backends used by [`StreamingClient`](super::StreamingClient).
+//!
+//! Each backend translates a [`ConversationState`] into its provider's request format and returns
+//! the response as a [`SendMessageOutput`] event stream. Adding a new provider only requires a new
+//! [`ChatProviderBackend`] implementation.
+
+mod adapter;
+mod codewhisperer;
+mod mock;
+mod openai;
+mod qdeveloper;
+mod sse;
+
+pub use adapter::ToolCallPattern;
+pub use codewhisperer::CodewhispererBackend;
+pub use mock::MockBackend;
+pub use openai::{
+    OpenAiBackend,
+    OpenAiResponseStream,
+};
+pub use qdeveloper::QDeveloperBackend;
+
+use super::SendMessageOutput;
+use crate::api_client::ApiClientError;
+use crate::api_client::model::ConversationState;
+
+#[async_trait::async_trait]
+pub trait ChatProviderBackend: std::fmt::Debug + Send + Sync {
+    /// Sends the conversation to the provider and returns the stream of response events.
+    async fn send(&self, conversation_state: ConversationState) -> Result<SendMessageOutput, ApiClientError>;
+}
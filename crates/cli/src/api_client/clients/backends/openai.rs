@@ -0,0 +1,1033 @@
+use std::collections::{
+    BTreeMap,
+    VecDeque,
+};
+
+use bytes::Bytes;
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use serde_json::json;
+use tracing::{
+    debug,
+    warn,
+};
+
+use super::ChatProviderBackend;
+use super::adapter::{
+    self,
+    InlineToolCalls,
+    ProviderAdapter,
+    Segment,
+    ToolCallPattern,
+};
+use super::sse::{
+    SseEvent,
+    SseParser,
+};
+use crate::api_client::ApiClientError;
+use crate::api_client::clients::SendMessageOutput;
+use crate::api_client::model::{
+    AssistantResponseMessage,
+    ChatMessage,
+    ChatResponseStream,
+    ConversationState,
+    FigDocument,
+    TokenUsage,
+    Tool,
+    ToolResult,
+    ToolResultContentBlock,
+    ToolSpecification,
+    UserInputMessage,
+};
+use crate::cli::chat::model_capabilities::ModelCapabilities;
+use crate::cli::chat::openai_config::{
+    ChatProvider,
+    OpenAiConfig,
+};
+
+/// The tool providers without `response_format` are made to call with the response as its
+/// arguments, when a response schema is set.
+const RESPONSE_TOOL_NAME: &str = "structured_response";
+
+/// Backend for providers exposing an OpenAI-compatible `/chat/completions` endpoint.
+#[derive(Debug)]
+pub struct OpenAiBackend {
+    config: OpenAiConfig,
+    http_client: reqwest::Client,
+    adapter: Box<dyn ProviderAdapter>,
+    /// The patterns of `chat.toolCallPatterns`, if it is set.
+    tool_call_patterns: Option<Vec<ToolCallPattern>>,
+}
+
+impl OpenAiBackend {
+    pub fn new(config: OpenAiConfig, capabilities: ModelCapabilities, http_client: reqwest::Client) -> Self {
+        Self {
+            adapter: adapter::adapter_for(&config, &capabilities),
+            config,
+            http_client,
+            tool_call_patterns: None,
+        }
+    }
+
+    /// Sets how tool calls written in the text of responses are recognized, also for the
+    /// providers with native tool support.
+    pub fn with_tool_call_patterns(mut self, tool_call_patterns: Vec<ToolCallPattern>) -> Self {
+        self.tool_call_patterns = Some(tool_call_patterns);
+        self
+    }
+}
+
+/// Incrementally converts an OpenAI-compatible streaming response into [ChatResponseStream]
+/// events.
+///
+/// The response body is only read as events are requested, so at most one network chunk worth of
+/// events is buffered at a time.
+pub struct OpenAiResponseStream {
+    stream: BoxStream<'static, reqwest::Result<Bytes>>,
+    parser: SseParser,
+    pending: VecDeque<ChatResponseStream>,
+    tool_calls: BTreeMap<u64, PendingToolCall>,
+    /// The calls written in the text of the response, kept apart from [Self::tool_calls] whose
+    /// indexes are the provider's.
+    inline_calls: Vec<PendingToolCall>,
+    /// Whether calls to [RESPONSE_TOOL_NAME] are the response rather than tool uses.
+    response_tool: bool,
+    /// Reads the tool calls written in the text of the response.
+    inline_tool_calls: Option<InlineToolCalls>,
+    done: bool,
+}
+
+impl OpenAiResponseStream {
+    pub fn new(response: reqwest::Response) -> Self {
+        Self::from_stream(response.bytes_stream())
+    }
+
+    fn from_stream(stream: impl futures::Stream<Item = reqwest::Result<Bytes>> + Send + 'static) -> Self {
+        Self {
+            stream: stream.boxed(),
+            parser: SseParser::new(),
+            pending: VecDeque::new(),
+            tool_calls: BTreeMap::new(),
+            inline_calls: Vec::new(),
+            response_tool: false,
+            inline_tool_calls: None,
+            done: false,
+        }
+    }
+
+    /// Emits the arguments of calls to [RESPONSE_TOOL_NAME] as the response.
+    fn with_response_tool(mut self) -> Self {
+        self.response_tool = true;
+        self
+    }
+
+    /// Emits the tool calls of `tool_names` written in the response with `patterns` as tool uses,
+    /// and strips them from its text.
+    fn with_inline_tool_calls(mut self, patterns: Vec<ToolCallPattern>, tool_names: Vec<String>) -> Self {
+        self.inline_tool_calls = Some(InlineToolCalls::new(patterns, tool_names));
+        self
+    }
+
+    pub async fn recv(&mut self) -> Result<Option<ChatResponseStream>, ApiClientError> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+            if self.done {
+                return Ok(None);
+            }
+
+            match self.stream.next().await {
+                Some(chunk) => {
+                    let chunk = chunk.map_err(ApiClientError::Network)?;
+                    for event in self.parser.push(&chunk) {
+                        self.handle_event(event);
+                    }
+                },
+                None => {
+                    if let Some(event) = self.parser.finish() {
+                        self.handle_event(event);
+                    }
+                    self.flush_tool_calls();
+                    self.done = true;
+                },
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: SseEvent) {
+        if self.done {
+            return;
+        }
+        if let Some(kind) = event.event.as_deref().filter(|kind| *kind != "message") {
+            debug!(id = ?event.id, "Skipping server-sent event of type '{}': {}", kind, event.data);
+            return;
+        }
+        if event.data == "[DONE]" {
+            self.flush_tool_calls();
+            self.done = true;
+            return;
+        }
+        self.handle_data(&event.data);
+    }
+
+    fn handle_data(&mut self, data: &str) {
+        let Ok(json_data) = serde_json::from_str::<serde_json::Value>(data) else {
+            return;
+        };
+
+        // Sent in a last chunk without choices when `stream_options.include_usage` is set.
+        if let Some(usage) = json_data.get("usage").filter(|v| v.is_object()) {
+            self.pending
+                .push_back(ChatResponseStream::UsageEvent(token_usage(usage)));
+        }
+
+        let Some(choice) = json_data
+            .get("choices")
+            .and_then(|v| v.as_array())
+            .and_then(|choices| choices.first())
+        else {
+            return;
+        };
+
+        if let Some(delta) = choice.get("delta").and_then(|v| v.as_object()) {
+            // Handle text content
+            if let Some(content) = delta.get("content").and_then(|v| v.as_str()) {
+                match self.inline_tool_calls.as_mut().map(|parser| parser.push(content)) {
+                    Some(segments) => self.push_segments(segments),
+                    None => self.pending.push_back(ChatResponseStream::AssistantResponseEvent {
+                        content: content.to_string(),
+                    }),
+                }
+            }
+
+            // Tool call fragments are buffered per index, since providers may interleave the
+            // fragments of parallel calls and the response parser expects each call to arrive as a
+            // contiguous start/input/stop sequence.
+            if let Some(tool_calls) = delta.get("tool_calls").and_then(|v| v.as_array()) {
+                for tool_call in tool_calls {
+                    let index = tool_call.get("index").and_then(|v| v.as_u64()).unwrap_or_default();
+                    let entry = self.tool_calls.entry(index).or_default();
+
+                    if let Some(id) = tool_call.get("id").and_then(|v| v.as_str()) {
+                        entry.id = id.to_string();
+                    }
+                    if let Some(function) = tool_call.get("function") {
+                        if let Some(name) = function.get("name").and_then(|v| v.as_str()) {
+                            entry.name.push_str(name);
+                        }
+                        if let Some(arguments) = function.get("arguments").and_then(|v| v.as_str()) {
+                            entry.arguments.push_str(arguments);
+                        }
+                    }
+                }
+            }
+        }
+
+        if choice.get("finish_reason").is_some_and(|v| !v.is_null()) {
+            self.flush_tool_calls();
+        }
+    }
+
+    /// Emits the text of inline segments, and buffers their tool calls after the others.
+    fn push_segments(&mut self, segments: Vec<Segment>) {
+        for segment in segments {
+            match segment {
+                Segment::Text(content) => self
+                    .pending
+                    .push_back(ChatResponseStream::AssistantResponseEvent { content }),
+                Segment::ToolCall { name, arguments } => self.inline_calls.push(PendingToolCall {
+                    id: String::new(),
+                    name,
+                    arguments,
+                }),
+            }
+        }
+    }
+
+    /// Emits every buffered tool call, in index order and then the inline ones, as the
+    /// start/input/stop event sequence the native backends produce.
+    fn flush_tool_calls(&mut self) {
+        if let Some(segments) = self.inline_tool_calls.as_mut().map(InlineToolCalls::finish) {
+            self.push_segments(segments);
+        }
+        let tool_calls = std::mem::take(&mut self.tool_calls).into_values();
+        for tool_call in tool_calls.chain(std::mem::take(&mut self.inline_calls)) {
+            if tool_call.name.is_empty() {
+                warn!("Dropping a tool call without a function name");
+                continue;
+            }
+
+            let tool_use_id = if tool_call.id.is_empty() {
+                format!("tooluse_{}", uuid::Uuid::new_v4().simple())
+            } else {
+                tool_call.id
+            };
+            let input = if tool_call.arguments.trim().is_empty() {
+                "{}".to_string()
+            } else {
+                tool_call.arguments
+            };
+
+            if self.response_tool && tool_call.name == RESPONSE_TOOL_NAME {
+                self.pending
+                    .push_back(ChatResponseStream::AssistantResponseEvent { content: input });
+                continue;
+            }
+
+            self.pending.push_back(ChatResponseStream::ToolUseEvent {
+                tool_use_id: tool_use_id.clone(),
+                name: tool_call.name.clone(),
+                input: None,
+                stop: None,
+            });
+            self.pending.push_back(ChatResponseStream::ToolUseEvent {
+                tool_use_id: tool_use_id.clone(),
+                name: tool_call.name.clone(),
+                input: Some(input),
+                stop: None,
+            });
+            self.pending.push_back(ChatResponseStream::ToolUseEvent {
+                tool_use_id,
+                name: tool_call.name,
+                input: None,
+                stop: Some(true),
+            });
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PendingToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+impl std::fmt::Debug for OpenAiResponseStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenAiResponseStream")
+            .field("pending", &self.pending)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The body of a `/chat/completions` request, with what the response stream needs to know about
+/// it.
+struct OpenAiRequest {
+    body: serde_json::Value,
+    /// Whether the response is requested as a call to [RESPONSE_TOOL_NAME].
+    response_tool: bool,
+    tool_names: Vec<String>,
+}
+
+impl OpenAiBackend {
+    fn request(&self, conversation_state: ConversationState) -> OpenAiRequest {
+        let ConversationState {
+            user_input_message,
+            history,
+            response_schema,
+            ..
+        } = conversation_state;
+
+        let inline_tools = self.adapter.inline_tool_calls();
+        let mut messages = convert_messages(history.unwrap_or_default(), &user_input_message, inline_tools);
+        if uses_cache_control(&self.config.model) {
+            add_cache_breakpoints(&mut messages);
+        }
+
+        // Get available tools from conversation state
+        let tool_specs = user_input_message
+            .user_input_message_context
+            .as_ref()
+            .and_then(|ctx| ctx.tools.as_ref())
+            .map(|tools| {
+                tools
+                    .iter()
+                    .map(|tool| match tool {
+                        Tool::ToolSpecification(spec) => spec.clone(),
+                    })
+                    .collect::<Vec<ToolSpecification>>()
+            });
+        let tool_names = tool_specs
+            .iter()
+            .flatten()
+            .map(|spec| spec.name.clone())
+            .collect::<Vec<_>>();
+        if let Some(guidance) = self.adapter.system_guidance(tool_specs.as_deref().unwrap_or_default()) {
+            messages.insert(0, json!({ "role": "system", "content": guidance }));
+        }
+        let tools = tool_specs.map(|specs| {
+            specs
+                .iter()
+                .map(|spec| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": spec.name,
+                            "description": spec.description,
+                            "parameters": spec
+                                .input_schema
+                                .json
+                                .as_ref()
+                                .and_then(|schema| serde_json::to_value(schema).ok())
+                                .unwrap_or_else(|| json!({ "type": "object", "properties": {} }))
+                        }
+                    })
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let mut request_body = json!({
+            "model": self.config.model,
+            "messages": messages,
+            "stream": true,
+            "stream_options": { "include_usage": true }
+        });
+
+        match tools {
+            Some(_) if !self.adapter.native_tools() => {
+                debug!(
+                    "Prompting model '{}' to write its tool calls in the response",
+                    self.config.model
+                );
+            },
+            Some(tools) if !tools.is_empty() => {
+                // Don't set tool_choice to maintain compatibility with different providers
+                debug!(
+                    "Sending {} tools to OpenAI-compatible API without tool_choice parameter",
+                    tools.len()
+                );
+                request_body["tools"] = json!(tools);
+            },
+            Some(_) => {},
+            None => debug!("No tools available for OpenAI-compatible API request"),
+        }
+
+        let mut response_tool = false;
+        if let Some(schema) = response_schema {
+            if self.config.provider == ChatProvider::OpenAI {
+                request_body["response_format"] = json!({
+                    "type": "json_schema",
+                    "json_schema": {
+                        "name": "response",
+                        "schema": schema
+                    }
+                });
+            } else if self.adapter.native_tools() {
+                let tool = json!({
+                    "type": "function",
+                    "function": {
+                        "name": RESPONSE_TOOL_NAME,
+                        "description": "Gives the final response to the user, once any other tool use is done.",
+                        "parameters": schema
+                    }
+                });
+                match request_body["tools"].as_array_mut() {
+                    Some(tools) => tools.push(tool),
+                    None => request_body["tools"] = json!([tool]),
+                }
+                request_body["tool_choice"] = json!("required");
+                response_tool = true;
+            } else {
+                debug!("Relying on the prompt for the response schema of {}", self.config.model);
+            }
+        }
+
+        OpenAiRequest {
+            body: request_body,
+            response_tool,
+            tool_names,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ChatProviderBackend for OpenAiBackend {
+    async fn send(&self, conversation_state: ConversationState) -> Result<SendMessageOutput, ApiClientError> {
+        let OpenAiRequest {
+            body: request_body,
+            response_tool,
+            tool_names,
+        } = self.request(conversation_state);
+
+        let mut request_builder = self
+            .http_client
+            .post(format!("{}/chat/completions", self.config.base_url))
+            .header("Content-Type", "application/json")
+            .json(&request_body);
+
+        if let Some(api_key) = &self.config.api_key {
+            request_builder = request_builder.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        let response = request_builder.send().await.map_err(ApiClientError::Network)?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(ApiClientError::from_provider_response(status.as_u16(), error_text));
+        }
+
+        let mut stream = OpenAiResponseStream::new(response);
+        if response_tool {
+            stream = stream.with_response_tool();
+        }
+        // Native providers are only read for calls in the text when chat.toolCallPatterns is set,
+        // for local models ignoring the tools of the request, as their answers may hold JSON that
+        // merely looks like a call.
+        let inline_tools = self.adapter.inline_tool_calls();
+        let mut patterns = match &self.tool_call_patterns {
+            Some(patterns) => patterns.clone(),
+            None if inline_tools => ToolCallPattern::defaults(),
+            None => Vec::new(),
+        };
+        if inline_tools && !patterns.contains(&ToolCallPattern::tool_call()) {
+            patterns.push(ToolCallPattern::tool_call());
+        }
+        if !tool_names.is_empty() && !patterns.is_empty() {
+            stream = stream.with_inline_tool_calls(patterns, tool_names);
+        }
+        Ok(SendMessageOutput::OpenAI(stream))
+    }
+}
+
+/// Converts the conversation into OpenAI chat messages.
+///
+/// Assistant tool uses become `tool_calls` on the assistant message, and each tool result becomes a
+/// separate `tool` message referencing the id of the call it answers. With `inline_tools`, they are
+/// written as blocks in the text of the messages instead, see [adapter::PromptedTools].
+fn convert_messages(
+    history: Vec<ChatMessage>,
+    user_input_message: &UserInputMessage,
+    inline_tools: bool,
+) -> Vec<serde_json::Value> {
+    let mut messages = Vec::new();
+    for msg in &history {
+        match msg {
+            ChatMessage::UserInputMessage(user_msg) => push_user_messages(&mut messages, user_msg, inline_tools),
+            ChatMessage::AssistantResponseMessage(assistant_msg) => {
+                messages.push(assistant_message(assistant_msg, inline_tools));
+            },
+        }
+    }
+    push_user_messages(&mut messages, user_input_message, inline_tools);
+    messages
+}
+
+fn push_user_messages(messages: &mut Vec<serde_json::Value>, user_msg: &UserInputMessage, inline_tools: bool) {
+    let tool_results = user_msg
+        .user_input_message_context
+        .as_ref()
+        .and_then(|ctx| ctx.tool_results.as_deref())
+        .unwrap_or_default();
+
+    if inline_tools {
+        let content = tool_results
+            .iter()
+            .map(|tool_result| adapter::tool_result_block(&tool_result.tool_use_id, &tool_result_content(tool_result)))
+            .chain(Some(user_msg.content.clone()).filter(|content| !content.trim().is_empty()))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        messages.push(json!({
+            "role": "user",
+            "content": content
+        }));
+        return;
+    }
+
+    // Tool messages have to directly follow the assistant message that made the calls
+    for tool_result in tool_results {
+        messages.push(json!({
+            "role": "tool",
+            "tool_call_id": tool_result.tool_use_id,
+            "content": tool_result_content(tool_result)
+        }));
+    }
+
+    if tool_results.is_empty() || !user_msg.content.trim().is_empty() {
+        messages.push(json!({
+            "role": "user",
+            "content": user_msg.content
+        }));
+    }
+}
+
+fn assistant_message(assistant_msg: &AssistantResponseMessage, inline_tools: bool) -> serde_json::Value {
+    if inline_tools {
+        let content = Some(assistant_msg.content.clone())
+            .filter(|content| !content.is_empty())
+            .into_iter()
+            .chain(assistant_msg.tool_uses.iter().flatten().map(|tool_use| {
+                let arguments = serde_json::to_value(&tool_use.input).unwrap_or_else(|_| json!({}));
+                adapter::tool_call_block(&tool_use.name, &arguments)
+            }))
+            .collect::<Vec<_>>()
+            .join("\n");
+        return json!({
+            "role": "assistant",
+            "content": content
+        });
+    }
+
+    let tool_calls = assistant_msg
+        .tool_uses
+        .as_ref()
+        .map(|tool_uses| {
+            tool_uses
+                .iter()
+                .map(|tool_use| {
+                    json!({
+                        "id": tool_use.tool_use_id,
+                        "type": "function",
+                        "function": {
+                            "name": tool_use.name,
+                            "arguments": serde_json::to_string(&tool_use.input).unwrap_or_else(|_| "{}".to_string())
+                        }
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    if tool_calls.is_empty() {
+        json!({
+            "role": "assistant",
+            "content": assistant_msg.content
+        })
+    } else {
+        // Some providers reject an empty string alongside tool calls
+        let content = Some(&assistant_msg.content).filter(|content| !content.is_empty());
+        json!({
+            "role": "assistant",
+            "content": content,
+            "tool_calls": tool_calls
+        })
+    }
+}
+
+/// Anthropic models only cache the prompt up to explicit `cache_control` breakpoints, while OpenAI
+/// and most other providers cache repeated prefixes on their own.
+fn uses_cache_control(model: &str) -> bool {
+    model.to_lowercase().contains("claude")
+}
+
+/// Marks the end of the first exchange, which holds the context files and the summary when there
+/// are any, and the end of the request as cache breakpoints. The next request then reads both the
+/// context and the conversation so far from the cache.
+fn add_cache_breakpoints(messages: &mut [serde_json::Value]) {
+    let last = messages.len().saturating_sub(1);
+    for index in [1, last] {
+        let Some(message) = messages.get_mut(index) else {
+            continue;
+        };
+        // Messages with only tool calls have no content to mark.
+        let Some(text) = message
+            .get("content")
+            .and_then(|v| v.as_str())
+            .filter(|text| !text.is_empty())
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        message["content"] = json!([{
+            "type": "text",
+            "text": text,
+            "cache_control": { "type": "ephemeral" }
+        }]);
+    }
+}
+
+/// Reads the usage of a response, with the cache fields of whichever convention the provider
+/// follows.
+fn token_usage(usage: &serde_json::Value) -> TokenUsage {
+    let field = |pointer: &str| usage.pointer(pointer).and_then(|v| v.as_u64());
+    TokenUsage {
+        input_tokens: field("/prompt_tokens").unwrap_or_default(),
+        // OpenAI and OpenRouter, then Anthropic models behind LiteLLM, then DeepSeek.
+        cached_input_tokens: field("/prompt_tokens_details/cached_tokens")
+            .or_else(|| field("/cache_read_input_tokens"))
+            .or_else(|| field("/prompt_cache_hit_tokens"))
+            .unwrap_or_default(),
+        cache_write_tokens: field("/cache_creation_input_tokens").unwrap_or_default(),
+        output_tokens: field("/completion_tokens").unwrap_or_default(),
+    }
+}
+
+fn tool_result_content(tool_result: &ToolResult) -> String {
+    tool_result
+        .content
+        .iter()
+        .map(|block| match block {
+            ToolResultContentBlock::Text(text) => text.clone(),
+            ToolResultContentBlock::Json(document) => {
+                serde_json::to_string(&FigDocument::from(document.clone())).unwrap_or_default()
+            },
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_from_chunks(chunks: &[&'static str]) -> OpenAiResponseStream {
+        OpenAiResponseStream::from_stream(futures::stream::iter(
+            chunks
+                .iter()
+                .map(|chunk| Ok(Bytes::from_static(chunk.as_bytes())))
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_response_stream_handles_split_crlf_events() {
+        let mut stream = stream_from_chunks(&[
+            ": keep-alive\r\n\r\ndata: {\"choices\":[{\"delta\":",
+            "{\"content\":\"Hel\"}}]}\r\n\r",
+            "\ndata: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\r\n\r\ndata: [DONE]\r\n\r\n",
+        ]);
+        assert_eq!(collect_content(&mut stream).await, "Hello");
+    }
+
+    /// Recorded from an OpenAI `gpt-4o` response making two parallel tool calls.
+    const PARALLEL_TOOL_CALLS_TRACE: &[&str] = &[
+        "data: {\"id\":\"chatcmpl-1\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":null,\"tool_calls\":[{\"index\":0,\"id\":\"call_a\",\"type\":\"function\",\"function\":{\"name\":\"fs_read\",\"arguments\":\"\"}}]},\"finish_reason\":null}]}\n\n",
+        "data: {\"id\":\"chatcmpl-1\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"{\\\"path\"}}]},\"finish_reason\":null}]}\n\n",
+        "data: {\"id\":\"chatcmpl-1\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":1,\"id\":\"call_b\",\"type\":\"function\",\"function\":{\"name\":\"execute_bash\",\"arguments\":\"{\\\"command\\\": \"}}]},\"finish_reason\":null}]}\n\n",
+        "data: {\"id\":\"chatcmpl-1\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\": \\\"a.txt\\\"}\"}}]},\"finish_reason\":null}]}\n\n",
+        "data: {\"id\":\"chatcmpl-1\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":1,\"function\":{\"arguments\":\"\\\"ls\\\"}\"}}]},\"finish_reason\":null}]}\n\n",
+        "data: {\"id\":\"chatcmpl-1\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n",
+        "data: [DONE]\n\n",
+    ];
+
+    /// Recorded from an OpenAI-compatible provider that omits tool call ids and never sends a
+    /// finish reason.
+    const MISSING_ID_TRACE: &[&str] = &[
+        "data: {\"choices\":[{\"delta\":{\"content\":\"Checking.\"}}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"name\":\"fs_read\"}}]}}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"{\\\"path\\\":\\\"b\\\"}\"}}]}}]}\n\n",
+    ];
+
+    async fn collect_events(stream: &mut OpenAiResponseStream) -> Vec<ChatResponseStream> {
+        let mut events = Vec::new();
+        while let Some(event) = stream.recv().await.unwrap() {
+            events.push(event);
+        }
+        events
+    }
+
+    fn tool_use(tool_use_id: &str, name: &str, input: Option<&str>, stop: Option<bool>) -> ChatResponseStream {
+        ChatResponseStream::ToolUseEvent {
+            tool_use_id: tool_use_id.to_string(),
+            name: name.to_string(),
+            input: input.map(str::to_string),
+            stop,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parallel_tool_calls_are_emitted_contiguously() {
+        let mut stream = stream_from_chunks(PARALLEL_TOOL_CALLS_TRACE);
+        let events = collect_events(&mut stream).await;
+        let expected = vec![
+            tool_use("call_a", "fs_read", None, None),
+            tool_use("call_a", "fs_read", Some("{\"path\": \"a.txt\"}"), None),
+            tool_use("call_a", "fs_read", None, Some(true)),
+            tool_use("call_b", "execute_bash", None, None),
+            tool_use("call_b", "execute_bash", Some("{\"command\": \"ls\"}"), None),
+            tool_use("call_b", "execute_bash", None, Some(true)),
+        ];
+        assert_eq!(events, expected);
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_without_id_or_finish_reason() {
+        let mut stream = stream_from_chunks(MISSING_ID_TRACE);
+        let events = collect_events(&mut stream).await;
+        assert_eq!(events.len(), 4);
+        assert!(matches!(&events[0], ChatResponseStream::AssistantResponseEvent { content } if content == "Checking."));
+        let ChatResponseStream::ToolUseEvent { tool_use_id, .. } = &events[1] else {
+            panic!("expected a tool use event, found {:?}", events[1]);
+        };
+        assert!(!tool_use_id.is_empty());
+        assert!(
+            matches!(&events[2], ChatResponseStream::ToolUseEvent { input: Some(input), .. } if input == "{\"path\":\"b\"}")
+        );
+        assert!(matches!(&events[3], ChatResponseStream::ToolUseEvent {
+            stop: Some(true),
+            ..
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_response_tool_call_is_the_response() {
+        let mut stream = stream_from_chunks(&[
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_a\",\"function\":{\"name\":\"structured_response\",\"arguments\":\"{\\\"todos\\\": \"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"[]}\"}}]},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: [DONE]\n\n",
+        ])
+        .with_response_tool();
+        assert_eq!(collect_events(&mut stream).await, vec![
+            ChatResponseStream::AssistantResponseEvent {
+                content: "{\"todos\": []}".to_string()
+            }
+        ]);
+
+        // Other tools are still used while the response tool is expected.
+        let mut stream = stream_from_chunks(PARALLEL_TOOL_CALLS_TRACE).with_response_tool();
+        assert_eq!(collect_events(&mut stream).await.len(), 6);
+    }
+
+    #[test]
+    fn test_convert_messages_tool_round_trip() {
+        use crate::api_client::model::{
+            ToolResultStatus,
+            ToolUse,
+            UserInputMessageContext,
+        };
+
+        let user = |content: &str, tool_results: Option<Vec<ToolResult>>| UserInputMessage {
+            content: content.to_string(),
+            user_input_message_context: Some(UserInputMessageContext {
+                tool_results,
+                ..Default::default()
+            }),
+            user_intent: None,
+            images: None,
+        };
+        let tool_result = |id: &str, text: &str| ToolResult {
+            tool_use_id: id.to_string(),
+            content: vec![ToolResultContentBlock::Text(text.to_string())],
+            status: ToolResultStatus::Success,
+        };
+        let tool_use = |id: &str, input: serde_json::Value| ToolUse {
+            tool_use_id: id.to_string(),
+            name: "fs_read".to_string(),
+            input: serde_json::from_value(input).unwrap(),
+        };
+
+        let history = vec![
+            ChatMessage::UserInputMessage(user("read both files", None)),
+            ChatMessage::AssistantResponseMessage(AssistantResponseMessage {
+                message_id: None,
+                content: String::new(),
+                tool_uses: Some(vec![
+                    tool_use("call_a", json!({"path": "a"})),
+                    tool_use("call_b", json!({"path": "b"})),
+                ]),
+            }),
+        ];
+        let current = user("", Some(vec![tool_result("call_a", "A"), tool_result("call_b", "B")]));
+
+        assert_eq!(convert_messages(history.clone(), &current, false), vec![
+            json!({"role": "user", "content": "read both files"}),
+            json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [
+                    {"id": "call_a", "type": "function", "function": {"name": "fs_read", "arguments": "{\"path\":\"a\"}"}},
+                    {"id": "call_b", "type": "function", "function": {"name": "fs_read", "arguments": "{\"path\":\"b\"}"}}
+                ]
+            }),
+            json!({"role": "tool", "tool_call_id": "call_a", "content": "A"}),
+            json!({"role": "tool", "tool_call_id": "call_b", "content": "B"}),
+        ]);
+
+        assert_eq!(convert_messages(history, &current, true), vec![
+            json!({"role": "user", "content": "read both files"}),
+            json!({
+                "role": "assistant",
+                "content": "<tool_call>{\"name\":\"fs_read\",\"arguments\":{\"path\":\"a\"}}</tool_call>\n<tool_call>{\"name\":\"fs_read\",\"arguments\":{\"path\":\"b\"}}</tool_call>"
+            }),
+            json!({
+                "role": "user",
+                "content": "<tool_result id=\"call_a\">\nA\n</tool_result>\n\n<tool_result id=\"call_b\">\nB\n</tool_result>"
+            }),
+        ]);
+    }
+
+    #[test]
+    fn test_request_passes_tool_schema() {
+        use crate::api_client::model::{
+            ToolInputSchema,
+            UserInputMessageContext,
+        };
+
+        let schema = json!({
+            "type": "object",
+            "properties": {"path": {"type": "string", "description": "Path of the file"}},
+            "required": ["path"]
+        });
+        let tool = Tool::ToolSpecification(ToolSpecification {
+            name: "fs_read".to_string(),
+            description: "Reads a file.".to_string(),
+            input_schema: ToolInputSchema {
+                json: Some(serde_json::from_value(schema.clone()).unwrap()),
+            },
+        });
+        let conversation_state = ConversationState {
+            conversation_id: None,
+            user_input_message: UserInputMessage {
+                content: "read a".to_string(),
+                user_input_message_context: Some(UserInputMessageContext {
+                    tools: Some(vec![tool]),
+                    ..Default::default()
+                }),
+                user_intent: None,
+                images: None,
+            },
+            history: None,
+            response_schema: None,
+        };
+        let config = OpenAiConfig {
+            provider: ChatProvider::OpenAI,
+            model: "gpt-4o".to_string(),
+            ..Default::default()
+        };
+        let backend = OpenAiBackend::new(config, ModelCapabilities::default(), reqwest::Client::new());
+
+        let request = backend.request(conversation_state);
+        assert_eq!(request.tool_names, vec!["fs_read"]);
+        assert_eq!(request.body["tools"][0]["function"]["name"], "fs_read");
+        assert_eq!(request.body["tools"][0]["function"]["parameters"], schema);
+    }
+
+    #[tokio::test]
+    async fn test_inline_tool_calls() {
+        let mut stream = stream_from_chunks(&[
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Checking.<tool_\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"call>{\\\"name\\\": \\\"fs_read\\\", \\\"arguments\\\": {\\\"path\\\": \\\"b\\\"}}</tool_call>\"},\"finish_reason\":\"stop\"}]}\n\n",
+            "data: [DONE]\n\n",
+        ])
+        .with_inline_tool_calls(ToolCallPattern::defaults(), vec!["fs_read".to_string()]);
+        let events = collect_events(&mut stream).await;
+        assert_eq!(events.len(), 4);
+        assert!(matches!(&events[0], ChatResponseStream::AssistantResponseEvent { content } if content == "Checking."));
+        assert!(
+            matches!(&events[2], ChatResponseStream::ToolUseEvent { name, input: Some(input), .. } if name == "fs_read" && input == "{\"path\":\"b\"}")
+        );
+        assert!(matches!(&events[3], ChatResponseStream::ToolUseEvent {
+            stop: Some(true),
+            ..
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_inline_and_native_tool_calls_are_kept_apart() {
+        let mut stream = stream_from_chunks(&[
+            "data: {\"choices\":[{\"delta\":{\"content\":\"<tool_call>{\\\"name\\\": \\\"fs_read\\\", \\\"arguments\\\": {\\\"path\\\": \\\"a\\\"}}</tool_call>\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_b\",\"function\":{\"name\":\"execute_bash\",\"arguments\":\"{\\\"command\\\": \\\"ls\\\"}\"}}]},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: [DONE]\n\n",
+        ])
+        .with_inline_tool_calls(ToolCallPattern::defaults(), vec![
+            "fs_read".to_string(),
+            "execute_bash".to_string(),
+        ]);
+        let inputs = collect_events(&mut stream)
+            .await
+            .into_iter()
+            .filter_map(|event| match event {
+                ChatResponseStream::ToolUseEvent {
+                    name,
+                    input: Some(input),
+                    ..
+                } => Some((name, input)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(inputs, vec![
+            ("execute_bash".to_string(), "{\"command\": \"ls\"}".to_string()),
+            ("fs_read".to_string(), "{\"path\":\"a\"}".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_cache_breakpoints() {
+        let mut messages = vec![
+            json!({"role": "user", "content": "--- CONTEXT ENTRY BEGIN ---"}),
+            json!({"role": "assistant", "content": "I will fully incorporate this information"}),
+            json!({"role": "user", "content": "read a"}),
+            json!({"role": "assistant", "content": null, "tool_calls": []}),
+            json!({"role": "tool", "tool_call_id": "call_a", "content": "A"}),
+        ];
+        add_cache_breakpoints(&mut messages);
+
+        let breakpoint = |text: &str| json!([{"type": "text", "text": text, "cache_control": {"type": "ephemeral"}}]);
+        assert_eq!(messages[0]["content"], "--- CONTEXT ENTRY BEGIN ---");
+        assert_eq!(
+            messages[1]["content"],
+            breakpoint("I will fully incorporate this information")
+        );
+        assert_eq!(messages[2]["content"], "read a");
+        assert_eq!(messages[4]["content"], breakpoint("A"));
+
+        assert!(uses_cache_control("anthropic/claude-3.7-sonnet"));
+        assert!(!uses_cache_control("gpt-4o"));
+    }
+
+    #[tokio::test]
+    async fn test_usage_event() {
+        let mut stream = stream_from_chunks(&[
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"},\"finish_reason\":\"stop\"}],\"usage\":null}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":2048,\"completion_tokens\":1,\"prompt_tokens_details\":{\"cached_tokens\":1920}}}\n\n",
+            "data: [DONE]\n\n",
+        ]);
+        let events = collect_events(&mut stream).await;
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[1],
+            ChatResponseStream::UsageEvent(TokenUsage {
+                input_tokens: 2048,
+                cached_input_tokens: 1920,
+                cache_write_tokens: 0,
+                output_tokens: 1,
+            })
+        );
+
+        let usage = token_usage(&json!({
+            "prompt_tokens": 3000,
+            "cache_read_input_tokens": 0,
+            "cache_creation_input_tokens": 2800
+        }));
+        assert_eq!(usage.cache_write_tokens, 2800);
+        assert_eq!(usage.cached_input_tokens, 0);
+    }
+
+    async fn collect_content(stream: &mut OpenAiResponseStream) -> String {
+        let mut content = String::new();
+        while let Some(event) = stream.recv().await.unwrap() {
+            if let ChatResponseStream::AssistantResponseEvent { content: text } = event {
+                content.push_str(&text);
+            }
+        }
+        content
+    }
+
+    #[tokio::test]
+    async fn test_response_stream_yields_events_incrementally() {
+        let mut stream = stream_from_chunks(&[
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hello\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\" world\"}}]}\n\n",
+        ]);
+
+        let first = stream.recv().await.unwrap();
+        assert!(
+            matches!(first, Some(ChatResponseStream::AssistantResponseEvent { ref content }) if content == "Hello")
+        );
+        // Only the first chunk has been read from the body so far
+        assert!(stream.pending.is_empty());
+        assert_eq!(collect_content(&mut stream).await, " world");
+    }
+
+    #[tokio::test]
+    async fn test_response_stream_stops_at_done() {
+        let mut stream = stream_from_chunks(&[
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\ndata: [DONE]\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"ignored\"}}]}\n\n",
+        ]);
+        assert_eq!(collect_content(&mut stream).await, "Hi");
+    }
+}
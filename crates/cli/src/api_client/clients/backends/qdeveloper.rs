@@ -0,0 +1,52 @@
+use amzn_qdeveloper_streaming_client::Client as QDeveloperStreamingClient;
+use amzn_qdeveloper_streaming_client::types::{
+    ChatMessage as QDeveloperChatMessage,
+    ChatTriggerType,
+    ConversationState as QDeveloperConversationState,
+};
+
+use super::ChatProviderBackend;
+use crate::api_client::ApiClientError;
+use crate::api_client::clients::SendMessageOutput;
+use crate::api_client::model::ConversationState;
+
+#[derive(Debug)]
+pub struct QDeveloperBackend {
+    client: QDeveloperStreamingClient,
+}
+
+impl QDeveloperBackend {
+    pub fn new(client: QDeveloperStreamingClient) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait::async_trait]
+impl ChatProviderBackend for QDeveloperBackend {
+    async fn send(&self, conversation_state: ConversationState) -> Result<SendMessageOutput, ApiClientError> {
+        let ConversationState {
+            conversation_id,
+            user_input_message,
+            history,
+            ..
+        } = conversation_state;
+
+        let conversation_state_builder = QDeveloperConversationState::builder()
+            .set_conversation_id(conversation_id)
+            .current_message(QDeveloperChatMessage::UserInputMessage(user_input_message.into()))
+            .chat_trigger_type(ChatTriggerType::Manual)
+            .set_history(
+                history
+                    .map(|v| v.into_iter().map(|i| i.try_into()).collect::<Result<Vec<_>, _>>())
+                    .transpose()?,
+            );
+
+        Ok(SendMessageOutput::QDeveloper(
+            self.client
+                .send_message()
+                .conversation_state(conversation_state_builder.build().expect("fix me"))
+                .send()
+                .await?,
+        ))
+    }
+}
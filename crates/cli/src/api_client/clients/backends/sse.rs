@@ -0,0 +1,180 @@
+//! Incremental parser for `text/event-stream` responses.
+//!
+//! Implements the event stream interpretation from the HTML living standard: lines may end in
+//! `\n`, `\r\n` or `\r`, comment lines start with `:`, multiple `data` fields are joined with
+//! newlines, and an event is dispatched on a blank line. Input can be split at arbitrary byte
+//! boundaries, including in the middle of a line terminator or a UTF-8 sequence.
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Option<String>,
+    id: Option<String>,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk of the response body, returning every event completed by it.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        let mut start = 0;
+        while let Some(offset) = self.buffer[start..].iter().position(|b| *b == b'\n' || *b == b'\r') {
+            let end = start + offset;
+            let terminator_len = match (self.buffer[end], self.buffer.get(end + 1)) {
+                (b'\r', Some(b'\n')) => 2,
+                // A trailing `\r` may be the first half of a `\r\n` split across chunks
+                (b'\r', None) => break,
+                _ => 1,
+            };
+
+            let line = String::from_utf8_lossy(&self.buffer[start..end]).into_owned();
+            start = end + terminator_len;
+            if let Some(event) = self.process_line(&line) {
+                events.push(event);
+            }
+        }
+        self.buffer.drain(..start);
+
+        events
+    }
+
+    /// Signals the end of the stream, returning the last event if the body did not end with a
+    /// blank line.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let buffer = std::mem::take(&mut self.buffer);
+        let line = String::from_utf8_lossy(&buffer);
+        let line = line.trim_end_matches('\r');
+        if !line.is_empty() {
+            self.process_line(line);
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            // Comment, commonly used as a keep-alive
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                },
+                None => self.data = Some(value.to_string()),
+            },
+            "event" => self.event = Some(value.to_string()),
+            "id" if !value.contains('\0') => self.id = Some(value.to_string()),
+            _ => {},
+        }
+
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        let data = self.data.take()?;
+        Some(SseEvent {
+            event,
+            data,
+            id: self.id.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_events(events: Vec<SseEvent>) -> Vec<String> {
+        events.into_iter().map(|e| e.data).collect()
+    }
+
+    #[test]
+    fn test_single_chunk() {
+        let mut parser = SseParser::new();
+        let events = parser.push(b"data: one\n\ndata: two\n\n");
+        assert_eq!(data_events(events), vec!["one", "two"]);
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn test_split_across_chunks() {
+        let input = b"data: {\"content\":\"hello\"}\n\ndata: [DONE]\n\n";
+        for split in 0..input.len() {
+            let mut parser = SseParser::new();
+            let mut events = parser.push(&input[..split]);
+            events.extend(parser.push(&input[split..]));
+            assert_eq!(
+                data_events(events),
+                vec!["{\"content\":\"hello\"}", "[DONE]"],
+                "split at {split}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_crlf_and_cr_line_endings() {
+        let mut parser = SseParser::new();
+        let mut events = parser.push(b"data: a\r\n\r");
+        events.extend(parser.push(b"\ndata: b\r\rdata: c\r"));
+        events.extend(parser.push(b"\n\r\n"));
+        assert_eq!(data_events(events), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_comments_and_keep_alive() {
+        let mut parser = SseParser::new();
+        let events = parser.push(b": keep-alive\n\n:ping\ndata: value\n\n");
+        assert_eq!(data_events(events), vec!["value"]);
+    }
+
+    #[test]
+    fn test_multi_line_data_and_fields() {
+        let mut parser = SseParser::new();
+        let events = parser.push(b"event: message\nid: 7\ndata: first\ndata:second\ndata\n\n");
+        assert_eq!(events, vec![SseEvent {
+            event: Some("message".to_string()),
+            data: "first\nsecond\n".to_string(),
+            id: Some("7".to_string()),
+        }]);
+    }
+
+    #[test]
+    fn test_split_utf8() {
+        let input = "data: héllo\n\n".as_bytes();
+        let split = input.iter().position(|b| *b > 0x7f).unwrap() + 1;
+        let mut parser = SseParser::new();
+        let mut events = parser.push(&input[..split]);
+        events.extend(parser.push(&input[split..]));
+        assert_eq!(data_events(events), vec!["héllo"]);
+    }
+
+    #[test]
+    fn test_finish_flushes_unterminated_event() {
+        let mut parser = SseParser::new();
+        assert!(parser.push(b"data: partial").is_empty());
+        assert_eq!(parser.finish().map(|e| e.data), Some("partial".to_string()));
+    }
+}
@@ -1,13 +1,21 @@
 use amzn_codewhisperer_client::Client as CodewhispererClient;
 use amzn_codewhisperer_client::types::{
+    FileContext,
     OptOutPreference,
+    ProgrammingLanguage,
     TelemetryEvent,
     UserContext,
 };
+use aws_types::request_id::RequestId;
 use tracing::error;
 
 use super::shared::bearer_sdk_config;
 use crate::api_client::interceptor::opt_out::OptOutInterceptor;
+use crate::api_client::model::{
+    Recommendation,
+    RecommendationsInput,
+    RecommendationsOutput,
+};
 use crate::api_client::{
     ApiClientError,
     Endpoint,
@@ -23,6 +31,9 @@ use crate::database::{
     Database,
 };
 
+/// Characters of the file on each side of the cursor accepted by the completions API.
+const MAX_FILE_CONTENT: usize = 10240;
+
 mod inner {
     use amzn_codewhisperer_client::Client as CodewhispererClient;
 
@@ -98,6 +109,68 @@ impl Client {
         }
     }
 
+    pub async fn generate_recommendations(
+        &self,
+        input: RecommendationsInput,
+    ) -> Result<RecommendationsOutput, ApiClientError> {
+        match &self.inner {
+            inner::Inner::Codewhisperer(client) => {
+                let context = input.file_context;
+                // The content closest to the cursor is kept when there's too much of it.
+                let left = context
+                    .left_file_content
+                    .chars()
+                    .rev()
+                    .take(MAX_FILE_CONTENT)
+                    .collect::<Vec<_>>();
+                let file_context = FileContext::builder()
+                    .left_file_content(left.into_iter().rev().collect::<String>())
+                    .right_file_content(
+                        context
+                            .right_file_content
+                            .chars()
+                            .take(MAX_FILE_CONTENT)
+                            .collect::<String>(),
+                    )
+                    .filename(context.filename)
+                    .programming_language(
+                        ProgrammingLanguage::builder()
+                            .language_name(context.programming_language.language_name.as_ref())
+                            .build()?,
+                    )
+                    .build()?;
+                let output = client
+                    .generate_completions()
+                    .file_context(file_context)
+                    .max_results(input.max_results)
+                    .set_next_token(input.next_token)
+                    .set_profile_arn(self.profile.as_ref().map(|p| p.arn.clone()))
+                    .send()
+                    .await?;
+
+                Ok(RecommendationsOutput {
+                    recommendations: output
+                        .completions()
+                        .iter()
+                        .map(|completion| Recommendation {
+                            content: completion.content().to_owned(),
+                            references: completion.references().iter().cloned().map(Into::into).collect(),
+                        })
+                        .collect(),
+                    next_token: output.next_token().map(ToOwned::to_owned),
+                    session_id: None,
+                    request_id: output.request_id().map(ToOwned::to_owned),
+                })
+            },
+            inner::Inner::Mock => Ok(RecommendationsOutput {
+                recommendations: vec![],
+                next_token: None,
+                session_id: None,
+                request_id: None,
+            }),
+        }
+    }
+
     pub async fn list_available_profiles(&self) -> Result<Vec<AuthProfile>, ApiClientError> {
         match &self.inner {
             inner::Inner::Codewhisperer(client) => {
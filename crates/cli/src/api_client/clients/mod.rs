@@ -1,3 +1,4 @@
+pub mod backends;
 mod client;
 pub(crate) mod shared;
 mod streaming_client;
@@ -12,8 +12,11 @@ use crate::api_client::consts::{
     PROD_Q_ENDPOINT_REGION,
     PROD_Q_ENDPOINT_URL,
 };
-use crate::database::Database;
 use crate::database::settings::Setting;
+use crate::database::{
+    AuthProfile,
+    Database,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Endpoint {
@@ -69,6 +72,16 @@ impl Endpoint {
         }
     }
 
+    /// The CodeWhisperer endpoint serving the region encoded in a profile ARN.
+    pub fn for_profile(profile: &AuthProfile) -> Self {
+        let region = profile.arn.split(':').nth(3).unwrap_or_default();
+        Self::CODEWHISPERER_ENDPOINTS
+            .iter()
+            .find(|e| e.region().as_ref() == region)
+            .cloned()
+            .unwrap_or(Self::DEFAULT_ENDPOINT)
+    }
+
     pub fn load_q(database: &Database) -> Self {
         match database.settings.get(Setting::ApiQService) {
             Some(Value::Object(o)) => {
@@ -119,4 +132,25 @@ mod tests {
         Url::parse(custom.url()).unwrap();
         assert_eq!(custom.region(), &Region::new("us-west-2"));
     }
+
+    #[test]
+    fn test_endpoint_for_profile() {
+        let profile = |arn: &str| AuthProfile {
+            arn: arn.to_string(),
+            profile_name: "p".to_string(),
+        };
+
+        assert_eq!(
+            Endpoint::for_profile(&profile("arn:aws:codewhisperer:eu-central-1:123456789012:profile/ABC")),
+            Endpoint::CODEWHISPERER_ENDPOINTS[1]
+        );
+        assert_eq!(
+            Endpoint::for_profile(&profile("arn:aws:codewhisperer:us-east-1:123456789012:profile/ABC")),
+            Endpoint::DEFAULT_ENDPOINT
+        );
+        assert_eq!(
+            Endpoint::for_profile(&profile("not-an-arn")),
+            Endpoint::DEFAULT_ENDPOINT
+        );
+    }
 }
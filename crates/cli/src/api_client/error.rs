@@ -66,8 +66,136 @@ pub enum ApiClientError {
     #[error(transparent)]
     AuthError(#[from] AuthError),
 
-    #[error("{0}")]
-    Other(String),
+    /// The provider rejected the credentials, e.g. an invalid or expired API key.
+    #[error("the provider rejected the credentials: {0}")]
+    Unauthorized(String),
+
+    /// The provider could not be reached.
+    #[error("failed to reach the provider: {0}")]
+    Network(#[source] reqwest::Error),
+
+    /// The provider is not set up correctly, e.g. a wrong base URL or an unknown model.
+    #[error("the provider is misconfigured: {0}")]
+    ProviderMisconfigured(String),
+
+    /// Any other error response of an OpenAI-compatible provider.
+    #[error("the provider returned {status}: {message}")]
+    Provider { status: u16, message: String },
+}
+
+/// The category of an [ApiClientError], telling the user what they can do about it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    AuthExpired,
+    QuotaExceeded,
+    ContextOverflow,
+    Network,
+    ProviderMisconfigured,
+    Service,
+}
+
+impl ErrorKind {
+    /// A stable, machine-readable code for the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::AuthExpired => "auth_expired",
+            ErrorKind::QuotaExceeded => "quota_exceeded",
+            ErrorKind::ContextOverflow => "context_overflow",
+            ErrorKind::Network => "network_error",
+            ErrorKind::ProviderMisconfigured => "provider_misconfigured",
+            ErrorKind::Service => "service_error",
+        }
+    }
+
+    /// What the user can do to fix the error.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            ErrorKind::AuthExpired => {
+                Some("Run `q login` to sign in again, or check your provider's API key in the openai.api.key setting.")
+            },
+            ErrorKind::QuotaExceeded => Some("Wait a moment before trying again."),
+            ErrorKind::ContextOverflow => {
+                Some("Run /compact to summarize the conversation, or /clear to start a new one.")
+            },
+            ErrorKind::Network => Some("Check your internet connection and the network.proxy setting, then try again."),
+            ErrorKind::ProviderMisconfigured => {
+                Some("Check the provider settings with `q settings list`, e.g. openai.api.baseUrl and openai.model.")
+            },
+            ErrorKind::Service => None,
+        }
+    }
+}
+
+impl ApiClientError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ApiClientError::Credentials(_) | ApiClientError::AuthError(_) | ApiClientError::Unauthorized(_) => {
+                ErrorKind::AuthExpired
+            },
+            ApiClientError::GenerateCompletions(err) => http_error_kind(err),
+            ApiClientError::GenerateRecommendations(err) => http_error_kind(err),
+            ApiClientError::ListAvailableCustomizations(err) => http_error_kind(err),
+            ApiClientError::ListAvailableServices(err) => http_error_kind(err),
+            ApiClientError::CodewhispererGenerateAssistantResponse(err) => http_error_kind(err),
+            ApiClientError::QDeveloperSendMessage(err) => http_error_kind(err),
+            ApiClientError::ListAvailableProfilesError(err) => http_error_kind(err),
+            ApiClientError::CodewhispererChatResponseStream(err) => stream_error_kind(err),
+            ApiClientError::QDeveloperChatResponseStream(err) => stream_error_kind(err),
+            ApiClientError::QuotaBreach(_) => ErrorKind::QuotaExceeded,
+            ApiClientError::ContextWindowOverflow => ErrorKind::ContextOverflow,
+            ApiClientError::Network(_) => ErrorKind::Network,
+            ApiClientError::ProviderMisconfigured(_) => ErrorKind::ProviderMisconfigured,
+            ApiClientError::SmithyBuild(_) | ApiClientError::Provider { .. } => ErrorKind::Service,
+        }
+    }
+
+    /// Whether logging in again with `q login` can fix the error, i.e. the bearer token of the
+    /// Amazon Q service is missing or was rejected even after refreshing it.
+    pub fn requires_login(&self) -> bool {
+        match self {
+            ApiClientError::AuthError(_) => true,
+            ApiClientError::CodewhispererGenerateAssistantResponse(_) => self.kind() == ErrorKind::AuthExpired,
+            _ => false,
+        }
+    }
+
+    /// Classifies an error response of an OpenAI-compatible provider.
+    pub fn from_provider_response(status: u16, body: String) -> Self {
+        const CONTEXT_OVERFLOW_MARKERS: [&str; 3] =
+            ["context_length_exceeded", "maximum context length", "too many tokens"];
+
+        match status {
+            401 | 403 => ApiClientError::Unauthorized(body),
+            429 => ApiClientError::QuotaBreach("quota has reached its limit"),
+            400 | 413
+                if CONTEXT_OVERFLOW_MARKERS
+                    .iter()
+                    .any(|marker| body.to_lowercase().contains(marker)) =>
+            {
+                ApiClientError::ContextWindowOverflow
+            },
+            404 => ApiClientError::ProviderMisconfigured(format!("the endpoint or model was not found: {body}")),
+            _ => ApiClientError::Provider { status, message: body },
+        }
+    }
+}
+
+fn http_error_kind<E>(err: &SdkError<E, HttpResponse>) -> ErrorKind {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) => ErrorKind::Network,
+        _ => match err.raw_response().map(|response| response.status().as_u16()) {
+            Some(401 | 403) => ErrorKind::AuthExpired,
+            Some(429) => ErrorKind::QuotaExceeded,
+            _ => ErrorKind::Service,
+        },
+    }
+}
+
+fn stream_error_kind<E>(err: &SdkError<E, RawMessage>) -> ErrorKind {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) => ErrorKind::Network,
+        _ => ErrorKind::Service,
+    }
 }
 
 #[cfg(test)]
@@ -131,7 +259,28 @@ mod tests {
     fn test_errors() {
         for error in all_errors() {
             let _ = error.source();
-            println!("{error} {error:?}");
+            println!("{error} {error:?} {}", error.kind().code());
         }
     }
+
+    #[test]
+    fn test_requires_login() {
+        assert!(ApiClientError::AuthError(AuthError::NoToken).requires_login());
+        assert!(!ApiClientError::Unauthorized("invalid api key".to_string()).requires_login());
+        assert!(!ApiClientError::ContextWindowOverflow.requires_login());
+    }
+
+    #[test]
+    fn test_from_provider_response() {
+        let kind = |status, body: &str| ApiClientError::from_provider_response(status, body.to_string()).kind();
+        assert_eq!(kind(401, "invalid api key"), ErrorKind::AuthExpired);
+        assert_eq!(kind(429, "rate limited"), ErrorKind::QuotaExceeded);
+        assert_eq!(
+            kind(400, r#"{"error":{"code":"context_length_exceeded"}}"#),
+            ErrorKind::ContextOverflow
+        );
+        assert_eq!(kind(400, "invalid request"), ErrorKind::Service);
+        assert_eq!(kind(404, "model not found"), ErrorKind::ProviderMisconfigured);
+        assert_eq!(kind(500, "internal error"), ErrorKind::Service);
+    }
 }
@@ -11,8 +11,7 @@ use crate::database::settings::Setting;
 fn is_codewhisperer_content_optout(database: &Database) -> bool {
     !database
         .settings
-        .get_bool(Setting::ShareCodeWhispererContent)
-        .unwrap_or(true)
+        .get_bool_or_default(Setting::ShareCodeWhispererContent)
 }
 
 #[derive(Debug, Clone)]
@@ -13,5 +13,8 @@ pub use clients::{
     StreamingClient,
 };
 pub use endpoints::Endpoint;
-pub use error::ApiClientError;
+pub use error::{
+    ApiClientError,
+    ErrorKind,
+};
 pub use profile::list_available_profiles;
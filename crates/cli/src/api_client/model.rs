@@ -26,15 +26,88 @@ pub struct FileContext {
     pub programming_language: ProgrammingLanguage,
 }
 
+impl FileContext {
+    /// The context of a file, in the language detected from `filename` or from the shebang at the
+    /// start of `left_file_content`, and in [LanguageName::Plaintext] when neither tells.
+    pub fn new(filename: String, left_file_content: String, right_file_content: String) -> Self {
+        let language_name = std::path::Path::new(&filename)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(LanguageName::from_extension)
+            .or_else(|| left_file_content.lines().next().and_then(LanguageName::from_shebang))
+            .unwrap_or(LanguageName::Plaintext);
+        Self {
+            left_file_content,
+            right_file_content,
+            filename,
+            programming_language: ProgrammingLanguage { language_name },
+        }
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ProgrammingLanguage {
     pub language_name: LanguageName,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, strum::AsRefStr)]
-#[serde(rename_all = "lowercase")]
-#[strum(serialize_all = "lowercase")]
+/// Languages by the extensions of their files. The names without a variant of [LanguageName] end
+/// up in [LanguageName::Other].
+const EXTENSIONS: &[(&str, &[&str])] = &[
+    ("python", &["py", "pyi", "pyw"]),
+    ("javascript", &["js", "jsx", "mjs", "cjs"]),
+    ("java", &["java"]),
+    ("csharp", &["cs"]),
+    ("typescript", &["ts", "tsx", "mts", "cts"]),
+    ("c", &["c", "h"]),
+    ("cpp", &["cpp", "cc", "cxx", "hpp", "hh", "hxx"]),
+    ("go", &["go"]),
+    ("kotlin", &["kt", "kts"]),
+    ("php", &["php"]),
+    ("ruby", &["rb"]),
+    ("rust", &["rs"]),
+    ("scala", &["scala", "sc"]),
+    ("shell", &["sh", "bash", "zsh", "fish", "ksh"]),
+    ("sql", &["sql"]),
+    ("dart", &["dart"]),
+    ("hcl", &["hcl"]),
+    ("json", &["json", "jsonc"]),
+    ("lua", &["lua"]),
+    ("powershell", &["ps1", "psm1"]),
+    ("r", &["r"]),
+    ("swift", &["swift"]),
+    ("systemverilog", &["sv", "svh"]),
+    ("tf", &["tf"]),
+    ("vue", &["vue"]),
+    ("yaml", &["yaml", "yml"]),
+    ("clojure", &["clj", "cljs", "cljc"]),
+    ("elixir", &["ex", "exs"]),
+    ("erlang", &["erl", "hrl"]),
+    ("fsharp", &["fs", "fsi", "fsx"]),
+    ("haskell", &["hs", "lhs"]),
+    ("julia", &["jl"]),
+    ("objectivec", &["m", "mm"]),
+    ("ocaml", &["ml", "mli"]),
+    ("perl", &["pl", "pm"]),
+    ("zig", &["zig"]),
+];
+
+/// Languages by the interpreters of their scripts.
+const INTERPRETERS: &[(&str, &[&str])] = &[
+    ("python", &["python"]),
+    ("javascript", &["node", "deno", "bun"]),
+    ("ruby", &["ruby"]),
+    ("php", &["php"]),
+    ("shell", &["sh", "bash", "zsh", "fish", "ksh", "dash"]),
+    ("lua", &["lua"]),
+    ("powershell", &["pwsh"]),
+    ("r", &["Rscript"]),
+    ("haskell", &["runghc", "runhaskell"]),
+    ("perl", &["perl"]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(from = "String", into = "String")]
 pub enum LanguageName {
     Python,
     Javascript,
@@ -51,6 +124,134 @@ pub enum LanguageName {
     Scala,
     Shell,
     Sql,
+    Dart,
+    Hcl,
+    Json,
+    Lua,
+    Powershell,
+    R,
+    Swift,
+    Systemverilog,
+    Tf,
+    Vue,
+    Yaml,
+    /// Text that isn't in a language that could be detected.
+    Plaintext,
+    /// A language without its own variant, by its name, e.g. `haskell`.
+    Other(String),
+}
+
+impl LanguageName {
+    const NAMED: [LanguageName; 27] = [
+        Self::Python,
+        Self::Javascript,
+        Self::Java,
+        Self::Csharp,
+        Self::Typescript,
+        Self::C,
+        Self::Cpp,
+        Self::Go,
+        Self::Kotlin,
+        Self::Php,
+        Self::Ruby,
+        Self::Rust,
+        Self::Scala,
+        Self::Shell,
+        Self::Sql,
+        Self::Dart,
+        Self::Hcl,
+        Self::Json,
+        Self::Lua,
+        Self::Powershell,
+        Self::R,
+        Self::Swift,
+        Self::Systemverilog,
+        Self::Tf,
+        Self::Vue,
+        Self::Yaml,
+        Self::Plaintext,
+    ];
+
+    /// The language named `name`, e.g. `rust`.
+    pub fn from_name(name: &str) -> Self {
+        let name = name.to_ascii_lowercase();
+        Self::NAMED
+            .iter()
+            .find(|language| language.as_ref() == name)
+            .cloned()
+            .unwrap_or(Self::Other(name))
+    }
+
+    /// The language of a file with the extension `extension`, e.g. `rs`.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let extension = extension.to_ascii_lowercase();
+        EXTENSIONS
+            .iter()
+            .find(|(_, extensions)| extensions.contains(&extension.as_str()))
+            .map(|(name, _)| Self::from_name(name))
+    }
+
+    /// The language of the interpreter of a shebang line, e.g. `#!/usr/bin/env python3`.
+    pub fn from_shebang(line: &str) -> Option<Self> {
+        let mut command = line.strip_prefix("#!")?.split_whitespace();
+        let mut interpreter = command.next()?;
+        if interpreter.ends_with("/env") {
+            interpreter = command.find(|arg| !arg.starts_with('-'))?;
+        }
+        let name = interpreter.rsplit('/').next().unwrap_or_default();
+        let name = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        INTERPRETERS
+            .iter()
+            .find(|(_, interpreters)| interpreters.contains(&name))
+            .map(|(language, _)| Self::from_name(language))
+    }
+}
+
+impl AsRef<str> for LanguageName {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Python => "python",
+            Self::Javascript => "javascript",
+            Self::Java => "java",
+            Self::Csharp => "csharp",
+            Self::Typescript => "typescript",
+            Self::C => "c",
+            Self::Cpp => "cpp",
+            Self::Go => "go",
+            Self::Kotlin => "kotlin",
+            Self::Php => "php",
+            Self::Ruby => "ruby",
+            Self::Rust => "rust",
+            Self::Scala => "scala",
+            Self::Shell => "shell",
+            Self::Sql => "sql",
+            Self::Dart => "dart",
+            Self::Hcl => "hcl",
+            Self::Json => "json",
+            Self::Lua => "lua",
+            Self::Powershell => "powershell",
+            Self::R => "r",
+            Self::Swift => "swift",
+            Self::Systemverilog => "systemverilog",
+            Self::Tf => "tf",
+            Self::Vue => "vue",
+            Self::Yaml => "yaml",
+            Self::Plaintext => "plaintext",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+impl From<String> for LanguageName {
+    fn from(name: String) -> Self {
+        Self::from_name(&name)
+    }
+}
+
+impl From<LanguageName> for String {
+    fn from(language: LanguageName) -> Self {
+        language.as_ref().to_string()
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -86,6 +287,8 @@ pub struct RecommendationsOutput {
 #[serde(rename_all = "camelCase")]
 pub struct Recommendation {
     pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<CodeReference>,
 }
 
 // =========
@@ -97,6 +300,8 @@ pub struct ConversationState {
     pub conversation_id: Option<String>,
     pub user_input_message: UserInputMessage,
     pub history: Option<Vec<ChatMessage>>,
+    /// JSON schema the response must conform to, enforced by the providers supporting it.
+    pub response_schema: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone)]
@@ -541,6 +746,18 @@ impl TryFrom<AssistantResponseMessage> for amzn_qdeveloper_streaming_client::typ
     }
 }
 
+/// Tokens of a request, including the input tokens served from the provider's prompt cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    /// Input tokens read from the prompt cache, billed at a fraction of the price.
+    pub cached_input_tokens: u64,
+    /// Input tokens written to the prompt cache, for providers that charge for it.
+    pub cache_write_tokens: u64,
+    /// Tokens of the response.
+    pub output_tokens: u64,
+}
+
 #[non_exhaustive]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ChatResponseStream {
@@ -551,9 +768,15 @@ pub enum ChatResponseStream {
     CodeEvent {
         content: String,
     },
+    /// Licensed code the previous part of the response matches.
+    CodeReferenceEvent {
+        references: Vec<CodeReference>,
+    },
+    /// A prompt the user may want to send next, empty if the event had none.
+    FollowupPromptEvent {
+        content: String,
+    },
     // TODO: finish events here
-    CodeReferenceEvent(()),
-    FollowupPromptEvent(()),
     IntentsEvent(()),
     InvalidStateEvent {
         reason: String,
@@ -563,13 +786,18 @@ pub enum ChatResponseStream {
         conversation_id: Option<String>,
         utterance_id: Option<String>,
     },
-    SupplementaryWebLinksEvent(()),
+    /// Pages the response is based on.
+    SupplementaryWebLinksEvent {
+        links: Vec<WebLink>,
+    },
     ToolUseEvent {
         tool_use_id: String,
         name: String,
         input: Option<String>,
         stop: Option<bool>,
     },
+    /// Tokens billed for the request, sent by some providers at the end of the response.
+    UsageEvent(TokenUsage),
 
     #[non_exhaustive]
     Unknown,
@@ -584,11 +812,15 @@ impl From<amzn_codewhisperer_streaming_client::types::ChatResponseStream> for Ch
             amzn_codewhisperer_streaming_client::types::ChatResponseStream::CodeEvent(
                 amzn_codewhisperer_streaming_client::types::CodeEvent { content, .. },
             ) => ChatResponseStream::CodeEvent { content },
-            amzn_codewhisperer_streaming_client::types::ChatResponseStream::CodeReferenceEvent(_) => {
-                ChatResponseStream::CodeReferenceEvent(())
+            amzn_codewhisperer_streaming_client::types::ChatResponseStream::CodeReferenceEvent(
+                amzn_codewhisperer_streaming_client::types::CodeReferenceEvent { references, .. },
+            ) => ChatResponseStream::CodeReferenceEvent {
+                references: references.unwrap_or_default().into_iter().map(Into::into).collect(),
             },
-            amzn_codewhisperer_streaming_client::types::ChatResponseStream::FollowupPromptEvent(_) => {
-                ChatResponseStream::FollowupPromptEvent(())
+            amzn_codewhisperer_streaming_client::types::ChatResponseStream::FollowupPromptEvent(
+                amzn_codewhisperer_streaming_client::types::FollowupPromptEvent { followup_prompt, .. },
+            ) => ChatResponseStream::FollowupPromptEvent {
+                content: followup_prompt.map(|prompt| prompt.content).unwrap_or_default(),
             },
             amzn_codewhisperer_streaming_client::types::ChatResponseStream::IntentsEvent(_) => {
                 ChatResponseStream::IntentsEvent(())
@@ -623,8 +855,17 @@ impl From<amzn_codewhisperer_streaming_client::types::ChatResponseStream> for Ch
                 input,
                 stop,
             },
-            amzn_codewhisperer_streaming_client::types::ChatResponseStream::SupplementaryWebLinksEvent(_) => {
-                ChatResponseStream::SupplementaryWebLinksEvent(())
+            amzn_codewhisperer_streaming_client::types::ChatResponseStream::SupplementaryWebLinksEvent(
+                amzn_codewhisperer_streaming_client::types::SupplementaryWebLinksEvent {
+                    supplementary_web_links,
+                    ..
+                },
+            ) => ChatResponseStream::SupplementaryWebLinksEvent {
+                links: supplementary_web_links
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
             },
             _ => ChatResponseStream::Unknown,
         }
@@ -640,11 +881,15 @@ impl From<amzn_qdeveloper_streaming_client::types::ChatResponseStream> for ChatR
             amzn_qdeveloper_streaming_client::types::ChatResponseStream::CodeEvent(
                 amzn_qdeveloper_streaming_client::types::CodeEvent { content, .. },
             ) => ChatResponseStream::CodeEvent { content },
-            amzn_qdeveloper_streaming_client::types::ChatResponseStream::CodeReferenceEvent(_) => {
-                ChatResponseStream::CodeReferenceEvent(())
+            amzn_qdeveloper_streaming_client::types::ChatResponseStream::CodeReferenceEvent(
+                amzn_qdeveloper_streaming_client::types::CodeReferenceEvent { references, .. },
+            ) => ChatResponseStream::CodeReferenceEvent {
+                references: references.unwrap_or_default().into_iter().map(Into::into).collect(),
             },
-            amzn_qdeveloper_streaming_client::types::ChatResponseStream::FollowupPromptEvent(_) => {
-                ChatResponseStream::FollowupPromptEvent(())
+            amzn_qdeveloper_streaming_client::types::ChatResponseStream::FollowupPromptEvent(
+                amzn_qdeveloper_streaming_client::types::FollowupPromptEvent { followup_prompt, .. },
+            ) => ChatResponseStream::FollowupPromptEvent {
+                content: followup_prompt.map(|prompt| prompt.content).unwrap_or_default(),
             },
             amzn_qdeveloper_streaming_client::types::ChatResponseStream::IntentsEvent(_) => {
                 ChatResponseStream::IntentsEvent(())
@@ -679,14 +924,89 @@ impl From<amzn_qdeveloper_streaming_client::types::ChatResponseStream> for ChatR
                 input,
                 stop,
             },
-            amzn_qdeveloper_streaming_client::types::ChatResponseStream::SupplementaryWebLinksEvent(_) => {
-                ChatResponseStream::SupplementaryWebLinksEvent(())
+            amzn_qdeveloper_streaming_client::types::ChatResponseStream::SupplementaryWebLinksEvent(
+                amzn_qdeveloper_streaming_client::types::SupplementaryWebLinksEvent {
+                    supplementary_web_links,
+                    ..
+                },
+            ) => ChatResponseStream::SupplementaryWebLinksEvent {
+                links: supplementary_web_links
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
             },
             _ => ChatResponseStream::Unknown,
         }
     }
 }
 
+/// Attribution of code in a response to a licensed repository.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeReference {
+    pub license_name: Option<String>,
+    pub repository: Option<String>,
+    pub url: Option<String>,
+}
+
+impl From<amzn_codewhisperer_streaming_client::types::Reference> for CodeReference {
+    fn from(value: amzn_codewhisperer_streaming_client::types::Reference) -> Self {
+        Self {
+            license_name: value.license_name,
+            repository: value.repository,
+            url: value.url,
+        }
+    }
+}
+
+impl From<amzn_codewhisperer_client::types::Reference> for CodeReference {
+    fn from(value: amzn_codewhisperer_client::types::Reference) -> Self {
+        Self {
+            license_name: value.license_name,
+            repository: value.repository,
+            url: value.url,
+        }
+    }
+}
+
+impl From<amzn_qdeveloper_streaming_client::types::Reference> for CodeReference {
+    fn from(value: amzn_qdeveloper_streaming_client::types::Reference) -> Self {
+        Self {
+            license_name: value.license_name,
+            repository: value.repository,
+            url: value.url,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebLink {
+    pub url: String,
+    pub title: String,
+    pub snippet: Option<String>,
+}
+
+impl From<amzn_codewhisperer_streaming_client::types::SupplementaryWebLink> for WebLink {
+    fn from(value: amzn_codewhisperer_streaming_client::types::SupplementaryWebLink) -> Self {
+        Self {
+            url: value.url,
+            title: value.title,
+            snippet: value.snippet,
+        }
+    }
+}
+
+impl From<amzn_qdeveloper_streaming_client::types::SupplementaryWebLink> for WebLink {
+    fn from(value: amzn_qdeveloper_streaming_client::types::SupplementaryWebLink) -> Self {
+        Self {
+            url: value.url,
+            title: value.title,
+            snippet: value.snippet,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct EnvState {
     pub operating_system: Option<String>,
@@ -1072,7 +1392,7 @@ mod tests {
         );
         assert_eq!(
             ChatResponseStream::from(code_reference_event),
-            ChatResponseStream::CodeReferenceEvent(())
+            ChatResponseStream::CodeReferenceEvent { references: vec![] }
         );
 
         let code_reference_event = amzn_qdeveloper_streaming_client::types::ChatResponseStream::CodeReferenceEvent(
@@ -1080,7 +1400,29 @@ mod tests {
         );
         assert_eq!(
             ChatResponseStream::from(code_reference_event),
-            ChatResponseStream::CodeReferenceEvent(())
+            ChatResponseStream::CodeReferenceEvent { references: vec![] }
+        );
+
+        let code_reference_event = amzn_codewhisperer_streaming_client::types::ChatResponseStream::CodeReferenceEvent(
+            amzn_codewhisperer_streaming_client::types::CodeReferenceEvent::builder()
+                .references(
+                    amzn_codewhisperer_streaming_client::types::Reference::builder()
+                        .license_name("MIT")
+                        .repository("octo/repo")
+                        .url("https://github.com/octo/repo")
+                        .build(),
+                )
+                .build(),
+        );
+        assert_eq!(
+            ChatResponseStream::from(code_reference_event),
+            ChatResponseStream::CodeReferenceEvent {
+                references: vec![CodeReference {
+                    license_name: Some("MIT".into()),
+                    repository: Some("octo/repo".into()),
+                    url: Some("https://github.com/octo/repo".into()),
+                }]
+            }
         );
 
         let followup_prompt_event = amzn_codewhisperer_streaming_client::types::ChatResponseStream::FollowupPromptEvent(
@@ -1088,7 +1430,7 @@ mod tests {
         );
         assert_eq!(
             ChatResponseStream::from(followup_prompt_event),
-            ChatResponseStream::FollowupPromptEvent(())
+            ChatResponseStream::FollowupPromptEvent { content: String::new() }
         );
 
         let followup_prompt_event = amzn_qdeveloper_streaming_client::types::ChatResponseStream::FollowupPromptEvent(
@@ -1096,7 +1438,24 @@ mod tests {
         );
         assert_eq!(
             ChatResponseStream::from(followup_prompt_event),
-            ChatResponseStream::FollowupPromptEvent(())
+            ChatResponseStream::FollowupPromptEvent { content: String::new() }
+        );
+
+        let followup_prompt_event = amzn_qdeveloper_streaming_client::types::ChatResponseStream::FollowupPromptEvent(
+            amzn_qdeveloper_streaming_client::types::FollowupPromptEvent::builder()
+                .followup_prompt(
+                    amzn_qdeveloper_streaming_client::types::FollowupPrompt::builder()
+                        .content("How do I test it?")
+                        .build()
+                        .unwrap(),
+                )
+                .build(),
+        );
+        assert_eq!(
+            ChatResponseStream::from(followup_prompt_event),
+            ChatResponseStream::FollowupPromptEvent {
+                content: "How do I test it?".into()
+            }
         );
 
         let intents_event = amzn_codewhisperer_streaming_client::types::ChatResponseStream::IntentsEvent(
@@ -1174,7 +1533,7 @@ mod tests {
             );
         assert_eq!(
             ChatResponseStream::from(user_input_event),
-            ChatResponseStream::SupplementaryWebLinksEvent(())
+            ChatResponseStream::SupplementaryWebLinksEvent { links: vec![] }
         );
 
         let user_input_event = amzn_qdeveloper_streaming_client::types::ChatResponseStream::SupplementaryWebLinksEvent(
@@ -1182,7 +1541,7 @@ mod tests {
         );
         assert_eq!(
             ChatResponseStream::from(user_input_event),
-            ChatResponseStream::SupplementaryWebLinksEvent(())
+            ChatResponseStream::SupplementaryWebLinksEvent { links: vec![] }
         );
 
         let user_input_event = amzn_codewhisperer_streaming_client::types::ChatResponseStream::ToolUseEvent(
@@ -1219,4 +1578,36 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn detect_language_name() {
+        assert_eq!(LanguageName::from_extension("rs"), Some(LanguageName::Rust));
+        assert_eq!(LanguageName::from_extension("TSX"), Some(LanguageName::Typescript));
+        assert_eq!(
+            LanguageName::from_extension("hs"),
+            Some(LanguageName::Other("haskell".to_string()))
+        );
+        assert_eq!(LanguageName::from_extension("dart"), Some(LanguageName::Dart));
+        assert_eq!(LanguageName::from_extension("md"), None);
+        assert_eq!(
+            LanguageName::from_shebang("#!/usr/bin/env python3"),
+            Some(LanguageName::Python)
+        );
+        assert_eq!(
+            LanguageName::from_shebang("#!/usr/bin/env -S node --no-warnings"),
+            Some(LanguageName::Javascript)
+        );
+        assert_eq!(LanguageName::from_shebang("#!/bin/bash -e"), Some(LanguageName::Shell));
+        assert_eq!(LanguageName::from_shebang("# comment"), None);
+        assert_eq!(LanguageName::Csharp.as_ref(), "csharp");
+
+        let context = FileContext::new("notes.txt".to_string(), "a".to_string(), String::new());
+        assert_eq!(context.programming_language.language_name, LanguageName::Plaintext);
+        assert_eq!(
+            serde_json::to_value(&context.programming_language).unwrap(),
+            serde_json::json!({ "languageName": "plaintext" })
+        );
+        let language: ProgrammingLanguage = serde_json::from_str(r#"{"languageName": "Haskell"}"#).unwrap();
+        assert_eq!(language.language_name, LanguageName::Other("haskell".to_string()));
+    }
 }
@@ -330,6 +330,22 @@ impl BuilderIdToken {
         }
     }
 
+    /// Refresh the stored token even if it has not expired yet, e.g. after the service rejected it.
+    ///
+    /// Returns [None] when there is no token to refresh and the user has to log in again.
+    pub async fn force_refresh(database: &Database) -> Result<Option<Self>, AuthError> {
+        let Some(secret) = database.get_secret(Self::SECRET_KEY).await? else {
+            return Ok(None);
+        };
+        let Some(token) = serde_json::from_str::<Option<Self>>(&secret.0)? else {
+            return Ok(None);
+        };
+
+        let region = token.region.clone().map_or(OIDC_BUILDER_ID_REGION, Region::new);
+        let client = client(region.clone());
+        token.refresh_token(&client, database, &region).await
+    }
+
     /// Refresh the access token
     pub async fn refresh_token(
         &self,
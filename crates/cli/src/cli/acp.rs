@@ -0,0 +1,585 @@
+//! `q acp`, serving the agent over the Agent Client Protocol so that editors can embed it:
+//! JSON-RPC messages, one per line, on stdin and stdout.
+//!
+//! The editor starts a session with `session/new` and sends prompts with `session/prompt`. The
+//! response streams back as `session/update` notifications, tool uses that need approval are sent
+//! to the editor with `session/request_permission`, and file edits come with a diff that the
+//! editor can show natively.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+use std::sync::{
+    Arc,
+    Mutex,
+};
+
+use clap::Args;
+use eyre::{
+    Result,
+    bail,
+};
+use serde::Deserialize;
+use serde_json::{
+    Value,
+    json,
+};
+use tokio::io::{
+    AsyncBufReadExt,
+    AsyncWriteExt,
+    BufReader,
+};
+use tokio::sync::{
+    Notify,
+    mpsc,
+    oneshot,
+};
+use tracing::{
+    debug,
+    warn,
+};
+
+use crate::api_client::StreamingClient;
+use crate::cli::chat::agent_session::{
+    AgentEvent,
+    AgentSession,
+    ClientMessage,
+    error_kind,
+};
+use crate::database::Database;
+use crate::platform::Context;
+
+const PROTOCOL_VERSION: u64 = 1;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+#[derive(Debug, Args, PartialEq, Eq)]
+pub struct AcpArgs {}
+
+impl AcpArgs {
+    pub async fn execute(&self, database: &mut Database) -> Result<ExitCode> {
+        let client = StreamingClient::new(database).await?;
+        let (output_tx, mut output_rx) = mpsc::unbounded_channel::<Value>();
+        let writer = tokio::spawn(async move {
+            let mut stdout = tokio::io::stdout();
+            while let Some(message) = output_rx.recv().await {
+                let line = format!("{message}\n");
+                if stdout.write_all(line.as_bytes()).await.is_err() || stdout.flush().await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let agent = Agent::new(client, output_tx);
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Some(line) = lines.next_line().await? {
+            agent.handle_line(&line);
+        }
+
+        drop(agent);
+        writer.await?;
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// An error of a prompt turn, with the machine-readable code and the hint of provider errors
+    /// as `data`.
+    fn from_turn(err: &eyre::Report) -> Self {
+        let mut rpc_error = Self::new(INTERNAL_ERROR, err.to_string());
+        rpc_error.data = error_kind(err).map(|kind| json!({ "code": kind.code(), "hint": kind.hint() }));
+        rpc_error
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NewSessionParams {
+    cwd: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptParams {
+    session_id: String,
+    prompt: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CancelParams {
+    session_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    /// A file embedded by the editor, e.g. one mentioned with `@`.
+    Resource {
+        resource: EmbeddedResource,
+    },
+    ResourceLink {
+        uri: String,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddedResource {
+    uri: String,
+    text: Option<String>,
+}
+
+struct AcpSession {
+    approvals: mpsc::Sender<ClientMessage>,
+    /// Locked while a prompt runs.
+    inner: Arc<tokio::sync::Mutex<SessionInner>>,
+    cancel: Arc<Notify>,
+}
+
+struct SessionInner {
+    agent: AgentSession,
+    approvals: mpsc::Receiver<ClientMessage>,
+}
+
+/// The result of a request sent to the editor, the error object when it failed.
+type ClientResponse = oneshot::Sender<Result<Value, Value>>;
+
+#[derive(Clone)]
+struct Agent {
+    client: StreamingClient,
+    output: mpsc::UnboundedSender<Value>,
+    sessions: Arc<Mutex<HashMap<String, AcpSession>>>,
+    /// The requests sent to the editor waiting for a response, by id.
+    pending: Arc<Mutex<HashMap<u64, ClientResponse>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Agent {
+    fn new(client: StreamingClient, output: mpsc::UnboundedSender<Value>) -> Self {
+        Self {
+            client,
+            output,
+            sessions: Default::default(),
+            pending: Default::default(),
+            next_id: Default::default(),
+        }
+    }
+
+    fn handle_line(&self, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        let message = match serde_json::from_str::<Value>(line) {
+            Ok(message) => message,
+            Err(err) => {
+                self.respond(Value::Null, Err(RpcError::new(PARSE_ERROR, err.to_string())));
+                return;
+            },
+        };
+
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // A response to a request sent to the editor.
+            let sender = id
+                .as_ref()
+                .and_then(Value::as_u64)
+                .and_then(|id| self.pending.lock().unwrap().remove(&id));
+            if let Some(sender) = sender {
+                let _ = sender.send(match message.get("error") {
+                    Some(error) => Err(error.clone()),
+                    None => Ok(message.get("result").cloned().unwrap_or_default()),
+                });
+            }
+            return;
+        };
+
+        // Requests are handled concurrently so that a running prompt doesn't hold up the others.
+        let (agent, method) = (self.clone(), method.to_string());
+        let params = message.get("params").cloned().unwrap_or_default();
+        tokio::spawn(async move {
+            debug!(?method, "Handling request");
+            let result = agent.handle_request(&method, params).await;
+            // Notifications have no id and get no response.
+            if let Some(id) = id {
+                agent.respond(id, result);
+            }
+        });
+    }
+
+    async fn handle_request(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "agentCapabilities": {
+                    "loadSession": false,
+                    "promptCapabilities": { "image": false, "audio": false, "embeddedContext": true },
+                },
+                "authMethods": [],
+            })),
+            "session/new" => self.new_session(parse_params(params)?).await,
+            "session/prompt" => self.prompt(parse_params(params)?).await,
+            "session/cancel" => {
+                let params: CancelParams = parse_params(params)?;
+                if let Some(session) = self.sessions.lock().unwrap().get(&params.session_id) {
+                    session.cancel.notify_waiters();
+                }
+                Ok(Value::Null)
+            },
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method '{method}'"))),
+        }
+    }
+
+    async fn new_session(&self, params: NewSessionParams) -> Result<Value, RpcError> {
+        // Tools resolve relative paths against the directory of the session, and run commands in it.
+        let ctx = Context::new();
+        if let Err(err) = ctx.fs().read_dir(&params.cwd).await {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                format!("Invalid cwd {}: {err}", params.cwd.display()),
+            ));
+        }
+        let ctx = ctx.with_current_dir(params.cwd);
+
+        let database = Database::new()
+            .await
+            .map_err(|err| RpcError::new(INTERNAL_ERROR, format!("Failed to open the database: {err}")))?;
+        let agent = AgentSession::new(ctx, &database, self.client.clone())
+            .await
+            .map_err(|err| RpcError::new(INTERNAL_ERROR, format!("Failed to create the session: {err}")))?;
+
+        let session_id = agent.conversation_id().to_string();
+        let (approvals_tx, approvals_rx) = mpsc::channel(16);
+        self.sessions.lock().unwrap().insert(session_id.clone(), AcpSession {
+            approvals: approvals_tx,
+            inner: Arc::new(tokio::sync::Mutex::new(SessionInner {
+                agent,
+                approvals: approvals_rx,
+            })),
+            cancel: Arc::new(Notify::new()),
+        });
+        Ok(json!({ "sessionId": session_id }))
+    }
+
+    async fn prompt(&self, params: PromptParams) -> Result<Value, RpcError> {
+        let session_id = params.session_id;
+        let (approvals_tx, inner, cancel) = {
+            let sessions = self.sessions.lock().unwrap();
+            let session = sessions
+                .get(&session_id)
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("No session with id '{session_id}'")))?;
+            (
+                session.approvals.clone(),
+                Arc::clone(&session.inner),
+                Arc::clone(&session.cancel),
+            )
+        };
+        let mut inner = inner
+            .try_lock_owned()
+            .map_err(|_err| RpcError::new(INVALID_REQUEST, "A prompt is already running in this session"))?;
+
+        let SessionInner { agent, approvals } = &mut *inner;
+        // Approvals sent while no prompt was running can't be for this prompt.
+        while approvals.try_recv().is_ok() {}
+
+        let (event_tx, mut event_rx) = mpsc::channel(64);
+        let turn = async move { agent.run_turn(prompt_text(&params.prompt), approvals, &event_tx).await };
+        let forward = async {
+            while let Some(event) = event_rx.recv().await {
+                self.forward_event(&session_id, event, &approvals_tx).await;
+            }
+        };
+
+        tokio::select! {
+            (result, ()) = async { tokio::join!(turn, forward) } => match result {
+                Ok(()) => Ok(json!({ "stopReason": "end_turn" })),
+                Err(err) => Err(RpcError::from_turn(&err)),
+            },
+            _ = cancel.notified() => Ok(json!({ "stopReason": "cancelled" })),
+        }
+    }
+
+    /// Sends an event of a running prompt to the editor, asking it for approval when needed.
+    async fn forward_event(&self, session_id: &str, event: AgentEvent, approvals: &mpsc::Sender<ClientMessage>) {
+        let update = match event {
+            AgentEvent::Text { content } => json!({
+                "sessionUpdate": "agent_message_chunk",
+                "content": { "type": "text", "text": content },
+            }),
+            AgentEvent::ToolUse {
+                id,
+                name,
+                input,
+                requires_approval,
+                diff,
+            } => {
+                let mut tool_call = json!({
+                    "sessionUpdate": "tool_call",
+                    "toolCallId": id,
+                    "title": tool_title(&name, &input),
+                    "kind": tool_kind(&name),
+                    "status": "pending",
+                    "rawInput": input,
+                });
+                if let Some(diff) = diff {
+                    tool_call["content"] = json!([{
+                        "type": "diff",
+                        "path": diff.path,
+                        "oldText": diff.old_text,
+                        "newText": diff.new_text,
+                    }]);
+                    tool_call["locations"] = json!([{ "path": diff.path }]);
+                }
+                self.notify(session_id, tool_call);
+
+                if requires_approval {
+                    let approved = self.request_permission(session_id, &id).await;
+                    let _ = approvals.send(ClientMessage::ToolApproval { id, approved }).await;
+                }
+                return;
+            },
+            AgentEvent::ToolResult { id, success, content } => json!({
+                "sessionUpdate": "tool_call_update",
+                "toolCallId": id,
+                "status": if success { "completed" } else { "failed" },
+                "content": [{ "type": "content", "content": { "type": "text", "text": content } }],
+            }),
+            AgentEvent::Error { message, .. } => {
+                warn!(?message, "Error while running a prompt");
+                return;
+            },
+            AgentEvent::TurnStart { .. } | AgentEvent::Done => return,
+        };
+        self.notify(session_id, update);
+    }
+
+    /// Asks the editor whether the tool call `tool_call_id` may run.
+    async fn request_permission(&self, session_id: &str, tool_call_id: &str) -> bool {
+        let response = self
+            .request(
+                "session/request_permission",
+                json!({
+                    "sessionId": session_id,
+                    "toolCall": { "toolCallId": tool_call_id },
+                    "options": [
+                        { "optionId": "allow", "name": "Allow", "kind": "allow_once" },
+                        { "optionId": "reject", "name": "Reject", "kind": "reject_once" },
+                    ],
+                }),
+            )
+            .await;
+        match response {
+            Ok(response) => response["outcome"]["outcome"] == "selected" && response["outcome"]["optionId"] == "allow",
+            Err(err) => {
+                warn!(?err, "Failed to request a permission, denying the tool call");
+                false
+            },
+        }
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        match rx.await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(error)) => bail!("{method} failed: {error}"),
+            Err(_) => bail!("{method} got no response"),
+        }
+    }
+
+    fn notify(&self, session_id: &str, update: Value) {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": "session/update",
+            "params": { "sessionId": session_id, "update": update },
+        }));
+    }
+
+    fn respond(&self, id: Value, result: Result<Value, RpcError>) {
+        self.send(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => {
+                let mut error = json!({ "code": err.code, "message": err.message });
+                if let Some(data) = err.data {
+                    error["data"] = data;
+                }
+                json!({ "jsonrpc": "2.0", "id": id, "error": error })
+            },
+        });
+    }
+
+    fn send(&self, message: Value) {
+        // Fails only once stdout is closed, when there is no one left to tell.
+        let _ = self.output.send(message);
+    }
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+/// Joins the content of a prompt into the text sent to the model.
+fn prompt_text(prompt: &[ContentBlock]) -> String {
+    let mut text = String::new();
+    for block in prompt {
+        match block {
+            ContentBlock::Text { text: block } => text.push_str(block),
+            ContentBlock::Resource { resource } => match &resource.text {
+                Some(content) => text.push_str(&format!("\n\n--- {} ---\n{content}\n", resource.uri)),
+                None => text.push_str(&format!(" {} ", resource.uri)),
+            },
+            ContentBlock::ResourceLink { uri } => text.push_str(&format!(" {uri} ")),
+            ContentBlock::Unsupported => {},
+        }
+    }
+    text.trim().to_string()
+}
+
+fn tool_kind(name: &str) -> &'static str {
+    match name {
+        "fs_read" => "read",
+        "code_symbols" => "search",
+        "fs_write" | "refactor_rename" => "edit",
+        "execute_bash" | "iac_plan" => "execute",
+        "web_browse" | "web_crawl" | "download_file" => "fetch",
+        "thinking" => "think",
+        _ => "other",
+    }
+}
+
+fn tool_title(name: &str, input: &Value) -> String {
+    match (input.get("command"), input.get("path")) {
+        (_, Some(Value::String(path))) => format!("{name}: {path}"),
+        (Some(Value::String(command)), _) if name == "execute_bash" => command.clone(),
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_client::model::ChatResponseStream;
+
+    #[test]
+    fn test_prompt_text() {
+        let prompt: Vec<ContentBlock> = serde_json::from_value(json!([
+            { "type": "text", "text": "Explain" },
+            { "type": "resource", "resource": { "uri": "file:///src/main.rs", "text": "fn main() {}" } },
+            { "type": "image", "data": "..." },
+        ]))
+        .unwrap();
+        assert_eq!(
+            prompt_text(&prompt),
+            "Explain\n\n--- file:///src/main.rs ---\nfn main() {}"
+        );
+    }
+
+    #[test]
+    fn test_tool_title() {
+        assert_eq!(
+            tool_title("fs_write", &json!({"command": "create", "path": "a.rs"})),
+            "fs_write: a.rs"
+        );
+        assert_eq!(tool_title("execute_bash", &json!({"command": "ls"})), "ls");
+        assert_eq!(tool_title("use_aws", &json!({})), "use_aws");
+    }
+
+    /// Sends `request` and returns the messages sent back until its response.
+    async fn request(agent: &Agent, output: &mut mpsc::UnboundedReceiver<Value>, request: Value) -> Vec<Value> {
+        agent.handle_line(&request.to_string());
+        let mut messages = Vec::new();
+        loop {
+            let message = output.recv().await.unwrap();
+            let done = message["id"] == request["id"];
+            messages.push(message);
+            if done {
+                return messages;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompt() {
+        let client = StreamingClient::mock(vec![vec![ChatResponseStream::AssistantResponseEvent {
+            content: "Hello!".to_string(),
+        }]]);
+        let (output_tx, mut output_rx) = mpsc::unbounded_channel();
+        let agent = Agent::new(client, output_tx);
+        let process_dir = std::env::current_dir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = dir.path();
+
+        let request_json = json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}});
+        let response = request(&agent, &mut output_rx, request_json).await;
+        assert_eq!(response[0]["result"]["protocolVersion"], PROTOCOL_VERSION);
+
+        let response = request(
+            &agent,
+            &mut output_rx,
+            json!({
+                "jsonrpc": "2.0", "id": 2, "method": "session/new", "params": {"cwd": cwd, "mcpServers": []}
+            }),
+        )
+        .await;
+        let session_id = response[0]["result"]["sessionId"].clone();
+        // The session runs in its directory, the one of the process doesn't change.
+        assert_eq!(std::env::current_dir().unwrap(), process_dir);
+
+        let response = request(
+            &agent,
+            &mut output_rx,
+            json!({
+                "jsonrpc": "2.0", "id": 3, "method": "session/new",
+                "params": {"cwd": cwd.join("missing"), "mcpServers": []}
+            }),
+        )
+        .await;
+        assert_eq!(response[0]["error"]["code"], INVALID_PARAMS);
+
+        let messages = request(
+            &agent,
+            &mut output_rx,
+            json!({
+                "jsonrpc": "2.0", "id": 4, "method": "session/prompt",
+                "params": {"sessionId": session_id, "prompt": [{"type": "text", "text": "Hi"}]}
+            }),
+        )
+        .await;
+        assert_eq!(messages[0]["method"], "session/update");
+        assert_eq!(messages[0]["params"]["update"]["content"]["text"], "Hello!");
+        assert_eq!(messages[1]["result"]["stopReason"], "end_turn");
+
+        let request_json = json!({"jsonrpc": "2.0", "id": 5, "method": "session/load", "params": {}});
+        let response = request(&agent, &mut output_rx, request_json).await;
+        assert_eq!(response[0]["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,541 @@
+//! `q audit`, the tamper-evident log of the tools used in chat sessions.
+//!
+//! When chat.auditLog is enabled, every tool the model asks to use is appended to a JSON lines
+//! file with its arguments, whether it was trusted, approved or rejected, and a digest of the
+//! result sent back to the model. Every entry holds the hash of the previous one and its own hash,
+//! so changing or removing an entry breaks the chain from there on, which `q audit verify` reports.
+//! Nothing outside the log anchors its end though: entries cut from the end leave a valid chain,
+//! and aren't detected. An entry torn by a crash doesn't stop the log: later entries are chained to
+//! the last valid one, and `q audit verify` reports the torn entry.
+
+use std::fs::{
+    File,
+    OpenOptions,
+};
+use std::io::{
+    BufRead,
+    BufReader,
+    Read as _,
+    Seek as _,
+    SeekFrom,
+    Write as _,
+};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anstream::{
+    eprintln,
+    println,
+};
+use clap::Subcommand;
+use crossterm::style::Stylize;
+use eyre::{
+    Result,
+    WrapErr,
+};
+use fd_lock::RwLock;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use time::OffsetDateTime;
+use time::macros::format_description;
+use tracing::warn;
+
+use super::OutputFormat;
+use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::platform::Context;
+use crate::util::directories;
+
+/// The previous hash of the first entry.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Bytes read at a time from the end of the log, looking for the last entry.
+const TAIL_BLOCK_SIZE: u64 = 4096;
+
+/// Characters of the arguments shown per entry by `q audit show`.
+const MAX_ARGS_CHARS: usize = 80;
+
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum AuditSubcommand {
+    /// Show the last tool invocations
+    Show {
+        /// Number of invocations to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Output format
+        #[arg(long, short, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Check that no entry of the log was changed or removed, except from its end
+    Verify,
+}
+
+impl AuditSubcommand {
+    pub fn execute(self) -> Result<ExitCode> {
+        let log = AuditLog::new(directories::audit_log_path(&Context::new())?);
+        match self {
+            AuditSubcommand::Show { limit, format } => {
+                let entries = log.entries()?;
+                let entries = &entries[entries.len().saturating_sub(limit)..];
+                format.print(
+                    || {
+                        if entries.is_empty() {
+                            return "No tool invocations were recorded, enable the log with `q settings chat.auditLog true`"
+                                .to_string();
+                        }
+                        entries.iter().map(AuditEntry::summary).collect::<Vec<_>>().join("\n")
+                    },
+                    || entries,
+                );
+                Ok(ExitCode::SUCCESS)
+            },
+            AuditSubcommand::Verify => match log.verify()? {
+                Ok(count) => {
+                    println!(
+                        "{} The {count} entries of {} are intact",
+                        "✓".green(),
+                        log.path.display()
+                    );
+                    Ok(ExitCode::SUCCESS)
+                },
+                Err(broken) => {
+                    eprintln!("{} {}: {broken}", "✗".red(), log.path.display());
+                    Ok(ExitCode::FAILURE)
+                },
+            },
+        }
+    }
+}
+
+/// How the use of a tool was decided.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Run without asking, because the tool is trusted or only reads.
+    Trusted,
+    /// Run after the user approved it.
+    Approved,
+    /// Not run, because the user rejected it or couldn't be asked.
+    Rejected,
+}
+
+impl Decision {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Decision::Trusted => "trusted",
+            Decision::Approved => "approved",
+            Decision::Rejected => "rejected",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+    pub conversation_id: String,
+    pub tool_use_id: String,
+    pub tool: String,
+    pub args: serde_json::Value,
+    pub decision: Decision,
+    /// Whether the tool succeeded, unset if it wasn't run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+    /// SHA-256 of the result sent to the model, unset if the tool wasn't run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_digest: Option<String>,
+    /// Hash of the previous entry, [GENESIS_HASH] for the first one.
+    #[serde(default)]
+    pub prev_hash: String,
+    /// SHA-256 of the entry without this field, set when it is appended.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hash: String,
+}
+
+impl AuditEntry {
+    pub fn new(
+        conversation_id: &str,
+        tool_use_id: &str,
+        tool: &str,
+        args: serde_json::Value,
+        decision: Decision,
+    ) -> Self {
+        Self {
+            time: OffsetDateTime::now_utc()
+                .replace_nanosecond(0)
+                .unwrap_or(OffsetDateTime::now_utc()),
+            conversation_id: conversation_id.to_string(),
+            tool_use_id: tool_use_id.to_string(),
+            tool: tool.to_string(),
+            args,
+            decision,
+            success: None,
+            result_digest: None,
+            prev_hash: String::new(),
+            hash: String::new(),
+        }
+    }
+
+    /// Records the result of the tool, as serialized for the model.
+    pub fn with_result(mut self, success: bool, result: &[u8]) -> Self {
+        self.success = Some(success);
+        self.result_digest = Some(digest(result));
+        self
+    }
+
+    /// The hash the entry should have.
+    fn compute_hash(&self) -> Result<String> {
+        let unhashed = Self {
+            hash: String::new(),
+            ..self.clone()
+        };
+        Ok(digest(&serde_json::to_vec(&unhashed)?))
+    }
+
+    fn summary(&self) -> String {
+        let status = match self.success {
+            Some(true) => "✓".green(),
+            Some(false) => "✗".red(),
+            None => "-".dark_grey(),
+        };
+        let mut args = self.args.to_string();
+        if args.chars().count() > MAX_ARGS_CHARS {
+            args = args.chars().take(MAX_ARGS_CHARS - 1).chain(['…']).collect();
+        }
+        let time = self
+            .time
+            .format(format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"))
+            .unwrap_or_default();
+        format!(
+            "{} {status} {:<8} {:<16} {}",
+            time.dark_grey(),
+            self.decision.as_str(),
+            self.tool,
+            args.dark_grey()
+        )
+    }
+}
+
+/// Where a chain of entries stops being valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokenChain {
+    /// Line of the first invalid entry, starting at 1.
+    pub line: usize,
+    pub reason: String,
+}
+
+impl std::fmt::Display for BrokenChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "entry {} {}", self.line, self.reason)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The log in the default location if chat.auditLog is enabled.
+    pub fn from_database(ctx: &Context, database: &Database) -> Option<Self> {
+        if !database.settings.get_bool_or_default(Setting::ChatAuditLog) {
+            return None;
+        }
+        match directories::audit_log_path(ctx) {
+            Ok(path) => Some(Self::new(path)),
+            Err(err) => {
+                warn!(?err, "Failed to find the path of the audit log");
+                None
+            },
+        }
+    }
+
+    /// Chains the entry to the last one of the log and appends it.
+    ///
+    /// The log is locked from reading the last entry until the new one is written, so that the
+    /// entries of concurrent chat sessions still form a single chain. If the last entry is invalid,
+    /// e.g. torn by a crash, the entry is chained to the last valid one instead.
+    pub fn append(&self, mut entry: AuditEntry) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file_opts = OpenOptions::new();
+        file_opts.create(true).read(true).append(true);
+        // The entries hold the arguments of the tools, e.g. whole commands and file contents.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            file_opts.mode(0o600);
+        }
+        let file = file_opts
+            .open(&self.path)
+            .wrap_err_with(|| format!("Failed to open {}", self.path.display()))?;
+        let mut lock = RwLock::new(file);
+        let mut file = lock
+            .write()
+            .wrap_err_with(|| format!("Failed to lock {}", self.path.display()))?;
+
+        entry.prev_hash = match last_line(&mut file)? {
+            Some(line) => match serde_json::from_str::<AuditEntry>(&line) {
+                Ok(last) => last.hash,
+                Err(err) => {
+                    warn!(?err, path = ?self.path, "The last entry of the audit log is invalid");
+                    end_line(&mut file)?;
+                    last_valid_hash(&mut file)?
+                },
+            },
+            None => GENESIS_HASH.to_string(),
+        };
+        entry.hash = entry.compute_hash()?;
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        Ok(())
+    }
+
+    /// The valid entries of the log, oldest first.
+    pub fn entries(&self) -> Result<Vec<AuditEntry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut entries = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // Torn entries are reported by `q audit verify`, show the others
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(err) => warn!(?err, line = index + 1, "Skipping an invalid entry of the audit log"),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Checks the chain of the log, returning the number of entries if it is intact.
+    pub fn verify(&self) -> Result<Result<usize, BrokenChain>> {
+        match File::open(&self.path) {
+            Ok(file) => Ok(verify_chain(BufReader::new(file))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Ok(0)),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// The last non-empty line of `file`, read from the end so that appending to the log doesn't get
+/// slower as it grows.
+fn last_line(file: &mut File) -> std::io::Result<Option<String>> {
+    let mut end = file.seek(SeekFrom::End(0))?;
+    let mut tail = Vec::new();
+    while end > 0 {
+        let start = end.saturating_sub(TAIL_BLOCK_SIZE);
+        let mut block = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut block)?;
+        block.extend_from_slice(&tail);
+        tail = block;
+        end = start;
+
+        // The last line is complete once the newline before it was read
+        let content = tail.trim_ascii_end();
+        if let Some(newline) = content.iter().rposition(|byte| *byte == b'\n') {
+            return Ok(Some(String::from_utf8_lossy(&content[newline + 1..]).into_owned()));
+        }
+    }
+    let content = tail.trim_ascii();
+    Ok((!content.is_empty()).then(|| String::from_utf8_lossy(content).into_owned()))
+}
+
+/// Ends the last line of `file` if it was cut short, so that the next entry starts on its own line.
+fn end_line(file: &mut File) -> std::io::Result<()> {
+    if file.seek(SeekFrom::End(0))? == 0 {
+        return Ok(());
+    }
+    let mut last = [0];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    if last[0] != b'\n' {
+        file.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// The hash of the last valid entry of `file`, [GENESIS_HASH] if there is none. Reads the whole
+/// log, so it's only used once the last entry turned out invalid.
+fn last_valid_hash(file: &mut File) -> std::io::Result<String> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut hash = GENESIS_HASH.to_string();
+    for line in BufReader::new(file).lines() {
+        if let Ok(entry) = serde_json::from_str::<AuditEntry>(&line?) {
+            hash = entry.hash;
+        }
+    }
+    Ok(hash)
+}
+
+fn verify_chain(reader: impl BufRead) -> Result<usize, BrokenChain> {
+    let mut prev_hash = GENESIS_HASH.to_string();
+    let mut count = 0;
+    for (index, line) in reader.lines().enumerate() {
+        let broken = |reason: String| BrokenChain {
+            line: index + 1,
+            reason,
+        };
+        let line = line.map_err(|err| broken(format!("can't be read: {err}")))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str::<AuditEntry>(&line).map_err(|err| broken(format!("is invalid: {err}")))?;
+        if entry.prev_hash != prev_hash {
+            return Err(broken(
+                "doesn't follow the previous entry, which was removed or changed".to_string(),
+            ));
+        }
+        if entry.compute_hash().ok().as_ref() != Some(&entry.hash) {
+            return Err(broken("was changed after it was recorded".to_string()));
+        }
+        prev_hash = entry.hash;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn digest(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(tool: &str, decision: Decision) -> AuditEntry {
+        AuditEntry::new(
+            "conversation",
+            "tooluse_1",
+            tool,
+            serde_json::json!({ "path": "/a" }),
+            decision,
+        )
+    }
+
+    #[test]
+    fn test_append_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("audit.jsonl"));
+        assert_eq!(log.verify().unwrap(), Ok(0));
+
+        log.append(entry("fs_read", Decision::Trusted).with_result(true, b"contents"))
+            .unwrap();
+        log.append(entry("fs_write", Decision::Rejected)).unwrap();
+        log.append(entry("execute_bash", Decision::Approved).with_result(false, b"error"))
+            .unwrap();
+
+        let entries = log.entries().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].prev_hash, GENESIS_HASH);
+        assert_eq!(entries[1].prev_hash, entries[0].hash);
+        assert_eq!(entries[0].result_digest, Some(digest(b"contents")));
+        assert_eq!(log.verify().unwrap(), Ok(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_log_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("audit.jsonl"));
+        log.append(entry("execute_bash", Decision::Approved)).unwrap();
+        let metadata = std::fs::metadata(dir.path().join("audit.jsonl")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_last_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        let long_line = "x".repeat(TAIL_BLOCK_SIZE as usize * 2);
+        for (contents, expected) in [
+            ("", None),
+            ("\n\n", None),
+            ("first", Some("first")),
+            ("first\nsecond\n\n", Some("second")),
+            (&format!("first\n{long_line}\n") as &str, Some(long_line.as_str())),
+        ] {
+            std::fs::write(&path, contents).unwrap();
+            let mut file = File::open(&path).unwrap();
+            assert_eq!(last_line(&mut file).unwrap().as_deref(), expected);
+        }
+    }
+
+    #[test]
+    fn test_concurrent_appends_form_a_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("audit.jsonl"));
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..25 {
+                        log.append(entry("fs_read", Decision::Trusted)).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(log.verify().unwrap(), Ok(100));
+    }
+
+    #[test]
+    fn test_verify_detects_tampering() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("audit.jsonl"));
+        for decision in [Decision::Trusted, Decision::Rejected, Decision::Approved] {
+            log.append(entry("fs_write", decision)).unwrap();
+        }
+        let contents = std::fs::read_to_string(&log.path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+
+        std::fs::write(&log.path, contents.replacen("rejected", "approved", 1)).unwrap();
+        assert_eq!(log.verify().unwrap().unwrap_err().line, 2);
+
+        std::fs::write(&log.path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        assert_eq!(log.verify().unwrap().unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn test_append_after_torn_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("audit.jsonl"));
+        log.append(entry("fs_read", Decision::Trusted)).unwrap();
+        let valid = std::fs::read_to_string(&log.path).unwrap();
+
+        // A crash cut the second entry short
+        std::fs::write(&log.path, format!("{valid}{{\"time\":\"2025")).unwrap();
+        log.append(entry("fs_write", Decision::Approved)).unwrap();
+
+        let contents = std::fs::read_to_string(&log.path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        let first = serde_json::from_str::<AuditEntry>(lines[0]).unwrap();
+        let last = serde_json::from_str::<AuditEntry>(lines[2]).unwrap();
+        assert_eq!(last.prev_hash, first.hash);
+        assert_eq!(last.tool, "fs_write");
+
+        assert_eq!(log.entries().unwrap().len(), 2);
+        let broken = log.verify().unwrap().unwrap_err();
+        assert_eq!(broken.line, 2);
+        assert!(broken.reason.starts_with("is invalid"), "{broken}");
+    }
+}
@@ -0,0 +1,154 @@
+//! Screen-reader-friendly output, `chat.accessibilityMode`.
+//!
+//! The output of the chat is written without styling, emoji or box drawing, which screen readers
+//! read out as noise, spinners and the typewriter effect are left out, and the start and end of
+//! tools and the approval prompts are announced with explicit markers. Diffs label their lines as
+//! added or removed in words.
+
+use std::io::{
+    self,
+    Write,
+};
+use std::sync::OnceLock;
+
+use crossterm::{
+    queue,
+    style,
+};
+use similar::ChangeTag;
+
+use super::util::shared_writer::SharedWriter;
+use crate::database::Database;
+use crate::database::settings::Setting;
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+/// Symbols replaced with words, the other symbols and emoji are left out.
+const SYMBOLS: &[(char, &str)] = &[
+    ('✔', "Done:"),
+    ('✓', "Done:"),
+    ('✘', "Failed:"),
+    ('✗', "Failed:"),
+    ('❗', "Warning:"),
+    ('⚠', "Warning:"),
+];
+
+/// Reads `chat.accessibilityMode`. Only the first call has an effect.
+pub fn configure(database: &Database) {
+    let enabled = database.settings.get_bool_or_default(Setting::ChatAccessibilityMode);
+    if enabled {
+        // Also applies to what is printed outside of the output of the chat.
+        style::force_color_output(false);
+        anstream::ColorChoice::Never.write_global();
+    }
+    let _ = ENABLED.set(enabled);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.get().copied().unwrap_or_default()
+}
+
+/// `output` writing plain text, see [plain_text], with the escape sequences of styling and cursor
+/// movements removed.
+pub fn plain_output(output: SharedWriter) -> SharedWriter {
+    SharedWriter::new(strip_ansi_escapes::Writer::new(PlainText {
+        inner: output,
+        pending: Vec::new(),
+    }))
+}
+
+/// Prints `[text]` on its own line in accessibility mode, e.g. when a tool starts.
+pub fn marker(output: &mut impl Write, text: &str) -> io::Result<()> {
+    if enabled() {
+        queue!(output, style::Print(format!("[{text}]\n")))?;
+    }
+    Ok(())
+}
+
+/// How a line of a diff is labelled in accessibility mode.
+pub fn diff_label(tag: ChangeTag) -> &'static str {
+    match tag {
+        ChangeTag::Equal => "unchanged",
+        ChangeTag::Delete => "removed",
+        ChangeTag::Insert => "added",
+    }
+}
+
+/// `text` with the symbols of [SYMBOLS] in words and without other symbols, emoji and box drawing.
+fn plain_text(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    for c in text.chars() {
+        match SYMBOLS.iter().find(|(symbol, _)| *symbol == c) {
+            Some((_, words)) => plain.push_str(words),
+            None if is_decoration(c) => (),
+            None => plain.push(c),
+        }
+    }
+    plain
+}
+
+fn is_decoration(c: char) -> bool {
+    matches!(c,
+        // Vertical ellipsis, e.g. the continuation line of tools.
+        '\u{22EE}'
+        // Box drawing, block elements and geometric shapes, e.g. borders and bullets.
+        | '\u{2500}'..='\u{25FF}'
+        // Miscellaneous symbols and dingbats.
+        | '\u{2600}'..='\u{27BF}'
+        // Braille patterns, e.g. spinners.
+        | '\u{2800}'..='\u{28FF}'
+        // Emoji, with their variation selector and joiner.
+        | '\u{1F300}'..='\u{1FAFF}'
+        | '\u{FE0F}'
+        | '\u{200D}'
+    )
+}
+
+/// Writes [plain_text], keeping a character split between two writes for the next one.
+struct PlainText<W> {
+    inner: W,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Write for PlainText<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let complete = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+        let text = String::from_utf8_lossy(&self.pending[..complete]).into_owned();
+        self.pending.drain(..complete);
+        self.inner.write_all(plain_text(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(
+            plain_text("🛠️  Using tool: fs_read\n ⋮ \n ● Completed in 0.2s"),
+            "  Using tool: fs_read\n  \n  Completed in 0.2s"
+        );
+        assert_eq!(plain_text("✔ Exported • to a.md"), "Done: Exported • to a.md");
+
+        let mut output = PlainText {
+            inner: Vec::new(),
+            pending: Vec::new(),
+        };
+        let bytes = "✔ ok é".as_bytes();
+        for chunk in bytes.chunks(2) {
+            output.write_all(chunk).unwrap();
+        }
+        assert_eq!(String::from_utf8(output.inner).unwrap(), "Done: ok é");
+    }
+}
@@ -0,0 +1,565 @@
+//! An agentic chat session driven by messages instead of a terminal, exposing the full tool use
+//! loop to other frontends such as the `/ws` endpoint of `q server` and `q chat --format json`.
+//!
+//! The client starts a turn with [ClientMessage::Message] and receives [AgentEvent]s while the
+//! turn runs. Tools that would prompt for acceptance in `q chat` only run once the client answers
+//! the matching [AgentEvent::ToolUse] with [ClientMessage::ToolApproval]. Frontends sending the
+//! events as JSON use the versioned schema of [event_schema](super::event_schema).
+
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use serde::Deserialize;
+use tokio::io::{
+    AsyncBufReadExt,
+    AsyncWriteExt,
+    BufReader,
+};
+use tokio::sync::mpsc;
+use tracing::{
+    debug,
+    error,
+};
+
+use super::conversation_state::ConversationState;
+use super::message::{
+    AssistantToolUse,
+    ToolUseResult,
+    ToolUseResultBlock,
+};
+use super::parser::{
+    RecvError,
+    ResponseEvent,
+    ResponseParser,
+};
+use super::tool_manager::ToolManager;
+use super::tools::fs_write::FileChange;
+use super::tools::limits::ToolLimits;
+use super::tools::tool_policy::{
+    PolicyDecision,
+    ToolPolicy,
+};
+use super::tools::{
+    Tool,
+    ToolContext,
+};
+use super::util::shared_writer::SharedWriter;
+use super::{
+    audit_entry,
+    event_schema,
+};
+use crate::api_client::model::ToolResultStatus;
+use crate::api_client::{
+    ApiClientError,
+    ErrorKind,
+    StreamingClient,
+};
+use crate::cli::audit::{
+    AuditLog,
+    Decision,
+};
+use crate::database::Database;
+use crate::platform::Context;
+
+/// Events sent to the client of an [AgentSession].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentEvent {
+    /// A prompt started a turn.
+    TurnStart { conversation_id: String },
+    /// A chunk of the assistant response.
+    Text { content: String },
+    /// The assistant uses a tool. When `requires_approval` is set, the tool only runs once the
+    /// client answers with [ClientMessage::ToolApproval].
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+        requires_approval: bool,
+        /// The change to a file made by `fs_write`.
+        diff: Option<FileChange>,
+    },
+    /// The result of a tool use, as sent back to the model.
+    ToolResult { id: String, success: bool, content: String },
+    /// The assistant finished its turn, the session waits for the next message.
+    Done,
+    Error {
+        message: String,
+        /// A machine-readable code for errors of the model provider, see [ErrorKind::code].
+        code: Option<&'static str>,
+    },
+}
+
+impl AgentEvent {
+    fn error(err: &eyre::Report) -> Self {
+        AgentEvent::Error {
+            message: err.to_string(),
+            code: error_kind(err).map(|kind| kind.code()),
+        }
+    }
+}
+
+/// The category of an error returned by [AgentSession::run_turn], when it was caused by the model
+/// provider.
+pub fn error_kind(err: &eyre::Report) -> Option<ErrorKind> {
+    match err.downcast_ref::<ApiClientError>() {
+        Some(err) => Some(err.kind()),
+        None => err.downcast_ref::<RecvError>().and_then(RecvError::kind),
+    }
+}
+
+/// Messages sent by the client of an [AgentSession].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Starts a turn with a user prompt.
+    Message { content: String },
+    /// Approves or denies a tool use that requires approval.
+    ToolApproval { id: String, approved: bool },
+}
+
+pub struct AgentSession {
+    ctx: Arc<Context>,
+    client: StreamingClient,
+    conversation_state: ConversationState,
+    tool_context: ToolContext,
+    tool_policy: ToolPolicy,
+    tool_limits: ToolLimits,
+    /// Where the tools used are recorded, if chat.auditLog is enabled.
+    audit_log: Option<AuditLog>,
+}
+
+impl AgentSession {
+    /// Creates a session with the native tools, MCP servers are not loaded.
+    pub async fn new(ctx: Arc<Context>, database: &Database, client: StreamingClient) -> Result<Self> {
+        let mut tool_manager = ToolManager::default();
+        let tool_config = tool_manager.load_tools(database, &mut SharedWriter::null()).await?;
+        let conversation_id = uuid::Uuid::new_v4().to_string();
+        let conversation_state = ConversationState::new(
+            Arc::clone(&ctx),
+            &conversation_id,
+            tool_config,
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+        let tool_policy = ToolPolicy::from_database(&ctx, database)?;
+        let audit_log = AuditLog::from_database(&ctx, database);
+
+        Ok(Self {
+            ctx,
+            client,
+            conversation_state,
+            // No terminal is attached to the session, STDIN being the protocol of `q chat --format json`.
+            tool_context: ToolContext {
+                headless: true,
+                ..ToolContext::from_database(database)
+            },
+            tool_policy,
+            tool_limits: ToolLimits::from_database(database),
+            audit_log,
+        })
+    }
+
+    pub fn conversation_id(&self) -> &str {
+        self.conversation_state.conversation_id()
+    }
+
+    /// Runs the session until the client goes away, i.e. `messages` is closed or `events` is
+    /// dropped. The conversation is not saved.
+    pub async fn run(mut self, mut messages: mpsc::Receiver<ClientMessage>, events: mpsc::Sender<AgentEvent>) {
+        while let Some(message) = messages.recv().await {
+            let event = match message {
+                ClientMessage::Message { content } => {
+                    let conversation_id = self.conversation_id().to_string();
+                    if events.send(AgentEvent::TurnStart { conversation_id }).await.is_err() {
+                        return;
+                    }
+                    match self.run_turn(content, &mut messages, &events).await {
+                        Ok(()) => AgentEvent::Done,
+                        Err(err) => AgentEvent::error(&err),
+                    }
+                },
+                ClientMessage::ToolApproval { id, .. } => AgentEvent::Error {
+                    message: format!("Tool use '{id}' is not waiting for approval"),
+                    code: None,
+                },
+            };
+            if events.send(event).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Runs a single turn: sends `prompt` and keeps running the tools used by the assistant until
+    /// it answers without using any. Approvals for the tools are read from `messages`.
+    pub async fn run_turn(
+        &mut self,
+        prompt: String,
+        messages: &mut mpsc::Receiver<ClientMessage>,
+        events: &mpsc::Sender<AgentEvent>,
+    ) -> Result<()> {
+        self.conversation_state.set_next_user_message(prompt).await;
+
+        // Hooks only run for the user prompt, not when sending tool results.
+        let mut run_hooks = true;
+        loop {
+            let conversation_state = self.conversation_state.as_sendable_conversation_state(run_hooks).await;
+            run_hooks = false;
+            let mut parser = ResponseParser::new(self.client.send_message(conversation_state).await?);
+
+            let mut tool_uses = Vec::new();
+            loop {
+                match parser.recv().await? {
+                    ResponseEvent::AssistantText(content) => send(events, AgentEvent::Text { content }).await?,
+                    ResponseEvent::ToolUseStart { .. } => {},
+                    ResponseEvent::ToolUse(tool_use) => tool_uses.push(tool_use),
+                    ResponseEvent::CodeReference { .. }
+                    | ResponseEvent::WebLinks(_)
+                    | ResponseEvent::FollowupPrompt(_) => {},
+                    ResponseEvent::EndStream { message } => {
+                        self.conversation_state.record_assistant_message(message);
+                        break;
+                    },
+                }
+            }
+
+            if tool_uses.is_empty() {
+                return Ok(());
+            }
+
+            let mut tool_results = Vec::new();
+            for tool_use in tool_uses {
+                tool_results.push(self.run_tool(tool_use, messages, events).await?);
+            }
+            self.conversation_state.add_tool_results(tool_results);
+        }
+    }
+
+    async fn run_tool(
+        &mut self,
+        tool_use: AssistantToolUse,
+        messages: &mut mpsc::Receiver<ClientMessage>,
+        events: &mpsc::Sender<AgentEvent>,
+    ) -> Result<ToolUseResult> {
+        debug!(?tool_use, "Running tool use");
+        let id = tool_use.id.clone();
+        let tool_use_event = |requires_approval, diff| AgentEvent::ToolUse {
+            id: tool_use.id.clone(),
+            name: tool_use.name.clone(),
+            input: tool_use.args.clone(),
+            requires_approval,
+            diff,
+        };
+
+        let mut tool = match self
+            .conversation_state
+            .tool_manager
+            .get_tool_from_tool_use(tool_use.clone())
+        {
+            Ok(tool) => tool,
+            Err(err) => {
+                send(events, tool_use_event(false, None)).await?;
+                return report(events, err.into()).await;
+            },
+        };
+        self.tool_context.contextualize(&mut tool);
+        if let Err(err) = tool.validate(&self.ctx).await {
+            send(events, tool_use_event(false, None)).await?;
+            return report(
+                events,
+                error_result(id, format!("Failed to validate tool parameters: {err}")),
+            )
+            .await;
+        }
+
+        let rule = self
+            .tool_policy
+            .evaluate(&self.ctx, &tool_use.name, &tool_use.args, &tool);
+        let requires_approval = match rule {
+            Some(rule) if rule.decision == PolicyDecision::Deny => {
+                send(events, tool_use_event(false, None)).await?;
+                self.audit(&tool_use, Decision::Rejected, None);
+                return report(events, error_result(id, rule.denial(&tool_use.name))).await;
+            },
+            Some(rule) => rule.decision == PolicyDecision::Prompt || tool.always_requires_acceptance(),
+            None => tool.requires_acceptance(&self.ctx),
+        };
+        let diff = match &tool {
+            Tool::FsWrite(fs_write) => fs_write.preview(&self.ctx).await.ok(),
+            _ => None,
+        };
+        send(events, tool_use_event(requires_approval, diff)).await?;
+        if requires_approval && !wait_for_approval(&id, messages, events).await? {
+            self.audit(&tool_use, Decision::Rejected, None);
+            return report(events, error_result(id, "The user denied the tool use".to_string())).await;
+        }
+
+        let result = match tool.invoke(&self.ctx, &mut std::io::sink(), &self.tool_limits).await {
+            Ok(output) => ToolUseResult {
+                tool_use_id: id,
+                content: vec![output.into()],
+                status: ToolResultStatus::Success,
+            },
+            Err(err) => error_result(id, format!("An error occurred processing the tool: \n{err}")),
+        };
+        let decision = match requires_approval {
+            true => Decision::Approved,
+            false => Decision::Trusted,
+        };
+        self.audit(&tool_use, decision, Some(&result));
+        report(events, result).await
+    }
+
+    /// Appends the use of a tool to the audit log, if it is enabled, with its result if it ran.
+    fn audit(&self, tool_use: &AssistantToolUse, decision: Decision, result: Option<&ToolUseResult>) {
+        let Some(audit_log) = &self.audit_log else {
+            return;
+        };
+        let entry = audit_entry(
+            self.conversation_id(),
+            &tool_use.id,
+            &tool_use.name,
+            tool_use.args.clone(),
+            decision,
+            result,
+        );
+        if let Err(err) = audit_log.append(entry) {
+            error!(?err, "Failed to record {} in the audit log", tool_use.name);
+        }
+    }
+}
+
+/// Runs a session over STDIN and STDOUT, for `q chat --format json`. The messages of the client are
+/// read as JSON lines, after `input` as the first prompt, and the events are printed as JSON lines
+/// of the versioned schema. The session ends when STDIN is closed.
+pub async fn run_json_lines(ctx: Arc<Context>, database: &mut Database, input: Option<String>) -> Result<ExitCode> {
+    let client = StreamingClient::new(database).await?;
+    let session = AgentSession::new(ctx, database, client).await?;
+    let (message_tx, message_rx) = mpsc::channel(16);
+    let (event_tx, mut event_rx) = mpsc::channel(64);
+    if let Some(content) = input {
+        message_tx.send(ClientMessage::Message { content }).await?;
+    }
+
+    let read = {
+        let event_tx = event_tx.clone();
+        async move {
+            let mut lines = BufReader::new(tokio::io::stdin()).lines();
+            while let Some(line) = lines.next_line().await? {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<ClientMessage>(&line) {
+                    Ok(message) => {
+                        if message_tx.send(message).await.is_err() {
+                            break;
+                        }
+                    },
+                    Err(err) => {
+                        let message = format!("Invalid message: {err}");
+                        let _ = event_tx.send(AgentEvent::Error { message, code: None }).await;
+                    },
+                }
+            }
+            Ok::<_, eyre::Report>(())
+        }
+    };
+    let write = async move {
+        let mut stdout = tokio::io::stdout();
+        let mut failed = false;
+        while let Some(event) = event_rx.recv().await {
+            failed |= matches!(event, AgentEvent::Error { .. });
+            for event in event_schema::versioned(event) {
+                let mut line = serde_json::to_vec(&event)?;
+                line.push(b'\n');
+                stdout.write_all(&line).await?;
+            }
+            stdout.flush().await?;
+        }
+        Ok::<_, eyre::Report>(failed)
+    };
+
+    let (read, write, ()) = tokio::join!(read, write, session.run(message_rx, event_tx));
+    read?;
+    Ok(match write? {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    })
+}
+
+/// Waits for the client to approve or deny the tool use `id`. Other messages are answered with
+/// an error, since a single turn runs at a time.
+async fn wait_for_approval(
+    id: &str,
+    messages: &mut mpsc::Receiver<ClientMessage>,
+    events: &mpsc::Sender<AgentEvent>,
+) -> Result<bool> {
+    loop {
+        match messages.recv().await {
+            Some(ClientMessage::ToolApproval { id: answered, approved }) if answered == id => return Ok(approved),
+            Some(_) => {
+                send(events, AgentEvent::Error {
+                    message: format!("Waiting for the approval of tool use '{id}'"),
+                    code: None,
+                })
+                .await?;
+            },
+            None => bail!("The client went away while tool use '{id}' was waiting for approval"),
+        }
+    }
+}
+
+async fn send(events: &mpsc::Sender<AgentEvent>, event: AgentEvent) -> Result<()> {
+    events.send(event).await.map_err(|_err| eyre!("The client went away"))
+}
+
+/// Sends the result of a tool use to the client, returning it to be sent to the model.
+async fn report(events: &mpsc::Sender<AgentEvent>, result: ToolUseResult) -> Result<ToolUseResult> {
+    let content = result
+        .content
+        .iter()
+        .map(|block| match block {
+            ToolUseResultBlock::Text(text) => text.clone(),
+            ToolUseResultBlock::Json(value) => value.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    send(events, AgentEvent::ToolResult {
+        id: result.tool_use_id.clone(),
+        success: matches!(result.status, ToolResultStatus::Success),
+        content,
+    })
+    .await?;
+    Ok(result)
+}
+
+fn error_result(tool_use_id: String, message: String) -> ToolUseResult {
+    ToolUseResult {
+        tool_use_id,
+        content: vec![ToolUseResultBlock::Text(message)],
+        status: ToolResultStatus::Error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::cli::chat::create_stream;
+
+    async fn run_session(client: StreamingClient, messages: Vec<ClientMessage>) -> (Arc<Context>, Vec<AgentEvent>) {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let database = Database::new().await.unwrap();
+        let session = AgentSession::new(Arc::clone(&ctx), &database, client).await.unwrap();
+
+        let (message_tx, message_rx) = mpsc::channel(messages.len());
+        for message in messages {
+            message_tx.send(message).await.unwrap();
+        }
+        drop(message_tx);
+
+        let (event_tx, mut event_rx) = mpsc::channel(64);
+        session.run(message_rx, event_tx).await;
+
+        let mut events = Vec::new();
+        while let Some(event) = event_rx.recv().await {
+            events.push(event);
+        }
+        (ctx, events)
+    }
+
+    fn stream(approved: bool) -> StreamingClient {
+        create_stream(json!([
+            [
+                "Sure, I'll create a file for you",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [if approved { "Done!" } else { "Ok, I won't." }],
+        ]))
+    }
+
+    #[tokio::test]
+    async fn test_approved_tool_use() {
+        let (ctx, events) = run_session(stream(true), vec![
+            ClientMessage::Message {
+                content: "create a new file".to_string(),
+            },
+            ClientMessage::ToolApproval {
+                id: "1".to_string(),
+                approved: true,
+            },
+        ])
+        .await;
+
+        assert!(matches!(events[0], AgentEvent::TurnStart { .. }));
+        assert_eq!(events[1], AgentEvent::Text {
+            content: "Sure, I'll create a file for you".to_string()
+        });
+        assert!(
+            matches!(&events[2], AgentEvent::ToolUse { id, name, requires_approval: true, .. }
+            if id == "1" && name == "fs_write")
+        );
+        assert!(matches!(&events[3], AgentEvent::ToolResult { success: true, .. }));
+        assert_eq!(events[4], AgentEvent::Text {
+            content: "Done!".to_string()
+        });
+        assert_eq!(events[5], AgentEvent::Done);
+        assert_eq!(ctx.fs().read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+    }
+
+    #[tokio::test]
+    async fn test_denied_tool_use() {
+        let (ctx, events) = run_session(stream(false), vec![
+            ClientMessage::Message {
+                content: "create a new file".to_string(),
+            },
+            ClientMessage::Message {
+                content: "hello?".to_string(),
+            },
+            ClientMessage::ToolApproval {
+                id: "1".to_string(),
+                approved: false,
+            },
+        ])
+        .await;
+
+        assert!(
+            matches!(&events[3], AgentEvent::Error { message, .. } if message.contains("Waiting for the approval"))
+        );
+        assert!(matches!(&events[4], AgentEvent::ToolResult { success: false, .. }));
+        assert_eq!(events.last(), Some(&AgentEvent::Done));
+        assert!(!ctx.fs().exists("/file.txt"));
+    }
+
+    #[test]
+    fn test_message_format() {
+        let message: ClientMessage =
+            serde_json::from_value(json!({"type": "tool_approval", "id": "1", "approved": true})).unwrap();
+        assert_eq!(message, ClientMessage::ToolApproval {
+            id: "1".to_string(),
+            approved: true
+        });
+        assert_eq!(
+            AgentEvent::error(&ApiClientError::ContextWindowOverflow.into()),
+            AgentEvent::Error {
+                message: "the context window has overflowed".to_string(),
+                code: Some("context_overflow"),
+            }
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Extraction of file contents from code blocks in assistant responses, used by `/apply` for
+//! providers that answer with code instead of calling `fs_write`.
+
+/// A fenced code block annotated with the path of the file it contains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileBlock {
+    pub path: String,
+    pub content: String,
+}
+
+/// Returns every fenced code block in `markdown` that is annotated with a file path.
+///
+/// The path is taken from the info string (e.g. ` ```rust src/main.rs`, ` ```rust:src/main.rs` or
+/// ` ```python title="app.py"`), or otherwise from the line right before the block when it
+/// names a single path (e.g. `**src/main.rs**`, `### src/main.rs` or ``Update `src/main.rs`:``).
+/// When the same file appears more than once, the last block wins.
+pub fn extract_file_blocks(markdown: &str) -> Vec<FileBlock> {
+    let mut blocks: Vec<FileBlock> = Vec::new();
+    let mut previous_line = "";
+    let mut lines = markdown.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        let Some(fence) = opening_fence(trimmed) else {
+            if !trimmed.is_empty() {
+                previous_line = trimmed;
+            }
+            continue;
+        };
+
+        let info = trimmed[fence.len()..].trim();
+        let mut content = String::new();
+        for line in lines.by_ref() {
+            let closing = line.trim_start();
+            if closing.starts_with(fence) && closing[fence.len()..].trim().is_empty() {
+                break;
+            }
+            content.push_str(line);
+            content.push('\n');
+        }
+
+        if let Some(path) = path_from_info_string(info).or_else(|| path_from_preceding_line(previous_line)) {
+            blocks.retain(|block| block.path != path);
+            blocks.push(FileBlock { path, content });
+        }
+        previous_line = "";
+    }
+
+    blocks
+}
+
+/// Returns the fence (three or more backticks or tildes) that opens a code block on `line`.
+fn opening_fence(line: &str) -> Option<&str> {
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.chars().take_while(|c| *c == marker).count();
+    (len >= 3).then(|| &line[..len])
+}
+
+fn path_from_info_string(info: &str) -> Option<String> {
+    for token in info.split_whitespace() {
+        let token = match token.split_once('=') {
+            Some((key, value)) if ["path", "file", "filename", "title"].contains(&key) => value,
+            Some(_) => continue,
+            None => token,
+        };
+        // `rust:src/main.rs`
+        let token = token.split_once(':').map_or(token, |(_, path)| path);
+        let token = token.trim_matches(|c| c == '"' || c == '\'');
+        if looks_like_path(token) {
+            return Some(token.to_string());
+        }
+    }
+    None
+}
+
+fn path_from_preceding_line(line: &str) -> Option<String> {
+    let line = line
+        .trim_start_matches(['#', '>', '-', '*', ' '])
+        .trim_end_matches([':', '*', ' ']);
+
+    // A sentence mentioning a single path in backticks, e.g. "Update `src/main.rs`:"
+    let quoted = line
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .filter(|s| looks_like_path(s))
+        .collect::<Vec<_>>();
+    if let [path] = quoted.as_slice() {
+        return Some((*path).to_string());
+    }
+
+    let line = ["File:", "file:", "Path:", "path:", "Filename:", "filename:"]
+        .iter()
+        .find_map(|prefix| line.strip_prefix(prefix))
+        .unwrap_or(line);
+    let line = line.trim().trim_matches(|c| c == '*' || c == '`' || c == '"');
+    looks_like_path(line).then(|| line.to_string())
+}
+
+fn looks_like_path(s: &str) -> bool {
+    const ALLOWED_PUNCTUATION: [char; 7] = ['.', '/', '_', '-', '~', '@', '+'];
+    const KNOWN_FILES: [&str; 2] = ["Makefile", "Dockerfile"];
+
+    if s.is_empty() || s.ends_with('/') || s.contains("://") {
+        return false;
+    }
+    if !s
+        .chars()
+        .all(|c| c.is_alphanumeric() || ALLOWED_PUNCTUATION.contains(&c))
+    {
+        return false;
+    }
+
+    // Require a directory or an extension, except for well known extension-less files.
+    let name = s.rsplit('/').next().unwrap_or(s);
+    s.contains('/') || (name.contains('.') && !name.ends_with('.')) || KNOWN_FILES.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_file_blocks() {
+        let markdown = indoc::indoc! {r#"
+            Here are the changes.
+
+            ```rust src/main.rs
+            fn main() {}
+            ```
+
+            ```toml:Cargo.toml
+            [package]
+            ```
+
+            **src/lib.rs**
+            ```rust
+            pub fn lib() {}
+            ```
+
+            Update `scripts/build.sh` as follows:
+
+            ~~~bash
+            echo "```"
+            ~~~
+
+            ```python title="app.py"
+            print("hi")
+            ```
+
+            This block has no path:
+            ```rust
+            let x = 1;
+            ```
+        "#};
+
+        let blocks = extract_file_blocks(markdown);
+        let paths = blocks.iter().map(|b| b.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(paths, vec![
+            "src/main.rs",
+            "Cargo.toml",
+            "src/lib.rs",
+            "scripts/build.sh",
+            "app.py"
+        ]);
+        assert_eq!(blocks[0].content, "fn main() {}\n");
+        assert_eq!(blocks[3].content, "echo \"```\"\n");
+    }
+
+    #[test]
+    fn test_last_block_for_a_path_wins() {
+        let markdown = "```rust a.rs\nold\n```\n\n```rust a.rs\nnew\n```\n";
+        assert_eq!(extract_file_blocks(markdown), vec![FileBlock {
+            path: "a.rs".to_string(),
+            content: "new\n".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn test_looks_like_path() {
+        for path in ["src/main.rs", "Cargo.toml", "./a.py", "bin/run", "Makefile"] {
+            assert!(looks_like_path(path), "{}", path);
+        }
+        for path in ["rust", "https://example.com/a.rs", "two words.rs", "src/", "end."] {
+            assert!(!looks_like_path(path), "{}", path);
+        }
+    }
+}
@@ -8,6 +8,7 @@ use clap::{
 };
 
 #[derive(Debug, Clone, PartialEq, Eq, Default, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Chat {
     /// (Deprecated, use --trust-all-tools) Enabling this flag allows the model to execute
     /// all commands without first accepting them.
@@ -15,11 +16,15 @@ pub struct Chat {
     pub accept_all: bool,
     /// Print the first response to STDOUT without interactive mode. This will fail if the
     /// prompt requests permissions to use a tool, unless --trust-all-tools is also used.
-    #[arg(long)]
+    #[arg(short, long)]
     pub no_interactive: bool,
     /// Resumes the previous conversation from this directory.
     #[arg(short, long)]
     pub resume: bool,
+    /// Recovers the conversation of the last session in this directory that crashed or whose
+    /// terminal was closed, including the tool uses waiting for approval
+    #[arg(long, conflicts_with_all = ["resume", "input", "last_command"])]
+    pub recover: bool,
     /// The first question to ask
     pub input: Option<String>,
     /// Context profile to use
@@ -32,6 +37,21 @@ pub struct Chat {
     /// '--trust-tools=fs_read,fs_write', trust no tools: '--trust-tools='
     #[arg(long, value_delimiter = ',', value_name = "TOOL_NAMES")]
     pub trust_tools: Option<Vec<String>>,
+    /// Post the final answer, or the error, to the webhook set in chat.notify.webhookUrl
+    #[arg(long, requires = "no_interactive")]
+    pub notify: bool,
+    /// Answer with a JSON document conforming to the JSON schema in this file, printed to STDOUT.
+    /// The answer is validated, and the model is asked to fix it when it doesn't conform
+    #[arg(long, value_name = "PATH", requires = "no_interactive")]
+    pub schema: Option<String>,
+    /// Show the chat full-screen, with panes for the transcript, the running tool and the context
+    /// usage
+    #[arg(long, conflicts_with = "no_interactive")]
+    pub tui: bool,
+    /// Start with the last command run in the shell, its exit status and output, as recorded by
+    /// `q shell-hook`. The input, if any, is the question asked about it
+    #[arg(long)]
+    pub last_command: bool,
     /// Chat provider to use (amazon-q, openai, or custom provider name)
     #[arg(long, value_name = "PROVIDER")]
     pub provider: Option<String>,
@@ -44,6 +64,103 @@ pub struct Chat {
     /// Model name to use with OpenAI-compatible providers
     #[arg(long, value_name = "MODEL")]
     pub model: Option<String>,
+    /// Chat with a local model (chat.offline.baseUrl, Ollama by default) and disable the tools
+    /// that need network access
+    #[arg(long, conflicts_with_all = ["provider", "api_base_url", "api_key"])]
+    pub offline: bool,
+    /// Leave out the tools that can change files or resources, MCP servers and plugins, and only
+    /// run the commands and AWS operations that read, e.g. to explore an untrusted repository
+    #[arg(long)]
+    pub read_only: bool,
+    /// Describe what fs_write, execute_bash, use_aws and the other tools that change anything
+    /// would do instead of running them, without asking for confirmation, to preview a plan
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Root of the workspace, instead of the current directory. Repeat it for several roots: the
+    /// first one is where relative paths are resolved, and tools using paths outside all of them
+    /// always ask for confirmation
+    #[arg(long = "workspace", value_name = "PATH")]
+    pub workspaces: Vec<String>,
+    /// Run execute_bash, fs_read and fs_write on this host over SSH, e.g. `user@devbox` or an
+    /// alias of ~/.ssh/config, instead of this machine. Overrides chat.remoteHost
+    #[arg(long, value_name = "HOST")]
+    pub remote: Option<String>,
+    /// Run execute_bash, fs_read and fs_write in this running container, with `docker exec`, or in
+    /// the dev container of the current directory with `devcontainer`. Overrides chat.container
+    #[arg(long, value_name = "NAME", conflicts_with = "remote")]
+    pub container: Option<String>,
+    /// MCP config file loaded after the global and workspace ones, its servers replacing theirs.
+    /// Repeat it for several files, the last one taking precedence
+    #[arg(long = "mcp-config", value_name = "FILE")]
+    pub mcp_configs: Vec<String>,
+    /// Print the events of the chat as JSON lines, in the versioned schema of `q server --agent`.
+    /// The input is the first prompt, and the next prompts and the approvals of tools are read as
+    /// JSON lines from STDIN until it is closed
+    #[arg(long, value_enum, default_value_t, conflicts_with_all = ["resume", "recover", "tui", "schema"])]
+    pub format: ChatFormat,
+    /// Set from the global `--verbose` flag, prints the timing of every turn
+    #[arg(skip)]
+    pub verbose: bool,
+    #[command(subcommand)]
+    pub subcommand: Option<ChatSubcommand>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, ValueEnum)]
+pub enum ChatFormat {
+    /// The interactive chat
+    #[default]
+    Text,
+    /// JSON lines of events
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum ChatSubcommand {
+    /// List saved conversations
+    List(ChatList),
+    /// Remove saved conversations
+    #[command(alias = "rm")]
+    Remove(ChatRemove),
+    /// Import a session of another tool as the conversation of the current directory, to continue
+    /// it with `q chat --resume`
+    Import(ChatImport),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+pub struct ChatList {
+    /// Fuzzy search over conversation titles, dates and workspace paths
+    pub query: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+pub struct ChatRemove {
+    /// Workspace paths of the conversations to remove. If omitted, a conversation is selected
+    /// interactively.
+    pub paths: Vec<String>,
+    /// Remove every saved conversation
+    #[arg(long, conflicts_with = "paths")]
+    pub all: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+pub struct ChatImport {
+    /// Path of the session file, e.g. a Claude Code session from ~/.claude/projects or the
+    /// .aider.chat.history.md of Aider
+    pub path: String,
+    /// Format of the session file, detected from its content if omitted
+    #[arg(long, value_enum)]
+    pub format: Option<SessionFormat>,
+    /// Replace the conversation already saved for the current directory
+    #[arg(long, short, default_value_t = false)]
+    pub force: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum SessionFormat {
+    /// JSONL session of Claude Code
+    ClaudeCode,
+    /// Markdown chat history of Aider
+    Aider,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
@@ -62,6 +179,9 @@ pub enum Mcp {
         #[arg(long)]
         name: String,
     },
+    /// Serve fs_read, fs_write, execute_bash and use_aws over MCP on STDIN and STDOUT, for other
+    /// agents to use
+    Serve(McpServe),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Args)]
@@ -113,6 +233,44 @@ pub struct McpImport {
     pub force: bool,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Default, Args)]
+pub struct McpServe {
+    /// Run every tool without approval. Tools that need approval fail otherwise, since MCP can't
+    /// ask for it
+    #[arg(long)]
+    pub trust_all_tools: bool,
+    /// Trust only this set of tools, instead of the ones in chat.trustedTools. Example:
+    /// '--trust-tools=fs_read,fs_write'
+    #[arg(long, value_delimiter = ',', value_name = "TOOL_NAMES")]
+    pub trust_tools: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum Config {
+    /// Export settings, MCP servers and context profiles to a bundle file
+    Export(ConfigExport),
+    /// Import a bundle created by `config export`
+    Import(ConfigImport),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+pub struct ConfigExport {
+    /// Path of the bundle file to write
+    pub path: String,
+    /// Also export secrets, such as API keys and the environment of MCP servers
+    #[arg(long, default_value_t = false)]
+    pub include_secrets: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+pub struct ConfigImport {
+    /// Path of the bundle file to import
+    pub path: String,
+    /// Overwrite existing settings, MCP servers and profiles instead of keeping them
+    #[arg(long, default_value_t = false)]
+    pub force: bool,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
 pub enum Scope {
     Workspace,
@@ -43,7 +43,28 @@ pub enum Command {
         prompt: Option<String>,
         show_summary: bool,
         help: bool,
+        /// Show the summary and the messages it replaces, and ask before replacing them.
+        preview: bool,
+        /// Restore the history replaced by the last compaction.
+        undo: bool,
     },
+    Summarize {
+        format: SummaryFormat,
+    },
+    Snippet {
+        subcommand: SnippetSubcommand,
+    },
+    Plan {
+        subcommand: PlanSubcommand,
+    },
+    Watch {
+        enabled: Option<bool>,
+    },
+    Attach {
+        path: String,
+    },
+    Speak,
+    Apply,
     Tools {
         subcommand: Option<ToolsSubcommand>,
     },
@@ -51,6 +72,16 @@ pub enum Command {
         subcommand: Option<PromptsSubcommand>,
     },
     Usage,
+    Stats,
+    Search {
+        pattern: String,
+    },
+    Scrollback {
+        line: Option<usize>,
+    },
+    Translate {
+        description: String,
+    },
     Load {
         path: String,
     },
@@ -58,9 +89,45 @@ pub enum Command {
         path: String,
         force: bool,
     },
+    /// Writes the transcript, with its secrets and personal information redacted, to `path`.
+    Export {
+        path: String,
+        force: bool,
+    },
+    /// Uploads the redacted transcript to `chat.shareTarget` and prints its link, encrypted with a
+    /// passphrase when `encrypt` is set.
+    Share {
+        encrypt: bool,
+    },
     Mcp,
 }
 
+/// Output format of the shareable summary produced by `/summarize`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SummaryFormat {
+    #[default]
+    Markdown,
+    Jira,
+    Slack,
+}
+
+impl SummaryFormat {
+    const USAGE: &str = "/summarize [--format md|jira|slack]";
+
+    fn parse(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "md" | "markdown" => Ok(Self::Markdown),
+            "jira" => Ok(Self::Jira),
+            "slack" => Ok(Self::Slack),
+            other => Err(format!(
+                "Unknown summary format '{}'.\n\nUsage:\n  {}",
+                other,
+                Self::USAGE
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProfileSubcommand {
     List,
@@ -179,6 +246,7 @@ pub enum ContextSubcommand {
     Hooks {
         subcommand: Option<HooksSubcommand>,
     },
+    Usage,
     Help,
 }
 
@@ -202,7 +270,9 @@ impl ContextSubcommand {
   <em>clear [--global]</em>               <black!>Remove all rules from current profile</black!>
                                  <black!>--global: Remove global rules</black!>
 
-  <em>hooks</em>                          <black!>View and manage context hooks</black!>"};
+  <em>hooks</em>                          <black!>View and manage context hooks</black!>
+
+  <em>usage</em>                          <black!>Show how the context window is spent, by category</black!>"};
     const CLEAR_USAGE: &str = "/context clear [--global]";
     const HOOKS_AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
   <em>hooks help</em>                         <black!>Show an explanation for context hooks commands</black!>
@@ -228,6 +298,7 @@ impl ContextSubcommand {
                                          <black!>--global: Disable all in global hooks</black!>"};
     const REMOVE_USAGE: &str = "/context rm [--global] <path1> [path2...]";
     const SHOW_USAGE: &str = "/context show [--expand]";
+    const USAGE_USAGE: &str = "/context usage";
 
     fn usage_msg(header: impl AsRef<str>) -> String {
         format!("{}\n\n{}", header.as_ref(), Self::AVAILABLE_COMMANDS)
@@ -257,6 +328,7 @@ the output as context to Amazon Q.
 • Profile rules apply only to the current profile
 • Global rules apply across all profiles
 • Context is preserved between chat sessions
+• {{{{cwd}}}}, {{{{git_branch}}}}, {{{{date}}}} and {{{{env:VAR}}}} in context files are replaced when they are sent
 "#,
             Self::AVAILABLE_COMMANDS
         )
@@ -389,6 +461,167 @@ Or if you prefer the long way:
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnippetSubcommand {
+    List,
+    Show {
+        name: String,
+    },
+    /// Saves `content`, or the text written in the editor if it is unset.
+    Save {
+        name: String,
+        workspace: bool,
+        content: Option<String>,
+    },
+    Remove {
+        name: String,
+        workspace: bool,
+    },
+    Help,
+}
+
+impl SnippetSubcommand {
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>                                     <black!>Show an explanation for the snippet command</black!>
+  <em>list</em>                                     <black!>List the snippets of the workspace and the global ones</black!>
+  <em>show <<name>></em>                              <black!>Print a snippet</black!>
+  <em>save [--workspace] <<name>> [text]</em>         <black!>Save the text, or what you write in $EDITOR, as a snippet</black!>
+  <em>rm [--workspace] <<name>></em>                  <black!>Remove a snippet</black!>"};
+    const BASE_COMMAND: &str = color_print::cstr! {"<cyan!>Usage: /snippet [SUBCOMMAND]</cyan!>
+
+<cyan!>Description</cyan!>
+  Manage reusable prompt fragments, inserted in a prompt by writing <em>#name</em>."};
+
+    fn usage_msg(header: impl AsRef<str>) -> String {
+        format!(
+            "{}\n\n{}\n\n{}",
+            header.as_ref(),
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Snippets</magenta,em>
+
+Snippets are prompt fragments you write once and reuse, such as a code review checklist or a bug
+report template. Write <em>#name</em> anywhere in a prompt and it is replaced by the snippet before
+the prompt is sent. Snippets are saved in ~/.aws/amazonq/snippets, or with <em>--workspace</em> in
+.amazonq/snippets of the current directory, whose snippets take precedence.
+
+Snippets can contain {{{{cwd}}}}, {{{{git_branch}}}}, {{{{date}}}} and {{{{env:VAR}}}}, which are replaced with
+their current values when the snippet is expanded.
+
+{}
+
+{}"#,
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+
+    fn parse(command: &str) -> Result<Self, String> {
+        // The text of a snippet is taken as typed, so the arguments are split by hand.
+        let mut args = command.trim();
+        let mut next_word = || {
+            let (word, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            args = rest.trim_start();
+            word
+        };
+        next_word();
+        let subcommand = next_word();
+        let mut workspace = false;
+        let mut name = next_word();
+        while name == "--workspace" {
+            workspace = true;
+            name = next_word();
+        }
+
+        let usage = |usage: &str| {
+            Err(Self::usage_msg(format!(
+                "Invalid /snippet arguments.\n\nUsage:\n  {usage}"
+            )))
+        };
+        Ok(match subcommand.to_lowercase().as_str() {
+            "" | "list" => Self::List,
+            "help" => Self::Help,
+            "show" if !name.is_empty() && !workspace => Self::Show { name: name.to_string() },
+            "show" => return usage("/snippet show <name>"),
+            "save" if !name.is_empty() => Self::Save {
+                name: name.to_string(),
+                workspace,
+                content: Some(args.to_string()).filter(|content| !content.is_empty()),
+            },
+            "save" => return usage("/snippet save [--workspace] <name> [text]"),
+            "rm" | "remove" if !name.is_empty() && args.is_empty() => Self::Remove {
+                name: name.to_string(),
+                workspace,
+            },
+            "rm" | "remove" => return usage("/snippet rm [--workspace] <name>"),
+            other => return Err(Self::usage_msg(format!("Unknown subcommand '{other}'."))),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanSubcommand {
+    /// Asks the model for a plan of the task.
+    Start {
+        task: String,
+    },
+    Show,
+    Continue,
+    Stop,
+    Help,
+}
+
+impl PlanSubcommand {
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>                 <black!>Show an explanation for the plan command</black!>
+  <em><<task>></em>               <black!>Ask for a plan of the task, to approve or edit before it runs</black!>
+  <em>show</em>                 <black!>Show the steps of the plan and which ones are done</black!>
+  <em>continue</em>             <black!>Resume a plan stopped between steps</black!>
+  <em>stop</em>                 <black!>Discard the plan</black!>"};
+    const BASE_COMMAND: &str = color_print::cstr! {"<cyan!>Usage: /plan [SUBCOMMAND | TASK]</cyan!>
+
+<cyan!>Description</cyan!>
+  Plan a task first, then run the approved plan one step at a time."};
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Plans</magenta,em>
+
+For large tasks, <em>/plan <<task>></em> asks Amazon Q for a numbered plan with the tools each step
+will use, before anything is changed. You can run the plan, edit it in $EDITOR or discard it.
+Each step is then sent on its own, and you are asked before the next one starts, so you can stop
+between steps, look at the changes and resume with <em>/plan continue</em>.
+
+{}
+
+{}"#,
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+
+    fn parse(command: &str) -> Self {
+        let task = command
+            .trim()
+            .split_once(char::is_whitespace)
+            .map_or("", |(_, task)| task.trim());
+        match task.to_lowercase().as_str() {
+            "" | "show" => Self::Show,
+            "continue" | "resume" => Self::Continue,
+            "stop" | "cancel" => Self::Stop,
+            "help" => Self::Help,
+            _ => Self::Start { task: task.to_string() },
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PromptsGetCommand {
     pub orig_input: Option<String>,
@@ -451,27 +684,102 @@ impl Command {
                     let mut prompt = None;
                     let show_summary = true;
                     let mut help = false;
+                    let mut preview = false;
+                    let mut undo = false;
+
+                    // Check if "help" or "undo" is the first subcommand
+                    match parts.get(1).map(|part| part.to_lowercase()).as_deref() {
+                        Some("help") => help = true,
+                        Some("undo") => undo = true,
+                        _ => {
+                            // A prompt with an unbalanced quote, e.g. an apostrophe, is taken as is
+                            let args = shlex::split(&parts[1..].join(" "))
+                                .unwrap_or_else(|| parts[1..].iter().map(|part| (*part).to_string()).collect());
+                            let mut remaining_parts = Vec::new();
+                            let mut args = args.into_iter();
+                            while let Some(arg) = args.next() {
+                                match arg.as_str() {
+                                    "--preview" => preview = true,
+                                    "--prompt" => match args.next() {
+                                        Some(value) => prompt = Some(value),
+                                        None => {
+                                            return Err(
+                                                "Invalid /compact arguments.\n\nUsage:\n  /compact [--prompt \"...\"] [--preview]"
+                                                    .to_string(),
+                                            );
+                                        },
+                                    },
+                                    _ => remaining_parts.push(arg),
+                                }
+                            }
 
-                    // Check if "help" is the first subcommand
-                    if parts.len() > 1 && parts[1].to_lowercase() == "help" {
-                        help = true;
-                    } else {
-                        let mut remaining_parts = Vec::new();
-
-                        remaining_parts.extend_from_slice(&parts[1..]);
-
-                        // If we have remaining parts after parsing flags, join them as the prompt
-                        if !remaining_parts.is_empty() {
-                            prompt = Some(remaining_parts.join(" "));
-                        }
+                            // If we have remaining parts after parsing flags, join them as the prompt
+                            if prompt.is_none() && !remaining_parts.is_empty() {
+                                prompt = Some(remaining_parts.join(" "));
+                            }
+                        },
                     }
 
                     Self::Compact {
                         prompt,
                         show_summary,
                         help,
+                        preview,
+                        undo,
                     }
                 },
+                "snippet" | "snippets" => Self::Snippet {
+                    subcommand: SnippetSubcommand::parse(command)?,
+                },
+                "plan" => Self::Plan {
+                    subcommand: PlanSubcommand::parse(command),
+                },
+                "summarize" => {
+                    let mut format = SummaryFormat::default();
+                    let mut args = parts[1..].iter();
+                    while let Some(arg) = args.next() {
+                        match arg.split_once('=') {
+                            Some(("--format", value)) => format = SummaryFormat::parse(value)?,
+                            _ if *arg == "--format" => match args.next() {
+                                Some(value) => format = SummaryFormat::parse(value)?,
+                                None => {
+                                    return Err(format!(
+                                        "Missing summary format.\n\nUsage:\n  {}",
+                                        SummaryFormat::USAGE
+                                    ));
+                                },
+                            },
+                            _ => {
+                                return Err(format!(
+                                    "Invalid /summarize arguments.\n\nUsage:\n  {}",
+                                    SummaryFormat::USAGE
+                                ));
+                            },
+                        }
+                    }
+                    Self::Summarize { format }
+                },
+                "attach" => {
+                    if parts.len() < 2 {
+                        return Err("Invalid /attach arguments.\n\nUsage:\n  /attach <path>".to_string());
+                    }
+                    let path = command.trim_start()["attach".len()..].trim();
+                    Self::Attach {
+                        path: path
+                            .strip_prefix(['\'', '"'])
+                            .and_then(|p| p.strip_suffix(['\'', '"']))
+                            .unwrap_or(path)
+                            .to_string(),
+                    }
+                },
+                "speak" => Self::Speak,
+                "apply" => Self::Apply,
+                "watch" => match parts.get(1).map(|s| s.to_lowercase()).as_deref() {
+                    None => Self::Watch { enabled: None },
+                    Some("on") => Self::Watch { enabled: Some(true) },
+                    Some("off") => Self::Watch { enabled: Some(false) },
+                    Some(_) => return Err("Invalid /watch arguments.\n\nUsage:\n  /watch [on|off]".to_string()),
+                },
                 "acceptall" => {
                     let _ = queue!(
                         output,
@@ -607,6 +915,14 @@ impl Command {
                                 subcommand: ContextSubcommand::Show { expand },
                             }
                         },
+                        "usage" => {
+                            if parts.len() > 2 {
+                                usage_err!(ContextSubcommand::USAGE_USAGE);
+                            }
+                            Self::Context {
+                                subcommand: ContextSubcommand::Usage,
+                            }
+                        },
                         "add" => {
                             // Parse add command with paths and flags
                             let mut global = false;
@@ -819,6 +1135,30 @@ impl Command {
                     }
                 },
                 "usage" => Self::Usage,
+                "stats" => Self::Stats,
+                "search" => {
+                    let pattern = command.trim_start()["search".len()..].trim();
+                    if pattern.is_empty() {
+                        return Err("Invalid /search arguments.\n\nUsage:\n  /search <regex>".to_string());
+                    }
+                    Self::Search {
+                        pattern: pattern.to_string(),
+                    }
+                },
+                "scrollback" => match parts.get(1).map(|line| line.parse()) {
+                    None => Self::Scrollback { line: None },
+                    Some(Ok(line)) if parts.len() == 2 => Self::Scrollback { line: Some(line) },
+                    _ => return Err("Invalid /scrollback arguments.\n\nUsage:\n  /scrollback [line]".to_string()),
+                },
+                "translate" => {
+                    let description = command.trim_start()["translate".len()..].trim();
+                    if description.is_empty() {
+                        return Err("Invalid /translate arguments.\n\nUsage:\n  /translate <description>".to_string());
+                    }
+                    Self::Translate {
+                        description: description.to_string(),
+                    }
+                },
                 "load" => {
                     let Some(path) = parts.get(1) else {
                         return Err("path is required".to_string());
@@ -838,6 +1178,21 @@ impl Command {
                     }
                     Self::Save { path, force }
                 },
+                "export" => {
+                    let force = parts.contains(&"-f") || parts.contains(&"--force");
+                    let Some(path) = parts.iter().skip(1).find(|part| !matches!(**part, "-f" | "--force")) else {
+                        return Err("Invalid /export arguments.\n\nUsage:\n  /export <path> [-f|--force]".to_string());
+                    };
+                    Self::Export {
+                        path: (*path).to_string(),
+                        force,
+                    }
+                },
+                "share" => match &parts[1..] {
+                    [] => Self::Share { encrypt: false },
+                    ["-e" | "--encrypt"] => Self::Share { encrypt: true },
+                    _ => return Err("Invalid /share arguments.\n\nUsage:\n  /share [-e|--encrypt]".to_string()),
+                },
                 "mcp" => Self::Mcp,
                 unknown_command => {
                     let looks_like_path = {
@@ -939,10 +1294,15 @@ mod tests {
         }
         macro_rules! compact {
             ($prompt:expr, $show_summary:expr) => {
+                compact!($prompt, $show_summary, false)
+            };
+            ($prompt:expr, $show_summary:expr, $preview:expr) => {
                 Command::Compact {
                     prompt: $prompt,
                     show_summary: $show_summary,
                     help: false,
+                    preview: $preview,
+                    undo: false,
                 }
             };
         }
@@ -952,6 +1312,94 @@ mod tests {
                 "/compact custom prompt",
                 compact!(Some("custom prompt".to_string()), true),
             ),
+            (
+                "/compact --prompt \"keep the API design\" --preview",
+                compact!(Some("keep the API design".to_string()), true, true),
+            ),
+            (
+                "/compact don't lose the plan",
+                compact!(Some("don't lose the plan".to_string()), true),
+            ),
+            ("/compact undo", Command::Compact {
+                prompt: None,
+                show_summary: true,
+                help: false,
+                preview: false,
+                undo: true,
+            }),
+            ("/plan", Command::Plan {
+                subcommand: PlanSubcommand::Show,
+            }),
+            ("/plan continue", Command::Plan {
+                subcommand: PlanSubcommand::Continue,
+            }),
+            ("/plan Add a --dry-run flag\nto q chat", Command::Plan {
+                subcommand: PlanSubcommand::Start {
+                    task: "Add a --dry-run flag\nto q chat".to_string(),
+                },
+            }),
+            ("/snippet", Command::Snippet {
+                subcommand: SnippetSubcommand::List,
+            }),
+            ("/snippet save review Check  naming\nand tests", Command::Snippet {
+                subcommand: SnippetSubcommand::Save {
+                    name: "review".to_string(),
+                    workspace: false,
+                    content: Some("Check  naming\nand tests".to_string()),
+                },
+            }),
+            ("/snippet save --workspace bug", Command::Snippet {
+                subcommand: SnippetSubcommand::Save {
+                    name: "bug".to_string(),
+                    workspace: true,
+                    content: None,
+                },
+            }),
+            ("/snippet rm --workspace bug", Command::Snippet {
+                subcommand: SnippetSubcommand::Remove {
+                    name: "bug".to_string(),
+                    workspace: true,
+                },
+            }),
+            ("/snippets show review", Command::Snippet {
+                subcommand: SnippetSubcommand::Show {
+                    name: "review".to_string(),
+                },
+            }),
+            ("/summarize", Command::Summarize {
+                format: SummaryFormat::Markdown,
+            }),
+            ("/summarize --format jira", Command::Summarize {
+                format: SummaryFormat::Jira,
+            }),
+            ("/summarize --format=slack", Command::Summarize {
+                format: SummaryFormat::Slack,
+            }),
+            ("/search error\\[E\\d+\\] in  main", Command::Search {
+                pattern: "error\\[E\\d+\\] in  main".to_string(),
+            }),
+            ("/scrollback", Command::Scrollback { line: None }),
+            ("/scrollback 120", Command::Scrollback { line: Some(120) }),
+            ("/translate find files modified last week", Command::Translate {
+                description: "find files modified last week".to_string(),
+            }),
+            ("/export -f transcript.md", Command::Export {
+                path: "transcript.md".to_string(),
+                force: true,
+            }),
+            ("/share", Command::Share { encrypt: false }),
+            ("/share --encrypt", Command::Share { encrypt: true }),
+            ("/attach image.png", Command::Attach {
+                path: "image.png".to_string(),
+            }),
+            ("/attach \"my screenshot.png\"", Command::Attach {
+                path: "my screenshot.png".to_string(),
+            }),
+            ("/speak", Command::Speak),
+            ("/apply", Command::Apply),
+            ("/watch", Command::Watch { enabled: None }),
+            ("/watch on", Command::Watch { enabled: Some(true) }),
+            ("/watch OFF", Command::Watch { enabled: Some(false) }),
             ("/profile list", profile!(ProfileSubcommand::List)),
             (
                 "/profile create new_profile",
@@ -1013,6 +1461,7 @@ mod tests {
                     paths: vec!["p1".into(), "p2".into()]
                 }),
             ),
+            ("/context usage", context!(ContextSubcommand::Usage)),
             ("/context clear", context!(ContextSubcommand::Clear { global: false })),
             (
                 "/context clear --global",
@@ -1092,6 +1541,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_summarize_parse_errors() {
+        let mut stdout = std::io::stdout();
+        for input in ["/summarize --format", "/summarize --format docx", "/summarize extra"] {
+            assert!(Command::parse(input, &mut stdout).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_search_parse_errors() {
+        let mut stdout = std::io::stdout();
+        for input in [
+            "/search",
+            "/search   ",
+            "/scrollback top",
+            "/scrollback 1 2",
+            "/translate",
+            "/export --force",
+            "/share --public",
+        ] {
+            assert!(Command::parse(input, &mut stdout).is_err(), "{}", input);
+        }
+    }
+
     #[test]
     fn test_common_command_suggestions() {
         let mut stdout = std::io::stdout();
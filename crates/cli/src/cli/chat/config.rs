@@ -0,0 +1,353 @@
+//! `q config export` and `q config import`, moving the CLI configuration between machines as a
+//! single JSON bundle.
+//!
+//! A bundle holds the global settings (including trusted tools), the global MCP servers, the
+//! global context configuration and the context profiles. Secrets, i.e. secret settings and the
+//! environment variables of MCP servers, are left out unless `--include-secrets` is passed.
+
+use std::collections::BTreeMap;
+use std::io::Write as _;
+use std::process::ExitCode;
+
+use eyre::{
+    Result,
+    bail,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::{
+    Map,
+    Value,
+};
+
+use super::context::{
+    ContextConfig,
+    load_global_config,
+    profile_context_path,
+};
+use crate::cli::chat::cli::{
+    Config,
+    ConfigExport,
+    ConfigImport,
+};
+use crate::cli::chat::tool_manager::{
+    McpServerConfig,
+    global_mcp_config_path,
+};
+use crate::cli::chat::tools::custom_tool::CustomToolConfig;
+use crate::cli::chat::util::shared_writer::SharedWriter;
+use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::platform::Context;
+use crate::util::directories;
+
+const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigBundle {
+    pub version: u32,
+    #[serde(default)]
+    pub settings: Map<String, Value>,
+    #[serde(default)]
+    pub mcp_servers: BTreeMap<String, CustomToolConfig>,
+    #[serde(default)]
+    pub global_context: Option<ContextConfig>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ContextConfig>,
+}
+
+pub async fn execute_config(database: &mut Database, args: Config) -> Result<ExitCode> {
+    let ctx = Context::new();
+    let mut output = SharedWriter::stdout();
+
+    match args {
+        Config::Export(args) => export_config(&ctx, database, &mut output, args).await?,
+        Config::Import(args) => import_config(&ctx, database, &mut output, args).await?,
+    }
+
+    output.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+pub async fn export_config(
+    ctx: &Context,
+    database: &Database,
+    output: &mut SharedWriter,
+    args: ConfigExport,
+) -> Result<()> {
+    let mut bundle = ConfigBundle {
+        version: BUNDLE_VERSION,
+        settings: export_settings(database.settings.map(), args.include_secrets),
+        ..Default::default()
+    };
+
+    let mcp_path = global_mcp_config_path(ctx)?;
+    if ctx.fs().exists(&mcp_path) {
+        bundle.mcp_servers = McpServerConfig::load_from_file(ctx, &mcp_path)
+            .await?
+            .mcp_servers
+            .into_iter()
+            .collect();
+    }
+    if !args.include_secrets {
+        for (name, server) in &mut bundle.mcp_servers {
+            if let Some(env) = server.env.take() {
+                let mut vars = env.into_keys().collect::<Vec<_>>();
+                vars.sort();
+                writeln!(
+                    output,
+                    "Left out the environment of MCP server '{name}': {}",
+                    vars.join(", ")
+                )?;
+            }
+        }
+    }
+
+    if ctx.fs().exists(directories::chat_global_context_path(ctx)?) {
+        bundle.global_context = Some(load_global_config(ctx).await?);
+    }
+
+    let profiles_dir = directories::chat_profiles_dir(ctx)?;
+    if ctx.fs().exists(&profiles_dir) {
+        let mut read_dir = ctx.fs().read_dir(&profiles_dir).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = profile_context_path(ctx, &name)?;
+            if ctx.fs().exists(&path) {
+                let config = serde_json::from_str(&ctx.fs().read_to_string(&path).await?)?;
+                bundle.profiles.insert(name, config);
+            }
+        }
+    }
+
+    let path = args.path;
+    ctx.fs().write(&path, serde_json::to_string_pretty(&bundle)?).await?;
+    writeln!(
+        output,
+        "✓ Exported {} settings, {} MCP servers and {} profiles to {path}",
+        bundle.settings.len(),
+        bundle.mcp_servers.len(),
+        bundle.profiles.len()
+    )?;
+    if !args.include_secrets {
+        writeln!(
+            output,
+            "  Secrets were not exported, use --include-secrets to export them."
+        )?;
+    }
+    Ok(())
+}
+
+pub async fn import_config(
+    ctx: &Context,
+    database: &mut Database,
+    output: &mut SharedWriter,
+    args: ConfigImport,
+) -> Result<()> {
+    let bundle: ConfigBundle = serde_json::from_str(&ctx.fs().read_to_string(&args.path).await?)?;
+    if bundle.version > BUNDLE_VERSION {
+        bail!(
+            "{} was exported by a newer version (bundle version {}), update and try again",
+            args.path,
+            bundle.version
+        );
+    }
+
+    let mut imported_settings = 0;
+    for (key, value) in bundle.settings {
+        let setting = match Setting::try_from(key.as_str()) {
+            Ok(setting) if !setting.is_internal() => setting,
+            _ => {
+                writeln!(output, "Skipped unknown setting `{key}`")?;
+                continue;
+            },
+        };
+        if let Err(err) = setting.validate(&value) {
+            writeln!(output, "Skipped setting: {err}")?;
+            continue;
+        }
+        if database.settings.map().contains_key(setting.as_ref()) && !args.force {
+            continue;
+        }
+        database.settings.set(setting, value).await?;
+        imported_settings += 1;
+    }
+
+    let mut imported_servers = 0;
+    if !bundle.mcp_servers.is_empty() {
+        let mcp_path = global_mcp_config_path(ctx)?;
+        let mut config = match ctx.fs().exists(&mcp_path) {
+            true => McpServerConfig::load_from_file(ctx, &mcp_path).await?,
+            false => McpServerConfig::default(),
+        };
+        for (name, server) in bundle.mcp_servers {
+            if config.mcp_servers.contains_key(&name) && !args.force {
+                writeln!(output, "Skipped existing MCP server '{name}'")?;
+                continue;
+            }
+            config.mcp_servers.insert(name, server);
+            imported_servers += 1;
+        }
+        if let Some(parent) = mcp_path.parent() {
+            ctx.fs().create_dir_all(parent).await?;
+        }
+        config.save_to_file(ctx, &mcp_path).await?;
+    }
+
+    if let Some(imported) = bundle.global_context {
+        let path = directories::chat_global_context_path(ctx)?;
+        let config = match ctx.fs().exists(&path) && !args.force {
+            true => merge_context_config(load_global_config(ctx).await?, imported),
+            false => imported,
+        };
+        write_context_config(ctx, &path, &config).await?;
+    }
+
+    let mut imported_profiles = 0;
+    for (name, imported) in bundle.profiles {
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            writeln!(output, "Skipped profile with invalid name '{name}'")?;
+            continue;
+        }
+        let path = profile_context_path(ctx, &name)?;
+        let config = match ctx.fs().exists(&path) && !args.force {
+            true => {
+                let existing = serde_json::from_str(&ctx.fs().read_to_string(&path).await?)?;
+                merge_context_config(existing, imported)
+            },
+            false => imported,
+        };
+        write_context_config(ctx, &path, &config).await?;
+        imported_profiles += 1;
+    }
+
+    writeln!(
+        output,
+        "✓ Imported {imported_settings} settings, {imported_servers} MCP servers and {imported_profiles} profiles from {}",
+        args.path
+    )?;
+    if !args.force {
+        writeln!(output, "  Existing values were kept, use --force to overwrite them.")?;
+    }
+    Ok(())
+}
+
+/// Returns the settings to export, leaving out internal state and, unless `include_secrets` is
+/// set, secrets.
+fn export_settings(settings: &Map<String, Value>, include_secrets: bool) -> Map<String, Value> {
+    settings
+        .iter()
+        .filter(|(key, _)| {
+            Setting::try_from(key.as_str())
+                .is_ok_and(|setting| !setting.is_internal() && (include_secrets || !setting.is_secret()))
+        })
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Adds the paths and hooks of `imported` missing from `existing`.
+fn merge_context_config(mut existing: ContextConfig, imported: ContextConfig) -> ContextConfig {
+    for path in imported.paths {
+        if !existing.paths.contains(&path) {
+            existing.paths.push(path);
+        }
+    }
+    for (name, hook) in imported.hooks {
+        existing.hooks.entry(name).or_insert(hook);
+    }
+    existing
+}
+
+async fn write_context_config(ctx: &Context, path: &std::path::Path, config: &ContextConfig) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        ctx.fs().create_dir_all(parent).await?;
+    }
+    ctx.fs().write(path, serde_json::to_string_pretty(config)?).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_export_settings_leaves_out_secrets() {
+        let settings = json!({
+            "openai.model": "gpt-4o",
+            "openai.api.key": "sk-secret",
+            "chat.trustedTools": ["fs_read"],
+            "mcp.loadedBefore": true,
+            "unknown.key": 1,
+        });
+        let settings = settings.as_object().unwrap();
+
+        let exported = export_settings(settings, false);
+        let mut keys = exported.keys().cloned().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec!["chat.trustedTools", "openai.model"]);
+
+        assert!(export_settings(settings, true).contains_key("openai.api.key"));
+    }
+
+    #[tokio::test]
+    async fn test_export_then_import() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let mcp_path = global_mcp_config_path(&ctx).unwrap();
+        ctx.fs().create_dir_all(mcp_path.parent().unwrap()).await.unwrap();
+        ctx.fs()
+            .write(
+                &mcp_path,
+                r#"{"mcpServers": {"github": {"command": "github-mcp", "env": {"GITHUB_TOKEN": "secret"}}}}"#,
+            )
+            .await
+            .unwrap();
+        let profile_path = profile_context_path(&ctx, "rust").unwrap();
+        ctx.fs().create_dir_all(profile_path.parent().unwrap()).await.unwrap();
+        ctx.fs()
+            .write(&profile_path, r#"{"paths": ["Cargo.toml"]}"#)
+            .await
+            .unwrap();
+        database.settings.set(Setting::OpenAiModel, "gpt-4o").await.unwrap();
+
+        export_config(&ctx, &database, &mut output, ConfigExport {
+            path: "/bundle.json".to_string(),
+            include_secrets: false,
+        })
+        .await
+        .unwrap();
+
+        let bundle: ConfigBundle =
+            serde_json::from_str(&ctx.fs().read_to_string("/bundle.json").await.unwrap()).unwrap();
+        assert_eq!(bundle.settings.get("openai.model"), Some(&json!("gpt-4o")));
+        assert_eq!(bundle.mcp_servers["github"].command, "github-mcp");
+        assert!(bundle.mcp_servers["github"].env.is_none(), "secrets are not exported");
+        assert_eq!(bundle.profiles["rust"].paths, vec!["Cargo.toml"]);
+
+        // Import into a machine with an existing profile of the same name.
+        ctx.fs().remove_file(&mcp_path).await.unwrap();
+        ctx.fs()
+            .write(&profile_path, r#"{"paths": ["README.md"]}"#)
+            .await
+            .unwrap();
+        import_config(&ctx, &mut database, &mut output, ConfigImport {
+            path: "/bundle.json".to_string(),
+            force: false,
+        })
+        .await
+        .unwrap();
+
+        let config = McpServerConfig::load_from_file(&ctx, &mcp_path).await.unwrap();
+        assert!(config.mcp_servers.contains_key("github"));
+        let profile: ContextConfig =
+            serde_json::from_str(&ctx.fs().read_to_string(&profile_path).await.unwrap()).unwrap();
+        assert_eq!(profile.paths, vec!["README.md", "Cargo.toml"]);
+    }
+}
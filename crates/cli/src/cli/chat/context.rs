@@ -1,4 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{
+    HashMap,
+    HashSet,
+};
 use std::io::Write;
 use std::path::{
     Path,
@@ -23,7 +26,12 @@ use super::hooks::{
     Hook,
     HookExecutor,
 };
+use super::pruning::PruningPolicy;
+use super::template;
+use super::tools::env_policy::EnvPolicy;
 use super::util::drop_matched_context_files;
+use crate::database::Database;
+use crate::database::settings::Setting;
 use crate::platform::Context;
 use crate::util::directories;
 
@@ -59,6 +67,15 @@ pub struct ContextManager {
     /// Context configuration for the current profile.
     pub profile_config: ContextConfig,
 
+    /// Paths from the `chat.contextPaths` setting. These are not part of any profile and are
+    /// never saved to the context configuration files.
+    #[serde(skip)]
+    pub settings_paths: Vec<String>,
+
+    /// Which environment variables the context files can refer to, see [template].
+    #[serde(skip)]
+    pub env_policy: EnvPolicy,
+
     #[serde(skip)]
     pub hook_executor: HookExecutor,
 }
@@ -89,6 +106,10 @@ impl ContextManager {
         let global_config = load_global_config(&ctx).await?;
         let current_profile = "default".to_string();
         let profile_config = load_profile_config(&ctx, &current_profile).await?;
+        let hook_executor = HookExecutor {
+            current_dir: ctx.env().current_dir().ok(),
+            ..HookExecutor::new()
+        };
 
         Ok(Self {
             ctx,
@@ -96,7 +117,9 @@ impl ContextManager {
             global_config,
             current_profile,
             profile_config,
-            hook_executor: HookExecutor::new(),
+            settings_paths: Vec::new(),
+            env_policy: EnvPolicy::default(),
+            hook_executor,
         })
     }
 
@@ -158,7 +181,7 @@ impl ContextManager {
             for path in &paths {
                 // We're using a temporary context_files vector just for validation
                 // Pass is_validation=true to ensure we error if glob patterns don't match any files
-                match process_path(&self.ctx, path, &mut context_files, true).await {
+                match process_path(&self.ctx, &self.env_policy, path, &mut context_files, true).await {
                     Ok(_) => {}, // Path is valid
                     Err(e) => return Err(eyre!("Invalid path '{}': {}. Use --force to add anyway.", path, e)),
                 }
@@ -451,22 +474,39 @@ impl ContextManager {
     /// # Returns
     /// A Result containing a vector of (filename, content) pairs or an error
     pub async fn get_context_files(&self) -> Result<Vec<(String, String)>> {
-        let mut context_files = Vec::new();
+        Ok(self.get_context_files_and_pins().await?.0)
+    }
 
-        self.collect_context_files(&self.global_config.paths, &mut context_files)
-            .await?;
-        self.collect_context_files(&self.profile_config.paths, &mut context_files)
-            .await?;
+    /// Like [Self::get_context_files], also returning the files that are pinned: the ones the
+    /// configuration names with a path of their own rather than a glob pattern.
+    async fn get_context_files_and_pins(&self) -> Result<(Vec<(String, String)>, HashSet<String>)> {
+        let mut context_files = Vec::new();
+        let mut pinned = HashSet::new();
+
+        let paths = self
+            .global_config
+            .paths
+            .iter()
+            .chain(&self.profile_config.paths)
+            .chain(&self.settings_paths);
+        for path in paths {
+            let start = context_files.len();
+            process_path(&self.ctx, &self.env_policy, path, &mut context_files, false).await?;
+            let is_glob = path.contains('*') || path.contains('?') || path.contains('[');
+            if !is_glob && context_files.len() == start + 1 {
+                pinned.insert(context_files[start].0.clone());
+            }
+        }
 
         context_files.sort_by(|a, b| a.0.cmp(&b.0));
         context_files.dedup_by(|a, b| a.0 == b.0);
 
-        Ok(context_files)
+        Ok((context_files, pinned))
     }
 
     pub async fn get_context_files_by_path(&self, path: &str) -> Result<Vec<(String, String)>> {
         let mut context_files = Vec::new();
-        process_path(&self.ctx, path, &mut context_files, true).await?;
+        process_path(&self.ctx, &self.env_policy, path, &mut context_files, true).await?;
         Ok(context_files)
     }
 
@@ -490,12 +530,19 @@ impl ContextManager {
         Ok(context_files)
     }
 
-    /// Collects context files and optionally drops files if the total size exceeds the limit.
+    /// Collects context files and drops the ones scored the lowest by `policy` if the total size
+    /// exceeds the limit, see [drop_matched_context_files].
     /// Returns (files_to_use, dropped_files)
-    pub async fn collect_context_files_with_limit(&self) -> Result<(Vec<(String, String)>, Vec<(String, String)>)> {
-        let mut files = self.get_context_files().await?;
+    pub async fn collect_context_files_with_limit(
+        &self,
+        conversation: &[&str],
+        policy: &dyn PruningPolicy,
+    ) -> Result<(Vec<(String, String)>, Vec<(String, String)>)> {
+        let (mut files, pinned) = self.get_context_files_and_pins().await?;
 
-        let dropped_files = drop_matched_context_files(&mut files, self.max_context_files_size).unwrap_or_default();
+        let dropped_files =
+            drop_matched_context_files(&files, self.max_context_files_size, &pinned, conversation, policy)
+                .unwrap_or_default();
 
         // remove dropped files from files
         files.retain(|file| !dropped_files.iter().any(|dropped| dropped.0 == file.0));
@@ -506,7 +553,7 @@ impl ContextManager {
     async fn collect_context_files(&self, paths: &[String], context_files: &mut Vec<(String, String)>) -> Result<()> {
         for path in paths {
             // Use is_validation=false to handle non-matching globs gracefully
-            process_path(&self.ctx, path, context_files, false).await?;
+            process_path(&self.ctx, &self.env_policy, path, context_files, false).await?;
         }
         Ok(())
     }
@@ -625,7 +672,7 @@ pub fn profile_context_path(ctx: &Context, profile_name: &str) -> Result<PathBuf
 /// Load the global context configuration.
 ///
 /// If the global configuration file doesn't exist, returns a default configuration.
-async fn load_global_config(ctx: &Context) -> Result<ContextConfig> {
+pub async fn load_global_config(ctx: &Context) -> Result<ContextConfig> {
     let global_path = directories::chat_global_context_path(ctx)?;
     debug!(?global_path, "loading profile config");
     if ctx.fs().exists(&global_path) {
@@ -679,8 +726,33 @@ async fn load_profile_config(ctx: &Context, profile_name: &str) -> Result<Contex
 ///
 /// # Returns
 /// A Result indicating success or an error
+/// Returns the paths of the `chat.contextPaths` setting. Relative paths from the workspace settings
+/// file are resolved against the workspace root rather than the current directory.
+pub fn context_paths_from_settings(database: &Database) -> Vec<String> {
+    let paths = database
+        .settings
+        .get_string_list(Setting::ChatContextPaths)
+        .unwrap_or_default();
+    let root = database
+        .settings
+        .workspace_root()
+        .filter(|_| database.settings.is_workspace_override(Setting::ChatContextPaths));
+
+    match root {
+        Some(root) => paths
+            .into_iter()
+            .map(|path| match path.starts_with('~') || path.starts_with('/') {
+                true => path,
+                false => root.join(path).to_string_lossy().to_string(),
+            })
+            .collect(),
+        None => paths,
+    }
+}
+
 async fn process_path(
     ctx: &Context,
+    env_policy: &EnvPolicy,
     path: &str,
     context_files: &mut Vec<(String, String)>,
     is_validation: bool,
@@ -721,7 +793,7 @@ async fn process_path(
                     match entry {
                         Ok(path) => {
                             if path.is_file() {
-                                add_file_to_context(ctx, &path, context_files).await?;
+                                add_file_to_context(ctx, env_policy, &path, context_files).await?;
                                 found_any = true;
                             }
                         },
@@ -743,14 +815,14 @@ async fn process_path(
         let path = Path::new(&full_path);
         if path.exists() {
             if path.is_file() {
-                add_file_to_context(ctx, path, context_files).await?;
+                add_file_to_context(ctx, env_policy, path, context_files).await?;
             } else if path.is_dir() {
                 // For directories, add all files in the directory (non-recursive)
                 let mut read_dir = ctx.fs().read_dir(path).await?;
                 while let Some(entry) = read_dir.next_entry().await? {
                     let path = entry.path();
                     if path.is_file() {
-                        add_file_to_context(ctx, &path, context_files).await?;
+                        add_file_to_context(ctx, env_policy, &path, context_files).await?;
                     }
                 }
             }
@@ -767,7 +839,8 @@ async fn process_path(
 ///
 /// This method:
 /// 1. Reads the content of the file
-/// 2. Adds the (filename, content) pair to the context collection
+/// 2. Renders its template variables, see [template]
+/// 3. Adds the (filename, content) pair to the context collection
 ///
 /// # Arguments
 /// * `path` - The path to the file
@@ -775,9 +848,14 @@ async fn process_path(
 ///
 /// # Returns
 /// A Result indicating success or an error
-async fn add_file_to_context(ctx: &Context, path: &Path, context_files: &mut Vec<(String, String)>) -> Result<()> {
+async fn add_file_to_context(
+    ctx: &Context,
+    env_policy: &EnvPolicy,
+    path: &Path,
+    context_files: &mut Vec<(String, String)>,
+) -> Result<()> {
     let filename = path.to_string_lossy().to_string();
-    let content = ctx.fs().read_to_string(path).await?;
+    let content = template::render(ctx, env_policy, &ctx.fs().read_to_string(path).await?).await;
     context_files.push((filename, content));
     Ok(())
 }
@@ -817,6 +895,7 @@ mod tests {
     use std::io::Stdout;
 
     use super::super::hooks::HookTrigger;
+    use super::super::pruning::ImportancePolicy;
     use super::*;
 
     // Helper function to create a test ContextManager with Context
@@ -897,7 +976,10 @@ mod tests {
             .await?;
         manager.add_paths(vec!["test/*.md".to_string()], false, false).await?;
 
-        let (used, dropped) = manager.collect_context_files_with_limit().await.unwrap();
+        let (used, dropped) = manager
+            .collect_context_files_with_limit(&[], &ImportancePolicy)
+            .await
+            .unwrap();
 
         assert!(used.len() + dropped.len() == 2);
         assert!(used.len() == 1);
@@ -905,6 +987,31 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_collect_keeps_pinned() -> Result<()> {
+        let mut manager = create_test_context_manager(Some(2)).await?;
+        let ctx: Arc<Context> = Arc::clone(&manager.ctx);
+
+        ctx.fs().create_dir_all("test").await?;
+        ctx.fs().write("test/to-include.md", "ha").await?;
+        ctx.fs()
+            .write("test/named.md", "long content that exceed limit")
+            .await?;
+        manager
+            .add_paths(vec!["test/*.md".to_string(), "test/named.md".to_string()], false, false)
+            .await?;
+
+        // The file named explicitly is kept even though it doesn't fit.
+        let (used, dropped) = manager
+            .collect_context_files_with_limit(&[], &ImportancePolicy)
+            .await
+            .unwrap();
+        assert_eq!(used.len(), 1);
+        assert!(used[0].0.ends_with("named.md"));
+        assert_eq!(dropped.len(), 1);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_path_ops() -> Result<()> {
         let mut manager = create_test_context_manager(None).await?;
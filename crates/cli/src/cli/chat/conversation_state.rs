@@ -1,8 +1,10 @@
+use std::borrow::Cow;
 use std::collections::{
     HashMap,
     HashSet,
     VecDeque,
 };
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::Ordering;
 
@@ -15,23 +17,29 @@ use serde::{
     Deserialize,
     Serialize,
 };
+use tokio::io::AsyncWriteExt;
 use tracing::{
     debug,
     error,
     warn,
 };
 
+use super::command::SummaryFormat;
 use super::consts::{
     DUMMY_TOOL_NAME,
-    MAX_CHARS,
     MAX_CONVERSATION_STATE_HISTORY_LEN,
     MAX_USER_MESSAGE_SIZE,
 };
 use super::context::ContextManager;
+use super::git_context::build_git_state;
 use super::hooks::{
     Hook,
     HookTrigger,
 };
+use super::journal::{
+    Journal,
+    JournalEntry,
+};
 use super::message::{
     AssistantMessage,
     ToolUseResult,
@@ -40,11 +48,21 @@ use super::message::{
     UserMessageContent,
     build_env_state,
 };
+use super::pruning::{
+    ImportancePolicy,
+    PruningPolicy,
+    conversation_text,
+    history_turns,
+    select_dropped,
+};
 use super::token_counter::{
     CharCount,
     CharCounter,
+    TokenCount,
+    TokenCounted,
 };
 use super::tool_manager::ToolManager;
+use super::tools::remote::ExecutionTarget;
 use super::tools::{
     InputSchema,
     QueuedTool,
@@ -73,9 +91,15 @@ use crate::cli::chat::util::shared_writer::SharedWriter;
 use crate::database::Database;
 use crate::mcp_client::Prompt;
 use crate::platform::Context;
+use crate::util::directories;
 
 const CONTEXT_ENTRY_START_HEADER: &str = "--- CONTEXT ENTRY BEGIN ---\n";
 const CONTEXT_ENTRY_END_HEADER: &str = "--- CONTEXT ENTRY END ---\n\n";
+/// Aged tool results longer than this are compressed, see
+/// [ConversationState::compress_aged_tool_results].
+const MAX_AGED_TOOL_RESULT_LEN: usize = 2_000;
+/// Length of the start of a compressed tool result kept in the history.
+const TOOL_RESULT_DIGEST_LEN: usize = 500;
 
 /// Tracks state related to an ongoing conversation.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -103,8 +127,50 @@ pub struct ConversationState {
     context_message_length: Option<usize>,
     /// Stores the latest conversation summary created by /compact
     latest_summary: Option<String>,
+    /// What the last /compact replaced, restored by `/compact undo`.
+    #[serde(skip)]
+    original_history: Option<OriginalHistory>,
+    /// Short title generated from the first exchange, used to browse saved conversations.
+    #[serde(default)]
+    title: Option<String>,
+    /// Unix timestamp, in seconds, of the last message added to the history.
+    #[serde(default)]
+    updated_at: Option<i64>,
     #[serde(skip)]
     pub updates: Option<SharedWriter>,
+    /// Used to save the tool results compressed out of the history.
+    #[serde(skip)]
+    ctx: Option<Arc<Context>>,
+    /// Number of exchanges after which large tool results are compressed, see
+    /// [Self::compress_aged_tool_results].
+    #[serde(skip)]
+    compress_tool_results_after: Option<usize>,
+    /// Whether the state of the git repository is sent with each message, see
+    /// [build_git_state].
+    #[serde(skip)]
+    git_context: bool,
+    /// Language the model is asked to respond in, from `chat.language`.
+    #[serde(skip)]
+    language: Option<String>,
+    /// Where the changes of the conversation are recorded for `q chat --recover`, if they are.
+    #[serde(skip)]
+    journal: Option<Journal>,
+    /// Decides which turns of the history and which context files are dropped when they don't fit.
+    #[serde(skip, default = "default_pruning_policy")]
+    pruning_policy: Arc<dyn PruningPolicy>,
+}
+
+/// The history and summary replaced by a compaction, see [ConversationState::undo_compaction].
+#[derive(Debug, Clone)]
+struct OriginalHistory {
+    history: VecDeque<(UserMessage, AssistantMessage)>,
+    latest_summary: Option<String>,
+    /// Number of exchanges the compaction kept at the start of the history.
+    kept: usize,
+}
+
+fn default_pruning_policy() -> Arc<dyn PruningPolicy> {
+    Arc::new(ImportancePolicy)
 }
 
 impl ConversationState {
@@ -117,7 +183,7 @@ impl ConversationState {
         tool_manager: ToolManager,
     ) -> Self {
         // Initialize context manager
-        let context_manager = match ContextManager::new(ctx, None).await {
+        let context_manager = match ContextManager::new(Arc::clone(&ctx), None).await {
             Ok(mut manager) => {
                 // Switch to specified profile if provided
                 if let Some(profile_name) = profile {
@@ -156,7 +222,16 @@ impl ConversationState {
             tool_manager,
             context_message_length: None,
             latest_summary: None,
+            original_history: None,
+            title: None,
+            updated_at: None,
             updates,
+            ctx: Some(ctx),
+            compress_tool_results_after: None,
+            git_context: false,
+            language: None,
+            journal: None,
+            pruning_policy: default_pruning_policy(),
         }
     }
 
@@ -164,6 +239,7 @@ impl ConversationState {
     /// deserialization.
     pub async fn reload_serialized_state(&mut self, ctx: Arc<Context>, updates: Option<SharedWriter>) {
         self.updates = updates;
+        self.ctx = Some(Arc::clone(&ctx));
 
         // Try to reload ContextManager, but do not return an error if we fail.
         // TODO: Currently the failure modes around ContextManager is unclear, and we don't return
@@ -191,6 +267,21 @@ impl ConversationState {
         }
     }
 
+    /// Returns the conversation title, generating one for conversations saved before titles
+    /// were introduced.
+    pub fn title(&self) -> Option<String> {
+        self.title.clone().or_else(|| {
+            self.history
+                .front()
+                .and_then(|(user, _)| user.prompt())
+                .and_then(generate_title)
+        })
+    }
+
+    pub fn updated_at(&self) -> Option<i64> {
+        self.updated_at
+    }
+
     pub fn latest_summary(&self) -> Option<&str> {
         self.latest_summary.as_deref()
     }
@@ -203,9 +294,28 @@ impl ConversationState {
     pub fn clear(&mut self, preserve_summary: bool) {
         self.next_message = None;
         self.history.clear();
+        self.original_history = None;
         if !preserve_summary {
             self.latest_summary = None;
         }
+        self.remove_tool_results();
+        self.journal_snapshot();
+    }
+
+    /// Removes the full tool results saved by [Self::compress_aged_tool_results], once the
+    /// conversation is cleared or has ended.
+    pub fn remove_tool_results(&self) {
+        let Some(ctx) = &self.ctx else {
+            return;
+        };
+        let Ok(dir) = directories::chat_tool_results_dir(ctx) else {
+            return;
+        };
+        match std::fs::remove_dir_all(ctx.fs().chroot_path(dir.join(&self.conversation_id))) {
+            Ok(()) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => warn!(?err, "Failed to remove the saved tool results"),
+        }
     }
 
     /// Appends a collection prompts into history and returns the last message in the collection.
@@ -234,6 +344,7 @@ impl ConversationState {
                 self.history.push_back((user, asst));
             }
         }
+        self.journal_snapshot();
         Some(last_msg.content.to_string())
     }
 
@@ -243,6 +354,7 @@ impl ConversationState {
 
     pub fn reset_next_user_message(&mut self) {
         self.next_message = None;
+        self.journal_next_message();
     }
 
     pub async fn set_next_user_message(&mut self, input: String) {
@@ -260,18 +372,89 @@ impl ConversationState {
 
         let msg = UserMessage::new_prompt(input);
         self.next_message = Some(msg);
+        self.journal_next_message();
     }
 
-    /// Sets the response message according to the currently set [Self::next_message].
+    /// Sets the response message according to the currently set [Self::next_message], and saves
+    /// the conversation for the current directory.
     pub fn push_assistant_message(&mut self, message: AssistantMessage, database: &mut Database) {
+        self.record_assistant_message(message);
+
+        let cwd = match &self.ctx {
+            Some(ctx) => ctx.env().current_dir(),
+            None => std::env::current_dir(),
+        };
+        if let Ok(cwd) = cwd {
+            database.set_conversation_by_path(cwd, self).ok();
+        }
+    }
+
+    /// Like [Self::push_assistant_message], without saving the conversation. Used by conversations
+    /// that are not tied to the current directory, e.g. the ones driven through `q server`.
+    pub fn record_assistant_message(&mut self, message: AssistantMessage) {
         debug_assert!(self.next_message.is_some(), "next_message should exist");
         let next_user_message = self.next_message.take().expect("next user message should exist");
 
         self.append_assistant_transcript(&message);
+        if self.title.is_none() {
+            self.title = next_user_message.prompt().and_then(generate_title);
+        }
+        self.updated_at = Some(time::OffsetDateTime::now_utc().unix_timestamp());
+        let result = self.journal.as_ref().map(|journal| {
+            journal.append(&JournalEntry::Response {
+                message: Cow::Borrowed(&message),
+            })
+        });
+        self.stop_journal_on_error(result);
         self.history.push_back((next_user_message, message));
+    }
 
-        if let Ok(cwd) = std::env::current_dir() {
-            database.set_conversation_by_path(cwd, self).ok();
+    /// Starts recording the changes of the conversation, so that it can be recovered with
+    /// `q chat --recover` if the session is interrupted.
+    pub fn start_journal(&mut self, ctx: &Context, cwd: PathBuf) {
+        match Journal::create(ctx, cwd, self) {
+            Ok(journal) => self.journal = Some(journal),
+            Err(err) => warn!(?err, "Failed to start the chat journal"),
+        }
+    }
+
+    /// Removes the journal once the session ended normally.
+    pub fn finish_journal(&mut self) {
+        if let Some(journal) = self.journal.take() {
+            journal.remove();
+        }
+    }
+
+    /// Applies an entry of the journal of an interrupted session, see [super::journal::replay].
+    pub fn apply_journal_entry(&mut self, entry: JournalEntry<'_>) {
+        match entry {
+            JournalEntry::Snapshot { state, .. } => *self = state.into_owned(),
+            JournalEntry::NextMessage { message } => self.next_message = message.map(Cow::into_owned),
+            JournalEntry::Response { message } => {
+                if self.next_message.is_some() {
+                    self.record_assistant_message(message.into_owned());
+                }
+            },
+        }
+    }
+
+    fn journal_next_message(&mut self) {
+        let entry = JournalEntry::NextMessage {
+            message: self.next_message.as_ref().map(Cow::Borrowed),
+        };
+        let result = self.journal.as_ref().map(|journal| journal.append(&entry));
+        self.stop_journal_on_error(result);
+    }
+
+    fn journal_snapshot(&mut self) {
+        let result = self.journal.as_ref().map(|journal| journal.snapshot(self));
+        self.stop_journal_on_error(result);
+    }
+
+    fn stop_journal_on_error(&mut self, result: Option<eyre::Result<()>>) {
+        if let Some(Err(err)) = result {
+            warn!(?err, "Failed to write to the chat journal, stopping it");
+            self.journal = None;
         }
     }
 
@@ -288,8 +471,8 @@ impl ConversationState {
     }
 
     /// Updates the history so that, when non-empty, the following invariants are in place:
-    /// 1. The history length is `<= MAX_CONVERSATION_STATE_HISTORY_LEN`. Oldest messages are
-    ///    dropped.
+    /// 1. The history length is `<= MAX_CONVERSATION_STATE_HISTORY_LEN`. The turns scored the
+    ///    lowest by the [PruningPolicy] are dropped, except for the latest one.
     /// 2. The first message is from the user, and does not contain tool results. Oldest messages
     ///    are dropped.
     /// 3. If the last message from the assistant contains tool results, and a next user message is
@@ -299,24 +482,37 @@ impl ConversationState {
         // later below.
         self.valid_history_range = (0, self.history.len());
 
-        // Trim the conversation history by finding the second oldest message from the user without
-        // tool results - this will be the new oldest message in the history.
+        // Trim the conversation history by dropping whole turns, so that the history still starts
+        // with a message from the user without tool results, and tool uses keep their results.
         //
         // Note that we reserve extra slots for [ConversationState::context_messages].
-        if (self.history.len() * 2) > MAX_CONVERSATION_STATE_HISTORY_LEN - 6 {
-            match self
-                .history
-                .iter()
-                .enumerate()
-                .skip(1)
-                .find(|(_, (m, _))| -> bool { !m.has_tool_use_results() })
-                .map(|v| v.0)
-            {
-                Some(i) => {
-                    debug!("removing the first {i} user/assistant response pairs in the history");
-                    self.valid_history_range.0 = i;
+        let budget = MAX_CONVERSATION_STATE_HISTORY_LEN - 6;
+        if (self.history.len() * 2) > budget {
+            let turns = history_turns(&self.history);
+            match turns.first().map(|turn| turn.range.start) {
+                Some(start) if turns.len() > 1 || start > 0 => {
+                    let candidates = turns.iter().map(|turn| turn.candidate.clone()).collect::<Vec<_>>();
+                    let dropped = select_dropped(&candidates, budget, self.pruning_policy.as_ref());
+                    debug!(
+                        "removing {} turns and the {start} user/assistant response pairs before the first turn",
+                        dropped.len()
+                    );
+                    let mut kept = turns
+                        .iter()
+                        .enumerate()
+                        .filter(|(n, _)| !dropped.contains(n))
+                        .flat_map(|(_, turn)| turn.range.clone())
+                        .peekable();
+                    let mut index = 0;
+                    self.history.retain(|_| {
+                        let keep = kept.next_if_eq(&index).is_some();
+                        index += 1;
+                        keep
+                    });
+                    self.valid_history_range = (0, self.history.len());
+                    self.journal_snapshot();
                 },
-                None => {
+                _ => {
                     debug!("no valid starting user message found in the history, clearing");
                     self.valid_history_range = (0, 0);
                     // Edge case: if the next message contains tool results, then we have to just
@@ -418,19 +614,30 @@ impl ConversationState {
     pub fn add_tool_results(&mut self, tool_results: Vec<ToolUseResult>) {
         debug_assert!(self.next_message.is_none());
         self.next_message = Some(UserMessage::new_tool_use_results(tool_results));
+        self.journal_next_message();
     }
 
     pub fn add_tool_results_with_images(&mut self, tool_results: Vec<ToolUseResult>, images: Vec<ImageBlock>) {
         debug_assert!(self.next_message.is_none());
         self.next_message = Some(UserMessage::new_tool_use_results_with_images(tool_results, images));
+        self.journal_next_message();
     }
 
     /// Sets the next user message with "cancelled" tool results.
+    /// Attaches images to the message that will be sent next.
+    pub fn add_images_to_next_message(&mut self, images: Vec<ImageBlock>) {
+        if let Some(next_message) = self.next_message.as_mut() {
+            next_message.images.get_or_insert_default().extend(images);
+            self.journal_next_message();
+        }
+    }
+
     pub fn abandon_tool_use(&mut self, tools_to_be_abandoned: Vec<QueuedTool>, deny_input: String) {
         self.next_message = Some(UserMessage::new_cancelled_tool_uses(
             Some(deny_input),
             tools_to_be_abandoned.iter().map(|t| t.id.as_str()),
         ));
+        self.journal_next_message();
     }
 
     /// Returns a [FigConversationState] capable of being sent by [api_client::StreamingClient].
@@ -442,6 +649,15 @@ impl ConversationState {
         self.enforce_conversation_invariants();
         self.history.drain(self.valid_history_range.1..);
         self.history.drain(..self.valid_history_range.0);
+        self.compress_aged_tool_results().await;
+        if let (Some(ctx), Some(next_message)) = (self.ctx.as_ref(), self.next_message.as_mut()) {
+            if let Ok(cwd) = ctx.env().current_dir() {
+                next_message.set_current_dir(&cwd);
+            }
+            if self.git_context {
+                next_message.set_git_state(build_git_state(ctx).await);
+            }
+        }
 
         let context = self.backend_conversation_state(run_hooks, false).await;
         if !context.dropped_context_files.is_empty() {
@@ -464,6 +680,41 @@ impl ConversationState {
             .expect("unable to construct conversation state")
     }
 
+    pub fn set_compress_tool_results_after(&mut self, exchanges: Option<usize>) {
+        self.compress_tool_results_after = exchanges;
+    }
+
+    pub fn set_git_context(&mut self, enabled: bool) {
+        self.git_context = enabled;
+    }
+
+    pub fn set_language(&mut self, language: Option<String>) {
+        self.language = language;
+    }
+
+    /// Replaces the large tool results of the exchanges older than
+    /// `compress_tool_results_after` with a digest, so that e.g. a file read many turns ago isn't
+    /// sent again with every request. The full results are saved to
+    /// [directories::chat_tool_results_dir] for the model to read again with `fs_read`, unless the
+    /// tools run on another machine, where `fs_read` can't reach them.
+    async fn compress_aged_tool_results(&mut self) {
+        let (Some(after), Some(ctx)) = (self.compress_tool_results_after, self.ctx.clone()) else {
+            return;
+        };
+
+        let aged = self.history.len().saturating_sub(after);
+        for (user, _) in self.history.iter_mut().take(aged) {
+            let tool_use_results = match &mut user.content {
+                UserMessageContent::ToolUseResults { tool_use_results }
+                | UserMessageContent::CancelledToolUses { tool_use_results, .. } => tool_use_results,
+                UserMessageContent::Prompt { .. } => continue,
+            };
+            for result in tool_use_results {
+                compress_tool_result(&ctx, &self.conversation_id, result).await;
+            }
+        }
+    }
+
     pub async fn update_state(&mut self, force_update: bool) {
         let needs_update = self.tool_manager.has_new_stuff.load(Ordering::Acquire) || force_update;
         if !needs_update {
@@ -615,12 +866,74 @@ impl ConversationState {
             conversation_id: Some(self.conversation_id.clone()),
             user_input_message: summary_message,
             history: Some(history),
+            response_schema: None,
+        }
+    }
+
+    /// Returns a [FigConversationState] asking the model for a shareable summary of the whole
+    /// session. Unlike [Self::create_summary_request], the result is meant for the user and the
+    /// history is left untouched.
+    pub async fn create_session_summary_request(&mut self, format: SummaryFormat) -> FigConversationState {
+        let format_instructions = match format {
+            SummaryFormat::Markdown => {
+                "Use GitHub flavored markdown with `##` headings and `-` bullet points. Wrap commands and file paths in backticks."
+            },
+            SummaryFormat::Jira => {
+                "Use Jira wiki markup: `h3.` headings, `*` bullet points, and `{{...}}` for commands and file paths. Do not use markdown."
+            },
+            SummaryFormat::Slack => {
+                "Use Slack mrkdwn: `*bold*` lines instead of headings, `•` bullet points, and backticks for commands and file paths. Keep it short enough for a standup note. Do not use markdown headings."
+            },
+        };
+        let summary_content = format!(
+            "[SYSTEM NOTE: This is an automated summarization request, not from the user]\n\n\
+            Write a concise summary of this session that the user can paste into a ticket or standup note. \
+            DO NOT respond conversationally and DO NOT call any tools.\n\n\
+            Include only the sections that apply, in this order:\n\
+            1) Summary: one or two sentences describing the goal and outcome\n\
+            2) Decisions: decisions made and their rationale\n\
+            3) Commands executed: commands that were run and whether they succeeded\n\
+            4) Files changed: files that were created or modified, with a short description of each change\n\
+            5) Open items: remaining follow-ups, if any\n\n\
+            FORMAT: {}",
+            format_instructions
+        );
+
+        let conv_state = self.backend_conversation_state(false, true).await;
+        let history = flatten_history(conv_state.history);
+
+        let mut summary_message = UserInputMessage {
+            content: summary_content,
+            user_input_message_context: None,
+            user_intent: None,
+            images: None,
+        };
+
+        // Pending tool uses have to be answered before another user message can be sent.
+        if let Some(ChatMessage::AssistantResponseMessage(AssistantResponseMessage {
+            tool_uses: Some(tool_uses),
+            ..
+        })) = history.last()
+        {
+            self.set_cancelled_tool_results(&mut summary_message, tool_uses);
+        }
+
+        FigConversationState {
+            conversation_id: Some(self.conversation_id.clone()),
+            user_input_message: summary_message,
+            history: Some(history),
+            response_schema: None,
         }
     }
 
     pub fn replace_history_with_summary(&mut self, summary: String) {
+        let original_history = self.history.clone();
         self.history.drain(..(self.history.len().saturating_sub(1)));
-        self.latest_summary = Some(summary);
+        self.original_history = Some(OriginalHistory {
+            history: original_history,
+            latest_summary: self.latest_summary.replace(summary),
+            kept: self.history.len(),
+        });
         // If the last message contains tool results, then we add the results to the content field
         // instead. This is required to avoid validation errors.
         // TODO: this can break since the max user content size is less than the max tool response
@@ -648,6 +961,34 @@ impl ConversationState {
                 user.content = UserMessageContent::Prompt { prompt };
             }
         }
+        self.journal_snapshot();
+    }
+
+    /// Restores the history and summary replaced by the last compaction, followed by the
+    /// exchanges added since. Returns false if there is no compaction to undo.
+    pub fn undo_compaction(&mut self) -> bool {
+        let Some(original) = self.original_history.take() else {
+            return false;
+        };
+        let added = self.history.split_off(original.kept.min(self.history.len()));
+        self.history = original.history;
+        self.history.extend(added);
+        self.latest_summary = original.latest_summary;
+        self.journal_snapshot();
+        true
+    }
+
+    /// The context files that don't fit in the next request, see
+    /// [ContextManager::collect_context_files_with_limit].
+    pub async fn dropped_context_files(&self) -> eyre::Result<Vec<(String, String)>> {
+        let Some(context_manager) = &self.context_manager else {
+            return Ok(Vec::new());
+        };
+        let conversation = conversation_text(&self.history, self.next_message.as_ref());
+        let (_, dropped) = context_manager
+            .collect_context_files_with_limit(&conversation, self.pruning_policy.as_ref())
+            .await?;
+        Ok(dropped)
     }
 
     pub fn current_profile(&self) -> Option<&str> {
@@ -682,8 +1023,12 @@ impl ConversationState {
         }
 
         // Add context files if available
+        let conversation = conversation_text(&self.history, self.next_message.as_ref());
         if let Some(context_manager) = self.context_manager.as_mut() {
-            match context_manager.collect_context_files_with_limit().await {
+            match context_manager
+                .collect_context_files_with_limit(&conversation, self.pruning_policy.as_ref())
+                .await
+            {
                 Ok((files_to_use, files_dropped)) => {
                     if !files_dropped.is_empty() {
                         dropped_context_files.extend(files_dropped);
@@ -707,6 +1052,14 @@ impl ConversationState {
             context_content.push_str(&context);
         }
 
+        if let Some(language) = &self.language {
+            context_content.push_str(CONTEXT_ENTRY_START_HEADER);
+            context_content.push_str(&format!(
+                "Respond in the language with the tag {language}, unless the user asks for another one. Keep code, commands, file paths and identifiers as they are.\n"
+            ));
+            context_content.push_str(CONTEXT_ENTRY_END_HEADER);
+        }
+
         if !context_content.is_empty() {
             self.context_message_length = Some(context_content.len());
             let user_msg = UserMessage::new_prompt(context_content);
@@ -722,16 +1075,64 @@ impl ConversationState {
         self.context_message_length
     }
 
+    /// Breaks down what the next request would spend of the context window, for
+    /// `/context usage`.
+    pub async fn context_usage(&mut self) -> ContextUsage {
+        let summary = self.latest_summary.as_ref().map_or(0, String::len);
+        let conversation = conversation_text(&self.history, self.next_message.as_ref());
+        let context_files = match self.context_manager.as_mut() {
+            Some(context_manager) => context_manager
+                .collect_context_files_with_limit(&conversation, self.pruning_policy.as_ref())
+                .await
+                .map(|(files, _)| files.iter().map(|(name, content)| name.len() + content.len()).sum())
+                .unwrap_or_default(),
+            None => 0,
+        };
+
+        let state = self.backend_conversation_state(true, true).await;
+        let size = state.calculate_conversation_size();
+        let mut tools = state
+            .tools
+            .iter()
+            .map(|(origin, tools)| {
+                let chars = tools
+                    .iter()
+                    .map(|tool| serde_json::to_string(tool).map_or(0, |json| json.len()))
+                    .sum::<usize>();
+                (origin.clone(), chars.into())
+            })
+            .collect::<Vec<(ToolOrigin, CharCount)>>();
+        tools.sort_by(|(a, _), (b, _)| match (a, b) {
+            (ToolOrigin::Native, _) => std::cmp::Ordering::Less,
+            (_, ToolOrigin::Native) => std::cmp::Ordering::Greater,
+            (ToolOrigin::Plugin, _) => std::cmp::Ordering::Less,
+            (_, ToolOrigin::Plugin) => std::cmp::Ordering::Greater,
+            (ToolOrigin::McpServer(a), ToolOrigin::McpServer(b)) => a.cmp(b),
+        });
+
+        ContextUsage {
+            context_files: context_files.into(),
+            summary: summary.into(),
+            // Whatever else is in the context message: hook output, headers and the instructions
+            // around the summary.
+            other_context: size.context_messages.saturating_sub(context_files + summary).into(),
+            history: size.user_messages + size.assistant_messages,
+            tools,
+            pending_message: state.next_user_message.map_or(0, |msg| *msg.char_count()).into(),
+        }
+    }
+
     /// Calculate the total character count in the conversation
-    pub async fn calculate_char_count(&mut self) -> CharCount {
-        self.backend_conversation_state(false, true).await.char_count()
+    /// The number of tokens of the conversation, counted with the tokenizer of the model in use.
+    pub async fn calculate_token_count(&mut self) -> TokenCount {
+        self.backend_conversation_state(false, true).await.token_count()
     }
 
-    /// Get the current token warning level
-    pub async fn get_token_warning_level(&mut self) -> TokenWarningLevel {
-        let total_chars = self.calculate_char_count().await;
+    /// Get the current token warning level, given the context window of the model in use
+    pub async fn get_token_warning_level(&mut self, context_window_tokens: usize) -> TokenWarningLevel {
+        let total_tokens = self.calculate_token_count().await;
 
-        if *total_chars >= MAX_CHARS {
+        if *total_tokens >= context_window_tokens {
             TokenWarningLevel::Critical
         } else {
             TokenWarningLevel::None
@@ -799,7 +1200,7 @@ impl ConversationState {
                     tools: if self.tools.is_empty() {
                         None
                     } else {
-                        Some(self.tools.values().flatten().cloned().collect::<Vec<_>>())
+                        Some(sorted_tools(&self.tools))
                     },
                     ..Default::default()
                 };
@@ -847,13 +1248,14 @@ impl
             .map(UserMessage::into_user_input_message)
             .ok_or(eyre::eyre!("next user message is not set"))?;
         if let Some(ctx) = user_input_message.user_input_message_context.as_mut() {
-            ctx.tools = Some(self.tools.values().flatten().cloned().collect::<Vec<_>>());
+            ctx.tools = Some(sorted_tools(self.tools));
         }
 
         Ok(FigConversationState {
             conversation_id: Some(self.conversation_id.to_string()),
             user_input_message,
             history: Some(history),
+            response_schema: None,
         })
     }
 
@@ -897,6 +1299,115 @@ pub struct ConversationSize {
     pub assistant_messages: CharCount,
 }
 
+/// The context window spent per category, see [ConversationState::context_usage].
+#[derive(Debug, Clone)]
+pub struct ContextUsage {
+    pub context_files: CharCount,
+    /// The summary left by `/compact`, which is kept in place of the history it replaced.
+    pub summary: CharCount,
+    pub other_context: CharCount,
+    pub history: CharCount,
+    /// The tool specifications sent with every request, by where the tools come from.
+    pub tools: Vec<(ToolOrigin, CharCount)>,
+    pub pending_message: CharCount,
+}
+
+impl ContextUsage {
+    pub fn total(&self) -> CharCount {
+        self.tools.iter().fold(
+            self.context_files + self.summary + self.other_context + self.history + self.pending_message,
+            |acc, (_, chars)| acc + *chars,
+        )
+    }
+}
+
+/// Creates a short title for a conversation out of its first prompt, returning [None] if the
+/// prompt has no usable text.
+fn generate_title(prompt: &str) -> Option<String> {
+    const MAX_TITLE_LEN: usize = 60;
+
+    let line = prompt.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    let mut title = String::new();
+    for word in &words {
+        if title.chars().count() + word.chars().count() + 1 > MAX_TITLE_LEN {
+            break;
+        }
+        if !title.is_empty() {
+            title.push(' ');
+        }
+        title.push_str(word);
+    }
+
+    if title.is_empty() {
+        // A single word longer than the limit.
+        title = truncate_safe(line, MAX_TITLE_LEN).to_string();
+    }
+    if title.len() < line.len() || prompt.trim() != line {
+        title.push('…');
+    }
+    Some(title)
+}
+
+/// Replaces `result` with a digest if it is longer than [MAX_AGED_TOOL_RESULT_LEN], saving the
+/// full result to a file named after the tool use, readable only by the user.
+async fn compress_tool_result(ctx: &Context, conversation_id: &str, result: &mut ToolUseResult) {
+    let full = result
+        .content
+        .iter()
+        .map(|block| match block {
+            ToolUseResultBlock::Text(text) => text.clone(),
+            ToolUseResultBlock::Json(value) => serde_json::to_string_pretty(value).unwrap_or_default(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if full.len() <= MAX_AGED_TOOL_RESULT_LEN {
+        return;
+    }
+
+    let saved = async {
+        let dir = directories::chat_tool_results_dir(ctx)?.join(conversation_id);
+        let file_name = result
+            .tool_use_id
+            .replace(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_', "_");
+        let path = dir.join(format!("{file_name}.txt"));
+        ctx.fs().create_dir_all(&dir).await?;
+
+        let mut file_opts = tokio::fs::File::options();
+        file_opts.create(true).write(true).truncate(true);
+        #[cfg(unix)]
+        file_opts.mode(0o600);
+        let mut file = file_opts.open(ctx.fs().chroot_path(&path)).await?;
+        file.write_all(full.as_bytes()).await?;
+        Ok::<_, eyre::Report>(path)
+    };
+    let location = match ctx.execution_target() {
+        // The file is on this machine, fs_read would look for it on the target
+        ExecutionTarget::Ssh(_) | ExecutionTarget::Container(_) => None,
+        ExecutionTarget::Local => match saved.await {
+            Ok(path) => Some(path),
+            Err(err) => {
+                warn!(?err, "Failed to save the compressed tool result");
+                None
+            },
+        },
+    };
+    let location = match location {
+        Some(path) => format!(
+            "The full result was saved to {}, read it with fs_read if it is needed again.",
+            path.display()
+        ),
+        None => "Use the tool again if the full result is needed.".to_string(),
+    };
+
+    result.content = vec![ToolUseResultBlock::Text(format!(
+        "{}\n... [{} characters of this old tool result were compressed. {}]",
+        truncate_safe(&full, TOOL_RESULT_DIGEST_LEN),
+        full.len(),
+        location
+    ))];
+}
+
 /// Converts a list of user/assistant message pairs into a flattened list of ChatMessage.
 fn flatten_history<'a, T>(history: T) -> Vec<ChatMessage>
 where
@@ -909,6 +1420,14 @@ where
     })
 }
 
+/// The tools to send, sorted by name so that every request lists them in the same order and
+/// providers can reuse the cached prompt prefix they are part of.
+fn sorted_tools(tools: &HashMap<ToolOrigin, Vec<Tool>>) -> Vec<Tool> {
+    let mut tools = tools.values().flatten().cloned().collect::<Vec<_>>();
+    tools.sort_by(|Tool::ToolSpecification(a), Tool::ToolSpecification(b)| a.name.cmp(&b.name));
+    tools
+}
+
 /// Character count warning levels for conversation size
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TokenWarningLevel {
@@ -1074,6 +1593,109 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_generate_title() {
+        assert_eq!(generate_title("  \n"), None);
+        assert_eq!(
+            generate_title("How do I list S3 buckets?"),
+            Some("How do I list S3 buckets?".to_string())
+        );
+        assert_eq!(
+            generate_title("Fix the failing test\nHere is the output: ..."),
+            Some("Fix the failing test…".to_string())
+        );
+        let long = "Refactor the authentication module so that token refresh happens in the background";
+        let title = generate_title(long).unwrap();
+        assert_eq!(title, "Refactor the authentication module so that token refresh…");
+        assert!(title.chars().count() <= 61);
+    }
+
+    #[tokio::test]
+    async fn test_session_summary_request_keeps_history() {
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager.load_tools(&database, &mut output).await.unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+
+        for i in 0..3 {
+            conversation_state.set_next_user_message(i.to_string()).await;
+            conversation_state.as_sendable_conversation_state(true).await;
+            conversation_state
+                .push_assistant_message(AssistantMessage::new_response(None, i.to_string()), &mut database);
+        }
+
+        let request = conversation_state
+            .create_session_summary_request(SummaryFormat::Jira)
+            .await;
+        assert_eq!(
+            request.history.unwrap().len(),
+            6,
+            "the whole session should be summarized"
+        );
+        assert!(request.user_input_message.content.contains("Jira wiki markup"));
+        assert_eq!(conversation_state.history().len(), 3);
+        assert_eq!(
+            conversation_state.title().as_deref(),
+            Some("0"),
+            "title comes from the first prompt"
+        );
+        assert!(conversation_state.updated_at().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_undo_compaction() {
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager.load_tools(&database, &mut output).await.unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+        assert!(!conversation_state.undo_compaction());
+
+        for i in 0..3 {
+            conversation_state.set_next_user_message(i.to_string()).await;
+            conversation_state.as_sendable_conversation_state(true).await;
+            conversation_state
+                .push_assistant_message(AssistantMessage::new_response(None, i.to_string()), &mut database);
+        }
+        conversation_state.replace_history_with_summary("summary".to_string());
+        assert_eq!(conversation_state.history().len(), 1);
+        assert_eq!(conversation_state.latest_summary(), Some("summary"));
+
+        conversation_state.set_next_user_message("3".to_string()).await;
+        conversation_state.as_sendable_conversation_state(true).await;
+        conversation_state.push_assistant_message(AssistantMessage::new_response(None, "3".to_string()), &mut database);
+
+        assert!(conversation_state.undo_compaction());
+        let prompts = conversation_state
+            .history()
+            .iter()
+            .map(|(user, _)| user.prompt().unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(prompts, ["0", "1", "2", "3"]);
+        assert_eq!(conversation_state.latest_summary(), None);
+        assert!(
+            !conversation_state.undo_compaction(),
+            "only the last compaction can be undone"
+        );
+    }
+
     #[tokio::test]
     async fn test_conversation_state_history_handling_with_tool_results() {
         let mut database = Database::new().await.unwrap();
@@ -1149,6 +1771,96 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_compress_aged_tool_results() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let mut database = Database::new().await.unwrap();
+        let mut conversation_state = ConversationState::new(
+            Arc::clone(&ctx),
+            "fake_conv_id",
+            HashMap::new(),
+            None,
+            None,
+            ToolManager::default(),
+        )
+        .await;
+        conversation_state.set_compress_tool_results_after(Some(1));
+
+        let large_result = "line\n".repeat(1000);
+        conversation_state
+            .set_next_user_message("read the file".to_string())
+            .await;
+        for i in 0..3 {
+            conversation_state.as_sendable_conversation_state(false).await;
+            conversation_state.push_assistant_message(
+                AssistantMessage::new_tool_use(None, i.to_string(), vec![AssistantToolUse {
+                    id: format!("tool_{i}"),
+                    name: "fs_read".to_string(),
+                    args: serde_json::Value::Null,
+                    ..Default::default()
+                }]),
+                &mut database,
+            );
+            conversation_state.add_tool_results(vec![ToolUseResult {
+                tool_use_id: format!("tool_{i}"),
+                content: vec![ToolUseResultBlock::Text(large_result.clone())],
+                status: ToolResultStatus::Success,
+            }]);
+        }
+        conversation_state.as_sendable_conversation_state(false).await;
+
+        let result_len = |index: usize| match &conversation_state.history()[index].0.content {
+            UserMessageContent::ToolUseResults { tool_use_results } => match &tool_use_results[0].content[0] {
+                ToolUseResultBlock::Text(text) => text.len(),
+                ToolUseResultBlock::Json(_) => panic!("expected a text result"),
+            },
+            content => panic!("expected tool results, got {:?}", content),
+        };
+        assert!(
+            result_len(1) < MAX_AGED_TOOL_RESULT_LEN,
+            "the oldest tool result is compressed"
+        );
+        assert_eq!(result_len(2), large_result.len(), "recent tool results are kept");
+
+        let saved = directories::chat_tool_results_dir(&ctx)
+            .unwrap()
+            .join("fake_conv_id")
+            .join("tool_0.txt");
+        assert_eq!(ctx.fs().read_to_string(&saved).await.unwrap(), large_result);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = std::fs::metadata(ctx.fs().chroot_path(&saved)).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+
+        conversation_state.clear(true);
+        assert!(
+            !ctx.fs().exists(&saved),
+            "the saved results are removed with the history"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compress_tool_result_on_remote_target() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let ctx = ctx.with_execution_target(ExecutionTarget::Ssh("devbox".to_string()));
+        let mut result = ToolUseResult {
+            tool_use_id: "tool_0".to_string(),
+            content: vec![ToolUseResultBlock::Text("line\n".repeat(1000))],
+            status: ToolResultStatus::Success,
+        };
+        compress_tool_result(&ctx, "fake_conv_id", &mut result).await;
+
+        let ToolUseResultBlock::Text(text) = &result.content[0] else {
+            panic!("expected a text result");
+        };
+        assert!(text.contains("Use the tool again"), "{text}");
+        assert!(!text.contains("fs_read"), "{text}");
+        let dir = directories::chat_tool_results_dir(&ctx).unwrap().join("fake_conv_id");
+        assert!(!ctx.fs().exists(dir));
+    }
+
     #[tokio::test]
     async fn test_conversation_state_with_context_files() {
         let mut database = Database::new().await.unwrap();
@@ -1197,6 +1909,38 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_context_usage() {
+        let database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().write(AMAZONQ_FILENAME, "test context").await.unwrap();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            ctx,
+            "fake_conv_id",
+            tool_manager.load_tools(&database, &mut output).await.unwrap(),
+            None,
+            None,
+            tool_manager,
+        )
+        .await;
+        conversation_state.set_next_user_message("hello".to_string()).await;
+
+        let usage = conversation_state.context_usage().await;
+        assert!(*usage.context_files > "test context".len());
+        assert_eq!(*usage.summary, 0);
+        assert_eq!(*usage.history, 0);
+        // The prompt with the per-prompt hook context added to it.
+        let next_message = conversation_state.next_message.as_ref().unwrap();
+        assert!(!next_message.additional_context().is_empty());
+        assert_eq!(*usage.pending_message, *next_message.char_count());
+        assert!(matches!(usage.tools.first(), Some((ToolOrigin::Native, chars)) if **chars > 0));
+        assert!(*usage.total() > *usage.context_files + *usage.pending_message);
+    }
+
     #[tokio::test]
     async fn test_conversation_state_additional_context() {
         // tracing_subscriber::fmt::try_init().ok();
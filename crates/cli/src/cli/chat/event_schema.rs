@@ -0,0 +1,210 @@
+//! The versioned JSON schema of the events of an
+//! [AgentSession](super::agent_session::AgentSession), sent over the `/ws` endpoint of `q server
+//! --agent` and printed by `q chat --format json`.
+//!
+//! Every event is a JSON object with the `version` of the schema and its `type`. Fields can be
+//! added to an event within a version, but renaming or removing an event or a field, or changing
+//! what it means, needs a new [SCHEMA_VERSION]. The events are converted from [AgentEvent] rather
+//! than serializing it, and the tests pin their JSON, so refactoring the session doesn't change
+//! what integrations receive.
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::Value;
+
+use super::agent_session::AgentEvent;
+
+/// Version of the schema, sent with every event.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionedEvent {
+    pub version: u32,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A prompt of the client started a turn of the conversation `conversation_id`.
+    TurnStart { conversation_id: String },
+    /// A chunk of the response.
+    Token { content: String },
+    /// The assistant uses a tool. `diff` is the change to a file made by `fs_write`, with its
+    /// `path`, `old_text`, null for a new file, and `new_text`.
+    ToolRequest {
+        id: String,
+        name: String,
+        input: Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        diff: Option<Value>,
+    },
+    /// The tool use `id` only runs once the client answers with a `tool_approval` message.
+    ApprovalRequest { id: String },
+    /// The result of a tool use, as sent back to the model.
+    ToolResult { id: String, success: bool, content: String },
+    /// The turn ended, the session waits for the next prompt.
+    TurnEnd,
+    Error {
+        message: String,
+        /// A machine-readable code for errors of the model provider, e.g. `quota_exceeded` or
+        /// `context_overflow`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        code: Option<String>,
+    },
+}
+
+impl From<Event> for VersionedEvent {
+    fn from(event: Event) -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            event,
+        }
+    }
+}
+
+/// The events of the schema for `event`, a tool use waiting for approval being followed by its
+/// approval request.
+pub fn versioned(event: AgentEvent) -> Vec<VersionedEvent> {
+    let events = match event {
+        AgentEvent::TurnStart { conversation_id } => vec![Event::TurnStart { conversation_id }],
+        AgentEvent::Text { content } => vec![Event::Token { content }],
+        AgentEvent::ToolUse {
+            id,
+            name,
+            input,
+            requires_approval,
+            diff,
+        } => {
+            let request = Event::ToolRequest {
+                id: id.clone(),
+                name,
+                input,
+                diff: diff.and_then(|diff| serde_json::to_value(diff).ok()),
+            };
+            match requires_approval {
+                true => vec![request, Event::ApprovalRequest { id }],
+                false => vec![request],
+            }
+        },
+        AgentEvent::ToolResult { id, success, content } => vec![Event::ToolResult { id, success, content }],
+        AgentEvent::Done => vec![Event::TurnEnd],
+        AgentEvent::Error { message, code } => vec![Event::Error {
+            message,
+            code: code.map(str::to_string),
+        }],
+    };
+    events.into_iter().map(VersionedEvent::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::api_client::ApiClientError;
+    use crate::cli::chat::tools::fs_write::FileChange;
+
+    fn to_json(event: AgentEvent) -> Vec<Value> {
+        versioned(event)
+            .iter()
+            .map(|event| serde_json::to_value(event).unwrap())
+            .collect()
+    }
+
+    /// Changing any of these needs a new [SCHEMA_VERSION].
+    #[test]
+    fn test_schema_v1() {
+        assert_eq!(SCHEMA_VERSION, 1);
+        assert_eq!(
+            to_json(AgentEvent::TurnStart {
+                conversation_id: "abc".to_string()
+            }),
+            [json!({"version": 1, "type": "turn_start", "conversation_id": "abc"})]
+        );
+        assert_eq!(
+            to_json(AgentEvent::Text {
+                content: "Sure".to_string()
+            }),
+            [json!({"version": 1, "type": "token", "content": "Sure"})]
+        );
+        assert_eq!(
+            to_json(AgentEvent::ToolUse {
+                id: "1".to_string(),
+                name: "fs_write".to_string(),
+                input: json!({"command": "create", "path": "/a.txt", "file_text": "hi"}),
+                requires_approval: true,
+                diff: Some(FileChange {
+                    path: PathBuf::from("/a.txt"),
+                    old_text: None,
+                    new_text: "hi".to_string(),
+                }),
+            }),
+            [
+                json!({
+                    "version": 1,
+                    "type": "tool_request",
+                    "id": "1",
+                    "name": "fs_write",
+                    "input": {"command": "create", "path": "/a.txt", "file_text": "hi"},
+                    "diff": {"path": "/a.txt", "old_text": null, "new_text": "hi"},
+                }),
+                json!({"version": 1, "type": "approval_request", "id": "1"}),
+            ]
+        );
+        assert_eq!(
+            to_json(AgentEvent::ToolUse {
+                id: "2".to_string(),
+                name: "fs_read".to_string(),
+                input: json!({}),
+                requires_approval: false,
+                diff: None,
+            }),
+            [json!({"version": 1, "type": "tool_request", "id": "2", "name": "fs_read", "input": {}})]
+        );
+        assert_eq!(
+            to_json(AgentEvent::ToolResult {
+                id: "1".to_string(),
+                success: false,
+                content: "denied".to_string(),
+            }),
+            [json!({"version": 1, "type": "tool_result", "id": "1", "success": false, "content": "denied"})]
+        );
+        assert_eq!(to_json(AgentEvent::Done), [json!({"version": 1, "type": "turn_end"})]);
+        assert_eq!(
+            to_json(AgentEvent::Error {
+                message: "the context window has overflowed".to_string(),
+                code: Some(ApiClientError::ContextWindowOverflow.kind().code()),
+            }),
+            [json!({
+                "version": 1,
+                "type": "error",
+                "message": "the context window has overflowed",
+                "code": "context_overflow",
+            })]
+        );
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let event: VersionedEvent = serde_json::from_value(json!({
+            "version": 1,
+            "type": "error",
+            "message": "oops",
+            "added_in_a_later_release": true,
+        }))
+        .unwrap();
+        assert_eq!(
+            event,
+            VersionedEvent::from(Event::Error {
+                message: "oops".to_string(),
+                code: None,
+            })
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::time::SystemTime;
+
+use crate::platform::Context;
+
+/// Identifies a version of a file without reading its content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    /// Returns [None] if the file does not exist or cannot be accessed.
+    async fn load(ctx: &Context, path: &Path) -> Option<Self> {
+        let metadata = ctx.fs().symlink_metadata(path).await.ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Tracks the files read or written during a chat session so that the model can be told when
+/// they are edited outside of the conversation, e.g. by the user in their IDE.
+///
+/// Files are compared against the state they were in when a tool last accessed them, right
+/// before each user message is sent.
+#[derive(Debug, Default)]
+pub struct FileWatcher {
+    enabled: bool,
+    files: HashMap<PathBuf, Option<FileStamp>>,
+}
+
+impl FileWatcher {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            files: HashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn tracked_files(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Records the current state of `paths` as the one known to the model.
+    ///
+    /// Files are tracked even while the watcher is disabled so that turning it on later does not
+    /// miss files discussed earlier in the session.
+    pub async fn track(&mut self, ctx: &Context, paths: impl IntoIterator<Item = PathBuf>) {
+        for path in paths {
+            let stamp = FileStamp::load(ctx, &path).await;
+            self.files.insert(path, stamp);
+        }
+    }
+
+    /// Returns the tracked files that changed since they were last tracked, marking them as seen.
+    pub async fn changed_files(&mut self, ctx: &Context) -> Vec<PathBuf> {
+        if !self.enabled {
+            return Vec::new();
+        }
+
+        let mut changed = Vec::new();
+        for (path, stamp) in &mut self.files {
+            let current = FileStamp::load(ctx, path).await;
+            if current != *stamp {
+                *stamp = current;
+                changed.push(path.clone());
+            }
+        }
+        changed.sort();
+        changed
+    }
+
+    /// Prefixes `input` with a note listing the tracked files that were edited externally.
+    pub async fn annotate_user_input(&mut self, ctx: &Context, input: String) -> String {
+        let changed = self.changed_files(ctx).await;
+        if changed.is_empty() {
+            return input;
+        }
+
+        let files = changed
+            .iter()
+            .map(|path| format!("- {}", path.display()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!(
+            "[SYSTEM NOTE: The following files were modified outside of this conversation since they were last read or written. Read them again before relying on their previous content.\n{}]\n\n{}",
+            files, input
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_changed_files() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let path = PathBuf::from("/file.txt");
+        ctx.fs().write(&path, "one").await.unwrap();
+
+        let mut watcher = FileWatcher::new(true);
+        watcher.track(&ctx, [path.clone()]).await;
+        assert!(watcher.changed_files(&ctx).await.is_empty());
+
+        ctx.fs().write(&path, "two!").await.unwrap();
+        assert_eq!(watcher.changed_files(&ctx).await, vec![path.clone()]);
+        assert!(
+            watcher.changed_files(&ctx).await.is_empty(),
+            "changes are reported once"
+        );
+
+        ctx.fs().remove_file(&path).await.unwrap();
+        let input = watcher.annotate_user_input(&ctx, "hello".to_string()).await;
+        assert!(input.contains("- /file.txt"));
+        assert!(input.ends_with("\n\nhello"));
+    }
+
+    #[tokio::test]
+    async fn test_disabled_watcher_reports_nothing() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let path = PathBuf::from("/file.txt");
+        ctx.fs().write(&path, "one").await.unwrap();
+
+        let mut watcher = FileWatcher::new(false);
+        watcher.track(&ctx, [path.clone()]).await;
+        ctx.fs().write(&path, "two!").await.unwrap();
+        assert!(watcher.changed_files(&ctx).await.is_empty());
+
+        watcher.set_enabled(true);
+        assert_eq!(watcher.changed_files(&ctx).await, vec![path]);
+    }
+}
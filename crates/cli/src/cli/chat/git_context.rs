@@ -0,0 +1,137 @@
+//! The [GitState] sent with each message when `chat.gitContext` is enabled, describing the branch
+//! of the current directory, its divergence from upstream and the uncommitted changes, so that the
+//! model can tell what the user is working on without running git itself.
+
+use std::process::Stdio;
+
+use tracing::debug;
+
+use crate::api_client::model::GitState;
+use crate::platform::Context;
+
+/// Maximum number of files listed in the diffstat, the others are summarized in one line.
+const MAX_DIFFSTAT_FILES: usize = 20;
+
+/// Runs git in the current directory, returning its output if it succeeded.
+pub async fn run_git(ctx: &Context, args: &[&str]) -> Option<String> {
+    let output = tokio::process::Command::new("git")
+        .args(args)
+        .current_dir(ctx.env().current_dir().ok()?)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .await
+        .inspect_err(|err| debug!(?err, "failed to run git"))
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The state of the repository of the current directory, [None] outside of a repository.
+pub async fn build_git_state(ctx: &Context) -> Option<GitState> {
+    let status = run_git(ctx, &["status", "--porcelain=v2", "--branch"]).await?;
+    // Fails before the first commit, when there is nothing to compare with.
+    let diffstat = run_git(ctx, &["diff", "--stat", "HEAD"]).await.unwrap_or_default();
+    Some(GitState {
+        status: format_status(&status, &diffstat),
+    })
+}
+
+/// Formats the output of `git status --porcelain=v2 --branch` and `git diff --stat`.
+fn format_status(porcelain: &str, diffstat: &str) -> String {
+    let mut head = "(unknown)";
+    let mut upstream = None;
+    let mut divergence = None;
+    let (mut changed, mut untracked, mut conflicts) = (0, 0, 0);
+    for line in porcelain.lines() {
+        if let Some(header) = line.strip_prefix("# ") {
+            match header.split_once(' ') {
+                Some(("branch.head", value)) => head = value,
+                Some(("branch.upstream", value)) => upstream = Some(value),
+                Some(("branch.ab", value)) => divergence = value.split_once(' '),
+                _ => (),
+            }
+            continue;
+        }
+        match line.chars().next() {
+            Some('1' | '2') => changed += 1,
+            Some('?') => untracked += 1,
+            Some('u') => conflicts += 1,
+            _ => (),
+        }
+    }
+
+    let mut status = format!("Branch: {head}");
+    if let Some(upstream) = upstream {
+        status.push_str(&format!(" (tracking {upstream}"));
+        if let Some((ahead, behind)) = divergence {
+            let ahead = ahead.trim_start_matches('+');
+            let behind = behind.trim_start_matches('-');
+            status.push_str(&format!(", {ahead} ahead, {behind} behind"));
+        }
+        status.push(')');
+    }
+
+    if changed + untracked + conflicts == 0 {
+        status.push_str("\nWorking tree clean");
+        return status;
+    }
+    let counts = [
+        (changed, "changed"),
+        (untracked, "untracked"),
+        (conflicts, "conflicted"),
+    ];
+    let counts = counts
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, kind)| format!("{count} {kind}"))
+        .collect::<Vec<_>>();
+    status.push_str(&format!("\nUncommitted changes: {}", counts.join(", ")));
+
+    let lines = diffstat.lines().collect::<Vec<_>>();
+    if let Some((summary, files)) = lines.split_last() {
+        for file in files.iter().take(MAX_DIFFSTAT_FILES) {
+            status.push_str(&format!("\n{file}"));
+        }
+        if files.len() > MAX_DIFFSTAT_FILES {
+            status.push_str(&format!("\n ... {} more files", files.len() - MAX_DIFFSTAT_FILES));
+        }
+        status.push_str(&format!("\n{summary}"));
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_status() {
+        let porcelain = "# branch.oid 1234\n# branch.head feature/git\n# branch.upstream origin/feature/git\n\
+                         # branch.ab +2 -1\n1 .M N... 100644 100644 100644 a b src/main.rs\n? notes.md\n";
+        let diffstat = " src/main.rs | 4 ++--\n 1 file changed, 2 insertions(+), 2 deletions(-)\n";
+        assert_eq!(
+            format_status(porcelain, diffstat),
+            "Branch: feature/git (tracking origin/feature/git, 2 ahead, 1 behind)\n\
+             Uncommitted changes: 1 changed, 1 untracked\n \
+             src/main.rs | 4 ++--\n \
+             1 file changed, 2 insertions(+), 2 deletions(-)"
+        );
+
+        assert_eq!(
+            format_status("# branch.oid (initial)\n# branch.head main\n", ""),
+            "Branch: main\nWorking tree clean"
+        );
+
+        let diffstat = (0..25)
+            .map(|i| format!(" file{i}.rs | 1 +\n"))
+            .chain([" 25 files changed, 25 insertions(+)\n".to_string()])
+            .collect::<String>();
+        let status = format_status("# branch.head main\n1 .M\n", &diffstat);
+        assert!(status.contains("file19.rs"));
+        assert!(!status.contains("file20.rs"));
+        assert!(status.ends_with(" ... 5 more files\n 25 files changed, 25 insertions(+)"));
+    }
+}
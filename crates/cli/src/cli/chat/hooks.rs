@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::io::Write;
+use std::path::PathBuf;
 use std::process::Stdio;
 use std::time::{
     Duration,
@@ -35,6 +36,7 @@ use spinners::{
     Spinners,
 };
 
+use super::accessibility;
 use super::util::truncate_safe;
 
 const DEFAULT_TIMEOUT_MS: u64 = 30_000;
@@ -130,6 +132,8 @@ pub struct CachedHook {
 pub struct HookExecutor {
     pub global_cache: HashMap<String, CachedHook>,
     pub profile_cache: HashMap<String, CachedHook>,
+    /// Directory the hooks run in, the current directory of `q` if not set.
+    pub current_dir: Option<PathBuf>,
 }
 
 impl HookExecutor {
@@ -137,6 +141,7 @@ impl HookExecutor {
         Self {
             global_cache: HashMap::new(),
             profile_cache: HashMap::new(),
+            current_dir: None,
         }
     }
 
@@ -182,7 +187,7 @@ impl HookExecutor {
                 total.to_string().blue(),
             )
         };
-        if total != 0 && updates.is_some() {
+        if total != 0 && updates.is_some() && !accessibility::enabled() {
             spinner = Some(Spinner::new(Spinners::Dots12, spinner_text(succeeded, total)));
         }
 
@@ -259,7 +264,7 @@ impl HookExecutor {
                         style::Print(format!("{:.2} s\n", start_time.elapsed().as_secs_f32())),
                         style::ResetColor,
                     );
-                } else {
+                } else if !accessibility::enabled() {
                     spinner = Some(Spinner::new(Spinners::Dots, spinner_text(succeeded, total)));
                 }
             }
@@ -295,13 +300,17 @@ impl HookExecutor {
     async fn execute_inline_hook(&self, hook: &Hook) -> Result<String> {
         let command = hook.command.as_ref().ok_or_else(|| eyre!("no command specified"))?;
 
-        let command_future = tokio::process::Command::new("bash")
+        let mut process = tokio::process::Command::new("bash");
+        process
             .arg("-c")
             .arg(command)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output();
+            .stderr(Stdio::piped());
+        if let Some(dir) = &self.current_dir {
+            process.current_dir(dir);
+        }
+        let command_future = process.output();
         let timeout = Duration::from_millis(hook.timeout_ms);
 
         // Run with timeout
@@ -0,0 +1,243 @@
+//! `q chat import`, which saves the session of another tool as the conversation of the current
+//! directory, to continue it with `q chat --resume`.
+//!
+//! Only the text of the prompts and responses is kept. The tool uses of the session are mentioned
+//! by name in the responses, and their results are left out, since they don't map to the tools of
+//! this CLI.
+
+use std::collections::HashMap;
+use std::io::Write as _;
+
+use crossterm::style::Stylize;
+use eyre::{
+    Result,
+    WrapErr,
+    bail,
+};
+use rand::distr::{
+    Alphanumeric,
+    SampleString,
+};
+use serde_json::Value;
+
+use super::cli::{
+    ChatImport,
+    SessionFormat,
+};
+use super::conversation_state::ConversationState;
+use super::message::AssistantMessage;
+use super::tool_manager::ToolManager;
+use super::util::shared_writer::SharedWriter;
+use crate::database::Database;
+use crate::platform::Context;
+
+pub async fn import_session(database: &mut Database, output: &mut SharedWriter, args: ChatImport) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let saved = database.get_conversation_by_path(&cwd)?;
+    if !args.force && saved.is_some_and(|state| !state.history().is_empty()) {
+        writeln!(
+            output,
+            "A conversation is already saved for {}, replace it with {}.",
+            cwd.display(),
+            "q chat import --force".green()
+        )?;
+        return Ok(());
+    }
+
+    let text = std::fs::read_to_string(&args.path).wrap_err_with(|| format!("Failed to read {}", args.path))?;
+    let exchanges = match args.format.unwrap_or_else(|| detect_format(&text)) {
+        SessionFormat::ClaudeCode => parse_claude_code(&text)?,
+        SessionFormat::Aider => parse_aider(&text),
+    };
+    if exchanges.is_empty() {
+        bail!("No prompt with a response was found in {}", args.path);
+    }
+
+    let conversation_id = Alphanumeric.sample_string(&mut rand::rng(), 9);
+    let mut state = ConversationState::new(
+        Context::new(),
+        &conversation_id,
+        HashMap::new(),
+        None,
+        None,
+        ToolManager::default(),
+    )
+    .await;
+    for (prompt, response) in &exchanges {
+        state.append_user_transcript(prompt);
+        state.set_next_user_message(prompt.clone()).await;
+        state.record_assistant_message(AssistantMessage::new_response(None, response.clone()));
+    }
+    database.set_conversation_by_path(&cwd, &state)?;
+
+    writeln!(
+        output,
+        "✓ Imported {} exchanges from {}, continue the conversation with {}",
+        exchanges.len(),
+        args.path,
+        "q chat --resume".green()
+    )?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    User,
+    Assistant,
+}
+
+/// A Claude Code session has a JSON object per line, anything else is read as an Aider history.
+fn detect_format(text: &str) -> SessionFormat {
+    let first_line = text.lines().find(|line| !line.trim().is_empty()).unwrap_or_default();
+    match serde_json::from_str::<Value>(first_line) {
+        Ok(Value::Object(_)) => SessionFormat::ClaudeCode,
+        _ => SessionFormat::Aider,
+    }
+}
+
+fn parse_claude_code(text: &str) -> Result<Vec<(String, String)>> {
+    let mut messages = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: Value =
+            serde_json::from_str(line).wrap_err_with(|| format!("Invalid JSON on line {}", index + 1))?;
+        // Messages of subagents, and the ones added by Claude Code rather than typed.
+        if entry["isSidechain"] == true || entry["isMeta"] == true {
+            continue;
+        }
+        let role = match entry["type"].as_str() {
+            Some("user") => Role::User,
+            Some("assistant") => Role::Assistant,
+            _ => continue,
+        };
+        let text = match &entry["message"]["content"] {
+            Value::String(text) => text.clone(),
+            Value::Array(blocks) => blocks
+                .iter()
+                .filter_map(|block| match block["type"].as_str()? {
+                    "text" => block["text"].as_str().map(str::to_string),
+                    "tool_use" => Some(format!("[Tool use: {}]", block["name"].as_str().unwrap_or("unknown"))),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            _ => continue,
+        };
+        messages.push((role, text));
+    }
+    Ok(exchanges(messages))
+}
+
+/// Prompts are the lines starting with `####`, the lines starting with `>` are the output of Aider
+/// and the others are responses.
+fn parse_aider(text: &str) -> Vec<(String, String)> {
+    let mut messages: Vec<(Role, String)> = Vec::new();
+    for line in text.lines() {
+        let (role, line) = match line.strip_prefix("####") {
+            Some(prompt) => (Role::User, prompt.strip_prefix(' ').unwrap_or(prompt)),
+            // The start of a session and the output of Aider, e.g. the edits it applied.
+            None if line.starts_with("# aider chat started at") || line.starts_with('>') => continue,
+            None => (Role::Assistant, line),
+        };
+        match messages.last_mut() {
+            Some((last_role, text)) if *last_role == role => {
+                text.push('\n');
+                text.push_str(line);
+            },
+            _ => messages.push((role, line.to_string())),
+        }
+    }
+    exchanges(messages)
+}
+
+/// Pairs the prompts with the responses following them. Consecutive messages of the same role are
+/// joined, and a prompt without a response, e.g. the last one, is left out.
+fn exchanges(messages: Vec<(Role, String)>) -> Vec<(String, String)> {
+    let mut exchanges: Vec<(String, String)> = Vec::new();
+    let mut prompt: Option<String> = None;
+    for (role, text) in messages {
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        match (role, prompt.take()) {
+            (Role::User, Some(previous)) => prompt = Some(format!("{previous}\n\n{text}")),
+            (Role::User, None) => prompt = Some(text.to_string()),
+            (Role::Assistant, Some(prompt)) => exchanges.push((prompt, text.to_string())),
+            (Role::Assistant, None) => {
+                if let Some((_, response)) = exchanges.last_mut() {
+                    response.push_str("\n\n");
+                    response.push_str(text);
+                }
+            },
+        }
+    }
+    exchanges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_claude_code() {
+        let session = r#"{"type":"summary","summary":"Fix the parser"}
+{"type":"user","isMeta":true,"message":{"role":"user","content":"Caveat: the messages below were generated"}}
+{"type":"user","message":{"role":"user","content":"why does the parser fail?"}}
+{"type":"assistant","message":{"role":"assistant","content":[{"type":"thinking","thinking":"..."},{"type":"text","text":"Let me look."},{"type":"tool_use","name":"Read","input":{"file_path":"parse.rs"}}]}}
+{"type":"user","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"1","content":"fn parse() {}"}]}}
+{"type":"assistant","message":{"role":"assistant","content":[{"type":"text","text":"It never returns."}]}}
+{"type":"user","isSidechain":true,"message":{"role":"user","content":"subagent prompt"}}
+{"type":"user","message":{"role":"user","content":[{"type":"text","text":"fix it"}]}}
+{"type":"assistant","message":{"role":"assistant","content":[{"type":"text","text":"Done."}]}}
+{"type":"user","message":{"role":"user","content":"thanks"}}
+"#;
+        assert_eq!(detect_format(session), SessionFormat::ClaudeCode);
+        assert_eq!(parse_claude_code(session).unwrap(), vec![
+            (
+                "why does the parser fail?".to_string(),
+                "Let me look.\n\n[Tool use: Read]\n\nIt never returns.".to_string()
+            ),
+            ("fix it".to_string(), "Done.".to_string()),
+        ]);
+        assert!(parse_claude_code("{\"type\":\"user\"}\nnot json").is_err());
+    }
+
+    #[test]
+    fn test_parse_aider() {
+        let history = "
+# aider chat started at 2024-05-01 10:00:00
+
+> Add parse.rs to the chat? (Y)es/(N)o [Yes]: y
+
+#### why does the parser fail?
+#### it hangs
+
+It never returns from the loop.
+
+> Tokens: 2.1k sent, 120 received.
+
+#### fix it
+
+parse.rs
+```rust
+fn parse() {}
+```
+
+> Applied edit to parse.rs
+";
+        assert_eq!(detect_format(history), SessionFormat::Aider);
+        assert_eq!(parse_aider(history), vec![
+            (
+                "why does the parser fail?\nit hangs".to_string(),
+                "It never returns from the loop.".to_string()
+            ),
+            (
+                "fix it".to_string(),
+                "parse.rs\n```rust\nfn parse() {}\n```".to_string()
+            ),
+        ]);
+    }
+}
@@ -2,6 +2,10 @@ use eyre::Result;
 use rustyline::error::ReadlineError;
 
 use super::prompt::rl;
+use super::scrollback::{
+    Scrollback,
+    ScrollbackPager,
+};
 #[cfg(unix)]
 use super::skim_integration::SkimCommandSelector;
 use crate::database::Database;
@@ -14,10 +18,17 @@ mod inner {
     use rustyline::history::FileHistory;
 
     use super::super::prompt::ChatHelper;
+    use super::super::tui::TuiHandle;
 
     #[derive(Debug)]
     pub enum Inner {
         Readline(Editor<ChatHelper, FileHistory>),
+        /// Lines typed in the input box of `--tui`, `None` for Ctrl+C.
+        #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+        Channel {
+            tui: TuiHandle,
+            lines: std::sync::mpsc::Receiver<Option<String>>,
+        },
         #[allow(dead_code)]
         Mock {
             index: usize,
@@ -35,6 +46,28 @@ impl InputSource {
         Ok(Self(inner::Inner::Readline(rl(database, sender, receiver)?)))
     }
 
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn new_channel(tui: super::tui::TuiHandle, lines: std::sync::mpsc::Receiver<Option<String>>) -> Self {
+        Self(inner::Inner::Channel { tui, lines })
+    }
+
+    /// Opens the scrollback pager with PageUp.
+    pub fn bind_scrollback(&mut self, scrollback: Scrollback) {
+        use rustyline::{
+            EventHandler,
+            KeyCode,
+            KeyEvent,
+            Modifiers,
+        };
+
+        if let inner::Inner::Readline(rl) = &mut self.0 {
+            rl.bind_sequence(
+                KeyEvent(KeyCode::PageUp, Modifiers::NONE),
+                EventHandler::Conditional(Box::new(ScrollbackPager(scrollback))),
+            );
+        }
+    }
+
     #[cfg(unix)]
     pub fn put_skim_command_selector(
         &mut self,
@@ -80,6 +113,11 @@ impl InputSource {
                     Err(err) => Err(err),
                 }
             },
+            inner::Inner::Channel { tui, lines } => {
+                tui.send(super::tui::TuiEvent::Prompt(prompt.unwrap_or_default().to_string()));
+                // The input box was closed with Ctrl+D.
+                Ok(lines.recv().unwrap_or_default())
+            },
             inner::Inner::Mock { index, lines } => {
                 *index += 1;
                 Ok(lines.get(*index - 1).cloned())
@@ -0,0 +1,278 @@
+//! Crash-safe journal of the conversation, replayed by `q chat --recover`.
+//!
+//! Interactive sessions append every change of the conversation to a JSON lines file as it
+//! happens, starting with a snapshot of the whole conversation. The journal is removed when the
+//! session ends normally, so one left behind means that the session crashed or its terminal was
+//! killed.
+
+use std::borrow::Cow;
+use std::fs::{
+    File,
+    OpenOptions,
+};
+use std::io::{
+    BufRead,
+    BufReader,
+    Write,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use eyre::{
+    Result,
+    WrapErr,
+    eyre,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tracing::warn;
+
+use super::conversation_state::ConversationState;
+use super::message::{
+    AssistantMessage,
+    UserMessage,
+};
+use crate::platform::Context;
+use crate::util::directories;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JournalEntry<'a> {
+    /// The whole conversation, written first and whenever the history is rewritten, e.g. by
+    /// `/clear` or `/compact`.
+    Snapshot {
+        cwd: Cow<'a, Path>,
+        state: Cow<'a, ConversationState>,
+    },
+    /// The message to send next, a prompt or the results of the tools that ran.
+    NextMessage { message: Option<Cow<'a, UserMessage>> },
+    /// The response to the next message, which moves both to the history.
+    Response { message: Cow<'a, AssistantMessage> },
+}
+
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: PathBuf,
+    cwd: PathBuf,
+}
+
+impl Journal {
+    /// Starts the journal of the conversation with a snapshot of it, replacing its previous
+    /// journal, e.g. the one it was recovered from.
+    pub fn create(ctx: &Context, cwd: PathBuf, state: &ConversationState) -> Result<Self> {
+        let dir = directories::chat_journal_dir(ctx)?;
+        std::fs::create_dir_all(&dir)?;
+        let journal = Self {
+            path: dir.join(format!("{}.jsonl", state.conversation_id())),
+            cwd,
+        };
+
+        // Written aside first, so that a crash now doesn't lose the journal being replaced.
+        let partial = journal.path.with_extension("jsonl.partial");
+        let mut file = Self::open(OpenOptions::new().write(true).create(true).truncate(true), &partial)?;
+        file.write_all(&Self::line(&JournalEntry::Snapshot {
+            cwd: Cow::Borrowed(&journal.cwd),
+            state: Cow::Borrowed(state),
+        })?)?;
+        file.sync_data()?;
+        std::fs::rename(&partial, &journal.path)?;
+        Ok(journal)
+    }
+
+    pub fn snapshot(&self, state: &ConversationState) -> Result<()> {
+        self.append(&JournalEntry::Snapshot {
+            cwd: Cow::Borrowed(&self.cwd),
+            state: Cow::Borrowed(state),
+        })
+    }
+
+    /// Appends the entry, on disk once this returns.
+    pub fn append(&self, entry: &JournalEntry<'_>) -> Result<()> {
+        let mut file = Self::open(OpenOptions::new().append(true), &self.path)?;
+        file.write_all(&Self::line(entry)?)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Removes the journal once the session ended normally.
+    pub fn remove(self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            warn!(?err, path = ?self.path, "Failed to remove the chat journal");
+        }
+    }
+
+    /// Opens the journal readable only by the user, since it holds the whole conversation.
+    fn open(file_opts: &mut OpenOptions, path: &Path) -> std::io::Result<File> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            file_opts.mode(0o600);
+        }
+        file_opts.open(path)
+    }
+
+    fn line(entry: &JournalEntry<'_>) -> Result<Vec<u8>> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        Ok(line)
+    }
+}
+
+/// The journal of the last interrupted session in `cwd`, if any.
+pub fn find_interrupted(ctx: &Context, cwd: &Path) -> Result<Option<PathBuf>> {
+    let dir = directories::chat_journal_dir(ctx)?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).wrap_err("Failed to read the chat journals"),
+    };
+
+    let mut latest = None;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|extension| extension != "jsonl") {
+            continue;
+        }
+        let Ok(modified) = entry.metadata().and_then(|metadata| metadata.modified()) else {
+            continue;
+        };
+        if latest.as_ref().is_some_and(|(_, latest)| *latest >= modified) {
+            continue;
+        }
+        let mut first_line = String::new();
+        if File::open(&path)
+            .and_then(|file| BufReader::new(file).read_line(&mut first_line))
+            .is_err()
+        {
+            continue;
+        }
+        if let Ok(JournalEntry::Snapshot { cwd: journal_cwd, .. }) = serde_json::from_str(&first_line) {
+            if journal_cwd == cwd {
+                latest = Some((path, modified));
+            }
+        }
+    }
+    Ok(latest.map(|(path, _)| path))
+}
+
+/// Rebuilds the conversation from its journal.
+pub fn replay(path: &Path) -> Result<ConversationState> {
+    let file = File::open(path).wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+    let mut state: Option<ConversationState> = None;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let entry = match serde_json::from_str::<JournalEntry<'_>>(&line) {
+            Ok(entry) => entry,
+            // The last line may have been cut short by the crash.
+            Err(err) => {
+                warn!(?err, "Ignoring the end of the chat journal");
+                break;
+            },
+        };
+        match (entry, state.as_mut()) {
+            (JournalEntry::Snapshot { state: snapshot, .. }, _) => state = Some(snapshot.into_owned()),
+            (entry, Some(state)) => state.apply_journal_entry(entry),
+            (_, None) => break,
+        }
+    }
+    state.ok_or_else(|| eyre!("The chat journal {} is empty", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::cli::chat::message::AssistantToolUse;
+    use crate::cli::chat::tool_manager::ToolManager;
+
+    async fn conversation(ctx: &Arc<Context>) -> ConversationState {
+        ConversationState::new(
+            Arc::clone(ctx),
+            "fake_conv_id",
+            HashMap::new(),
+            None,
+            None,
+            ToolManager::default(),
+        )
+        .await
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_journal_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let cwd = PathBuf::from("/workspace/project");
+        let mut state = conversation(&ctx).await;
+        state.start_journal(&ctx, cwd.clone());
+        state.set_next_user_message("hello".to_string()).await;
+
+        let path = find_interrupted(&ctx, &cwd).unwrap().unwrap();
+        let metadata = std::fs::metadata(path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let cwd = PathBuf::from("/workspace/project");
+        let mut state = conversation(&ctx).await;
+        state.start_journal(&ctx, cwd.clone());
+
+        state.set_next_user_message("list the files".to_string()).await;
+        state.record_assistant_message(AssistantMessage::new_tool_use(None, "Listing".to_string(), vec![
+            AssistantToolUse {
+                id: "tool_1".to_string(),
+                name: "execute_bash".to_string(),
+                args: serde_json::json!({ "command": "ls" }),
+                ..Default::default()
+            },
+        ]));
+
+        let path = find_interrupted(&ctx, &cwd).unwrap().expect("journal should be found");
+        assert_eq!(find_interrupted(&ctx, Path::new("/elsewhere")).unwrap(), None);
+
+        // A line cut short by a crash is ignored.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"kind\":\"next_mess").unwrap();
+
+        let recovered = replay(&path).unwrap();
+        assert_eq!(recovered.conversation_id(), "fake_conv_id");
+        assert_eq!(recovered.history().len(), 1);
+        assert!(recovered.next_user_message().is_none());
+        match &recovered.history()[0].1 {
+            AssistantMessage::ToolUse { tool_uses, .. } => assert_eq!(tool_uses[0].id, "tool_1"),
+            message @ AssistantMessage::Response { .. } => panic!("expected tool uses, got {message:?}"),
+        }
+
+        state.finish_journal();
+        assert_eq!(find_interrupted(&ctx, &cwd).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_replay_snapshot() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let cwd = PathBuf::from("/workspace/project");
+        let mut state = conversation(&ctx).await;
+        state.start_journal(&ctx, cwd.clone());
+
+        state.set_next_user_message("hello".to_string()).await;
+        state.record_assistant_message(AssistantMessage::new_response(None, "hi".to_string()));
+        state.clear(true);
+        state.set_next_user_message("are you there?".to_string()).await;
+
+        let recovered = replay(&find_interrupted(&ctx, &cwd).unwrap().unwrap()).unwrap();
+        assert!(recovered.history().is_empty());
+        assert_eq!(
+            recovered.next_user_message().and_then(|message| message.prompt()),
+            Some("are you there?")
+        );
+    }
+}
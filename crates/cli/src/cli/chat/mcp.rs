@@ -21,6 +21,7 @@ use crate::cli::chat::cli::{
     McpRemove,
     Scope,
 };
+use crate::cli::chat::mcp_serve;
 use crate::cli::chat::tool_manager::{
     McpServerConfig,
     global_mcp_config_path,
@@ -31,9 +32,10 @@ use crate::cli::chat::tools::custom_tool::{
     default_timeout,
 };
 use crate::cli::chat::util::shared_writer::SharedWriter;
+use crate::database::Database;
 use crate::platform::Context;
 
-pub async fn execute_mcp(args: Mcp) -> Result<ExitCode> {
+pub async fn execute_mcp(database: &Database, args: Mcp) -> Result<ExitCode> {
     let ctx = Context::new();
     let mut output = SharedWriter::stdout();
 
@@ -43,6 +45,8 @@ pub async fn execute_mcp(args: Mcp) -> Result<ExitCode> {
         Mcp::List(args) => list_mcp_server(&ctx, &mut output, args).await?,
         Mcp::Import(args) => import_mcp_server(&ctx, &mut output, args).await?,
         Mcp::Status { name } => get_mcp_server_status(&ctx, &mut output, name).await?,
+        // STDOUT is the transport of the server, nothing else is written to it.
+        Mcp::Serve(args) => return mcp_serve::serve(ctx, database, args).await,
     }
 
     output.flush()?;
@@ -0,0 +1,349 @@
+//! `q mcp serve`, serving the native tools over MCP on STDIN and STDOUT, so that other agents can
+//! use their implementations and permission model.
+//!
+//! The tools are checked like in `q chat`: the tool policy comes first, then the trusted tools, and
+//! the tools that would ask for confirmation fail instead, since MCP can't ask for it. The calls
+//! are recorded in the audit log when chat.auditLog is enabled, a server being one conversation.
+
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use eyre::Result;
+use serde::Deserialize;
+use serde_json::{
+    Value,
+    json,
+};
+use tracing::error;
+
+use super::audit_entry;
+use super::cli::McpServe;
+use super::message::{
+    ToolUseResult,
+    ToolUseResultBlock,
+};
+use super::tools::execute_bash::ExecuteBash;
+use super::tools::fs_read::FsRead;
+use super::tools::fs_write::FsWrite;
+use super::tools::limits::ToolLimits;
+use super::tools::tool_policy::{
+    PolicyDecision,
+    ToolPolicy,
+};
+use super::tools::use_aws::UseAws;
+use super::tools::{
+    Tool,
+    ToolContext,
+    ToolSpec,
+};
+use crate::api_client::model::ToolResultStatus;
+use crate::cli::audit::{
+    AuditLog,
+    Decision,
+};
+use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::mcp_client::{
+    JsonRpcRequest,
+    JsonRpcResponse,
+    JsonRpcStdioTransport,
+    PreServerRequestHandler,
+    Response,
+    Server,
+    ServerError,
+    ServerRequestHandler,
+};
+use crate::platform::Context;
+
+/// The tools that are served.
+const SERVED_TOOLS: [&str; 4] = ["fs_read", "fs_write", "execute_bash", "use_aws"];
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+pub async fn serve(ctx: Arc<Context>, database: &Database, args: McpServe) -> Result<ExitCode> {
+    let handler = ToolServer::new(ctx, database, args)?;
+    let server = Server::<JsonRpcStdioTransport, _>::new(handler, tokio::io::stdin(), tokio::io::stdout())?.init()?;
+    // Runs until the client closes STDIN.
+    server.await??;
+    Ok(ExitCode::SUCCESS)
+}
+
+#[derive(Debug, Deserialize)]
+struct CallToolParams {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+struct ToolServer {
+    ctx: Arc<Context>,
+    specs: Vec<ToolSpec>,
+    trust_all: bool,
+    trusted: HashSet<String>,
+    tool_policy: ToolPolicy,
+    tool_context: ToolContext,
+    tool_limits: ToolLimits,
+    /// Where the calls are recorded, if chat.auditLog is enabled.
+    audit_log: Option<AuditLog>,
+    /// Recorded as the conversation of the calls in the audit log.
+    session_id: String,
+}
+
+impl ToolServer {
+    fn new(ctx: Arc<Context>, database: &Database, args: McpServe) -> Result<Self> {
+        let mut specs = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))?
+            .into_values()
+            .filter(|spec| SERVED_TOOLS.contains(&spec.name.as_str()))
+            .collect::<Vec<_>>();
+        specs.sort_by_key(|spec| SERVED_TOOLS.iter().position(|name| *name == spec.name));
+        // --trust-tools=... takes precedence over the chat.trustedTools setting
+        let trusted = args
+            .trust_tools
+            .or_else(|| database.settings.get_string_list(Setting::ChatTrustedTools))
+            .unwrap_or_default();
+        Ok(Self {
+            tool_policy: ToolPolicy::from_database(&ctx, database)?,
+            audit_log: AuditLog::from_database(&ctx, database),
+            session_id: uuid::Uuid::new_v4().to_string(),
+            ctx,
+            specs,
+            trust_all: args.trust_all_tools,
+            trusted: trusted.into_iter().collect(),
+            // STDIN and STDOUT are the protocol, commands can't prompt on them.
+            tool_context: ToolContext {
+                headless: true,
+                ..ToolContext::from_database(database)
+            },
+            tool_limits: ToolLimits::from_database(database),
+        })
+    }
+
+    fn list_tools(&self) -> Value {
+        let tools = self
+            .specs
+            .iter()
+            .map(|spec| {
+                json!({
+                    "name": spec.name,
+                    "description": spec.description,
+                    "inputSchema": spec.input_schema.0,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "tools": tools })
+    }
+
+    /// Runs the tool, the errors being sent to the client as the result of the tool.
+    async fn call_tool(&self, params: CallToolParams) -> Value {
+        let (text, is_error) = match self.run(params).await {
+            Ok(block) => (
+                match block {
+                    ToolUseResultBlock::Text(text) => text,
+                    ToolUseResultBlock::Json(value) => value.to_string(),
+                },
+                false,
+            ),
+            Err(message) => (message, true),
+        };
+        json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": is_error,
+        })
+    }
+
+    async fn run(&self, CallToolParams { name, arguments }: CallToolParams) -> Result<ToolUseResultBlock, String> {
+        let tool_use_id = uuid::Uuid::new_v4().to_string();
+        let invalid = |err: serde_json::Error| format!("Invalid arguments for {name}: {err}");
+        let mut tool = match name.as_str() {
+            "fs_read" => Tool::FsRead(serde_json::from_value::<FsRead>(arguments.clone()).map_err(invalid)?),
+            "fs_write" => Tool::FsWrite(serde_json::from_value::<FsWrite>(arguments.clone()).map_err(invalid)?),
+            "execute_bash" => {
+                Tool::ExecuteBash(serde_json::from_value::<ExecuteBash>(arguments.clone()).map_err(invalid)?)
+            },
+            "use_aws" => Tool::UseAws(serde_json::from_value::<UseAws>(arguments.clone()).map_err(invalid)?),
+            _ => {
+                return Err(format!(
+                    "Unknown tool {name}, the tools are {}",
+                    SERVED_TOOLS.join(", ")
+                ));
+            },
+        };
+        self.tool_context.contextualize(&mut tool);
+        tool.validate(&self.ctx)
+            .await
+            .map_err(|err| format!("Failed to validate tool parameters: {err}"))?;
+
+        let policy = self.tool_policy.evaluate(&self.ctx, &name, &arguments, &tool);
+        if let Some(rule) = policy.filter(|rule| rule.decision == PolicyDecision::Deny) {
+            self.audit(&tool_use_id, &name, &arguments, Decision::Rejected, None);
+            return Err(rule.denial(&name));
+        }
+        let allowed = !tool.always_requires_acceptance()
+            && !tool.outside_workspace(&self.ctx).await
+            && match policy.map(|rule| rule.decision) {
+                Some(PolicyDecision::Allow) => true,
+                Some(PolicyDecision::Prompt | PolicyDecision::Deny) => false,
+                None => self.trust_all || self.trusted.contains(&name) || !tool.requires_acceptance(&self.ctx),
+            };
+        if !allowed {
+            self.audit(&tool_use_id, &name, &arguments, Decision::Rejected, None);
+            return Err(format!(
+                "This use of {name} needs the approval of the user, which can't be asked for over MCP. Trust the tool with `q mcp serve --trust-tools` or allow it in the tool policy"
+            ));
+        }
+
+        let result: Result<ToolUseResultBlock, String> =
+            match tool.invoke(&self.ctx, &mut std::io::sink(), &self.tool_limits).await {
+                Ok(output) => Ok(output.into()),
+                Err(err) => Err(format!("An error occurred processing the tool: \n{err}")),
+            };
+        let (block, status) = match &result {
+            Ok(block) => (block.clone(), ToolResultStatus::Success),
+            Err(message) => (ToolUseResultBlock::Text(message.clone()), ToolResultStatus::Error),
+        };
+        let audited = ToolUseResult {
+            tool_use_id: tool_use_id.clone(),
+            content: vec![block],
+            status,
+        };
+        self.audit(&tool_use_id, &name, &arguments, Decision::Trusted, Some(&audited));
+        result
+    }
+
+    /// Appends the call to the audit log, if it is enabled, with its result if the tool ran.
+    fn audit(
+        &self,
+        tool_use_id: &str,
+        name: &str,
+        arguments: &Value,
+        decision: Decision,
+        result: Option<&ToolUseResult>,
+    ) {
+        let Some(audit_log) = &self.audit_log else {
+            return;
+        };
+        let entry = audit_entry(&self.session_id, tool_use_id, name, arguments.clone(), decision, result);
+        if let Err(err) = audit_log.append(entry) {
+            error!(?err, "Failed to record {name} in the audit log");
+        }
+    }
+}
+
+impl PreServerRequestHandler for ToolServer {
+    // The server sends no requests of its own.
+    fn register_pending_request_callback(
+        &mut self,
+        _cb: impl Fn(u64) -> Option<JsonRpcRequest> + Send + Sync + 'static,
+    ) {
+    }
+
+    fn register_send_request_callback(
+        &mut self,
+        _cb: impl Fn(&str, Option<Value>) -> Result<(), ServerError> + Send + Sync + 'static,
+    ) {
+    }
+}
+
+#[async_trait::async_trait]
+impl ServerRequestHandler for ToolServer {
+    async fn handle_initialize(&self, _params: Option<Value>) -> Result<Response, ServerError> {
+        Ok(Some(json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": { "tools": {} },
+            "serverInfo": { "name": "amazon-q-cli", "version": env!("CARGO_PKG_VERSION") },
+        })))
+    }
+
+    async fn handle_incoming(&self, method: &str, params: Option<Value>) -> Result<Response, ServerError> {
+        match method {
+            "notifications/initialized" | "notifications/cancelled" => Ok(None),
+            "ping" => Ok(Some(json!({}))),
+            "tools/list" => Ok(Some(self.list_tools())),
+            "tools/call" => {
+                let params = serde_json::from_value(params.unwrap_or_default())?;
+                Ok(Some(self.call_tool(params).await))
+            },
+            _ => Err(ServerError::MethodNotFound(method.to_string())),
+        }
+    }
+
+    async fn handle_response(&self, _resp: JsonRpcResponse) -> Result<(), ServerError> {
+        Ok(())
+    }
+
+    async fn handle_shutdown(&self) -> Result<(), ServerError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn tool_server(args: McpServe) -> (Arc<Context>, ToolServer) {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let database = Database::new().await.unwrap();
+        let server = ToolServer::new(Arc::clone(&ctx), &database, args).unwrap();
+        (ctx, server)
+    }
+
+    async fn call(server: &ToolServer, name: &str, arguments: Value) -> Value {
+        server
+            .handle_incoming("tools/call", Some(json!({ "name": name, "arguments": arguments })))
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_list_tools() {
+        let (_, server) = tool_server(McpServe::default()).await;
+        let tools = server.handle_incoming("tools/list", None).await.unwrap().unwrap();
+        let names = tools["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, SERVED_TOOLS);
+        assert!(tools["tools"][0]["inputSchema"].is_object());
+        assert!(matches!(
+            server.handle_incoming("resources/list", None).await,
+            Err(ServerError::MethodNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_call_tool() {
+        let write = json!({ "command": "create", "path": "/file.txt", "file_text": "Hello" });
+
+        let (ctx, server) = tool_server(McpServe::default()).await;
+        let result = call(&server, "fs_write", write.clone()).await;
+        assert_eq!(result["isError"], true);
+        assert!(
+            result["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("needs the approval")
+        );
+        assert!(!ctx.fs().exists("/file.txt"));
+        let result = call(&server, "web_browse", json!({})).await;
+        assert_eq!(result["isError"], true);
+
+        let (ctx, server) = tool_server(McpServe {
+            trust_tools: Some(vec!["fs_write".to_string()]),
+            ..Default::default()
+        })
+        .await;
+        let result = call(&server, "fs_write", write).await;
+        assert_eq!(result["isError"], false);
+        let result = call(&server, "fs_read", json!({ "mode": "Line", "path": "/file.txt" })).await;
+        assert_eq!(result["isError"], false);
+        assert!(result["content"][0]["text"].as_str().unwrap().contains("Hello"));
+        assert!(ctx.fs().exists("/file.txt"));
+    }
+}
@@ -1,4 +1,5 @@
 use std::env;
+use std::path::Path;
 
 use serde::{
     Deserialize,
@@ -19,6 +20,7 @@ use super::util::{
 use crate::api_client::model::{
     AssistantResponseMessage,
     EnvState,
+    GitState,
     ImageBlock,
     ToolResult,
     ToolResultContentBlock,
@@ -109,6 +111,19 @@ impl UserMessage {
         }
     }
 
+    /// Sets the state of the git repository sent with this message. It is not kept in the history.
+    pub fn set_git_state(&mut self, git_state: Option<GitState>) {
+        self.env_context.git_state = git_state;
+    }
+
+    /// Sets the directory the message is sent from, the current directory of the session rather
+    /// than the one of `q`, e.g. with `q chat --workspace`.
+    pub fn set_current_dir(&mut self, cwd: &Path) {
+        if let Some(env_state) = self.env_context.env_state.as_mut() {
+            env_state.current_working_directory = Some(truncate_current_dir(cwd));
+        }
+    }
+
     /// Converts this message into a [UserInputMessage] to be stored in the history of
     /// [api_client::model::ConversationState].
     pub fn into_history_entry(self) -> UserInputMessage {
@@ -147,6 +162,7 @@ impl UserMessage {
                 .to_string(),
             user_input_message_context: Some(UserInputMessageContext {
                 env_state: self.env_context.env_state,
+                git_state: self.env_context.git_state,
                 tool_results: match self.content {
                     UserMessageContent::CancelledToolUses { tool_use_results, .. }
                     | UserMessageContent::ToolUseResults { tool_use_results } => {
@@ -155,7 +171,6 @@ impl UserMessage {
                     UserMessageContent::Prompt { .. } => None,
                 },
                 tools: None,
-                ..Default::default()
             }),
             user_intent: None,
         }
@@ -260,12 +275,16 @@ impl From<InvokeOutput> for ToolUseResultBlock {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserEnvContext {
     env_state: Option<EnvState>,
+    /// Only sent with the next message, see [UserMessage::set_git_state].
+    #[serde(skip)]
+    git_state: Option<GitState>,
 }
 
 impl UserEnvContext {
     pub fn generate_new() -> Self {
         Self {
             env_state: Some(build_env_state()),
+            git_state: None,
         }
     }
 }
@@ -384,10 +403,7 @@ pub fn build_env_state() -> EnvState {
     };
 
     match env::current_dir() {
-        Ok(current_dir) => {
-            env_state.current_working_directory =
-                Some(truncate_safe(&current_dir.to_string_lossy(), MAX_CURRENT_WORKING_DIRECTORY_LEN).into());
-        },
+        Ok(current_dir) => env_state.current_working_directory = Some(truncate_current_dir(&current_dir)),
         Err(err) => {
             error!(?err, "Attempted to fetch the CWD but it did not exist.");
         },
@@ -396,6 +412,10 @@ pub fn build_env_state() -> EnvState {
     env_state
 }
 
+fn truncate_current_dir(cwd: &Path) -> String {
+    truncate_safe(&cwd.to_string_lossy(), MAX_CURRENT_WORKING_DIRECTORY_LEN).into()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
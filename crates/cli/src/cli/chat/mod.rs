@@ -1,23 +1,49 @@
+mod accessibility;
+pub mod agent_session;
+mod apply;
 pub mod cli;
 mod command;
+pub mod config;
 mod consts;
 mod context;
 mod conversation_state;
+pub mod event_schema;
+mod file_watcher;
+mod git_context;
 mod hooks;
+mod import;
 mod input_source;
+mod journal;
 pub mod mcp;
+mod mcp_serve;
 mod message;
+pub mod model_capabilities;
 pub mod openai_config;
 mod parse;
 mod parser;
+mod plan;
 mod prompt;
+mod pruning;
+mod sampling;
+mod scrollback;
 mod server_messenger;
+mod sessions;
+mod share;
 #[cfg(unix)]
 mod skim_integration;
-mod token_counter;
+pub mod snippets;
+mod speech;
+mod structured_output;
+mod template;
+mod timing;
+pub mod token_counter;
+mod tool_loop;
 mod tool_manager;
 mod tools;
+mod tui;
+mod turn_budget;
 pub mod util;
+mod workspace;
 
 use std::borrow::Cow;
 use std::collections::{
@@ -30,6 +56,10 @@ use std::io::{
     Read,
     Write,
 };
+use std::path::{
+    Path,
+    PathBuf,
+};
 use std::process::{
     Command as ProcessCommand,
     ExitCode,
@@ -41,19 +71,29 @@ use std::{
     fs,
 };
 
+use apply::extract_file_blocks;
 use command::{
     Command,
+    PlanSubcommand,
     PromptsSubcommand,
+    SnippetSubcommand,
+    SummaryFormat,
     ToolsSubcommand,
 };
 use consts::{
     CONTEXT_FILES_MAX_SIZE,
-    CONTEXT_WINDOW_SIZE,
     DUMMY_TOOL_NAME,
+    MAX_NUMBER_OF_IMAGES_PER_REQUEST,
+};
+use context::{
+    ContextManager,
+    context_paths_from_settings,
 };
-use context::ContextManager;
 pub use conversation_state::ConversationState;
-use conversation_state::TokenWarningLevel;
+use conversation_state::{
+    ContextUsage,
+    TokenWarningLevel,
+};
 use crossterm::style::{
     Attribute,
     Color,
@@ -71,6 +111,7 @@ use eyre::{
     Result,
     bail,
 };
+use file_watcher::FileWatcher;
 use hooks::{
     Hook,
     HookTrigger,
@@ -82,6 +123,10 @@ use message::{
     ToolUseResult,
     ToolUseResultBlock,
 };
+use model_capabilities::{
+    ModelCapabilities,
+    ModelCapabilityRegistry,
+};
 use parse::{
     ParseState,
     interpret_markdown,
@@ -90,22 +135,37 @@ use parser::{
     RecvErrorKind,
     ResponseParser,
 };
+use plan::{
+    Plan,
+    PlanStatus,
+};
 use rand::distr::{
     Alphanumeric,
     SampleString,
 };
 use regex::Regex;
+use sampling::ChatSampler;
+use scrollback::Scrollback;
 use serde_json::Map;
+use share::ShareTarget;
+use snippets::SnippetScope;
+use speech::{
+    SpeechConfig,
+    SpeechError,
+};
 use spinners::{
     Spinner,
     Spinners,
 };
+use structured_output::StructuredOutput;
 use thiserror::Error;
+use timing::SessionTimings;
 use token_counter::{
     TokenCount,
     TokenCounter,
 };
 use tokio::signal::ctrl_c;
+use tool_loop::LoopDetector;
 use tool_manager::{
     GetPromptError,
     LoadingRecord,
@@ -114,11 +174,29 @@ use tool_manager::{
     ToolManager,
     ToolManagerBuilder,
 };
-use tools::gh_issue::GhIssueContext;
+use tools::aws_resources::{
+    self,
+    ResourceLookup,
+};
+use tools::env_policy::EnvPolicy;
+use tools::fs_write::FsWrite;
+use tools::gh_issue::{
+    GhIssue,
+    GhIssueContext,
+};
+use tools::limits::ToolLimits;
+use tools::plugin_tool::PluginManifest;
+pub use tools::remote::ExecutionTarget;
+use tools::tool_policy::{
+    PolicyDecision,
+    ToolPolicy,
+};
+use tools::use_aws::UseAws;
 use tools::{
     OutputKind,
     QueuedTool,
     Tool,
+    ToolContext,
     ToolOrigin,
     ToolPermissions,
     ToolSpec,
@@ -130,8 +208,25 @@ use tracing::{
     trace,
     warn,
 };
+use tui::{
+    Tui,
+    TuiEvent,
+    TuiHandle,
+    TuiUsage,
+};
+use turn_budget::{
+    BudgetExhausted,
+    TurnBudget,
+};
 use unicode_width::UnicodeWidthStr;
-use util::images::RichImageBlock;
+use util::images::{
+    RichImageBlock,
+    RichImageBlocks,
+    detect_pasted_image_path,
+    format_image_size,
+    image_dimensions,
+    load_image_attachment,
+};
 use util::shared_writer::{
     NullWriter,
     SharedWriter,
@@ -139,26 +234,61 @@ use util::shared_writer::{
 use util::ui::draw_box;
 use util::{
     animate_output,
-    drop_matched_context_files,
     play_notification_bell,
+    redact,
     region_check,
+    truncate_safe,
 };
 use uuid::Uuid;
 use winnow::Partial;
 use winnow::stream::Offset;
 
-use crate::api_client::StreamingClient;
 use crate::api_client::clients::SendMessageOutput;
+use crate::api_client::clients::backends::ToolCallPattern;
 use crate::api_client::model::{
     ChatResponseStream,
+    CodeReference,
+    ConversationState as FigConversationState,
     Tool as FigTool,
     ToolResultStatus,
+    WebLink,
+};
+use crate::api_client::{
+    ErrorKind,
+    StreamingClient,
+};
+use crate::cli::audit::{
+    AuditEntry,
+    AuditLog,
+    Decision,
+};
+use crate::cli::i18n;
+use crate::cli::notify::{
+    Notification,
+    NotificationSink,
+    NotificationStatus,
+};
+use crate::cli::references::{
+    ReferenceEntry,
+    ReferenceLog,
+    response_snippet,
+};
+use crate::cli::shell_hook::LastCommand;
+use crate::cli::usage::UsageLedger;
+use crate::cli::user::{
+    LoginArgs,
+    login_interactive,
 };
 use crate::database::Database;
-use crate::database::settings::Setting;
+use crate::database::settings::{
+    Setting,
+    Settings,
+};
 use crate::mcp_client::{
     Prompt,
     PromptGetResult,
+    Roots,
+    Sampler,
 };
 use crate::platform::Context;
 use crate::telemetry::TelemetryThread;
@@ -178,6 +308,9 @@ that may eventually reach memory constraints.
 <cyan!>Usage</cyan!>
   <em>/compact</em>                   <black!>Summarize the conversation and clear history</black!>
   <em>/compact [prompt]</em>          <black!>Provide custom guidance for summarization</black!>
+  <em>/compact --prompt "..."</em>    <black!>Provide custom guidance along with other flags</black!>
+  <em>/compact --preview</em>         <black!>Show the summary and the messages it replaces before applying it</black!>
+  <em>/compact undo</em>              <black!>Restore the history replaced by the last compaction</black!>
 
 <cyan!>When to use</cyan!>
 • When you see the memory constraint warning message
@@ -243,6 +376,15 @@ const HELP_TEXT: &str = color_print::cstr! {"
 <em>/compact</em>      <black!>Summarize the conversation to free up context space</black!>
   <em>help</em>        <black!>Show help for the compact command</black!>
   <em>[prompt]</em>    <black!>Optional custom prompt to guide summarization</black!>
+  <em>--preview</em>   <black!>Show the summary and ask before replacing the history</black!>
+  <em>undo</em>        <black!>Restore the history replaced by the last compaction</black!>
+<em>/attach</em>       <black!>Attach an image to your next message</black!>
+<em>/apply</em>        <black!>Write the files in the code blocks of the last response</black!>
+<em>/speak</em>        <black!>Record a prompt from the microphone and transcribe it</black!>
+<em>/watch</em>        <black!>Notify the model when files from this session are edited externally [on|off]</black!>
+<em>/plan</em>         <black!>Plan a task, approve or edit the plan, then run it step by step</black!>
+<em>/snippet</em>      <black!>Save prompt fragments and insert them with #name [list|show|save|rm]</black!>
+<em>/summarize</em>    <black!>Summarize decisions, commands and file changes to share [--format md|jira|slack]</black!>
 <em>/tools</em>        <black!>View and manage tools and permissions</black!>
   <em>help</em>        <black!>Show an explanation for the trust command</black!>
   <em>trust</em>       <black!>Trust a specific tool or tools for the session</black!>
@@ -268,9 +410,16 @@ const HELP_TEXT: &str = color_print::cstr! {"
   <em>rm</em>          <black!>Remove file(s) from context [--global]</black!>
   <em>clear</em>       <black!>Clear all files from current context [--global]</black!>
   <em>hooks</em>       <black!>View and manage context hooks</black!>
+  <em>usage</em>       <black!>Show how the context window is spent, by category</black!>
 <em>/usage</em>        <black!>Show current session's context window usage</black!>
+<em>/stats</em>        <black!>Show the latency of recent turns: first chunk, model and tool time</black!>
+<em>/search</em>       <black!>Search the transcript of this session with a regex</black!>
+<em>/scrollback</em>   <black!>Page through the transcript, from a line of /search [line]. Also opened with PageUp</black!>
+<em>/translate</em>    <black!>Turn a description into a shell command to run, explain or refine</black!>
 <em>/load</em>         <black!>Load conversation state from a JSON file</black!>
 <em>/save</em>         <black!>Save conversation state to a JSON file</black!>
+<em>/export</em>       <black!>Export the transcript with secrets and personal information redacted [-f]</black!>
+<em>/share</em>        <black!>Upload the redacted transcript to chat.shareTarget and print its link [-e to encrypt]</black!>
 
 <cyan,em>MCP:</cyan,em>
 <black!>You can now configure the Amazon Q CLI to use MCP servers. \nLearn how: https://docs.aws.amazon.com/en_us/amazonq/latest/qdeveloper-ug/command-line-mcp.html</black!>
@@ -285,10 +434,14 @@ const HELP_TEXT: &str = color_print::cstr! {"
 "};
 
 const RESPONSE_TIMEOUT_CONTENT: &str = "Response timed out - message took too long to generate";
+/// Follow-ups suggested by the model that are shown after a response.
+const MAX_FOLLOWUP_PROMPTS: usize = 3;
 const TRUST_ALL_TEXT: &str = color_print::cstr! {"<green!>All tools are now trusted (<red!>!</red!>). Amazon Q will execute tools <bold>without</bold> asking for confirmation.\
 \nAgents can sometimes do unexpected things so understand the risks.</green!>
 \nLearn more at https://docs.aws.amazon.com/amazonq/latest/qdeveloper-ug/command-line-chat-security.html#command-line-chat-trustall-safety"};
 
+/// Pause after each element of a response with `chat.typewriter`.
+const TYPEWRITER_DELAY: Duration = Duration::from_millis(8);
 const TOOL_BULLET: &str = " ● ";
 const CONTINUATION_LINE: &str = " ⋮ ";
 const PURPOSE_ARROW: &str = " ↳ ";
@@ -301,56 +454,145 @@ pub async fn launch_chat(database: &mut Database, telemetry: &TelemetryThread, a
         tools
     });
 
+    if let Some(subcommand) = args.subcommand {
+        return sessions::execute_chat_subcommand(database, subcommand).await;
+    }
+
+    let ctx = workspace::configure(Context::new(), &args.workspaces).await?;
+    if !args.workspaces.is_empty() {
+        // The workspace settings are the ones of the first root, the current directory of the session.
+        database.settings = Settings::in_dir(Some(&ctx.env().current_dir()?)).await?;
+    }
+    let ctx = tools::remote::configure(ctx, database, args.remote.clone(), args.container.clone()).await?;
+    i18n::configure(database);
+    accessibility::configure(database);
+
+    let offline = args
+        .offline
+        .then(|| openai_config::OpenAiConfig::offline(database, args.model.clone()));
+
     // Save OpenAI configuration if provided
     if let Some(provider) = &args.provider {
-        use openai_config::{ChatProvider, OpenAiConfig};
-        
+        use openai_config::{
+            ChatProvider,
+            OpenAiConfig,
+        };
+
         let config = OpenAiConfig {
             provider: ChatProvider::from(provider.as_str()),
-            base_url: args.api_base_url.clone().unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
+            base_url: args
+                .api_base_url
+                .clone()
+                .unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
             api_key: args.api_key.clone(),
             model: args.model.clone().unwrap_or_else(|| "gpt-3.5-turbo".to_string()),
         };
-        
+
         config.save_to_database(database).await?;
     }
 
-    chat(
-        database,
-        telemetry,
-        args.input,
-        args.no_interactive,
-        args.resume,
-        args.accept_all,
-        args.profile,
-        args.trust_all_tools,
+    let input = match args.last_command {
+        true => Some(LastCommand::load()?.prompt(args.input.as_deref())),
+        false => args.input,
+    };
+
+    if args.format == cli::ChatFormat::Json {
+        return agent_session::run_json_lines(ctx, database, input).await;
+    }
+
+    chat(ctx, database, telemetry, ChatOptions {
+        input,
+        no_interactive: args.no_interactive,
+        resume_conversation: args.resume,
+        recover: args.recover,
+        accept_all: args.accept_all,
+        profile: args.profile,
+        trust_all_tools: args.trust_all_tools,
         trust_tools,
-    )
+        offline,
+        read_only: args.read_only,
+        dry_run: args.dry_run,
+        mcp_configs: args.mcp_configs.iter().map(PathBuf::from).collect(),
+        notify: args.notify,
+        schema: args.schema,
+        tui: args.tui,
+        verbose: args.verbose,
+    })
     .await
 }
 
-#[allow(clippy::too_many_arguments, clippy::fn_params_excessive_bools)]
+/// The options of a chat session, from the arguments of `q chat`.
+#[derive(Debug)]
+pub struct ChatOptions {
+    /// The first question to ask, with the output of the last command for `--last-command`.
+    pub input: Option<String>,
+    pub no_interactive: bool,
+    pub resume_conversation: bool,
+    pub recover: bool,
+    pub accept_all: bool,
+    pub profile: Option<String>,
+    pub trust_all_tools: bool,
+    pub trust_tools: Option<Vec<String>>,
+    /// The local model to chat with, for `--offline`.
+    pub offline: Option<openai_config::OpenAiConfig>,
+    pub read_only: bool,
+    pub dry_run: bool,
+    pub mcp_configs: Vec<PathBuf>,
+    pub notify: bool,
+    /// Path of the JSON schema the response must conform to.
+    pub schema: Option<String>,
+    pub tui: bool,
+    pub verbose: bool,
+}
+
+/// Runs a chat session in `ctx`, the context of the workspace of `q chat --workspace`.
 pub async fn chat(
+    ctx: Arc<Context>,
     database: &mut Database,
     telemetry: &TelemetryThread,
-    input: Option<String>,
-    no_interactive: bool,
-    resume_conversation: bool,
-    accept_all: bool,
-    profile: Option<String>,
-    trust_all_tools: bool,
-    trust_tools: Option<Vec<String>>,
+    options: ChatOptions,
 ) -> Result<ExitCode> {
-    if !crate::util::system_info::in_cloudshell() && !crate::auth::is_logged_in(database).await {
-        bail!(
-            "You are not logged in, please log in with {}",
-            format!("{CLI_BINARY_NAME} login").bold()
-        );
-    }
+    let ChatOptions {
+        input,
+        no_interactive,
+        resume_conversation,
+        recover,
+        accept_all,
+        profile,
+        trust_all_tools,
+        trust_tools,
+        offline,
+        read_only,
+        dry_run,
+        mcp_configs,
+        notify,
+        schema,
+        tui,
+        verbose,
+    } = options;
+
+    // Offline sessions never reach Amazon Q, so they don't need a login.
+    if offline.is_none() {
+        if !crate::util::system_info::in_cloudshell() && !crate::auth::is_logged_in(database).await {
+            bail!(
+                "You are not logged in, please log in with {}",
+                format!("{CLI_BINARY_NAME} login").bold()
+            );
+        }
 
-    region_check("chat")?;
+        region_check("chat")?;
+    }
 
-    let ctx = Context::new();
+    let notification_sink = match notify {
+        true => match NotificationSink::from_database(database) {
+            Some(sink) => Some(sink),
+            None => bail!(
+                "--notify needs a webhook, set one with {}",
+                format!("{CLI_BINARY_NAME} settings chat.notify.webhookUrl <URL>").bold()
+            ),
+        },
+        false => None,
+    };
 
     let stdin = std::io::stdin();
     // no_interactive flag or part of a pipe
@@ -364,19 +606,91 @@ pub async fn chat(
         input
     };
 
-    let mut output = match interactive {
-        true => SharedWriter::stderr(),
-        false => SharedWriter::stdout(),
+    let structured_output = schema
+        .map(|path| StructuredOutput::load(Path::new(&path)))
+        .transpose()?;
+    let input = match (&structured_output, input) {
+        (Some(_), None) => bail!("--schema needs a question to answer"),
+        (Some(structured_output), Some(input)) => Some(format!("{input}\n\n{}", structured_output.instructions())),
+        (None, input) => input,
+    };
+
+    if tui && !interactive {
+        bail!("--tui needs an interactive terminal");
+    }
+    if tui && accessibility::enabled() {
+        bail!("--tui isn't available with chat.accessibilityMode");
+    }
+    let (tui, tui_input) = match tui {
+        true => {
+            let (tui, input_source) = Tui::start()?;
+            (Some(tui), Some(input_source))
+        },
+        false => (None, None),
+    };
+
+    // With --schema, only the validated response is printed to STDOUT.
+    let mut output = match (&tui, interactive) {
+        (Some(tui), _) => tui.handle().transcript_writer(),
+        (None, true) => SharedWriter::adaptive(std::io::stderr()),
+        (None, false) if structured_output.is_some() => SharedWriter::stderr(),
+        (None, false) => SharedWriter::stdout(),
     };
+    if accessibility::enabled() {
+        output = accessibility::plain_output(output);
+    }
+    let scrollback = Scrollback::default();
+    if interactive {
+        output = scrollback.tee(output);
+    }
 
-    let client = match ctx.env().get("Q_MOCK_CHAT_RESPONSE") {
-        Ok(json) => create_stream(serde_json::from_str(std::fs::read_to_string(json)?.as_str())?),
+    let client = match (ctx.env().get("Q_MOCK_CHAT_RESPONSE"), &offline) {
+        (Ok(json), _) => create_stream(serde_json::from_str(std::fs::read_to_string(json)?.as_str())?),
+        (_, Some(config)) => {
+            let capabilities = ModelCapabilityRegistry::from_database(database).capabilities(&config.model);
+            let patterns = ToolCallPattern::from_database(database);
+            StreamingClient::new_openai_client(config.clone(), capabilities, patterns).await?
+        },
         _ => StreamingClient::new(database).await?,
     };
+    if let Some(config) = offline.as_ref().filter(|_| interactive) {
+        queue!(
+            output,
+            style::SetForegroundColor(Color::Yellow),
+            style::Print(format!(
+                "\nOffline mode: chatting with {} at {}. {} are disabled.\n",
+                config.model,
+                config.base_url,
+                tool_manager::NETWORK_TOOLS.join(", ")
+            )),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+    }
 
-    let mcp_server_configs = match McpServerConfig::load_config(&mut output).await {
+    if dry_run && interactive {
+        queue!(
+            output,
+            style::SetForegroundColor(Color::Yellow),
+            style::Print("\nDry run: the tools that change files or resources only describe what they would do.\n"),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+    }
+
+    if read_only && interactive {
+        queue!(
+            output,
+            style::SetForegroundColor(Color::Yellow),
+            style::Print(format!(
+                "\nRead-only mode: {}, MCP servers and plugins are disabled, and only commands that read can be run.\n",
+                tool_manager::MUTATING_TOOLS.join(", ")
+            )),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+    }
+
+    let mcp_server_configs = match McpServerConfig::load_config(&ctx, &mut output, database, &mcp_configs).await {
         Ok(config) => {
-            if interactive && !database.settings.get_bool(Setting::McpLoadedBefore).unwrap_or(false) {
+            if interactive && !database.settings.get_bool_or_default(Setting::McpLoadedBefore) {
                 execute!(
                     output,
                     style::Print(
@@ -387,11 +701,14 @@ pub async fn chat(
             database.settings.set(Setting::McpLoadedBefore, true).await?;
             config
         },
+        // Files given with --mcp-config have to load.
+        Err(e) if !mcp_configs.is_empty() => return Err(e),
         Err(e) => {
             warn!("No mcp server config loaded: {}", e);
             McpServerConfig::default()
         },
     };
+    let plugins = PluginManifest::load_all(&ctx, &mut output).await;
 
     // If profile is specified, verify it exists before starting the chat
     if let Some(ref profile_name) = profile {
@@ -423,16 +740,66 @@ pub async fn chat(
     } else {
         Box::new(NullWriter {})
     };
+    let sampling_model = match &offline {
+        Some(config) => config.model.clone(),
+        None => {
+            let config = openai_config::OpenAiConfig::from_database(database);
+            match config.is_openai_compatible() {
+                true => config.model,
+                false => "amazon-q".to_string(),
+            }
+        },
+    };
+    let sampler = ChatSampler::from_database(database, client.clone(), sampling_model, interactive)
+        .map(|sampler| Arc::new(sampler) as Arc<dyn Sampler>);
     let mut tool_manager = ToolManagerBuilder::default()
         .mcp_server_config(mcp_server_configs)
         .prompt_list_sender(prompt_response_sender)
         .prompt_list_receiver(prompt_request_receiver)
         .conversation_id(&conversation_id)
         .interactive(interactive)
+        .offline(offline.is_some())
+        .read_only(read_only)
+        .env_policy(EnvPolicy::from_database(database))
+        .plugins(plugins)
+        .sampler(sampler)
+        .roots(Roots::new(workspace::mcp_roots(&ctx)))
+        .workspace_roots(ctx.workspace_roots())
+        .execution_target(ctx.execution_target())
         .build(telemetry, tool_manager_output)
         .await?;
     let tool_config = tool_manager.load_tools(database, &mut output).await?;
     let mut tool_permissions = ToolPermissions::new(tool_config.len());
+    tool_permissions.trusted_plugins = tool_manager
+        .plugins
+        .values()
+        .filter(|plugin| plugin.trusted)
+        .map(|plugin| plugin.name.clone())
+        .collect();
+    // --trust-tools=... takes precedence over the chat.trustedTools setting
+    let trust_tools = match trust_tools {
+        Some(trust_tools) => Some(trust_tools),
+        None => database
+            .settings
+            .get_string_list(Setting::ChatTrustedTools)
+            .inspect(|tools| {
+                if interactive
+                    && !tools.is_empty()
+                    && database.settings.is_workspace_override(Setting::ChatTrustedTools)
+                {
+                    queue!(
+                        output,
+                        style::SetForegroundColor(Color::Yellow),
+                        style::Print(format!(
+                            "\nTrusting tools from the workspace settings: {}",
+                            tools.join(", ")
+                        )),
+                        style::SetForegroundColor(Color::Reset),
+                    )
+                    .ok();
+                }
+            }),
+    };
     if accept_all || trust_all_tools {
         tool_permissions.trust_all = true;
         for tool in tool_config.values() {
@@ -459,25 +826,83 @@ pub async fn chat(
         }
     }
 
+    let mut input_source = match tui_input {
+        Some(input_source) => input_source,
+        None => InputSource::new(database, prompt_request_sender, prompt_response_receiver)?,
+    };
+    input_source.bind_scrollback(scrollback.clone());
+    let terminal_width_provider: fn() -> Option<usize> = match tui.is_some() {
+        true => tui::transcript_width,
+        false => || terminal::window_size().map(|s| s.columns.into()).ok(),
+    };
     let mut chat = ChatContext::new(
         ctx,
         database,
         &conversation_id,
         output,
         input,
-        InputSource::new(database, prompt_request_sender, prompt_response_receiver)?,
+        input_source,
         interactive,
         resume_conversation,
         client,
-        || terminal::window_size().map(|s| s.columns.into()).ok(),
+        terminal_width_provider,
         tool_manager,
         profile,
         tool_config,
         tool_permissions,
     )
     .await?;
+    if let Some(config) = &offline {
+        chat.model_capabilities = ModelCapabilityRegistry::from_database(database).capabilities(&config.model);
+        chat.usage_ledger = UsageLedger::from_database(&chat.ctx, database, config);
+    }
+    TokenCounter::set_tokenizer(chat.model_capabilities.tokenizer);
+    chat.show_timings = verbose;
+    chat.dry_run = dry_run;
+    chat.structured_output = structured_output;
+    chat.tui = tui.as_ref().map(Tui::handle);
+    // The TUI draws on the terminal, commands can't prompt on it.
+    chat.tool_context.headless = !interactive || tui.is_some();
+    chat.scrollback = scrollback;
+    if recover {
+        chat.recover_conversation(database).await?;
+    }
+    if interactive {
+        let cwd = chat.ctx.env().current_dir()?;
+        chat.conversation_state.start_journal(&chat.ctx, cwd);
+    }
 
     let result = chat.try_chat(database, telemetry).await.map(|_| ExitCode::SUCCESS);
+    if result.is_ok() {
+        chat.conversation_state.finish_journal();
+        chat.conversation_state.remove_tool_results();
+    }
+    // Restores the terminal before anything else is printed.
+    drop(tui);
+    if let Some(sink) = notification_sink {
+        let title = format!(
+            "{CLI_BINARY_NAME} chat in {}",
+            chat.ctx.env().current_dir().unwrap_or_default().display()
+        );
+        let notification = match &result {
+            Ok(_) => Notification {
+                title,
+                status: NotificationStatus::Success,
+                message: chat
+                    .conversation_state
+                    .history()
+                    .back()
+                    .map(|(_, assistant)| assistant.content().to_string())
+                    .unwrap_or_default(),
+            },
+            Err(err) => Notification {
+                title,
+                status: NotificationStatus::Failure,
+                message: format!("{err:#}"),
+            },
+        };
+        sink.send_or_warn(&notification).await;
+    }
     drop(chat); // Explicit drop for clarity
 
     result
@@ -508,14 +933,23 @@ pub enum ChatError {
     Custom(Cow<'static, str>),
     #[error("interrupted")]
     Interrupted { tool_uses: Option<Vec<QueuedTool>> },
-    #[error(
-        "Tool approval required but --no-interactive was specified. Use --trust-all-tools to automatically approve tools."
-    )]
+    #[error("{}", i18n::message("error-tool-approval-non-interactive"))]
     NonInteractiveToolApproval,
     #[error(transparent)]
     GetPromptError(#[from] GetPromptError),
 }
 
+impl ChatError {
+    /// The category of errors caused by the model provider, if any.
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self {
+            ChatError::Client(err) => Some(err.kind()),
+            ChatError::ResponseStream(err) => err.kind(),
+            _ => None,
+        }
+    }
+}
+
 pub struct ChatContext {
     ctx: Arc<Context>,
     /// The [Write] destination for printing conversation text.
@@ -523,6 +957,9 @@ pub struct ChatContext {
     initial_input: Option<String>,
     /// Whether we're starting a new conversation or continuing an old one.
     existing_conversation: bool,
+    /// Whether the conversation was recovered from the journal of an interrupted session, to pick
+    /// up where it stopped.
+    recovered: bool,
     input_source: InputSource,
     interactive: bool,
     /// The client to use to interact with the model.
@@ -542,6 +979,54 @@ pub struct ChatContext {
     failed_request_ids: Vec<String>,
     /// Pending prompts to be sent
     pending_prompts: VecDeque<Prompt>,
+    /// What the model in use supports, e.g. its context window and image input.
+    model_capabilities: ModelCapabilities,
+    /// Files read or written during the session, checked for external edits.
+    file_watcher: FileWatcher,
+    /// Images attached with `/attach` to be sent with the next message.
+    pending_images: RichImageBlocks,
+    /// How `/speak` records and transcribes voice input.
+    speech: SpeechConfig,
+    /// Where `/share` uploads transcripts.
+    share_target: ShareTarget,
+    /// Latency of the turns of the session, shown by `/stats`.
+    timings: SessionTimings,
+    /// Whether to print the timing of every turn, set with `-v`.
+    show_timings: bool,
+    /// Whether the tools that change anything only describe what they would do, set with
+    /// `--dry-run`.
+    dry_run: bool,
+    /// Whether responses are revealed progressively, with `chat.typewriter` when printed to a
+    /// terminal.
+    typewriter: bool,
+    /// The plan started with `/plan`, until it is done or discarded.
+    plan: Option<Plan>,
+    /// The policies and settings applied to the tools. Its environment policy also applies to the
+    /// templates of snippets.
+    tool_context: ToolContext,
+    /// How long tools may run and how much of their output is sent to the model.
+    tool_limits: ToolLimits,
+    /// How many tools the model may use before the user is asked whether it should go on.
+    turn_budget: TurnBudget,
+    /// Stops the model when it keeps making the same tool call.
+    loop_detector: LoopDetector,
+    /// Where the activity of the session is sent when shown with `--tui`.
+    tui: Option<TuiHandle>,
+    /// Everything printed during the session, for `/search` and `/scrollback`.
+    scrollback: Scrollback,
+    /// Prompts suggested after the last response, sent by typing their number. Only kept while
+    /// shown, until the next input.
+    followup_prompts: Vec<String>,
+    /// The schema the response must conform to, with `--schema`.
+    structured_output: Option<StructuredOutput>,
+    /// Where the tools used are recorded, if chat.auditLog is enabled.
+    audit_log: Option<AuditLog>,
+    /// Tool uses the user approved when asked, recorded as such in the audit log.
+    approved_tool_uses: HashSet<String>,
+    /// Rules allowing, denying or prompting for tool uses, which apply to trusted tools too.
+    tool_policy: ToolPolicy,
+    /// Where the tokens of the responses are recorded, if chat.usageLedger is enabled.
+    usage_ledger: Option<UsageLedger>,
 }
 
 impl ChatContext {
@@ -566,8 +1051,10 @@ impl ChatContext {
         let output_clone = output.clone();
 
         let mut existing_conversation = false;
-        let conversation_state = if resume_conversation {
-            let prior = std::env::current_dir()
+        let mut conversation_state = if resume_conversation {
+            let prior = ctx
+                .env()
+                .current_dir()
                 .ok()
                 .and_then(|cwd| database.get_conversation_by_path(cwd).ok())
                 .flatten();
@@ -607,11 +1094,20 @@ impl ChatContext {
             .await
         };
 
+        configure_conversation(&mut conversation_state, database);
+
+        let watch_files = database.settings.get_bool_or_default(Setting::ChatWatchFiles);
+        let audit_log = AuditLog::from_database(&ctx, database);
+        let usage_ledger =
+            UsageLedger::from_database(&ctx, database, &openai_config::OpenAiConfig::from_database(database));
+        let tool_policy = ToolPolicy::from_database(&ctx, database)?;
+
         Ok(Self {
             ctx,
             output,
             initial_input: input,
             existing_conversation,
+            recovered: false,
             input_source,
             interactive,
             client,
@@ -623,10 +1119,162 @@ impl ChatContext {
             tool_use_status: ToolUseStatus::Idle,
             failed_request_ids: Vec::new(),
             pending_prompts: VecDeque::new(),
+            model_capabilities: ModelCapabilityRegistry::for_current_provider(database),
+            file_watcher: FileWatcher::new(watch_files),
+            pending_images: Vec::new(),
+            speech: SpeechConfig::from_database(database),
+            share_target: ShareTarget::from_database(database),
+            timings: SessionTimings::default(),
+            show_timings: false,
+            dry_run: false,
+            typewriter: interactive
+                && database.settings.get_bool_or_default(Setting::ChatTypewriter)
+                && std::io::stderr().is_terminal()
+                && !accessibility::enabled(),
+            plan: None,
+            tool_context: ToolContext {
+                headless: !interactive,
+                ..ToolContext::from_database(database)
+            },
+            tool_limits: ToolLimits::from_database(database),
+            turn_budget: TurnBudget::from_database(database),
+            loop_detector: LoopDetector::from_database(database),
+            tui: None,
+            scrollback: Scrollback::default(),
+            followup_prompts: Vec::new(),
+            structured_output: None,
+            audit_log,
+            approved_tool_uses: HashSet::new(),
+            tool_policy,
+            usage_ledger,
         })
     }
 }
 
+impl ChatContext {
+    /// Replaces the conversation with the one of the last session interrupted in the current
+    /// directory, as recorded in its journal, see `q chat --recover`.
+    async fn recover_conversation(&mut self, database: &Database) -> Result<()> {
+        let cwd = self.ctx.env().current_dir()?;
+        let Some(path) = journal::find_interrupted(&self.ctx, &cwd)? else {
+            bail!("No interrupted conversation to recover in {}", cwd.display());
+        };
+        let mut conversation_state = journal::replay(&path)?;
+        conversation_state
+            .reload_serialized_state(Arc::clone(&self.ctx), Some(self.output.clone()))
+            .await;
+        conversation_state.tool_manager = std::mem::take(&mut self.conversation_state.tool_manager);
+        conversation_state.update_state(true).await;
+        conversation_state.enforce_tool_use_history_invariants();
+        configure_conversation(&mut conversation_state, database);
+
+        self.conversation_state = conversation_state;
+        self.existing_conversation = true;
+        self.recovered = true;
+        Ok(())
+    }
+
+    /// Where a recovered conversation picks up: asking again for the approval of the tools the
+    /// model wanted to use, or sending the message that got no response.
+    fn recovered_state(&self) -> ChatState {
+        if self.conversation_state.next_user_message().is_some() {
+            return ChatState::RetryMessage;
+        }
+        match self.conversation_state.history().back() {
+            Some((_, AssistantMessage::ToolUse { tool_uses, .. })) => ChatState::ValidateTools(tool_uses.clone()),
+            _ => ChatState::PromptUser {
+                tool_uses: None,
+                pending_tool_index: None,
+                skip_printing_tools: true,
+            },
+        }
+    }
+
+    /// With `--schema`, prints the last response to STDOUT once it conforms to the schema, or
+    /// returns the state asking the model to fix it.
+    fn check_structured_output(&mut self) -> Result<Option<ChatState>> {
+        let Some(structured_output) = self.structured_output.as_mut() else {
+            return Ok(None);
+        };
+        let response = self
+            .conversation_state
+            .history()
+            .back()
+            .map(|(_, assistant)| assistant.content())
+            .unwrap_or_default();
+        let errors = match structured_output.validate(response) {
+            Ok(document) => {
+                writeln!(std::io::stdout(), "{}", serde_json::to_string_pretty(&document)?)?;
+                return Ok(None);
+            },
+            Err(errors) => errors,
+        };
+        if !structured_output.retry() {
+            bail!(
+                "The response does not conform to the schema after {} attempts:\n{}",
+                StructuredOutput::MAX_ATTEMPTS,
+                errors.join("\n")
+            );
+        }
+
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::Yellow),
+            style::Print(format!(
+                "\nThe response does not conform to the schema, asking for a fix: {}\n",
+                errors.join("; ")
+            )),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        Ok(Some(ChatState::HandleInput {
+            input: StructuredOutput::feedback(&errors),
+            tool_uses: None,
+            pending_tool_index: None,
+        }))
+    }
+}
+
+/// The entry of the audit log for the use of a tool, with the result sent to the model if it ran.
+fn audit_entry(
+    conversation_id: &str,
+    tool_use_id: &str,
+    tool_name: &str,
+    args: serde_json::Value,
+    decision: Decision,
+    result: Option<&ToolUseResult>,
+) -> AuditEntry {
+    let entry = AuditEntry::new(conversation_id, tool_use_id, tool_name, args, decision);
+    match result {
+        Some(result) => entry.with_result(
+            matches!(result.status, ToolResultStatus::Success),
+            &serde_json::to_vec(&result.content).unwrap_or_default(),
+        ),
+        None => entry,
+    }
+}
+
+/// Applies the settings of the conversation kept out of its saved state.
+fn configure_conversation(conversation_state: &mut ConversationState, database: &Database) {
+    if let Some(context_manager) = conversation_state.context_manager.as_mut() {
+        context_manager.settings_paths = context_paths_from_settings(database);
+        context_manager.env_policy = EnvPolicy::from_database(database);
+    }
+    conversation_state.set_compress_tool_results_after(
+        database
+            .settings
+            .get_int_or_default(Setting::ChatCompressToolResultsAfter)
+            .filter(|exchanges| *exchanges > 0)
+            .map(|exchanges| exchanges as usize),
+    );
+    conversation_state.set_git_context(database.settings.get_bool_or_default(Setting::ChatGitContext));
+    conversation_state.set_language(
+        database
+            .settings
+            .get_string(Setting::ChatLanguage)
+            .filter(|language| !language.trim().is_empty()),
+    );
+}
+
 impl Drop for ChatContext {
     fn drop(&mut self) {
         if let Some(spinner) = &mut self.spinner {
@@ -676,6 +1324,8 @@ enum ChatState {
     ExecuteTools(Vec<QueuedTool>),
     /// Consume the response stream and display to the user.
     HandleResponseStream(SendMessageOutput),
+    /// Send the pending message again, e.g. after logging in again.
+    RetryMessage,
     /// Compact the chat history.
     CompactHistory {
         tool_uses: Option<Vec<QueuedTool>>,
@@ -686,6 +1336,8 @@ enum ChatState {
         show_summary: bool,
         /// Whether or not to show the /compact help text.
         help: bool,
+        /// Whether to show the summary and the messages it replaces, and ask before replacing them.
+        preview: bool,
     },
     /// Exit the chat.
     Exit,
@@ -755,7 +1407,7 @@ impl ChatContext {
 
     async fn try_chat(&mut self, database: &mut Database, telemetry: &TelemetryThread) -> Result<()> {
         let is_small_screen = self.terminal_width() < GREETING_BREAK_POINT;
-        if self.interactive && database.settings.get_bool(Setting::ChatGreetingEnabled).unwrap_or(true) {
+        if self.interactive && database.settings.get_bool_or_default(Setting::ChatGreetingEnabled) {
             let welcome_text = match self.existing_conversation {
                 true => RESUME_TEXT,
                 false => match is_small_screen {
@@ -827,6 +1479,8 @@ impl ChatContext {
                 tool_uses: None,
                 pending_tool_index: None,
             });
+        } else if self.recovered {
+            next_state = Some(self.recovered_state());
         }
 
         loop {
@@ -844,12 +1498,17 @@ impl ChatContext {
                     pending_tool_index,
                     skip_printing_tools,
                 } => {
-                    // Cannot prompt in non-interactive mode no matter what.
+                    // Cannot prompt in non-interactive mode no matter what, the model is only asked
+                    // to fix a response not conforming to the --schema.
                     if !self.interactive {
-                        return Ok(());
+                        match self.check_structured_output()? {
+                            Some(retry) => Ok(retry),
+                            None => return Ok(()),
+                        }
+                    } else {
+                        self.prompt_user(database, tool_uses, pending_tool_index, skip_printing_tools)
+                            .await
                     }
-                    self.prompt_user(database, tool_uses, pending_tool_index, skip_printing_tools)
-                        .await
                 },
                 ChatState::HandleInput {
                     input,
@@ -868,10 +1527,11 @@ impl ChatContext {
                     prompt,
                     show_summary,
                     help,
+                    preview,
                 } => {
                     let tool_uses_clone = tool_uses.clone();
                     tokio::select! {
-                        res = self.compact_history(telemetry, tool_uses, pending_tool_index, prompt, show_summary, help) => res,
+                        res = self.compact_history(telemetry, tool_uses, pending_tool_index, prompt, show_summary, help, preview) => res,
                         Ok(_) = ctrl_c_stream => Err(ChatError::Interrupted { tool_uses: tool_uses_clone })
                     }
                 },
@@ -892,10 +1552,14 @@ impl ChatContext {
                     res = self.handle_response(database, telemetry, response) => res,
                     Ok(_) = ctrl_c_stream => Err(ChatError::Interrupted { tool_uses: None })
                 },
+                ChatState::RetryMessage => tokio::select! {
+                    res = self.retry_message() => res,
+                    Ok(_) = ctrl_c_stream => Err(ChatError::Interrupted { tool_uses: None })
+                },
                 ChatState::Exit => return Ok(()),
             };
 
-            next_state = Some(self.handle_state_execution_result(database, result).await?);
+            next_state = Some(self.handle_state_execution_result(database, telemetry, result).await?);
         }
     }
 
@@ -904,6 +1568,7 @@ impl ChatContext {
     async fn handle_state_execution_result(
         &mut self,
         database: &mut Database,
+        telemetry: &TelemetryThread,
         result: Result<ChatState, ChatError>,
     ) -> Result<ChatState, ChatError> {
         // Remove non-ASCII and ANSI characters.
@@ -938,11 +1603,12 @@ impl ChatContext {
 
                 macro_rules! print_default_error {
                     ($err:expr) => {
-                        print_err!("Amazon Q is having trouble responding right now", $err);
+                        print_err!(i18n::message("error-responding"), $err);
                     };
                 }
 
                 error!(?e, "An error occurred processing the current state");
+                let hint = e.kind().and_then(|kind| kind.hint());
                 if self.interactive && self.spinner.is_some() {
                     drop(self.spinner.take());
                     queue!(
@@ -982,12 +1648,15 @@ impl ChatContext {
                                 execute!(
                                     self.output,
                                     style::SetForegroundColor(Color::Red),
-                                    style::Print("Your conversation is too large to continue.\n"),
+                                    style::Print(format!("{}\n", i18n::message("error-conversation-too-large"))),
                                     style::SetForegroundColor(Color::Reset),
-                                    style::Print(format!("• Run {} to analyze your context usage\n", "/usage".green())),
                                     style::Print(format!(
-                                        "• Run {} to reset your conversation state\n",
-                                        "/clear".green()
+                                        "{}\n",
+                                        i18n::message_with("hint-usage", &[("command", &"/usage".green().to_string())])
+                                    )),
+                                    style::Print(format!(
+                                        "{}\n",
+                                        i18n::message_with("hint-clear", &[("command", &"/clear".green().to_string())])
                                     )),
                                     style::SetAttribute(Attribute::Reset),
                                     style::Print("\n\n"),
@@ -1003,7 +1672,7 @@ impl ChatContext {
                             execute!(
                                 self.output,
                                 style::SetForegroundColor(Color::Yellow),
-                                style::Print("The context window has overflowed, summarizing the history..."),
+                                style::Print(i18n::message("context-overflow")),
                                 style::SetAttribute(Attribute::Reset),
                                 style::Print("\n\n"),
                             )?;
@@ -1014,11 +1683,20 @@ impl ChatContext {
                                 prompt: None,
                                 show_summary: false,
                                 help: false,
+                                preview: false,
                             });
                         },
                         crate::api_client::ApiClientError::QuotaBreach(msg) => {
                             print_err!(msg, err);
                         },
+                        // The token could not be refreshed, offer to log in again and resume the
+                        // pending message instead of losing it.
+                        err if err.requires_login() && self.interactive => {
+                            if self.login_again(database, telemetry).await? {
+                                return Ok(ChatState::RetryMessage);
+                            }
+                            print_default_error!(err);
+                        },
                         _ => {
                             print_default_error!(err);
                         },
@@ -1027,6 +1705,14 @@ impl ChatContext {
                         print_default_error!(e);
                     },
                 }
+                if let Some(hint) = hint {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(format!("{hint}\n\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
                 self.conversation_state.enforce_conversation_invariants();
                 self.conversation_state.reset_next_user_message();
                 Ok(ChatState::PromptUser {
@@ -1038,10 +1724,84 @@ impl ChatContext {
         }
     }
 
+    /// Asks the user whether to log in again after their session expired, returning whether they
+    /// are logged in afterwards.
+    async fn login_again(&mut self, database: &mut Database, telemetry: &TelemetryThread) -> Result<bool, ChatError> {
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::Yellow),
+            style::Print("Your session has expired. Log in again to continue? "),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("["),
+            style::SetForegroundColor(Color::Green),
+            style::Print("y"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("/"),
+            style::SetForegroundColor(Color::Green),
+            style::Print("n"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("]:\n\n"),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        let confirmation = self
+            .read_user_input("> ".yellow().to_string().as_str(), true)
+            .unwrap_or_default();
+        if !["y", "Y"].contains(&confirmation.trim()) {
+            return Ok(false);
+        }
+
+        let args = LoginArgs {
+            license: None,
+            identity_provider: None,
+            region: None,
+            use_device_flow: false,
+        };
+        if let Err(err) = login_interactive(database, telemetry, args).await {
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::Red),
+                style::Print(format!("Failed to log in: {err}\n\n")),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+            return Ok(false);
+        }
+
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::Green),
+            style::Print("\nLogged in, resending your message...\n\n"),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        Ok(true)
+    }
+
+    /// Sends the pending message again, its history and tool results are kept as they were.
+    async fn retry_message(&mut self) -> Result<ChatState, ChatError> {
+        let conv_state = self.conversation_state.as_sendable_conversation_state(false).await;
+        if self.interactive {
+            queue!(self.output, cursor::Hide)?;
+            self.start_spinner("Thinking...");
+        }
+        Ok(ChatState::HandleResponseStream(self.send_message(conv_state).await?))
+    }
+
+    /// Sends the next request of the turn to the model, timing it for `/stats`.
+    async fn send_message(
+        &mut self,
+        mut conversation_state: FigConversationState,
+    ) -> Result<SendMessageOutput, ChatError> {
+        conversation_state.response_schema = self.structured_output.as_ref().map(|s| s.schema().clone());
+        self.timings.request_sent();
+        Ok(self.client.send_message(conversation_state).await?)
+    }
+
     /// Compacts the conversation history, replacing the history with a summary generated by the
     /// model.
     ///
     /// The last two user messages in the history are not included in the compaction process.
+    /// With `preview`, the summary and the messages it replaces are shown first, and the history
+    /// is only replaced if the user confirms.
+    #[allow(clippy::too_many_arguments)]
     async fn compact_history(
         &mut self,
         telemetry: &TelemetryThread,
@@ -1050,6 +1810,7 @@ impl ChatContext {
         custom_prompt: Option<String>,
         show_summary: bool,
         help: bool,
+        preview: bool,
     ) -> Result<ChatState, ChatError> {
         let hist = self.conversation_state.history();
         debug!(?hist, "compacting history");
@@ -1092,7 +1853,7 @@ impl ChatContext {
             .await;
         if self.interactive {
             execute!(self.output, cursor::Hide, style::Print("\n"))?;
-            self.spinner = Some(Spinner::new(Spinners::Dots, "Creating summary...".to_string()));
+            self.start_spinner("Creating summary...");
         }
         let response = self.client.send_message(summary_state).await;
 
@@ -1126,12 +1887,12 @@ impl ChatContext {
             },
         };
 
-        let summary = {
+        let (summary, usage) = {
             let mut parser = ResponseParser::new(response);
             loop {
                 match parser.recv().await {
                     Ok(parser::ResponseEvent::EndStream { message }) => {
-                        break message.content().to_string();
+                        break (message.content().to_string(), parser.usage());
                     },
                     Ok(_) => (),
                     Err(err) => {
@@ -1160,10 +1921,26 @@ impl ChatContext {
                     self.conversation_state.conversation_id().to_owned(),
                     message_id.to_owned(),
                     self.conversation_state.context_message_length(),
+                    None,
+                    None,
+                    None,
+                    usage,
                 )
                 .ok();
         }
 
+        if preview && !self.confirm_compaction(&summary)? {
+            execute!(
+                self.output,
+                style::Print("\nThe conversation history was left as is.\n\n")
+            )?;
+            return Ok(ChatState::PromptUser {
+                tool_uses,
+                pending_tool_index,
+                skip_printing_tools: true,
+            });
+        }
+
         self.conversation_state.replace_history_with_summary(summary.clone());
 
         // Print output to the user.
@@ -1182,7 +1959,7 @@ impl ChatContext {
                     style::Print(format!("• Custom prompt applied: {}\n", custom_prompt))
                 )?;
             }
-            animate_output(&mut self.output, &output)?;
+            animate_output(&mut self.output, &output, self.typewriter)?;
 
             // Display the summary if the show_summary flag is set
             if show_summary {
@@ -1211,7 +1988,7 @@ impl ChatContext {
                     style::Print("The conversation history has been replaced with this summary.\n"),
                     style::Print("It contains all important details from previous interactions.\n"),
                 )?;
-                animate_output(&mut self.output, &output)?;
+                animate_output(&mut self.output, &output, self.typewriter)?;
 
                 execute!(
                     self.output,
@@ -1224,11 +2001,8 @@ impl ChatContext {
 
         // If a next message is set, then retry the request.
         if self.conversation_state.next_user_message().is_some() {
-            Ok(ChatState::HandleResponseStream(
-                self.client
-                    .send_message(self.conversation_state.as_sendable_conversation_state(false).await)
-                    .await?,
-            ))
+            let conv_state = self.conversation_state.as_sendable_conversation_state(false).await;
+            Ok(ChatState::HandleResponseStream(self.send_message(conv_state).await?))
         } else {
             // Otherwise, return back to the prompt for any pending tool uses.
             Ok(ChatState::PromptUser {
@@ -1239,51 +2013,692 @@ impl ChatContext {
         }
     }
 
-    /// Read input from the user.
-    async fn prompt_user(
-        &mut self,
-        database: &Database,
-        mut tool_uses: Option<Vec<QueuedTool>>,
-        pending_tool_index: Option<usize>,
-        skip_printing_tools: bool,
-    ) -> Result<ChatState, ChatError> {
-        execute!(self.output, cursor::Show)?;
-        let tool_uses = tool_uses.take().unwrap_or_default();
+    /// Shows the summary a compaction would replace the history with along with the messages it
+    /// replaces, and asks whether to go ahead.
+    fn confirm_compaction(&mut self, summary: &str) -> Result<bool, ChatError> {
+        let history = self.conversation_state.history();
+        let replaced = history.iter().take(history.len().saturating_sub(1)).collect::<Vec<_>>();
+        let terminal_width = self.terminal_width();
+        let border = "─".repeat(terminal_width.min(80));
 
-        // Check token usage and display warnings if needed
-        if pending_tool_index.is_none() {
-            // Only display warnings when not waiting for tool approval
-            if self.conversation_state.can_create_summary_request().await {
-                if let Err(e) = self.display_char_warnings().await {
-                    warn!("Failed to display character limit warnings: {}", e);
-                }
-            }
+        queue!(
+            self.output,
+            style::SetAttribute(Attribute::Bold),
+            style::Print(format!("The summary replaces {} exchange(s):\n", replaced.len())),
+            style::SetAttribute(Attribute::Reset),
+            style::SetForegroundColor(Color::DarkGrey),
+        )?;
+        for (user, assistant) in replaced {
+            let prompt = match user.prompt() {
+                Some(prompt) => prompt.lines().next().unwrap_or_default().to_string(),
+                None => "(tool results)".to_string(),
+            };
+            let tools = assistant.tool_uses().map_or(0, |tool_uses| tool_uses.len());
+            let line = match tools {
+                0 => format!("> {prompt}"),
+                _ => format!("> {prompt} ({tools} tool use(s))"),
+            };
+            queue!(
+                self.output,
+                style::Print(truncate_safe(&line, terminal_width.saturating_sub(2))),
+                style::Print("\n")
+            )?;
         }
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::Cyan),
+            style::Print(format!("\n{border}\n")),
+            style::SetForegroundColor(Color::Reset),
+            style::Print(summary),
+            style::SetForegroundColor(Color::Cyan),
+            style::Print(format!("\n{border}\n\n")),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("Replace the conversation history with this summary? ["),
+            style::SetForegroundColor(Color::Green),
+            style::Print("y"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("/"),
+            style::SetForegroundColor(Color::Green),
+            style::Print("n"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("]:\n\n"),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        let confirmation = self
+            .read_user_input("> ".yellow().to_string().as_str(), true)
+            .unwrap_or_default();
+        Ok(["y", "Y"].contains(&confirmation.trim()))
+    }
 
-        let show_tool_use_confirmation_dialog = !skip_printing_tools && pending_tool_index.is_some();
-        if show_tool_use_confirmation_dialog {
+    /// Writes the files contained in the code blocks of the last assistant response through
+    /// `fs_write`, after showing a diff and asking for approval unless the tool is trusted.
+    async fn apply_code_blocks(&mut self) -> Result<(), ChatError> {
+        let blocks = self
+            .conversation_state
+            .history()
+            .back()
+            .map(|(_, assistant)| extract_file_blocks(assistant.content()))
+            .unwrap_or_default();
+        if blocks.is_empty() {
             execute!(
                 self.output,
-                style::SetForegroundColor(Color::DarkGrey),
-                style::Print("\nAllow this action? Use '"),
-                style::SetForegroundColor(Color::Green),
-                style::Print("t"),
-                style::SetForegroundColor(Color::DarkGrey),
-                style::Print("' to trust (always allow) this tool for the session. ["),
-                style::SetForegroundColor(Color::Green),
-                style::Print("y"),
-                style::SetForegroundColor(Color::DarkGrey),
-                style::Print("/"),
-                style::SetForegroundColor(Color::Green),
-                style::Print("n"),
-                style::SetForegroundColor(Color::DarkGrey),
+                style::SetForegroundColor(Color::Yellow),
+                style::Print("\nThe last response has no code blocks annotated with a file path.\n\n"),
+                style::SetForegroundColor(Color::Reset)
+            )?;
+            return Ok(());
+        }
+
+        let tools = blocks
+            .into_iter()
+            .map(|block| {
+                Tool::FsWrite(FsWrite::Create {
+                    path: block.path,
+                    file_text: Some(block.content),
+                    new_str: None,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        execute!(
+            self.output,
+            style::Print(format!("\nFound {} file(s) to write:\n\n", tools.len()))
+        )?;
+        for tool in &tools {
+            tool.queue_description(&self.ctx, &mut self.output)
+                .await
+                .map_err(|e| ChatError::Custom(format!("failed to print diff: {}", e).into()))?;
+            execute!(self.output, style::Print("\n"))?;
+        }
+
+        if !self.tool_permissions.is_trusted("fs_write") {
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print("Apply these changes? ["),
+                style::SetForegroundColor(Color::Green),
+                style::Print("y"),
+                style::SetForegroundColor(Color::DarkGrey),
                 style::Print("/"),
                 style::SetForegroundColor(Color::Green),
-                style::Print("t"),
+                style::Print("n"),
                 style::SetForegroundColor(Color::DarkGrey),
                 style::Print("]:\n\n"),
                 style::SetForegroundColor(Color::Reset),
             )?;
+            let confirmation = self
+                .read_user_input("> ".yellow().to_string().as_str(), true)
+                .unwrap_or_default();
+            if !["y", "Y"].contains(&confirmation.trim()) {
+                execute!(self.output, style::Print("\nNo files were written.\n\n"))?;
+                return Ok(());
+            }
+        }
+
+        for tool in &tools {
+            let result = tool.invoke(&self.ctx, &mut self.output, &self.tool_limits).await;
+            self.file_watcher.track(&self.ctx, tool.file_paths(&self.ctx)).await;
+            if let Err(err) = result {
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Red),
+                    style::Print(format!("Failed to write file: {}\n", err)),
+                    style::SetForegroundColor(Color::Reset)
+                )?;
+            }
+        }
+        execute!(self.output, style::Print("\n"))?;
+
+        Ok(())
+    }
+
+    /// Records and transcribes a prompt, returning it once confirmed by the user.
+    async fn record_speech(&mut self) -> Result<Option<String>, ChatError> {
+        if !self.interactive {
+            return Err(ChatError::Custom("/speak is only available in interactive mode".into()));
+        }
+
+        let result: Result<String, SpeechError> = async {
+            let dir = tempfile::tempdir()?;
+            let audio = dir.path().join("speech.wav");
+            let recording = self.speech.start_recording(&audio)?;
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::Cyan),
+                style::Print("\nRecording... press Enter to stop.\n"),
+                style::SetForegroundColor(Color::Reset)
+            )?;
+            tokio::task::spawn_blocking(|| std::io::stdin().read_line(&mut String::new()))
+                .await
+                .map_err(std::io::Error::other)??;
+            recording.stop().await?;
+
+            execute!(self.output, cursor::Hide)?;
+            self.start_spinner("Transcribing...");
+            let text = self.speech.transcribe(&audio).await;
+            drop(self.spinner.take());
+            queue!(
+                self.output,
+                terminal::Clear(terminal::ClearType::CurrentLine),
+                cursor::MoveToColumn(0),
+                cursor::Show
+            )?;
+            text
+        }
+        .await;
+
+        let text = match result {
+            Ok(text) if !text.is_empty() => text,
+            Ok(_) => {
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Yellow),
+                    style::Print("\nNo speech was recognized.\n\n"),
+                    style::SetForegroundColor(Color::Reset)
+                )?;
+                return Ok(None);
+            },
+            Err(err) => {
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Red),
+                    style::Print(format!("\nError: {}\n\n", err)),
+                    style::SetForegroundColor(Color::Reset)
+                )?;
+                return Ok(None);
+            },
+        };
+
+        execute!(
+            self.output,
+            style::Print("\n"),
+            style::Print(&text),
+            style::Print("\n\n"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("Send this message? ["),
+            style::SetForegroundColor(Color::Green),
+            style::Print("y"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("/"),
+            style::SetForegroundColor(Color::Green),
+            style::Print("n"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("/"),
+            style::SetForegroundColor(Color::Green),
+            style::Print("e"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("dit]:\n\n"),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+
+        let confirmation = self
+            .read_user_input("> ".yellow().to_string().as_str(), true)
+            .unwrap_or_default();
+        Ok(match confirmation.trim() {
+            "y" | "Y" => Some(text),
+            "e" | "E" => Self::open_editor(Some(text))
+                .ok()
+                .filter(|text| !text.trim().is_empty()),
+            _ => None,
+        })
+    }
+
+    /// Validates and queues an image to be sent with the next user message.
+    fn attach_image(&mut self, path: &str) -> Result<(), ChatError> {
+        let error = if !self.model_capabilities.supports_images {
+            Some("The current model does not support image input.".to_string())
+        } else if self.pending_images.len() >= MAX_NUMBER_OF_IMAGES_PER_REQUEST {
+            Some(format!(
+                "At most {} images can be attached to a message.",
+                MAX_NUMBER_OF_IMAGES_PER_REQUEST
+            ))
+        } else {
+            match load_image_attachment(path) {
+                Ok(image) => {
+                    let (block, metadata) = &image;
+                    let dimensions = image_dimensions(block)
+                        .map(|(width, height)| format!("{}x{}, ", width, height))
+                        .unwrap_or_default();
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format!(
+                            "\nAttached {} ({}{}). It will be sent with your next message.\n\n",
+                            metadata.filename,
+                            dimensions,
+                            format_image_size(metadata.size)
+                        )),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                    self.pending_images.push(image);
+                    None
+                },
+                Err(err) => Some(err),
+            }
+        };
+
+        if let Some(error) = error {
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::Red),
+                style::Print(format!("\nError: {}\n\n", error)),
+                style::SetForegroundColor(Color::Reset)
+            )?;
+        }
+        Ok(())
+    }
+
+    async fn handle_snippet_command(&mut self, subcommand: SnippetSubcommand) -> Result<(), ChatError> {
+        let scope = |workspace: bool| {
+            if workspace {
+                SnippetScope::Workspace
+            } else {
+                SnippetScope::Global
+            }
+        };
+        let result = match subcommand {
+            SnippetSubcommand::List => {
+                let snippets = snippets::load_all(&self.ctx).await;
+                if snippets.is_empty() {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Yellow),
+                        style::Print("\nNo snippets saved yet. Save one with /snippet save <name> [text].\n\n"),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                }
+                for snippet in &snippets {
+                    let first_line = snippet.content.lines().next().unwrap_or_default();
+                    execute!(
+                        self.output,
+                        style::Print(format!("\n#{} ", snippet.name).green()),
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(format!("({}) {}", snippet.scope, truncate_safe(first_line, 60))),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                }
+                if !snippets.is_empty() {
+                    execute!(self.output, style::Print("\n\n"))?;
+                }
+                Ok(())
+            },
+            SnippetSubcommand::Show { name } => {
+                match snippets::load_all(&self.ctx)
+                    .await
+                    .into_iter()
+                    .find(|snippet| snippet.name == name)
+                {
+                    Some(snippet) => {
+                        execute!(self.output, style::Print(format!("\n{}\n\n", snippet.content)))?;
+                        Ok(())
+                    },
+                    None => Err(eyre::eyre!("No snippet named '{name}'")),
+                }
+            },
+            SnippetSubcommand::Save {
+                name,
+                workspace,
+                content,
+            } => {
+                let content = match content {
+                    Some(content) => content,
+                    None => Self::open_editor(None)?,
+                };
+                if content.trim().is_empty() {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Yellow),
+                        style::Print("\nEmpty snippet, not saving.\n\n"),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                    return Ok(());
+                }
+                match snippets::save(&self.ctx, &name, &content, scope(workspace)).await {
+                    Ok(path) => {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Green),
+                            style::Print(format!("\nSaved #{name} to {}\n\n", path.display())),
+                            style::SetForegroundColor(Color::Reset)
+                        )?;
+                        Ok(())
+                    },
+                    Err(err) => Err(err),
+                }
+            },
+            SnippetSubcommand::Remove { name, workspace } => {
+                let scope = scope(workspace);
+                match snippets::remove(&self.ctx, &name, scope).await {
+                    Ok(true) => {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Green),
+                            style::Print(format!("\nRemoved the {scope} snippet #{name}\n\n")),
+                            style::SetForegroundColor(Color::Reset)
+                        )?;
+                        Ok(())
+                    },
+                    Ok(false) => Err(eyre::eyre!("No {scope} snippet named '{name}'")),
+                    Err(err) => Err(err),
+                }
+            },
+            SnippetSubcommand::Help => {
+                execute!(
+                    self.output,
+                    style::Print(format!("\n{}\n\n", SnippetSubcommand::help_text()))
+                )?;
+                Ok(())
+            },
+        };
+
+        if let Err(error) = result {
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::Red),
+                style::Print(format!("\nError: {}\n\n", error)),
+                style::SetForegroundColor(Color::Reset)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Handles `/plan`, returning the prompt to send, if any.
+    fn handle_plan_command(&mut self, subcommand: PlanSubcommand) -> Result<Option<String>, ChatError> {
+        match subcommand {
+            PlanSubcommand::Start { task } => {
+                let plan = Plan::new(task);
+                let request = plan.request();
+                self.plan = Some(plan);
+                return Ok(Some(request));
+            },
+            PlanSubcommand::Continue => match self.plan.as_mut() {
+                Some(plan) if plan.status == PlanStatus::Paused => {
+                    plan.status = PlanStatus::Running;
+                    return Ok(Some(plan.step_prompt()));
+                },
+                _ => execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Yellow),
+                    style::Print("\nThere is no stopped plan to continue.\n\n"),
+                    style::SetForegroundColor(Color::Reset)
+                )?,
+            },
+            PlanSubcommand::Stop => {
+                let message = match self.plan.take() {
+                    Some(_) => "\nThe plan was discarded.\n\n",
+                    None => "\nThere is no plan.\n\n",
+                };
+                execute!(self.output, style::Print(message))?;
+            },
+            PlanSubcommand::Show => match self.plan.take() {
+                Some(plan) => {
+                    self.print_plan(&plan)?;
+                    self.plan = Some(plan);
+                },
+                None => execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Yellow),
+                    style::Print("\nThere is no plan. Start one with /plan <task>.\n\n"),
+                    style::SetForegroundColor(Color::Reset)
+                )?,
+            },
+            PlanSubcommand::Help => {
+                execute!(
+                    self.output,
+                    style::Print(format!("\n{}\n\n", PlanSubcommand::help_text()))
+                )?;
+            },
+        }
+        Ok(None)
+    }
+
+    /// Moves the plan on once the model answered: reads the plan from the response and asks the
+    /// user to approve it, or asks whether to start the next step. Returns the prompt to send, if
+    /// any.
+    fn plan_checkpoint(&mut self) -> Result<Option<String>, ChatError> {
+        let Some(mut plan) = self.plan.take() else {
+            return Ok(None);
+        };
+        match plan.status {
+            PlanStatus::Paused => (),
+            PlanStatus::Drafting => {
+                let response = self
+                    .conversation_state
+                    .history()
+                    .back()
+                    .map(|(_, assistant)| assistant.content().to_string())
+                    .unwrap_or_default();
+                plan.steps = plan::parse_steps(&response);
+                return self.review_plan(plan);
+            },
+            PlanStatus::Running => {
+                plan.done += 1;
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("\n✓ Step {} of {} done\n", plan.done, plan.steps.len())),
+                    style::SetForegroundColor(Color::Reset)
+                )?;
+                let Some(step) = plan.next_step() else {
+                    execute!(self.output, style::Print("\nThe plan is complete.\n\n"))?;
+                    return Ok(None);
+                };
+                execute!(
+                    self.output,
+                    style::Print(format!("\nNext, step {}: {}\n", plan.done + 1, step)),
+                )?;
+                if self.confirm("Continue?")? {
+                    let prompt = plan.step_prompt();
+                    self.plan = Some(plan);
+                    return Ok(Some(prompt));
+                }
+                plan.status = PlanStatus::Paused;
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Yellow),
+                    style::Print("\nThe plan is stopped, resume it with /plan continue.\n\n"),
+                    style::SetForegroundColor(Color::Reset)
+                )?;
+            },
+        }
+        self.plan = Some(plan);
+        Ok(None)
+    }
+
+    /// Asks the user to run, edit or discard the plan, returning the prompt of its first step if
+    /// it is run.
+    fn review_plan(&mut self, mut plan: Plan) -> Result<Option<String>, ChatError> {
+        loop {
+            if plan.steps.is_empty() {
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Yellow),
+                    style::Print("\nThe plan has no numbered steps, it was discarded.\n\n"),
+                    style::SetForegroundColor(Color::Reset)
+                )?;
+                return Ok(None);
+            }
+
+            self.print_plan(&plan)?;
+            let answer = self.ask("Run this plan? Use 'e' to edit it.", &["y", "n", "e"])?;
+            match answer.as_str() {
+                "y" => {
+                    plan.status = PlanStatus::Running;
+                    let prompt = plan.step_prompt();
+                    self.plan = Some(plan);
+                    return Ok(Some(prompt));
+                },
+                "e" => plan.steps = plan::parse_steps(&Self::open_editor(Some(plan.to_text()))?),
+                _ => {
+                    execute!(self.output, style::Print("\nThe plan was discarded.\n\n"))?;
+                    return Ok(None);
+                },
+            }
+        }
+    }
+
+    fn print_plan(&mut self, plan: &Plan) -> Result<(), ChatError> {
+        execute!(
+            self.output,
+            style::SetAttribute(Attribute::Bold),
+            style::Print(format!("\nPlan: {}\n", plan.task)),
+            style::SetAttribute(Attribute::Reset)
+        )?;
+        for (i, step) in plan.steps.iter().enumerate() {
+            let (marker, color) = match i.cmp(&plan.done) {
+                std::cmp::Ordering::Less => ("✓", Color::Green),
+                std::cmp::Ordering::Equal if plan.status != PlanStatus::Drafting => ("→", Color::Yellow),
+                _ => (" ", Color::Reset),
+            };
+            execute!(
+                self.output,
+                style::SetForegroundColor(color),
+                style::Print(format!("{marker} {}. ", i + 1)),
+                style::SetForegroundColor(Color::Reset),
+                style::Print(&step.description),
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(match step.tools.is_empty() {
+                    true => String::new(),
+                    false => format!(" [{}]", step.tools.join(", ")),
+                }),
+                style::SetForegroundColor(Color::Reset),
+                style::Print("\n")
+            )?;
+        }
+        execute!(self.output, style::Print("\n"))?;
+        Ok(())
+    }
+
+    /// Prints a shareable summary of the session without modifying the conversation history.
+    async fn summarize_session(&mut self, format: SummaryFormat) -> Result<(), ChatError> {
+        if self.conversation_state.history().is_empty() {
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::Yellow),
+                style::Print("\nNothing to summarize yet.\n\n"),
+                style::SetForegroundColor(Color::Reset)
+            )?;
+            return Ok(());
+        }
+
+        let summary_state = self.conversation_state.create_session_summary_request(format).await;
+        if self.interactive {
+            execute!(self.output, cursor::Hide, style::Print("\n"))?;
+            self.start_spinner("Summarizing session...");
+        }
+
+        let response = self.client.send_message(summary_state).await?;
+        let summary = {
+            let mut parser = ResponseParser::new(response);
+            loop {
+                match parser.recv().await {
+                    Ok(parser::ResponseEvent::EndStream { message }) => {
+                        break message.content().to_string();
+                    },
+                    Ok(_) => (),
+                    Err(err) => {
+                        if let Some(request_id) = &err.request_id {
+                            self.failed_request_ids.push(request_id.clone());
+                        };
+                        return Err(err.into());
+                    },
+                }
+            }
+        };
+
+        if self.interactive && self.spinner.is_some() {
+            drop(self.spinner.take());
+            queue!(
+                self.output,
+                terminal::Clear(terminal::ClearType::CurrentLine),
+                cursor::MoveToColumn(0),
+                cursor::Show
+            )?;
+        }
+
+        // Printed as is so it can be copied without any styling.
+        execute!(
+            self.output,
+            style::Print(summary.trim()),
+            style::Print("\n\n"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("This summary was not added to the conversation history.\n\n"),
+            style::SetForegroundColor(Color::Reset)
+        )?;
+
+        Ok(())
+    }
+
+    /// Read input from the user.
+    async fn prompt_user(
+        &mut self,
+        database: &Database,
+        mut tool_uses: Option<Vec<QueuedTool>>,
+        pending_tool_index: Option<usize>,
+        skip_printing_tools: bool,
+    ) -> Result<ChatState, ChatError> {
+        execute!(self.output, cursor::Show)?;
+        let tool_uses = tool_uses.take().unwrap_or_default();
+
+        // A response to a plan or to one of its steps ends here.
+        if pending_tool_index.is_none() {
+            if let Some(input) = self.plan_checkpoint()? {
+                return Ok(ChatState::HandleInput {
+                    input,
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                });
+            }
+        }
+
+        // Check token usage and display warnings if needed
+        if pending_tool_index.is_none() {
+            // Only display warnings when not waiting for tool approval
+            if self.conversation_state.can_create_summary_request().await {
+                if let Err(e) = self.display_char_warnings().await {
+                    warn!("Failed to display character limit warnings: {}", e);
+                }
+            }
+        }
+
+        if pending_tool_index.is_none() {
+            self.send_tui_usage().await;
+        }
+
+        let show_tool_use_confirmation_dialog = !skip_printing_tools && pending_tool_index.is_some();
+        if show_tool_use_confirmation_dialog {
+            queue!(self.output, style::Print("\n"))?;
+            accessibility::marker(&mut self.output, "Approval needed")?;
+            // The model may have guessed the name of a resource of use_aws.
+            let fixable = match pending_tool_index.and_then(|index| tool_uses.get(index)) {
+                Some(QueuedTool {
+                    tool: Tool::UseAws(use_aws),
+                    ..
+                }) => !use_aws.resource_parameters().is_empty(),
+                _ => false,
+            };
+            if fixable {
+                i18n::print_highlighted(
+                    &mut self.output,
+                    "approval-fix-resource",
+                    &[("fix", "f")],
+                    Color::DarkGrey,
+                    Color::Green,
+                )?;
+                queue!(self.output, style::Print("\n"))?;
+            }
+            i18n::print_highlighted(
+                &mut self.output,
+                "approval-prompt",
+                &[("yes", "y"), ("no", "n"), ("trust", "t")],
+                Color::DarkGrey,
+                Color::Green,
+            )?;
+            execute!(
+                self.output,
+                style::Print("\n\n"),
+                style::SetForegroundColor(Color::Reset)
+            )?;
         }
 
         // Do this here so that the skim integration sees an updated view of the context *during the current
@@ -1327,6 +2742,25 @@ impl ChatContext {
         tool_uses: Option<Vec<QueuedTool>>,
         pending_tool_index: Option<usize>,
     ) -> Result<ChatState, ChatError> {
+        // The suggestions only apply to the input right after them
+        let followup_prompts = std::mem::take(&mut self.followup_prompts);
+        if pending_tool_index.is_none() {
+            let followup = user_input
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .and_then(|number| followup_prompts.get(number.checked_sub(1)?));
+            if let Some(followup) = followup {
+                user_input = followup.clone();
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(format!("> {user_input}\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            }
+        }
+
         let command_result = Command::parse(&user_input, &mut self.output);
 
         if let Err(error_message) = &command_result {
@@ -1352,6 +2786,11 @@ impl ChatContext {
             Command::Ask { prompt } => {
                 // Check for a pending tool approval
                 if let Some(index) = pending_tool_index {
+                    if ["f", "F"].contains(&prompt.as_str()) {
+                        if let Tool::UseAws(_) = &tool_uses[index].tool {
+                            return self.fix_aws_resource(tool_uses, index).await;
+                        }
+                    }
                     let tool_use = &mut tool_uses[index];
 
                     let is_trust = ["t", "T"].contains(&prompt.as_str());
@@ -1360,9 +2799,38 @@ impl ChatContext {
                             self.tool_permissions.trust_tool(&tool_use.name);
                         }
                         tool_use.accepted = true;
+                        self.approved_tool_uses.insert(tool_use.id.clone());
 
                         return Ok(ChatState::ExecuteTools(tool_uses));
                     }
+                } else if let Some(path) = detect_pasted_image_path(&prompt) {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(format!("\nAttach image {} to your next message? ", path)),
+                        style::Print("["),
+                        style::SetForegroundColor(Color::Green),
+                        style::Print("y"),
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("/"),
+                        style::SetForegroundColor(Color::Green),
+                        style::Print("n"),
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("]:\n\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+
+                    let confirmation = self
+                        .read_user_input("> ".yellow().to_string().as_str(), true)
+                        .unwrap_or_default();
+                    if ["y", "Y"].contains(&confirmation.as_str()) {
+                        self.attach_image(&path)?;
+                        return Ok(ChatState::PromptUser {
+                            tool_uses: Some(tool_uses),
+                            pending_tool_index,
+                            skip_printing_tools: true,
+                        });
+                    }
                 } else if !self.pending_prompts.is_empty() {
                     let prompts = self.pending_prompts.drain(0..).collect();
                     user_input = self
@@ -1371,14 +2839,35 @@ impl ChatContext {
                         .ok_or(ChatError::Custom("Prompt append failed".into()))?;
                 }
 
+                let (expanded, names) =
+                    snippets::expand_saved(&self.ctx, &self.tool_context.env_policy, &user_input).await;
+                if !names.is_empty() {
+                    let names = names.iter().map(|name| format!("#{name}")).collect::<Vec<_>>();
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(format!("Expanded {}\n", names.join(", "))),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                    user_input = expanded;
+                }
+
                 // Otherwise continue with normal chat on 'n' or other responses
                 self.tool_use_status = ToolUseStatus::Idle;
+                user_input = self.file_watcher.annotate_user_input(&self.ctx, user_input).await;
 
-                if pending_tool_index.is_some() {
+                if let Some(index) = pending_tool_index {
+                    if let Some(tool) = tool_uses.get(index) {
+                        self.audit_tool_use(&tool.id, &tool.name, Decision::Rejected, None);
+                    }
                     self.conversation_state.abandon_tool_use(tool_uses, user_input);
                 } else {
                     self.conversation_state.set_next_user_message(user_input).await;
                 }
+                if !self.pending_images.is_empty() {
+                    let images = self.pending_images.drain(..).map(|(block, _)| block).collect();
+                    self.conversation_state.add_images_to_next_message(images);
+                }
 
                 let conv_state = self.conversation_state.as_sendable_conversation_state(true).await;
                 self.send_tool_use_telemetry(telemetry).await;
@@ -1388,14 +2877,21 @@ impl ChatContext {
                     queue!(self.output, style::SetForegroundColor(Color::Reset))?;
                     queue!(self.output, cursor::Hide)?;
                     execute!(self.output, style::Print("\n"))?;
-                    self.spinner = Some(Spinner::new(Spinners::Dots, "Thinking...".to_owned()));
+                    self.start_spinner("Thinking...");
                 }
 
-                ChatState::HandleResponseStream(self.client.send_message(conv_state).await?)
+                self.timings.start_turn();
+                self.turn_budget.reset();
+                ChatState::HandleResponseStream(self.send_message(conv_state).await?)
             },
             Command::Execute { command } => {
                 queue!(self.output, style::Print('\n'))?;
-                std::process::Command::new("bash").args(["-c", &command]).status().ok();
+                let mut process = std::process::Command::new("bash");
+                process.args(["-c", &command]);
+                if let Ok(cwd) = self.ctx.env().current_dir() {
+                    process.current_dir(cwd);
+                }
+                process.status().ok();
                 queue!(self.output, style::Print('\n'))?;
                 ChatState::PromptUser {
                     tool_uses: None,
@@ -1449,10 +2945,34 @@ impl ChatContext {
                     skip_printing_tools: true,
                 }
             },
+            Command::Compact { undo: true, .. } => {
+                if self.conversation_state.undo_compaction() {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print("\n✔ Restored the conversation history replaced by the last compaction.\n\n"),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                } else {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Yellow),
+                        style::Print("\nThere is no compaction to undo in this session.\n\n"),
+                        style::SetForegroundColor(Color::Reset)
+                    )?;
+                }
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
             Command::Compact {
                 prompt,
                 show_summary,
                 help,
+                preview,
+                ..
             } => {
                 self.compact_history(
                     telemetry,
@@ -1461,9 +2981,73 @@ impl ChatContext {
                     prompt,
                     show_summary,
                     help,
+                    preview,
                 )
                 .await?
             },
+            Command::Apply => {
+                self.apply_code_blocks().await?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Speak => match self.record_speech().await? {
+                Some(input) => ChatState::HandleInput {
+                    input,
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                },
+                None => ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                },
+            },
+            Command::Attach { path } => {
+                self.attach_image(&path)?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Watch { enabled } => {
+                if let Some(enabled) = enabled {
+                    self.file_watcher.set_enabled(enabled);
+                }
+                let status = if self.file_watcher.is_enabled() { "on" } else { "off" };
+                execute!(
+                    self.output,
+                    style::Print(format!(
+                        "\nFile watching is {}. {} file(s) from this session are tracked.\n\n",
+                        status,
+                        self.file_watcher.tracked_files()
+                    ))
+                )?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Summarize { format } => {
+                self.summarize_session(format).await?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Snippet { subcommand } => {
+                self.handle_snippet_command(subcommand).await?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
             Command::Help => {
                 execute!(self.output, style::Print(HELP_TEXT))?;
                 ChatState::PromptUser {
@@ -1472,6 +3056,18 @@ impl ChatContext {
                     skip_printing_tools: true,
                 }
             },
+            Command::Plan { subcommand } => match self.handle_plan_command(subcommand)? {
+                Some(input) => ChatState::HandleInput {
+                    input,
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                },
+                None => ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                },
+            },
             Command::Issue { prompt } => {
                 let input = "I would like to report an issue or make a feature request";
                 ChatState::HandleInput {
@@ -1731,6 +3327,33 @@ impl ChatContext {
                                 .map_err(map_chat_error)?;
                             }
 
+                            if !context_manager.settings_paths.is_empty() {
+                                execute!(
+                                    self.output,
+                                    style::SetAttribute(Attribute::Bold),
+                                    style::SetForegroundColor(Color::Magenta),
+                                    style::Print("\n⚙️ settings (chat.contextPaths):\n"),
+                                    style::SetAttribute(Attribute::Reset),
+                                )?;
+                                for path in &context_manager.settings_paths {
+                                    execute!(self.output, style::Print(format!("    {} ", path)))?;
+                                    if let Ok(context_files) = context_manager.get_context_files_by_path(path).await {
+                                        execute!(
+                                            self.output,
+                                            style::SetForegroundColor(Color::Green),
+                                            style::Print(format!(
+                                                "({} match{})",
+                                                context_files.len(),
+                                                if context_files.len() == 1 { "" } else { "es" }
+                                            )),
+                                            style::SetForegroundColor(Color::Reset)
+                                        )?;
+                                        global_context_files.extend(context_files);
+                                    }
+                                    execute!(self.output, style::Print("\n"))?;
+                                }
+                            }
+
                             // Display profile context
                             execute!(
                                 self.output,
@@ -1862,14 +3485,7 @@ impl ChatContext {
                                     execute!(self.output, style::Print(format!("{}\n\n", "▔".repeat(3))),)?;
                                 }
 
-                                let mut combined_files: Vec<(String, String)> = global_context_files
-                                    .iter()
-                                    .chain(profile_context_files.iter())
-                                    .cloned()
-                                    .collect();
-
-                                let dropped_files =
-                                    drop_matched_context_files(&mut combined_files, CONTEXT_FILES_MAX_SIZE).ok();
+                                let dropped_files = self.conversation_state.dropped_context_files().await.ok();
 
                                 execute!(
                                     self.output,
@@ -2009,6 +3625,10 @@ impl ChatContext {
                                 )?;
                             },
                         },
+                        command::ContextSubcommand::Usage => {
+                            let usage = self.conversation_state.context_usage().await;
+                            self.print_context_usage(&usage)?;
+                        },
                         command::ContextSubcommand::Help => {
                             execute!(
                                 self.output,
@@ -2421,10 +4041,12 @@ impl ChatContext {
 
                         let mut origin_tools: Vec<_> = self.conversation_state.tools.iter().collect();
 
-                        // Built in tools always appear first.
+                        // Built in tools always appear first, then plugins.
                         origin_tools.sort_by(|(origin_a, _), (origin_b, _)| match (origin_a, origin_b) {
                             (ToolOrigin::Native, _) => std::cmp::Ordering::Less,
                             (_, ToolOrigin::Native) => std::cmp::Ordering::Greater,
+                            (ToolOrigin::Plugin, _) => std::cmp::Ordering::Less,
+                            (_, ToolOrigin::Plugin) => std::cmp::Ordering::Greater,
                             (ToolOrigin::McpServer(name_a), ToolOrigin::McpServer(name_b)) => name_a.cmp(name_b),
                         });
 
@@ -2732,6 +4354,7 @@ impl ChatContext {
                 }
 
                 let data = state.calculate_conversation_size();
+                let context_window_size = self.model_capabilities.context_window_tokens;
 
                 let context_token_count: TokenCount = data.context_messages.into();
                 let assistant_token_count: TokenCount = data.assistant_messages.into();
@@ -2743,11 +4366,11 @@ impl ChatContext {
                 // set a max width for the progress bar for better aesthetic
                 let progress_bar_width = std::cmp::min(window_width, 80);
 
-                let context_width = ((context_token_count.value() as f64 / CONTEXT_WINDOW_SIZE as f64)
+                let context_width = ((context_token_count.value() as f64 / context_window_size as f64)
                     * progress_bar_width as f64) as usize;
-                let assistant_width = ((assistant_token_count.value() as f64 / CONTEXT_WINDOW_SIZE as f64)
+                let assistant_width = ((assistant_token_count.value() as f64 / context_window_size as f64)
                     * progress_bar_width as f64) as usize;
-                let user_width = ((user_token_count.value() as f64 / CONTEXT_WINDOW_SIZE as f64)
+                let user_width = ((user_token_count.value() as f64 / context_window_size as f64)
                     * progress_bar_width as f64) as usize;
 
                 let left_over_width = progress_bar_width
@@ -2761,7 +4384,7 @@ impl ChatContext {
                         style::Print(format!(
                             "\nCurrent context window ({} of {}k tokens used)\n",
                             total_token_used,
-                            CONTEXT_WINDOW_SIZE / 1000
+                            context_window_size / 1000
                         )),
                         style::SetForegroundColor(Color::DarkRed),
                         style::Print("█".repeat(progress_bar_width)),
@@ -2769,7 +4392,7 @@ impl ChatContext {
                         style::Print(" "),
                         style::Print(format!(
                             "{:.2}%",
-                            (total_token_used.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
+                            (total_token_used.value() as f32 / context_window_size as f32) * 100.0
                         )),
                     )?;
                 } else {
@@ -2778,7 +4401,7 @@ impl ChatContext {
                         style::Print(format!(
                             "\nCurrent context window ({} of {}k tokens used)\n",
                             total_token_used,
-                            CONTEXT_WINDOW_SIZE / 1000
+                            context_window_size / 1000
                         )),
                         style::SetForegroundColor(Color::DarkCyan),
                         // add a nice visual to mimic "tiny" progress, so the overral progress bar doesn't look too
@@ -2800,70 +4423,205 @@ impl ChatContext {
                         style::Print("|".repeat(if user_width == 0 && *user_token_count > 0 { 1 } else { 0 })),
                         style::Print("█".repeat(user_width)),
                         style::SetForegroundColor(Color::DarkGrey),
-                        style::Print("█".repeat(left_over_width)),
-                        style::Print(" "),
+                        style::Print("█".repeat(left_over_width)),
+                        style::Print(" "),
+                        style::SetForegroundColor(Color::Reset),
+                        style::Print(format!(
+                            "{:.2}%",
+                            (total_token_used.value() as f32 / context_window_size as f32) * 100.0
+                        )),
+                    )?;
+                }
+
+                queue!(self.output, style::Print("\n\n"))?;
+                self.output.flush()?;
+
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::DarkCyan),
+                    style::Print("█ Context files: "),
+                    style::SetForegroundColor(Color::Reset),
+                    style::Print(format!(
+                        "~{} tokens ({:.2}%)\n",
+                        context_token_count,
+                        (context_token_count.value() as f32 / context_window_size as f32) * 100.0
+                    )),
+                    style::SetForegroundColor(Color::Blue),
+                    style::Print("█ Q responses: "),
+                    style::SetForegroundColor(Color::Reset),
+                    style::Print(format!(
+                        "  ~{} tokens ({:.2}%)\n",
+                        assistant_token_count,
+                        (assistant_token_count.value() as f32 / context_window_size as f32) * 100.0
+                    )),
+                    style::SetForegroundColor(Color::Magenta),
+                    style::Print("█ Your prompts: "),
+                    style::SetForegroundColor(Color::Reset),
+                    style::Print(format!(
+                        " ~{} tokens ({:.2}%)\n\n",
+                        user_token_count,
+                        (user_token_count.value() as f32 / context_window_size as f32) * 100.0
+                    )),
+                )?;
+
+                queue!(
+                    self.output,
+                    style::SetAttribute(Attribute::Bold),
+                    style::Print("\n💡 Pro Tips:\n"),
+                    style::SetAttribute(Attribute::Reset),
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print("Run "),
+                    style::SetForegroundColor(Color::DarkGreen),
+                    style::Print("/compact"),
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(" to replace the conversation history with its summary\n"),
+                    style::Print("Run "),
+                    style::SetForegroundColor(Color::DarkGreen),
+                    style::Print("/clear"),
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(" to erase the entire chat history\n"),
+                    style::Print("Run "),
+                    style::SetForegroundColor(Color::DarkGreen),
+                    style::Print("/context show"),
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(" to see tokens per context file\n\n"),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Stats => {
+                let turns = self.timings.turns().collect::<Vec<_>>();
+                if turns.is_empty() {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("\nNo turns yet.\n\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                } else {
+                    queue!(
+                        self.output,
+                        style::SetAttribute(Attribute::Bold),
+                        style::Print("\nLatency of recent turns\n\n"),
+                        style::SetAttribute(Attribute::Reset),
+                    )?;
+                    for (i, turn) in turns.iter().enumerate() {
+                        queue!(
+                            self.output,
+                            style::Print(format!("{:>4}. {}\n", i + 1, turn.summary())),
+                            style::SetForegroundColor(Color::DarkGrey),
+                        )?;
+                        for request in &turn.requests {
+                            queue!(
+                                self.output,
+                                style::Print(format!(
+                                    "      request {}: {} first chunk, {:.2}s total{}\n",
+                                    request.request_id.as_deref().unwrap_or("<unknown>"),
+                                    request
+                                        .time_to_first_chunk
+                                        .map_or("no".to_string(), |d| format!("{:.2}s", d.as_secs_f64())),
+                                    request.stream_duration.as_secs_f64(),
+                                    request
+                                        .usage
+                                        .filter(|usage| usage.input_tokens > 0)
+                                        .map_or(String::new(), |usage| format!(", {}", timing::cache_summary(&usage)))
+                                )),
+                            )?;
+                        }
+                        for tool in &turn.tools {
+                            queue!(
+                                self.output,
+                                style::Print(format!(
+                                    "      tool {}: {:.2}s\n",
+                                    tool.name,
+                                    tool.duration.as_secs_f64()
+                                )),
+                            )?;
+                        }
+                        queue!(self.output, style::SetForegroundColor(Color::Reset))?;
+                    }
+                    execute!(self.output, style::Print("\n"))?;
+                }
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Search { pattern } => {
+                match Regex::new(&pattern) {
+                    Ok(regex) => {
+                        let matches = self.scrollback.search(&regex);
+                        self.print_search_results(&regex, &matches)?;
+                    },
+                    Err(err) => {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Red),
+                            style::Print(format!("\nInvalid pattern: {err}\n\n")),
+                            style::SetForegroundColor(Color::Reset),
+                        )?;
+                    },
+                }
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Scrollback { line } => {
+                if self.tui.is_some() {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("\nScroll the transcript with PageUp and PageDown.\n\n"),
                         style::SetForegroundColor(Color::Reset),
-                        style::Print(format!(
-                            "{:.2}%",
-                            (total_token_used.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
-                        )),
                     )?;
+                } else {
+                    self.output.flush()?;
+                    if let Err(err) = self.scrollback.page(line) {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Red),
+                            style::Print(format!("\nUnable to show the scrollback: {err}\n\n")),
+                            style::SetForegroundColor(Color::Reset),
+                        )?;
+                    }
                 }
 
-                queue!(self.output, style::Print("\n\n"))?;
-                self.output.flush()?;
-
-                queue!(
-                    self.output,
-                    style::SetForegroundColor(Color::DarkCyan),
-                    style::Print("█ Context files: "),
-                    style::SetForegroundColor(Color::Reset),
-                    style::Print(format!(
-                        "~{} tokens ({:.2}%)\n",
-                        context_token_count,
-                        (context_token_count.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
-                    )),
-                    style::SetForegroundColor(Color::Blue),
-                    style::Print("█ Q responses: "),
-                    style::SetForegroundColor(Color::Reset),
-                    style::Print(format!(
-                        "  ~{} tokens ({:.2}%)\n",
-                        assistant_token_count,
-                        (assistant_token_count.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
-                    )),
-                    style::SetForegroundColor(Color::Magenta),
-                    style::Print("█ Your prompts: "),
-                    style::SetForegroundColor(Color::Reset),
-                    style::Print(format!(
-                        " ~{} tokens ({:.2}%)\n\n",
-                        user_token_count,
-                        (user_token_count.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
-                    )),
-                )?;
-
-                queue!(
-                    self.output,
-                    style::SetAttribute(Attribute::Bold),
-                    style::Print("\n💡 Pro Tips:\n"),
-                    style::SetAttribute(Attribute::Reset),
-                    style::SetForegroundColor(Color::DarkGrey),
-                    style::Print("Run "),
-                    style::SetForegroundColor(Color::DarkGreen),
-                    style::Print("/compact"),
-                    style::SetForegroundColor(Color::DarkGrey),
-                    style::Print(" to replace the conversation history with its summary\n"),
-                    style::Print("Run "),
-                    style::SetForegroundColor(Color::DarkGreen),
-                    style::Print("/clear"),
-                    style::SetForegroundColor(Color::DarkGrey),
-                    style::Print(" to erase the entire chat history\n"),
-                    style::Print("Run "),
-                    style::SetForegroundColor(Color::DarkGreen),
-                    style::Print("/context show"),
-                    style::SetForegroundColor(Color::DarkGrey),
-                    style::Print(" to see tokens per context file\n\n"),
-                    style::SetForegroundColor(Color::Reset),
-                )?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Translate { description } => {
+                if self.tui.is_some() {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("\n/translate isn't available in --tui, run q translate instead.\n\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                } else {
+                    self.output.flush()?;
+                    if let Err(err) = crate::cli::translate::run(&self.client, &description).await {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Red),
+                            style::Print(format!("\nUnable to translate: {err}\n")),
+                            style::SetForegroundColor(Color::Reset),
+                        )?;
+                    }
+                    execute!(self.output, style::Print("\n"))?;
+                }
 
                 ChatState::PromptUser {
                     tool_uses: Some(tool_uses),
@@ -2898,6 +4656,10 @@ impl ChatContext {
                 new_state
                     .reload_serialized_state(Arc::clone(&self.ctx), Some(self.output.clone()))
                     .await;
+                if self.interactive {
+                    self.conversation_state.finish_journal();
+                    new_state.start_journal(&self.ctx, self.ctx.env().current_dir()?);
+                }
                 self.conversation_state = new_state;
 
                 execute!(
@@ -2922,7 +4684,13 @@ impl ChatContext {
                                 execute!(
                                     self.output,
                                     style::SetForegroundColor(Color::Red),
-                                    style::Print(format!("\nFailed to export to {}: {}\n\n", &path, &err)),
+                                    style::Print(format!(
+                                        "\n{}\n\n",
+                                        i18n::message_with("error-export", &[
+                                            ("path", &path),
+                                            ("error", &err.to_string())
+                                        ])
+                                    )),
                                     style::SetAttribute(Attribute::Reset)
                                 )?;
                                 return Ok(ChatState::PromptUser {
@@ -2941,8 +4709,8 @@ impl ChatContext {
                         self.output,
                         style::SetForegroundColor(Color::Red),
                         style::Print(format!(
-                            "\nFile at {} already exists. To overwrite, use -f or --force\n\n",
-                            &path
+                            "\n{}\n\n",
+                            i18n::message_with("error-file-exists", &[("path", &path)])
                         )),
                         style::SetAttribute(Attribute::Reset)
                     )?;
@@ -2967,6 +4735,126 @@ impl ChatContext {
                     skip_printing_tools: true,
                 }
             },
+            Command::Export { path, force } => {
+                if self.ctx.fs().exists(&path) && !force {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!(
+                            "\n{}\n\n",
+                            i18n::message_with("error-file-exists", &[("path", &path)])
+                        )),
+                        style::SetAttribute(Attribute::Reset)
+                    )?;
+                    return Ok(ChatState::PromptUser {
+                        tool_uses: Some(tool_uses),
+                        pending_tool_index,
+                        skip_printing_tools: true,
+                    });
+                }
+
+                let transcript = self
+                    .conversation_state
+                    .transcript
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                let redacted = redact::redact(&transcript);
+                let changed = redact::changed_lines(&transcript, &redacted);
+                if let Err(err) = self.ctx.fs().write(&path, format!("{redacted}\n")).await {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!(
+                            "\n{}\n\n",
+                            i18n::message_with("error-export", &[("path", &path), ("error", &err.to_string())])
+                        )),
+                        style::SetAttribute(Attribute::Reset)
+                    )?;
+                    return Ok(ChatState::PromptUser {
+                        tool_uses: Some(tool_uses),
+                        pending_tool_index,
+                        skip_printing_tools: true,
+                    });
+                }
+
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("\n✔ Exported the transcript to {}", &path)),
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(format!(", {changed} lines redacted\n\n")),
+                    style::SetAttribute(Attribute::Reset)
+                )?;
+
+                ChatState::PromptUser {
+                    tool_uses: None,
+                    pending_tool_index: None,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Share { encrypt } => {
+                let passphrase = match encrypt {
+                    true => {
+                        let passphrase = dialoguer::Password::with_theme(&crate::util::dialoguer_theme())
+                            .with_prompt("Passphrase")
+                            .with_confirmation("Repeat the passphrase", "The passphrases don't match")
+                            .interact();
+                        match passphrase {
+                            Ok(passphrase) => Some(passphrase),
+                            Err(_) => {
+                                return Ok(ChatState::PromptUser {
+                                    tool_uses: None,
+                                    pending_tool_index: None,
+                                    skip_printing_tools: true,
+                                });
+                            },
+                        }
+                    },
+                    false => None,
+                };
+
+                let transcript = self
+                    .conversation_state
+                    .transcript
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                let redacted = redact::redact(&transcript);
+                let changed = redact::changed_lines(&transcript, &redacted);
+                let upload = self
+                    .share_target
+                    .upload(&format!("{redacted}\n"), passphrase.as_deref())
+                    .await;
+                match upload {
+                    Ok(link) => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format!("\n✔ Shared the transcript at {link}")),
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(format!(", {changed} lines redacted")),
+                        style::Print(match passphrase {
+                            Some(_) => ", decrypt it with age -d\n\n",
+                            None => "\n\n",
+                        }),
+                        style::SetAttribute(Attribute::Reset)
+                    )?,
+                    Err(err) => execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("\nFailed to share the transcript: {err}\n\n")),
+                        style::SetAttribute(Attribute::Reset)
+                    )?,
+                }
+
+                ChatState::PromptUser {
+                    tool_uses: None,
+                    pending_tool_index: None,
+                    skip_printing_tools: true,
+                }
+            },
             Command::Mcp => {
                 let terminal_width = self.terminal_width();
                 let loaded_servers = self.conversation_state.tool_manager.mcp_load_record.lock().await;
@@ -3030,16 +4918,25 @@ impl ChatContext {
                 continue;
             }
 
-            // If there is an override, we will use it. Otherwise fall back to Tool's default.
-            let allowed = self.tool_permissions.trust_all
-                || (self.tool_permissions.has(&tool.name) && self.tool_permissions.is_trusted(&tool.name))
-                || !tool.tool.requires_acceptance(&self.ctx);
+            // If there is an override, we will use it. Otherwise fall back to Tool's default. The
+            // tool policy comes first, even with --trust-all-tools. Dry runs don't change anything,
+            // so they need no confirmation. Paths outside the roots of --workspace always do.
+            let outside_workspace = tool.tool.outside_workspace(&self.ctx).await;
+            let allowed = (self.dry_run && tool.tool.mutates())
+                || (!tool.tool.always_requires_acceptance()
+                    && !outside_workspace
+                    && match tool.policy {
+                        Some(PolicyDecision::Allow) => true,
+                        Some(PolicyDecision::Prompt | PolicyDecision::Deny) => false,
+                        None => {
+                            self.tool_permissions.trust_all
+                                || (self.tool_permissions.has(&tool.name)
+                                    && self.tool_permissions.is_trusted(&tool.name))
+                                || !tool.tool.requires_acceptance(&self.ctx)
+                        },
+                    });
 
-            if database
-                .settings
-                .get_bool(Setting::ChatEnableNotifications)
-                .unwrap_or(false)
-            {
+            if database.settings.get_bool_or_default(Setting::ChatEnableNotifications) {
                 play_notification_bell(!allowed);
             }
 
@@ -3053,6 +4950,7 @@ impl ChatContext {
             let pending_tool_index = Some(index);
             if !self.interactive {
                 // Cannot request in non-interactive, so fail.
+                self.audit_tool_use(&tool.id, &tool.name, Decision::Rejected, None);
                 return Err(ChatError::NonInteractiveToolApproval);
             }
 
@@ -3067,12 +4965,38 @@ impl ChatContext {
         let mut tool_results = vec![];
         let mut image_blocks: Vec<RichImageBlock> = Vec::new();
 
-        for tool in tool_uses {
+        for mut tool in tool_uses {
+            if let Tool::GhIssue(gh_issue) = &mut tool.tool {
+                if self.interactive && !self.dry_run {
+                    self.review_issue(gh_issue).await?;
+                }
+            }
             let mut tool_telemetry = self.tool_use_telemetry_events.entry(tool.id.clone());
             tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_accepted = true);
 
             let tool_start = std::time::Instant::now();
-            let invoke_result = tool.tool.invoke(&self.ctx, &mut self.output).await;
+            accessibility::marker(&mut self.output, &format!("Tool {} started", tool.name))?;
+            // The TUI streams the output of the tool to its own pane.
+            let mut tool_output = match &self.tui {
+                Some(tui) => {
+                    tui.send(TuiEvent::ToolStarted(tool.name.clone()));
+                    tui.tool_output_writer()
+                },
+                None => self.output.clone(),
+            };
+            let invoke_result = match self.dry_run {
+                true => tool.tool.dry_run(&self.ctx, &mut tool_output, &self.tool_limits).await,
+                false => tool.tool.invoke(&self.ctx, &mut tool_output, &self.tool_limits).await,
+            };
+            if let Some(tui) = &self.tui {
+                tui.send(TuiEvent::ToolFinished {
+                    success: invoke_result.is_ok(),
+                    duration: tool_start.elapsed(),
+                });
+            }
+            self.file_watcher
+                .track(&self.ctx, tool.tool.file_paths(&self.ctx))
+                .await;
 
             if self.interactive && self.spinner.is_some() {
                 queue!(
@@ -3085,6 +5009,9 @@ impl ChatContext {
             execute!(self.output, style::Print("\n"))?;
 
             let tool_time = std::time::Instant::now().duration_since(tool_start);
+            self.timings.tool_executed(tool.name.clone(), tool_time);
+            tool_telemetry =
+                tool_telemetry.and_modify(|ev| ev.execution_duration_ms = Some(tool_time.as_millis() as u64));
             if let Tool::Custom(ct) = &tool.tool {
                 tool_telemetry = tool_telemetry.and_modify(|ev| {
                     ev.custom_tool_call_latency = Some(tool_time.as_secs() as usize);
@@ -3118,6 +5045,7 @@ impl ChatContext {
                         style::SetForegroundColor(Color::Reset),
                         style::Print("\n"),
                     )?;
+                    accessibility::marker(&mut self.output, &format!("Tool {} finished", tool.name))?;
 
                     tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_success = Some(true));
                     if let Tool::Custom(_) = &tool.tool {
@@ -3125,7 +5053,7 @@ impl ChatContext {
                             .and_modify(|ev| ev.output_token_size = Some(TokenCounter::count_tokens(result.as_str())));
                     }
                     tool_results.push(ToolUseResult {
-                        tool_use_id: tool.id,
+                        tool_use_id: tool.id.clone(),
                         content: vec![result.into()],
                         status: ToolResultStatus::Success,
                     });
@@ -3145,10 +5073,11 @@ impl ChatContext {
                         style::SetAttribute(Attribute::Reset),
                         style::Print("\n\n"),
                     )?;
+                    accessibility::marker(&mut self.output, &format!("Tool {} failed", tool.name))?;
 
                     tool_telemetry.and_modify(|ev| ev.is_success = Some(false));
                     tool_results.push(ToolUseResult {
-                        tool_use_id: tool.id,
+                        tool_use_id: tool.id.clone(),
                         content: vec![ToolUseResultBlock::Text(format!(
                             "An error occurred processing the tool: \n{}",
                             &err
@@ -3164,8 +5093,24 @@ impl ChatContext {
                     }
                 },
             }
+
+            let decision = if self.approved_tool_uses.remove(&tool.id) {
+                Decision::Approved
+            } else {
+                Decision::Trusted
+            };
+            self.audit_tool_use(&tool.id, &tool.name, decision, tool_results.last());
         }
 
+        if !image_blocks.is_empty() && !self.model_capabilities.supports_images {
+            image_blocks.clear();
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::DarkYellow),
+                style::Print("\nImages were not attached because the current model does not support image input.\n"),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+        }
         if !image_blocks.is_empty() {
             let images = image_blocks.into_iter().map(|(block, _)| block).collect();
             self.conversation_state
@@ -3182,15 +5127,12 @@ impl ChatContext {
         if self.interactive {
             execute!(self.output, cursor::Hide)?;
             execute!(self.output, style::Print("\n"), style::SetAttribute(Attribute::Reset))?;
-            self.spinner = Some(Spinner::new(Spinners::Dots, "Thinking...".to_string()));
+            self.start_spinner("Thinking...");
         }
 
         self.send_tool_use_telemetry(telemetry).await;
-        return Ok(ChatState::HandleResponseStream(
-            self.client
-                .send_message(self.conversation_state.as_sendable_conversation_state(false).await)
-                .await?,
-        ));
+        let conv_state = self.conversation_state.as_sendable_conversation_state(false).await;
+        return Ok(ChatState::HandleResponseStream(self.send_message(conv_state).await?));
     }
 
     async fn handle_response(
@@ -3203,11 +5145,18 @@ impl ChatContext {
         let mut buf = String::new();
         let mut offset = 0;
         let mut ended = false;
-        let mut parser = ResponseParser::new(response);
+        let mut parser = ResponseParser::new(response)
+            .block_code_references(database.settings.get_bool_or_default(Setting::ChatBlockCodeReferences));
         let mut state = ParseState::new(Some(self.terminal_width()));
 
         let mut tool_uses = Vec::new();
         let mut tool_name_being_recvd: Option<String> = None;
+        // Shown once the text they refer to is printed.
+        let mut code_references: Vec<(Vec<CodeReference>, bool)> = Vec::new();
+        // Recorded in the log of `q references` once the response ended.
+        let mut shown_references: Vec<(CodeReference, bool)> = Vec::new();
+        let mut web_links: Vec<WebLink> = Vec::new();
+        self.followup_prompts.clear();
 
         if self.interactive && self.spinner.is_some() {
             drop(self.spinner.take());
@@ -3226,6 +5175,7 @@ impl ChatContext {
             match parser.recv().await {
                 Ok(msg_event) => {
                     trace!("Consumed: {:?}", msg_event);
+                    self.timings.chunk_received();
                     match msg_event {
                         parser::ResponseEvent::ToolUseStart { name } => {
                             // We need to flush the buffer here, otherwise text will not be
@@ -3249,6 +5199,23 @@ impl ChatContext {
                             tool_uses.push(tool_use);
                             tool_name_being_recvd = None;
                         },
+                        parser::ResponseEvent::CodeReference { references, withheld } => {
+                            code_references.push((references, withheld));
+                        },
+                        parser::ResponseEvent::WebLinks(links) => {
+                            for link in links {
+                                if !web_links.iter().any(|l| l.url == link.url) {
+                                    web_links.push(link);
+                                }
+                            }
+                        },
+                        parser::ResponseEvent::FollowupPrompt(prompt) => {
+                            if self.followup_prompts.len() < MAX_FOLLOWUP_PROMPTS
+                                && !self.followup_prompts.contains(&prompt)
+                            {
+                                self.followup_prompts.push(prompt);
+                            }
+                        },
                         parser::ResponseEvent::EndStream { message } => {
                             // This log is attempting to help debug instances where users encounter
                             // the response timeout message.
@@ -3275,9 +5242,9 @@ impl ChatContext {
                             );
                             if self.interactive {
                                 execute!(self.output, cursor::Hide)?;
-                                self.spinner =
-                                    Some(Spinner::new(Spinners::Dots, "Dividing up the work...".to_string()));
+                                self.start_spinner("Dividing up the work...");
                             }
+                            self.timings.response_ended(recv_error.request_id.clone());
                             // For stream timeouts, we'll tell the model to try and split its response into
                             // smaller chunks.
                             self.conversation_state.push_assistant_message(
@@ -3291,11 +5258,8 @@ impl ChatContext {
                                 )
                                 .await;
                             self.send_tool_use_telemetry(telemetry).await;
-                            return Ok(ChatState::HandleResponseStream(
-                                self.client
-                                    .send_message(self.conversation_state.as_sendable_conversation_state(false).await)
-                                    .await?,
-                            ));
+                            let conv_state = self.conversation_state.as_sendable_conversation_state(false).await;
+                            return Ok(ChatState::HandleResponseStream(self.send_message(conv_state).await?));
                         },
                         RecvErrorKind::UnexpectedToolUseEos {
                             tool_use_id,
@@ -3320,19 +5284,17 @@ impl ChatContext {
                                         time_elapsed.as_secs_f64()
                                     )),
                                 )?;
-                                if let Some(request_id) = recv_error.request_id {
+                                if let Some(request_id) = &recv_error.request_id {
                                     queue!(
                                         self.output,
                                         style::Print(format!("\n         request_id: {}", request_id))
                                     )?;
                                 }
                                 execute!(self.output, style::Print("\n\n"), style::SetAttribute(Attribute::Reset))?;
-                                self.spinner = Some(Spinner::new(
-                                    Spinners::Dots,
-                                    "Trying to divide up the work...".to_string(),
-                                ));
+                                self.start_spinner("Trying to divide up the work...");
                             }
 
+                            self.timings.response_ended(recv_error.request_id.clone());
                             self.conversation_state.push_assistant_message(*message, database);
                             let tool_results = vec![ToolUseResult {
                                     tool_use_id,
@@ -3343,11 +5305,8 @@ impl ChatContext {
                                 }];
                             self.conversation_state.add_tool_results(tool_results);
                             self.send_tool_use_telemetry(telemetry).await;
-                            return Ok(ChatState::HandleResponseStream(
-                                self.client
-                                    .send_message(self.conversation_state.as_sendable_conversation_state(false).await)
-                                    .await?,
-                            ));
+                            let conv_state = self.conversation_state.as_sendable_conversation_state(false).await;
+                            return Ok(ChatState::HandleResponseStream(self.send_message(conv_state).await?));
                         },
                         _ => return Err(recv_error.into()),
                     }
@@ -3359,8 +5318,13 @@ impl ChatContext {
             // still left in the buffer. I'm not sure how this is intended to be handled.
             if ended {
                 buf.push('\n');
+                state.ended = true;
             }
 
+            // The terminal may have been resized since the last chunk, so what is left of the
+            // response, and a table that is still being received, fits its new width.
+            state.terminal_width = Some(self.terminal_width());
+
             if tool_name_being_recvd.is_none() && !buf.is_empty() && self.interactive && self.spinner.is_some() {
                 drop(self.spinner.take());
                 queue!(
@@ -3377,7 +5341,6 @@ impl ChatContext {
                 match interpret_markdown(input, &mut self.output, &mut state) {
                     Ok(parsed) => {
                         offset += parsed.offset_from(&input);
-                        self.output.flush()?;
                         state.newline = state.set_newline;
                         state.set_newline = false;
                     },
@@ -3387,34 +5350,56 @@ impl ChatContext {
                     },
                 }
 
-                // TODO: We should buffer output based on how much we have to parse, not as a constant
-                // Do not remove unless you are nabochay :)
-                std::thread::sleep(Duration::from_millis(8));
+                // The output is written out once per frame, unless each element is revealed in
+                // turn.
+                if self.typewriter {
+                    self.output.flush()?;
+                    std::thread::sleep(TYPEWRITER_DELAY);
+                }
+            }
+            self.output.flush()?;
+
+            if !code_references.is_empty() && (ended || offset == buf.len()) {
+                for (references, withheld) in code_references.drain(..) {
+                    self.print_code_references(&references, withheld)?;
+                    shown_references.extend(references.into_iter().map(|reference| (reference, withheld)));
+                }
             }
 
             // Set spinner after showing all of the assistant text content so far.
             if let (Some(_name), true) = (&tool_name_being_recvd, self.interactive) {
                 queue!(self.output, cursor::Hide)?;
-                self.spinner = Some(Spinner::new(Spinners::Dots, "Thinking...".to_string()));
+                self.start_spinner("Thinking...");
             }
 
             if ended {
+                if !shown_references.is_empty() {
+                    self.record_code_references(&shown_references, &buf, &tool_uses);
+                }
+                if let Some(usage) = parser.usage() {
+                    self.timings.usage_received(usage);
+                    if let Some(usage_ledger) = &self.usage_ledger {
+                        if let Err(err) = usage_ledger.record(self.conversation_state.conversation_id(), &usage) {
+                            warn!(?err, "Failed to record the usage of the response");
+                        }
+                    }
+                }
+                let timing = self.timings.response_ended(request_id.clone());
                 if let Some(message_id) = self.conversation_state.message_id() {
                     telemetry
                         .send_chat_added_message(
                             self.conversation_state.conversation_id().to_owned(),
                             message_id.to_owned(),
                             self.conversation_state.context_message_length(),
+                            request_id.clone(),
+                            timing.as_ref().and_then(|t| t.time_to_first_chunk),
+                            timing.as_ref().map(|t| t.stream_duration),
+                            timing.and_then(|t| t.usage),
                         )
                         .ok();
                 }
 
-                if self.interactive
-                    && database
-                        .settings
-                        .get_bool(Setting::ChatEnableNotifications)
-                        .unwrap_or(false)
-                {
+                if self.interactive && database.settings.get_bool_or_default(Setting::ChatEnableNotifications) {
                     // For final responses (no tools suggested), always play the bell
                     play_notification_bell(tool_uses.is_empty());
                 }
@@ -3436,6 +5421,59 @@ impl ChatContext {
                     }
                 }
 
+                if !web_links.is_empty() {
+                    queue!(
+                        self.output,
+                        style::SetAttribute(Attribute::Bold),
+                        style::Print("\nSources\n"),
+                        style::SetAttribute(Attribute::Reset),
+                    )?;
+                    for (i, link) in web_links.iter().enumerate() {
+                        queue!(
+                            self.output,
+                            style::Print(format!("{}. {} ", i + 1, link.title)),
+                            style::SetForegroundColor(Color::Blue),
+                            style::Print(format!("{}\n", link.url)),
+                            style::SetForegroundColor(Color::Reset),
+                        )?;
+                    }
+                    self.output.flush()?;
+                }
+
+                // Numbers only stand for the prompts the user saw
+                if !self.interactive || !tool_uses.is_empty() {
+                    self.followup_prompts.clear();
+                }
+                if !self.followup_prompts.is_empty() {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("\nType a number to ask a follow-up:\n"),
+                    )?;
+                    for (i, prompt) in self.followup_prompts.iter().enumerate() {
+                        queue!(
+                            self.output,
+                            style::SetForegroundColor(Color::Green),
+                            style::Print(format!("  {}. ", i + 1)),
+                            style::SetForegroundColor(Color::Reset),
+                            style::Print(format!("{prompt}\n")),
+                        )?;
+                    }
+                    self.output.flush()?;
+                }
+
+                if tool_uses.is_empty() {
+                    let show_timings = self.show_timings;
+                    if let (Some(turn), true) = (self.timings.finish_turn(), show_timings) {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::DarkGrey),
+                            style::Print(format!("\n⏱ {}\n", turn.summary())),
+                            style::SetForegroundColor(Color::Reset),
+                        )?;
+                    }
+                }
+
                 break;
             }
         }
@@ -3460,6 +5498,8 @@ impl ChatContext {
         debug!(?tool_uses, "Validating tool uses");
         let mut queued_tools: Vec<QueuedTool> = Vec::new();
         let mut tool_results: Vec<ToolUseResult> = Vec::new();
+        // The tool called with the same arguments too many times in a row, and how many.
+        let mut looping = None;
 
         for tool_use in tool_uses {
             let tool_use_id = tool_use.id.clone();
@@ -3468,7 +5508,19 @@ impl ChatContext {
                 .set_tool_use_id(tool_use_id.clone())
                 .set_tool_name(tool_use.name.clone())
                 .utterance_id(self.conversation_state.message_id().map(|s| s.to_string()));
+            let args = tool_use.args.clone();
             match self.conversation_state.tool_manager.get_tool_from_tool_use(tool_use) {
+                // Like in a read-only session, nothing is changed before the plan is approved
+                Ok(tool) if tool.mutates() && self.plan.as_ref().is_some_and(|plan| !plan.allows_changes()) => {
+                    tool_telemetry.is_valid = Some(false);
+                    tool_results.push(ToolUseResult {
+                        tool_use_id: tool_use_id.clone(),
+                        content: vec![ToolUseResultBlock::Text(format!(
+                            "The plan isn't approved yet, so {tool_use_name} can't be used to change anything."
+                        ))],
+                        status: ToolResultStatus::Error,
+                    });
+                },
                 Ok(mut tool) => {
                     // Apply non-Q-generated context to tools
                     self.contextualize_tool(&mut tool);
@@ -3476,12 +5528,29 @@ impl ChatContext {
                     match tool.validate(&self.ctx).await {
                         Ok(()) => {
                             tool_telemetry.is_valid = Some(true);
-                            queued_tools.push(QueuedTool {
-                                id: tool_use_id.clone(),
-                                name: tool_use_name,
-                                tool,
-                                accepted: false,
-                            });
+                            let rule = self.tool_policy.evaluate(&self.ctx, &tool_use_name, &args, &tool);
+                            match rule.filter(|rule| rule.decision == PolicyDecision::Deny) {
+                                Some(rule) => {
+                                    tool_results.push(ToolUseResult {
+                                        tool_use_id: tool_use_id.clone(),
+                                        content: vec![ToolUseResultBlock::Text(rule.denial(&tool_use_name))],
+                                        status: ToolResultStatus::Error,
+                                    });
+                                    self.audit_tool_use(&tool_use_id, &tool_use_name, Decision::Rejected, None);
+                                },
+                                None => {
+                                    if let Some(repeats) = self.loop_detector.observe(&tool_use_name, &args) {
+                                        looping = Some((tool_use_name.clone(), repeats));
+                                    }
+                                    queued_tools.push(QueuedTool {
+                                        id: tool_use_id.clone(),
+                                        name: tool_use_name,
+                                        tool,
+                                        accepted: false,
+                                        policy: rule.map(|rule| rule.decision),
+                                    });
+                                },
+                            }
                         },
                         Err(err) => {
                             tool_telemetry.is_valid = Some(false);
@@ -3542,22 +5611,139 @@ impl ChatContext {
                 );
             }
 
-            let response = self
-                .client
-                .send_message(self.conversation_state.as_sendable_conversation_state(false).await)
-                .await?;
-            return Ok(ChatState::HandleResponseStream(response));
+            let conv_state = self.conversation_state.as_sendable_conversation_state(false).await;
+            let response = self.send_message(conv_state).await?;
+            return Ok(ChatState::HandleResponseStream(response));
+        }
+
+        // Rather than running the same call again, tell the model to stop unless the user lets it go
+        // on.
+        if let Some((name, repeats)) = looping {
+            if self.interactive && self.confirm_loop(&name, repeats)? {
+                self.loop_detector.reset();
+            } else {
+                // Without a user to ask, the model is stopped once it ignores the intervention.
+                if self.loop_detector.intervene() && !self.interactive {
+                    return Err(ChatError::Custom(
+                        format!(
+                            "Stopped after the model called {name} with the same arguments {repeats} times in a row"
+                        )
+                        .into(),
+                    ));
+                }
+                warn!(?name, repeats, "Stopping a tool call loop");
+                let intervention = tool_loop::intervention(&name, repeats);
+                let tool_results = queued_tools
+                    .iter()
+                    .map(|tool| ToolUseResult {
+                        tool_use_id: tool.id.clone(),
+                        content: vec![ToolUseResultBlock::Text(intervention.clone())],
+                        status: ToolResultStatus::Error,
+                    })
+                    .collect();
+                self.conversation_state.add_tool_results(tool_results);
+                self.send_tool_use_telemetry(telemetry).await;
+
+                let conv_state = self.conversation_state.as_sendable_conversation_state(false).await;
+                let response = self.send_message(conv_state).await?;
+                return Ok(ChatState::HandleResponseStream(response));
+            }
+        }
+
+        if let Err(exhausted) = self.turn_budget.spend(queued_tools.len()) {
+            if !self.interactive {
+                return Err(ChatError::Custom(format!("Stopped after {exhausted}").into()));
+            }
+            if !self.confirm_budget(exhausted)? {
+                return Err(ChatError::Interrupted {
+                    tool_uses: Some(queued_tools),
+                });
+            }
+            // The tools of this response count against the new budget.
+            self.turn_budget.reset();
+            self.turn_budget.spend(queued_tools.len()).ok();
         }
 
         Ok(ChatState::ExecuteTools(queued_tools))
     }
 
+    /// Asks whether the model may go on using tools once the [TurnBudget] is exhausted.
+    fn confirm_budget(&mut self, exhausted: BudgetExhausted) -> Result<bool, ChatError> {
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::Yellow),
+            style::Print(format!("\nBudget exhausted: {exhausted}. ")),
+        )?;
+        self.confirm("Continue?")
+    }
+
+    /// Asks whether the model may make the same tool call again.
+    fn confirm_loop(&mut self, tool: &str, repeats: usize) -> Result<bool, ChatError> {
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::Yellow),
+            style::Print(format!(
+                "\nThe model called {tool} with the same arguments {repeats} times in a row. "
+            )),
+        )?;
+        self.confirm("Run it again?")
+    }
+
+    /// Asks a yes or no question, returning whether the answer is yes.
+    fn confirm(&mut self, question: &str) -> Result<bool, ChatError> {
+        Ok(self.ask(question, &["y", "n"])?.eq_ignore_ascii_case("y"))
+    }
+
+    /// Asks a question answered with one of `choices`, returning the trimmed answer in lower case.
+    fn ask(&mut self, question: &str, choices: &[&str]) -> Result<String, ChatError> {
+        queue!(
+            self.output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("{question} [")),
+        )?;
+        for (i, choice) in choices.iter().enumerate() {
+            if i > 0 {
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print("/")
+                )?;
+            }
+            queue!(
+                self.output,
+                style::SetForegroundColor(Color::Green),
+                style::Print(choice)
+            )?;
+        }
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("]:\n\n"),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        let answer = self
+            .read_user_input("> ".yellow().to_string().as_str(), true)
+            .unwrap_or_default();
+        Ok(answer.trim().to_lowercase())
+    }
+
+    /// Asks the user which excerpts of the conversation to keep in an issue report, on the prompt
+    /// of the chat so that it works with `--tui` too.
+    async fn review_issue(&mut self, gh_issue: &mut GhIssue) -> Result<(), ChatError> {
+        let output = &mut self.output;
+        let input_source = &mut self.input_source;
+        let prompt = "> ".yellow().to_string();
+        gh_issue
+            .review(|text| redact::review(text, output, || input_source.read_line(Some(&prompt)).ok().flatten()))
+            .await
+            .map_err(|err| ChatError::Custom(err.to_string().into()))
+    }
+
     /// Apply program context to tools that Q may not have.
     // We cannot attach this any other way because Tools are constructed by deserializing
     // output from Amazon Q.
     // TODO: Is there a better way?
     fn contextualize_tool(&self, tool: &mut Tool) {
-        #[allow(clippy::single_match)]
         match tool {
             Tool::GhIssue(gh_issue) => {
                 gh_issue.set_context(GhIssueContext {
@@ -3571,11 +5757,150 @@ impl ChatContext {
                     interactive: self.interactive,
                 });
             },
-            _ => (),
+            _ => self.tool_context.contextualize(tool),
+        };
+    }
+
+    /// Appends the use of a tool to the audit log, if it is enabled, with its result if it ran.
+    fn audit_tool_use(
+        &mut self,
+        tool_use_id: &str,
+        tool_name: &str,
+        decision: Decision,
+        result: Option<&ToolUseResult>,
+    ) {
+        let Some(audit_log) = &self.audit_log else {
+            return;
+        };
+        let args = self
+            .conversation_state
+            .history()
+            .back()
+            .and_then(|(_, assistant)| assistant.tool_uses())
+            .and_then(|tool_uses| tool_uses.iter().find(|tool_use| tool_use.id == tool_use_id))
+            .map(|tool_use| tool_use.args.clone())
+            .unwrap_or_default();
+        let entry = audit_entry(
+            self.conversation_state.conversation_id(),
+            tool_use_id,
+            tool_name,
+            args,
+            decision,
+            result,
+        );
+        if let Err(err) = audit_log.append(entry) {
+            error!(?err, "Failed to write the audit log");
+            let _ = execute!(
+                self.output,
+                style::SetForegroundColor(Color::Yellow),
+                style::Print(format!("\nFailed to record {tool_name} in the audit log: {err}\n")),
+                style::SetForegroundColor(Color::Reset),
+            );
+        }
+    }
+
+    /// Lets the user replace a resource name of the pending use_aws call with one of the resources
+    /// of the account, listed with a read-only call, and asks for the approval again.
+    async fn fix_aws_resource(&mut self, mut tool_uses: Vec<QueuedTool>, index: usize) -> Result<ChatState, ChatError> {
+        let Tool::UseAws(use_aws) = &mut tool_uses[index].tool else {
+            return Ok(ChatState::PromptUser {
+                tool_uses: Some(tool_uses),
+                pending_tool_index: Some(index),
+                skip_printing_tools: true,
+            });
+        };
+        let parameters = use_aws.resource_parameters();
+        if parameters.is_empty() {
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print("\nNone of the parameters of this call name resources that can be listed.\n\n"),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+        }
+        let chosen = match parameters.len() {
+            0 => None,
+            1 => Some(0),
+            _ => {
+                let labels = parameters
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {value}"))
+                    .collect::<Vec<_>>();
+                crate::util::choose("Which parameter?", &labels).ok().flatten()
+            },
+        };
+        let chosen = chosen
+            .and_then(|chosen| parameters.get(chosen))
+            .and_then(|(name, guess)| Some((name, guess, use_aws.resource_lookup(name)?)));
+        let fixed = match chosen {
+            Some((name, guess, lookup)) => self.pick_aws_resource(use_aws, name, guess, lookup).await?,
+            None => false,
+        };
+
+        if fixed {
+            queue!(self.output, style::Print("\n"))?;
+            self.print_tool_descriptions(&tool_uses[index], false).await?;
+        }
+        Ok(ChatState::PromptUser {
+            tool_uses: Some(tool_uses),
+            pending_tool_index: Some(index),
+            skip_printing_tools: !fixed,
+        })
+    }
+
+    /// Lists the resources `parameter` can name, closest to the `guess` of the model first, and
+    /// replaces the value with the one the user picks. Returns whether it was replaced.
+    async fn pick_aws_resource(
+        &mut self,
+        use_aws: &mut UseAws,
+        parameter: &str,
+        guess: &str,
+        lookup: &ResourceLookup,
+    ) -> Result<bool, ChatError> {
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("\nListing the resources with {}...\n", lookup.description())),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        let names = match lookup.list(&use_aws.region, use_aws.profile_name.as_deref()).await {
+            Ok(names) if names.is_empty() => {
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Yellow),
+                    style::Print(format!("No resources found for {parameter}.\n\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+                return Ok(false);
+            },
+            Ok(names) => aws_resources::rank(guess, names),
+            Err(err) => {
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Red),
+                    style::Print(format!("Failed to list the resources: {err}\n\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+                return Ok(false);
+            },
         };
+
+        let picked = dialoguer::FuzzySelect::with_theme(&crate::util::dialoguer_theme())
+            .with_prompt(format!("{parameter} (the model used {guess})"))
+            .items(&names)
+            .default(0)
+            .interact_opt();
+        match picked {
+            Ok(Some(picked)) => {
+                use_aws.fix_parameter(parameter, names[picked].clone());
+                Ok(true)
+            },
+            _ => Ok(false),
+        }
     }
 
     async fn print_tool_descriptions(&mut self, tool_use: &QueuedTool, trusted: bool) -> Result<(), ChatError> {
+        accessibility::marker(&mut self.output, &format!("Tool {} requested", tool_use.name))?;
         queue!(
             self.output,
             style::SetForegroundColor(Color::Magenta),
@@ -3596,6 +5921,15 @@ impl ChatContext {
                 style::SetForegroundColor(Color::Reset),
             )?;
         }
+        if let Some(target) = tool_use.tool.remote_target(&self.ctx) {
+            queue!(
+                self.output,
+                style::Print(" on "),
+                style::SetForegroundColor(Color::Magenta),
+                style::Print(target),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+        }
         queue!(self.output, style::Print("\n"), style::Print(CONTINUATION_LINE))?;
         queue!(self.output, style::Print("\n"))?;
         queue!(self.output, style::Print(TOOL_BULLET))?;
@@ -3613,6 +5947,7 @@ impl ChatContext {
 
     /// Helper function to read user input with a prompt and Ctrl+C handling
     fn read_user_input(&mut self, prompt: &str, exit_on_single_ctrl_c: bool) -> Option<String> {
+        let _ = self.output.flush();
         let mut ctrl_c = false;
         loop {
             match (self.input_source.read_line(Some(prompt)), ctrl_c) {
@@ -3620,6 +5955,10 @@ impl ChatContext {
                     if line.trim().is_empty() {
                         continue; // Reprompt if the input is empty
                     }
+                    // Prompts are searchable with the answers, but the commands aren't.
+                    if !line.trim_start().starts_with('/') {
+                        self.scrollback.push(format!("{prompt}{line}\n").as_bytes());
+                    }
                     return Some(line);
                 },
                 (Ok(None), false) => {
@@ -3642,6 +5981,116 @@ impl ChatContext {
         }
     }
 
+    /// Prints the attribution of code in the response, or that it was withheld because of
+    /// `chat.blockCodeReferences`.
+    fn print_code_references(&mut self, references: &[CodeReference], withheld: bool) -> Result<(), ChatError> {
+        for reference in references {
+            let source = describe_code_reference(reference);
+            let line = match withheld {
+                true => format!("Withheld code matching {source}, allow it with chat.blockCodeReferences\n"),
+                false => format!("Reference: the code above matches {source}\n"),
+            };
+            queue!(
+                self.output,
+                style::SetForegroundColor(if withheld { Color::DarkYellow } else { Color::DarkGrey }),
+                style::Print(line),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+        }
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Appends the code references of a response to the log of the workspace, with the files the
+    /// response writes.
+    fn record_code_references(
+        &self,
+        references: &[(CodeReference, bool)],
+        response: &str,
+        tool_uses: &[AssistantToolUse],
+    ) {
+        let files = tool_uses
+            .iter()
+            .filter(|tool_use| tool_use.name == "fs_write")
+            .filter_map(|tool_use| tool_use.args.get("path")?.as_str().map(str::to_string))
+            .collect::<Vec<_>>();
+        let snippet = response_snippet(response);
+        let entries = references
+            .iter()
+            .map(|(reference, withheld)| ReferenceEntry {
+                conversation_id: Some(self.conversation_state.conversation_id().to_string()),
+                files: files.clone(),
+                withheld: *withheld,
+                ..ReferenceEntry::new(reference, &snippet)
+            })
+            .collect::<Vec<_>>();
+        let result = self
+            .ctx
+            .env()
+            .current_dir()
+            .map_err(ErrReport::from)
+            .and_then(|cwd| ReferenceLog::for_workspace(&cwd).append(&entries));
+        if let Err(err) = result {
+            warn!(?err, "Failed to record the code references");
+        }
+    }
+
+    /// Prints the lines found by `/search`, the most recent last, with the matches highlighted.
+    fn print_search_results(&mut self, regex: &Regex, matches: &[(usize, String)]) -> Result<(), ChatError> {
+        const MAX_RESULTS: usize = 50;
+
+        if matches.is_empty() {
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print("\nNo lines match.\n\n"),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+            return Ok(());
+        }
+
+        queue!(
+            self.output,
+            style::SetAttribute(Attribute::Bold),
+            style::Print(format!("\n{} matching line(s)\n\n", matches.len())),
+            style::SetAttribute(Attribute::Reset),
+        )?;
+        for (number, line) in &matches[matches.len().saturating_sub(MAX_RESULTS)..] {
+            queue!(
+                self.output,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!("{number:>6}  ")),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+            let mut last = 0;
+            for found in regex.find_iter(line) {
+                queue!(
+                    self.output,
+                    style::Print(&line[last..found.start()]),
+                    style::SetForegroundColor(Color::Yellow),
+                    style::SetAttribute(Attribute::Bold),
+                    style::Print(found.as_str()),
+                    style::SetAttribute(Attribute::Reset),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+                last = found.end();
+            }
+            queue!(self.output, style::Print(&line[last..]), style::Print("\n"))?;
+        }
+
+        let hint = match matches.len() > MAX_RESULTS {
+            true => format!("Showing the last {MAX_RESULTS}. "),
+            false => String::new(),
+        };
+        execute!(
+            self.output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("\n{hint}Use /scrollback <line> to see a line in context.\n\n")),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        Ok(())
+    }
+
     /// Helper function to generate a prompt based on the current context
     fn generate_tool_trust_prompt(&self) -> String {
         prompt::generate_prompt(self.conversation_state.current_profile(), self.all_tools_trusted())
@@ -3663,6 +6112,129 @@ impl ChatContext {
         (self.terminal_width_provider)().unwrap_or(80)
     }
 
+    /// Shows the spinner, or the status of the TUI which the spinner would draw over.
+    fn start_spinner(&mut self, message: &str) {
+        // The spinner draws on STDOUT, after what is still buffered for the output.
+        let _ = self.output.flush();
+        match &self.tui {
+            Some(tui) => tui.send(TuiEvent::Status(message.to_string())),
+            // Announced once, instead of a spinner redrawn in place.
+            None if accessibility::enabled() => {
+                let _ = execute!(self.output, style::Print(format!("{message}\n")));
+            },
+            None => self.spinner = Some(Spinner::new(Spinners::Dots, message.to_string())),
+        }
+    }
+
+    /// Sends the context usage and the session details shown in the sidebar of the TUI.
+    async fn send_tui_usage(&mut self) {
+        let Some(tui) = self.tui.clone() else {
+            return;
+        };
+        let (profile, context_paths) = match &self.conversation_state.context_manager {
+            Some(context_manager) => (
+                Some(context_manager.current_profile.clone()),
+                context_manager
+                    .global_config
+                    .paths
+                    .iter()
+                    .chain(&context_manager.profile_config.paths)
+                    .chain(&context_manager.settings_paths)
+                    .cloned()
+                    .collect(),
+            ),
+            None => (None, Vec::new()),
+        };
+        let turns = self.conversation_state.history().len();
+        let data = self
+            .conversation_state
+            .backend_conversation_state(false, true)
+            .await
+            .calculate_conversation_size();
+        let tokens_used: TokenCount = (data.context_messages + data.user_messages + data.assistant_messages).into();
+        tui.send(TuiEvent::Usage(TuiUsage {
+            profile,
+            context_paths,
+            tokens_used: tokens_used.value(),
+            context_window: self.model_capabilities.context_window_tokens,
+            turns,
+        }));
+    }
+
+    /// Prints one bar per category of [ContextUsage], scaled to the context window so that the
+    /// longest bars are the ones worth pruning.
+    fn print_context_usage(&mut self, usage: &ContextUsage) -> Result<(), ChatError> {
+        const LABEL_WIDTH: usize = 28;
+
+        let context_window_size = self.model_capabilities.context_window_tokens;
+        let bar_width = std::cmp::min(self.terminal_width().saturating_sub(LABEL_WIDTH + 30), 40).max(10);
+        let percent = |tokens: TokenCount| (tokens.value() as f32 / context_window_size as f32) * 100.0;
+
+        let mut rows = vec![
+            ("Context files".to_string(), usage.context_files, Color::DarkCyan),
+            ("Conversation summary".to_string(), usage.summary, Color::DarkCyan),
+            (
+                "Hooks and other context".to_string(),
+                usage.other_context,
+                Color::DarkCyan,
+            ),
+            ("History".to_string(), usage.history, Color::Blue),
+        ];
+        for (origin, chars) in &usage.tools {
+            rows.push((format!("Tools: {origin}"), *chars, Color::DarkYellow));
+        }
+        rows.push(("Pending message".to_string(), usage.pending_message, Color::Magenta));
+
+        let total: TokenCount = usage.total().into();
+        queue!(
+            self.output,
+            style::Print(format!(
+                "\nContext window usage (~{} of {}k tokens, {:.2}%)\n\n",
+                total,
+                context_window_size / 1000,
+                percent(total)
+            )),
+        )?;
+        for (label, chars, color) in rows {
+            let tokens: TokenCount = chars.into();
+            let width = std::cmp::min(
+                (tokens.value() as f64 / context_window_size as f64 * bar_width as f64).ceil() as usize,
+                bar_width,
+            );
+            queue!(
+                self.output,
+                style::Print(format!("  {:<LABEL_WIDTH$}", truncate_safe(&label, LABEL_WIDTH - 1))),
+                style::SetForegroundColor(color),
+                style::Print("█".repeat(width)),
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print("░".repeat(bar_width - width)),
+                style::SetForegroundColor(Color::Reset),
+                style::Print(format!(" ~{} tokens ({:.2}%)\n", tokens, percent(tokens))),
+            )?;
+        }
+
+        queue!(
+            self.output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("\nRun "),
+            style::SetForegroundColor(Color::DarkGreen),
+            style::Print("/context show"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(" to see the context files, "),
+            style::SetForegroundColor(Color::DarkGreen),
+            style::Print("/tools"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(" to see the tools of each server and "),
+            style::SetForegroundColor(Color::DarkGreen),
+            style::Print("/compact"),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(" to summarize the history\n\n"),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        self.output.flush()?;
+        Ok(())
+    }
+
     fn all_tools_trusted(&self) -> bool {
         self.conversation_state.tools.values().flatten().all(|t| match t {
             FigTool::ToolSpecification(t) => self.tool_permissions.is_trusted(&t.name),
@@ -3671,7 +6243,10 @@ impl ChatContext {
 
     /// Display character limit warnings based on current conversation size
     async fn display_char_warnings(&mut self) -> Result<(), std::io::Error> {
-        let warning_level = self.conversation_state.get_token_warning_level().await;
+        let warning_level = self
+            .conversation_state
+            .get_token_warning_level(self.model_capabilities.context_window_tokens)
+            .await;
 
         match warning_level {
             TokenWarningLevel::Critical => {
@@ -3698,6 +6273,22 @@ impl ChatContext {
 }
 
 /// Prints hook configuration grouped by trigger: conversation session start or per user message
+/// E.g. "octo/repo (MIT license) https://github.com/octo/repo".
+fn describe_code_reference(reference: &CodeReference) -> String {
+    let mut description = reference
+        .repository
+        .clone()
+        .unwrap_or_else(|| "a repository".to_string());
+    match &reference.license_name {
+        Some(license) => description.push_str(&format!(" ({license} license)")),
+        None => description.push_str(" (unknown license)"),
+    }
+    if let Some(url) = &reference.url {
+        description.push_str(&format!(" {url}"));
+    }
+    description
+}
+
 fn print_hook_section(output: &mut impl Write, hooks: &HashMap<String, Hook>, trigger: HookTrigger) -> Result<()> {
     let section = match trigger {
         HookTrigger::ConversationStart => "On Session Start",
@@ -3782,6 +6373,11 @@ fn create_stream(model_responses: serde_json::Value) -> StreamingClient {
                         content: assistant_text.to_string(),
                     });
                 },
+                serde_json::Value::Object(event) if event.contains_key("followup_prompt") => {
+                    stream.push(ChatResponseStream::FollowupPromptEvent {
+                        content: event["followup_prompt"].as_str().unwrap().to_string(),
+                    });
+                },
                 serde_json::Value::Object(tool_use) => {
                     stream.append(&mut split_tool_use_event(tool_use));
                 },
@@ -4180,6 +6776,85 @@ mod tests {
         assert!(!ctx.fs().exists("/file2.txt"));
     }
 
+    #[tokio::test]
+    async fn test_flow_hidden_followup_prompts() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Which one? 1. The first 2. The second, or I can create the files",
+                { "followup_prompt": "Tell me more" },
+                { "followup_prompt": "Show an example" },
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file1.txt",
+                    }
+                },
+                {
+                    "tool_use_id": "2",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file2.txt",
+                    }
+                }
+            ],
+            [
+                "The second it is",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+        // Declining to go over the budget leaves the tools and the prompts unused
+        database
+            .settings
+            .set(Setting::ChatMaxToolCallsPerTurn, 1)
+            .await
+            .unwrap();
+
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut chat_context = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::null(),
+            None,
+            InputSource::new_mock(vec![
+                "pick something".to_string(),
+                "n".to_string(),
+                "2".to_string(),
+                "exit".to_string(),
+            ]),
+            true,
+            false,
+            test_client,
+            || Some(80),
+            ToolManager::default(),
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+        )
+        .await
+        .unwrap();
+        chat_context.try_chat(&mut database, &telemetry).await.unwrap();
+
+        // The prompts weren't shown along with the tools, so "2" answers the question of the model
+        let prompts = chat_context
+            .conversation_state
+            .history()
+            .iter()
+            .map(|(user, _)| user.prompt().unwrap_or_default().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(prompts.last().map(String::as_str), Some("2"), "{prompts:?}");
+    }
+
     #[test]
     fn test_editor_content_processing() {
         // Since we no longer have template replacement, this test is simplified
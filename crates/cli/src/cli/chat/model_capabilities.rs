@@ -0,0 +1,218 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tracing::warn;
+
+use super::consts::CONTEXT_WINDOW_SIZE;
+use super::openai_config::OpenAiConfig;
+use super::token_counter::Tokenizer;
+use crate::database::Database;
+use crate::database::settings::Setting;
+
+/// What a model is able to handle, used to adapt requests to the model in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelCapabilities {
+    /// Maximum number of tokens the model accepts, including the response.
+    pub context_window_tokens: usize,
+    /// Whether images can be attached to requests.
+    pub supports_images: bool,
+    /// Whether the model can be offered tools to call.
+    pub supports_tools: bool,
+    /// How the conversation is counted against the context window.
+    pub tokenizer: Tokenizer,
+}
+
+impl ModelCapabilities {
+    const fn new(
+        context_window_tokens: usize,
+        supports_images: bool,
+        supports_tools: bool,
+        tokenizer: Tokenizer,
+    ) -> Self {
+        Self {
+            context_window_tokens,
+            supports_images,
+            supports_tools,
+            tokenizer,
+        }
+    }
+}
+
+impl Default for ModelCapabilities {
+    /// The capabilities of the Amazon Q backend.
+    fn default() -> Self {
+        Self::new(CONTEXT_WINDOW_SIZE, true, true, Tokenizer::Cl100k)
+    }
+}
+
+/// Capabilities of well known models, matched by model id prefix. Families without a public BPE
+/// encoding are counted with cl100k, which is closer to their tokenizers than the heuristic.
+const BUILTIN_CAPABILITIES: &[(&str, ModelCapabilities)] = &[
+    (
+        "gpt-4.1",
+        ModelCapabilities::new(1_047_576, true, true, Tokenizer::O200k),
+    ),
+    ("gpt-4o", ModelCapabilities::new(128_000, true, true, Tokenizer::O200k)),
+    (
+        "gpt-4-turbo",
+        ModelCapabilities::new(128_000, true, true, Tokenizer::Cl100k),
+    ),
+    ("gpt-4", ModelCapabilities::new(8_192, false, true, Tokenizer::Cl100k)),
+    (
+        "gpt-3.5-turbo",
+        ModelCapabilities::new(16_385, false, true, Tokenizer::Cl100k),
+    ),
+    ("o1", ModelCapabilities::new(200_000, true, true, Tokenizer::O200k)),
+    ("o3", ModelCapabilities::new(200_000, true, true, Tokenizer::O200k)),
+    ("o4-mini", ModelCapabilities::new(200_000, true, true, Tokenizer::O200k)),
+    ("claude", ModelCapabilities::new(200_000, true, true, Tokenizer::Cl100k)),
+    (
+        "deepseek-reasoner",
+        ModelCapabilities::new(64_000, false, false, Tokenizer::Cl100k),
+    ),
+    (
+        "deepseek",
+        ModelCapabilities::new(64_000, false, true, Tokenizer::Cl100k),
+    ),
+    ("kimi", ModelCapabilities::new(128_000, false, true, Tokenizer::Cl100k)),
+    (
+        "moonshot",
+        ModelCapabilities::new(128_000, false, true, Tokenizer::Cl100k),
+    ),
+    ("qwen", ModelCapabilities::new(32_768, false, true, Tokenizer::Cl100k)),
+    ("llama", ModelCapabilities::new(128_000, false, true, Tokenizer::Cl100k)),
+    (
+        "mistral",
+        ModelCapabilities::new(32_000, false, true, Tokenizer::Cl100k),
+    ),
+];
+
+/// A user provided override from the `chat.modelCapabilities` setting. Unset fields keep the
+/// built-in value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CapabilityOverride {
+    context_window_tokens: Option<usize>,
+    supports_images: Option<bool>,
+    supports_tools: Option<bool>,
+    tokenizer: Option<Tokenizer>,
+}
+
+/// Looks up [ModelCapabilities] for a model.
+///
+/// Built-in entries can be overridden or extended with the `chat.modelCapabilities` setting, an
+/// object mapping model id prefixes to capabilities, e.g.
+/// `{"my-model": {"contextWindowTokens": 32000, "supportsImages": false, "tokenizer": "o200k"}}`.
+/// The longest matching prefix wins.
+#[derive(Debug, Clone, Default)]
+pub struct ModelCapabilityRegistry {
+    overrides: Vec<(String, CapabilityOverride)>,
+}
+
+impl ModelCapabilityRegistry {
+    pub fn from_database(database: &Database) -> Self {
+        let mut overrides = Vec::new();
+        if let Some(serde_json::Value::Object(map)) = database.settings.get(Setting::ChatModelCapabilities) {
+            for (model, value) in map {
+                match serde_json::from_value::<CapabilityOverride>(value.clone()) {
+                    Ok(capability) => overrides.push((model.to_lowercase(), capability)),
+                    Err(err) => warn!("Ignoring invalid capabilities for model '{}': {}", model, err),
+                }
+            }
+        }
+        Self { overrides }
+    }
+
+    /// The capabilities of the model that chat requests are currently sent to.
+    pub fn for_current_provider(database: &Database) -> ModelCapabilities {
+        let config = OpenAiConfig::from_database(database);
+        if config.is_openai_compatible() {
+            Self::from_database(database).capabilities(&config.model)
+        } else {
+            ModelCapabilities::default()
+        }
+    }
+
+    pub fn capabilities(&self, model: &str) -> ModelCapabilities {
+        let base = longest_prefix_match(model, BUILTIN_CAPABILITIES.iter().copied()).unwrap_or_default();
+
+        match longest_prefix_match(
+            model,
+            self.overrides
+                .iter()
+                .map(|(prefix, capability)| (prefix.as_str(), capability)),
+        ) {
+            Some(capability) => ModelCapabilities {
+                context_window_tokens: capability.context_window_tokens.unwrap_or(base.context_window_tokens),
+                supports_images: capability.supports_images.unwrap_or(base.supports_images),
+                supports_tools: capability.supports_tools.unwrap_or(base.supports_tools),
+                tokenizer: capability.tokenizer.unwrap_or(base.tokenizer),
+            },
+            None => base,
+        }
+    }
+}
+
+/// The value of the longest prefix of the model id among `entries`, which are lowercase.
+pub fn longest_prefix_match<'a, T>(model: &str, entries: impl IntoIterator<Item = (&'a str, T)>) -> Option<T> {
+    let model = model.to_lowercase();
+    // Providers commonly namespace models, e.g. `openai/gpt-4o` or `anthropic.claude-3`
+    let name = model
+        .rsplit(['/', '.'])
+        .find(|s| !s.is_empty() && !s.chars().all(|c| c.is_ascii_digit()));
+    let matches = |prefix: &str| model.starts_with(prefix) || name.is_some_and(|n| n.starts_with(prefix));
+
+    entries
+        .into_iter()
+        .filter(|(prefix, _)| matches(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_capabilities() {
+        let registry = ModelCapabilityRegistry::default();
+        assert_eq!(registry.capabilities("gpt-4o-mini").context_window_tokens, 128_000);
+        assert!(!registry.capabilities("gpt-4").supports_images);
+        assert!(registry.capabilities("openai/gpt-4o").supports_images);
+        assert!(!registry.capabilities("deepseek-reasoner").supports_tools);
+        assert!(registry.capabilities("deepseek-chat").supports_tools);
+        assert_eq!(registry.capabilities("unknown-model"), ModelCapabilities::default());
+        assert_eq!(registry.capabilities("gpt-4o-mini").tokenizer, Tokenizer::O200k);
+        assert_eq!(registry.capabilities("o3-mini").tokenizer, Tokenizer::O200k);
+        assert_eq!(registry.capabilities("gpt-4").tokenizer, Tokenizer::Cl100k);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let registry = ModelCapabilityRegistry {
+            overrides: vec![
+                ("gpt-4o".to_string(), CapabilityOverride {
+                    supports_tools: Some(false),
+                    ..Default::default()
+                }),
+                ("my-model".to_string(), CapabilityOverride {
+                    context_window_tokens: Some(8_000),
+                    supports_images: Some(false),
+                    supports_tools: None,
+                    tokenizer: Some(Tokenizer::Heuristic),
+                }),
+            ],
+        };
+
+        assert_eq!(
+            registry.capabilities("gpt-4o"),
+            ModelCapabilities::new(128_000, true, false, Tokenizer::O200k)
+        );
+        assert_eq!(
+            registry.capabilities("my-model-v2"),
+            ModelCapabilities::new(8_000, false, true, Tokenizer::Heuristic)
+        );
+    }
+}
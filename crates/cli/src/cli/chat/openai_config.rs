@@ -1,10 +1,19 @@
-use std::fmt::{Display, Formatter};
+use std::fmt::{
+    Display,
+    Formatter,
+};
+
+use eyre::{
+    Result,
+    WrapErr,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
 
-use eyre::{Result, WrapErr};
-use serde::{Deserialize, Serialize};
-
-use crate::database::settings::Setting;
 use crate::database::Database;
+use crate::database::settings::Setting;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ChatProvider {
@@ -87,8 +96,7 @@ impl OpenAiConfig {
         let provider = database
             .settings
             .get_string(Setting::OpenAiProvider)
-            .map(|s| ChatProvider::from(s.as_str()))
-            .unwrap_or(ChatProvider::AmazonQ);
+            .map_or(ChatProvider::AmazonQ, |s| ChatProvider::from(s.as_str()));
 
         let base_url = database
             .settings
@@ -113,6 +121,26 @@ impl OpenAiConfig {
     pub fn is_openai_compatible(&self) -> bool {
         !matches!(self.provider, ChatProvider::AmazonQ)
     }
+
+    /// The local provider used by `q chat --offline`, e.g. Ollama or a llama.cpp server.
+    /// `model` overrides the `chat.offline.model` setting.
+    pub fn offline(database: &Database, model: Option<String>) -> Self {
+        let base_url = database
+            .settings
+            .get_string_or_default(Setting::ChatOfflineBaseUrl)
+            .unwrap_or_else(|| "http://localhost:11434/v1".to_string());
+
+        let model = model
+            .or_else(|| database.settings.get_string_or_default(Setting::ChatOfflineModel))
+            .unwrap_or_else(|| "llama3.2".to_string());
+
+        Self {
+            provider: ChatProvider::Custom("local".to_string()),
+            base_url,
+            api_key: None,
+            model,
+        }
+    }
 }
 
 #[cfg(test)]
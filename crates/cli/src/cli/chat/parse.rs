@@ -31,6 +31,7 @@ use winnow::combinator::{
 use winnow::error::{
     ErrMode,
     ErrorKind,
+    Needed,
     ParserError,
 };
 use winnow::prelude::*;
@@ -52,6 +53,9 @@ const URL_TEXT_COLOR: Color = Color::Blue;
 const URL_LINK_COLOR: Color = Color::DarkGrey;
 
 const DEFAULT_RULE_WIDTH: usize = 40;
+/// Below this width the columns of a table are too narrow to read, and each row is printed as a
+/// list of the headers and values instead.
+const MIN_COLUMN_WIDTH: usize = 6;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error<'a> {
@@ -89,6 +93,11 @@ pub struct ParseState {
     pub strikethrough: bool,
     pub set_newline: bool,
     pub newline: bool,
+    /// Printed at the start of the lines a wrapped line continues on, e.g. to align them with the
+    /// text of a list item.
+    pub indent: String,
+    /// Whether the whole response is in the input, so a table can't get more rows.
+    pub ended: bool,
     pub citations: Vec<(String, String)>,
 }
 
@@ -103,6 +112,8 @@ impl ParseState {
             strikethrough: false,
             set_newline: false,
             newline: true,
+            indent: String::new(),
+            ended: false,
             citations: vec![],
         }
     }
@@ -143,6 +154,7 @@ pub fn interpret_markdown<'a, 'b>(
                 text,
                 // multiline patterns
                 blockquote,
+                table,
                 // linted_codeblock,
                 codeblock_begin,
                 // single line patterns
@@ -230,6 +242,7 @@ fn bulleted_item<'a, 'b>(
         let print = format!("{ws}• ");
 
         queue_newline_or_advance(&mut o, state, print.width())?;
+        state.indent = " ".repeat(print.width());
         queue(&mut o, style::Print(print))
     }
 }
@@ -247,6 +260,7 @@ fn numbered_item<'a, 'b>(
         let print = format!("{ws}{digits}. ");
 
         queue_newline_or_advance(&mut o, state, print.width())?;
+        state.indent = " ".repeat(print.width());
         queue(&mut o, style::Print(print))
     }
 }
@@ -306,10 +320,252 @@ fn blockquote<'a, 'b>(
 
         queue(&mut o, style::SetForegroundColor(BLOCKQUOTE_COLOR))?;
         queue_newline_or_advance(&mut o, state, print.width())?;
+        state.indent = print.clone();
         queue(&mut o, style::Print(print))
     }
 }
 
+fn table<'a, 'b>(
+    mut o: impl Write + 'b,
+    state: &'b mut ParseState,
+) -> impl FnMut(&mut Partial<&'a str>) -> PResult<(), Error<'a>> + 'b {
+    move |i| {
+        if !state.newline {
+            return Err(ErrMode::from_error_kind(i, ErrorKind::Fail));
+        }
+
+        // The whole table is needed to know how wide its columns are.
+        let header = terminated(table_row, ascii::line_ending).parse_next(i)?;
+        let delimiter = terminated(table_row, ascii::line_ending).parse_next(i)?;
+        let alignments = match table_alignments(&delimiter) {
+            Some(alignments) if alignments.len() == header.len() => alignments,
+            _ => return Err(ErrMode::from_error_kind(i, ErrorKind::Fail)),
+        };
+
+        let mut rows = Vec::new();
+        loop {
+            let next = i.trim_start_matches([' ', '\t']);
+            if next.is_empty() {
+                match state.ended {
+                    true => break,
+                    false => return Err(ErrMode::Incomplete(Needed::Unknown)),
+                }
+            }
+            if !next.starts_with('|') {
+                break;
+            }
+            let mut row = terminated(table_row, ascii::line_ending).parse_next(i)?;
+            row.resize(header.len(), String::new());
+            rows.push(row);
+        }
+
+        state.column = 0;
+        state.set_newline = true;
+
+        queue_table(&mut o, &header, &alignments, &rows, state.terminal_width)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// The cells of a line of a table, e.g. `| a | b |`.
+fn table_row<'a>(i: &mut Partial<&'a str>) -> PResult<Vec<String>, Error<'a>> {
+    let line = preceded((space0, "|"), till_line_ending).parse_next(i)?;
+    let line = line.trim_end();
+    let line = line
+        .strip_suffix('|')
+        .filter(|_| !line.ends_with("\\|"))
+        .unwrap_or(line);
+
+    let mut cells = vec![String::new()];
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => cells.last_mut().unwrap().push(chars.next().unwrap()),
+            '|' => cells.push(String::new()),
+            c => cells.last_mut().unwrap().push(c),
+        }
+    }
+    Ok(cells.iter().map(|cell| table_cell(cell)).collect())
+}
+
+/// The text of a cell, without the markers of inline code and emphasis.
+fn table_cell(cell: &str) -> String {
+    cell.trim()
+        .replace("**", "")
+        .replace("__", "")
+        .replace('`', "")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+/// The alignments of the columns of a table from its delimiter row, e.g. `|:---|---:|`, or [None]
+/// when `cells` isn't one.
+fn table_alignments(cells: &[String]) -> Option<Vec<Alignment>> {
+    cells
+        .iter()
+        .map(|cell| {
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Alignment::Center,
+                (false, true) => Alignment::Right,
+                _ => Alignment::Left,
+            })
+        })
+        .collect()
+}
+
+/// Prints a table with its columns narrowed to fit `terminal_width`, wrapping the text of their
+/// cells. When even that doesn't fit, each row is printed as a list of the headers and values.
+fn queue_table<'a>(
+    o: &mut impl Write,
+    header: &[String],
+    alignments: &[Alignment],
+    rows: &[Vec<String>],
+    terminal_width: Option<usize>,
+) -> Result<(), ErrMode<Error<'a>>> {
+    let natural = (0..header.len())
+        .map(|column| {
+            std::iter::once(&header[column])
+                .chain(rows.iter().map(|row| &row[column]))
+                .map(|cell| cell.width())
+                .max()
+                .unwrap_or_default()
+                .max(1)
+        })
+        .collect::<Vec<_>>();
+
+    // Each column is padded with a space on both sides, and separated from the next by a line.
+    let available = terminal_width.map(|width| width.saturating_sub(3 * header.len() - 1));
+    let widths = match available {
+        Some(available) => match column_widths(&natural, available) {
+            Some(widths) => widths,
+            None => {
+                for row in rows {
+                    for (name, value) in header.iter().zip(row) {
+                        queue(o, style::SetAttribute(Attribute::Bold))?;
+                        queue(o, style::Print(format!("{name}:")))?;
+                        queue(o, style::SetAttribute(Attribute::NormalIntensity))?;
+                        queue(o, style::Print(format!(" {value}\n")))?;
+                    }
+                    queue(o, style::Print("\n"))?;
+                }
+                return Ok(());
+            },
+        },
+        None => natural,
+    };
+
+    queue(o, style::SetAttribute(Attribute::Bold))?;
+    queue_table_row(o, header, &widths, alignments)?;
+    queue(o, style::SetAttribute(Attribute::NormalIntensity))?;
+    let rule = widths.iter().map(|width| "─".repeat(width + 2)).collect::<Vec<_>>();
+    queue(o, style::Print(format!("{}\n", rule.join("┼"))))?;
+    for row in rows {
+        queue_table_row(o, row, &widths, alignments)?;
+    }
+
+    Ok(())
+}
+
+/// Prints the cells of a row of a table, on as many lines as its longest cell is wrapped on.
+fn queue_table_row<'a>(
+    o: &mut impl Write,
+    cells: &[String],
+    widths: &[usize],
+    alignments: &[Alignment],
+) -> Result<(), ErrMode<Error<'a>>> {
+    let lines = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| wrap(cell, *width))
+        .collect::<Vec<_>>();
+    let height = lines.iter().map(Vec::len).max().unwrap_or(1);
+    for line in 0..height {
+        let cells = lines
+            .iter()
+            .zip(widths)
+            .zip(alignments)
+            .map(|((lines, width), alignment)| {
+                let text = lines.get(line).map(String::as_str).unwrap_or_default();
+                let padding = width.saturating_sub(text.width());
+                let (left, right) = match alignment {
+                    Alignment::Left => (0, padding),
+                    Alignment::Center => (padding / 2, padding - padding / 2),
+                    Alignment::Right => (padding, 0),
+                };
+                format!(" {}{text}{} ", " ".repeat(left), " ".repeat(right))
+            })
+            .collect::<Vec<_>>();
+        queue(o, style::Print(format!("{}\n", cells.join("│").trim_end())))?;
+    }
+    Ok(())
+}
+
+/// Narrows the widest columns until they fit in `available`, leaving the ones narrower than their
+/// share as they are. Returns [None] when the columns would be narrower than [MIN_COLUMN_WIDTH].
+fn column_widths(natural: &[usize], available: usize) -> Option<Vec<usize>> {
+    if natural.iter().sum::<usize>() <= available {
+        return Some(natural.to_vec());
+    }
+
+    let mut widths = natural.to_vec();
+    let mut narrowed = (0..natural.len()).collect::<Vec<_>>();
+    let mut remaining = available;
+    loop {
+        let share = remaining / narrowed.len();
+        let (fitting, wider): (Vec<_>, Vec<_>) = narrowed.iter().partition(|column| natural[**column] <= share);
+        if fitting.is_empty() {
+            for (index, column) in wider.iter().enumerate() {
+                widths[*column] = share + usize::from(index < remaining % wider.len());
+            }
+            break;
+        }
+        remaining -= fitting.iter().map(|column| natural[*column]).sum::<usize>();
+        narrowed = wider;
+    }
+
+    let readable = widths
+        .iter()
+        .zip(natural)
+        .all(|(width, natural)| *width >= (*natural).min(MIN_COLUMN_WIDTH));
+    readable.then_some(widths)
+}
+
+/// The lines of `text` wrapped at `width`, breaking words that are wider than that.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    for word in text.split_whitespace() {
+        let line = lines.last_mut().unwrap();
+        if !line.is_empty() && line.width() + 1 + word.width() <= width {
+            line.push(' ');
+            line.push_str(word);
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(String::new());
+        }
+        for c in word.chars() {
+            let line = lines.last_mut().unwrap();
+            if !line.is_empty() && line.width() + c.width().unwrap_or_default() > width {
+                lines.push(String::new());
+            }
+            lines.last_mut().unwrap().push(c);
+        }
+    }
+    lines
+}
+
 fn bold<'a, 'b>(
     mut o: impl Write + 'b,
     state: &'b mut ParseState,
@@ -491,6 +747,7 @@ fn line_ending<'a, 'b>(
 
         state.column = 0;
         state.set_newline = true;
+        state.indent.clear();
 
         queue(&mut o, style::ResetColor)?;
         queue(&mut o, style::SetAttribute(style::Attribute::Reset))?;
@@ -506,7 +763,7 @@ fn fallback<'a, 'b>(
         let fallback = any.parse_next(i)?;
         if let Some(width) = fallback.width() {
             queue_newline_or_advance(&mut o, state, width)?;
-            if fallback != ' ' || state.column != 1 {
+            if fallback != ' ' || state.column != state.indent.width() + 1 {
                 queue(&mut o, style::Print(fallback))?;
             }
         }
@@ -522,8 +779,9 @@ fn queue_newline_or_advance<'a, 'b>(
 ) -> Result<(), ErrMode<Error<'a>>> {
     if let Some(terminal_width) = state.terminal_width {
         if state.column > 0 && state.column + width > terminal_width {
-            state.column = width;
+            state.column = state.indent.width() + width;
             queue(&mut o, style::Print('\n'))?;
+            queue(&mut o, style::Print(&state.indent))?;
             return Ok(());
         }
     }
@@ -747,6 +1005,79 @@ mod tests {
         style::SetForegroundColor(BLOCKQUOTE_COLOR),
         style::Print("│ hello"),
     ]);
+    fn render(input: &str, terminal_width: usize) -> String {
+        let mut state = ParseState::new(Some(terminal_width));
+        state.ended = true;
+        let mut output = vec![];
+        let mut offset = 0;
+        loop {
+            let partial = Partial::new(&input[offset..]);
+            match interpret_markdown(partial, &mut output, &mut state) {
+                Ok(parsed) => {
+                    offset += parsed.offset_from(&partial);
+                    state.newline = state.set_newline;
+                    state.set_newline = false;
+                },
+                Err(err) => match err.into_inner() {
+                    Some(err) => panic!("{err}"),
+                    None => break,
+                },
+            }
+        }
+        strip_styles(&String::from_utf8(output).unwrap())
+    }
+
+    fn strip_styles(text: &str) -> String {
+        let mut plain = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\u{1b}' => {
+                    for c in chars.by_ref() {
+                        if c.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                },
+                c => plain.push(c),
+            }
+        }
+        plain
+    }
+
+    #[test]
+    fn list_item_wrap_1() {
+        assert_eq!(
+            render("- one two three four five six seven eight nine ten\n", 24),
+            "• one two three four \n  five six seven eight \n  nine ten\n"
+        );
+    }
+
+    #[test]
+    fn table_1() {
+        let input = "| Tool | What it does |\n|:--|--:|\n| fs_read | Reads files and directories |\n| a \\| b | `x` |\n\nafter\n";
+        assert_eq!(
+            render(input, 80),
+            " Tool    │                What it does\n\
+             ─────────┼─────────────────────────────\n \
+             fs_read │ Reads files and directories\n \
+             a | b   │                           x\n\nafter\n"
+        );
+        assert_eq!(
+            render(input, 24),
+            " Tool    │ What it does\n\
+             ─────────┼──────────────\n \
+             fs_read │  Reads files\n         │          and\n         │  directories\n \
+             a | b   │            x\n\nafter\n"
+        );
+        assert_eq!(
+            render(input, 12),
+            "Tool: fs_read\nWhat it does: Reads files and directories\n\nTool: a | b\nWhat it does: x\n\n\nafter\n"
+        );
+        // Not a table without the delimiter row.
+        assert_eq!(render("| a |\nb\n", 80), "| a |\nb\n");
+    }
+
     validate!(square_bracket_1, "[test]", [style::Print("[test]")]);
     validate!(square_bracket_2, "Text with [brackets]", [style::Print(
         "Text with [brackets]"
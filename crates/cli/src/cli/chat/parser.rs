@@ -19,8 +19,14 @@ use super::message::{
     AssistantMessage,
     AssistantToolUse,
 };
+use crate::api_client::ErrorKind;
 use crate::api_client::clients::SendMessageOutput;
-use crate::api_client::model::ChatResponseStream;
+use crate::api_client::model::{
+    ChatResponseStream,
+    CodeReference,
+    TokenUsage,
+    WebLink,
+};
 
 #[derive(Debug, Error)]
 pub struct RecvError {
@@ -30,6 +36,16 @@ pub struct RecvError {
     pub source: RecvErrorKind,
 }
 
+impl RecvError {
+    /// The category of the error when it was caused by the model provider.
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match &self.source {
+            RecvErrorKind::Client(err) | RecvErrorKind::StreamTimeout { source: err, .. } => Some(err.kind()),
+            _ => None,
+        }
+    }
+}
+
 impl std::fmt::Display for RecvError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Failed to receive the next message: ")?;
@@ -94,6 +110,10 @@ pub struct ResponseParser {
     /// Whether or not we are currently receiving tool use delta events. Tuple of
     /// `Some((tool_use_id, name))` if true, [None] otherwise.
     parsing_tool_use: Option<(String, String)>,
+    /// Whether text that matches licensed code is dropped instead of returned.
+    block_code_references: bool,
+    /// Tokens billed for the request, if the provider reported them.
+    usage: Option<TokenUsage>,
 }
 
 impl ResponseParser {
@@ -107,9 +127,22 @@ impl ResponseParser {
             assistant_text: String::new(),
             tool_uses: Vec::new(),
             parsing_tool_use: None,
+            block_code_references: false,
+            usage: None,
         }
     }
 
+    /// Drops the text that a [ChatResponseStream::CodeReferenceEvent] attributes to licensed code.
+    pub fn block_code_references(mut self, block: bool) -> Self {
+        self.block_code_references = block;
+        self
+    }
+
+    /// The usage of the request, known once the response ended.
+    pub fn usage(&self) -> Option<TokenUsage> {
+        self.usage
+    }
+
     /// Consumes the associated [ConverseStreamResponse] until a valid [ResponseEvent] is parsed.
     pub async fn recv(&mut self) -> Result<ResponseEvent, RecvError> {
         if let Some((id, name)) = self.parsing_tool_use.take() {
@@ -119,14 +152,15 @@ impl ResponseParser {
         }
 
         // First, handle discarding AssistantResponseEvent's that immediately precede a
-        // CodeReferenceEvent.
+        // CodeReferenceEvent when references are blocked.
         let peek = self.peek().await?;
         if let Some(ChatResponseStream::AssistantResponseEvent { content }) = peek {
             // Cloning to bypass borrowchecker stuff.
             let content = content.clone();
             self.next().await?;
+            let block_code_references = self.block_code_references;
             match self.peek().await? {
-                Some(ChatResponseStream::CodeReferenceEvent(_)) => (),
+                Some(ChatResponseStream::CodeReferenceEvent { .. }) if block_code_references => (),
                 _ => {
                     self.assistant_text.push_str(&content);
                     return Ok(ResponseEvent::AssistantText(content));
@@ -158,6 +192,19 @@ impl ResponseParser {
                         self.parsing_tool_use = Some((tool_use_id.clone(), name.clone()));
                         return Ok(ResponseEvent::ToolUseStart { name });
                     },
+                    ChatResponseStream::CodeReferenceEvent { references } if !references.is_empty() => {
+                        return Ok(ResponseEvent::CodeReference {
+                            references,
+                            withheld: self.block_code_references,
+                        });
+                    },
+                    ChatResponseStream::SupplementaryWebLinksEvent { links } if !links.is_empty() => {
+                        return Ok(ResponseEvent::WebLinks(links));
+                    },
+                    ChatResponseStream::FollowupPromptEvent { content } if !content.trim().is_empty() => {
+                        return Ok(ResponseEvent::FollowupPrompt(content));
+                    },
+                    ChatResponseStream::UsageEvent(usage) => self.usage = Some(usage),
                     _ => {},
                 },
                 Ok(None) => {
@@ -315,6 +362,15 @@ pub enum ResponseEvent {
     /// A tool use requested by the assistant. This should be displayed to the user as it is
     /// received.
     ToolUse(AssistantToolUse),
+    /// The text before this event matches licensed code. It was dropped if `withheld`.
+    CodeReference {
+        references: Vec<CodeReference>,
+        withheld: bool,
+    },
+    /// Pages the response is based on, to be listed after it.
+    WebLinks(Vec<WebLink>),
+    /// A prompt suggested for the next message.
+    FollowupPrompt(String),
     /// Represents the end of the response. No more events will be returned.
     EndStream {
         /// The completed message containing all of the assistant text and tool use events
@@ -348,7 +404,9 @@ mod tests {
             ChatResponseStream::AssistantResponseEvent {
                 content: "IGNORE ME PLEASE".to_string(),
             },
-            ChatResponseStream::CodeReferenceEvent(()),
+            ChatResponseStream::CodeReferenceEvent {
+                references: vec![mit_reference()],
+            },
             ChatResponseStream::ToolUseEvent {
                 tool_use_id: tool_use_id.clone(),
                 name: tool_name.clone(),
@@ -376,10 +434,93 @@ mod tests {
         ];
         events.reverse();
         let mock = SendMessageOutput::Mock(events);
-        let mut parser = ResponseParser::new(mock);
+        let mut parser = ResponseParser::new(mock).block_code_references(true);
 
         for _ in 0..5 {
             println!("{:?}", parser.recv().await.unwrap());
         }
     }
+
+    fn mit_reference() -> CodeReference {
+        CodeReference {
+            license_name: Some("MIT".to_string()),
+            repository: Some("octo/repo".to_string()),
+            url: Some("https://github.com/octo/repo".to_string()),
+        }
+    }
+
+    async fn recv_all(events: Vec<ChatResponseStream>, block_code_references: bool) -> Vec<ResponseEvent> {
+        let mut events = events;
+        events.reverse();
+        let mut parser =
+            ResponseParser::new(SendMessageOutput::Mock(events)).block_code_references(block_code_references);
+        let mut received = Vec::new();
+        loop {
+            let event = parser.recv().await.unwrap();
+            let end = matches!(event, ResponseEvent::EndStream { .. });
+            received.push(event);
+            if end {
+                return received;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_followup_prompts() {
+        let events = vec![
+            ChatResponseStream::AssistantResponseEvent {
+                content: "Done.".to_string(),
+            },
+            ChatResponseStream::FollowupPromptEvent {
+                content: "How do I test it?".to_string(),
+            },
+            ChatResponseStream::FollowupPromptEvent {
+                content: " ".to_string(),
+            },
+        ];
+
+        let received = recv_all(events, false).await;
+        assert_eq!(received.len(), 3);
+        assert!(matches!(&received[1], ResponseEvent::FollowupPrompt(prompt) if prompt == "How do I test it?"));
+        assert!(matches!(&received[2], ResponseEvent::EndStream { message } if message.content() == "Done."));
+    }
+
+    #[tokio::test]
+    async fn test_parse_code_references() {
+        let events = vec![
+            ChatResponseStream::AssistantResponseEvent {
+                content: "Here you go:".to_string(),
+            },
+            ChatResponseStream::AssistantResponseEvent {
+                content: "```rust\nfn licensed() {}\n```".to_string(),
+            },
+            ChatResponseStream::CodeReferenceEvent {
+                references: vec![mit_reference()],
+            },
+            ChatResponseStream::SupplementaryWebLinksEvent {
+                links: vec![WebLink {
+                    url: "https://doc.rust-lang.org".to_string(),
+                    title: "Rust docs".to_string(),
+                    snippet: None,
+                }],
+            },
+            ChatResponseStream::FollowupPromptEvent { content: String::new() },
+        ];
+
+        let received = recv_all(events.clone(), false).await;
+        assert!(matches!(&received[1], ResponseEvent::AssistantText(text) if text.contains("licensed")));
+        assert!(
+            matches!(&received[2], ResponseEvent::CodeReference { references, withheld: false } if references[0] == mit_reference())
+        );
+        assert!(matches!(&received[3], ResponseEvent::WebLinks(links) if links[0].title == "Rust docs"));
+        assert!(matches!(&received[4], ResponseEvent::EndStream { message } if message.content().contains("licensed")));
+
+        let received = recv_all(events, true).await;
+        assert_eq!(received.len(), 4);
+        assert!(matches!(&received[1], ResponseEvent::CodeReference {
+            withheld: true,
+            ..
+        }));
+        assert!(matches!(&received[3], ResponseEvent::EndStream { message } if message.content() == "Here you go:"));
+    }
 }
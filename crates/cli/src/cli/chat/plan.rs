@@ -0,0 +1,175 @@
+//! Plan-then-execute mode, started with `/plan <task>`.
+//!
+//! The model is first asked for a numbered plan of the task, without changing anything. Once the
+//! user approves the plan, possibly after editing it, each step is sent as its own prompt, and the
+//! user is asked before moving on to the next one, so that the plan can be stopped between steps
+//! and resumed with `/plan continue`.
+
+use std::fmt::Write as _;
+
+/// A step of a [Plan].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanStep {
+    pub description: String,
+    /// The tools the model intends to use for the step.
+    pub tools: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanStatus {
+    /// The model was asked for the plan, which is read from its next response.
+    Drafting,
+    /// A step was sent, the user is asked about the next one once the model answers.
+    Running,
+    /// Stopped between steps by the user.
+    Paused,
+}
+
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub task: String,
+    pub steps: Vec<PlanStep>,
+    /// Number of steps done.
+    pub done: usize,
+    pub status: PlanStatus,
+}
+
+impl Plan {
+    pub fn new(task: String) -> Self {
+        Self {
+            task,
+            steps: Vec::new(),
+            done: 0,
+            status: PlanStatus::Drafting,
+        }
+    }
+
+    /// The prompt asking the model for the plan of the task.
+    pub fn request(&self) -> String {
+        format!(
+            "Before making any change, plan this task: {}\n\n\
+             You may read files to plan it, but don't change anything yet. Reply with the plan only, \
+             one numbered step per line in this format:\n\
+             1. <what the step does> [tools: <the tools the step will use>]",
+            self.task
+        )
+    }
+
+    /// The prompt asking the model to carry out the next step.
+    pub fn step_prompt(&self) -> String {
+        format!(
+            "Carry out step {} of {} of the plan for \"{}\": {}\n\n\
+             Only do this step, then briefly report what you did. Don't start the next step.",
+            self.done + 1,
+            self.steps.len(),
+            self.task,
+            self.steps[self.done].description
+        )
+    }
+
+    /// Whether the model may use the tools that change something, which it can't until the plan is
+    /// approved.
+    pub fn allows_changes(&self) -> bool {
+        self.status != PlanStatus::Drafting
+    }
+
+    pub fn next_step(&self) -> Option<&PlanStep> {
+        self.steps.get(self.done)
+    }
+
+    /// The steps in the format of [parse_steps], e.g. to edit them.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            let _ = writeln!(text, "{}. {}", i + 1, step);
+        }
+        text
+    }
+}
+
+impl std::fmt::Display for PlanStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.description)?;
+        if !self.tools.is_empty() {
+            write!(f, " [tools: {}]", self.tools.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the numbered lines of `text`, such as `2. Update the tests [tools: fs_write]`, ignoring
+/// the others.
+pub fn parse_steps(text: &str) -> Vec<PlanStep> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim().trim_start_matches(['*', '-']).trim_start();
+            let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+            if rest.len() == line.len() {
+                return None;
+            }
+            let description = rest.strip_prefix(['.', ')'])?.trim();
+            let (description, tools) = match description.strip_suffix(']').and_then(|d| d.rsplit_once("[tools:")) {
+                Some((description, tools)) => (
+                    description.trim(),
+                    tools
+                        .split(',')
+                        .map(|tool| tool.trim().trim_matches('`').to_string())
+                        .filter(|tool| !tool.is_empty())
+                        .collect(),
+                ),
+                None => (description, Vec::new()),
+            };
+            (!description.is_empty()).then(|| PlanStep {
+                description: description.trim_matches('*').trim().to_string(),
+                tools,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_steps() {
+        let response = "Here is the plan:\n\n\
+                        1. Read the config loader [tools: fs_read]\n\
+                        2) **Add the setting** [tools: fs_write, `execute_bash`]\n\
+                        - 3. Run the tests\n\
+                        4.\n\
+                        The plan keeps the 2024 format.";
+        let steps = parse_steps(response);
+        assert_eq!(steps, [
+            PlanStep {
+                description: "Read the config loader".to_string(),
+                tools: vec!["fs_read".to_string()],
+            },
+            PlanStep {
+                description: "Add the setting".to_string(),
+                tools: vec!["fs_write".to_string(), "execute_bash".to_string()],
+            },
+            PlanStep {
+                description: "Run the tests".to_string(),
+                tools: Vec::new(),
+            },
+        ]);
+
+        let mut plan = Plan::new("add a setting".to_string());
+        assert!(!plan.allows_changes());
+        plan.steps = steps.clone();
+        assert_eq!(parse_steps(&plan.to_text()), steps);
+        plan.status = PlanStatus::Running;
+        assert!(plan.allows_changes());
+    }
+
+    #[test]
+    fn test_step_prompt() {
+        let mut plan = Plan::new("add a setting".to_string());
+        plan.steps = parse_steps("1. Read the loader\n2. Add the setting");
+        plan.done = 1;
+        assert!(plan.step_prompt().starts_with("Carry out step 2 of 2"));
+        assert!(plan.step_prompt().contains("Add the setting"));
+        assert_eq!(plan.next_step(), plan.steps.get(1));
+    }
+}
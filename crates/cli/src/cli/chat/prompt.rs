@@ -66,6 +66,7 @@ pub const COMMANDS: &[&str] = &[
     "/context rm --global",
     "/context clear",
     "/context clear --global",
+    "/context usage",
     "/context hooks help",
     "/context hooks add",
     "/context hooks rm",
@@ -75,9 +76,35 @@ pub const COMMANDS: &[&str] = &[
     "/context hooks disable-all",
     "/compact",
     "/compact help",
+    "/compact --preview",
+    "/compact undo",
+    "/summarize",
+    "/plan",
+    "/plan show",
+    "/plan continue",
+    "/plan stop",
+    "/plan help",
+    "/snippet",
+    "/snippet list",
+    "/snippet show",
+    "/snippet save",
+    "/snippet rm",
+    "/snippet help",
+    "/attach",
+    "/speak",
+    "/apply",
+    "/watch on",
+    "/watch off",
+    "/summarize --format",
     "/usage",
+    "/stats",
+    "/search",
+    "/scrollback",
+    "/translate",
     "/save",
     "/load",
+    "/export",
+    "/share",
 ];
 
 pub fn generate_prompt(current_profile: Option<&str>, warning: bool) -> String {
@@ -0,0 +1,362 @@
+//! What is dropped when the context files or the conversation history don't fit in a request.
+//!
+//! Context files and the turns of the history are scored by a [PruningPolicy], and the ones with
+//! the lowest score are dropped first. The default [ImportancePolicy] keeps what is recent, what
+//! the conversation keeps referring to and what the user asked for explicitly, rather than
+//! whatever happens to be small.
+
+use std::collections::{
+    HashSet,
+    VecDeque,
+};
+use std::fmt::Debug;
+use std::ops::Range;
+use std::path::Path;
+
+use super::message::{
+    AssistantMessage,
+    UserMessage,
+};
+
+/// What a candidate for pruning is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// A context file, e.g. added with `/context add`.
+    ContextFile,
+    /// A turn of the history answered without tools.
+    Exchange,
+    /// A turn of the history in which the model used tools, whose results can be fetched again.
+    ToolUse,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub role: Role,
+    /// In the unit of the budget: tokens for context files, messages for turns.
+    pub size: usize,
+    /// Turns since the candidate was added, 0 for the latest one and for context files.
+    pub age: usize,
+    /// Number of messages referring to the candidate, e.g. mentioning the file or a path it read.
+    pub references: usize,
+    /// Pinned candidates are never dropped.
+    pub pinned: bool,
+}
+
+pub trait PruningPolicy: Debug + Send + Sync {
+    /// How much the candidate is worth keeping, the lowest scores being dropped first.
+    fn score(&self, candidate: &Candidate) -> f64;
+}
+
+/// Scores candidates by their importance for each unit of the budget they use.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImportancePolicy;
+
+impl ImportancePolicy {
+    /// Importance of a reference relative to the one of the latest turn.
+    const REFERENCE_WEIGHT: f64 = 0.5;
+
+    fn role_weight(role: Role) -> f64 {
+        match role {
+            Role::ContextFile => 2.0,
+            Role::Exchange => 1.0,
+            Role::ToolUse => 0.5,
+        }
+    }
+}
+
+impl PruningPolicy for ImportancePolicy {
+    fn score(&self, candidate: &Candidate) -> f64 {
+        let recency = 1.0 / (1.0 + candidate.age as f64);
+        let importance = recency + Self::REFERENCE_WEIGHT * candidate.references as f64;
+        Self::role_weight(candidate.role) * importance / candidate.size.max(1) as f64
+    }
+}
+
+/// Indices of the candidates to drop for the others to fit within `budget`, in order. Candidates
+/// are kept by decreasing score as long as they fit, pinned ones first.
+pub fn select_dropped(candidates: &[Candidate], budget: usize, policy: &dyn PruningPolicy) -> Vec<usize> {
+    let scores = candidates.iter().map(|c| policy.score(c)).collect::<Vec<_>>();
+    let mut order = (0..candidates.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| {
+        candidates[b]
+            .pinned
+            .cmp(&candidates[a].pinned)
+            .then(scores[b].total_cmp(&scores[a]))
+            .then(a.cmp(&b))
+    });
+
+    let mut total = 0;
+    let mut dropped = Vec::new();
+    for i in order {
+        let candidate = &candidates[i];
+        if candidate.pinned || total + candidate.size <= budget {
+            total += candidate.size;
+        } else {
+            dropped.push(i);
+        }
+    }
+    dropped.sort_unstable();
+    dropped
+}
+
+/// The text of the conversation in which context files count as referenced.
+pub fn conversation_text<'a>(
+    history: &'a VecDeque<(UserMessage, AssistantMessage)>,
+    next_message: Option<&'a UserMessage>,
+) -> Vec<&'a str> {
+    history
+        .iter()
+        .flat_map(|(user, assistant)| [user.prompt(), Some(assistant.content())])
+        .chain([next_message.and_then(UserMessage::prompt)])
+        .flatten()
+        .collect()
+}
+
+/// Scores the context file `name`, mentioned by its file name in `conversation`.
+pub fn context_file_candidate(name: &str, size: usize, pinned: bool, conversation: &[&str]) -> Candidate {
+    let file_name = Path::new(name)
+        .file_name()
+        .map_or(name.into(), |file_name| file_name.to_string_lossy());
+    Candidate {
+        role: Role::ContextFile,
+        size,
+        age: 0,
+        references: conversation
+            .iter()
+            .filter(|text| text.contains(file_name.as_ref()))
+            .count(),
+        pinned,
+    }
+}
+
+/// A turn of the history: a prompt of the user, and the tool uses and results that followed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turn {
+    /// Exchanges of the turn in the history.
+    pub range: Range<usize>,
+    pub candidate: Candidate,
+}
+
+/// Splits the history into turns, the latest one being pinned as the next message continues it.
+/// Exchanges before the first prompt aren't part of any turn.
+pub fn history_turns(history: &VecDeque<(UserMessage, AssistantMessage)>) -> Vec<Turn> {
+    let starts = history
+        .iter()
+        .enumerate()
+        .filter(|(_, (user, _))| !user.has_tool_use_results())
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let ranges = starts
+        .iter()
+        .enumerate()
+        .map(|(n, &start)| start..starts.get(n + 1).copied().unwrap_or(history.len()))
+        .collect::<Vec<_>>();
+
+    // The paths each turn used tools on, and its text, to find the turns referring to them.
+    let paths = ranges
+        .iter()
+        .map(|range| {
+            let mut paths = HashSet::new();
+            for (_, assistant) in history.range(range.clone()) {
+                for tool_use in assistant.tool_uses().unwrap_or_default() {
+                    collect_paths(&tool_use.args, &mut paths);
+                }
+            }
+            paths
+        })
+        .collect::<Vec<_>>();
+    let texts = ranges
+        .iter()
+        .map(|range| {
+            history
+                .range(range.clone())
+                .flat_map(|(user, assistant)| [user.prompt(), Some(assistant.content())])
+                .flatten()
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let last = ranges.len().saturating_sub(1);
+    ranges
+        .iter()
+        .enumerate()
+        .map(|(n, range)| {
+            let uses_tools = history
+                .range(range.clone())
+                .any(|(_, assistant)| assistant.tool_uses().is_some_and(|tool_uses| !tool_uses.is_empty()));
+            let references = (n + 1..ranges.len())
+                .filter(|&later| {
+                    paths[n]
+                        .iter()
+                        .any(|path| paths[later].contains(path) || texts[later].iter().any(|text| text.contains(path)))
+                })
+                .count();
+            Turn {
+                range: range.clone(),
+                candidate: Candidate {
+                    role: if uses_tools { Role::ToolUse } else { Role::Exchange },
+                    size: range.len() * 2,
+                    age: last - n,
+                    references,
+                    pinned: n == last,
+                },
+            }
+        })
+        .collect()
+}
+
+/// Collects the values of the `path` arguments, at any depth, e.g. the operations of fs_read.
+fn collect_paths<'a>(args: &'a serde_json::Value, paths: &mut HashSet<&'a str>) {
+    match args {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    serde_json::Value::String(path) if key == "path" && !path.is_empty() => {
+                        paths.insert(path);
+                    },
+                    value => collect_paths(value, paths),
+                }
+            }
+        },
+        serde_json::Value::Array(values) => values.iter().for_each(|value| collect_paths(value, paths)),
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::cli::chat::message::{
+        AssistantToolUse,
+        ToolUseResult,
+    };
+
+    /// How context files were dropped before, the largest first.
+    #[derive(Debug)]
+    struct LargestFirstPolicy;
+
+    impl PruningPolicy for LargestFirstPolicy {
+        fn score(&self, candidate: &Candidate) -> f64 {
+            1.0 / candidate.size.max(1) as f64
+        }
+    }
+
+    /// How the history was trimmed before, the oldest turns first.
+    #[derive(Debug)]
+    struct OldestFirstPolicy;
+
+    impl PruningPolicy for OldestFirstPolicy {
+        fn score(&self, candidate: &Candidate) -> f64 {
+            1.0 / (1.0 + candidate.age as f64)
+        }
+    }
+
+    fn file(size: usize, references: usize, pinned: bool) -> Candidate {
+        Candidate {
+            role: Role::ContextFile,
+            size,
+            age: 0,
+            references,
+            pinned,
+        }
+    }
+
+    #[test]
+    fn test_select_dropped_keeps_pinned() {
+        let candidates = [file(50, 0, true), file(10, 0, false), file(60, 0, false)];
+        assert_eq!(select_dropped(&candidates, 60, &ImportancePolicy), vec![2]);
+        assert_eq!(select_dropped(&candidates, 10, &ImportancePolicy), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_referenced_context_file_is_kept() {
+        let conversation = [
+            "How is the cache invalidated? See DESIGN.md",
+            "DESIGN.md describes two layers of caching...",
+            "Does DESIGN.md cover eviction?",
+        ];
+        let candidates = [
+            context_file_candidate("/repo/DESIGN.md", 400, false, &conversation),
+            context_file_candidate("/repo/CHANGELOG.md", 250, false, &conversation),
+            context_file_candidate("/repo/CONTRIBUTING.md", 200, false, &conversation),
+        ];
+        assert_eq!(candidates[0].references, 3);
+
+        // Dropping the largest file loses the one the conversation is about.
+        assert_eq!(select_dropped(&candidates, 600, &LargestFirstPolicy), vec![0]);
+        assert_eq!(select_dropped(&candidates, 600, &ImportancePolicy), vec![1]);
+    }
+
+    fn tool_turn(prompt: &str, id: &str, path: &str) -> Vec<(UserMessage, AssistantMessage)> {
+        vec![
+            (
+                UserMessage::new_prompt(prompt.to_string()),
+                AssistantMessage::new_tool_use(None, String::new(), vec![AssistantToolUse {
+                    id: id.to_string(),
+                    name: "fs_read".to_string(),
+                    args: json!({ "operations": [{ "mode": "Line", "path": path }] }),
+                    ..Default::default()
+                }]),
+            ),
+            (
+                UserMessage::new_tool_use_results(vec![ToolUseResult {
+                    tool_use_id: id.to_string(),
+                    content: vec![],
+                    status: crate::api_client::model::ToolResultStatus::Success,
+                }]),
+                AssistantMessage::new_response(None, format!("{path} was read")),
+            ),
+        ]
+    }
+
+    fn exchange(prompt: &str, response: &str) -> Vec<(UserMessage, AssistantMessage)> {
+        vec![(
+            UserMessage::new_prompt(prompt.to_string()),
+            AssistantMessage::new_response(None, response.to_string()),
+        )]
+    }
+
+    #[test]
+    fn test_history_turns_keep_referenced_turn() {
+        let history = [
+            tool_turn("read the config", "t1", "src/config.rs"),
+            exchange("thanks", "you're welcome"),
+            tool_turn("read the readme", "t2", "README.md"),
+            exchange("what does src/config.rs default to?", "It defaults to..."),
+            exchange("and the timeout?", "30 seconds"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<VecDeque<_>>();
+
+        let turns = history_turns(&history);
+        assert_eq!(turns.iter().map(|turn| turn.range.clone()).collect::<Vec<_>>(), vec![
+            0..2,
+            2..3,
+            3..5,
+            5..6,
+            6..7
+        ]);
+        assert_eq!(turns[0].candidate.references, 1);
+        assert_eq!(turns[0].candidate.role, Role::ToolUse);
+        assert!(turns[4].candidate.pinned);
+
+        // Room for 10 of the 14 messages: dropping the oldest turn loses the file the conversation
+        // is still about, while the scores drop the unrelated one.
+        let candidates = turns.iter().map(|turn| turn.candidate.clone()).collect::<Vec<_>>();
+        assert_eq!(select_dropped(&candidates, 10, &OldestFirstPolicy), vec![0]);
+        assert_eq!(select_dropped(&candidates, 10, &ImportancePolicy), vec![2]);
+    }
+
+    #[test]
+    fn test_history_turns_skip_leading_tool_results() {
+        let mut history = tool_turn("read it", "t1", "a.rs").into_iter().collect::<VecDeque<_>>();
+        history.pop_front();
+        history.extend(exchange("hi", "hello"));
+        let turns = history_turns(&history);
+        assert_eq!(turns.len(), 1);
+        assert_eq!(turns[0].range, 1..2);
+    }
+}
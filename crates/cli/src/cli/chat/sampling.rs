@@ -0,0 +1,413 @@
+//! Sampling for MCP servers, which ask the model of the chat for completions with
+//! `sampling/createMessage`.
+//!
+//! With `mcp.sampling` set to ask, the default, the user approves every request, and requests are
+//! rejected when there is no one to ask. The requests of a chat session share a budget of
+//! `mcp.samplingTokenBudget` tokens, a request reserving the tokens of its messages and the
+//! `maxTokens` of its completion until the completion is done. Completions longer than `maxTokens`
+//! are cut there, with the `maxTokens` stop reason.
+
+use std::io::stderr;
+use std::sync::Mutex;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use crossterm::style::Stylize;
+use crossterm::{
+    execute,
+    style,
+};
+
+use super::token_counter::TokenCounter;
+use super::util::truncate_safe;
+use crate::api_client::StreamingClient;
+use crate::api_client::model::{
+    AssistantResponseMessage,
+    ChatMessage,
+    ChatResponseStream,
+    ConversationState,
+    ImageBlock,
+    ImageFormat,
+    ImageSource,
+    UserInputMessage,
+};
+use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::mcp_client::{
+    CreateMessageParams,
+    CreateMessageResult,
+    Sampler,
+    SamplingContent,
+    SamplingError,
+    SamplingMessage,
+    SamplingRole,
+};
+
+/// Bytes of the last message shown when asking to approve a request.
+const PREVIEW_BYTES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SamplingPolicy {
+    Ask,
+    Allow,
+}
+
+#[derive(Debug)]
+pub struct ChatSampler {
+    client: StreamingClient,
+    model: String,
+    policy: SamplingPolicy,
+    interactive: bool,
+    /// Tokens left in the budget of the session.
+    budget: Mutex<usize>,
+    /// Held while asking, so the requests of servers sampling at the same time are asked one after
+    /// the other.
+    prompt_lock: tokio::sync::Mutex<()>,
+}
+
+impl ChatSampler {
+    /// The sampler of a chat with `client`, or none when `mcp.sampling` is deny.
+    pub fn from_database(
+        database: &Database,
+        client: StreamingClient,
+        model: String,
+        interactive: bool,
+    ) -> Option<Self> {
+        let policy = match database.settings.get_string_or_default(Setting::McpSampling).as_deref() {
+            Some("deny") => return None,
+            Some("allow") => SamplingPolicy::Allow,
+            _ => SamplingPolicy::Ask,
+        };
+        let budget = database
+            .settings
+            .get_int_or_default(Setting::McpSamplingTokenBudget)
+            .unwrap_or_default()
+            .max(0) as usize;
+        Some(Self {
+            client,
+            model,
+            policy,
+            interactive,
+            budget: Mutex::new(budget),
+            prompt_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Takes `tokens` out of the budget.
+    fn reserve(&self, tokens: usize) -> Result<(), SamplingError> {
+        let mut budget = self.budget.lock().unwrap_or_else(|err| err.into_inner());
+        if tokens > *budget {
+            return Err(SamplingError::Failed(format!(
+                "The request needs {tokens} tokens but only {} are left, see mcp.samplingTokenBudget",
+                *budget
+            )));
+        }
+        *budget -= tokens;
+        Ok(())
+    }
+
+    fn refund(&self, tokens: usize) {
+        *self.budget.lock().unwrap_or_else(|err| err.into_inner()) += tokens;
+    }
+
+    async fn approve(&self, server_name: &str, params: &CreateMessageParams) -> Result<(), SamplingError> {
+        match (self.policy, self.interactive) {
+            (SamplingPolicy::Allow, _) => return Ok(()),
+            (SamplingPolicy::Ask, false) => return Err(SamplingError::Rejected),
+            (SamplingPolicy::Ask, true) => {},
+        }
+
+        let _guard = self.prompt_lock.lock().await;
+        let preview = match params.messages.last().map(|message| &message.content) {
+            Some(SamplingContent::Text { text }) => truncate_safe(text.trim(), PREVIEW_BYTES).to_string(),
+            Some(SamplingContent::Image { mime_type, .. }) => format!("[{mime_type} image]"),
+            None => String::new(),
+        };
+        let _ = execute!(
+            stderr(),
+            style::Print(format!(
+                "\n{} asks the model for a completion of up to {} tokens:\n\n{}\n\n",
+                server_name.bold(),
+                params.max_tokens,
+                preview.dark_grey()
+            ))
+        );
+        let approved = tokio::task::spawn_blocking(|| {
+            dialoguer::Confirm::with_theme(&crate::util::dialoguer_theme())
+                .with_prompt("Allow the request?")
+                .default(false)
+                .interact()
+                .unwrap_or(false)
+        })
+        .await
+        .unwrap_or(false);
+        match approved {
+            true => Ok(()),
+            false => Err(SamplingError::Rejected),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Sampler for ChatSampler {
+    async fn create_message(
+        &self,
+        server_name: &str,
+        params: CreateMessageParams,
+    ) -> Result<CreateMessageResult, SamplingError> {
+        let input_tokens = params
+            .messages
+            .iter()
+            .filter_map(|message| match &message.content {
+                SamplingContent::Text { text } => Some(text.as_str()),
+                SamplingContent::Image { .. } => None,
+            })
+            .chain(params.system_prompt.as_deref())
+            .map(TokenCounter::count_tokens_exact)
+            .sum::<usize>();
+        let max_tokens = params.max_tokens as usize;
+        let reserved = input_tokens + max_tokens;
+        self.reserve(reserved)?;
+
+        let result = async {
+            self.approve(server_name, &params).await?;
+            let (history, user_input_message) = conversation(params)?;
+            let mut response = self
+                .client
+                .send_message(ConversationState {
+                    conversation_id: None,
+                    user_input_message,
+                    history: (!history.is_empty()).then_some(history),
+                    response_schema: None,
+                })
+                .await
+                .map_err(|err| SamplingError::Failed(err.to_string()))?;
+            let mut text = String::new();
+            while let Some(event) = response
+                .recv()
+                .await
+                .map_err(|err| SamplingError::Failed(err.to_string()))?
+            {
+                if let ChatResponseStream::AssistantResponseEvent { content } = event {
+                    text.push_str(&content);
+                }
+            }
+            Ok::<_, SamplingError>(text)
+        }
+        .await
+        .map(|text| {
+            let truncated = truncate_tokens(&text, max_tokens);
+            match truncated.len() < text.len() {
+                true => (truncated.to_string(), "maxTokens"),
+                false => (text, "endTurn"),
+            }
+        });
+
+        let used = match &result {
+            Ok((text, _)) => input_tokens + TokenCounter::count_tokens_exact(text),
+            Err(_) => 0,
+        };
+        self.refund(reserved.saturating_sub(used));
+        let (text, stop_reason) = result?;
+        Ok(CreateMessageResult {
+            role: SamplingRole::Assistant,
+            content: SamplingContent::Text { text },
+            model: self.model.clone(),
+            stop_reason: Some(stop_reason.to_string()),
+        })
+    }
+}
+
+/// The longest start of `text` of at most `max_tokens` tokens.
+fn truncate_tokens(text: &str, max_tokens: usize) -> &str {
+    if TokenCounter::count_tokens_exact(text) <= max_tokens {
+        return text;
+    }
+    // The start of `text` up to `ends[low]` fits, the one up to `ends[high]` doesn't.
+    let ends = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([text.len()])
+        .collect::<Vec<_>>();
+    let (mut low, mut high) = (0, ends.len() - 1);
+    while high - low > 1 {
+        let mid = (low + high) / 2;
+        if TokenCounter::count_tokens_exact(&text[..ends[mid]]) <= max_tokens {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    &text[..ends[low]]
+}
+
+/// The history and the last message of the conversation of a request. Consecutive messages of the
+/// same role are joined, and the system prompt is put before the first user message.
+fn conversation(params: CreateMessageParams) -> Result<(Vec<ChatMessage>, UserInputMessage), SamplingError> {
+    let mut system_prompt = params.system_prompt.filter(|prompt| !prompt.trim().is_empty());
+    let mut messages: Vec<(SamplingRole, String, Vec<ImageBlock>)> = Vec::new();
+    for SamplingMessage { role, content } in params.messages {
+        if messages.last().is_none_or(|(last_role, ..)| *last_role != role) {
+            messages.push((role, String::new(), Vec::new()));
+        }
+        let Some((_, text, images)) = messages.last_mut() else {
+            continue;
+        };
+        match (role, content) {
+            (_, SamplingContent::Text { text: content }) => {
+                if !text.is_empty() {
+                    text.push_str("\n\n");
+                }
+                text.push_str(&content);
+            },
+            (SamplingRole::User, SamplingContent::Image { data, mime_type }) => images.push(image(&data, &mime_type)?),
+            (SamplingRole::Assistant, SamplingContent::Image { .. }) => {
+                return Err(SamplingError::Failed(
+                    "Images are only supported in user messages".to_string(),
+                ));
+            },
+        }
+    }
+
+    let mut history = Vec::new();
+    for (role, text, images) in messages {
+        history.push(match role {
+            SamplingRole::User => ChatMessage::UserInputMessage(UserInputMessage {
+                content: match system_prompt.take() {
+                    Some(prompt) => format!("{prompt}\n\n{text}"),
+                    None => text,
+                },
+                user_input_message_context: None,
+                user_intent: None,
+                images: (!images.is_empty()).then_some(images),
+            }),
+            SamplingRole::Assistant => ChatMessage::AssistantResponseMessage(AssistantResponseMessage {
+                message_id: None,
+                content: text,
+                tool_uses: None,
+            }),
+        });
+    }
+    match history.pop() {
+        Some(ChatMessage::UserInputMessage(message)) => Ok((history, message)),
+        _ => Err(SamplingError::Failed(
+            "The last message has to be a user message".to_string(),
+        )),
+    }
+}
+
+fn image(data: &str, mime_type: &str) -> Result<ImageBlock, SamplingError> {
+    let format = mime_type
+        .strip_prefix("image/")
+        .and_then(|format| format.parse::<ImageFormat>().ok())
+        .ok_or_else(|| SamplingError::Failed(format!("Unsupported image type {mime_type}")))?;
+    let bytes = STANDARD
+        .decode(data)
+        .map_err(|err| SamplingError::Failed(format!("Invalid image data: {err}")))?;
+    Ok(ImageBlock {
+        format,
+        source: ImageSource::Bytes(bytes),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(role: SamplingRole, text: &str) -> SamplingMessage {
+        SamplingMessage {
+            role,
+            content: SamplingContent::Text { text: text.to_string() },
+        }
+    }
+
+    fn params(messages: Vec<SamplingMessage>, max_tokens: u32) -> CreateMessageParams {
+        CreateMessageParams {
+            messages,
+            system_prompt: Some("Be brief.".to_string()),
+            max_tokens,
+        }
+    }
+
+    fn chat_sampler(policy: SamplingPolicy, interactive: bool, budget: usize, response: &str) -> ChatSampler {
+        ChatSampler {
+            client: StreamingClient::mock(vec![vec![ChatResponseStream::AssistantResponseEvent {
+                content: response.to_string(),
+            }]]),
+            model: "test".to_string(),
+            policy,
+            interactive,
+            budget: Mutex::new(budget),
+            prompt_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    #[test]
+    fn test_conversation() {
+        let (history, last) = conversation(params(
+            vec![
+                text(SamplingRole::User, "Summarize:"),
+                text(SamplingRole::User, "a long diff"),
+                text(SamplingRole::Assistant, "A diff."),
+                text(SamplingRole::User, "Shorter"),
+            ],
+            100,
+        ))
+        .unwrap();
+        assert_eq!(history.len(), 2);
+        let ChatMessage::UserInputMessage(first) = &history[0] else {
+            panic!("expected a user message");
+        };
+        assert_eq!(first.content, "Be brief.\n\nSummarize:\n\na long diff");
+        assert_eq!(last.content, "Shorter");
+
+        assert!(conversation(params(vec![text(SamplingRole::Assistant, "Hi")], 100)).is_err());
+        assert!(conversation(params(vec![], 100)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_message() {
+        let messages = vec![text(SamplingRole::User, "Name a color")];
+
+        let sampler = chat_sampler(SamplingPolicy::Allow, false, 1000, "Blue");
+        let result = sampler
+            .create_message("paint", params(messages.clone(), 100))
+            .await
+            .unwrap();
+        assert_eq!(result.content, SamplingContent::Text {
+            text: "Blue".to_string()
+        });
+        assert_eq!(result.model, "test");
+        // Only the tokens used are taken out of the budget.
+        let left = *sampler.budget.lock().unwrap();
+        assert!(left < 1000 && left > 900, "{left}");
+        assert_eq!(result.stop_reason.as_deref(), Some("endTurn"));
+
+        let response = "Red, orange, yellow, green, blue, indigo and violet are the colors of the rainbow.";
+        let sampler = chat_sampler(SamplingPolicy::Allow, false, 1000, response);
+        let result = sampler
+            .create_message("paint", params(messages.clone(), 5))
+            .await
+            .unwrap();
+        let SamplingContent::Text { text } = result.content else {
+            panic!("expected a text completion");
+        };
+        assert!(response.starts_with(&text) && text.len() < response.len(), "{text}");
+        assert!(TokenCounter::count_tokens_exact(&text) <= 5);
+        assert_eq!(result.stop_reason.as_deref(), Some("maxTokens"));
+        // The cut tokens aren't taken out of the budget.
+        let input_tokens =
+            TokenCounter::count_tokens_exact("Name a color") + TokenCounter::count_tokens_exact("Be brief.");
+        assert!(*sampler.budget.lock().unwrap() >= 1000 - input_tokens - 5);
+
+        let sampler = chat_sampler(SamplingPolicy::Allow, false, 50, "Blue");
+        let result = sampler.create_message("paint", params(messages.clone(), 100)).await;
+        assert!(matches!(result, Err(SamplingError::Failed(_))));
+        assert_eq!(*sampler.budget.lock().unwrap(), 50);
+
+        let sampler = chat_sampler(SamplingPolicy::Ask, false, 1000, "Blue");
+        let result = sampler.create_message("paint", params(messages, 100)).await;
+        assert!(matches!(result, Err(SamplingError::Rejected)));
+        assert_eq!(*sampler.budget.lock().unwrap(), 1000);
+    }
+}
@@ -0,0 +1,532 @@
+//! The scrollback of the chat: everything printed during the session, kept independently of the
+//! terminal emulator. `/search` looks through it, and `/scrollback` or PageUp at the prompt pages
+//! through it.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{
+    self,
+    Write,
+    stdout,
+};
+use std::sync::{
+    Arc,
+    Mutex,
+};
+
+use crossterm::event::{
+    self,
+    Event,
+    KeyCode,
+    KeyEvent,
+    KeyEventKind,
+    KeyModifiers,
+};
+use crossterm::style::{
+    self,
+    Attribute,
+    Color,
+};
+use crossterm::terminal::{
+    self,
+    ClearType,
+    EnterAlternateScreen,
+    LeaveAlternateScreen,
+};
+use crossterm::{
+    cursor,
+    execute,
+    queue,
+};
+use eyre::Result;
+use regex::Regex;
+use rustyline::{
+    Cmd,
+    ConditionalEventHandler,
+    EventContext,
+    RepeatCount,
+};
+use tracing::warn;
+use unicode_width::UnicodeWidthChar;
+
+use super::util::shared_writer::SharedWriter;
+
+/// Lines kept in a buffer, older ones are dropped.
+const MAX_LINES: usize = 10_000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    #[default]
+    None,
+    Started,
+    Csi,
+    Osc,
+    OscEnd,
+}
+
+/// Text written to a terminal, split into lines and without escape sequences. Sequences and UTF-8
+/// characters may be split across writes.
+#[derive(Debug, Default)]
+pub struct TextBuffer {
+    lines: VecDeque<String>,
+    current: Vec<u8>,
+    escape: Escape,
+    /// Whether the current line is overwritten by the next character, after a `\r`.
+    carriage_return: bool,
+}
+
+impl TextBuffer {
+    pub fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.escape = match (self.escape, byte) {
+                (Escape::None, 0x1b) => Escape::Started,
+                (Escape::None, _) => {
+                    self.push_byte(byte);
+                    Escape::None
+                },
+                (Escape::Started, b'[') => Escape::Csi,
+                (Escape::Started, b']') => Escape::Osc,
+                (Escape::Csi, 0x40..=0x7e) | (Escape::Osc, 0x07) | (Escape::Started | Escape::OscEnd, _) => {
+                    Escape::None
+                },
+                (Escape::Osc, 0x1b) => Escape::OscEnd,
+                (escape, _) => escape,
+            };
+        }
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => {
+                self.carriage_return = false;
+                let line = String::from_utf8_lossy(&self.current).into_owned();
+                self.current.clear();
+                self.lines.push_back(line);
+                if self.lines.len() > MAX_LINES {
+                    self.lines.pop_front();
+                }
+            },
+            b'\r' => self.carriage_return = true,
+            b'\t' => self.push_text(b"    "),
+            0..=0x1f | 0x7f => {},
+            _ => self.push_text(&[byte]),
+        }
+    }
+
+    fn push_text(&mut self, bytes: &[u8]) {
+        if std::mem::take(&mut self.carriage_return) {
+            self.current.clear();
+        }
+        self.current.extend_from_slice(bytes);
+    }
+
+    /// The lines, including the unfinished last one.
+    pub fn lines(&self) -> impl Iterator<Item = Cow<'_, str>> {
+        self.lines
+            .iter()
+            .map(|line| Cow::Borrowed(line.as_str()))
+            .chain((!self.current.is_empty()).then(|| String::from_utf8_lossy(&self.current)))
+    }
+
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// The lines printed during the session, shared by the output of the chat and the pager.
+#[derive(Debug, Clone, Default)]
+pub struct Scrollback(Arc<Mutex<TextBuffer>>);
+
+impl Scrollback {
+    /// Records everything written to `output`.
+    pub fn tee(&self, output: SharedWriter) -> SharedWriter {
+        SharedWriter::new(Tee {
+            output,
+            scrollback: self.clone(),
+        })
+    }
+
+    pub fn push(&self, bytes: &[u8]) {
+        self.0.lock().expect("Mutex poisoned").push(bytes);
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.0
+            .lock()
+            .expect("Mutex poisoned")
+            .lines()
+            .map(Cow::into_owned)
+            .collect()
+    }
+
+    /// The numbers, starting at 1, and the text of the lines matching the pattern.
+    pub fn search(&self, pattern: &Regex) -> Vec<(usize, String)> {
+        self.lines()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, line)| pattern.is_match(line))
+            .map(|(i, line)| (i + 1, line))
+            .collect()
+    }
+
+    /// Pages through the scrollback from the line, or from its end.
+    pub fn page(&self, line: Option<usize>) -> Result<()> {
+        let start = match line {
+            Some(line) => Start::Line(line),
+            None => Start::Bottom,
+        };
+        terminal::enable_raw_mode()?;
+        let result = run_pager(self.lines(), start);
+        terminal::disable_raw_mode()?;
+        result
+    }
+}
+
+struct Tee {
+    output: SharedWriter,
+    scrollback: Scrollback,
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.output.write(buf)?;
+        self.scrollback.push(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Opens the pager with PageUp at the prompt. The prompt is left as it was.
+pub struct ScrollbackPager(pub Scrollback);
+
+impl ConditionalEventHandler for ScrollbackPager {
+    fn handle(
+        &self,
+        _evt: &rustyline::Event,
+        _n: RepeatCount,
+        _positive: bool,
+        _ctx: &EventContext<'_>,
+    ) -> Option<Cmd> {
+        // The terminal is already in raw mode while reading a line.
+        if let Err(err) = run_pager(self.0.lines(), Start::PageUp) {
+            warn!(?err, "failed to show the scrollback");
+        }
+        Some(Cmd::Noop)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Start {
+    Bottom,
+    /// One page above the bottom.
+    PageUp,
+    /// The line, starting at 1.
+    Line(usize),
+}
+
+#[derive(Debug)]
+struct Pager {
+    lines: Vec<String>,
+    /// Index of the first line shown.
+    top: usize,
+    /// Lines shown, the status line excluded.
+    height: usize,
+    search: Option<Regex>,
+    /// Index of the line of the last match, where `n` and `N` continue from.
+    current: Option<usize>,
+    /// The search being typed after `/`.
+    input: Option<String>,
+    message: Option<String>,
+}
+
+impl Pager {
+    fn new(lines: Vec<String>, height: usize, start: Start) -> Self {
+        let mut pager = Self {
+            lines,
+            top: 0,
+            height: height.max(1),
+            search: None,
+            current: None,
+            input: None,
+            message: None,
+        };
+        pager.top = match start {
+            Start::Bottom => pager.max_top(),
+            Start::PageUp => pager.max_top().saturating_sub(pager.height),
+            Start::Line(line) => line.saturating_sub(1).min(pager.max_top()),
+        };
+        pager
+    }
+
+    fn max_top(&self) -> usize {
+        self.lines.len().saturating_sub(self.height)
+    }
+
+    fn resize(&mut self, height: usize) {
+        self.height = height.max(1);
+        self.top = self.top.min(self.max_top());
+    }
+
+    fn scroll_up(&mut self, rows: usize) {
+        self.top = self.top.saturating_sub(rows);
+        self.current = None;
+    }
+
+    fn scroll_down(&mut self, rows: usize) {
+        self.top = (self.top + rows).min(self.max_top());
+        self.current = None;
+    }
+
+    /// Moves to the next line matching the search, or to the previous one.
+    fn find(&mut self, forward: bool) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        let is_match = |i: &usize| search.is_match(&self.lines[*i]);
+        let found = match forward {
+            true => (self.current.map_or(self.top, |current| current + 1)..self.lines.len()).find(is_match),
+            false => (0..self.current.unwrap_or(self.top)).rev().find(is_match),
+        };
+        match found {
+            Some(i) => {
+                self.current = Some(i);
+                self.top = i.min(self.max_top());
+            },
+            None => self.message = Some("Pattern not found".to_string()),
+        }
+    }
+
+    /// Returns `false` once the pager is closed.
+    fn on_key(&mut self, key: KeyEvent) -> bool {
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Enter => {
+                    let pattern = std::mem::take(input);
+                    self.input = None;
+                    match Regex::new(&pattern) {
+                        Ok(search) => {
+                            self.search = Some(search);
+                            self.current = None;
+                            self.find(true);
+                        },
+                        Err(err) => self.message = Some(format!("Invalid pattern: {err}")),
+                    }
+                },
+                KeyCode::Esc => self.input = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                },
+                KeyCode::Char(c) => input.push(c),
+                _ => {},
+            }
+            return true;
+        }
+
+        self.message = None;
+        let page = self.height.saturating_sub(1).max(1);
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::PageUp | KeyCode::Char('b') => self.scroll_up(page),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.scroll_down(page),
+            KeyCode::Up | KeyCode::Char('k') => self.scroll_up(1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll_down(1),
+            KeyCode::Home | KeyCode::Char('g') => self.scroll_up(self.top),
+            KeyCode::End | KeyCode::Char('G') => self.scroll_down(self.max_top()),
+            KeyCode::Char('/') => self.input = Some(String::new()),
+            KeyCode::Char('n') => self.find(true),
+            KeyCode::Char('N') => self.find(false),
+            _ => {},
+        }
+        true
+    }
+
+    fn status(&self) -> String {
+        if let Some(input) = &self.input {
+            return format!("/{input}");
+        }
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+        let last = (self.top + self.height).min(self.lines.len());
+        format!(
+            "lines {}-{last} of {} · PgUp/PgDn scroll · / search · n/N next/previous · q quit",
+            (self.top + 1).min(last),
+            self.lines.len()
+        )
+    }
+}
+
+fn run_pager(lines: Vec<String>, start: Start) -> Result<()> {
+    let mut stdout = stdout();
+    let (width, height) = terminal::size()?;
+    let mut pager = Pager::new(lines, usize::from(height).saturating_sub(1), start);
+
+    execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
+    let result = pager_loop(&mut stdout, &mut pager, usize::from(width));
+    execute!(stdout, cursor::Show, LeaveAlternateScreen)?;
+    result
+}
+
+fn pager_loop(output: &mut impl Write, pager: &mut Pager, mut width: usize) -> Result<()> {
+    loop {
+        draw(output, pager, width)?;
+        match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release && !pager.on_key(key) => return Ok(()),
+            Event::Resize(columns, rows) => {
+                width = usize::from(columns);
+                pager.resize(usize::from(rows).saturating_sub(1));
+            },
+            _ => {},
+        }
+    }
+}
+
+fn draw(output: &mut impl Write, pager: &Pager, width: usize) -> Result<()> {
+    queue!(output, terminal::Clear(ClearType::All))?;
+    for (row, (i, line)) in pager
+        .lines
+        .iter()
+        .enumerate()
+        .skip(pager.top)
+        .take(pager.height)
+        .enumerate()
+    {
+        queue!(output, cursor::MoveTo(0, row as u16))?;
+        let line = fit(line, width);
+        if pager.current == Some(i) {
+            queue!(output, style::SetAttribute(Attribute::Underlined))?;
+        }
+        let mut last = 0;
+        if let Some(search) = &pager.search {
+            for found in search.find_iter(line) {
+                queue!(
+                    output,
+                    style::Print(&line[last..found.start()]),
+                    style::SetForegroundColor(Color::Black),
+                    style::SetBackgroundColor(Color::Yellow),
+                    style::Print(found.as_str()),
+                    style::ResetColor,
+                )?;
+                last = found.end();
+            }
+        }
+        queue!(
+            output,
+            style::Print(&line[last..]),
+            style::SetAttribute(Attribute::Reset)
+        )?;
+    }
+    queue!(
+        output,
+        cursor::MoveTo(0, pager.height as u16),
+        style::SetAttribute(Attribute::Reverse),
+        style::Print(fit(&pager.status(), width)),
+        style::SetAttribute(Attribute::Reset),
+    )?;
+    output.flush()?;
+    Ok(())
+}
+
+/// The start of the line which fits in the width.
+fn fit(line: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (i, c) in line.char_indices() {
+        used += c.width().unwrap_or(0);
+        if used > width {
+            return &line[..i];
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(buffer: &TextBuffer) -> Vec<String> {
+        buffer.lines().map(|line| line.into_owned()).collect()
+    }
+
+    #[test]
+    fn test_text_buffer_strips_escapes() {
+        let mut buffer = TextBuffer::default();
+        buffer.push(b"\x1b[38;5;10mgreen\x1b[0m text\n\x1b]8;;https://aws.com\x07link\x1b]8;;\x07");
+        assert_eq!(lines(&buffer), vec!["green text", "link"]);
+
+        // Sequences and characters split across writes.
+        let mut buffer = TextBuffer::default();
+        for chunk in [&b"a\x1b["[..], b"1m", b"\xe2\x97", b"\x8f b\n"] {
+            buffer.push(chunk);
+        }
+        assert_eq!(lines(&buffer), vec!["a● b"]);
+    }
+
+    #[test]
+    fn test_text_buffer_carriage_return() {
+        let mut buffer = TextBuffer::default();
+        buffer.push(b"Thinking...\r\x1b[2Kanswer\r\nnext\tline\n");
+        assert_eq!(lines(&buffer), vec!["answer", "next    line"]);
+    }
+
+    #[test]
+    fn test_scrollback_search() {
+        let scrollback = Scrollback::default();
+        let mut output = scrollback.tee(SharedWriter::null());
+        output
+            .write_all(b"\x1b[1mcargo test\x1b[0m\n3 passed\nerror[E0308]: mismatched types\n")
+            .unwrap();
+        assert_eq!(scrollback.search(&Regex::new(r"error\[E\d+\]").unwrap()), vec![(
+            3,
+            "error[E0308]: mismatched types".to_string()
+        )]);
+        assert!(scrollback.search(&Regex::new("warning").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_pager() {
+        let lines = (1..=100).map(|i| format!("line {i}")).collect::<Vec<_>>();
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+
+        assert_eq!(Pager::new(lines.clone(), 10, Start::Bottom).top, 90);
+        assert_eq!(Pager::new(lines.clone(), 10, Start::PageUp).top, 80);
+        assert_eq!(Pager::new(lines.clone(), 10, Start::Line(1000)).top, 90);
+
+        let mut pager = Pager::new(lines, 10, Start::Line(1));
+        pager.on_key(key(KeyCode::PageDown));
+        assert_eq!(pager.top, 9);
+        pager.on_key(key(KeyCode::Up));
+        assert_eq!(pager.top, 8);
+
+        for code in [
+            KeyCode::Char('/'),
+            KeyCode::Char('5'),
+            KeyCode::Char('$'),
+            KeyCode::Enter,
+        ] {
+            pager.on_key(key(code));
+        }
+        assert_eq!(pager.current, Some(14));
+        pager.on_key(key(KeyCode::Char('n')));
+        assert_eq!(pager.current, Some(24));
+        pager.on_key(key(KeyCode::Char('N')));
+        pager.on_key(key(KeyCode::Char('N')));
+        assert_eq!(pager.current, Some(4));
+        assert_eq!(pager.top, 4);
+        pager.on_key(key(KeyCode::Char('N')));
+        assert_eq!(pager.status(), "Pattern not found");
+
+        assert!(!pager.on_key(key(KeyCode::Char('q'))));
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit("hello world", 5), "hello");
+        assert_eq!(fit("日本語", 5), "日本");
+        assert_eq!(fit("short", 80), "short");
+    }
+}
@@ -0,0 +1,248 @@
+//! `q chat list` and `q chat rm`, for browsing and cleaning up the conversations saved per
+//! workspace directory, and `q chat import`, see [import_session].
+
+use std::io::Write as _;
+use std::path::Path;
+use std::process::ExitCode;
+
+use crossterm::style::Stylize;
+use eyre::Result;
+use time::macros::format_description;
+use time::{
+    OffsetDateTime,
+    UtcOffset,
+};
+
+use crate::cli::chat::cli::{
+    ChatList,
+    ChatRemove,
+    ChatSubcommand,
+};
+use crate::cli::chat::import::import_session;
+use crate::cli::chat::util::shared_writer::SharedWriter;
+use crate::database::Database;
+
+/// A saved conversation, as shown by `q chat list`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SessionEntry {
+    path: String,
+    title: String,
+    updated_at: Option<i64>,
+    messages: usize,
+}
+
+impl SessionEntry {
+    fn date(&self) -> String {
+        self.updated_at.map_or_else(|| "unknown".to_string(), format_timestamp)
+    }
+
+    /// Text matched against the query of `q chat list`.
+    fn haystack(&self) -> String {
+        format!("{} {} {}", self.title, self.path, self.date())
+    }
+}
+
+pub async fn execute_chat_subcommand(database: &mut Database, subcommand: ChatSubcommand) -> Result<ExitCode> {
+    let mut output = SharedWriter::stdout();
+
+    match subcommand {
+        ChatSubcommand::List(args) => list_sessions(database, &mut output, args)?,
+        ChatSubcommand::Remove(args) => remove_sessions(database, &mut output, args)?,
+        ChatSubcommand::Import(args) => import_session(database, &mut output, args).await?,
+    }
+
+    output.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn load_sessions(database: &Database) -> Result<Vec<SessionEntry>> {
+    let mut sessions = database
+        .get_all_conversations()?
+        .into_iter()
+        .filter(|(_, state)| !state.history().is_empty())
+        .map(|(path, state)| SessionEntry {
+            title: state.title().unwrap_or_else(|| "(untitled)".to_string()),
+            updated_at: state.updated_at(),
+            messages: state.history().len(),
+            path,
+        })
+        .collect::<Vec<_>>();
+
+    // Most recent first, conversations saved before timestamps were recorded last.
+    sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then_with(|| a.path.cmp(&b.path)));
+    Ok(sessions)
+}
+
+fn list_sessions(database: &Database, output: &mut SharedWriter, args: ChatList) -> Result<()> {
+    let sessions = filter_sessions(load_sessions(database)?, args.query.as_deref());
+    if sessions.is_empty() {
+        match args.query {
+            Some(query) => writeln!(output, "No saved conversations match '{query}'.")?,
+            None => writeln!(output, "No saved conversations found.")?,
+        }
+        return Ok(());
+    }
+
+    writeln!(output)?;
+    for session in &sessions {
+        writeln!(
+            output,
+            "  {}  {}",
+            session.date().dark_grey(),
+            session.title.clone().bold()
+        )?;
+        writeln!(
+            output,
+            "  {:16}  {} {}",
+            "",
+            session.path.clone().cyan(),
+            format!("· {} messages", session.messages).dark_grey()
+        )?;
+    }
+    writeln!(
+        output,
+        "\nResume a conversation with {} from its directory, remove it with {}.\n",
+        "q chat --resume".green(),
+        "q chat rm <path>".green()
+    )?;
+    Ok(())
+}
+
+fn remove_sessions(database: &mut Database, output: &mut SharedWriter, args: ChatRemove) -> Result<()> {
+    let sessions = load_sessions(database)?;
+
+    let paths = if args.all {
+        sessions.into_iter().map(|session| session.path).collect::<Vec<_>>()
+    } else if args.paths.is_empty() {
+        if sessions.is_empty() {
+            writeln!(output, "No saved conversations found.")?;
+            return Ok(());
+        }
+        let labels = sessions
+            .iter()
+            .map(|session| format!("{}  {}  ({})", session.date(), session.title, session.path))
+            .collect::<Vec<_>>();
+        match crate::util::choose("Select a conversation to remove", &labels)? {
+            Some(index) => vec![sessions[index].path.clone()],
+            None => return Ok(()),
+        }
+    } else {
+        args.paths.iter().map(|path| normalize_path(path)).collect()
+    };
+
+    for path in paths {
+        if database.get_conversation_by_path(&path)?.is_none() {
+            writeln!(output, "No saved conversation for {path}")?;
+            continue;
+        }
+        database.delete_conversation_by_path(&path)?;
+        writeln!(output, "✓ Removed the conversation for {path}")?;
+    }
+    Ok(())
+}
+
+/// Conversations are keyed by absolute directory, so relative paths are resolved against the
+/// current directory.
+fn normalize_path(path: &str) -> String {
+    let path = Path::new(path);
+    path.canonicalize()
+        .or_else(|_| std::env::current_dir().map(|cwd| cwd.join(path)))
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Keeps the sessions matching `query`, best matches first.
+fn filter_sessions(sessions: Vec<SessionEntry>, query: Option<&str>) -> Vec<SessionEntry> {
+    let Some(query) = query.filter(|query| !query.trim().is_empty()) else {
+        return sessions;
+    };
+
+    let mut scored = sessions
+        .into_iter()
+        .filter_map(|session| fuzzy_score(&session.haystack(), query).map(|score| (score, session)))
+        .collect::<Vec<_>>();
+    // Stable sort, so sessions with the same score stay ordered by date.
+    scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+    scored.into_iter().map(|(_, session)| session).collect()
+}
+
+/// Scores `haystack` against `query`, returning [None] if the characters of the query (ignoring
+/// whitespace) do not all appear in order. Consecutive matches and matches at the start of a
+/// word score higher.
+fn fuzzy_score(haystack: &str, query: &str) -> Option<i64> {
+    let haystack = haystack.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+
+    for needle in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let index = position + haystack[position..].iter().position(|c| *c == needle)?;
+        score += 1;
+        if previous_match.is_some_and(|previous| previous + 1 == index) {
+            score += 5;
+        }
+        if index == 0 || !haystack[index - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous_match = Some(index);
+        position = index + 1;
+    }
+
+    Some(score)
+}
+
+fn format_timestamp(timestamp: i64) -> String {
+    let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|date| {
+            date.to_offset(offset)
+                .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+                .ok()
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(path: &str, title: &str, updated_at: Option<i64>) -> SessionEntry {
+        SessionEntry {
+            path: path.to_string(),
+            title: title.to_string(),
+            updated_at,
+            messages: 1,
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("Fix the parser /home/user/repo", "fxprs").is_some());
+        assert!(fuzzy_score("Fix the parser /home/user/repo", "parser repo").is_some());
+        assert_eq!(fuzzy_score("Fix the parser", "xyz"), None);
+        assert_eq!(fuzzy_score("abc", "cba"), None);
+        assert!(fuzzy_score("parser", "par").unwrap() > fuzzy_score("p_a_r", "par").unwrap());
+    }
+
+    #[test]
+    fn test_filter_sessions() {
+        let sessions = vec![
+            session("/work/api", "Add pagination to the users endpoint", Some(20)),
+            session("/work/web", "Fix flaky login test", Some(10)),
+            session("/home/me/dotfiles", "Configure zsh prompt", None),
+        ];
+
+        assert_eq!(filter_sessions(sessions.clone(), None), sessions);
+        assert_eq!(filter_sessions(sessions.clone(), Some("  ")), sessions);
+
+        let matches = filter_sessions(sessions.clone(), Some("login"));
+        assert_eq!(matches, vec![sessions[1].clone()]);
+
+        let matches = filter_sessions(sessions.clone(), Some("dotfiles"));
+        assert_eq!(matches, vec![sessions[2].clone()]);
+
+        assert!(filter_sessions(sessions, Some("kubernetes")).is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+//! Sharing of transcripts with `/share`.
+//!
+//! The redacted transcript is uploaded to `chat.shareTarget`: a secret GitHub gist, created with
+//! `GITHUB_TOKEN` or the token of the GitHub CLI, or a presigned S3 URL it is PUT to. With a
+//! passphrase, it is encrypted with age first, so whoever gets the link and the passphrase can read
+//! it with `age -d`.
+
+use std::io::Write;
+
+use age::armor::{
+    ArmoredWriter,
+    Format,
+};
+use age::secrecy::SecretString;
+use reqwest::header::{
+    ACCEPT,
+    USER_AGENT,
+};
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+use tokio::process::Command;
+use url::Url;
+
+use crate::database::Database;
+use crate::database::settings::Setting;
+
+const GIST_API_URL: &str = "https://api.github.com/gists";
+
+#[derive(Debug, Error)]
+pub enum ShareError {
+    #[error("no GitHub token, set GITHUB_TOKEN or log in with `gh auth login`")]
+    NoGithubToken,
+    #[error("invalid chat.shareTarget, expected gist or an https URL")]
+    InvalidTarget,
+    #[error("upload failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareTarget {
+    /// A secret GitHub gist.
+    Gist,
+    /// A presigned S3 URL, or any other URL accepting a PUT of the transcript.
+    PresignedUrl(String),
+}
+
+impl ShareTarget {
+    pub fn from_database(database: &Database) -> Self {
+        match database.settings.get_string_or_default(Setting::ChatShareTarget) {
+            Some(target) if target.trim() != "gist" => Self::PresignedUrl(target.trim().to_string()),
+            _ => Self::Gist,
+        }
+    }
+
+    /// Uploads `transcript`, encrypted with `passphrase` when there is one, and returns its link.
+    pub async fn upload(&self, transcript: &str, passphrase: Option<&str>) -> Result<String, ShareError> {
+        let (content, filename) = match passphrase {
+            Some(passphrase) => (encrypt(transcript, passphrase)?, "transcript.md.age"),
+            None => (transcript.to_string(), "transcript.md"),
+        };
+        let client =
+            crate::request::new_client().map_err(|err| ShareError::Io(std::io::Error::other(err.to_string())))?;
+
+        match self {
+            Self::Gist => {
+                #[derive(Deserialize)]
+                struct Gist {
+                    html_url: String,
+                }
+
+                let token = github_token().await.ok_or(ShareError::NoGithubToken)?;
+                let gist: Gist = client
+                    .post(GIST_API_URL)
+                    .bearer_auth(token)
+                    .header(ACCEPT, "application/vnd.github+json")
+                    .header(USER_AGENT, "amazon-q-cli")
+                    .json(&json!({
+                        "description": "Amazon Q chat transcript",
+                        "public": false,
+                        "files": { filename: { "content": content } },
+                    }))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                Ok(gist.html_url)
+            },
+            Self::PresignedUrl(url) => {
+                let url = Url::parse(url)
+                    .ok()
+                    .filter(|url| url.scheme() == "https")
+                    .ok_or(ShareError::InvalidTarget)?;
+                // No content type, which would have to be part of the signature of the URL.
+                client.put(url.clone()).body(content).send().await?.error_for_status()?;
+                Ok(object_link(&url))
+            },
+        }
+    }
+}
+
+/// `text` encrypted with `passphrase`, in the ASCII armor of age.
+fn encrypt(text: &str, passphrase: &str) -> std::io::Result<String> {
+    let encryptor = age::Encryptor::with_user_passphrase(SecretString::from(passphrase.to_string()));
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor.wrap_output(ArmoredWriter::wrap_output(&mut encrypted, Format::AsciiArmor)?)?;
+    writer.write_all(text.as_bytes())?;
+    writer.finish().and_then(|armor| armor.finish())?;
+    Ok(String::from_utf8_lossy(&encrypted).into_owned())
+}
+
+/// `GITHUB_TOKEN`, or the token the GitHub CLI is logged in with.
+async fn github_token() -> Option<String> {
+    if let Some(token) = std::env::var("GITHUB_TOKEN")
+        .ok()
+        .filter(|token| !token.trim().is_empty())
+    {
+        return Some(token.trim().to_string());
+    }
+    let output = Command::new("gh").args(["auth", "token"]).output().await.ok()?;
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !token.is_empty()).then_some(token)
+}
+
+/// The link of the object a presigned URL writes, without the signature, which would let anyone
+/// overwrite it.
+fn object_link(url: &Url) -> String {
+    let mut link = url.clone();
+    link.set_query(None);
+    link.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn test_object_link() {
+        let url =
+            Url::parse("https://bucket.s3.amazonaws.com/chats/t.md?X-Amz-Signature=abc&X-Amz-Expires=3600").unwrap();
+        assert_eq!(object_link(&url), "https://bucket.s3.amazonaws.com/chats/t.md");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_target() {
+        for target in ["not a url", "http://example.com/t.md"] {
+            let result = ShareTarget::PresignedUrl(target.to_string()).upload("hi", None).await;
+            assert!(matches!(result, Err(ShareError::InvalidTarget)), "{target}");
+        }
+    }
+
+    #[test]
+    fn test_encrypt() {
+        let encrypted = encrypt("> hello\n\nHi!", "correct horse").unwrap();
+        assert!(encrypted.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+        assert!(!encrypted.contains("hello"));
+
+        let decryptor = age::Decryptor::new(age::armor::ArmoredReader::new(encrypted.as_bytes())).unwrap();
+        let identity = age::scrypt::Identity::new(SecretString::from("correct horse".to_string()));
+        let mut decrypted = String::new();
+        decryptor
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .unwrap()
+            .read_to_string(&mut decrypted)
+            .unwrap();
+        assert_eq!(decrypted, "> hello\n\nHi!");
+    }
+}
@@ -0,0 +1,227 @@
+//! Named prompt fragments, saved with `/snippet save` and inserted in prompts with `#name`.
+//!
+//! Each snippet is a markdown file named after it, in `~/.aws/amazonq/snippets` or in the
+//! `.amazonq/snippets` directory of the workspace, whose snippets replace the global ones with the
+//! same name. `#name` is expanded before the prompt is sent only where it starts a word and names
+//! a snippet, so that issue numbers and markdown headings are left as they are. The template
+//! variables of a snippet, such as `{{git_branch}}`, are rendered when it is expanded.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use eyre::{
+    Result,
+    bail,
+};
+
+use super::template;
+use super::tools::env_policy::EnvPolicy;
+use crate::platform::Context;
+use crate::util::directories;
+
+/// Maximum length of the name of a snippet.
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnippetScope {
+    Global,
+    Workspace,
+}
+
+impl SnippetScope {
+    fn dir(&self, ctx: &Context) -> Result<PathBuf> {
+        Ok(match self {
+            SnippetScope::Global => directories::chat_snippets_dir(ctx)?,
+            SnippetScope::Workspace => ctx.env().current_dir()?.join(".amazonq").join("snippets"),
+        })
+    }
+}
+
+impl std::fmt::Display for SnippetScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnippetScope::Global => write!(f, "global"),
+            SnippetScope::Workspace => write!(f, "workspace"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    pub name: String,
+    pub content: String,
+    pub scope: SnippetScope,
+}
+
+/// Checks that `name` can be referenced with `#name` and used as a file name.
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN || !name.chars().all(is_name_char) {
+        bail!("Snippet names are 1 to {MAX_NAME_LEN} letters, digits, '-' or '_', got '{name}'");
+    }
+    Ok(())
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// The snippets of the home directory and of the workspace, sorted by name.
+pub async fn load_all(ctx: &Context) -> Vec<Snippet> {
+    let mut snippets = BTreeMap::new();
+    for scope in [SnippetScope::Global, SnippetScope::Workspace] {
+        let Ok(dir) = scope.dir(ctx) else {
+            continue;
+        };
+        let Ok(mut entries) = ctx.fs().read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let Some(name) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .filter(|_| path.extension().is_some_and(|extension| extension == "md"))
+                .filter(|name| validate_name(name).is_ok())
+            else {
+                continue;
+            };
+            if let Ok(content) = ctx.fs().read_to_string(&path).await {
+                snippets.insert(name.to_string(), Snippet {
+                    name: name.to_string(),
+                    content: content.trim_end().to_string(),
+                    scope,
+                });
+            }
+        }
+    }
+    snippets.into_values().collect()
+}
+
+/// Saves the snippet, replacing the one with the same name in the scope.
+pub async fn save(ctx: &Context, name: &str, content: &str, scope: SnippetScope) -> Result<PathBuf> {
+    validate_name(name)?;
+    if content.trim().is_empty() {
+        bail!("The snippet is empty");
+    }
+    let dir = scope.dir(ctx)?;
+    ctx.fs().create_dir_all(&dir).await?;
+    let path = dir.join(format!("{name}.md"));
+    ctx.fs().write(&path, format!("{}\n", content.trim_end())).await?;
+    Ok(path)
+}
+
+/// Removes the snippet from the scope, returning whether there was one.
+pub async fn remove(ctx: &Context, name: &str, scope: SnippetScope) -> Result<bool> {
+    validate_name(name)?;
+    let path = scope.dir(ctx)?.join(format!("{name}.md"));
+    if !ctx.fs().exists(&path) {
+        return Ok(false);
+    }
+    ctx.fs().remove_file(&path).await?;
+    Ok(true)
+}
+
+/// Expands the `#name` references in the prompt to the saved snippets, with their template
+/// variables rendered, see [expand] and [template::render].
+pub async fn expand_saved(ctx: &Context, env_policy: &EnvPolicy, prompt: &str) -> (String, Vec<String>) {
+    let mut snippets = load_all(ctx).await;
+    snippets.retain(|snippet| prompt.contains(&format!("#{}", snippet.name)));
+    for snippet in &mut snippets {
+        snippet.content = template::render(ctx, env_policy, &snippet.content).await;
+    }
+    expand(prompt, &snippets)
+}
+
+/// Replaces the `#name` references to `snippets` in the prompt with their content, returning the
+/// names of the snippets that were expanded.
+pub fn expand(prompt: &str, snippets: &[Snippet]) -> (String, Vec<String>) {
+    let mut expanded = String::with_capacity(prompt.len());
+    let mut names = Vec::new();
+    let mut rest = prompt;
+    while let Some(index) = rest.find('#') {
+        let starts_word = rest[..index]
+            .chars()
+            .next_back()
+            .or_else(|| expanded.chars().next_back())
+            .is_none_or(char::is_whitespace);
+        expanded.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+
+        let len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+        match snippets.iter().find(|snippet| snippet.name == rest[..len]) {
+            Some(snippet) if starts_word && len > 0 => {
+                expanded.push_str(&snippet.content);
+                if !names.contains(&snippet.name) {
+                    names.push(snippet.name.clone());
+                }
+                rest = &rest[len..];
+            },
+            _ => expanded.push('#'),
+        }
+    }
+    expanded.push_str(rest);
+    (expanded, names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippet(name: &str, content: &str) -> Snippet {
+        Snippet {
+            name: name.to_string(),
+            content: content.to_string(),
+            scope: SnippetScope::Global,
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        let snippets = [
+            snippet("review", "Check naming, errors and tests."),
+            snippet("bug", "Steps:\n1.\n2."),
+        ];
+        let (expanded, names) = expand("#review src/main.rs", &snippets);
+        assert_eq!(expanded, "Check naming, errors and tests. src/main.rs");
+        assert_eq!(names, ["review"]);
+        assert_eq!(
+            expand("Fix #123 using #bug, then #review. #bug", &snippets).0,
+            "Fix #123 using Steps:\n1.\n2., then Check naming, errors and tests.. Steps:\n1.\n2."
+        );
+        for unchanged in ["# review", "a#review", "#reviews", "#", "C# and F#", "##review"] {
+            assert_eq!(expand(unchanged, &snippets), (unchanged.to_string(), Vec::new()));
+        }
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("code-review_2").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../secrets").is_err());
+        assert!(validate_name("two words").is_err());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LEN + 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_save_load_remove() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        save(&ctx, "review", "global review\n\n", SnippetScope::Global)
+            .await
+            .unwrap();
+        save(&ctx, "bug", "bug template", SnippetScope::Global).await.unwrap();
+        save(&ctx, "review", "workspace review", SnippetScope::Workspace)
+            .await
+            .unwrap();
+        assert!(save(&ctx, "empty", "  ", SnippetScope::Global).await.is_err());
+
+        let snippets = load_all(&ctx).await;
+        assert_eq!(snippets.len(), 2);
+        assert_eq!(snippets[0], snippet("bug", "bug template"));
+        assert_eq!(snippets[1].content, "workspace review");
+        assert_eq!(snippets[1].scope, SnippetScope::Workspace);
+
+        assert!(remove(&ctx, "review", SnippetScope::Workspace).await.unwrap());
+        assert!(!remove(&ctx, "review", SnippetScope::Workspace).await.unwrap());
+        assert_eq!(load_all(&ctx).await[1].content, "global review");
+    }
+}
@@ -0,0 +1,286 @@
+//! Voice input for chat.
+//!
+//! Audio is recorded with an external command (`sox` by default) into a 16kHz mono WAV file and
+//! transcribed either by a local whisper.cpp binary or by an OpenAI compatible
+//! `/audio/transcriptions` endpoint, depending on the `chat.speech.*` settings.
+
+use std::path::Path;
+use std::process::Stdio;
+
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::process::{
+    Child,
+    Command,
+};
+use tracing::debug;
+
+use crate::database::Database;
+use crate::database::settings::Setting;
+
+#[derive(Debug, Error)]
+pub enum SpeechError {
+    #[error("invalid command '{0}'")]
+    InvalidCommand(String),
+    #[error("failed to run '{command}': {source}")]
+    Spawn {
+        command: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("'{command}' failed: {stderr}")]
+    CommandFailed { command: String, stderr: String },
+    #[error("no audio was recorded")]
+    NoAudio,
+    #[error("transcription request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transcriber {
+    /// A local command, e.g. the whisper.cpp CLI, that prints the transcription to stdout.
+    Command(String),
+    /// An OpenAI compatible transcription endpoint.
+    Endpoint {
+        url: String,
+        model: String,
+        api_key: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpeechConfig {
+    /// Command recording from the default microphone until interrupted, `{output}` is replaced
+    /// with the path of the WAV file to write.
+    pub record_command: String,
+    pub transcriber: Transcriber,
+}
+
+impl SpeechConfig {
+    pub fn from_database(database: &Database) -> Self {
+        let record_command = database
+            .settings
+            .get_string_or_default(Setting::ChatSpeechRecordCommand)
+            .unwrap_or_default();
+
+        let transcriber = match database.settings.get_string(Setting::ChatSpeechEndpoint) {
+            Some(url) => Transcriber::Endpoint {
+                url,
+                model: database
+                    .settings
+                    .get_string_or_default(Setting::ChatSpeechModel)
+                    .unwrap_or_default(),
+                api_key: database.settings.get_string(Setting::OpenAiApiKey),
+            },
+            None => Transcriber::Command(
+                database
+                    .settings
+                    .get_string_or_default(Setting::ChatSpeechTranscribeCommand)
+                    .unwrap_or_default(),
+            ),
+        };
+
+        Self {
+            record_command,
+            transcriber,
+        }
+    }
+
+    /// Starts recording to `output`. The recording continues until [Recording::stop] is called.
+    pub fn start_recording(&self, output: &Path) -> Result<Recording, SpeechError> {
+        let command = build_command(&self.record_command, "{output}", output)?;
+        let child = spawn(command, &self.record_command)?;
+        Ok(Recording {
+            child,
+            command: self.record_command.clone(),
+        })
+    }
+
+    pub async fn transcribe(&self, audio: &Path) -> Result<String, SpeechError> {
+        if tokio::fs::metadata(audio).await.map(|m| m.len()).unwrap_or_default() == 0 {
+            return Err(SpeechError::NoAudio);
+        }
+
+        let text = match &self.transcriber {
+            Transcriber::Command(template) => {
+                let mut command = build_command(template, "{input}", audio)?;
+                command.stdout(Stdio::piped());
+                let output = spawn(command, template)?.wait_with_output().await?;
+                if !output.status.success() {
+                    return Err(SpeechError::CommandFailed {
+                        command: template.clone(),
+                        stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                    });
+                }
+                parse_transcription_output(&String::from_utf8_lossy(&output.stdout))
+            },
+            Transcriber::Endpoint { url, model, api_key } => {
+                transcribe_with_endpoint(url, model, api_key.as_deref(), audio).await?
+            },
+        };
+
+        debug!(?text, "transcribed audio");
+        Ok(text)
+    }
+}
+
+pub struct Recording {
+    child: Child,
+    command: String,
+}
+
+impl Recording {
+    /// Stops the recording, giving the recorder a chance to finalize the file.
+    pub async fn stop(mut self) -> Result<(), SpeechError> {
+        if let Some(status) = self.child.try_wait()? {
+            // The recorder exited on its own, which only happens on failure.
+            if !status.success() {
+                let mut stderr = String::new();
+                if let Some(mut pipe) = self.child.stderr.take() {
+                    tokio::io::AsyncReadExt::read_to_string(&mut pipe, &mut stderr)
+                        .await
+                        .ok();
+                }
+                return Err(SpeechError::CommandFailed {
+                    command: self.command,
+                    stderr: stderr.trim().to_string(),
+                });
+            }
+            return Ok(());
+        }
+
+        #[cfg(unix)]
+        if let Some(pid) = self.child.id() {
+            use nix::sys::signal::{
+                Signal,
+                kill,
+            };
+            use nix::unistd::Pid;
+
+            kill(Pid::from_raw(pid as i32), Signal::SIGINT).ok();
+            if tokio::time::timeout(std::time::Duration::from_secs(5), self.child.wait())
+                .await
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+
+        self.child.kill().await?;
+        Ok(())
+    }
+}
+
+/// Splits a command template and substitutes `placeholder` with `path`.
+fn build_command(template: &str, placeholder: &str, path: &Path) -> Result<Command, SpeechError> {
+    let args = shlex::split(template).ok_or_else(|| SpeechError::InvalidCommand(template.to_string()))?;
+    let (program, args) = args
+        .split_first()
+        .ok_or_else(|| SpeechError::InvalidCommand(template.to_string()))?;
+
+    let path = path.to_string_lossy();
+    let mut command = Command::new(program);
+    command.args(args.iter().map(|arg| arg.replace(placeholder, &path)));
+    Ok(command)
+}
+
+fn spawn(mut command: Command, template: &str) -> Result<Child, SpeechError> {
+    command
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|source| SpeechError::Spawn {
+            command: template.to_string(),
+            source,
+        })
+}
+
+/// Joins the lines printed by a transcription command, dropping whisper.cpp timestamps and
+/// markers such as `[BLANK_AUDIO]`.
+fn parse_transcription_output(output: &str) -> String {
+    output
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            match line.strip_prefix('[').and_then(|l| l.split_once(']')) {
+                Some((_, text)) => text.trim(),
+                None => line,
+            }
+        })
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+async fn transcribe_with_endpoint(
+    url: &str,
+    model: &str,
+    api_key: Option<&str>,
+    audio: &Path,
+) -> Result<String, SpeechError> {
+    #[derive(Deserialize)]
+    struct TranscriptionResponse {
+        text: String,
+    }
+
+    let boundary = format!("----q-speech-{}", uuid::Uuid::new_v4().simple());
+    let mut body = Vec::new();
+    body.extend(
+        format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\n{model}\r\n\
+            --{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"audio.wav\"\r\n\
+            Content-Type: audio/wav\r\n\r\n"
+        )
+        .into_bytes(),
+    );
+    body.extend(tokio::fs::read(audio).await?);
+    body.extend(format!("\r\n--{boundary}--\r\n").into_bytes());
+
+    let mut request = crate::request::new_client()
+        .map_err(|err| SpeechError::Io(std::io::Error::other(err.to_string())))?
+        .post(url)
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={boundary}"),
+        )
+        .body(body);
+    if let Some(api_key) = api_key {
+        request = request.bearer_auth(api_key);
+    }
+
+    let response: TranscriptionResponse = request.send().await?.error_for_status()?.json().await?;
+    Ok(response.text.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_transcription_output() {
+        let output = "\n[00:00:00.000 --> 00:00:02.000]  Refactor the parser\n[BLANK_AUDIO]\n and add tests.\n";
+        assert_eq!(parse_transcription_output(output), "Refactor the parser and add tests.");
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_with_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let audio = dir.path().join("audio.wav");
+        std::fs::write(&audio, b"RIFF").unwrap();
+
+        let config = SpeechConfig {
+            record_command: "sox -q -d {output}".to_string(),
+            transcriber: Transcriber::Command("echo hello from {input}".to_string()),
+        };
+        if cfg!(unix) {
+            let text = config.transcribe(&audio).await.unwrap();
+            assert_eq!(text, format!("hello from {}", audio.display()));
+        }
+
+        std::fs::write(&audio, b"").unwrap();
+        assert!(matches!(config.transcribe(&audio).await, Err(SpeechError::NoAudio)));
+    }
+}
@@ -0,0 +1,153 @@
+//! `q chat --schema`: answers with a JSON document conforming to a user supplied JSON schema.
+//!
+//! The schema is sent along with every request, for providers able to enforce it, and described
+//! in the prompt for the others. The final response is validated against it, and the model is
+//! asked to fix its answer with the validation errors until it conforms or the attempts run out.
+
+use std::path::Path;
+
+use eyre::{
+    Result,
+    WrapErr,
+    eyre,
+};
+
+#[derive(Debug, Clone)]
+pub struct StructuredOutput {
+    schema: serde_json::Value,
+    attempts: usize,
+}
+
+impl StructuredOutput {
+    /// Responses checked before giving up, including the first one.
+    pub const MAX_ATTEMPTS: usize = 3;
+
+    /// Reads the schema at `path`, failing if it isn't a valid JSON schema.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        let schema =
+            serde_json::from_str(&content).wrap_err_with(|| format!("{} is not valid JSON", path.display()))?;
+        Self::new(schema).wrap_err_with(|| format!("{} is not a valid JSON schema", path.display()))
+    }
+
+    pub fn new(schema: serde_json::Value) -> Result<Self> {
+        jsonschema::validator_for(&schema).map_err(|err| eyre!("{err}"))?;
+        Ok(Self { schema, attempts: 1 })
+    }
+
+    pub fn schema(&self) -> &serde_json::Value {
+        &self.schema
+    }
+
+    /// Appended to the prompt, for providers that can't enforce the schema themselves.
+    pub fn instructions(&self) -> String {
+        format!(
+            "Once you are done, answer with only a JSON document conforming to the following JSON schema, without any \
+             other text:\n```json\n{}\n```",
+            serde_json::to_string_pretty(&self.schema).unwrap_or_default()
+        )
+    }
+
+    /// Parses the response and validates it against the schema, returning the validation errors
+    /// if it doesn't conform. A markdown code block around the document is ignored.
+    pub fn validate(&self, response: &str) -> Result<serde_json::Value, Vec<String>> {
+        let document = strip_code_block(response);
+        let value = serde_json::from_str::<serde_json::Value>(document)
+            .map_err(|err| vec![format!("the response is not a JSON document: {err}")])?;
+        let validator = jsonschema::validator_for(&self.schema).map_err(|err| vec![err.to_string()])?;
+        let errors = validator
+            .iter_errors(&value)
+            .map(|err| match err.instance_path.to_string() {
+                path if path.is_empty() => err.to_string(),
+                path => format!("{path}: {err}"),
+            })
+            .collect::<Vec<_>>();
+        match errors.is_empty() {
+            true => Ok(value),
+            false => Err(errors),
+        }
+    }
+
+    /// Counts another attempt, returning false once they ran out.
+    pub fn retry(&mut self) -> bool {
+        self.attempts += 1;
+        self.attempts <= Self::MAX_ATTEMPTS
+    }
+
+    /// The prompt asking the model to fix its answer.
+    pub fn feedback(errors: &[String]) -> String {
+        format!(
+            "Your answer does not conform to the JSON schema:\n{}\n\nAnswer again with only the corrected JSON document.",
+            errors
+                .iter()
+                .map(|err| format!("- {err}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )
+    }
+}
+
+/// The content of a markdown code block wrapping the whole text, or the text itself.
+fn strip_code_block(text: &str) -> &str {
+    let text = text.trim();
+    text.strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+        .and_then(|rest| rest.split_once('\n'))
+        .map_or(text, |(_, content)| content.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn todos() -> StructuredOutput {
+        StructuredOutput::new(json!({
+            "type": "object",
+            "properties": {
+                "todos": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": { "file": { "type": "string" }, "line": { "type": "integer" } },
+                        "required": ["file", "line"]
+                    }
+                }
+            },
+            "required": ["todos"]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        let output = todos();
+        assert_eq!(
+            output.validate("```json\n{\"todos\": [{\"file\": \"src/main.rs\", \"line\": 3}]}\n```"),
+            Ok(json!({ "todos": [{ "file": "src/main.rs", "line": 3 }] }))
+        );
+
+        let errors = output
+            .validate("{\"todos\": [{\"file\": \"src/main.rs\", \"line\": \"3\"}]}")
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("/todos/0/line: "), "{}", errors[0]);
+
+        let errors = output.validate("Here are the TODOs: none").unwrap_err();
+        assert!(errors[0].starts_with("the response is not a JSON document"));
+    }
+
+    #[test]
+    fn test_retry() {
+        let mut output = todos();
+        assert!(output.retry());
+        assert!(output.retry());
+        assert!(!output.retry());
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        assert!(StructuredOutput::new(json!({ "type": "not-a-type" })).is_err());
+    }
+}
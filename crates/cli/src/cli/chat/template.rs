@@ -0,0 +1,157 @@
+//! Variables interpolated in context files and snippets when they are sent, so that static
+//! documents can refer to the current state of the workspace.
+//!
+//! - `{{cwd}}`: the current directory
+//! - `{{git_branch}}`: the git branch checked out in the current directory
+//! - `{{date}}`: the local date, as `YYYY-MM-DD`
+//! - `{{env:VAR}}`: the value of the environment variable `VAR`, if tools may see it, see
+//!   [EnvPolicy]
+//!
+//! Unknown variables and the environment variables hidden from tools are left as they are, so
+//! that credentials don't end up in the conversation, and variables that can't be resolved, such as
+//! the branch outside of a git repository, are replaced with an empty string.
+
+use std::collections::HashMap;
+
+use time::macros::format_description;
+use time::{
+    OffsetDateTime,
+    UtcOffset,
+};
+
+use super::git_context::run_git;
+use super::tools::env_policy::EnvPolicy;
+use crate::platform::Context;
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+/// Replaces the variables in `text` with their current values.
+pub async fn render(ctx: &Context, env_policy: &EnvPolicy, text: &str) -> String {
+    let names = variables(text);
+    if names.is_empty() {
+        return text.to_string();
+    }
+
+    let mut values = HashMap::new();
+    for name in names {
+        if let Some(value) = resolve(ctx, env_policy, name).await {
+            values.insert(name, value);
+        }
+    }
+    substitute(text, &values)
+}
+
+/// The names of the variables in `text`, without duplicates.
+fn variables(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some((name, after)) = next_variable(rest) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+        rest = after;
+    }
+    names
+}
+
+/// Finds the next `{{name}}` in `text`, returning the trimmed name and the text after it.
+fn next_variable(text: &str) -> Option<(&str, &str)> {
+    let start = text.find(OPEN)? + OPEN.len();
+    let len = text[start..].find(CLOSE)?;
+    Some((text[start..start + len].trim(), &text[start + len + CLOSE.len()..]))
+}
+
+fn substitute(text: &str, values: &HashMap<&str, String>) -> String {
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some((name, after)) = next_variable(rest) {
+        let start = rest.find(OPEN).expect("the variable was found");
+        let variable = &rest[start..rest.len() - after.len()];
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(values.get(name).map_or(variable, String::as_str));
+        rest = after;
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+async fn resolve(ctx: &Context, env_policy: &EnvPolicy, name: &str) -> Option<String> {
+    if let Some(var) = name.strip_prefix("env:") {
+        let var = var.trim();
+        return env_policy.is_allowed(var).then(|| {
+            let value = ctx.env().get(var).unwrap_or_default();
+            env_policy.value(var, &value).into_owned()
+        });
+    }
+    match name {
+        "cwd" => Some(
+            ctx.env()
+                .current_dir()
+                .map(|dir| dir.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        ),
+        "date" => {
+            let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+            OffsetDateTime::now_utc()
+                .to_offset(offset)
+                .format(format_description!("[year]-[month]-[day]"))
+                .ok()
+        },
+        "git_branch" => Some(git_branch(ctx).await.unwrap_or_default()),
+        _ => None,
+    }
+}
+
+async fn git_branch(ctx: &Context) -> Option<String> {
+    run_git(ctx, &["rev-parse", "--abbrev-ref", "HEAD"])
+        .await
+        .map(|branch| branch.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitute() {
+        let values = HashMap::from([("cwd", "/repo".to_string()), ("env:USER", String::new())]);
+        assert_eq!(variables("{{cwd}} {{ env:USER }} {{cwd}} {{unknown}}"), [
+            "cwd", "env:USER", "unknown"
+        ]);
+        assert_eq!(
+            substitute("In {{ cwd }} as '{{env:USER}}', {{unknown}} and {{cwd", &values),
+            "In /repo as '', {{unknown}} and {{cwd"
+        );
+        assert_eq!(substitute("no variables }}", &values), "no variables }}");
+    }
+
+    #[tokio::test]
+    async fn test_render() {
+        let ctx = Context::builder().with_env_var("Q_TEMPLATE_TEST", "value").build_fake();
+        let env_policy = EnvPolicy {
+            allowed: vec!["Q_TEMPLATE_*".to_string()],
+        };
+        let rendered = render(
+            &ctx,
+            &env_policy,
+            "{{env:Q_TEMPLATE_TEST}} {{env:Q_TEMPLATE_MISSING}}| {{date}}",
+        )
+        .await;
+        let (env, date) = rendered.split_once("| ").unwrap();
+        assert_eq!(env, "value ");
+        assert_eq!(date.len(), "2025-01-01".len());
+    }
+
+    #[tokio::test]
+    async fn test_render_hides_credentials() {
+        let ctx = Context::builder()
+            .with_env_var("AWS_SECRET_ACCESS_KEY", "secret")
+            .with_env_var("Q_TEMPLATE_TEST", "value")
+            .build_fake();
+        let text = "{{env:AWS_SECRET_ACCESS_KEY}} {{env:Q_TEMPLATE_TEST}} {{env:HOME}}";
+        let rendered = render(&ctx, &EnvPolicy::default(), text).await;
+        assert!(rendered.starts_with("{{env:AWS_SECRET_ACCESS_KEY}} {{env:Q_TEMPLATE_TEST}} "));
+        assert!(!rendered.contains("secret"));
+    }
+}
@@ -0,0 +1,238 @@
+//! Latency of the turns of a chat session, shown by `/stats` and, with `-v`, after every turn.
+//!
+//! A turn spans from sending a prompt to the final response, including every request made in
+//! between to send tool results back to the model. Providers that report usage also tell how much
+//! of each request was read from their prompt cache.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use crate::api_client::model::TokenUsage;
+
+/// How many turns `/stats` keeps.
+const MAX_TURNS: usize = 20;
+
+/// Timing of a single request to the model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestTiming {
+    pub request_id: Option<String>,
+    /// From sending the request to the first chunk of the response, [None] if the response was
+    /// empty.
+    pub time_to_first_chunk: Option<Duration>,
+    /// From sending the request to the end of the response stream.
+    pub stream_duration: Duration,
+    /// Input tokens of the request, if the provider reported them.
+    pub usage: Option<TokenUsage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolTiming {
+    pub name: String,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TurnTiming {
+    pub requests: Vec<RequestTiming>,
+    pub tools: Vec<ToolTiming>,
+}
+
+impl TurnTiming {
+    pub fn time_to_first_chunk(&self) -> Option<Duration> {
+        self.requests.first().and_then(|r| r.time_to_first_chunk)
+    }
+
+    pub fn stream_duration(&self) -> Duration {
+        self.requests.iter().map(|r| r.stream_duration).sum()
+    }
+
+    pub fn tool_duration(&self) -> Duration {
+        self.tools.iter().map(|t| t.duration).sum()
+    }
+
+    /// The usage of the requests that reported it, [None] if none did.
+    pub fn usage(&self) -> Option<TokenUsage> {
+        self.requests
+            .iter()
+            .filter_map(|r| r.usage)
+            .reduce(|total, usage| TokenUsage {
+                input_tokens: total.input_tokens + usage.input_tokens,
+                cached_input_tokens: total.cached_input_tokens + usage.cached_input_tokens,
+                cache_write_tokens: total.cache_write_tokens + usage.cache_write_tokens,
+                output_tokens: total.output_tokens + usage.output_tokens,
+            })
+    }
+
+    /// A single line summary, e.g. `first chunk 1.20s · model 4.31s (2 requests) · tools 0.52s
+    /// (1)`.
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        if let Some(ttfc) = self.time_to_first_chunk() {
+            let _ = write!(summary, "first chunk {:.2}s · ", ttfc.as_secs_f64());
+        }
+        let _ = write!(
+            summary,
+            "model {:.2}s ({} request{})",
+            self.stream_duration().as_secs_f64(),
+            self.requests.len(),
+            if self.requests.len() == 1 { "" } else { "s" }
+        );
+        if !self.tools.is_empty() {
+            let _ = write!(
+                summary,
+                " · tools {:.2}s ({})",
+                self.tool_duration().as_secs_f64(),
+                self.tools.len()
+            );
+        }
+        if let Some(usage) = self.usage().filter(|usage| usage.input_tokens > 0) {
+            let _ = write!(summary, " · {}", cache_summary(&usage));
+        }
+        summary
+    }
+}
+
+/// E.g. `cached 92% of 24.1k input tokens`.
+pub fn cache_summary(usage: &TokenUsage) -> String {
+    let percent = usage.cached_input_tokens as f64 / usage.input_tokens.max(1) as f64 * 100.0;
+    let input = if usage.input_tokens >= 1000 {
+        format!("{:.1}k", usage.input_tokens as f64 / 1000.0)
+    } else {
+        usage.input_tokens.to_string()
+    };
+    format!("cached {percent:.0}% of {input} input tokens")
+}
+
+/// The request in flight, see [SessionTimings::request_sent].
+#[derive(Debug, Clone, Copy)]
+struct PendingRequest {
+    sent: Instant,
+    first_chunk: Option<Instant>,
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Debug, Default)]
+pub struct SessionTimings {
+    turns: VecDeque<TurnTiming>,
+    current: Option<TurnTiming>,
+    pending: Option<PendingRequest>,
+}
+
+impl SessionTimings {
+    /// Starts a new turn, dropping an unfinished one.
+    pub fn start_turn(&mut self) {
+        self.current = Some(TurnTiming::default());
+        self.pending = None;
+    }
+
+    pub fn request_sent(&mut self) {
+        self.pending = Some(PendingRequest {
+            sent: Instant::now(),
+            first_chunk: None,
+            usage: None,
+        });
+    }
+
+    pub fn chunk_received(&mut self) {
+        if let Some(pending) = self.pending.as_mut() {
+            pending.first_chunk.get_or_insert_with(Instant::now);
+        }
+    }
+
+    pub fn usage_received(&mut self, usage: TokenUsage) {
+        if let Some(pending) = self.pending.as_mut() {
+            pending.usage = Some(usage);
+        }
+    }
+
+    /// Ends the request in flight, returning its timing.
+    pub fn response_ended(&mut self, request_id: Option<String>) -> Option<RequestTiming> {
+        let pending = self.pending.take()?;
+        let timing = RequestTiming {
+            request_id,
+            time_to_first_chunk: pending.first_chunk.map(|t| t.duration_since(pending.sent)),
+            stream_duration: pending.sent.elapsed(),
+            usage: pending.usage,
+        };
+        self.current.get_or_insert_default().requests.push(timing.clone());
+        Some(timing)
+    }
+
+    pub fn tool_executed(&mut self, name: impl Into<String>, duration: Duration) {
+        self.current.get_or_insert_default().tools.push(ToolTiming {
+            name: name.into(),
+            duration,
+        });
+    }
+
+    /// Ends the current turn, returning its timing if it made any request.
+    pub fn finish_turn(&mut self) -> Option<&TurnTiming> {
+        let turn = self.current.take().filter(|turn| !turn.requests.is_empty())?;
+        if self.turns.len() == MAX_TURNS {
+            self.turns.pop_front();
+        }
+        self.turns.push_back(turn);
+        self.turns.back()
+    }
+
+    /// The finished turns, oldest first.
+    pub fn turns(&self) -> impl Iterator<Item = &TurnTiming> {
+        self.turns.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_timings() {
+        let mut timings = SessionTimings::default();
+        timings.start_turn();
+        timings.request_sent();
+        timings.chunk_received();
+        let request = timings.response_ended(Some("1".to_string())).unwrap();
+        assert!(request.time_to_first_chunk.unwrap() <= request.stream_duration);
+        timings.tool_executed("fs_read", Duration::from_millis(500));
+        timings.request_sent();
+        timings.usage_received(TokenUsage {
+            input_tokens: 12_000,
+            cached_input_tokens: 11_000,
+            cache_write_tokens: 0,
+            output_tokens: 300,
+        });
+        timings.response_ended(Some("2".to_string()));
+
+        let turn = timings.finish_turn().unwrap().clone();
+        assert_eq!(turn.requests.len(), 2);
+        assert_eq!(turn.tool_duration(), Duration::from_millis(500));
+        assert_eq!(turn.usage().unwrap().cached_input_tokens, 11_000);
+        assert!(
+            turn.summary()
+                .contains("(2 requests) · tools 0.50s (1) · cached 92% of 12.0k input tokens")
+        );
+
+        // Nothing was sent.
+        timings.start_turn();
+        assert!(timings.finish_turn().is_none());
+        assert_eq!(timings.turns().count(), 1);
+    }
+
+    #[test]
+    fn test_session_timings_keeps_recent_turns() {
+        let mut timings = SessionTimings::default();
+        for i in 0..MAX_TURNS + 5 {
+            timings.start_turn();
+            timings.request_sent();
+            timings.response_ended(Some(i.to_string()));
+            timings.finish_turn();
+        }
+        assert_eq!(timings.turns().count(), MAX_TURNS);
+        let first = timings.turns().next().unwrap();
+        assert_eq!(first.requests[0].request_id.as_deref(), Some("5"));
+    }
+}
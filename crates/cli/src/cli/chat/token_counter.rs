@@ -1,4 +1,10 @@
 use std::ops::Deref;
+use std::sync::RwLock;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
 
 use super::conversation_state::{
     BackendConversationState,
@@ -72,25 +78,70 @@ impl std::fmt::Display for TokenCount {
     }
 }
 
+/// How text is split into tokens, which depends on the model family.
+///
+/// The BPE encodings are only available with the `bpe-tokenizer` feature, without which they fall
+/// back to the heuristic.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tokenizer {
+    /// Content length / [TokenCounter::TOKEN_TO_CHAR_RATIO], which overestimates code by up to 65%.
+    #[default]
+    Heuristic,
+    /// The encoding of GPT-4 and GPT-3.5, and the closest one available for other families.
+    Cl100k,
+    /// The encoding of GPT-4o, GPT-4.1 and the o-series models.
+    O200k,
+}
+
+impl Tokenizer {
+    /// Counts the tokens of `content` exactly, or estimates them with the heuristic.
+    pub fn count(self, content: &str) -> usize {
+        match self {
+            #[cfg(feature = "bpe-tokenizer")]
+            Tokenizer::Cl100k => tiktoken_rs::cl100k_base_singleton().encode_ordinary(content).len(),
+            #[cfg(feature = "bpe-tokenizer")]
+            Tokenizer::O200k => tiktoken_rs::o200k_base_singleton().encode_ordinary(content).len(),
+            _ => content.len() / TokenCounter::TOKEN_TO_CHAR_RATIO,
+        }
+    }
+}
+
+/// The tokenizer of the model in use, set once the session knows which model it talks to.
+static TOKENIZER: RwLock<Tokenizer> = RwLock::new(Tokenizer::Heuristic);
+
 pub struct TokenCounter;
 
 impl TokenCounter {
     pub const TOKEN_TO_CHAR_RATIO: usize = 3;
 
-    /// Estimates the number of tokens in the input content.
-    /// Currently uses a simple heuristic: content length / TOKEN_TO_CHAR_RATIO
+    /// Counts tokens with `tokenizer` from now on, the one of the model in use.
+    pub fn set_tokenizer(tokenizer: Tokenizer) {
+        *TOKENIZER.write().unwrap_or_else(|err| err.into_inner()) = tokenizer;
+    }
+
+    pub fn tokenizer() -> Tokenizer {
+        *TOKENIZER.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Counts the tokens in the input content with the tokenizer of the model in use.
     ///
     /// Rounds up to the nearest multiple of 10 to avoid giving users a false sense of precision.
     pub fn count_tokens(content: &str) -> usize {
-        Self::count_tokens_char_count(content.len())
+        Self::round(Self::count_tokens_exact(content))
+    }
+
+    /// Counts the tokens in the input content without rounding, for sums over many messages.
+    pub fn count_tokens_exact(content: &str) -> usize {
+        Self::tokenizer().count(content)
     }
 
     fn count_tokens_char_count(count: usize) -> usize {
-        (count / Self::TOKEN_TO_CHAR_RATIO + 5) / 10 * 10
+        Self::round(count / Self::TOKEN_TO_CHAR_RATIO)
     }
 
-    pub const fn token_to_chars(token: usize) -> usize {
-        token * Self::TOKEN_TO_CHAR_RATIO
+    fn round(tokens: usize) -> usize {
+        (tokens + 5) / 10 * 10
     }
 }
 
@@ -167,6 +218,78 @@ impl CharCounter for &[ToolUseResult] {
     }
 }
 
+/// Like [CharCounter], for the number of tokens as counted by the tokenizer of the model in use.
+/// Used to decide when the conversation no longer fits in the context window.
+pub trait TokenCounted {
+    fn token_count(&self) -> TokenCount;
+}
+
+impl TokenCounted for BackendConversationState<'_> {
+    fn token_count(&self) -> TokenCount {
+        let history = self
+            .history
+            .clone()
+            .map(|(user, assistant)| *user.token_count() + *assistant.token_count())
+            .sum::<usize>();
+        let context = self.context_messages.as_ref().map_or(0, |messages| {
+            messages
+                .iter()
+                .map(|(user, assistant)| *user.token_count() + *assistant.token_count())
+                .sum()
+        });
+        TokenCount(history + context)
+    }
+}
+
+impl TokenCounted for UserMessage {
+    fn token_count(&self) -> TokenCount {
+        let mut total_tokens = TokenCounter::count_tokens_exact(self.additional_context());
+        match self.content() {
+            UserMessageContent::Prompt { prompt } => {
+                total_tokens += TokenCounter::count_tokens_exact(prompt);
+            },
+            UserMessageContent::CancelledToolUses {
+                prompt,
+                tool_use_results,
+            } => {
+                total_tokens += prompt.as_deref().map_or(0, TokenCounter::count_tokens_exact);
+                total_tokens += tool_use_results.as_slice().token_count().0;
+            },
+            UserMessageContent::ToolUseResults { tool_use_results } => {
+                total_tokens += tool_use_results.as_slice().token_count().0;
+            },
+        }
+        TokenCount(total_tokens)
+    }
+}
+
+impl TokenCounted for AssistantMessage {
+    fn token_count(&self) -> TokenCount {
+        let mut total_tokens = TokenCounter::count_tokens_exact(self.content());
+        if let Some(tool_uses) = self.tool_uses() {
+            total_tokens += tool_uses
+                .iter()
+                .map(|v| TokenCounter::count_tokens_exact(&v.args.to_string()))
+                .sum::<usize>();
+        }
+        TokenCount(total_tokens)
+    }
+}
+
+impl TokenCounted for &[ToolUseResult] {
+    fn token_count(&self) -> TokenCount {
+        TokenCount(
+            self.iter()
+                .flat_map(|v| &v.content)
+                .map(|v| match v {
+                    ToolUseResultBlock::Json(v) => TokenCounter::count_tokens_exact(&v.to_string()),
+                    ToolUseResultBlock::Text(s) => TokenCounter::count_tokens_exact(s),
+                })
+                .sum(),
+        )
+    }
+}
+
 fn calculate_value_char_count(document: &serde_json::Value) -> usize {
     match document {
         serde_json::Value::Null => 1,
@@ -190,6 +313,22 @@ mod tests {
         assert_eq!(count, (text.len() / 3 + 5) / 10 * 10);
     }
 
+    #[test]
+    fn test_tokenizer_count() {
+        let code = "fn main() {\n    println!(\"{:?}\", std::env::args().collect::<Vec<_>>());\n}\n";
+        assert_eq!(Tokenizer::Heuristic.count(code), code.len() / 3);
+        for tokenizer in [Tokenizer::Cl100k, Tokenizer::O200k] {
+            let count = tokenizer.count(code);
+            if cfg!(feature = "bpe-tokenizer") {
+                // The heuristic counts every space of the indentation.
+                assert_eq!(count, 22, "{tokenizer:?}");
+            } else {
+                assert_eq!(count, code.len() / 3);
+            }
+        }
+        assert_eq!(Tokenizer::Cl100k.count(""), 0);
+    }
+
     #[test]
     fn test_calculate_value_char_count() {
         // Test simple types
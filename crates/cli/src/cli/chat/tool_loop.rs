@@ -0,0 +1,127 @@
+//! Detection of a model stuck calling the same tool with the same arguments, e.g. retrying a
+//! failing command, so that it is stopped instead of using up the quota.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{
+    Hash,
+    Hasher,
+};
+
+use crate::database::Database;
+use crate::database::settings::Setting;
+
+/// Counts the consecutive calls with the same tool and arguments.
+#[derive(Debug, Clone, Default)]
+pub struct LoopDetector {
+    /// Number of identical consecutive calls from which the model is looping, [None] to disable.
+    threshold: Option<usize>,
+    /// Hash of the tool and arguments of the last call.
+    last: Option<u64>,
+    repeats: usize,
+    /// Whether the model was told to stop repeating the last call.
+    intervened: bool,
+}
+
+impl LoopDetector {
+    pub fn new(threshold: Option<usize>) -> Self {
+        Self {
+            threshold,
+            ..Default::default()
+        }
+    }
+
+    /// Reads the threshold from `chat.toolLoopThreshold`, where 0 disables the detection.
+    pub fn from_database(database: &Database) -> Self {
+        Self::new(
+            database
+                .settings
+                .get_int_or_default(Setting::ChatToolLoopThreshold)
+                .filter(|threshold| *threshold > 0)
+                .map(|threshold| threshold as usize),
+        )
+    }
+
+    /// Records a call, returning the number of identical consecutive calls once it reaches the
+    /// threshold.
+    pub fn observe(&mut self, tool: &str, args: &serde_json::Value) -> Option<usize> {
+        let mut hasher = DefaultHasher::new();
+        tool.hash(&mut hasher);
+        args.to_string().hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if self.last == Some(hash) {
+            self.repeats += 1;
+        } else {
+            self.last = Some(hash);
+            self.repeats = 1;
+            self.intervened = false;
+        }
+        self.threshold
+            .filter(|threshold| self.repeats >= *threshold)
+            .map(|_| self.repeats)
+    }
+
+    /// Starts counting again, once the user lets the model go on.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.repeats = 0;
+        self.intervened = false;
+    }
+
+    /// Records that the model is told to stop repeating the last call, returning whether it already
+    /// was and made the call again anyway.
+    pub fn intervene(&mut self) -> bool {
+        std::mem::replace(&mut self.intervened, true)
+    }
+}
+
+/// The result sent to the model in place of the calls that repeat.
+pub fn intervention(tool: &str, repeats: usize) -> String {
+    format!(
+        "The {tool} call was not run: you made the same call with the same arguments {repeats} times in a \
+         row. Stop repeating it. Explain what is going wrong, then try a different approach or ask the \
+         user for help."
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_observe() {
+        let mut detector = LoopDetector::new(Some(3));
+        let args = json!({ "command": "cargo test" });
+        assert_eq!(detector.observe("execute_bash", &args), None);
+        assert_eq!(detector.observe("execute_bash", &args), None);
+        assert_eq!(detector.observe("execute_bash", &args), Some(3));
+        assert_eq!(detector.observe("execute_bash", &args), Some(4));
+
+        // Other arguments or another tool break the sequence.
+        assert_eq!(
+            detector.observe("execute_bash", &json!({ "command": "cargo build" })),
+            None
+        );
+        assert_eq!(detector.observe("fs_read", &args), None);
+        assert_eq!(detector.observe("execute_bash", &args), None);
+
+        detector.reset();
+        assert_eq!(detector.observe("execute_bash", &args), None);
+
+        // Only repeating the call after being told to stop counts as ignoring the intervention.
+        detector.observe("execute_bash", &args);
+        detector.observe("execute_bash", &args);
+        assert!(!detector.intervene());
+        assert_eq!(detector.observe("execute_bash", &args), Some(4));
+        assert!(detector.intervene());
+        detector.observe("fs_read", &args);
+        assert!(!detector.intervene());
+
+        let mut disabled = LoopDetector::new(None);
+        for _ in 0..10 {
+            assert_eq!(disabled.observe("execute_bash", &args), None);
+        }
+    }
+}
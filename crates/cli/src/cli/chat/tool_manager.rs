@@ -71,18 +71,33 @@ use crate::cli::chat::server_messenger::{
     ServerMessengerBuilder,
     UpdateEventMessage,
 };
+use crate::cli::chat::tools::code_symbols::CodeSymbols;
 use crate::cli::chat::tools::custom_tool::{
     CustomTool,
     CustomToolClient,
     CustomToolConfig,
 };
+use crate::cli::chat::tools::download_file::DownloadFile;
+use crate::cli::chat::tools::env_policy::EnvPolicy;
 use crate::cli::chat::tools::execute_bash::ExecuteBash;
 use crate::cli::chat::tools::fs_read::FsRead;
 use crate::cli::chat::tools::fs_write::FsWrite;
 use crate::cli::chat::tools::gh_issue::GhIssue;
+use crate::cli::chat::tools::iac_plan::IacPlan;
+use crate::cli::chat::tools::plugin_tool::{
+    PluginManifest,
+    PluginTool,
+};
+use crate::cli::chat::tools::refactor_rename::RefactorRename;
+use crate::cli::chat::tools::remote::ExecutionTarget;
+use crate::cli::chat::tools::shell::{
+    Shell,
+    ShellKind,
+};
 use crate::cli::chat::tools::thinking::Thinking;
 use crate::cli::chat::tools::use_aws::UseAws;
 use crate::cli::chat::tools::web_browse::WebBrowse;
+use crate::cli::chat::tools::web_crawl::WebCrawl;
 use crate::cli::chat::tools::{
     Tool,
     ToolOrigin,
@@ -94,6 +109,8 @@ use crate::mcp_client::{
     JsonRpcResponse,
     Messenger,
     PromptGet,
+    Roots,
+    Sampler,
 };
 use crate::platform::Context;
 use crate::telemetry::TelemetryThread;
@@ -104,6 +121,18 @@ const NAMESPACE_DELIMITER: &str = "___";
 // model is just {server_name}{NAMESPACE_DELIMITER}{tool_name}
 const VALID_TOOL_NAME: &str = "^[a-zA-Z][a-zA-Z0-9_]*$";
 const SPINNER_CHARS: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+/// Native tools that need network access, unavailable in offline mode.
+pub const NETWORK_TOOLS: [&str; 6] = [
+    "use_aws",
+    "web_browse",
+    "web_crawl",
+    "download_file",
+    "iac_plan",
+    "report_issue",
+];
+/// Native tools that change files, unavailable in read-only mode. execute_bash and use_aws are
+/// kept, limited to the commands and operations that only read.
+pub const MUTATING_TOOLS: [&str; 3] = ["fs_write", "refactor_rename", "download_file"];
 
 pub fn workspace_mcp_config_path(ctx: &Context) -> eyre::Result<PathBuf> {
     Ok(ctx.env().current_dir()?.join(".amazonq").join("mcp.json"))
@@ -177,38 +206,72 @@ pub struct McpServerConfig {
 }
 
 impl McpServerConfig {
-    pub async fn load_config(output: &mut impl Write) -> eyre::Result<Self> {
-        let mut cwd = std::env::current_dir()?;
+    /// Loads the servers of the global config, then of the workspace config, then of the files of
+    /// `--mcp-config`, each server replacing the one of the same name loaded before. The scopes of
+    /// `mcp.disabledScopes` are left out, and so are the servers marked `disabled`.
+    pub async fn load_config(
+        ctx: &Context,
+        output: &mut impl Write,
+        database: &Database,
+        extra_files: &[PathBuf],
+    ) -> eyre::Result<Self> {
+        let disabled_scopes = database
+            .settings
+            .get_string_list(Setting::McpDisabledScopes)
+            .unwrap_or_default();
+        let mut cwd = ctx.env().current_dir()?;
         cwd.push(".amazonq/mcp.json");
         let expanded_path = shellexpand::tilde("~/.aws/amazonq/mcp.json");
         let global_path = PathBuf::from(expanded_path.as_ref());
-        let global_buf = tokio::fs::read(global_path).await.ok();
-        let local_buf = tokio::fs::read(cwd).await.ok();
-        let conf = match (global_buf, local_buf) {
-            (Some(global_buf), Some(local_buf)) => {
-                let mut global_conf = Self::from_slice(&global_buf, output, "global")?;
-                let local_conf = Self::from_slice(&local_buf, output, "local")?;
-                for (server_name, config) in local_conf.mcp_servers {
-                    if global_conf.mcp_servers.insert(server_name.clone(), config).is_some() {
-                        queue!(
-                            output,
-                            style::SetForegroundColor(style::Color::Yellow),
-                            style::Print("WARNING: "),
-                            style::ResetColor,
-                            style::Print("MCP config conflict for "),
-                            style::SetForegroundColor(style::Color::Green),
-                            style::Print(server_name),
-                            style::ResetColor,
-                            style::Print(". Using workspace version.\n")
-                        )?;
-                    }
+
+        let mut layers = [("global", global_path), ("workspace", cwd)]
+            .into_iter()
+            .filter(|(scope, _)| !disabled_scopes.iter().any(|disabled| disabled == scope))
+            .map(|(scope, path)| ConfigLayer {
+                location: scope.to_string(),
+                path,
+                required: false,
+            })
+            .collect::<Vec<_>>();
+        layers.extend(extra_files.iter().map(|path| ConfigLayer {
+            location: path.display().to_string(),
+            path: path.clone(),
+            required: true,
+        }));
+        Self::load_layers(output, layers).await
+    }
+
+    async fn load_layers(output: &mut impl Write, layers: Vec<ConfigLayer>) -> eyre::Result<Self> {
+        let mut conf = Self::default();
+        for layer in layers {
+            let buf = match tokio::fs::read(&layer.path).await {
+                Ok(buf) => buf,
+                Err(err) if layer.required => {
+                    return Err(eyre::eyre!(
+                        "Failed to read the MCP config {}: {err}",
+                        layer.path.display()
+                    ));
+                },
+                Err(_) => continue,
+            };
+            for (server_name, config) in Self::from_slice(&buf, output, &layer.location)?.mcp_servers {
+                let disabled = config.disabled;
+                if conf.mcp_servers.insert(server_name.clone(), config).is_some() && !disabled {
+                    queue!(
+                        output,
+                        style::SetForegroundColor(style::Color::Yellow),
+                        style::Print("WARNING: "),
+                        style::ResetColor,
+                        style::Print("MCP config conflict for "),
+                        style::SetForegroundColor(style::Color::Green),
+                        style::Print(server_name),
+                        style::ResetColor,
+                        style::Print(format!(". Using {} version.\n", layer.location))
+                    )?;
                 }
-                global_conf
-            },
-            (None, Some(local_buf)) => Self::from_slice(&local_buf, output, "local")?,
-            (Some(global_buf), None) => Self::from_slice(&global_buf, output, "global")?,
-            _ => Default::default(),
-        };
+            }
+        }
+        conf.mcp_servers.retain(|_, config| !config.disabled);
         output.flush()?;
         Ok(conf)
     }
@@ -242,6 +305,14 @@ impl McpServerConfig {
     }
 }
 
+/// A config file of [McpServerConfig::load_config], `location` naming it in warnings.
+struct ConfigLayer {
+    location: String,
+    path: PathBuf,
+    /// Whether a missing file is an error, for the files given on the command line.
+    required: bool,
+}
+
 #[derive(Default)]
 pub struct ToolManagerBuilder {
     mcp_server_config: Option<McpServerConfig>,
@@ -249,6 +320,14 @@ pub struct ToolManagerBuilder {
     prompt_list_receiver: Option<std::sync::mpsc::Receiver<Option<String>>>,
     conversation_id: Option<String>,
     is_interactive: bool,
+    offline: bool,
+    read_only: bool,
+    env_policy: EnvPolicy,
+    plugins: Vec<PluginManifest>,
+    sampler: Option<Arc<dyn Sampler>>,
+    roots: Option<Roots>,
+    workspace_roots: Vec<PathBuf>,
+    execution_target: ExecutionTarget,
 }
 
 impl ToolManagerBuilder {
@@ -277,12 +356,65 @@ impl ToolManagerBuilder {
         self
     }
 
+    /// Leaves out the native tools that need network access, see [NETWORK_TOOLS].
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Leaves out the tools that can change anything: the ones in [MUTATING_TOOLS], MCP servers
+    /// and plugins.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Which variables of the environment the MCP servers see.
+    pub fn env_policy(mut self, env_policy: EnvPolicy) -> Self {
+        self.env_policy = env_policy;
+        self
+    }
+
+    pub fn plugins(mut self, plugins: Vec<PluginManifest>) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    /// Answers the sampling requests of the MCP servers, which can't sample without one.
+    pub fn sampler(mut self, sampler: Option<Arc<dyn Sampler>>) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// The directories the MCP servers are told to work in.
+    pub fn roots(mut self, roots: Roots) -> Self {
+        self.roots.replace(roots);
+        self
+    }
+
+    /// The roots given with `--workspace`, the MCP servers running in the first one.
+    pub fn workspace_roots(mut self, roots: &[PathBuf]) -> Self {
+        self.workspace_roots = roots.to_vec();
+        self
+    }
+
+    /// Where the native tools run, told to the model when it isn't this machine.
+    pub fn execution_target(mut self, target: &ExecutionTarget) -> Self {
+        self.execution_target = target.clone();
+        self
+    }
+
     pub async fn build(
         mut self,
         telemetry: &TelemetryThread,
         mut output: Box<dyn Write + Send + Sync + 'static>,
     ) -> eyre::Result<ToolManager> {
-        let McpServerConfig { mcp_servers } = self.mcp_server_config.ok_or(eyre::eyre!("Missing mcp server config"))?;
+        let McpServerConfig { mut mcp_servers } =
+            self.mcp_server_config.ok_or(eyre::eyre!("Missing mcp server config"))?;
+        if self.read_only {
+            mcp_servers.clear();
+            self.plugins.clear();
+        }
         debug_assert!(self.conversation_id.is_some());
         let conversation_id = self.conversation_id.ok_or(eyre::eyre!("Missing conversation id"))?;
         let regex = regex::Regex::new(VALID_TOOL_NAME)?;
@@ -293,7 +425,12 @@ impl ToolManagerBuilder {
             .map(|(server_name, server_config)| {
                 let snaked_cased_name = server_name.to_case(convert_case::Case::Snake);
                 let sanitized_server_name = sanitize_name(snaked_cased_name, &regex, &mut hasher);
-                let custom_tool_client = CustomToolClient::from_config(sanitized_server_name.clone(), server_config);
+                let custom_tool_client = CustomToolClient::from_config(
+                    sanitized_server_name.clone(),
+                    server_config,
+                    &self.env_policy,
+                    self.workspace_roots.first().map(PathBuf::as_path),
+                );
                 (sanitized_server_name, custom_tool_client)
             })
             .collect::<Vec<(String, _)>>();
@@ -570,6 +707,12 @@ impl ToolManagerBuilder {
             match init_res {
                 Ok(mut client) => {
                     client.assign_messenger(Box::new(messenger));
+                    if let Some(sampler) = &self.sampler {
+                        client.assign_sampler(sampler.clone());
+                    }
+                    if let Some(roots) = &self.roots {
+                        client.assign_roots(roots.clone());
+                    }
                     let mut client = Arc::new(client);
                     while let Some(collided_client) = clients.insert(name.clone(), client) {
                         // to avoid server name collision we are going to circumvent this by
@@ -687,7 +830,16 @@ impl ToolManagerBuilder {
             new_tool_specs,
             has_new_stuff,
             is_interactive,
+            offline: self.offline,
+            read_only: self.read_only,
+            plugins: self
+                .plugins
+                .into_iter()
+                .map(|plugin| (plugin.name.clone(), Arc::new(plugin)))
+                .collect(),
             mcp_load_record: load_record,
+            workspace_roots: self.workspace_roots,
+            execution_target: self.execution_target,
             ..Default::default()
         })
     }
@@ -771,12 +923,27 @@ pub struct ToolManager {
 
     is_interactive: bool,
 
+    /// Whether the native tools that need network access are left out.
+    offline: bool,
+
+    /// Whether the tools that can change files or resources are left out.
+    read_only: bool,
+
+    /// Tools declared by plugin manifests, by name.
+    pub plugins: HashMap<String, Arc<PluginManifest>>,
+
     /// This serves as a record of the loading of mcp servers.
     /// The key of which is the server name as they are recognized by the current instance of chat
     /// (which may be different than how it is written in the config, depending of the presence of
     /// invalid characters).
     /// The value is the load message (i.e. load time, warnings, and errors)
     pub mcp_load_record: Arc<Mutex<HashMap<String, Vec<LoadingRecord>>>>,
+
+    /// The roots given with `--workspace`, told to the model when there are several.
+    workspace_roots: Vec<PathBuf>,
+
+    /// Where the native tools run, see [ExecutionTarget].
+    execution_target: ExecutionTarget,
 }
 
 impl Clone for ToolManager {
@@ -790,7 +957,12 @@ impl Clone for ToolManager {
             tn_map: self.tn_map.clone(),
             schema: self.schema.clone(),
             is_interactive: self.is_interactive,
+            offline: self.offline,
+            read_only: self.read_only,
+            plugins: self.plugins.clone(),
             mcp_load_record: self.mcp_load_record.clone(),
+            workspace_roots: self.workspace_roots.clone(),
+            execution_target: self.execution_target.clone(),
             ..Default::default()
         }
     }
@@ -810,6 +982,82 @@ impl ToolManager {
             if !crate::cli::chat::tools::thinking::Thinking::is_enabled(database) {
                 tool_specs.remove("thinking");
             }
+            if !WebBrowse::is_headless_browser_enabled(database) {
+                if let Some(properties) = tool_specs
+                    .get_mut("web_browse")
+                    .and_then(|spec| spec.input_schema.0.get_mut("properties"))
+                    .and_then(|properties| properties.as_object_mut())
+                {
+                    properties.remove("render_javascript");
+                    properties.remove("screenshot");
+                }
+            }
+            let shell = Shell::from_database(database);
+            if shell.kind != ShellKind::Bash {
+                if let Some(spec) = tool_specs.get_mut("execute_bash") {
+                    spec.description
+                        .push_str(&format!(" Commands run in {}, use its syntax.", shell.kind));
+                }
+            }
+            if let Some(description) = self.execution_target.description() {
+                for name in ["fs_read", "fs_write", "execute_bash"] {
+                    if let Some(spec) = tool_specs.get_mut(name) {
+                        spec.description.push(' ');
+                        spec.description.push_str(&description);
+                    }
+                }
+            }
+            if self.workspace_roots.len() > 1 {
+                let roots = self
+                    .workspace_roots
+                    .iter()
+                    .map(|root| root.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                for name in ["fs_read", "fs_write", "execute_bash"] {
+                    if let Some(spec) = tool_specs.get_mut(name) {
+                        spec.description.push_str(&format!(
+                            " The workspace has several roots: {roots}. Paths outside them need the approval of the user."
+                        ));
+                    }
+                }
+            }
+            if self.offline {
+                tool_specs.retain(|name, _| !NETWORK_TOOLS.contains(&name.as_str()));
+            }
+            if self.read_only {
+                tool_specs.retain(|name, _| !MUTATING_TOOLS.contains(&name.as_str()));
+                for (name, limit) in [
+                    ("execute_bash", "only commands that don't change anything"),
+                    ("use_aws", "only operations that don't change any resource"),
+                ] {
+                    if let Some(spec) = tool_specs.get_mut(name) {
+                        spec.description
+                            .push_str(&format!(" The session is read-only: {limit} are allowed."));
+                    }
+                }
+            }
+            let mut plugins = std::mem::take(&mut self.plugins);
+            plugins.retain(|name, plugin| {
+                if tool_specs.contains_key(name) {
+                    let _ = queue!(
+                        output,
+                        style::SetForegroundColor(style::Color::Yellow),
+                        style::Print("WARNING: "),
+                        style::ResetColor,
+                        style::Print(format!(
+                            "Plugin {} has the name of a built-in tool. Discarding.\n",
+                            plugin.path.display()
+                        )),
+                    );
+                    return false;
+                }
+                true
+            });
+            for plugin in plugins.values() {
+                tool_specs.insert(plugin.name.clone(), plugin.tool_spec());
+            }
+            self.plugins = plugins;
             tool_specs
         };
         let load_tools = self
@@ -828,7 +1076,7 @@ impl ToolManager {
         });
         // We need to cast it to erase the type otherwise the compiler will default to static
         // dispatch, which would result in an error of inconsistent match arm return type.
-        let timeout_fut: Pin<Box<dyn Future<Output = ()>>> = if self.clients.is_empty() {
+        let timeout_fut: Pin<Box<dyn Future<Output = ()> + Send>> = if self.clients.is_empty() {
             // If there is no server loaded, we want to resolve immediately
             Box::pin(future::ready(()))
         } else if self.is_interactive {
@@ -845,7 +1093,7 @@ impl ToolManager {
                 .map_or(30_000_u64, |s| s as u64);
             Box::pin(tokio::time::sleep(std::time::Duration::from_millis(init_timeout)))
         };
-        let server_loading_fut: Pin<Box<dyn Future<Output = ()>>> = if let Some(notify) = notify {
+        let server_loading_fut: Pin<Box<dyn Future<Output = ()> + Send>> = if let Some(notify) = notify {
             Box::pin(async move { notify.notified().await })
         } else {
             Box::pin(future::ready(()))
@@ -912,7 +1160,18 @@ impl ToolManager {
             status: ToolResultStatus::Error,
         };
 
-        Ok(match value.name.as_str() {
+        if self.offline && NETWORK_TOOLS.contains(&value.name.as_str()) {
+            return Err(ToolResult {
+                tool_use_id: value.id,
+                content: vec![ToolResultContentBlock::Text(format!(
+                    "The tool {} is not available in offline mode since it requires network access.",
+                    value.name
+                ))],
+                status: ToolResultStatus::Error,
+            });
+        }
+
+        let tool = match value.name.as_str() {
             "fs_read" => Tool::FsRead(serde_json::from_value::<FsRead>(value.args).map_err(map_err)?),
             "fs_write" => Tool::FsWrite(serde_json::from_value::<FsWrite>(value.args).map_err(map_err)?),
             "execute_bash" => Tool::ExecuteBash(serde_json::from_value::<ExecuteBash>(value.args).map_err(map_err)?),
@@ -920,6 +1179,17 @@ impl ToolManager {
             "report_issue" => Tool::GhIssue(serde_json::from_value::<GhIssue>(value.args).map_err(map_err)?),
             "thinking" => Tool::Thinking(serde_json::from_value::<Thinking>(value.args).map_err(map_err)?),
             "web_browse" => Tool::WebBrowse(serde_json::from_value::<WebBrowse>(value.args).map_err(map_err)?),
+            "web_crawl" => Tool::WebCrawl(serde_json::from_value::<WebCrawl>(value.args).map_err(map_err)?),
+            "code_symbols" => Tool::CodeSymbols(serde_json::from_value::<CodeSymbols>(value.args).map_err(map_err)?),
+            "refactor_rename" => {
+                Tool::RefactorRename(serde_json::from_value::<RefactorRename>(value.args).map_err(map_err)?)
+            },
+            "iac_plan" => Tool::IacPlan(serde_json::from_value::<IacPlan>(value.args).map_err(map_err)?),
+            "download_file" => Tool::DownloadFile(serde_json::from_value::<DownloadFile>(value.args).map_err(map_err)?),
+            name if self.plugins.contains_key(name) => Tool::Plugin(PluginTool {
+                manifest: Arc::clone(&self.plugins[name]),
+                input: value.args,
+            }),
             // Note that this name is namespaced with server_name{DELIMITER}tool_name
             name => {
                 // Note: tn_map also has tools that underwent no transformation. In otherwords, if
@@ -997,7 +1267,19 @@ impl ToolManager {
                 };
                 Tool::Custom(custom_tool)
             },
-        })
+        };
+
+        if self.read_only && tool.mutates() {
+            return Err(ToolResult {
+                tool_use_id: value.id,
+                content: vec![ToolResultContentBlock::Text(format!(
+                    "The session is read-only, so {} can't be used to change anything.",
+                    value.name
+                ))],
+                status: ToolResultStatus::Error,
+            });
+        }
+        Ok(tool)
     }
 
     /// Updates tool managers various states with new information
@@ -1486,4 +1768,109 @@ mod tests {
         let sanitized = sanitize_name(with_delim, &regex, &mut hasher);
         assert_eq!(sanitized, "abc");
     }
+
+    #[tokio::test]
+    async fn test_load_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, json: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, json).unwrap();
+            path
+        };
+        let global = write(
+            "global.json",
+            r#"{"mcpServers": {"git": {"command": "git-mcp"}, "fetch": {"command": "fetch-mcp"}}}"#,
+        );
+        let workspace = write(
+            "workspace.json",
+            r#"{"mcpServers": {"git": {"command": "./git-mcp"}, "fetch": {"disabled": true}}}"#,
+        );
+        let layer = |location: &str, path: PathBuf, required: bool| ConfigLayer {
+            location: location.to_string(),
+            path,
+            required,
+        };
+
+        let mut output = Vec::new();
+        let config = McpServerConfig::load_layers(&mut output, vec![
+            layer("global", global, false),
+            layer("workspace", workspace, false),
+            layer("missing", dir.path().join("none.json"), false),
+        ])
+        .await
+        .unwrap();
+        assert_eq!(config.mcp_servers.len(), 1);
+        assert_eq!(config.mcp_servers["git"].command, "./git-mcp");
+        let warnings = String::from_utf8(output).unwrap();
+        assert!(warnings.contains("Using workspace version"));
+        assert!(!warnings.contains("fetch"));
+
+        let result = McpServerConfig::load_layers(&mut Vec::new(), vec![layer(
+            "none.json",
+            dir.path().join("none.json"),
+            true,
+        )])
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_offline_rejects_network_tools() {
+        let tool_manager = ToolManager {
+            offline: true,
+            ..Default::default()
+        };
+        let tool_use = |name: &str, args| AssistantToolUse {
+            id: "1".to_string(),
+            name: name.to_string(),
+            args,
+            ..Default::default()
+        };
+
+        let result = tool_manager
+            .get_tool_from_tool_use(tool_use(
+                "web_browse",
+                serde_json::json!({ "url": "https://example.com" }),
+            ))
+            .unwrap_err();
+        assert!(matches!(result.status, ToolResultStatus::Error));
+        assert!(
+            tool_manager
+                .get_tool_from_tool_use(tool_use("fs_read", serde_json::json!({ "mode": "Line", "path": "/a" })))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_read_only_rejects_mutating_tools() {
+        let tool_manager = ToolManager {
+            read_only: true,
+            ..Default::default()
+        };
+        let tool_use = |name: &str, args| AssistantToolUse {
+            id: "1".to_string(),
+            name: name.to_string(),
+            args,
+            ..Default::default()
+        };
+
+        let fs_write = serde_json::json!({ "command": "create", "path": "/a", "file_text": "a" });
+        assert!(
+            tool_manager
+                .get_tool_from_tool_use(tool_use("fs_write", fs_write))
+                .is_err()
+        );
+        let rm = serde_json::json!({ "command": "rm -rf src" });
+        assert!(
+            tool_manager
+                .get_tool_from_tool_use(tool_use("execute_bash", rm))
+                .is_err()
+        );
+        let ls = serde_json::json!({ "command": "ls -la" });
+        assert!(
+            tool_manager
+                .get_tool_from_tool_use(tool_use("execute_bash", ls))
+                .is_ok()
+        );
+    }
 }
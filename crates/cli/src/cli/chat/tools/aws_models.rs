@@ -0,0 +1,455 @@
+//! Checks of use_aws calls against the botocore service models the AWS CLI is built from, so that
+//! a misspelled operation or parameter fails validation with suggestions, rather than after a
+//! round trip through the AWS CLI.
+//!
+//! The models are looked up in `chat.useAws.serviceModelsPath`, `~/.aws/models` and the
+//! installation of the AWS CLI v2, which vendors them. Calls of services without a model are not
+//! checked, and neither are the commands the AWS CLI adds on top of the models.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::sync::{
+    Arc,
+    LazyLock,
+    Mutex,
+};
+
+use eyre::{
+    Result,
+    bail,
+};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use serde::de::IgnoredAny;
+use tracing::{
+    debug,
+    warn,
+};
+
+use super::aws_resources::rank;
+use crate::platform::Context;
+
+/// The services of the AWS CLI whose models are named differently.
+const SERVICE_ALIASES: [(&str, &str); 3] = [("s3api", "s3"), ("configservice", "config"), ("deploy", "codedeploy")];
+
+/// The services of the AWS CLI that have no model of their own, `s3` being the high level
+/// commands over the `s3api` model.
+const CLI_SERVICES: [&str; 5] = ["s3", "ddb", "configure", "history", "cli-dev"];
+
+/// The commands the AWS CLI adds to every service.
+const CLI_OPERATIONS: [&str; 2] = ["wait", "help"];
+
+/// The commands the AWS CLI adds to some services.
+const CUSTOM_OPERATIONS: &[(&str, &str)] = &[
+    ("cloudformation", "deploy"),
+    ("cloudformation", "package"),
+    ("cloudtrail", "validate-logs"),
+    ("codeartifact", "login"),
+    ("codecommit", "credential-helper"),
+    ("configservice", "get-status"),
+    ("configservice", "subscribe"),
+    ("datapipeline", "create-default-roles"),
+    ("datapipeline", "list-runs"),
+    ("deploy", "deregister"),
+    ("deploy", "install"),
+    ("deploy", "push"),
+    ("deploy", "register"),
+    ("deploy", "uninstall"),
+    ("dlm", "create-default-role"),
+    ("ec2-instance-connect", "open-tunnel"),
+    ("ec2-instance-connect", "ssh"),
+    ("ecr", "get-login"),
+    ("ecr", "get-login-password"),
+    ("ecr-public", "get-login-password"),
+    ("ecs", "deploy"),
+    ("eks", "get-token"),
+    ("eks", "update-kubeconfig"),
+    ("emr", "create-cluster"),
+    ("emr", "create-default-roles"),
+    ("emr", "get"),
+    ("emr", "put"),
+    ("emr", "socks"),
+    ("emr", "ssh"),
+    ("gamelift", "get-game-session-log"),
+    ("gamelift", "upload-build"),
+    ("logs", "tail"),
+    ("rds", "generate-db-auth-token"),
+    ("servicecatalog", "generate"),
+    ("sso", "login"),
+    ("sso", "logout"),
+];
+
+/// The options the AWS CLI accepts for every operation.
+const CLI_PARAMETERS: [&str; 22] = [
+    "query",
+    "output",
+    "region",
+    "profile",
+    "endpoint-url",
+    "debug",
+    "no-verify-ssl",
+    "no-sign-request",
+    "ca-bundle",
+    "color",
+    "no-paginate",
+    "page-size",
+    "max-items",
+    "starting-token",
+    "cli-input-json",
+    "cli-input-yaml",
+    "generate-cli-skeleton",
+    "cli-binary-format",
+    "no-cli-pager",
+    "cli-auto-prompt",
+    "cli-read-timeout",
+    "cli-connect-timeout",
+];
+
+/// The parameters the AWS CLI adds to some operations.
+const CUSTOM_PARAMETERS: &[(&str, &str, &[&str])] = &[
+    ("ec2", "run-instances", &[
+        "count",
+        "secondary-private-ip-addresses",
+        "secondary-private-ip-address-count",
+        "associate-public-ip-address",
+    ]),
+    ("ec2", "bundle-instance", &[
+        "bucket",
+        "prefix",
+        "owner-akid",
+        "owner-sak",
+        "policy",
+    ]),
+    ("iam", "create-virtual-mfa-device", &["outfile", "bootstrap-method"]),
+    ("lambda", "create-function", &["zip-file"]),
+    ("lambda", "publish-layer-version", &["zip-file"]),
+];
+
+/// How many operations or parameters are suggested for a misspelled one.
+const SUGGESTIONS: usize = 3;
+
+/// The models loaded, by path.
+static MODELS: LazyLock<Mutex<HashMap<PathBuf, Arc<ServiceModel>>>> = LazyLock::new(Default::default);
+
+/// The parts of a `service-2.json` the calls are checked against.
+#[derive(Debug, Deserialize)]
+struct ServiceModel {
+    operations: HashMap<String, Operation>,
+    shapes: HashMap<String, Shape>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Operation {
+    input: Option<ShapeRef>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShapeRef {
+    shape: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Shape {
+    #[serde(default)]
+    #[allow(clippy::zero_sized_map_values)]
+    members: HashMap<String, IgnoredAny>,
+}
+
+/// Checks that `operation` of `service` exists and takes `parameters`, as named by the AWS CLI,
+/// if the model of the service is found in `models_path` or the default locations.
+pub async fn check_call(
+    ctx: &Context,
+    models_path: Option<&str>,
+    service: &str,
+    operation: &str,
+    parameters: &[String],
+) -> Result<()> {
+    if CLI_SERVICES.contains(&service)
+        || CLI_OPERATIONS.contains(&operation)
+        || CUSTOM_OPERATIONS.contains(&(service, operation))
+    {
+        return Ok(());
+    }
+    let model_name = SERVICE_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == service)
+        .map_or(service, |(_, name)| name);
+    let Some(path) = model_path(ctx, &model_dirs(ctx, models_path).await, model_name).await else {
+        debug!(service, "no service model found, the call is not checked");
+        return Ok(());
+    };
+    let model = match load(ctx, &path).await {
+        Ok(model) => model,
+        Err(err) => {
+            warn!(?path, ?err, "failed to load the service model");
+            return Ok(());
+        },
+    };
+
+    let Some((_, found)) = model
+        .operations
+        .iter()
+        .find(|(name, _)| normalize(name) == normalize(operation))
+    else {
+        let names = model.operations.keys().map(|name| cli_name(name)).collect();
+        match suggestions(operation, names, "") {
+            Some(names) => bail!("aws {service} has no operation {operation}, did you mean {names}?"),
+            None => bail!("aws {service} has no operation {operation}"),
+        }
+    };
+
+    let members = found
+        .input
+        .as_ref()
+        .and_then(|input| model.shapes.get(&input.shape))
+        .map(|shape| shape.members.keys().map(|name| cli_name(name)).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let custom = CUSTOM_PARAMETERS
+        .iter()
+        .find(|(custom_service, custom_operation, _)| (*custom_service, *custom_operation) == (service, operation))
+        .map_or(&[][..], |(_, _, parameters)| *parameters);
+    let known = |parameter: &str| {
+        CLI_PARAMETERS.contains(&parameter)
+            || custom.contains(&parameter)
+            || members.iter().any(|member| normalize(member) == normalize(parameter))
+    };
+    let names = members
+        .iter()
+        .cloned()
+        .chain(custom.iter().map(|name| (*name).to_string()))
+        .collect::<Vec<_>>();
+    let unknown = parameters
+        .iter()
+        .map(|parameter| parameter.trim_start_matches("--"))
+        // Booleans are turned off with --no-...
+        .filter(|parameter| !known(parameter) && !parameter.strip_prefix("no-").is_some_and(known))
+        .map(|parameter| match suggestions(parameter, names.clone(), "--") {
+            Some(names) => format!("--{parameter} (did you mean {names}?)"),
+            None => format!("--{parameter}"),
+        })
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        bail!(
+            "Unknown parameters of aws {service} {operation}: {}",
+            unknown.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// The names closest to `name`, each prefixed with `prefix`.
+fn suggestions(name: &str, names: Vec<String>, prefix: &str) -> Option<String> {
+    let names = rank(name, names)
+        .into_iter()
+        .take(SUGGESTIONS)
+        .map(|name| format!("{prefix}{name}"))
+        .collect::<Vec<_>>();
+    match names.as_slice() {
+        [] => None,
+        [name] => Some(name.clone()),
+        [rest @ .., last] => Some(format!("{} or {last}", rest.join(", "))),
+    }
+}
+
+/// The name of an operation or a parameter in the AWS CLI, e.g. `list-objects-v2` for
+/// `ListObjectsV2` and `db-instance-identifier` for `DBInstanceIdentifier`, like botocore's
+/// `xform_name`.
+fn cli_name(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut cli_name = String::with_capacity(name.len() + 4);
+    for (i, c) in chars.iter().enumerate() {
+        if i > 0 && c.is_ascii_uppercase() {
+            let previous = chars[i - 1];
+            let word_start = chars.get(i + 1).is_some_and(char::is_ascii_lowercase);
+            if previous.is_ascii_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_ascii_uppercase() && word_start)
+            {
+                cli_name.push('-');
+            }
+        }
+        cli_name.push(c.to_ascii_lowercase());
+    }
+    cli_name
+}
+
+/// Compares names in kebab, camel or pascal case, which the AWS CLI and the models split
+/// differently, e.g. `SSEKMSKeyId`.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// The directories holding a directory of models per service, in order of precedence.
+async fn model_dirs(ctx: &Context, models_path: Option<&str>) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(path) = models_path {
+        dirs.push(PathBuf::from(shellexpand::tilde(path).as_ref()));
+    }
+    if let Some(home) = ctx.env().home() {
+        dirs.push(home.join(".aws").join("models"));
+    }
+    // The AWS CLI v2 is installed with botocore next to its executable.
+    if let Some(paths) = ctx.env().get_os("PATH") {
+        let executable = format!("aws{}", std::env::consts::EXE_SUFFIX);
+        for dir in std::env::split_paths(&paths) {
+            let Ok(aws) = ctx.fs().canonicalize(dir.join(&executable)).await else {
+                continue;
+            };
+            if let Some(dist) = aws.parent() {
+                dirs.push(dist.join("awscli").join("botocore").join("data"));
+            }
+            break;
+        }
+    }
+    dirs
+}
+
+/// The model of the latest API version of `service` in the first of `dirs` that has one.
+async fn model_path(ctx: &Context, dirs: &[PathBuf], service: &str) -> Option<PathBuf> {
+    for dir in dirs {
+        let Ok(mut entries) = ctx.fs().read_dir(dir.join(service)).await else {
+            continue;
+        };
+        let mut versions = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            versions.push(entry.path());
+        }
+        // The versions are dates, e.g. 2006-03-01.
+        versions.sort();
+        for version in versions.iter().rev() {
+            for file in ["service-2.json", "service-2.json.gz"] {
+                let path = version.join(file);
+                if ctx.fs().exists(&path) {
+                    return Some(path);
+                }
+            }
+        }
+    }
+    None
+}
+
+async fn load(ctx: &Context, path: &Path) -> Result<Arc<ServiceModel>> {
+    if let Some(model) = MODELS.lock().unwrap_or_else(|err| err.into_inner()).get(path) {
+        return Ok(Arc::clone(model));
+    }
+    let mut bytes = ctx.fs().read(path).await?;
+    // Recent versions of botocore compress the models.
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        let mut json = Vec::new();
+        GzDecoder::new(bytes.as_slice()).read_to_end(&mut json)?;
+        bytes = json;
+    }
+    let model = Arc::new(serde_json::from_slice::<ServiceModel>(&bytes)?);
+    MODELS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(path.to_path_buf(), Arc::clone(&model));
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    use super::*;
+
+    const S3_MODEL: &str = r#"{
+        "operations": {
+            "ListBuckets": { "input": { "shape": "ListBucketsRequest" } },
+            "ListObjectsV2": { "input": { "shape": "ListObjectsV2Request" } },
+            "GetBucketPolicy": { "input": { "shape": "GetBucketPolicyRequest" } }
+        },
+        "shapes": {
+            "ListBucketsRequest": { "type": "structure", "members": { "MaxBuckets": {} } },
+            "ListObjectsV2Request": {
+                "type": "structure",
+                "members": { "Bucket": {}, "Prefix": {}, "SSEKMSKeyId": {}, "FetchOwner": {} }
+            },
+            "GetBucketPolicyRequest": { "type": "structure", "members": { "Bucket": {} } }
+        }
+    }"#;
+
+    async fn check(ctx: &Context, dir: &Path, service: &str, operation: &str, parameters: &[&str]) -> Result<()> {
+        let parameters = parameters.iter().map(|name| (*name).to_string()).collect::<Vec<_>>();
+        check_call(ctx, dir.to_str(), service, operation, &parameters).await
+    }
+
+    #[test]
+    fn test_cli_name() {
+        assert_eq!(cli_name("ListObjectsV2"), "list-objects-v2");
+        assert_eq!(cli_name("DBInstanceIdentifier"), "db-instance-identifier");
+        assert_eq!(cli_name("SSEKMSKeyId"), "ssekms-key-id");
+        assert_eq!(cli_name("Bucket"), "bucket");
+    }
+
+    #[tokio::test]
+    async fn test_check_call() {
+        let ctx = Context::new();
+        let dir = tempfile::tempdir().unwrap();
+        // Only the latest version is used.
+        for version in ["2006-03-01", "2001-01-01"] {
+            std::fs::create_dir_all(dir.path().join("s3").join(version)).unwrap();
+        }
+        std::fs::write(dir.path().join("s3/2006-03-01/service-2.json"), S3_MODEL).unwrap();
+        std::fs::write(dir.path().join("s3/2001-01-01/service-2.json"), "{}").unwrap();
+
+        check(&ctx, dir.path(), "s3api", "list-objects-v2", &[
+            "--bucket",
+            "--prefix",
+            "--sse-kms-key-id",
+            "--no-fetch-owner",
+            "--query",
+        ])
+        .await
+        .unwrap();
+        check(&ctx, dir.path(), "s3api", "wait", &["--whatever"]).await.unwrap();
+        check(&ctx, dir.path(), "s3", "ls", &[]).await.unwrap();
+        check(&ctx, dir.path(), "lambda", "invoke", &[]).await.unwrap();
+
+        let err = check(&ctx, dir.path(), "s3api", "list-bucket", &[]).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "aws s3api has no operation list-bucket, did you mean list-buckets, list-objects-v2 or get-bucket-policy?"
+        );
+        let err = check(&ctx, dir.path(), "s3api", "get-bucket-policy", &["--bucket-name"])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown parameters of aws s3api get-bucket-policy: --bucket-name (did you mean --bucket?)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compressed_model() {
+        let ctx = Context::new();
+        let dir = tempfile::tempdir().unwrap();
+        let version = dir.path().join("lambda").join("2015-03-31");
+        std::fs::create_dir_all(&version).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(br#"{ "operations": { "ListFunctions": {} }, "shapes": {} }"#)
+            .unwrap();
+        std::fs::write(version.join("service-2.json.gz"), encoder.finish().unwrap()).unwrap();
+
+        check(&ctx, dir.path(), "lambda", "list-functions", &[]).await.unwrap();
+        assert!(check(&ctx, dir.path(), "lambda", "list-function", &[]).await.is_err());
+        let err = check(&ctx, dir.path(), "lambda", "list-functions", &[
+            "--max-items",
+            "--region-name",
+        ])
+        .await
+        .unwrap_err();
+        assert!(err.to_string().ends_with("list-functions: --region-name"), "{err}");
+    }
+}
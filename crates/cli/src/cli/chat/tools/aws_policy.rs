@@ -0,0 +1,192 @@
+//! Guardrails for use_aws: which services may be called at all, and whether an operation only
+//! reads, changes resources, or may add to the AWS bill.
+//!
+//! The classification is a heuristic on the service and operation names of the AWS CLI, so it
+//! errs on the side of treating unknown operations as mutating.
+
+use eyre::{
+    Result,
+    bail,
+};
+
+use crate::database::Database;
+use crate::database::settings::Setting;
+
+/// Operations starting with these only read, and are free or billed per request at most.
+const READ_OPERATION_PREFIXES: [&str; 10] = [
+    "get",
+    "describe",
+    "list",
+    "ls",
+    "search",
+    "batch_get",
+    "batch-get",
+    "head",
+    "lookup",
+    "filter",
+];
+
+/// Operations starting with these start billable resources or work, whatever the service.
+const COST_OPERATION_PREFIXES: [&str; 9] = [
+    "run-",
+    "purchase-",
+    "start-",
+    "allocate-",
+    "request-spot",
+    "register-domain",
+    "invoke",
+    "converse",
+    "restore-",
+];
+
+/// Services whose resources are billed while they exist, so that creating or resizing one adds
+/// to the bill.
+const COST_SERVICES: [&str; 15] = [
+    "ec2",
+    "rds",
+    "redshift",
+    "eks",
+    "ecs",
+    "elasticache",
+    "opensearch",
+    "es",
+    "emr",
+    "sagemaker",
+    "kinesis",
+    "msk",
+    "kafka",
+    "lightsail",
+    "workspaces",
+];
+
+/// What running an operation does, as shown when asking the user to approve it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationClass {
+    Read,
+    Mutating,
+    CostIncurring,
+}
+
+impl OperationClass {
+    /// Classifies an operation from the service and operation names passed to the AWS CLI.
+    pub fn classify(service_name: &str, operation_name: &str) -> Self {
+        let service = service_name.to_lowercase();
+        let operation = operation_name.to_lowercase();
+        if READ_OPERATION_PREFIXES
+            .iter()
+            .any(|prefix| operation.starts_with(prefix))
+        {
+            return Self::Read;
+        }
+
+        let creates_billed_resource = COST_SERVICES.contains(&service.as_str())
+            && ["create-", "modify-", "update-", "scale", "copy-"]
+                .iter()
+                .any(|prefix| operation.starts_with(prefix));
+        if creates_billed_resource
+            || COST_OPERATION_PREFIXES
+                .iter()
+                .any(|prefix| operation.starts_with(prefix))
+        {
+            Self::CostIncurring
+        } else {
+            Self::Mutating
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            OperationClass::Read => "read-only",
+            OperationClass::Mutating => "mutating",
+            OperationClass::CostIncurring => "may incur cost",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AwsPolicy {
+    /// Services that may not be called, as named by the AWS CLI.
+    pub denied_services: Vec<String>,
+    /// Ask before cost incurring operations even if use_aws is trusted.
+    pub always_ask_cost_incurring: bool,
+    /// The directory of the service models calls are checked against, instead of the AWS CLI.
+    pub service_models_path: Option<String>,
+}
+
+impl Default for AwsPolicy {
+    fn default() -> Self {
+        Self {
+            denied_services: Vec::new(),
+            always_ask_cost_incurring: true,
+            service_models_path: None,
+        }
+    }
+}
+
+impl AwsPolicy {
+    pub fn from_database(database: &Database) -> Self {
+        Self {
+            denied_services: database
+                .settings
+                .get_string_list(Setting::ChatUseAwsDeniedServices)
+                .unwrap_or_default(),
+            always_ask_cost_incurring: database
+                .settings
+                .get_bool_or_default(Setting::ChatUseAwsAlwaysAskCostIncurring),
+            service_models_path: database.settings.get_string(Setting::ChatUseAwsServiceModelsPath),
+        }
+    }
+
+    pub fn check_service(&self, service_name: &str) -> Result<()> {
+        let service = service_name.to_lowercase();
+        // `s3api` is the low level API of `s3`, and so on.
+        let denied = self.denied_services.iter().any(|denied| {
+            let denied = denied.to_lowercase();
+            service == denied || service.strip_suffix("api") == Some(denied.as_str())
+        });
+        if denied {
+            bail!("{service_name} is denied by chat.useAws.deniedServices");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        for (service, operation, class) in [
+            ("ecs", "list-task-definitions", OperationClass::Read),
+            ("s3api", "head-object", OperationClass::Read),
+            ("dynamodb", "batch-get-item", OperationClass::Read),
+            ("s3api", "put-object", OperationClass::Mutating),
+            ("iam", "create-role", OperationClass::Mutating),
+            ("ec2", "terminate-instances", OperationClass::Mutating),
+            ("ec2", "run-instances", OperationClass::CostIncurring),
+            ("rds", "create-db-instance", OperationClass::CostIncurring),
+            ("athena", "start-query-execution", OperationClass::CostIncurring),
+            ("lambda", "invoke", OperationClass::CostIncurring),
+        ] {
+            assert_eq!(
+                OperationClass::classify(service, operation),
+                class,
+                "{service} {operation}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_service() {
+        let policy = AwsPolicy {
+            denied_services: vec!["S3".to_string(), "iam".to_string()],
+            ..Default::default()
+        };
+        assert!(policy.check_service("s3").is_err());
+        assert!(policy.check_service("s3api").is_err());
+        assert!(policy.check_service("iam").is_err());
+        assert!(policy.check_service("ec2").is_ok());
+        assert!(policy.check_service("s3control").is_ok());
+    }
+}
@@ -0,0 +1,197 @@
+//! Lookups of the resources that the parameters of use_aws name, so that the user can replace a
+//! resource name the model guessed with one of the resources of the account when approving a call.
+//!
+//! Every lookup is a single read-only list or describe call of the AWS CLI, with a JMESPath query
+//! reducing its output to the names.
+
+use std::process::Stdio;
+use std::time::Duration;
+
+use eyre::{
+    Result,
+    WrapErr,
+    bail,
+};
+
+use super::use_aws::aws_command;
+
+/// How long a lookup may take, the user waiting on it at the approval prompt.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// A parameter naming a resource, and the call listing the resources it can name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLookup {
+    /// Only for the parameter of this service, e.g. `name` is a cluster for eks only.
+    service: Option<&'static str>,
+    /// The parameter in kebab case, as passed to the AWS CLI.
+    parameter: &'static str,
+    /// The service and the operation of the call.
+    command: [&'static str; 2],
+    query: &'static str,
+}
+
+const fn lookup(
+    service: Option<&'static str>,
+    parameter: &'static str,
+    command: [&'static str; 2],
+    query: &'static str,
+) -> ResourceLookup {
+    ResourceLookup {
+        service,
+        parameter,
+        command,
+        query,
+    }
+}
+
+const LOOKUPS: &[ResourceLookup] = &[
+    lookup(None, "bucket", ["s3api", "list-buckets"], "Buckets[].Name"),
+    lookup(
+        None,
+        "function-name",
+        ["lambda", "list-functions"],
+        "Functions[].FunctionName",
+    ),
+    lookup(None, "table-name", ["dynamodb", "list-tables"], "TableNames[]"),
+    lookup(
+        None,
+        "log-group-name",
+        ["logs", "describe-log-groups"],
+        "logGroups[].logGroupName",
+    ),
+    lookup(None, "queue-url", ["sqs", "list-queues"], "QueueUrls[]"),
+    lookup(None, "topic-arn", ["sns", "list-topics"], "Topics[].TopicArn"),
+    lookup(
+        None,
+        "stack-name",
+        ["cloudformation", "describe-stacks"],
+        "Stacks[].StackName",
+    ),
+    lookup(None, "role-name", ["iam", "list-roles"], "Roles[].RoleName"),
+    lookup(
+        None,
+        "secret-id",
+        ["secretsmanager", "list-secrets"],
+        "SecretList[].Name",
+    ),
+    lookup(
+        None,
+        "repository-name",
+        ["ecr", "describe-repositories"],
+        "repositories[].repositoryName",
+    ),
+    lookup(
+        None,
+        "db-instance-identifier",
+        ["rds", "describe-db-instances"],
+        "DBInstances[].DBInstanceIdentifier",
+    ),
+    lookup(
+        None,
+        "state-machine-arn",
+        ["stepfunctions", "list-state-machines"],
+        "stateMachines[].stateMachineArn",
+    ),
+    lookup(
+        Some("ec2"),
+        "instance-id",
+        ["ec2", "describe-instances"],
+        "Reservations[].Instances[].InstanceId",
+    ),
+    lookup(Some("ecs"), "cluster", ["ecs", "list-clusters"], "clusterArns[]"),
+    lookup(Some("eks"), "name", ["eks", "list-clusters"], "clusters[]"),
+];
+
+impl ResourceLookup {
+    /// The lookup of `parameter`, in kebab case, of an operation of `service`.
+    pub fn find(service: &str, parameter: &str) -> Option<&'static Self> {
+        LOOKUPS
+            .iter()
+            .find(|lookup| lookup.parameter == parameter && lookup.service.is_none_or(|name| name == service))
+    }
+
+    /// The command the resources are listed with, shown to the user.
+    pub fn description(&self) -> String {
+        format!("aws {}", self.command.join(" "))
+    }
+
+    /// Lists the names of the resources in `region` with the credentials of `profile_name`.
+    pub async fn list(&self, region: &str, profile_name: Option<&str>) -> Result<Vec<String>> {
+        let output = aws_command(region, profile_name)
+            .args(self.command)
+            .args(["--query", self.query, "--output", "json"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(LOOKUP_TIMEOUT, output)
+            .await
+            .wrap_err_with(|| format!("{} took longer than {}s", self.description(), LOOKUP_TIMEOUT.as_secs()))?
+            .wrap_err_with(|| format!("Unable to run {}", self.description()))?;
+        if !output.status.success() {
+            bail!(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        // A query of a missing key outputs null.
+        let names: Option<Vec<String>> = serde_json::from_slice(&output.stdout)
+            .wrap_err_with(|| format!("Unexpected output of {}", self.description()))?;
+        Ok(names.unwrap_or_default())
+    }
+}
+
+/// Sorts `names` by how close they are to `guess`, the names containing it or contained in it
+/// first.
+pub fn rank(guess: &str, mut names: Vec<String>) -> Vec<String> {
+    let guess = guess.to_lowercase();
+    names.sort_by_cached_key(|name| {
+        let name = name.to_lowercase();
+        let related = name.contains(&guess) || guess.contains(&name);
+        (!related, edit_distance(&guess, &name))
+    });
+    names
+}
+
+/// The Levenshtein distance between `a` and `b`, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let bucket = ResourceLookup::find("s3api", "bucket").unwrap();
+        assert_eq!(bucket.description(), "aws s3api list-buckets");
+        assert_eq!(ResourceLookup::find("eks", "name").unwrap().command[0], "eks");
+        assert!(ResourceLookup::find("iam", "name").is_none());
+        assert!(ResourceLookup::find("s3api", "key").is_none());
+    }
+
+    #[test]
+    fn test_rank() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        let names = ["prod-logs", "my-app-assets-prod", "my-app-assets-dev", "other"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(rank("my-app-assets", names), vec![
+            "my-app-assets-dev",
+            "my-app-assets-prod",
+            "prod-logs",
+            "other"
+        ]);
+    }
+}
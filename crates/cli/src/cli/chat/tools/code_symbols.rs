@@ -0,0 +1,560 @@
+//! Outlines of source files and definition lookups across the workspace, parsed with tree-sitter,
+//! so that the model can read the exact lines it needs instead of whole files.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Color,
+};
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tracing::debug;
+use tree_sitter::{
+    Node,
+    Parser,
+};
+
+use super::{
+    InvokeOutput,
+    MAX_TOOL_RESPONSE_SIZE,
+    OutputKind,
+    sanitize_path_tool_arg,
+};
+use crate::cli::chat::util::truncate_safe;
+use crate::platform::Context;
+
+/// Directories that hold dependencies or build output rather than code of the workspace.
+pub const SKIPPED_DIRS: &[&str] = &["node_modules", "target", "dist", "build", "vendor", "__pycache__"];
+/// The most files looked at when walking a directory.
+pub const MAX_FILES: usize = 5000;
+/// Larger files are usually generated or minified and are skipped.
+pub const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// A language with a tree-sitter grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    Python,
+    Go,
+    JavaScript,
+    TypeScript,
+    Tsx,
+    Java,
+}
+
+impl Language {
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        Some(match extension {
+            "rs" => Self::Rust,
+            "py" | "pyi" => Self::Python,
+            "go" => Self::Go,
+            "js" | "jsx" | "mjs" | "cjs" => Self::JavaScript,
+            "ts" | "mts" | "cts" => Self::TypeScript,
+            "tsx" => Self::Tsx,
+            "java" => Self::Java,
+            _ => return None,
+        })
+    }
+
+    pub fn parser(&self) -> Result<Parser> {
+        let language = match self {
+            Self::Rust => tree_sitter_rust::LANGUAGE,
+            Self::Python => tree_sitter_python::LANGUAGE,
+            Self::Go => tree_sitter_go::LANGUAGE,
+            Self::JavaScript => tree_sitter_javascript::LANGUAGE,
+            Self::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT,
+            Self::Tsx => tree_sitter_typescript::LANGUAGE_TSX,
+            Self::Java => tree_sitter_java::LANGUAGE,
+        };
+        let mut parser = Parser::new();
+        parser.set_language(&language.into())?;
+        Ok(parser)
+    }
+
+    /// The label of the definition made by `node`, or `None` if it isn't a definition.
+    fn definition_label(&self, node: Node<'_>) -> Option<&'static str> {
+        Some(match (self, node.kind()) {
+            (Self::Rust, "function_item" | "function_signature_item") => "fn",
+            (Self::Rust, "struct_item") => "struct",
+            (Self::Rust, "enum_item") => "enum",
+            (Self::Rust, "union_item") => "union",
+            (Self::Rust, "trait_item") => "trait",
+            (Self::Rust, "impl_item") => "impl",
+            (Self::Rust, "mod_item") => "mod",
+            (Self::Rust, "const_item") => "const",
+            (Self::Rust, "static_item") => "static",
+            (Self::Rust, "type_item") => "type",
+            (Self::Rust, "macro_definition") => "macro",
+            (Self::Python, "function_definition") => "def",
+            (Self::Python, "class_definition") => "class",
+            (Self::Go, "function_declaration" | "method_declaration") => "func",
+            (Self::Go, "type_spec") => "type",
+            (
+                Self::JavaScript | Self::TypeScript | Self::Tsx,
+                "function_declaration" | "generator_function_declaration" | "function_signature",
+            ) => "function",
+            (Self::JavaScript | Self::TypeScript | Self::Tsx, "class_declaration" | "abstract_class_declaration") => {
+                "class"
+            },
+            (Self::JavaScript | Self::TypeScript | Self::Tsx, "method_definition" | "method_signature") => "method",
+            (Self::JavaScript | Self::TypeScript | Self::Tsx, "variable_declarator")
+                if node.child_by_field_name("value").is_some_and(|value| {
+                    matches!(value.kind(), "arrow_function" | "function_expression" | "function")
+                }) =>
+            {
+                "function"
+            },
+            (Self::TypeScript | Self::Tsx, "interface_declaration") => "interface",
+            (Self::TypeScript | Self::Tsx, "type_alias_declaration") => "type",
+            (Self::TypeScript | Self::Tsx, "enum_declaration") => "enum",
+            (Self::TypeScript | Self::Tsx, "internal_module") => "namespace",
+            (Self::Java, "class_declaration") => "class",
+            (Self::Java, "interface_declaration") => "interface",
+            (Self::Java, "enum_declaration") => "enum",
+            (Self::Java, "record_declaration") => "record",
+            (Self::Java, "annotation_type_declaration") => "@interface",
+            (Self::Java, "method_declaration") => "method",
+            (Self::Java, "constructor_declaration") => "constructor",
+            _ => return None,
+        })
+    }
+}
+
+/// A definition found in a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// The keyword of the definition, e.g. `fn` or `class`.
+    pub kind: &'static str,
+    /// 1-based and inclusive.
+    pub start_line: usize,
+    pub end_line: usize,
+    /// How many definitions this one is nested in, e.g. 1 for the methods of a class.
+    pub depth: usize,
+}
+
+/// The definitions of a source file, in order. The bodies of functions are not looked into.
+pub fn parse_symbols(language: Language, source: &str) -> Result<Vec<Symbol>> {
+    let tree = language
+        .parser()?
+        .parse(source, None)
+        .ok_or_else(|| eyre!("Failed to parse the file"))?;
+    let mut symbols = Vec::new();
+    collect_symbols(language, tree.root_node(), source.as_bytes(), 0, &mut symbols);
+    Ok(symbols)
+}
+
+fn collect_symbols(language: Language, node: Node<'_>, source: &[u8], depth: usize, symbols: &mut Vec<Symbol>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let Some(kind) = language.definition_label(child) else {
+            collect_symbols(language, child, source, depth, symbols);
+            continue;
+        };
+        if let Some(name) = symbol_name(child, source) {
+            symbols.push(Symbol {
+                name,
+                kind,
+                start_line: child.start_position().row + 1,
+                end_line: child.end_position().row + 1,
+                depth,
+            });
+        }
+        let is_function = matches!(
+            kind,
+            "fn" | "def" | "func" | "function" | "method" | "constructor" | "macro"
+        );
+        if !is_function {
+            collect_symbols(language, child, source, depth + 1, symbols);
+        }
+    }
+}
+
+fn symbol_name(node: Node<'_>, source: &[u8]) -> Option<String> {
+    let text = |field: &str| {
+        node.child_by_field_name(field)
+            .and_then(|child| child.utf8_text(source).ok())
+            .map(str::to_string)
+    };
+    match node.kind() {
+        "impl_item" => match (text("trait"), text("type")) {
+            (Some(trait_name), Some(type_name)) => Some(format!("{trait_name} for {type_name}")),
+            (None, type_name) => type_name,
+            (Some(_), None) => None,
+        },
+        "method_declaration" if node.child_by_field_name("receiver").is_some() => {
+            Some(format!("{} {}", text("receiver")?, text("name")?))
+        },
+        _ => text("name"),
+    }
+}
+
+/// The byte ranges of the identifiers named `name` in a source file, in order. Strings, comments
+/// and identifiers that merely contain `name` are not included.
+pub fn identifier_ranges(language: Language, source: &str, name: &str) -> Result<Vec<Range<usize>>> {
+    let tree = language
+        .parser()?
+        .parse(source, None)
+        .ok_or_else(|| eyre!("Failed to parse the file"))?;
+    let mut ranges = Vec::new();
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if node.child_count() == 0
+            && node.kind().ends_with("identifier")
+            && node.utf8_text(source.as_bytes()).is_ok_and(|text| text == name)
+        {
+            ranges.push(node.byte_range());
+        }
+        if cursor.goto_first_child() || cursor.goto_next_sibling() {
+            continue;
+        }
+        loop {
+            if !cursor.goto_parent() {
+                return Ok(ranges);
+            }
+            if cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+}
+
+/// The source files with a known language under `root`, skipping hidden directories and those in
+/// [SKIPPED_DIRS]. Stops at [MAX_FILES] files, in which case the second value is `true`.
+pub async fn source_files(ctx: &Context, root: &Path) -> Result<(Vec<PathBuf>, bool)> {
+    let mut files = Vec::new();
+    let mut dirs = VecDeque::from([root.to_path_buf()]);
+    while let Some(dir) = dirs.pop_front() {
+        let mut read_dir = ctx.fs().read_dir(&dir).await?;
+        let mut entries = Vec::new();
+        while let Some(entry) = read_dir.next_entry().await? {
+            entries.push(entry);
+        }
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str()) {
+                    dirs.push_back(entry.path());
+                }
+            } else if file_type.is_file()
+                && Language::from_path(&name).is_some()
+                && entry.metadata().await?.len() <= MAX_FILE_SIZE
+            {
+                if files.len() == MAX_FILES {
+                    return Ok((files, true));
+                }
+                files.push(entry.path());
+            }
+        }
+    }
+    Ok((files, false))
+}
+
+/// Outlines the definitions of a file, or finds where a symbol is defined in a directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeSymbols {
+    /// A source file to outline, or a directory to search
+    pub path: String,
+    /// Optional: Only return the definitions with this name
+    #[serde(default)]
+    pub symbol: Option<String>,
+}
+
+impl CodeSymbols {
+    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+        let path = sanitize_path_tool_arg(ctx, &self.path);
+        let is_dir = ctx.fs().symlink_metadata(&path).await?.is_dir();
+        let (files, truncated) = if is_dir {
+            source_files(ctx, &path).await?
+        } else {
+            (vec![path.clone()], false)
+        };
+        debug!(?path, files = files.len(), "Collecting symbols");
+
+        let mut output = String::new();
+        let mut symbol_count = 0;
+        for file in &files {
+            let Some(language) = Language::from_path(file) else {
+                continue;
+            };
+            let Ok(source) = ctx.fs().read_to_string(file).await else {
+                continue;
+            };
+            let symbols = match parse_symbols(language, &source) {
+                Ok(symbols) => symbols,
+                Err(err) => {
+                    debug!(?file, ?err, "Failed to parse");
+                    continue;
+                },
+            };
+            let symbols = symbols
+                .into_iter()
+                .filter(|symbol| self.symbol.as_ref().is_none_or(|name| symbol.name == *name))
+                .collect::<Vec<_>>();
+            if symbols.is_empty() {
+                continue;
+            }
+            symbol_count += symbols.len();
+
+            let display_path = match file.strip_prefix(&path) {
+                Ok(relative) if is_dir => Path::new(&self.path).join(relative),
+                _ => PathBuf::from(&self.path),
+            };
+            let _ = writeln!(output, "{}", display_path.display());
+            for symbol in symbols {
+                let indent = if self.symbol.is_some() { 0 } else { symbol.depth };
+                let _ = writeln!(
+                    output,
+                    "{}  {}-{} {} {}",
+                    "  ".repeat(indent),
+                    symbol.start_line,
+                    symbol.end_line,
+                    symbol.kind,
+                    symbol.name
+                );
+            }
+        }
+
+        if truncated {
+            let _ = writeln!(
+                output,
+                "[... Stopped after {MAX_FILES} files, use a more specific path ...]"
+            );
+        }
+        if output.len() > MAX_TOOL_RESPONSE_SIZE {
+            output = format!(
+                "{}\n[... Output truncated, use a more specific path or a symbol name ...]",
+                truncate_safe(&output, MAX_TOOL_RESPONSE_SIZE)
+            );
+        }
+        if symbol_count == 0 {
+            output = match &self.symbol {
+                Some(name) => format!("No definition of {name} found in {}", self.path),
+                None => format!("No definitions found in {}", self.path),
+            };
+        }
+
+        queue!(
+            updates,
+            style::Print(format!("Found {symbol_count} definitions in {} files\n", files.len())),
+        )?;
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(output),
+        })
+    }
+
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        match &self.symbol {
+            Some(symbol) => queue!(
+                updates,
+                style::Print("Finding definitions of "),
+                style::SetForegroundColor(Color::Green),
+                style::Print(symbol),
+                style::ResetColor,
+                style::Print(" in "),
+            )?,
+            None => queue!(updates, style::Print("Outlining "))?,
+        }
+        queue!(
+            updates,
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.path),
+            style::ResetColor,
+        )?;
+        Ok(())
+    }
+
+    pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
+        let path = sanitize_path_tool_arg(ctx, &self.path);
+        if !path.exists() {
+            bail!("'{}' does not exist", self.path);
+        }
+        let metadata = ctx.fs().symlink_metadata(&path).await?;
+        if metadata.is_file() && Language::from_path(&path).is_none() {
+            bail!(
+                "'{}' is not in a supported language: Rust, Python, Go, JavaScript, TypeScript or Java",
+                self.path
+            );
+        }
+        if self.symbol.as_ref().is_some_and(|symbol| symbol.trim().is_empty()) {
+            bail!("symbol must not be empty");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outline(language: Language, source: &str) -> Vec<(usize, &'static str, String)> {
+        parse_symbols(language, source)
+            .unwrap()
+            .into_iter()
+            .map(|symbol| (symbol.depth, symbol.kind, symbol.name))
+            .collect()
+    }
+
+    #[test]
+    fn test_rust_symbols() {
+        let source = "\
+struct Point { x: i32 }
+
+impl Display for Point {
+    fn fmt(&self) {
+        fn helper() {}
+    }
+}
+
+mod geometry {
+    pub trait Shape {
+        fn area(&self) -> f64;
+    }
+}
+";
+        assert_eq!(outline(Language::Rust, source), vec![
+            (0, "struct", "Point".to_string()),
+            (0, "impl", "Display for Point".to_string()),
+            (1, "fn", "fmt".to_string()),
+            (0, "mod", "geometry".to_string()),
+            (1, "trait", "Shape".to_string()),
+            (2, "fn", "area".to_string()),
+        ]);
+
+        let symbols = parse_symbols(Language::Rust, source).unwrap();
+        assert_eq!((symbols[1].start_line, symbols[1].end_line), (3, 7));
+    }
+
+    #[test]
+    fn test_python_and_typescript_symbols() {
+        let source = "\
+@dataclass
+class Config:
+    def load(self):
+        pass
+
+def main():
+    pass
+";
+        assert_eq!(outline(Language::Python, source), vec![
+            (0, "class", "Config".to_string()),
+            (1, "def", "load".to_string()),
+            (0, "def", "main".to_string()),
+        ]);
+
+        let source = "\
+export interface Options { verbose: boolean }
+export class Client {
+  send(message: string) {}
+}
+const handler = async (event) => {};
+";
+        assert_eq!(outline(Language::TypeScript, source), vec![
+            (0, "interface", "Options".to_string()),
+            (0, "class", "Client".to_string()),
+            (1, "method", "send".to_string()),
+            (0, "function", "handler".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_go_symbols() {
+        let source = "\
+package server
+
+type Server struct{}
+
+func (s *Server) Start() error { return nil }
+
+func New() *Server { return &Server{} }
+";
+        assert_eq!(outline(Language::Go, source), vec![
+            (0, "type", "Server".to_string()),
+            (0, "func", "(s *Server) Start".to_string()),
+            (0, "func", "New".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_identifier_ranges() {
+        let source = "\
+// Calls parse
+fn parse() {}
+fn main() {
+    let parsed = parse();
+    println!(\"parse {}\", parse());
+}
+";
+        let ranges = identifier_ranges(Language::Rust, source, "parse").unwrap();
+        let lines = ranges
+            .iter()
+            .map(|range| source[..range.start].lines().count())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![2, 4, 5]);
+        assert!(ranges.iter().all(|range| &source[range.clone()] == "parse"));
+    }
+
+    #[tokio::test]
+    async fn test_code_symbols_invoke() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().create_dir_all("/project/src").await.unwrap();
+        ctx.fs().create_dir_all("/project/node_modules/dep").await.unwrap();
+        ctx.fs()
+            .write("/project/src/lib.rs", "pub fn parse() {}\npub struct Parser;\n")
+            .await
+            .unwrap();
+        ctx.fs()
+            .write("/project/src/main.py", "def parse():\n    pass\n")
+            .await
+            .unwrap();
+        ctx.fs()
+            .write("/project/node_modules/dep/index.js", "function parse() {}\n")
+            .await
+            .unwrap();
+
+        let mut tool = CodeSymbols {
+            path: "/project".to_string(),
+            symbol: Some("parse".to_string()),
+        };
+        tool.validate(&ctx).await.unwrap();
+        let output = tool.invoke(&ctx, &mut std::io::sink()).await.unwrap();
+        let OutputKind::Text(text) = output.output else {
+            panic!("expected text output");
+        };
+        assert_eq!(
+            text,
+            "/project/src/lib.rs\n  1-1 fn parse\n/project/src/main.py\n  1-2 def parse\n"
+        );
+
+        let tool = CodeSymbols {
+            path: "/project/src/lib.rs".to_string(),
+            symbol: None,
+        };
+        let output = tool.invoke(&ctx, &mut std::io::sink()).await.unwrap();
+        let OutputKind::Text(text) = output.output else {
+            panic!("expected text output");
+        };
+        assert_eq!(text, "/project/src/lib.rs\n  1-1 fn parse\n  2-2 struct Parser\n");
+    }
+}
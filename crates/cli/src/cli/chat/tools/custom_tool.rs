@@ -1,7 +1,9 @@
 use std::collections::HashMap;
 use std::io::Write;
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 
 use crossterm::{
     queue,
@@ -16,16 +18,21 @@ use tokio::sync::RwLock;
 use tracing::warn;
 
 use super::InvokeOutput;
+use super::env_policy::EnvPolicy;
+use super::limits::ToolTimeoutError;
 use crate::cli::chat::CONTINUATION_LINE;
 use crate::cli::chat::token_counter::TokenCounter;
 use crate::mcp_client::{
     Client as McpClient,
     ClientConfig as McpClientConfig,
+    ClientError,
     JsonRpcResponse,
     JsonRpcStdioTransport,
     MessageContent,
     Messenger,
     PromptGet,
+    Roots,
+    Sampler,
     ServerCapabilities,
     StdioTransport,
     ToolCallResult,
@@ -35,6 +42,7 @@ use crate::platform::Context;
 // TODO: support http transport type
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct CustomToolConfig {
+    #[serde(default)]
     pub command: String,
     #[serde(default)]
     pub args: Vec<String>,
@@ -42,6 +50,10 @@ pub struct CustomToolConfig {
     pub env: Option<HashMap<String, String>>,
     #[serde(default = "default_timeout")]
     pub timeout: u64,
+    /// Whether the server isn't started. A scope of higher precedence can switch off a server of
+    /// another scope with an entry of the same name holding only `"disabled": true`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
 }
 
 pub fn default_timeout() -> u64 {
@@ -59,13 +71,28 @@ pub enum CustomToolClient {
 
 impl CustomToolClient {
     // TODO: add support for http transport
-    pub fn from_config(server_name: String, config: CustomToolConfig) -> Result<Self> {
+    /// Spawns the server with the variables of the environment `env_policy` allows, and the ones
+    /// of its config with their `${env:NAME}` references replaced.
+    pub fn from_config(
+        server_name: String,
+        config: CustomToolConfig,
+        env_policy: &EnvPolicy,
+        current_dir: Option<&Path>,
+    ) -> Result<Self> {
         let CustomToolConfig {
             command,
             args,
             env,
             timeout,
+            ..
         } = config;
+        let env = env
+            .map(|env| {
+                env.into_iter()
+                    .map(|(name, value)| Ok((name, env_policy.interpolate(&value)?)))
+                    .collect::<Result<HashMap<_, _>>>()
+            })
+            .transpose()?;
         let mcp_client_config = McpClientConfig {
             server_name: server_name.clone(),
             bin_path: command.clone(),
@@ -75,7 +102,9 @@ impl CustomToolClient {
                "name": "Q CLI Chat",
                "version": "1.0.0"
             }),
+            inherited_env: env_policy.passthrough().into_iter().collect(),
             env,
+            current_dir: current_dir.map(Path::to_path_buf),
         };
         let client = McpClient::<JsonRpcStdioTransport>::from_config(mcp_client_config)?;
         Ok(CustomToolClient::Stdio {
@@ -114,6 +143,22 @@ impl CustomToolClient {
         }
     }
 
+    pub fn assign_sampler(&mut self, sampler: Arc<dyn Sampler>) {
+        match self {
+            CustomToolClient::Stdio { client, .. } => {
+                client.sampler = Some(sampler);
+            },
+        }
+    }
+
+    pub fn assign_roots(&mut self, roots: Roots) {
+        match self {
+            CustomToolClient::Stdio { client, .. } => {
+                client.roots = Some(roots);
+            },
+        }
+    }
+
     pub fn get_server_name(&self) -> &str {
         match self {
             CustomToolClient::Stdio { server_name, .. } => server_name.as_str(),
@@ -126,6 +171,17 @@ impl CustomToolClient {
         }
     }
 
+    pub async fn request_with_timeout(
+        &self,
+        method: &str,
+        params: Option<serde_json::Value>,
+        timeout: Duration,
+    ) -> Result<JsonRpcResponse, ClientError> {
+        match self {
+            CustomToolClient::Stdio { client, .. } => client.request_with_timeout(method, params, timeout).await,
+        }
+    }
+
     pub fn list_prompt_gets(&self) -> Arc<std::sync::RwLock<HashMap<String, PromptGet>>> {
         match self {
             CustomToolClient::Stdio { client, .. } => client.prompt_gets.clone(),
@@ -169,9 +225,29 @@ pub struct CustomTool {
 }
 
 impl CustomTool {
-    pub async fn invoke(&self, _ctx: &Context, _updates: &mut impl Write) -> Result<InvokeOutput> {
+    /// Calls the tool, waiting for `timeout` if set, see `chat.toolTimeouts`, or for the timeout of
+    /// its server otherwise.
+    pub async fn invoke(
+        &self,
+        _ctx: &Context,
+        _updates: &mut impl Write,
+        timeout: Option<Duration>,
+    ) -> Result<InvokeOutput> {
         // Assuming a response shape as per https://spec.modelcontextprotocol.io/specification/2024-11-05/server/tools/#calling-tools
-        let resp = self.client.request(self.method.as_str(), self.params.clone()).await?;
+        let resp = match timeout {
+            Some(timeout) => self
+                .client
+                .request_with_timeout(self.method.as_str(), self.params.clone(), timeout)
+                .await
+                .map_err(|err| match err {
+                    ClientError::RuntimeError { .. } => eyre::Report::new(ToolTimeoutError {
+                        tool: self.name.clone(),
+                        timeout,
+                    }),
+                    err => err.into(),
+                })?,
+            None => self.client.request(self.method.as_str(), self.params.clone()).await?,
+        };
         let result = match resp.result {
             Some(result) => result,
             None => {
@@ -0,0 +1,280 @@
+//! Text extraction from PDF and Word documents, so that fs_read and web_browse return the text of
+//! design docs and papers instead of their binary content.
+
+use std::fmt::Write as _;
+use std::io::{
+    Cursor,
+    Read,
+};
+use std::panic::{
+    AssertUnwindSafe,
+    catch_unwind,
+};
+use std::path::Path;
+
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+
+use crate::cli::chat::util::truncate_safe;
+
+/// Documents larger than this are not read, to bound the time and memory spent parsing them.
+pub const MAX_DOCUMENT_SIZE: u64 = 50 * 1024 * 1024;
+
+const DOCX_CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    Pdf,
+    Docx,
+}
+
+impl DocumentKind {
+    /// Detects a document from its content.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"%PDF-") {
+            Some(Self::Pdf)
+        } else if bytes.starts_with(b"PK\x03\x04")
+            && zip::ZipArchive::new(Cursor::new(bytes)).is_ok_and(|mut zip| zip.by_name("word/document.xml").is_ok())
+        {
+            Some(Self::Docx)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_extension(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "pdf" => Some(Self::Pdf),
+            "docx" => Some(Self::Docx),
+            _ => None,
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        if mime.eq_ignore_ascii_case("application/pdf") {
+            Some(Self::Pdf)
+        } else if mime.eq_ignore_ascii_case(DOCX_CONTENT_TYPE) {
+            Some(Self::Docx)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pdf => "PDF document",
+            Self::Docx => "Word document",
+        }
+    }
+}
+
+/// The text of a document, page by page.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub pages: Vec<String>,
+}
+
+impl Document {
+    pub fn parse(kind: DocumentKind, bytes: &[u8]) -> Result<Self> {
+        let pages = match kind {
+            // pdf-extract panics on some malformed documents.
+            DocumentKind::Pdf => catch_unwind(AssertUnwindSafe(|| pdf_extract::extract_text_from_mem_by_pages(bytes)))
+                .map_err(|_err| eyre!("Failed to parse the PDF document"))?
+                .map_err(|err| eyre!("Failed to parse the PDF document: {err}"))?,
+            DocumentKind::Docx => {
+                let mut zip = zip::ZipArchive::new(Cursor::new(bytes))?;
+                let mut xml = String::new();
+                zip.by_name("word/document.xml")?.read_to_string(&mut xml)?;
+                docx_pages(&xml)
+            },
+        };
+        Ok(Self { pages })
+    }
+
+    /// The text of the pages from `start_page` to `end_page`, both 1-based and inclusive, up to
+    /// `max_bytes`. Pages that don't fit are left out with a note on where to continue.
+    pub fn text(&self, start_page: usize, end_page: Option<usize>, max_bytes: usize) -> Result<String> {
+        let page_count = self.pages.len();
+        if start_page == 0 || start_page > page_count {
+            bail!("start_page {start_page} is outside of the document's pages (1 to {page_count})");
+        }
+        let end_page = end_page.unwrap_or(page_count).clamp(start_page, page_count);
+
+        let mut text = String::new();
+        for number in start_page..=end_page {
+            let page = format!(
+                "--- Page {number} of {page_count} ---\n{}\n\n",
+                self.pages[number - 1].trim()
+            );
+            if text.len() + page.len() > max_bytes {
+                if text.is_empty() {
+                    text.push_str(truncate_safe(&page, max_bytes));
+                    let _ = write!(text, "\n[... Page {number} truncated ...]\n\n");
+                    if number == end_page {
+                        break;
+                    }
+                    let _ = write!(
+                        text,
+                        "[... Pages {} to {end_page} not included, read them with start_page {} ...]",
+                        number + 1,
+                        number + 1
+                    );
+                } else {
+                    let _ = write!(
+                        text,
+                        "[... Pages {number} to {end_page} not included, read them with start_page {number} ...]"
+                    );
+                }
+                break;
+            }
+            text.push_str(&page);
+        }
+        Ok(text.trim_end().to_string())
+    }
+}
+
+/// Extracts the text of `word/document.xml`, split at explicit and rendered page breaks.
+fn docx_pages(xml: &str) -> Vec<String> {
+    let mut pages = vec![String::new()];
+    let mut in_text = false;
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        if in_text {
+            let page = pages.last_mut().expect("pages is never empty");
+            page.push_str(&decode_entities(&rest[..start]));
+        }
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        let page = pages.last_mut().expect("pages is never empty");
+        match name {
+            "w:t" => in_text = !tag.ends_with('/'),
+            "" if tag == "/w:t" => in_text = false,
+            "" if tag == "/w:p" => page.push('\n'),
+            "w:tab" => page.push('\t'),
+            "w:br" if tag.contains("w:type=\"page\"") => pages.push(String::new()),
+            "w:br" | "w:cr" => page.push('\n'),
+            "w:lastRenderedPageBreak" if !page.trim().is_empty() => pages.push(String::new()),
+            _ => {},
+        }
+    }
+
+    pages.retain(|page| !page.trim().is_empty());
+    if pages.is_empty() {
+        pages.push(String::new());
+    }
+    pages
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let ch = entity.and_then(|(entity, _)| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => entity.strip_prefix('#')?.parse().ok().and_then(char::from_u32),
+            },
+        });
+        match (ch, entity) {
+            (Some(ch), Some((_, end))) => {
+                decoded.push(ch);
+                rest = &rest[end + 1..];
+            },
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const DOCUMENT_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<w:document><w:body>
+<w:p><w:r><w:t>Design &amp; rationale</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">Step</w:t><w:tab/><w:t>one &#x2013; two</w:t></w:r></w:p>
+<w:p><w:r><w:br w:type="page"/><w:t>Appendix</w:t></w:r></w:p>
+</w:body></w:document>"#;
+
+    fn docx() -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("word/document.xml", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(DOCUMENT_XML.as_bytes()).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(DocumentKind::detect(b"%PDF-1.7\n..."), Some(DocumentKind::Pdf));
+        assert_eq!(DocumentKind::detect(&docx()), Some(DocumentKind::Docx));
+        assert_eq!(DocumentKind::detect(b"plain text"), None);
+        assert_eq!(
+            DocumentKind::from_content_type("application/pdf; qs=0.001"),
+            Some(DocumentKind::Pdf)
+        );
+        assert_eq!(
+            DocumentKind::from_extension("/docs/Design.DOCX"),
+            Some(DocumentKind::Docx)
+        );
+        assert_eq!(DocumentKind::from_extension("/docs/notes.md"), None);
+    }
+
+    #[test]
+    fn test_parse_docx() {
+        let document = Document::parse(DocumentKind::Docx, &docx()).unwrap();
+        assert_eq!(document.pages.len(), 2);
+        assert_eq!(document.pages[0], "Design & rationale\nStep\tone – two\n");
+        assert_eq!(document.pages[1].trim(), "Appendix");
+    }
+
+    #[test]
+    fn test_document_text_page_ranges() {
+        let document = Document {
+            pages: vec!["one".to_string(), "two".to_string(), "three".repeat(20)],
+        };
+        assert_eq!(document.text(2, Some(2), 1000).unwrap(), "--- Page 2 of 3 ---\ntwo");
+        assert!(document.text(4, None, 1000).is_err());
+
+        // Pages past the budget are left out.
+        let text = document.text(1, None, 80).unwrap();
+        assert!(text.contains("Page 2 of 3"));
+        assert!(text.ends_with("[... Pages 3 to 3 not included, read them with start_page 3 ...]"));
+
+        // A single page larger than the budget is truncated.
+        let text = document.text(3, None, 40).unwrap();
+        assert!(text.ends_with("[... Page 3 truncated ...]"));
+    }
+}
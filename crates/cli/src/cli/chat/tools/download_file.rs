@@ -0,0 +1,331 @@
+//! Downloads a file to a path approved by the user, so that the model can fetch datasets and
+//! release assets without shelling out to curl.
+//!
+//! The file is written to a `.part` file next to the destination and only moved in place once it
+//! is complete, within the size limit and, if a checksum was given, verified.
+
+use std::io::Write;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::time::Duration;
+
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Color,
+};
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use reqwest::header::{
+    HeaderMap,
+    HeaderValue,
+    USER_AGENT,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use tokio::io::AsyncWriteExt;
+use url::Url;
+
+use super::url_policy::UrlPolicy;
+use super::{
+    InvokeOutput,
+    OutputKind,
+    format_path,
+    sanitize_path_tool_arg,
+};
+use crate::platform::Context;
+
+/// The largest `max_size_mb` the model may ask for.
+const MAX_DOWNLOAD_SIZE_MB: u64 = 1024;
+/// Progress is reported every time this many more percent, or bytes if the size is unknown, have
+/// been downloaded.
+const PROGRESS_PERCENT_STEP: u64 = 10;
+const PROGRESS_BYTES_STEP: u64 = 10 * 1024 * 1024;
+
+/// Downloads a URL to a file, optionally verifying its SHA256 checksum.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadFile {
+    /// The URL to download
+    pub url: String,
+    /// Where to save the file
+    pub path: String,
+    /// Optional: Expected SHA256 checksum of the file, in hex
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Optional: Maximum size of the file in MB (default: 100)
+    #[serde(default = "default_max_size_mb")]
+    pub max_size_mb: u64,
+    /// Optional: Replace the file if it already exists (default: false)
+    #[serde(default)]
+    pub overwrite: bool,
+    /// Optional: Timeout of the whole download in seconds (default: 300)
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// Which URLs may be fetched, set from the settings before validation.
+    #[serde(skip)]
+    pub policy: UrlPolicy,
+}
+
+fn default_max_size_mb() -> u64 {
+    100
+}
+
+fn default_timeout() -> u64 {
+    300
+}
+
+impl DownloadFile {
+    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+        let path = sanitize_path_tool_arg(ctx, &self.path);
+        let part_path = part_path(&path);
+        if let Some(parent) = path.parent() {
+            ctx.fs().create_dir_all(parent).await?;
+        }
+        // Left behind by an interrupted download.
+        let _ = ctx.fs().remove_file(&part_path).await;
+
+        writeln!(updates, "📡 Downloading {}", self.url)?;
+        let result = self.download(ctx, &part_path, updates).await;
+        let (size, checksum) = match result {
+            Ok(download) => download,
+            Err(err) => {
+                let _ = ctx.fs().remove_file(&part_path).await;
+                return Err(err);
+            },
+        };
+
+        if let Some(expected) = &self.sha256 {
+            if !checksum.eq_ignore_ascii_case(expected) {
+                let _ = ctx.fs().remove_file(&part_path).await;
+                bail!(
+                    "Checksum mismatch for {}: expected {expected}, got {checksum}",
+                    self.url
+                );
+            }
+        }
+        ctx.fs().rename(&part_path, &path).await?;
+
+        let verified = if self.sha256.is_some() {
+            ", checksum verified"
+        } else {
+            ""
+        };
+        writeln!(updates, "✅ Saved {} ({}{verified})", self.path, format_size(size))?;
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(format!(
+                "Downloaded {} to {} ({size} bytes, SHA256 {checksum}{verified})",
+                self.url, self.path
+            )),
+        })
+    }
+
+    /// Streams the body to `part_path`, returning its size and SHA256 checksum.
+    async fn download(&self, ctx: &Context, part_path: &Path, updates: &mut impl Write) -> Result<(u64, String)> {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static("Amazon Q CLI Downloader/1.0"));
+        let mut response = self
+            .policy
+            .get(&self.url, Duration::from_secs(self.timeout), headers)
+            .await?;
+        if !response.status().is_success() {
+            bail!("Download failed with status: {}", response.status());
+        }
+
+        let max_size = self.max_size_mb * 1024 * 1024;
+        let total = response.content_length();
+        if total.is_some_and(|total| total > max_size) {
+            bail!(
+                "The file is {}, larger than the limit of {} MB",
+                format_size(total.unwrap_or_default()),
+                self.max_size_mb
+            );
+        }
+
+        let mut file = ctx.fs().create_new(part_path).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        let mut reported = 0;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|err| eyre!("Failed to download {}: {err}", self.url))?
+        {
+            size += chunk.len() as u64;
+            if size > max_size {
+                bail!("The file is larger than the limit of {} MB", self.max_size_mb);
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+
+            let progress = match total {
+                Some(total) if total > 0 => size * 100 / total / PROGRESS_PERCENT_STEP,
+                _ => size / PROGRESS_BYTES_STEP,
+            };
+            if progress > reported {
+                reported = progress;
+                match total {
+                    Some(total) => writeln!(
+                        updates,
+                        "   {}% ({} of {})",
+                        size * 100 / total.max(1),
+                        format_size(size),
+                        format_size(total)
+                    )?,
+                    None => writeln!(updates, "   {}", format_size(size))?,
+                }
+            }
+        }
+        file.flush().await?;
+
+        let checksum = format!("{:x}", hasher.finalize());
+        Ok((size, checksum))
+    }
+
+    pub fn queue_description(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
+        let cwd = ctx.env().current_dir()?;
+        let path = sanitize_path_tool_arg(ctx, &self.path);
+        queue!(
+            updates,
+            style::Print("Download: "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.url),
+            style::ResetColor,
+            style::Print("\nTo: "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(format_path(ctx, cwd, &path)),
+            style::ResetColor,
+            style::Print(format!(" (up to {} MB", self.max_size_mb)),
+        )?;
+        if self.sha256.is_some() {
+            queue!(updates, style::Print(", checksum verified"))?;
+        }
+        if self.overwrite && ctx.fs().exists(&path) {
+            queue!(
+                updates,
+                style::Print(", "),
+                style::SetForegroundColor(Color::Yellow),
+                style::Print("replacing the existing file"),
+                style::ResetColor,
+            )?;
+        }
+        queue!(updates, style::Print(")\n"))?;
+        Ok(())
+    }
+
+    pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
+        let url = Url::parse(&self.url).map_err(|e| eyre!("Invalid URL format '{}': {}", self.url, e))?;
+        self.policy.check_url(&url)?;
+
+        if self.path.is_empty() {
+            bail!("Path must not be empty");
+        }
+        let path = sanitize_path_tool_arg(ctx, &self.path);
+        if let Ok(metadata) = ctx.fs().symlink_metadata(&path).await {
+            if !metadata.is_file() {
+                bail!("'{}' exists and is not a file", self.path);
+            }
+            if !self.overwrite {
+                bail!("'{}' already exists, set overwrite to replace it", self.path);
+            }
+        }
+        if let Some(sha256) = &self.sha256 {
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                bail!("sha256 must be 64 hexadecimal characters");
+            }
+        }
+        if !(1..=MAX_DOWNLOAD_SIZE_MB).contains(&self.max_size_mb) {
+            bail!("max_size_mb must be between 1 and {MAX_DOWNLOAD_SIZE_MB}");
+        }
+        if self.timeout == 0 {
+            bail!("timeout must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".part");
+    path.with_file_name(file_name)
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn download(value: serde_json::Value) -> DownloadFile {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().create_dir_all("/data").await.unwrap();
+        ctx.fs().write("/data/existing.csv", "a,b").await.unwrap();
+
+        let mut tool = download(serde_json::json!({
+            "url": "https://example.com/data.csv",
+            "path": "/data/new.csv",
+            "sha256": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+        }));
+        assert!(tool.validate(&ctx).await.is_ok());
+        assert_eq!(tool.max_size_mb, 100);
+
+        tool.sha256 = Some("abc".to_string());
+        assert!(tool.validate(&ctx).await.is_err());
+        tool.sha256 = None;
+
+        tool.max_size_mb = MAX_DOWNLOAD_SIZE_MB + 1;
+        assert!(tool.validate(&ctx).await.is_err());
+        tool.max_size_mb = 1;
+
+        tool.url = "file:///etc/passwd".to_string();
+        assert!(tool.validate(&ctx).await.is_err());
+        tool.url = "http://169.254.169.254/latest/meta-data/".to_string();
+        assert!(tool.validate(&ctx).await.is_err());
+        tool.url = "https://example.com/data.csv".to_string();
+
+        // Existing files are only replaced when asked to.
+        tool.path = "/data/existing.csv".to_string();
+        assert!(tool.validate(&ctx).await.is_err());
+        tool.overwrite = true;
+        assert!(tool.validate(&ctx).await.is_ok());
+        tool.path = "/data".to_string();
+        assert!(tool.validate(&ctx).await.is_err());
+    }
+
+    #[test]
+    fn test_part_path() {
+        assert_eq!(
+            part_path(Path::new("/data/release.tar.gz")),
+            PathBuf::from("/data/release.tar.gz.part")
+        );
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(10 * 1024 * 1024), "10.0 MB");
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::VecDeque;
 use std::io::Write;
+use std::path::PathBuf;
 use std::process::{
     ExitStatus,
     Stdio,
@@ -14,29 +15,66 @@ use crossterm::style::{
 use eyre::{
     Context as EyreContext,
     Result,
+    bail,
 };
 use serde::Deserialize;
 use tokio::io::AsyncBufReadExt;
 use tokio::select;
 use tracing::error;
 
-use super::super::util::truncate_safe;
+use super::super::util::truncate::{
+    Keep,
+    truncate_text,
+};
+use super::env_policy::EnvPolicy;
+use super::output_filter::filter_output;
+use super::remote::ExecutionTarget;
+use super::shell::Shell;
 use super::{
     InvokeOutput,
-    MAX_TOOL_RESPONSE_SIZE,
     OutputKind,
+    sanitize_path_tool_arg,
 };
+#[cfg(unix)]
+use crate::cli::chat::scrollback::TextBuffer;
 use crate::cli::chat::{
     CONTINUATION_LINE,
     PURPOSE_ARROW,
 };
 use crate::platform::Context;
 const READONLY_COMMANDS: &[&str] = &["ls", "cat", "echo", "pwd", "which", "head", "tail", "find", "grep"];
+/// Commands that prompt for input on the terminal, and hang when nobody answers.
+const INTERACTIVE_COMMANDS: &[&str] = &[
+    "sudo", "su", "ssh", "passwd", "login", "vi", "vim", "nvim", "nano", "emacs", "less", "more", "top", "htop", "ftp",
+    "sftp", "telnet",
+];
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ExecuteBash {
     pub command: String,
     pub summary: Option<String>,
+    /// Runs the command attached to the terminal, so that the user can answer its prompts.
+    #[serde(default)]
+    pub interactive: bool,
+    /// Directory to run the command in, the current directory if not set.
+    pub cwd: Option<String>,
+    /// [Self::cwd] once validated, or the current directory of the session.
+    #[serde(skip)]
+    working_dir: Option<PathBuf>,
+    /// Where the command runs, the target of the session once validated.
+    #[serde(skip)]
+    target: ExecutionTarget,
+    #[serde(skip)]
+    pub env_policy: EnvPolicy,
+    #[serde(skip)]
+    pub shell: Shell,
+    /// No terminal is free for the command to prompt on, without a user (`--no-interactive`) or
+    /// while the TUI draws on it.
+    #[serde(skip)]
+    pub headless: bool,
+    /// Runs the command under pseudo-terminals, when its output is shown on a terminal.
+    #[serde(skip)]
+    pub pty: bool,
 }
 
 impl ExecuteBash {
@@ -97,8 +135,44 @@ impl ExecuteBash {
         false
     }
 
-    pub async fn invoke(&self, updates: impl Write) -> Result<InvokeOutput> {
-        let output = run_command(&self.command, MAX_TOOL_RESPONSE_SIZE / 3, Some(updates)).await?;
+    /// Whether the command is known to prompt for input on the terminal, e.g. `sudo` or `ssh`
+    /// unless told not to with `sudo -n` or `ssh -o BatchMode=yes`.
+    pub fn prompts_for_input(&self) -> bool {
+        let Some(args) = shlex::split(&self.command) else {
+            return false;
+        };
+        args.split(|arg| ["|", "&&", "||", ";"].contains(&arg.as_str()))
+            .any(|cmd_args| {
+                // Skips the variables set for the command, as in `EDITOR=vim git commit`.
+                let mut cmd_args = cmd_args.iter().skip_while(|arg| arg.contains('='));
+                let Some(name) = cmd_args.next().map(|cmd| cmd.rsplit('/').next().unwrap_or_default()) else {
+                    return false;
+                };
+                let mut options = cmd_args.map(String::as_str);
+                match name {
+                    "sudo" => !options.any(|arg| ["-n", "--non-interactive", "-S", "--stdin"].contains(&arg)),
+                    "ssh" => !options.any(|arg| arg.contains("BatchMode=yes")),
+                    _ => INTERACTIVE_COMMANDS.contains(&name),
+                }
+            })
+    }
+
+    /// Whether the command runs attached to the terminal, because the model asked for it or the
+    /// command prompts for input. The user is always asked first.
+    pub fn attached(&self) -> bool {
+        self.interactive || self.prompts_for_input()
+    }
+
+    /// Runs the command, keeping at most a third of `max_bytes` of stdout and of stderr.
+    pub async fn invoke(&self, updates: impl Write, max_bytes: usize) -> Result<InvokeOutput> {
+        if self.attached() {
+            return self.invoke_attached(updates).await;
+        }
+        let output = match self.pty {
+            #[cfg(unix)]
+            true => run_command_in_pty(&self.command, self.process()?, max_bytes / 3, updates).await?,
+            _ => run_command(&self.command, self.process()?, max_bytes / 3, Some(updates)).await?,
+        };
         let result = serde_json::json!({
             "exit_status": output.exit_status.unwrap_or(0).to_string(),
             "stdout": output.stdout,
@@ -110,6 +184,71 @@ impl ExecuteBash {
         })
     }
 
+    /// The process running the command in the shell of `chat.shell`, with the variables of the
+    /// environment policy and in [Self::cwd], or on the target of `chat.remoteHost` or
+    /// `chat.container`. Its stdin is the terminal, or nothing when [Self::headless] so that
+    /// commands reading it see its end instead of waiting.
+    fn process(&self) -> Result<tokio::process::Command> {
+        let stdin = || match self.headless {
+            true => Stdio::null(),
+            false => Stdio::inherit(),
+        };
+        if !self.target.is_local() {
+            let mut process = self
+                .target
+                .shell_command(&self.shell, &self.command, self.cwd.as_deref())?;
+            process.stdin(stdin());
+            return Ok(process);
+        }
+        let mut process = self.shell.command(&self.command);
+        process
+            .stdin(stdin())
+            .env_clear()
+            .envs(self.env_policy.passthrough())
+            // Stops the command when the tool times out.
+            .kill_on_drop(true);
+        if let Some(dir) = &self.working_dir {
+            process.current_dir(dir);
+        }
+        Ok(process)
+    }
+
+    /// Runs the command with the terminal as its stdin, stdout and stderr, between banners telling
+    /// the user that they are talking to the command. Its output isn't captured.
+    async fn invoke_attached(&self, mut updates: impl Write) -> Result<InvokeOutput> {
+        queue!(
+            updates,
+            style::SetForegroundColor(Color::Yellow),
+            style::Print("── Attached to the terminal: answer the prompts of the command here ──\n"),
+            style::ResetColor,
+        )?;
+        updates.flush()?;
+
+        let status = self
+            .process()?
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .await
+            .wrap_err_with(|| format!("Unable to spawn command '{}'", self.command))?;
+
+        queue!(
+            updates,
+            style::SetForegroundColor(Color::Yellow),
+            style::Print("── Detached from the terminal ──\n"),
+            style::ResetColor,
+        )?;
+        updates.flush()?;
+
+        Ok(InvokeOutput {
+            output: OutputKind::Json(serde_json::json!({
+                "exit_status": status.code().unwrap_or(0).to_string(),
+                "note": "The command ran attached to the user's terminal, its output was only shown to the user.",
+            })),
+        })
+    }
+
     pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
         queue!(updates, style::Print("I will run the following shell command: "),)?;
 
@@ -141,12 +280,44 @@ impl ExecuteBash {
             )?;
         }
 
+        if self.attached() {
+            queue!(
+                updates,
+                style::SetForegroundColor(Color::Yellow),
+                style::Print("The command will be attached to the terminal to read your input.\n"),
+                style::ResetColor,
+            )?;
+        }
+
         queue!(updates, style::Print("\n"))?;
 
         Ok(())
     }
 
-    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
+    pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
+        self.target = ctx.execution_target().clone();
+        // The directory is checked when the command runs on the remote target.
+        if self.target.is_local() {
+            self.working_dir = match &self.cwd {
+                Some(cwd) => {
+                    let dir = sanitize_path_tool_arg(ctx, cwd);
+                    let metadata = ctx.fs().symlink_metadata(&dir).await;
+                    if !metadata.is_ok_and(|metadata| metadata.is_dir()) {
+                        bail!("The directory '{cwd}' to run the command in does not exist");
+                    }
+                    Some(dir)
+                },
+                // The current directory of the session, which isn't the one of `q` with `--workspace`.
+                None => ctx.env().current_dir().ok(),
+            };
+        }
+        // Fails early rather than hanging on a prompt that nobody can answer.
+        if self.headless && self.attached() {
+            bail!(
+                "The command needs a terminal to prompt for input, which isn't available in this session. Use a \
+                 form that doesn't prompt, e.g. `sudo -n` or `ssh -o BatchMode=yes`, or ask the user to run it."
+            );
+        }
         // TODO: probably some small amount of PATH checking
         Ok(())
     }
@@ -154,28 +325,28 @@ impl ExecuteBash {
 
 pub struct CommandResult {
     pub exit_status: Option<i32>,
-    /// Truncated stdout
+    /// Filtered and truncated stdout, see [filter_output]
     pub stdout: String,
-    /// Truncated stderr
+    /// Filtered and truncated stderr
     pub stderr: String,
 }
 
-/// Run a bash command.
+/// Run a shell command.
 /// # Arguments
+/// * `command` - the command, for the errors
+/// * `process` - the process running the command, see [ExecuteBash::process]
 /// * `max_result_size` - max size of output streams, truncating if required
 /// * `updates` - output stream to push informational messages about the progress
 /// # Returns
 /// A [`CommandResult`]
 pub async fn run_command<W: Write>(
     command: &str,
+    mut process: tokio::process::Command,
     max_result_size: usize,
     mut updates: Option<W>,
 ) -> Result<CommandResult> {
     // We need to maintain a handle on stderr and stdout, but pipe it to the terminal as well
-    let mut child = tokio::process::Command::new("bash")
-        .arg("-c")
-        .arg(command)
-        .stdin(Stdio::inherit())
+    let mut child = process
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
@@ -255,30 +426,88 @@ pub async fn run_command<W: Write>(
 
     Ok(CommandResult {
         exit_status: exit_status.code(),
-        stdout: format!(
-            "{}{}",
-            truncate_safe(&stdout_final, max_result_size),
-            if stdout_final.len() > max_result_size {
-                " ... truncated"
-            } else {
-                ""
-            }
-        ),
-        stderr: format!(
-            "{}{}",
-            truncate_safe(&stderr_final, max_result_size),
-            if stderr_final.len() > max_result_size {
-                " ... truncated"
-            } else {
-                ""
-            }
-        ),
+        stdout: truncate_text(&filter_output(&stdout_final), max_result_size, Keep::HeadAndTail).into_owned(),
+        stderr: truncate_text(&filter_output(&stderr_final), max_result_size, Keep::HeadAndTail).into_owned(),
     })
 }
 
+/// Runs a shell command with its stdout and stderr each attached to a pseudo-terminal, so that it
+/// keeps its colors and progress bars. Its output is streamed to `updates` as is, while the
+/// [`CommandResult`] has it as a [TextBuffer] reads it, without escape sequences and with the lines
+/// redrawn after a `\r` only as they were last drawn.
+#[cfg(unix)]
+async fn run_command_in_pty<W: Write>(
+    command: &str,
+    mut process: tokio::process::Command,
+    max_result_size: usize,
+    mut updates: W,
+) -> Result<CommandResult> {
+    use tokio::io::AsyncReadExt;
+
+    let (stdout_pty, mut stdout) = open_pty()?;
+    let (stderr_pty, mut stderr) = open_pty()?;
+    let child = process.stdout(stdout_pty).stderr(stderr_pty).spawn();
+    // Closes the terminal ends held by the process once spawned, so that reading the output stops
+    // when the command exits.
+    drop(process);
+    let mut child = child.wrap_err_with(|| format!("Unable to spawn command '{}'", command))?;
+
+    let (mut stdout_text, mut stderr_text) = (TextBuffer::default(), TextBuffer::default());
+    let (mut stdout_buf, mut stderr_buf) = ([0u8; 4096], [0u8; 4096]);
+    let (mut stdout_done, mut stderr_done) = (false, false);
+    let exit_status = loop {
+        select! {
+            biased;
+            // Once the command exits, reading fails rather than returning the end of the output.
+            read = stdout.read(&mut stdout_buf), if !stdout_done => match read {
+                Ok(0) | Err(_) => stdout_done = true,
+                Ok(len) => {
+                    updates.write_all(&stdout_buf[..len])?;
+                    updates.flush()?;
+                    stdout_text.push(&stdout_buf[..len]);
+                },
+            },
+            read = stderr.read(&mut stderr_buf), if !stderr_done => match read {
+                Ok(0) | Err(_) => stderr_done = true,
+                Ok(len) => {
+                    updates.write_all(&stderr_buf[..len])?;
+                    updates.flush()?;
+                    stderr_text.push(&stderr_buf[..len]);
+                },
+            },
+            exit_status = child.wait() => {
+                break exit_status;
+            },
+        };
+    }
+    .wrap_err_with(|| format!("No exit status for '{}'", command))?;
+
+    let text = |buffer: TextBuffer| filter_output(&buffer.lines().collect::<Vec<_>>().join("\n"));
+    Ok(CommandResult {
+        exit_status: exit_status.code(),
+        stdout: truncate_text(&text(stdout_text), max_result_size, Keep::HeadAndTail).into_owned(),
+        stderr: truncate_text(&text(stderr_text), max_result_size, Keep::HeadAndTail).into_owned(),
+    })
+}
+
+/// Opens a pseudo-terminal the size of the user's terminal, returning the end the command writes
+/// to and the one its output is read from.
+#[cfg(unix)]
+fn open_pty() -> Result<(std::os::fd::OwnedFd, tokio::fs::File)> {
+    let winsize = crossterm::terminal::size().ok().map(|(cols, rows)| nix::pty::Winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    });
+    let pty = nix::pty::openpty(winsize.as_ref(), None).wrap_err("Unable to open a pseudo-terminal")?;
+    Ok((pty.slave, tokio::fs::File::from_std(std::fs::File::from(pty.master))))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cli::chat::consts::MAX_TOOL_RESPONSE_SIZE;
 
     #[ignore = "todo: fix failing on musl for some reason"]
     #[tokio::test]
@@ -291,7 +520,7 @@ mod tests {
         });
         let out = serde_json::from_value::<ExecuteBash>(v)
             .unwrap()
-            .invoke(&mut stdout)
+            .invoke(&mut stdout, MAX_TOOL_RESPONSE_SIZE)
             .await
             .unwrap();
 
@@ -309,7 +538,7 @@ mod tests {
         });
         let out = serde_json::from_value::<ExecuteBash>(v)
             .unwrap()
-            .invoke(&mut stdout)
+            .invoke(&mut stdout, MAX_TOOL_RESPONSE_SIZE)
             .await
             .unwrap();
 
@@ -328,7 +557,7 @@ mod tests {
         });
         let out = serde_json::from_value::<ExecuteBash>(v)
             .unwrap()
-            .invoke(&mut stdout)
+            .invoke(&mut stdout, MAX_TOOL_RESPONSE_SIZE)
             .await
             .unwrap();
         if let OutputKind::Json(json) = out.output {
@@ -340,6 +569,64 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_prompts_for_input() {
+        let cmds = &[
+            ("sudo apt install jq", true),
+            ("sudo -n apt install jq", false),
+            ("ssh host uptime", true),
+            ("ssh -o BatchMode=yes host uptime", false),
+            ("git log | less", true),
+            ("cd src && EDITOR=vim /usr/bin/vim main.rs", true),
+            ("cargo test", false),
+            ("echo sudo", false),
+        ];
+        let ctx = Context::builder().build_fake();
+        for (cmd, expected) in cmds {
+            let mut tool = serde_json::from_value::<ExecuteBash>(serde_json::json!({
+                "command": cmd,
+            }))
+            .unwrap();
+            assert_eq!(tool.prompts_for_input(), *expected, "{cmd}");
+            tool.headless = true;
+            assert_eq!(tool.validate(&ctx).await.is_err(), *expected, "{cmd}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_headless_stdin() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let mut tool = serde_json::from_value::<ExecuteBash>(serde_json::json!({ "command": "cat" })).unwrap();
+        tool.headless = true;
+        tool.validate(&ctx).await.unwrap();
+        let out = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            tool.invoke(std::io::sink(), MAX_TOOL_RESPONSE_SIZE),
+        )
+        .await
+        .expect("cat should see the end of its stdin")
+        .unwrap();
+        let OutputKind::Json(json) = out.output else {
+            panic!("Expected JSON output");
+        };
+        assert_eq!(json.get("exit_status").unwrap(), &0.to_string());
+        assert_eq!(json.get("stdout").unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn test_validate_cwd() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().create_dir_all("/project/src").await.unwrap();
+        let tool = |cwd: &str| {
+            serde_json::from_value::<ExecuteBash>(serde_json::json!({ "command": "ls", "cwd": cwd })).unwrap()
+        };
+        let mut valid = tool("/project/src");
+        valid.validate(&ctx).await.unwrap();
+        assert_eq!(valid.working_dir, Some(ctx.fs().chroot_path("/project/src")));
+        assert!(tool("/project/missing").validate(&ctx).await.is_err());
+    }
+
     #[test]
     fn test_requires_acceptance_for_readonly_commands() {
         let cmds = &[
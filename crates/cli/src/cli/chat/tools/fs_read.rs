@@ -1,6 +1,7 @@
 use std::collections::VecDeque;
 use std::fs::Metadata;
 use std::io::Write;
+use std::path::PathBuf;
 
 use crossterm::queue;
 use crossterm::style::{
@@ -21,11 +22,16 @@ use tracing::{
     warn,
 };
 
+use super::document::{
+    Document,
+    DocumentKind,
+    MAX_DOCUMENT_SIZE,
+};
 use super::{
     InvokeOutput,
-    MAX_TOOL_RESPONSE_SIZE,
     OutputKind,
     format_path,
+    remote,
     sanitize_path_tool_arg,
 };
 use crate::cli::chat::util::images::{
@@ -33,6 +39,7 @@ use crate::cli::chat::util::images::{
     is_supported_image_type,
     pre_process,
 };
+use crate::cli::chat::util::truncate::truncate_lines;
 use crate::platform::Context;
 
 #[derive(Debug, Clone, Deserialize)]
@@ -42,15 +49,26 @@ pub enum FsRead {
     Directory(FsDirectory),
     Search(FsSearch),
     Image(FsImage),
+    Document(FsDocument),
 }
 
 impl FsRead {
     pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
+        let target = ctx.execution_target();
+        if !target.is_local() {
+            // The file is checked when it is read on the target, the other modes need the files on
+            // this machine.
+            return match self {
+                FsRead::Line(_) | FsRead::Search(_) => Ok(()),
+                _ => bail!("Only the Line and Search modes can read files on {target}, use execute_bash"),
+            };
+        }
         match self {
             FsRead::Line(fs_line) => fs_line.validate(ctx).await,
             FsRead::Directory(fs_directory) => fs_directory.validate(ctx).await,
             FsRead::Search(fs_search) => fs_search.validate(ctx).await,
             FsRead::Image(fs_image) => fs_image.validate(ctx).await,
+            FsRead::Document(fs_document) => fs_document.validate(ctx).await,
         }
     }
 
@@ -60,15 +78,33 @@ impl FsRead {
             FsRead::Directory(fs_directory) => fs_directory.queue_description(updates),
             FsRead::Search(fs_search) => fs_search.queue_description(updates),
             FsRead::Image(fs_image) => fs_image.queue_description(updates),
+            FsRead::Document(fs_document) => fs_document.queue_description(updates),
         }
     }
 
-    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+    /// Files whose content is returned to the model.
+    pub fn file_paths(&self, ctx: &Context) -> Vec<PathBuf> {
         match self {
-            FsRead::Line(fs_line) => fs_line.invoke(ctx, updates).await,
-            FsRead::Directory(fs_directory) => fs_directory.invoke(ctx, updates).await,
+            FsRead::Line(fs_line) => vec![sanitize_path_tool_arg(ctx, &fs_line.path)],
+            FsRead::Search(fs_search) => vec![sanitize_path_tool_arg(ctx, &fs_search.path)],
+            FsRead::Document(fs_document) => vec![sanitize_path_tool_arg(ctx, &fs_document.path)],
+            FsRead::Image(fs_image) => fs_image
+                .image_paths
+                .iter()
+                .map(|path| sanitize_path_tool_arg(ctx, path))
+                .collect(),
+            FsRead::Directory(_) => Vec::new(),
+        }
+    }
+
+    /// Reads at most `max_bytes` of text, see `chat.toolMaxOutputBytes`.
+    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write, max_bytes: usize) -> Result<InvokeOutput> {
+        match self {
+            FsRead::Line(fs_line) => fs_line.invoke(ctx, updates, max_bytes).await,
+            FsRead::Directory(fs_directory) => fs_directory.invoke(ctx, updates, max_bytes).await,
             FsRead::Search(fs_search) => fs_search.invoke(ctx, updates).await,
             FsRead::Image(fs_image) => fs_image.invoke(ctx, updates).await,
+            FsRead::Document(fs_document) => fs_document.invoke(ctx, updates, max_bytes).await,
         }
     }
 }
@@ -140,12 +176,18 @@ impl FsLine {
         if !is_file {
             bail!("'{}' is not a file", self.path);
         }
+        if let Some(kind) = DocumentKind::from_extension(&path) {
+            bail!(
+                "'{}' is a {}, read it with the Document mode instead",
+                self.path,
+                kind.name()
+            );
+        }
         Ok(())
     }
 
     pub async fn queue_description(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
-        let path = sanitize_path_tool_arg(ctx, &self.path);
-        let line_count = ctx.fs().read_to_string(&path).await?.lines().count();
+        let line_count = remote::read_to_string(ctx, &self.path).await?.lines().count();
         queue!(
             updates,
             style::Print("Reading file: "),
@@ -181,10 +223,10 @@ impl FsLine {
         }
     }
 
-    pub async fn invoke(&self, ctx: &Context, _updates: &mut impl Write) -> Result<InvokeOutput> {
+    pub async fn invoke(&self, ctx: &Context, _updates: &mut impl Write, max_bytes: usize) -> Result<InvokeOutput> {
         let path = sanitize_path_tool_arg(ctx, &self.path);
         debug!(?path, "Reading");
-        let file = ctx.fs().read_to_string(&path).await?;
+        let file = remote::read_to_string(ctx, &self.path).await?;
         let line_count = file.lines().count();
         let (start, end) = (
             convert_negative_index(line_count, self.start_line()),
@@ -212,11 +254,21 @@ impl FsLine {
             .join("\n");
 
         let byte_count = file_contents.len();
-        if byte_count > MAX_TOOL_RESPONSE_SIZE {
-            bail!(
-                "This tool only supports reading {MAX_TOOL_RESPONSE_SIZE} bytes at a
+        if byte_count > max_bytes {
+            // Sends the whole lines that fit, with the line to read from next.
+            let text = truncate_lines(&file_contents, max_bytes);
+            if text.is_empty() {
+                bail!(
+                    "This tool only supports reading {max_bytes} bytes at a
 time. You tried to read {byte_count} bytes. Try executing with fewer lines specified."
-            );
+                );
+            }
+            let next_line = start + text.lines().count() + 1;
+            return Ok(InvokeOutput {
+                output: OutputKind::Text(format!(
+                    "{text}[... Truncated to {max_bytes} bytes, read from line {next_line} to continue ...]"
+                )),
+            });
         }
 
         Ok(InvokeOutput {
@@ -233,6 +285,83 @@ time. You tried to read {byte_count} bytes. Try executing with fewer lines speci
     }
 }
 
+/// Read the text of a PDF or Word document.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FsDocument {
+    pub path: String,
+    pub start_page: Option<usize>,
+    pub end_page: Option<usize>,
+}
+
+impl FsDocument {
+    const DEFAULT_START_PAGE: usize = 1;
+
+    pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
+        let path = sanitize_path_tool_arg(ctx, &self.path);
+        if !path.exists() {
+            bail!("'{}' does not exist", self.path);
+        }
+        let metadata = ctx.fs().symlink_metadata(&path).await?;
+        if !metadata.is_file() {
+            bail!("'{}' is not a file", self.path);
+        }
+        if metadata.len() > MAX_DOCUMENT_SIZE {
+            bail!(
+                "'{}' is larger than the {}MB limit for documents",
+                self.path,
+                MAX_DOCUMENT_SIZE / (1024 * 1024)
+            );
+        }
+        if self.start_page == Some(0) {
+            bail!("start_page must be greater than 0");
+        }
+        Ok(())
+    }
+
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        queue!(
+            updates,
+            style::Print("Reading document: "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.path),
+            style::ResetColor,
+        )?;
+        match self.end_page {
+            Some(end_page) => queue!(
+                updates,
+                style::Print(format!(", pages {} to {}", self.start_page(), end_page))
+            )?,
+            None if self.start_page() > 1 => queue!(
+                updates,
+                style::Print(format!(", from page {} to the end", self.start_page()))
+            )?,
+            None => queue!(updates, style::Print(", all pages"))?,
+        }
+        Ok(())
+    }
+
+    pub async fn invoke(&self, ctx: &Context, _updates: &mut impl Write, max_bytes: usize) -> Result<InvokeOutput> {
+        let path = sanitize_path_tool_arg(ctx, &self.path);
+        debug!(?path, "Reading document");
+        let bytes = ctx.fs().read(&path).await?;
+        let Some(kind) = DocumentKind::detect(&bytes) else {
+            bail!("'{}' is not a PDF or Word document", self.path);
+        };
+
+        // Parsing is CPU bound and can take a while for large documents.
+        let document = tokio::task::spawn_blocking(move || Document::parse(kind, &bytes)).await??;
+        let text = document.text(self.start_page(), self.end_page, max_bytes)?;
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(text),
+        })
+    }
+
+    fn start_page(&self) -> usize {
+        self.start_page.unwrap_or(Self::DEFAULT_START_PAGE)
+    }
+}
+
 /// Search in a file.
 #[derive(Debug, Clone, Deserialize)]
 pub struct FsSearch {
@@ -248,7 +377,7 @@ impl FsSearch {
 
     pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
         let path = sanitize_path_tool_arg(ctx, &self.path);
-        let relative_path = format_path(ctx.env().current_dir()?, &path);
+        let relative_path = format_path(ctx, ctx.env().current_dir()?, &path);
         if !path.exists() {
             bail!("File not found: {}", relative_path);
         }
@@ -279,9 +408,9 @@ impl FsSearch {
     pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
         let file_path = sanitize_path_tool_arg(ctx, &self.path);
         let pattern = &self.pattern;
-        let relative_path = format_path(ctx.env().current_dir()?, &file_path);
+        let relative_path = format_path(ctx, ctx.env().current_dir()?, &file_path);
 
-        let file_content = ctx.fs().read_to_string(&file_path).await?;
+        let file_content = remote::read_to_string(ctx, &self.path).await?;
         let lines: Vec<&str> = LinesWithEndings::from(&file_content).collect();
 
         let mut results = Vec::new();
@@ -346,7 +475,7 @@ impl FsDirectory {
 
     pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
         let path = sanitize_path_tool_arg(ctx, &self.path);
-        let relative_path = format_path(ctx.env().current_dir()?, &path);
+        let relative_path = format_path(ctx, ctx.env().current_dir()?, &path);
         if !path.exists() {
             bail!("Directory not found: {}", relative_path);
         }
@@ -372,7 +501,7 @@ impl FsDirectory {
         )?)
     }
 
-    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write, max_bytes: usize) -> Result<InvokeOutput> {
         let path = sanitize_path_tool_arg(ctx, &self.path);
         let cwd = ctx.env().current_dir()?;
         let max_depth = self.depth();
@@ -384,7 +513,7 @@ impl FsDirectory {
             if depth > max_depth {
                 break;
             }
-            let relative_path = format_path(&cwd, &path);
+            let relative_path = format_path(ctx, &cwd, &path);
             if !relative_path.is_empty() {
                 queue!(
                     updates,
@@ -464,9 +593,9 @@ impl FsDirectory {
         let file_count = result.len();
         let result = result.join("\n");
         let byte_count = result.len();
-        if byte_count > MAX_TOOL_RESPONSE_SIZE {
+        if byte_count > max_bytes {
             bail!(
-                "This tool only supports reading up to {MAX_TOOL_RESPONSE_SIZE} bytes at a time. You tried to read {byte_count} bytes ({file_count} files). Try executing with fewer lines specified."
+                "This tool only supports reading up to {max_bytes} bytes at a time. You tried to read {byte_count} bytes ({file_count} files). Try executing with fewer lines specified."
             );
         }
 
@@ -536,6 +665,7 @@ mod tests {
     use std::sync::Arc;
 
     use super::*;
+    use crate::cli::chat::consts::MAX_TOOL_RESPONSE_SIZE;
 
     const TEST_FILE_CONTENTS: &str = "\
 1: Hello world!
@@ -597,6 +727,10 @@ mod tests {
             serde_json::json!({ "path": "/test_file.txt", "mode": "Search", "pattern": "hello" }),
         )
         .unwrap();
+        serde_json::from_value::<FsRead>(
+            serde_json::json!({ "path": "/design.pdf", "mode": "Document", "start_page": 2, "end_page": 4 }),
+        )
+        .unwrap();
     }
 
     #[tokio::test]
@@ -615,7 +749,7 @@ mod tests {
                 });
                 let output = serde_json::from_value::<FsRead>(v)
                     .unwrap()
-                    .invoke(&ctx, &mut stdout)
+                    .invoke(&ctx, &mut stdout, MAX_TOOL_RESPONSE_SIZE)
                     .await
                     .unwrap();
 
@@ -636,6 +770,35 @@ mod tests {
         assert_lines!(2, None::<i32>, lines[1..]);
     }
 
+    #[tokio::test]
+    async fn test_fs_read_line_truncated() {
+        let ctx = setup_test_directory().await;
+        let mut stdout = std::io::stdout();
+        let v = serde_json::json!({
+            "path": TEST_FILE_PATH,
+            "mode": "Line",
+            "start_line": 2,
+        });
+        let output = serde_json::from_value::<FsRead>(v)
+            .unwrap()
+            .invoke(&ctx, &mut stdout, 30)
+            .await
+            .unwrap();
+        assert_eq!(
+            output.as_str(),
+            "2: This is line 2\n3: asdf\n[... Truncated to 30 bytes, read from line 4 to continue ...]"
+        );
+
+        let v = serde_json::json!({ "path": TEST_FILE_PATH, "mode": "Line" });
+        assert!(
+            serde_json::from_value::<FsRead>(v)
+                .unwrap()
+                .invoke(&ctx, &mut stdout, 5)
+                .await
+                .is_err()
+        );
+    }
+
     #[tokio::test]
     async fn test_fs_read_line_past_eof() {
         let ctx = setup_test_directory().await;
@@ -649,7 +812,7 @@ mod tests {
         assert!(
             serde_json::from_value::<FsRead>(v)
                 .unwrap()
-                .invoke(&ctx, &mut stdout)
+                .invoke(&ctx, &mut stdout, MAX_TOOL_RESPONSE_SIZE)
                 .await
                 .is_err()
         );
@@ -680,7 +843,7 @@ mod tests {
         });
         let output = serde_json::from_value::<FsRead>(v)
             .unwrap()
-            .invoke(&ctx, &mut stdout)
+            .invoke(&ctx, &mut stdout, MAX_TOOL_RESPONSE_SIZE)
             .await
             .unwrap();
 
@@ -698,7 +861,7 @@ mod tests {
         });
         let output = serde_json::from_value::<FsRead>(v)
             .unwrap()
-            .invoke(&ctx, &mut stdout)
+            .invoke(&ctx, &mut stdout, MAX_TOOL_RESPONSE_SIZE)
             .await
             .unwrap();
 
@@ -724,7 +887,7 @@ mod tests {
                 let v = serde_json::json!($value);
                 let output = serde_json::from_value::<FsRead>(v)
                     .unwrap()
-                    .invoke(&ctx, &mut stdout)
+                    .invoke(&ctx, &mut stdout, MAX_TOOL_RESPONSE_SIZE)
                     .await
                     .unwrap();
 
@@ -1,5 +1,8 @@
 use std::io::Write;
-use std::path::Path;
+use std::path::{
+    Path,
+    PathBuf,
+};
 use std::sync::LazyLock;
 
 use crossterm::queue;
@@ -13,7 +16,10 @@ use eyre::{
     bail,
     eyre,
 };
-use serde::Deserialize;
+use serde::{
+    Deserialize,
+    Serialize,
+};
 use similar::DiffableStr;
 use syntect::easy::HighlightLines;
 use syntect::highlighting::ThemeSet;
@@ -30,14 +36,25 @@ use tracing::{
 use super::{
     InvokeOutput,
     format_path,
+    remote,
     sanitize_path_tool_arg,
     supports_truecolor,
 };
+use crate::cli::chat::accessibility;
 use crate::platform::Context;
 
 static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
 static THEME_SET: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
 
+/// A change to a file, see [FsWrite::preview].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileChange {
+    pub path: PathBuf,
+    /// [None] when the file is created.
+    pub old_text: Option<String>,
+    pub new_text: String,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "command")]
 pub enum FsWrite {
@@ -66,112 +83,162 @@ pub enum FsWrite {
 }
 
 impl FsWrite {
+    /// Writes the file on the host the tools run on, see [remote].
     pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
-        let fs = ctx.fs();
         let cwd = ctx.env().current_dir()?;
         match self {
-            FsWrite::Create { path, .. } => {
-                let file_text = self.canonical_create_command_text();
-                let path = sanitize_path_tool_arg(ctx, path);
-                if let Some(parent) = path.parent() {
-                    fs.create_dir_all(parent).await?;
-                }
+            FsWrite::Create { path: raw_path, .. } => {
+                let file_text = self.updated_content("")?;
+                let path = sanitize_path_tool_arg(ctx, raw_path);
 
-                let invoke_description = if fs.exists(&path) { "Replacing: " } else { "Creating: " };
+                let invoke_description = if remote::exists(ctx, raw_path).await {
+                    "Replacing: "
+                } else {
+                    "Creating: "
+                };
                 queue!(
                     updates,
                     style::Print(invoke_description),
                     style::SetForegroundColor(Color::Green),
-                    style::Print(format_path(cwd, &path)),
+                    style::Print(format_path(ctx, cwd, &path)),
                     style::ResetColor,
                     style::Print("\n"),
                 )?;
 
-                write_to_file(ctx, path, file_text).await?;
+                write_to_file(ctx, raw_path, file_text).await?;
                 Ok(Default::default())
             },
-            FsWrite::StrReplace { path, old_str, new_str } => {
-                let path = sanitize_path_tool_arg(ctx, path);
-                let file = fs.read_to_string(&path).await?;
-                let matches = file.match_indices(old_str).collect::<Vec<_>>();
+            FsWrite::StrReplace { path: raw_path, .. } => {
+                let path = sanitize_path_tool_arg(ctx, raw_path);
+                let file = remote::read_to_string(ctx, raw_path).await?;
                 queue!(
                     updates,
                     style::Print("Updating: "),
                     style::SetForegroundColor(Color::Green),
-                    style::Print(format_path(cwd, &path)),
+                    style::Print(format_path(ctx, cwd, &path)),
                     style::ResetColor,
                     style::Print("\n"),
                 )?;
-                match matches.len() {
-                    0 => Err(eyre!("no occurrences of \"{old_str}\" were found")),
-                    1 => {
-                        let file = file.replacen(old_str, new_str, 1);
-                        fs.write(path, file).await?;
-                        Ok(Default::default())
-                    },
-                    x => Err(eyre!("{x} occurrences of old_str were found when only 1 is expected")),
-                }
+                remote::write(ctx, raw_path, &self.updated_content(&file)?).await?;
+                Ok(Default::default())
             },
-            FsWrite::Insert {
-                path,
-                insert_line,
-                new_str,
-            } => {
-                let path = sanitize_path_tool_arg(ctx, path);
-                let mut file = fs.read_to_string(&path).await?;
+            FsWrite::Insert { path: raw_path, .. } => {
+                let path = sanitize_path_tool_arg(ctx, raw_path);
+                let file = remote::read_to_string(ctx, raw_path).await?;
                 queue!(
                     updates,
                     style::Print("Updating: "),
                     style::SetForegroundColor(Color::Green),
-                    style::Print(format_path(cwd, &path)),
+                    style::Print(format_path(ctx, cwd, &path)),
                     style::ResetColor,
                     style::Print("\n"),
                 )?;
 
-                // Get the index of the start of the line to insert at.
-                let num_lines = file.lines().enumerate().map(|(i, _)| i + 1).last().unwrap_or(1);
-                let insert_line = insert_line.clamp(&0, &num_lines);
-                let mut i = 0;
-                for _ in 0..*insert_line {
-                    let line_len = &file[i..].find("\n").map_or(file[i..].len(), |i| i + 1);
-                    i += line_len;
-                }
-                file.insert_str(i, new_str);
-                write_to_file(ctx, &path, file).await?;
+                write_to_file(ctx, raw_path, self.updated_content(&file)?).await?;
                 Ok(Default::default())
             },
-            FsWrite::Append { path, new_str } => {
-                let path = sanitize_path_tool_arg(ctx, path);
+            FsWrite::Append { path: raw_path, .. } => {
+                let path = sanitize_path_tool_arg(ctx, raw_path);
 
                 queue!(
                     updates,
                     style::Print("Appending to: "),
                     style::SetForegroundColor(Color::Green),
-                    style::Print(format_path(cwd, &path)),
+                    style::Print(format_path(ctx, cwd, &path)),
                     style::ResetColor,
                     style::Print("\n"),
                 )?;
 
-                let mut file = fs.read_to_string(&path).await?;
+                let file = remote::read_to_string(ctx, raw_path).await?;
+                write_to_file(ctx, raw_path, self.updated_content(&file)?).await?;
+                Ok(Default::default())
+            },
+        }
+    }
+
+    /// Returns the change this write would make, without writing the file.
+    pub async fn preview(&self, ctx: &Context) -> Result<FileChange> {
+        let path = sanitize_path_tool_arg(ctx, self.path());
+        let old_text = match self {
+            FsWrite::Create { .. } if !remote::exists(ctx, self.path()).await => None,
+            _ => Some(remote::read_to_string(ctx, self.path()).await?),
+        };
+        let new_text = self.updated_content(old_text.as_deref().unwrap_or_default())?;
+        Ok(FileChange {
+            path,
+            old_text,
+            new_text,
+        })
+    }
+
+    /// Describes the change this write would make as a unified diff, for `q chat --dry-run`.
+    pub async fn dry_run(&self, ctx: &Context) -> Result<String> {
+        let change = self.preview(ctx).await?;
+        let path = format_path(ctx, ctx.env().current_dir()?, &change.path);
+        let old_text = change.old_text.as_deref().unwrap_or_default();
+        let diff = similar::TextDiff::from_lines(old_text, change.new_text.as_str())
+            .unified_diff()
+            .header(&path, &path)
+            .to_string();
+        let action = match change.old_text {
+            Some(_) => "changed",
+            None => "created",
+        };
+        Ok(format!(
+            "Dry run: {path} was not {action}. The write would make these changes:\n\n```diff\n{diff}```"
+        ))
+    }
+
+    /// Returns the content of the file after this write, given its current content.
+    fn updated_content(&self, file: &str) -> Result<String> {
+        let mut content = match self {
+            FsWrite::Create { .. } => self.canonical_create_command_text(),
+            FsWrite::StrReplace { old_str, new_str, .. } => {
+                return match file.match_indices(old_str).count() {
+                    0 => Err(eyre!("no occurrences of \"{old_str}\" were found")),
+                    1 => Ok(file.replacen(old_str, new_str, 1)),
+                    x => Err(eyre!("{x} occurrences of old_str were found when only 1 is expected")),
+                };
+            },
+            FsWrite::Insert {
+                insert_line, new_str, ..
+            } => {
+                // Get the index of the start of the line to insert at.
+                let num_lines = file.lines().enumerate().map(|(i, _)| i + 1).last().unwrap_or(1);
+                let insert_line = insert_line.clamp(&0, &num_lines);
+                let mut i = 0;
+                for _ in 0..*insert_line {
+                    let line_len = &file[i..].find("\n").map_or(file[i..].len(), |i| i + 1);
+                    i += line_len;
+                }
+                let mut file = file.to_string();
+                file.insert_str(i, new_str);
+                file
+            },
+            FsWrite::Append { new_str, .. } => {
+                let mut file = file.to_string();
                 if !file.ends_with_newline() {
                     file.push('\n');
                 }
                 file.push_str(new_str);
-                write_to_file(ctx, path, file).await?;
-                Ok(Default::default())
+                file
             },
+        };
+        if !content.ends_with_newline() {
+            content.push('\n');
         }
+        Ok(content)
     }
 
-    pub fn queue_description(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
+    pub async fn queue_description(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
         let cwd = ctx.env().current_dir()?;
         self.print_relative_path(ctx, updates)?;
         match self {
             FsWrite::Create { path, .. } => {
                 let file_text = self.canonical_create_command_text();
-                let relative_path = format_path(cwd, path);
-                let prev = if ctx.fs().exists(path) {
-                    let file = ctx.fs().read_to_string_sync(path)?;
+                let relative_path = format_path(ctx, cwd, path);
+                let prev = if remote::exists(ctx, path).await {
+                    let file = remote::read_to_string(ctx, path).await?;
                     stylize_output_if_able(ctx, path, &file)
                 } else {
                     Default::default()
@@ -185,8 +252,8 @@ impl FsWrite {
                 insert_line,
                 new_str,
             } => {
-                let relative_path = format_path(cwd, path);
-                let file = ctx.fs().read_to_string_sync(&relative_path)?;
+                let relative_path = format_path(ctx, cwd, path);
+                let file = remote::read_to_string(ctx, path).await?;
 
                 // Diff the old with the new by adding extra context around the line being inserted
                 // at.
@@ -204,8 +271,8 @@ impl FsWrite {
                 Ok(())
             },
             FsWrite::StrReplace { path, old_str, new_str } => {
-                let relative_path = format_path(cwd, path);
-                let file = ctx.fs().read_to_string_sync(&relative_path)?;
+                let relative_path = format_path(ctx, cwd, path);
+                let file = remote::read_to_string(ctx, path).await?;
                 let (start_line, _) = match line_number_at(&file, old_str) {
                     Some((start_line, end_line)) => (start_line, end_line),
                     _ => (0, 0),
@@ -217,8 +284,8 @@ impl FsWrite {
                 Ok(())
             },
             FsWrite::Append { path, new_str } => {
-                let relative_path = format_path(cwd, path);
-                let start_line = ctx.fs().read_to_string_sync(&relative_path)?.lines().count() + 1;
+                let relative_path = format_path(ctx, cwd, path);
+                let start_line = remote::read_to_string(ctx, path).await?.lines().count() + 1;
                 let file = stylize_output_if_able(ctx, &relative_path, new_str);
                 print_diff(updates, &Default::default(), &file, start_line)?;
                 Ok(())
@@ -235,7 +302,8 @@ impl FsWrite {
             },
             FsWrite::StrReplace { path, .. } | FsWrite::Insert { path, .. } => {
                 let path = sanitize_path_tool_arg(ctx, path);
-                if !path.exists() {
+                // The file is checked when it is read on the remote target.
+                if ctx.execution_target().is_local() && !path.exists() {
                     bail!("The provided path must exist in order to replace or insert contents into it")
                 }
             },
@@ -252,15 +320,19 @@ impl FsWrite {
         Ok(())
     }
 
-    fn print_relative_path(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
-        let cwd = ctx.env().current_dir()?;
-        let path = match self {
+    /// The path of the file being written, as provided by the model.
+    pub fn path(&self) -> &str {
+        match self {
             FsWrite::Create { path, .. } => path,
             FsWrite::StrReplace { path, .. } => path,
             FsWrite::Insert { path, .. } => path,
             FsWrite::Append { path, .. } => path,
-        };
-        let relative_path = format_path(cwd, path);
+        }
+    }
+
+    fn print_relative_path(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
+        let cwd = ctx.env().current_dir()?;
+        let relative_path = format_path(ctx, cwd, self.path());
         queue!(
             updates,
             style::Print("Path: "),
@@ -293,12 +365,11 @@ impl FsWrite {
 }
 
 /// Writes `content` to `path`, adding a newline if necessary.
-async fn write_to_file(ctx: &Context, path: impl AsRef<Path>, mut content: String) -> Result<()> {
+async fn write_to_file(ctx: &Context, path: &str, mut content: String) -> Result<()> {
     if !content.ends_with_newline() {
         content.push('\n');
     }
-    ctx.fs().write(path.as_ref(), content).await?;
-    Ok(())
+    remote::write(ctx, path, &content).await
 }
 
 /// Returns a prefix/suffix pair before and after the content dictated by `[start_line, end_line]`
@@ -368,6 +439,26 @@ fn print_diff(
 ) -> Result<()> {
     let diff = similar::TextDiff::from_lines(&old_str.content, &new_str.content);
 
+    // Screen readers get each line labelled in words rather than a colored gutter.
+    if accessibility::enabled() {
+        for change in diff.iter_all_changes() {
+            let index = match change.tag() {
+                similar::ChangeTag::Delete => change.old_index(),
+                _ => change.new_index(),
+            };
+            queue!(
+                updates,
+                style::Print(format!(
+                    "{} line {}: {}\n",
+                    accessibility::diff_label(change.tag()),
+                    index.unwrap_or_default() + start_line,
+                    change.value().trim_end()
+                )),
+            )?;
+        }
+        return Ok(());
+    }
+
     // First, get the gutter width required for both the old and new lines.
     let (mut max_old_i, mut max_new_i) = (1, 1);
     for change in diff.iter_all_changes() {
@@ -929,6 +1020,55 @@ mod tests {
         assert!(result.is_err(), "Appending to non-existent file should fail");
     }
 
+    #[tokio::test]
+    async fn test_fs_write_preview() {
+        let ctx = setup_test_directory().await;
+
+        let fs_write = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "path": TEST_FILE_PATH,
+            "command": "append",
+            "new_str": "5: Appended line",
+        }))
+        .unwrap();
+        let change = fs_write.preview(&ctx).await.unwrap();
+        assert_eq!(change.old_text.as_deref(), Some(TEST_FILE_CONTENTS));
+        assert_eq!(change.new_text, format!("{}5: Appended line\n", TEST_FILE_CONTENTS));
+        assert_eq!(
+            ctx.fs().read_to_string(TEST_FILE_PATH).await.unwrap(),
+            TEST_FILE_CONTENTS
+        );
+
+        let fs_write = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "path": "/new-file",
+            "command": "create",
+            "file_text": "Hello",
+        }))
+        .unwrap();
+        let change = fs_write.preview(&ctx).await.unwrap();
+        assert_eq!(change.old_text, None);
+        assert_eq!(change.new_text, "Hello\n");
+    }
+
+    #[tokio::test]
+    async fn test_fs_write_dry_run() {
+        let ctx = setup_test_directory().await;
+
+        let fs_write = serde_json::from_value::<FsWrite>(serde_json::json!({
+            "path": TEST_FILE_PATH,
+            "command": "str_replace",
+            "old_str": "3: asdf",
+            "new_str": "3: qwer",
+        }))
+        .unwrap();
+        let description = fs_write.dry_run(&ctx).await.unwrap();
+        assert!(description.contains("was not changed"), "{description}");
+        assert!(description.contains("-3: asdf\n+3: qwer\n"), "{description}");
+        assert_eq!(
+            ctx.fs().read_to_string(TEST_FILE_PATH).await.unwrap(),
+            TEST_FILE_CONTENTS
+        );
+    }
+
     #[test]
     fn test_lines_with_context() {
         let content = "Hello\nWorld!\nhow\nare\nyou\ntoday?";
@@ -18,8 +18,10 @@ use serde::Deserialize;
 
 use super::super::context::ContextManager;
 use super::super::util::issue::IssueCreator;
+use super::super::util::redact;
 use super::{
     InvokeOutput,
+    OutputKind,
     ToolPermission,
 };
 use crate::cli::chat::token_counter::TokenCounter;
@@ -34,6 +36,17 @@ pub struct GhIssue {
 
     #[serde(skip_deserializing)]
     pub context: Option<GhIssueContext>,
+    #[serde(skip)]
+    review: IssueReview,
+}
+
+/// What the user decided after reviewing the issue, see [GhIssue::review].
+#[derive(Debug, Clone, Default)]
+enum IssueReview {
+    #[default]
+    Pending,
+    Submit(IssueCreator),
+    Cancelled,
 }
 
 #[derive(Debug, Clone)]
@@ -49,7 +62,38 @@ pub struct GhIssueContext {
 const MAX_TRANSCRIPT_CHAR_LEN: usize = 3_000;
 
 impl GhIssue {
+    /// Submits the issue, with the secrets and personal information redacted unless the user
+    /// reviewed it with [Self::review].
     pub async fn invoke(&self, _updates: impl Write) -> Result<InvokeOutput> {
+        let issue = match &self.review {
+            IssueReview::Pending => self.issue(|text| Ok(Some(redact::redact(text)))).await?,
+            IssueReview::Submit(issue) => Some(issue.clone()),
+            IssueReview::Cancelled => None,
+        };
+        let Some(issue) = issue else {
+            return Ok(InvokeOutput {
+                output: OutputKind::Text("The user cancelled the issue report.".to_string()),
+            });
+        };
+
+        let _ = issue.create_url().await.wrap_err("failed to invoke gh issue tool");
+
+        Ok(Default::default())
+    }
+
+    /// Has the excerpts of the conversation reviewed with `review`, e.g. [redact::review] on the
+    /// prompt of the chat, before the issue is submitted.
+    pub async fn review(&mut self, review: impl FnOnce(&str) -> Result<Option<String>>) -> Result<()> {
+        self.review = match self.issue(review).await? {
+            Some(issue) => IssueReview::Submit(issue),
+            None => IssueReview::Cancelled,
+        };
+        Ok(())
+    }
+
+    /// The issue with the excerpts of the conversation returned by `review`, [None] when the user
+    /// cancels. The details of the session are always redacted.
+    async fn issue(&self, review: impl FnOnce(&str) -> Result<Option<String>>) -> Result<Option<IssueCreator>> {
         let Some(context) = self.context.as_ref() else {
             return Err(eyre!(
                 "report_issue: Required tool context (GhIssueContext) not set by the program."
@@ -69,19 +113,17 @@ impl GhIssue {
             || Self::get_transcript(context),
             |behavior| format!("{behavior}\n\n{}\n", Self::get_transcript(context)),
         );
+        let Some(actual_behavior) = review(&actual_behavior)? else {
+            return Ok(None);
+        };
 
-        let _ = IssueCreator {
+        Ok(Some(IssueCreator {
             title: Some(self.title.clone()),
             expected_behavior: self.expected_behavior.clone(),
             actual_behavior: Some(actual_behavior),
             steps_to_reproduce: self.steps_to_reproduce.clone(),
-            additional_environment: Some(additional_environment),
-        }
-        .create_url()
-        .await
-        .wrap_err("failed to invoke gh issue tool");
-
-        Ok(Default::default())
+            additional_environment: Some(redact::redact(&additional_environment)),
+        }))
     }
 
     pub fn set_context(&mut self, context: GhIssueContext) {
@@ -220,3 +262,55 @@ impl GhIssue {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_issue_is_redacted() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let mut context_manager = ContextManager::new(ctx, None).await.unwrap();
+        context_manager
+            .global_config
+            .paths
+            .push("/home/jane/notes.md".to_string());
+        let mut gh_issue = GhIssue {
+            title: "The deploy fails".to_string(),
+            expected_behavior: None,
+            actual_behavior: Some("export AWS_SECRET_ACCESS_KEY=abcd1234efgh5678".to_string()),
+            steps_to_reproduce: None,
+            context: None,
+            review: IssueReview::Pending,
+        };
+        gh_issue.set_context(GhIssueContext {
+            context_manager: Some(context_manager),
+            transcript: VecDeque::from(["> mail jane.doe@example.com about it".to_string()]),
+            failed_request_ids: Vec::new(),
+            tool_permissions: HashMap::new(),
+            interactive: false,
+        });
+
+        let issue = gh_issue
+            .issue(|text| Ok(Some(redact::redact(text))))
+            .await
+            .unwrap()
+            .unwrap();
+        let actual_behavior = issue.actual_behavior.unwrap();
+        assert!(!actual_behavior.contains("abcd1234efgh5678"));
+        assert!(actual_behavior.contains("mail <email> about it"));
+        let additional_environment = issue.additional_environment.unwrap();
+        assert!(additional_environment.contains("/home/<user>/notes.md"));
+        assert!(!additional_environment.contains("jane"));
+
+        // The details of the session are redacted even when the user reviewed the excerpts.
+        gh_issue.review(|_| Ok(Some("reviewed".to_string()))).await.unwrap();
+        let IssueReview::Submit(issue) = &gh_issue.review else {
+            panic!("the issue should be submitted");
+        };
+        assert_eq!(issue.actual_behavior.as_deref(), Some("reviewed"));
+        assert!(!issue.additional_environment.as_ref().unwrap().contains("jane"));
+        gh_issue.review(|_| Ok(None)).await.unwrap();
+        assert!(matches!(gh_issue.review, IssueReview::Cancelled));
+    }
+}
@@ -0,0 +1,312 @@
+//! Renders pages in a local headless Chrome, for pages that only show their content once their
+//! JavaScript runs. Needs the `headless-browser` feature and the `chat.web.headlessBrowser`
+//! setting; Chrome is found on the usual install paths or through the `CHROME` environment
+//! variable.
+//!
+//! Chrome follows redirects and runs the JavaScript of the page itself, so every request it makes
+//! is paused with the CDP `Fetch` domain and checked against the [UrlPolicy] first, failing the
+//! ones it denies. Chrome uses `network.proxy` and trusts the certificates of `network.caBundle`.
+
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use eyre::Result;
+use rustls::RootCertStore;
+use rustls::pki_types::CertificateDer;
+use sha2::{
+    Digest,
+    Sha256,
+};
+use url::Url;
+
+use super::url_policy::UrlPolicy;
+
+/// Size of the browser window, and so of screenshots.
+#[cfg_attr(not(feature = "headless-browser"), allow(dead_code))]
+const VIEWPORT: (u32, u32) = (1280, 800);
+
+#[derive(Debug, Clone)]
+pub struct RenderedPage {
+    /// The URL the browser ended up on after redirects.
+    pub url: String,
+    /// The text of the page as rendered.
+    pub text: String,
+    /// A PNG screenshot of the viewport, if requested.
+    pub screenshot: Option<Vec<u8>>,
+    /// The requests of the page denied by the policy.
+    pub blocked: Vec<String>,
+}
+
+/// Whether this build includes the headless browser.
+pub fn is_available() -> bool {
+    cfg!(feature = "headless-browser")
+}
+
+/// Navigates to `url`, waiting up to `timeout` for the page to load, and returns its text. Every
+/// request of the page is checked against `policy`.
+#[cfg(feature = "headless-browser")]
+pub async fn render(url: &Url, timeout: Duration, screenshot: bool, policy: &UrlPolicy) -> Result<RenderedPage> {
+    use std::ffi::OsStr;
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+
+    use eyre::eyre;
+    use headless_chrome::browser::tab::RequestPausedDecision;
+    use headless_chrome::protocol::cdp::Fetch::events::RequestPausedEvent;
+    use headless_chrome::protocol::cdp::Fetch::{
+        FailRequest,
+        RequestPattern,
+        RequestStage,
+    };
+    use headless_chrome::protocol::cdp::Network::ErrorReason;
+    use headless_chrome::protocol::cdp::Page::CaptureScreenshotFormatOption;
+    use headless_chrome::{
+        Browser,
+        LaunchOptions,
+    };
+    use tracing::warn;
+
+    let url = url.to_string();
+    let policy = policy.clone();
+    let runtime = tokio::runtime::Handle::current();
+    let proxy = crate::request::proxy_server();
+    let spki_list = spki_list(&crate::request::extra_ca_certs());
+    tokio::task::spawn_blocking(move || {
+        // Keep cross-site frames in the process of the page, where their requests are paused too
+        let mut args = vec!["--disable-features=IsolateOrigins,site-per-process".to_string()];
+        if let Some((_, no_proxy)) = proxy.as_ref().filter(|(_, no_proxy)| !no_proxy.is_empty()) {
+            args.push(format!("--proxy-bypass-list={}", no_proxy.join(";")));
+        }
+        if let Some(spki_list) = spki_list {
+            args.push(format!("--ignore-certificate-errors-spki-list={spki_list}"));
+        }
+        let options = LaunchOptions::default_builder()
+            .headless(true)
+            .window_size(Some(VIEWPORT))
+            .idle_browser_timeout(timeout)
+            .ignore_certificate_errors(false)
+            .proxy_server(proxy.as_ref().map(|(server, _)| server.as_str()))
+            .args(args.iter().map(OsStr::new).collect())
+            .build()
+            .map_err(|err| eyre!("Invalid browser options: {err}"))?;
+        let browser = Browser::new(options).map_err(|err| eyre!("Failed to launch Chrome: {err}"))?;
+        let tab = browser.new_tab().map_err(|err| eyre!("Failed to open a tab: {err}"))?;
+        tab.set_default_timeout(timeout);
+
+        let blocked = Arc::new(Mutex::new(Vec::new()));
+        let blocked_requests = Arc::clone(&blocked);
+        tab.enable_request_interception(Arc::new(move |_transport, _session_id, event: RequestPausedEvent| {
+            let request_url = event.params.request.url;
+            match runtime.block_on(check_request(&policy, &request_url)) {
+                Ok(()) => RequestPausedDecision::Continue(None),
+                Err(err) => {
+                    warn!(?err, request_url, "Blocking a request of the headless browser");
+                    blocked_requests.lock().unwrap().push(request_url);
+                    RequestPausedDecision::Fail(FailRequest {
+                        request_id: event.params.request_id,
+                        error_reason: ErrorReason::BlockedByClient,
+                    })
+                },
+            }
+        }))
+        .and_then(|_| {
+            tab.enable_fetch(
+                Some(&[RequestPattern {
+                    url_pattern: Some("*".to_string()),
+                    resource_Type: None,
+                    request_stage: Some(RequestStage::Request),
+                }]),
+                None,
+            )
+        })
+        .map_err(|err| eyre!("Failed to intercept the requests of the browser: {err}"))?;
+
+        if let Err(err) = tab.navigate_to(&url).and_then(|tab| tab.wait_until_navigated()) {
+            let blocked = blocked.lock().unwrap();
+            return Err(match blocked.first() {
+                Some(request) => eyre!("Failed to load {url}, the policy blocked {request}: {err}"),
+                None => eyre!("Failed to load {url}: {err}"),
+            });
+        }
+
+        let text = tab
+            .evaluate("document.body ? document.body.innerText : ''", false)
+            .map_err(|err| eyre!("Failed to read the page text: {err}"))?
+            .value
+            .and_then(|value| value.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let screenshot = if screenshot {
+            let png = tab
+                .capture_screenshot(CaptureScreenshotFormatOption::Png, None, None, true)
+                .map_err(|err| eyre!("Failed to take a screenshot: {err}"))?;
+            Some(png)
+        } else {
+            None
+        };
+
+        let blocked = blocked.lock().unwrap().clone();
+        Ok(RenderedPage {
+            url: tab.get_url(),
+            text,
+            screenshot,
+            blocked,
+        })
+    })
+    .await?
+}
+
+#[cfg(not(feature = "headless-browser"))]
+pub async fn render(_url: &Url, _timeout: Duration, _screenshot: bool, _policy: &UrlPolicy) -> Result<RenderedPage> {
+    eyre::bail!("This build does not include the headless browser, it needs the headless-browser feature")
+}
+
+/// Checks a request of the browser against the policy. Only requests leaving the page are
+/// checked, `data:` and `blob:` URLs are read from the page itself.
+#[cfg_attr(not(feature = "headless-browser"), allow(dead_code))]
+async fn check_request(policy: &UrlPolicy, url: &str) -> Result<()> {
+    let url = Url::parse(url)?;
+    match url.scheme() {
+        "data" | "blob" => Ok(()),
+        _ => policy.check(&url).await.map(|_| ()),
+    }
+}
+
+/// The value of `--ignore-certificate-errors-spki-list` trusting `certs`, since Chrome can't be
+/// given CA certificates: the base64 SHA-256 digests of their public keys.
+#[cfg_attr(not(feature = "headless-browser"), allow(dead_code))]
+fn spki_list(certs: &[CertificateDer<'static>]) -> Option<String> {
+    let mut store = RootCertStore::empty();
+    store.add_parsable_certificates(certs.iter().cloned());
+    let digests = store
+        .roots
+        .iter()
+        .map(|root| STANDARD.encode(Sha256::digest(der_sequence(&root.subject_public_key_info))))
+        .collect::<Vec<_>>();
+    (!digests.is_empty()).then(|| digests.join(","))
+}
+
+/// Wraps `contents` in a DER SEQUENCE, which trust anchors leave out of their public key.
+#[cfg_attr(not(feature = "headless-browser"), allow(dead_code))]
+fn der_sequence(contents: &[u8]) -> Vec<u8> {
+    let mut der = vec![0x30];
+    match contents.len() {
+        len @ 0..0x80 => der.push(len as u8),
+        len => {
+            let bytes = len.to_be_bytes();
+            let bytes = &bytes[bytes.iter().position(|byte| *byte != 0).unwrap_or(bytes.len() - 1)..];
+            der.push(0x80 | bytes.len() as u8);
+            der.extend_from_slice(bytes);
+        },
+    }
+    der.extend_from_slice(contents);
+    der
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::pki_types::pem::PemObject;
+
+    use super::*;
+
+    const CA_CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIBejCCASGgAwIBAgIUWLvpiqRNJGKXS1sv4wF6UIF5z6UwCgYIKoZIzj0EAwIw
+EjEQMA4GA1UEAwwHVGVzdCBDQTAgFw0yNjEwMTYyMzU2MTFaGA8yMTI2MDkyMjIz
+NTYxMVowEjEQMA4GA1UEAwwHVGVzdCBDQTBZMBMGByqGSM49AgEGCCqGSM49AwEH
+A0IABPtzJTcCDVNSffp20eGN9sm3I6hFhocoQPpvKfwRZ4/Ev0AD+/70ZheDJtE+
+QJgX3P/hSCEyFcg1yd6KOTRs6WCjUzBRMB0GA1UdDgQWBBR1/Trv+/vEhUUoXUfs
+4v4MgXyUZDAfBgNVHSMEGDAWgBR1/Trv+/vEhUUoXUfs4v4MgXyUZDAPBgNVHRMB
+Af8EBTADAQH/MAoGCCqGSM49BAMCA0cAMEQCIAFA3hjJ73vGAy7QfJx+SK9bKBYF
+uRd+Et4wDEuz0tU8AiAbUSbL32fN3Nj/BqWzelIl+6I/mLLiDEUKYcblVh3q1Q==
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn test_spki_list() {
+        assert_eq!(spki_list(&[]), None);
+        let cert = CertificateDer::from_pem_slice(CA_CERT.as_bytes()).unwrap();
+        // openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary |
+        // base64
+        assert_eq!(
+            spki_list(&[cert]).as_deref(),
+            Some("8apZg1zeAUhONuiw8fsFyLBmV6Rju4nDC6QdJt+xelQ=")
+        );
+        assert_eq!(der_sequence(&[0; 200])[..3], [0x30, 0x81, 200]);
+        assert_eq!(der_sequence(&[0; 300])[..4], [0x30, 0x82, 0x01, 0x2c]);
+    }
+
+    #[tokio::test]
+    async fn test_check_request() {
+        let policy = UrlPolicy::default();
+        assert!(
+            check_request(&policy, "http://169.254.169.254/latest/meta-data/")
+                .await
+                .is_err()
+        );
+        assert!(check_request(&policy, "http://127.0.0.1:8080/").await.is_err());
+        assert!(check_request(&policy, "http://localhost/").await.is_err());
+        assert!(check_request(&policy, "file:///etc/passwd").await.is_err());
+        assert!(check_request(&policy, "data:text/plain,hello").await.is_ok());
+    }
+
+    /// A page served from an allowed host redirects to and requests a denied one, which must not
+    /// reach the rendered text.
+    #[cfg(feature = "headless-browser")]
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "needs Chrome"]
+    async fn test_render_blocks_denied_requests() {
+        let mut server = mockito::Server::new_async().await;
+        let port = server.socket_address().port();
+        let secret = format!("http://localhost:{port}/secret");
+        server
+            .mock("GET", "/secret")
+            .with_header("access-control-allow-origin", "*")
+            .with_body("secret-value")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/xhr")
+            .with_header("content-type", "text/html")
+            .with_body(format!(
+                "<body><script>
+                    try {{
+                        const xhr = new XMLHttpRequest();
+                        xhr.open('GET', '{secret}', false);
+                        xhr.send();
+                        document.body.innerText = 'xhr: ' + xhr.responseText;
+                    }} catch (err) {{
+                        document.body.innerText = 'xhr failed';
+                    }}
+                </script></body>"
+            ))
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/redirect")
+            .with_status(302)
+            .with_header("location", &secret)
+            .create_async()
+            .await;
+
+        // The server is on 127.0.0.1, the same server named localhost is denied
+        let policy = UrlPolicy {
+            denied_domains: vec!["localhost".to_string()],
+            allow_private_network: true,
+            ..Default::default()
+        };
+        let timeout = Duration::from_secs(30);
+
+        let url = Url::parse(&format!("{}/xhr", server.url())).unwrap();
+        let page = render(&url, timeout, false, &policy).await.unwrap();
+        assert!(!page.text.contains("secret-value"), "{}", page.text);
+        assert_eq!(page.blocked, vec![secret.clone()]);
+
+        let url = Url::parse(&format!("{}/redirect", server.url())).unwrap();
+        match render(&url, timeout, false, &policy).await {
+            Ok(page) => assert!(!page.text.contains("secret-value"), "{}", page.text),
+            Err(err) => assert!(err.to_string().contains(&secret), "{err}"),
+        }
+    }
+}
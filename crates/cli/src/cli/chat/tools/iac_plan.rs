@@ -0,0 +1,604 @@
+//! Summarizes the changes of a Terraform plan or a CloudFormation change set, so that the model
+//! can review an infrastructure change without the raw plan filling the context.
+
+use std::fmt::Write as _;
+use std::io::Write;
+use std::process::Stdio;
+
+use bstr::ByteSlice;
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Color,
+};
+use eyre::{
+    Result,
+    WrapErr,
+    bail,
+    eyre,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::process::Command;
+
+use super::aws_policy::AwsPolicy;
+use super::use_aws::aws_command;
+use super::{
+    InvokeOutput,
+    OutputKind,
+    format_path,
+    sanitize_path_tool_arg,
+};
+use crate::platform::Context;
+
+/// Changes listed one by one, the counts still cover every change.
+const MAX_LISTED_CHANGES: usize = 300;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "mode")]
+pub enum IacPlan {
+    Terraform(TerraformPlan),
+    CloudFormation(ChangeSet),
+}
+
+/// Runs `terraform plan -json` in a directory, or reads a saved plan with `terraform show -json`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TerraformPlan {
+    /// The Terraform working directory
+    pub path: String,
+    /// Optional: A saved plan file to summarize instead of planning again
+    pub plan_file: Option<String>,
+    /// Optional: Variable files passed to `terraform plan`
+    #[serde(default)]
+    pub var_files: Vec<String>,
+}
+
+/// Describes an existing CloudFormation change set.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChangeSet {
+    pub stack_name: String,
+    pub change_set_name: String,
+    pub region: String,
+    pub profile_name: Option<String>,
+    /// Which AWS services may be called, set from the settings before validation.
+    #[serde(skip)]
+    pub aws_policy: AwsPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Action {
+    Create,
+    Update,
+    Replace,
+    Delete,
+    Import,
+    Move,
+    Forget,
+}
+
+impl Action {
+    fn symbol(&self) -> &'static str {
+        match self {
+            Action::Create => "+",
+            Action::Update => "~",
+            Action::Replace => "-/+",
+            Action::Delete => "-",
+            Action::Import => "<=",
+            Action::Move => "->",
+            Action::Forget => ".",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Action::Create => "create",
+            Action::Update => "update",
+            Action::Replace => "replace",
+            Action::Delete => "delete",
+            Action::Import => "import",
+            Action::Move => "move",
+            Action::Forget => "forget",
+        }
+    }
+}
+
+/// A change to one resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ResourceChange {
+    action: Action,
+    /// The Terraform address or the CloudFormation logical ID
+    address: String,
+    resource_type: String,
+    /// Why the resource is replaced, its physical ID, and so on
+    detail: Option<String>,
+}
+
+/// The changes of a plan, and anything else worth knowing before applying it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct PlanSummary {
+    changes: Vec<ResourceChange>,
+    notes: Vec<String>,
+}
+
+impl PlanSummary {
+    fn render(&self, title: &str) -> String {
+        let mut output = format!("{title}: ");
+        let counts = [
+            Action::Create,
+            Action::Update,
+            Action::Replace,
+            Action::Delete,
+            Action::Import,
+            Action::Move,
+            Action::Forget,
+        ]
+        .iter()
+        .filter_map(|action| {
+            let count = self.changes.iter().filter(|change| change.action == *action).count();
+            (count > 0).then(|| format!("{count} to {}", action.description()))
+        })
+        .collect::<Vec<_>>();
+        if counts.is_empty() {
+            output.push_str("no changes\n");
+        } else {
+            let _ = writeln!(output, "{}", counts.join(", "));
+        }
+
+        // Destructive changes first, they are what a review is most about.
+        let mut changes = self.changes.iter().collect::<Vec<_>>();
+        changes.sort_by_key(|change| match change.action {
+            Action::Delete => 0,
+            Action::Replace => 1,
+            _ => 2,
+        });
+        for change in changes.iter().take(MAX_LISTED_CHANGES) {
+            let _ = write!(
+                output,
+                "{} {} ({})",
+                change.action.symbol(),
+                change.address,
+                change.resource_type
+            );
+            if let Some(detail) = &change.detail {
+                let _ = write!(output, ": {detail}");
+            }
+            output.push('\n');
+        }
+        if changes.len() > MAX_LISTED_CHANGES {
+            let _ = writeln!(output, "... and {} more changes", changes.len() - MAX_LISTED_CHANGES);
+        }
+        for note in &self.notes {
+            let _ = writeln!(output, "Note: {note}");
+        }
+        output
+    }
+}
+
+impl IacPlan {
+    pub fn requires_acceptance(&self) -> bool {
+        // Planning runs providers and external data sources, describing a change set only reads.
+        matches!(self, IacPlan::Terraform(_))
+    }
+
+    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+        let summary = match self {
+            IacPlan::Terraform(plan) => {
+                let dir = sanitize_path_tool_arg(ctx, &plan.path);
+                let mut command = Command::new("terraform");
+                command.current_dir(&dir);
+                match &plan.plan_file {
+                    Some(plan_file) => {
+                        command.args(["show", "-json", "-no-color"]).arg(plan_file);
+                    },
+                    None => {
+                        command.args(["plan", "-json", "-input=false", "-lock=false", "-no-color"]);
+                        for var_file in &plan.var_files {
+                            command.arg(format!("-var-file={var_file}"));
+                        }
+                    },
+                }
+                writeln!(updates, "Planning in {}", dir.display())?;
+                let output = run(command).await?;
+                let summary = match &plan.plan_file {
+                    Some(_) => parse_terraform_show(&output.stdout),
+                    None => parse_terraform_plan(&output.stdout),
+                };
+                if !output.success {
+                    // The errors are diagnostics in the JSON output, or on stderr if terraform
+                    // failed before planning.
+                    let mut message = format!("terraform failed: {}", output.stderr.trim());
+                    for note in summary.map(|summary| summary.notes).unwrap_or_default() {
+                        let _ = write!(message, "\n{note}");
+                    }
+                    bail!(message);
+                }
+                summary?.render("Terraform plan")
+            },
+            IacPlan::CloudFormation(change_set) => {
+                let mut command = aws_command(&change_set.region, change_set.profile_name.as_deref());
+                command
+                    .args(["cloudformation", "describe-change-set", "--output", "json"])
+                    .args(["--stack-name", &change_set.stack_name])
+                    .args(["--change-set-name", &change_set.change_set_name]);
+                let output = run(command).await?;
+                if !output.success {
+                    bail!(output.stderr);
+                }
+                parse_change_set(&output.stdout)?.render(&format!(
+                    "Change set {} of stack {}",
+                    change_set.change_set_name, change_set.stack_name
+                ))
+            },
+        };
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(summary),
+        })
+    }
+
+    pub fn queue_description(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
+        match self {
+            IacPlan::Terraform(plan) => {
+                let cwd = ctx.env().current_dir()?;
+                let command = match &plan.plan_file {
+                    Some(plan_file) => format!("terraform show -json {plan_file}"),
+                    None => "terraform plan -json".to_string(),
+                };
+                queue!(
+                    updates,
+                    style::Print("Running "),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(command),
+                    style::ResetColor,
+                    style::Print(" in "),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format_path(ctx, cwd, sanitize_path_tool_arg(ctx, &plan.path))),
+                    style::ResetColor,
+                    style::Print("\n"),
+                )?;
+            },
+            IacPlan::CloudFormation(change_set) => {
+                queue!(
+                    updates,
+                    style::Print("Describing change set "),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(&change_set.change_set_name),
+                    style::ResetColor,
+                    style::Print(" of stack "),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(&change_set.stack_name),
+                    style::ResetColor,
+                    style::Print(format!(" in {}\n", change_set.region)),
+                )?;
+            },
+        }
+        Ok(())
+    }
+
+    pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
+        match self {
+            IacPlan::Terraform(plan) => {
+                let dir = sanitize_path_tool_arg(ctx, &plan.path);
+                if !ctx.fs().symlink_metadata(&dir).await.is_ok_and(|m| m.is_dir()) {
+                    bail!("'{}' is not a directory", plan.path);
+                }
+                if plan.plan_file.is_some() && !plan.var_files.is_empty() {
+                    bail!("var_files can't be used with a saved plan_file");
+                }
+            },
+            IacPlan::CloudFormation(change_set) => {
+                change_set.aws_policy.check_service("cloudformation")?;
+                if change_set.stack_name.is_empty() || change_set.change_set_name.is_empty() {
+                    bail!("stack_name and change_set_name must not be empty");
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+struct CommandOutput {
+    success: bool,
+    stdout: String,
+    stderr: String,
+}
+
+async fn run(mut command: Command) -> Result<CommandOutput> {
+    let output = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .wrap_err_with(|| format!("Unable to run {:?}", command.as_std().get_program()))?;
+    Ok(CommandOutput {
+        success: output.status.success(),
+        stdout: output.stdout.to_str_lossy().into_owned(),
+        stderr: output.stderr.to_str_lossy().into_owned(),
+    })
+}
+
+/// Parses the JSON lines printed by `terraform plan -json`.
+fn parse_terraform_plan(output: &str) -> Result<PlanSummary> {
+    let mut summary = PlanSummary::default();
+    let mut messages = 0;
+    for line in output.lines() {
+        let Ok(message) = serde_json::from_str::<Value>(line) else {
+            continue;
+        };
+        messages += 1;
+        match message["type"].as_str() {
+            Some("planned_change") => {
+                let change = &message["change"];
+                let action = match change["action"].as_str() {
+                    Some("create") => Action::Create,
+                    Some("update") => Action::Update,
+                    Some("replace") => Action::Replace,
+                    Some("delete") => Action::Delete,
+                    Some("import") => Action::Import,
+                    Some("move") => Action::Move,
+                    Some("remove") => Action::Forget,
+                    _ => continue,
+                };
+                let detail = match action {
+                    Action::Move => change["previous_resource"]["addr"]
+                        .as_str()
+                        .map(|addr| format!("from {addr}")),
+                    _ => change["reason"].as_str().map(|reason| reason.replace('_', " ")),
+                };
+                summary.changes.push(ResourceChange {
+                    action,
+                    address: change["resource"]["addr"].as_str().unwrap_or_default().to_string(),
+                    resource_type: change["resource"]["resource_type"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    detail,
+                });
+            },
+            Some("resource_drift") => {
+                if let Some(addr) = message["change"]["resource"]["addr"].as_str() {
+                    summary.notes.push(format!("{addr} changed outside of Terraform"));
+                }
+            },
+            Some("diagnostic") => {
+                let diagnostic = &message["diagnostic"];
+                let severity = diagnostic["severity"].as_str().unwrap_or("warning");
+                let mut note = format!("{severity}: {}", diagnostic["summary"].as_str().unwrap_or_default());
+                if let Some(detail) = diagnostic["detail"].as_str().filter(|detail| !detail.is_empty()) {
+                    let _ = write!(note, " ({detail})");
+                }
+                summary.notes.push(note);
+            },
+            _ => (),
+        }
+    }
+    if messages == 0 {
+        bail!("terraform did not print a JSON plan");
+    }
+    Ok(summary)
+}
+
+/// Parses the plan printed by `terraform show -json <plan file>`.
+fn parse_terraform_show(output: &str) -> Result<PlanSummary> {
+    let plan = serde_json::from_str::<Value>(output).map_err(|err| eyre!("Invalid terraform show output: {err}"))?;
+    let mut summary = PlanSummary::default();
+    for change in plan["resource_changes"].as_array().into_iter().flatten() {
+        let actions = change["change"]["actions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|action| action.as_str())
+            .collect::<Vec<_>>();
+        let action = match actions.as_slice() {
+            ["create"] if change["change"]["importing"].is_object() => Action::Import,
+            ["create"] => Action::Create,
+            ["update"] => Action::Update,
+            ["delete", "create"] | ["create", "delete"] => Action::Replace,
+            ["delete"] => Action::Delete,
+            ["forget"] => Action::Forget,
+            _ if change["previous_address"].is_string() => Action::Move,
+            _ => continue,
+        };
+        let detail = match action {
+            Action::Move => change["previous_address"].as_str().map(|addr| format!("from {addr}")),
+            _ => change["action_reason"].as_str().map(|reason| reason.replace('_', " ")),
+        };
+        summary.changes.push(ResourceChange {
+            action,
+            address: change["address"].as_str().unwrap_or_default().to_string(),
+            resource_type: change["type"].as_str().unwrap_or_default().to_string(),
+            detail,
+        });
+    }
+    for drift in plan["resource_drift"].as_array().into_iter().flatten() {
+        if let Some(address) = drift["address"].as_str() {
+            summary.notes.push(format!("{address} changed outside of Terraform"));
+        }
+    }
+    Ok(summary)
+}
+
+/// Parses the output of `aws cloudformation describe-change-set`.
+fn parse_change_set(output: &str) -> Result<PlanSummary> {
+    let change_set =
+        serde_json::from_str::<Value>(output).map_err(|err| eyre!("Invalid describe-change-set output: {err}"))?;
+    let mut summary = PlanSummary::default();
+    for change in change_set["Changes"].as_array().into_iter().flatten() {
+        let resource = &change["ResourceChange"];
+        let replacement = resource["Replacement"].as_str();
+        let action = match resource["Action"].as_str() {
+            Some("Add") => Action::Create,
+            Some("Modify") if replacement == Some("True") => Action::Replace,
+            Some("Modify" | "Dynamic") => Action::Update,
+            Some("Remove") => Action::Delete,
+            Some("Import") => Action::Import,
+            _ => continue,
+        };
+        let mut details = Vec::new();
+        if let Some(physical_id) = resource["PhysicalResourceId"].as_str() {
+            details.push(physical_id.to_string());
+        }
+        if action == Action::Update && replacement == Some("Conditional") {
+            details.push("may be replaced".to_string());
+        }
+        summary.changes.push(ResourceChange {
+            action,
+            address: resource["LogicalResourceId"].as_str().unwrap_or_default().to_string(),
+            resource_type: resource["ResourceType"].as_str().unwrap_or_default().to_string(),
+            detail: (!details.is_empty()).then(|| details.join(", ")),
+        });
+    }
+
+    if let Some(status) = change_set["Status"]
+        .as_str()
+        .filter(|status| *status != "CREATE_COMPLETE")
+    {
+        let reason = change_set["StatusReason"].as_str().unwrap_or_default();
+        summary
+            .notes
+            .push(format!("the change set is {status} {reason}").trim().to_string());
+    }
+    if change_set["NextToken"].is_string() {
+        summary
+            .notes
+            .push("the change set has more changes than were described".to_string());
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_terraform_plan() {
+        let planned_change = |addr: &str, resource_type: &str, action: &str| {
+            serde_json::json!({
+                "type": "planned_change",
+                "change": {
+                    "resource": { "addr": addr, "resource_type": resource_type },
+                    "action": action,
+                },
+            })
+        };
+        let mut replace = planned_change("aws_db_instance.main", "aws_db_instance", "replace");
+        replace["change"]["reason"] = "cannot_update".into();
+        let mut drift = planned_change("aws_iam_role.app", "aws_iam_role", "update");
+        drift["type"] = "resource_drift".into();
+        let output = [
+            serde_json::json!({ "type": "version", "terraform": "1.7.0" }),
+            planned_change("aws_instance.web", "aws_instance", "create"),
+            replace,
+            planned_change("aws_s3_bucket.old", "aws_s3_bucket", "delete"),
+            drift,
+            serde_json::json!({
+                "type": "diagnostic",
+                "diagnostic": { "severity": "warning", "summary": "Deprecated attribute", "detail": "" },
+            }),
+            serde_json::json!({ "type": "change_summary", "changes": { "add": 1, "change": 0, "remove": 2 } }),
+        ]
+        .iter()
+        .map(|message| message.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+        let summary = parse_terraform_plan(&output).unwrap();
+        assert_eq!(summary.changes.len(), 3);
+        assert_eq!(summary.changes[1], ResourceChange {
+            action: Action::Replace,
+            address: "aws_db_instance.main".to_string(),
+            resource_type: "aws_db_instance".to_string(),
+            detail: Some("cannot update".to_string()),
+        });
+
+        let rendered = summary.render("Terraform plan");
+        assert!(rendered.starts_with("Terraform plan: 1 to create, 1 to replace, 1 to delete\n- aws_s3_bucket.old"));
+        assert!(rendered.contains("Note: aws_iam_role.app changed outside of Terraform"));
+        assert!(rendered.contains("Note: warning: Deprecated attribute"));
+
+        assert!(parse_terraform_plan("Error: no configuration files").is_err());
+    }
+
+    #[test]
+    fn test_parse_terraform_show() {
+        let output = serde_json::json!({
+            "resource_changes": [
+                { "address": "aws_instance.web", "type": "aws_instance", "change": { "actions": ["no-op"] } },
+                {
+                    "address": "aws_instance.db",
+                    "type": "aws_instance",
+                    "change": { "actions": ["delete", "create"] },
+                },
+                {
+                    "address": "aws_vpc.main",
+                    "type": "aws_vpc",
+                    "change": { "actions": ["create"], "importing": { "id": "vpc-1" } },
+                },
+                {
+                    "address": "module.a.aws_sqs_queue.q",
+                    "previous_address": "aws_sqs_queue.q",
+                    "type": "aws_sqs_queue",
+                    "change": { "actions": ["no-op"] },
+                },
+            ]
+        })
+        .to_string();
+        let summary = parse_terraform_show(&output).unwrap();
+        let actions = summary.changes.iter().map(|change| change.action).collect::<Vec<_>>();
+        assert_eq!(actions, vec![Action::Replace, Action::Import, Action::Move]);
+        assert_eq!(summary.changes[2].detail.as_deref(), Some("from aws_sqs_queue.q"));
+    }
+
+    #[test]
+    fn test_parse_change_set() {
+        let output = serde_json::json!({
+            "Status": "CREATE_COMPLETE",
+            "Changes": [
+                {
+                    "Type": "Resource",
+                    "ResourceChange": {
+                        "Action": "Add",
+                        "LogicalResourceId": "Queue",
+                        "ResourceType": "AWS::SQS::Queue",
+                    },
+                },
+                {
+                    "Type": "Resource",
+                    "ResourceChange": {
+                        "Action": "Modify",
+                        "Replacement": "True",
+                        "LogicalResourceId": "Table",
+                        "PhysicalResourceId": "orders",
+                        "ResourceType": "AWS::DynamoDB::Table",
+                    },
+                },
+                {
+                    "Type": "Resource",
+                    "ResourceChange": {
+                        "Action": "Modify",
+                        "Replacement": "Conditional",
+                        "LogicalResourceId": "Function",
+                        "ResourceType": "AWS::Lambda::Function",
+                    },
+                },
+            ]
+        })
+        .to_string();
+        let summary = parse_change_set(&output).unwrap();
+        assert!(summary.notes.is_empty());
+        assert_eq!(
+            summary.render("Change set"),
+            "Change set: 1 to create, 1 to update, 1 to replace\n\
+             -/+ Table (AWS::DynamoDB::Table): orders\n\
+             + Queue (AWS::SQS::Queue)\n\
+             ~ Function (AWS::Lambda::Function): may be replaced\n"
+        );
+
+        let output =
+            serde_json::json!({ "Status": "FAILED", "StatusReason": "No updates are to be performed." }).to_string();
+        let summary = parse_change_set(&output).unwrap();
+        assert_eq!(summary.notes, vec![
+            "the change set is FAILED No updates are to be performed."
+        ]);
+    }
+}
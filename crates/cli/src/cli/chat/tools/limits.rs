@@ -0,0 +1,163 @@
+//! Wall-clock timeouts and output caps of tool uses, set per tool with `chat.toolTimeouts` and
+//! `chat.toolMaxOutputBytes`, e.g. `{"execute_bash": 300, "default": 120}`.
+//!
+//! Both are enforced by [super::Tool::invoke], so that a tool running too long fails the same way
+//! whichever tool it is, and the model can retry with smaller steps.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde_json::Value;
+use tracing::warn;
+
+use super::{
+    InvokeOutput,
+    OutputKind,
+};
+use crate::cli::chat::consts::MAX_TOOL_RESPONSE_SIZE;
+use crate::cli::chat::util::truncate::{
+    Keep,
+    truncate_text,
+};
+use crate::database::Database;
+use crate::database::settings::Setting;
+
+/// Key of the limit applied to the tools that have none of their own.
+const DEFAULT_KEY: &str = "default";
+
+/// Returned by a tool use that ran longer than its timeout, after it was stopped.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "{tool} timed out after {}s and was stopped. Split the work into smaller steps, or ask the user to raise the limit with chat.toolTimeouts",
+    .timeout.as_secs()
+)]
+pub struct ToolTimeoutError {
+    pub tool: String,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolLimits {
+    /// Seconds per tool name, 0 for no timeout.
+    timeouts: HashMap<String, u64>,
+    /// Bytes per tool name, 0 for the default cap.
+    max_output_bytes: HashMap<String, u64>,
+}
+
+impl ToolLimits {
+    pub fn from_database(database: &Database) -> Self {
+        Self {
+            timeouts: read_limits(database, Setting::ChatToolTimeouts),
+            max_output_bytes: read_limits(database, Setting::ChatToolMaxOutputBytes),
+        }
+    }
+
+    /// How long the tool may run, `None` if it may run until it's done.
+    pub fn timeout(&self, tool: &str) -> Option<Duration> {
+        lookup(&self.timeouts, tool)
+            .filter(|seconds| *seconds > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// How much of the output of the tool is sent to the model.
+    pub fn max_output_bytes(&self, tool: &str) -> usize {
+        lookup(&self.max_output_bytes, tool)
+            .and_then(|bytes| usize::try_from(bytes).ok())
+            .filter(|bytes| *bytes > 0)
+            .unwrap_or(MAX_TOOL_RESPONSE_SIZE)
+    }
+}
+
+fn lookup(limits: &HashMap<String, u64>, tool: &str) -> Option<u64> {
+    limits.get(tool).or_else(|| limits.get(DEFAULT_KEY)).copied()
+}
+
+fn read_limits(database: &Database, setting: Setting) -> HashMap<String, u64> {
+    let Some(Value::Object(map)) = database.settings.get(setting) else {
+        return HashMap::new();
+    };
+    map.iter()
+        .filter_map(|(tool, value)| match value.as_u64() {
+            Some(limit) => Some((tool.clone(), limit)),
+            None => {
+                warn!("Ignoring the {setting} of '{tool}', it must be a positive integer: {value}");
+                None
+            },
+        })
+        .collect()
+}
+
+/// Cuts text and JSON output longer than `max_bytes` at line boundaries, JSON being sent as text
+/// once cut.
+pub fn truncate_output(output: InvokeOutput, max_bytes: usize) -> InvokeOutput {
+    let text = match &output.output {
+        OutputKind::Text(text) if text.len() > max_bytes => text.clone(),
+        OutputKind::Json(json) => match json.to_string() {
+            text if text.len() > max_bytes => text,
+            _ => return output,
+        },
+        _ => return output,
+    };
+    InvokeOutput {
+        output: OutputKind::Text(truncate_text(&text, max_bytes, Keep::Head).into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(timeouts: &[(&str, u64)], max_output_bytes: &[(&str, u64)]) -> ToolLimits {
+        let map = |limits: &[(&str, u64)]| {
+            limits
+                .iter()
+                .map(|(tool, limit)| ((*tool).to_string(), *limit))
+                .collect()
+        };
+        ToolLimits {
+            timeouts: map(timeouts),
+            max_output_bytes: map(max_output_bytes),
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        let limits = limits(&[("execute_bash", 300), ("fs_read", 0), ("default", 60)], &[]);
+        assert_eq!(limits.timeout("execute_bash"), Some(Duration::from_secs(300)));
+        assert_eq!(limits.timeout("use_aws"), Some(Duration::from_secs(60)));
+        assert_eq!(limits.timeout("fs_read"), None);
+        assert_eq!(ToolLimits::default().timeout("execute_bash"), None);
+    }
+
+    #[test]
+    fn test_max_output_bytes() {
+        let limits = limits(&[], &[("fs_read", 1000), ("use_aws", 0)]);
+        assert_eq!(limits.max_output_bytes("fs_read"), 1000);
+        assert_eq!(limits.max_output_bytes("use_aws"), MAX_TOOL_RESPONSE_SIZE);
+        assert_eq!(limits.max_output_bytes("execute_bash"), MAX_TOOL_RESPONSE_SIZE);
+    }
+
+    #[test]
+    fn test_truncate_output() {
+        let text = |text: &str| InvokeOutput {
+            output: OutputKind::Text(text.to_string()),
+        };
+        assert_eq!(truncate_output(text("short"), 10).as_str(), "short");
+        assert_eq!(
+            truncate_output(text(&"é".repeat(30)), 32).as_str(),
+            "éé\n[... 1 line truncated ...]\n"
+        );
+        assert_eq!(
+            truncate_output(text(&format!("one\ntwo\n{}\n", "three ".repeat(10))), 36).as_str(),
+            "one\ntwo\n[... 1 line truncated ...]\n"
+        );
+
+        let json = InvokeOutput {
+            output: OutputKind::Json(serde_json::json!({ "stdout": "0123456789".repeat(10) })),
+        };
+        assert_eq!(
+            truncate_output(json, 40).as_str(),
+            "{\"stdout\":\"0\n[... 1 line truncated ...]\n"
+        );
+    }
+}
@@ -1,36 +1,81 @@
+pub mod aws_models;
+pub mod aws_policy;
+pub mod aws_resources;
+pub mod code_symbols;
 pub mod custom_tool;
+pub mod document;
+pub mod download_file;
+pub mod env_policy;
 pub mod execute_bash;
 pub mod fs_read;
 pub mod fs_write;
 pub mod gh_issue;
+pub mod headless_browser;
+pub mod iac_plan;
+pub mod limits;
+pub mod output_filter;
+pub mod plugin_tool;
+pub mod refactor_rename;
+pub mod remote;
+pub mod shell;
 pub mod thinking;
+pub mod tool_policy;
+pub mod url_policy;
 pub mod use_aws;
+pub mod wasm_runtime;
 pub mod web_browse;
+pub mod web_cache;
+pub mod web_crawl;
 
-use std::collections::HashMap;
-use std::io::Write;
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+use std::io::{
+    IsTerminal,
+    Write,
+};
 use std::path::{
     Path,
     PathBuf,
 };
 
+use aws_policy::AwsPolicy;
+use code_symbols::CodeSymbols;
 use crossterm::style::Stylize;
 use custom_tool::CustomTool;
+use download_file::DownloadFile;
+use env_policy::EnvPolicy;
 use execute_bash::ExecuteBash;
 use eyre::Result;
 use fs_read::FsRead;
 use fs_write::FsWrite;
 use gh_issue::GhIssue;
+use iac_plan::IacPlan;
+use limits::{
+    ToolLimits,
+    ToolTimeoutError,
+};
+use plugin_tool::PluginTool;
+use refactor_rename::RefactorRename;
+use remote::ExecutionTarget;
 use serde::{
     Deserialize,
     Serialize,
 };
+use shell::Shell;
 use thinking::Thinking;
+use tool_policy::PolicyDecision;
+use url_policy::UrlPolicy;
 use use_aws::UseAws;
 use web_browse::WebBrowse;
+use web_cache::WebCache;
+use web_crawl::WebCrawl;
 
 use super::consts::MAX_TOOL_RESPONSE_SIZE;
 use super::util::images::RichImageBlocks;
+use super::workspace;
+use crate::database::Database;
 use crate::platform::Context;
 
 /// Represents an executable tool use.
@@ -45,6 +90,12 @@ pub enum Tool {
     GhIssue(GhIssue),
     Thinking(Thinking),
     WebBrowse(WebBrowse),
+    WebCrawl(WebCrawl),
+    DownloadFile(DownloadFile),
+    CodeSymbols(CodeSymbols),
+    RefactorRename(RefactorRename),
+    IacPlan(IacPlan),
+    Plugin(PluginTool),
 }
 
 impl Tool {
@@ -59,6 +110,12 @@ impl Tool {
             Tool::GhIssue(_) => "gh_issue",
             Tool::Thinking(_) => "thinking (prerelease)",
             Tool::WebBrowse(_) => "web_browse",
+            Tool::WebCrawl(_) => "web_crawl",
+            Tool::DownloadFile(_) => "download_file",
+            Tool::CodeSymbols(_) => "code_symbols",
+            Tool::RefactorRename(_) => "refactor_rename",
+            Tool::IacPlan(_) => "iac_plan",
+            Tool::Plugin(plugin) => &plugin.manifest.name,
         }
         .to_owned()
     }
@@ -74,20 +131,114 @@ impl Tool {
             Tool::GhIssue(_) => false,
             Tool::Thinking(_) => false,
             Tool::WebBrowse(_) => false, // Web browsing is generally safe, but could be made configurable
+            Tool::WebCrawl(_) => false,
+            Tool::DownloadFile(_) => true,
+            Tool::CodeSymbols(_) => false,
+            Tool::RefactorRename(_) => true,
+            Tool::IacPlan(iac_plan) => iac_plan.requires_acceptance(),
+            Tool::Plugin(plugin) => plugin.requires_acceptance(),
+        }
+    }
+
+    /// Whether the tool use can change anything or run code from the workspace, which read-only
+    /// sessions refuse.
+    pub fn mutates(&self) -> bool {
+        match self {
+            Tool::FsWrite(_) | Tool::DownloadFile(_) | Tool::RefactorRename(_) => true,
+            Tool::Custom(_) | Tool::Plugin(_) => true,
+            Tool::ExecuteBash(execute_bash) => execute_bash.requires_acceptance(),
+            Tool::UseAws(use_aws) => use_aws.requires_acceptance(),
+            Tool::IacPlan(iac_plan) => iac_plan.requires_acceptance(),
+            Tool::FsRead(_)
+            | Tool::GhIssue(_)
+            | Tool::Thinking(_)
+            | Tool::WebBrowse(_)
+            | Tool::WebCrawl(_)
+            | Tool::CodeSymbols(_) => false,
         }
     }
 
-    /// Invokes the tool asynchronously
-    pub async fn invoke(&self, context: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+    /// Whether every use of the tool must be accepted by the user, even when the tool is trusted.
+    pub fn always_requires_acceptance(&self) -> bool {
         match self {
-            Tool::FsRead(fs_read) => fs_read.invoke(context, updates).await,
-            Tool::FsWrite(fs_write) => fs_write.invoke(context, updates).await,
-            Tool::ExecuteBash(execute_bash) => execute_bash.invoke(updates).await,
-            Tool::UseAws(use_aws) => use_aws.invoke(context, updates).await,
-            Tool::Custom(custom_tool) => custom_tool.invoke(context, updates).await,
-            Tool::GhIssue(gh_issue) => gh_issue.invoke(updates).await,
-            Tool::Thinking(think) => think.invoke(updates).await,
-            Tool::WebBrowse(web_browse) => web_browse.invoke(context, updates).await,
+            Tool::DownloadFile(_) => true,
+            Tool::ExecuteBash(execute_bash) => execute_bash.attached(),
+            Tool::UseAws(use_aws) => use_aws.always_requires_acceptance(),
+            _ => false,
+        }
+    }
+
+    /// Invokes the tool asynchronously, stopping it once it runs longer than its timeout and
+    /// cutting its output to the size limit of the tool.
+    pub async fn invoke(
+        &self,
+        context: &Context,
+        updates: &mut impl Write,
+        limits: &ToolLimits,
+    ) -> Result<InvokeOutput> {
+        let name = self.setting_name();
+        let max_bytes = limits.max_output_bytes(&name);
+        let timeout = limits.timeout(&name);
+        let invoke = async {
+            match self {
+                Tool::FsRead(fs_read) => fs_read.invoke(context, updates, max_bytes).await,
+                Tool::FsWrite(fs_write) => fs_write.invoke(context, updates).await,
+                Tool::ExecuteBash(execute_bash) => execute_bash.invoke(updates, max_bytes).await,
+                Tool::UseAws(use_aws) => use_aws.invoke(context, updates, max_bytes).await,
+                Tool::Custom(custom_tool) => custom_tool.invoke(context, updates, timeout).await,
+                Tool::GhIssue(gh_issue) => gh_issue.invoke(updates).await,
+                Tool::Thinking(think) => think.invoke(updates).await,
+                Tool::WebBrowse(web_browse) => web_browse.invoke(context, updates).await,
+                Tool::WebCrawl(web_crawl) => web_crawl.invoke(context, updates).await,
+                Tool::DownloadFile(download_file) => download_file.invoke(context, updates).await,
+                Tool::CodeSymbols(code_symbols) => code_symbols.invoke(context, updates).await,
+                Tool::RefactorRename(refactor_rename) => refactor_rename.invoke(context, updates).await,
+                Tool::IacPlan(iac_plan) => iac_plan.invoke(context, updates).await,
+                Tool::Plugin(plugin) => plugin.invoke(context, updates).await,
+            }
+        };
+        let output = match timeout {
+            // The MCP client times out the request of a custom tool itself.
+            Some(timeout) if !matches!(self, Tool::Custom(_)) => tokio::time::timeout(timeout, invoke)
+                .await
+                .map_err(|_err| ToolTimeoutError { tool: name, timeout })??,
+            _ => invoke.await?,
+        };
+        Ok(limits::truncate_output(output, max_bytes))
+    }
+
+    /// Describes what the tool would change instead of changing it, for `q chat --dry-run`. The
+    /// tools that can't change anything are invoked as usual.
+    pub async fn dry_run(
+        &self,
+        context: &Context,
+        updates: &mut impl Write,
+        limits: &ToolLimits,
+    ) -> Result<InvokeOutput> {
+        if !self.mutates() {
+            return self.invoke(context, updates, limits).await;
+        }
+        let description = match self {
+            Tool::FsWrite(fs_write) => fs_write.dry_run(context).await?,
+            Tool::ExecuteBash(execute_bash) => {
+                format!("Dry run: the command was not run:\n{}", execute_bash.command)
+            },
+            Tool::UseAws(use_aws) => {
+                let max_bytes = limits.max_output_bytes(&self.setting_name());
+                return use_aws.dry_run(max_bytes).await;
+            },
+            _ => format!("Dry run: {} was not run", self.display_name()),
+        };
+        Ok(InvokeOutput {
+            output: OutputKind::Text(description),
+        })
+    }
+
+    /// The name of the tool in settings such as `chat.toolTimeouts`.
+    pub fn setting_name(&self) -> String {
+        match self {
+            Tool::Thinking(_) => "thinking".to_owned(),
+            _ => self.display_name(),
         }
     }
 
@@ -95,16 +246,67 @@ impl Tool {
     pub async fn queue_description(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
         match self {
             Tool::FsRead(fs_read) => fs_read.queue_description(ctx, updates).await,
-            Tool::FsWrite(fs_write) => fs_write.queue_description(ctx, updates),
+            Tool::FsWrite(fs_write) => fs_write.queue_description(ctx, updates).await,
             Tool::ExecuteBash(execute_bash) => execute_bash.queue_description(updates),
             Tool::UseAws(use_aws) => use_aws.queue_description(updates),
             Tool::Custom(custom_tool) => custom_tool.queue_description(updates),
             Tool::GhIssue(gh_issue) => gh_issue.queue_description(updates),
             Tool::Thinking(thinking) => thinking.queue_description(updates),
             Tool::WebBrowse(web_browse) => web_browse.queue_description(updates),
+            Tool::WebCrawl(web_crawl) => web_crawl.queue_description(updates),
+            Tool::DownloadFile(download_file) => download_file.queue_description(ctx, updates),
+            Tool::CodeSymbols(code_symbols) => code_symbols.queue_description(updates),
+            Tool::RefactorRename(refactor_rename) => refactor_rename.queue_description(ctx, updates).await,
+            Tool::IacPlan(iac_plan) => iac_plan.queue_description(ctx, updates),
+            Tool::Plugin(plugin) => plugin.queue_description(updates),
+        }
+    }
+
+    /// Files whose content is read or written by the tool.
+    pub fn file_paths(&self, ctx: &Context) -> Vec<PathBuf> {
+        match self {
+            Tool::FsRead(fs_read) => fs_read.file_paths(ctx),
+            Tool::FsWrite(fs_write) => vec![sanitize_path_tool_arg(ctx, fs_write.path())],
+            Tool::DownloadFile(download_file) => vec![sanitize_path_tool_arg(ctx, &download_file.path)],
+            Tool::RefactorRename(refactor_rename) => vec![sanitize_path_tool_arg(ctx, &refactor_rename.path)],
+            _ => Vec::new(),
         }
     }
 
+    /// The paths the `path` of the rules of the tool policy matches: the files the tool reads or
+    /// writes, and the directory a command runs in.
+    pub fn policy_paths(&self, ctx: &Context) -> Vec<PathBuf> {
+        match self {
+            Tool::ExecuteBash(execute_bash) => execute_bash
+                .cwd
+                .iter()
+                .map(|cwd| sanitize_path_tool_arg(ctx, cwd))
+                .collect(),
+            _ => self.file_paths(ctx),
+        }
+    }
+
+    /// Where the tool runs when it isn't this machine, for the tools that follow `chat.remoteHost`
+    /// and `chat.container`.
+    pub fn remote_target<'a>(&self, ctx: &'a Context) -> Option<&'a ExecutionTarget> {
+        match self {
+            Tool::FsRead(_) | Tool::FsWrite(_) | Tool::ExecuteBash(_) => {
+                Some(ctx.execution_target()).filter(|target| !target.is_local())
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether the tool uses a path outside the roots given with `--workspace`.
+    pub async fn outside_workspace(&self, ctx: &Context) -> bool {
+        for path in self.policy_paths(ctx) {
+            if !workspace::contains(ctx, &path).await {
+                return true;
+            }
+        }
+        false
+    }
+
     /// Validates the tool with the arguments supplied
     pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
         match self {
@@ -116,6 +318,69 @@ impl Tool {
             Tool::GhIssue(gh_issue) => gh_issue.validate(ctx).await,
             Tool::Thinking(think) => think.validate(ctx).await,
             Tool::WebBrowse(web_browse) => web_browse.validate(ctx).await,
+            Tool::WebCrawl(web_crawl) => web_crawl.validate(ctx).await,
+            Tool::DownloadFile(download_file) => download_file.validate(ctx).await,
+            Tool::CodeSymbols(code_symbols) => code_symbols.validate(ctx).await,
+            Tool::RefactorRename(refactor_rename) => refactor_rename.validate(ctx).await,
+            Tool::IacPlan(iac_plan) => iac_plan.validate(ctx).await,
+            Tool::Plugin(plugin) => plugin.validate(ctx).await,
+        }
+    }
+}
+
+/// The settings and state of the session the tools need, which they can't get from the tool uses
+/// of the model. Applied with [Self::contextualize] by every frontend running the tools.
+#[derive(Debug, Clone)]
+pub struct ToolContext {
+    /// Which URLs the web tools may fetch.
+    pub url_policy: UrlPolicy,
+    /// Whether web_browse may render pages in a headless browser.
+    pub headless_browser: bool,
+    /// Where web_browse caches the pages it fetched, if it does.
+    pub web_cache: Option<WebCache>,
+    /// Which AWS services use_aws may call, and which operations always need approval.
+    pub aws_policy: AwsPolicy,
+    /// Which variables of the environment execute_bash commands see.
+    pub env_policy: EnvPolicy,
+    /// The shell execute_bash runs commands in.
+    pub shell: Shell,
+    /// No terminal is free for commands to prompt on or read from, see [ExecuteBash::headless].
+    /// Set unless the session is the interactive chat.
+    pub headless: bool,
+}
+
+impl ToolContext {
+    pub fn from_database(database: &Database) -> Self {
+        Self {
+            url_policy: UrlPolicy::from_database(database),
+            headless_browser: WebBrowse::is_headless_browser_enabled(database),
+            web_cache: WebCache::from_database(database),
+            aws_policy: AwsPolicy::from_database(database),
+            env_policy: EnvPolicy::from_database(database),
+            shell: Shell::from_database(database),
+            headless: true,
+        }
+    }
+
+    /// Applies the context to a tool deserialized from a tool use of the model.
+    pub fn contextualize(&self, tool: &mut Tool) {
+        match tool {
+            Tool::WebBrowse(web_browse) => {
+                web_browse.policy = self.url_policy.clone();
+                web_browse.headless_browser = self.headless_browser;
+                web_browse.web_cache = self.web_cache;
+            },
+            Tool::WebCrawl(web_crawl) => web_crawl.policy = self.url_policy.clone(),
+            Tool::DownloadFile(download_file) => download_file.policy = self.url_policy.clone(),
+            Tool::UseAws(use_aws) => use_aws.policy = self.aws_policy.clone(),
+            Tool::IacPlan(IacPlan::CloudFormation(change_set)) => change_set.aws_policy = self.aws_policy.clone(),
+            Tool::ExecuteBash(execute_bash) => {
+                execute_bash.env_policy = self.env_policy.clone();
+                execute_bash.shell = self.shell;
+                execute_bash.headless = self.headless;
+                execute_bash.pty = !self.headless && std::io::stdout().is_terminal();
+            },
+            _ => (),
         }
     }
 }
@@ -133,6 +398,8 @@ pub struct ToolPermissions {
     // We need this field for any stragglers
     pub trust_all: bool,
     pub permissions: HashMap<String, ToolPermission>,
+    /// Plugins whose manifest trusts them, see [plugin_tool::PluginManifest::trusted].
+    pub trusted_plugins: HashSet<String>,
 }
 
 impl ToolPermissions {
@@ -140,6 +407,7 @@ impl ToolPermissions {
         Self {
             trust_all: false,
             permissions: HashMap::with_capacity(capacity),
+            trusted_plugins: HashSet::new(),
         }
     }
 
@@ -196,6 +464,12 @@ impl ToolPermissions {
             "report_issue" => "trusted".dark_green().bold(),
             "thinking" => "trusted (prerelease)".dark_green().bold(),
             "web_browse" => "trusted".dark_green().bold(),
+            "web_crawl" => "trusted".dark_green().bold(),
+            "download_file" => "always ask".dark_grey(),
+            "code_symbols" => "trusted".dark_green().bold(),
+            "refactor_rename" => "not trusted".dark_grey(),
+            "iac_plan" => "trust change set reviews".dark_grey(),
+            _ if self.trusted_plugins.contains(tool_name) => "trusted".dark_green().bold(),
             _ if self.trust_all => "trusted".dark_grey().bold(),
             _ => "not trusted".dark_grey(),
         };
@@ -219,6 +493,7 @@ pub struct ToolSpec {
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum ToolOrigin {
     Native,
+    Plugin,
     McpServer(String),
 }
 
@@ -230,6 +505,8 @@ impl<'de> Deserialize<'de> for ToolOrigin {
         let s = String::deserialize(deserializer)?;
         if s == "native___" {
             Ok(ToolOrigin::Native)
+        } else if s == "plugin___" {
+            Ok(ToolOrigin::Plugin)
         } else {
             Ok(ToolOrigin::McpServer(s))
         }
@@ -243,6 +520,7 @@ impl Serialize for ToolOrigin {
     {
         match self {
             ToolOrigin::Native => serializer.serialize_str("native___"),
+            ToolOrigin::Plugin => serializer.serialize_str("plugin___"),
             ToolOrigin::McpServer(server) => serializer.serialize_str(server),
         }
     }
@@ -252,6 +530,7 @@ impl std::fmt::Display for ToolOrigin {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ToolOrigin::Native => write!(f, "Built-in"),
+            ToolOrigin::Plugin => write!(f, "Plugins"),
             ToolOrigin::McpServer(server) => write!(f, "{} (MCP)", server),
         }
     }
@@ -267,6 +546,8 @@ pub struct QueuedTool {
     pub name: String,
     pub accepted: bool,
     pub tool: Tool,
+    /// What the tool policy decided for the tool use, which overrides the trusted tools.
+    pub policy: Option<PolicyDecision>,
 }
 
 /// The schema specification describing a tool's fields.
@@ -322,6 +603,13 @@ pub fn sanitize_path_tool_arg(ctx: &Context, path: impl AsRef<Path>) -> PathBuf
     for p in path {
         res.push(p);
     }
+    // Relative to the current directory of the session, which isn't the one of `q` in `q acp` or
+    // with `--workspace`.
+    if res.is_relative() {
+        if let Ok(cwd) = ctx.env().current_dir() {
+            res = cwd.join(res);
+        }
+    }
     // For testing scenarios, we need to make sure paths are appropriately handled in chroot test
     // file systems since they are passed directly from the model.
     ctx.fs().chroot_path(res)
@@ -357,7 +645,13 @@ fn absolute_to_relative(cwd: impl AsRef<Path>, path: impl AsRef<Path>) -> Result
 }
 
 /// Small helper for formatting the path as a relative path, if able.
-fn format_path(cwd: impl AsRef<Path>, path: impl AsRef<Path>) -> String {
+fn format_path(ctx: &Context, cwd: impl AsRef<Path>, path: impl AsRef<Path>) -> String {
+    // Paths in another root of the workspace are shown from that root, e.g. `api/src/main.rs`.
+    if let Some(root) = workspace::root_of(ctx, path.as_ref()).filter(|root| !cwd.as_ref().starts_with(root)) {
+        if let (Some(name), Ok(relative)) = (root.file_name(), path.as_ref().strip_prefix(root)) {
+            return Path::new(name).join(relative).to_string_lossy().to_string();
+        }
+    }
     absolute_to_relative(cwd, path.as_ref())
         .map(|p| p.to_string_lossy().to_string())
         // If we have three consecutive ".." then it should probably just stay as an absolute path.
@@ -417,7 +711,7 @@ mod tests {
             fs.create_dir_all(&path).await.unwrap();
             // Using `contains` since the chroot test directory will prefix the formatted path with a tmpdir
             // path.
-            assert!(format_path(cwd, path).contains(expected));
+            assert!(format_path(&ctx, cwd, path).contains(expected));
         }
         assert_paths("/Users/testuser/src", "/Users/testuser/Downloads", "../Downloads").await;
         assert_paths(
@@ -0,0 +1,137 @@
+//! Filters removing the noise of command output before it is sent to the model: the frames of
+//! progress bars redrawn after a `\r`, runs of identical lines and floods of warnings, which can
+//! otherwise fill the context of builds and test runs.
+
+/// Number of identical consecutive lines from which a run is collapsed.
+const MIN_REPEATS: usize = 3;
+/// Number of warnings kept, the next ones are left out.
+const MAX_WARNINGS: usize = 20;
+
+/// Applies all the filters to the output of a command.
+pub fn filter_output(text: &str) -> String {
+    let lines = collapse_repeats(text.lines().map(last_frame));
+    let mut filtered = limit_warnings(lines).join("\n");
+    if text.ends_with('\n') {
+        filtered.push('\n');
+    }
+    filtered
+}
+
+/// The line as it was last drawn, when it was redrawn after carriage returns, e.g. by a progress
+/// bar.
+fn last_frame(line: &str) -> &str {
+    line.trim_end_matches('\r').rsplit('\r').next().unwrap_or_default()
+}
+
+/// Replaces the copies of a line repeated at least [MIN_REPEATS] times in a row with a note.
+fn collapse_repeats<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut collapsed = Vec::new();
+    let mut lines = lines.peekable();
+    while let Some(line) = lines.next() {
+        let mut count = 1;
+        while lines.next_if_eq(&line).is_some() {
+            count += 1;
+        }
+        collapsed.push(line.to_string());
+        if count >= MIN_REPEATS && !line.trim().is_empty() {
+            collapsed.push(format!("[... previous line repeated {} more times ...]", count - 1));
+        } else {
+            collapsed.extend(std::iter::repeat_n(line.to_string(), count - 1));
+        }
+    }
+    collapsed
+}
+
+/// Leaves out the warnings after the first [MAX_WARNINGS], with the lines that follow them, e.g.
+/// the code they point to, noting how many there were where the first one was left out.
+fn limit_warnings(lines: Vec<String>) -> Vec<String> {
+    let mut kept = Vec::with_capacity(lines.len());
+    let (mut warnings, mut omitted) = (0, 0);
+    let mut note = None;
+    let mut skipping = false;
+    for line in lines {
+        if is_warning(&line) {
+            warnings += 1;
+            skipping = warnings > MAX_WARNINGS;
+            if skipping {
+                omitted += 1;
+                note.get_or_insert(kept.len());
+                continue;
+            }
+        } else if skipping {
+            // A blank line ends the warning, as after the warnings of cargo.
+            if line.trim().is_empty() {
+                skipping = false;
+                continue;
+            }
+            if is_continuation(&line) {
+                continue;
+            }
+            skipping = false;
+        }
+        kept.push(line);
+    }
+    if let Some(index) = note {
+        kept.insert(index, format!("[... {omitted} more warnings left out ...]"));
+    }
+    kept
+}
+
+fn is_warning(line: &str) -> bool {
+    let line = line.trim_start();
+    ["warning:", "warning[", "WARNING:", "Warning:", "WARN ", "npm WARN ", "npm warn "]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+        // Python, e.g. `test.py:3: DeprecationWarning: ...`
+        || line.contains("Warning: ")
+}
+
+/// Whether the line continues the message above it, e.g. `  --> src/main.rs:2:9` or
+/// `12 |     let x = 1;` after a warning of cargo.
+fn is_continuation(line: &str) -> bool {
+    line.starts_with(char::is_whitespace) || line.trim_start_matches(|c: char| c.is_ascii_digit()).starts_with(" |")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write as _;
+
+    use super::*;
+
+    #[test]
+    fn test_filter_output_progress_and_repeats() {
+        let output = "Downloading  10%\r Downloading  60%\r Downloading 100%\r\n\
+                      retrying\nretrying\nretrying\nretrying\n\
+                      ok\nok\n\n\n\n\
+                      done\n";
+        assert_eq!(
+            filter_output(output),
+            " Downloading 100%\n\
+             retrying\n[... previous line repeated 3 more times ...]\n\
+             ok\nok\n\n\n\n\
+             done\n"
+        );
+    }
+
+    #[test]
+    fn test_filter_output_warnings() {
+        let warning = |i: usize| {
+            format!(
+                "warning: unused variable: `x{i}`\n  --> src/main.rs:{i}:9\n   |\n{i} |     let x{i} = 1;\n   |\n\n"
+            )
+        };
+        let mut output = (1..=25).map(warning).collect::<String>();
+        output.push_str("error: could not compile\n");
+        let filtered = filter_output(&output);
+        assert!(filtered.contains("`x20`"));
+        assert!(!filtered.contains("x21"));
+        assert!(filtered.ends_with("[... 5 more warnings left out ...]\nerror: could not compile\n"));
+
+        let mut output = String::new();
+        for i in 1..=22 {
+            let _ = writeln!(output, "npm WARN deprecated pkg{i}");
+        }
+        output.push_str("added 10 packages\n");
+        assert!(filter_output(&output).ends_with("pkg20\n[... 2 more warnings left out ...]\nadded 10 packages\n"));
+    }
+}
@@ -0,0 +1,602 @@
+//! Tools declared by a manifest in ~/.aws/amazonq/plugins or .amazonq/plugins of the workspace,
+//! for tools that are a single command and don't need an MCP server.
+//!
+//! The command gets the input of the tool as JSON on stdin, once it has been validated against the
+//! schema of the manifest, and prints its result on stdout. Instead of a command, a plugin can be a
+//! WASI module run in a sandbox, see [super::wasm_runtime].
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Color,
+};
+use eyre::{
+    Result,
+    WrapErr,
+    bail,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+
+use super::wasm_runtime::{
+    self,
+    WasmPreopen,
+};
+use super::{
+    InputSchema,
+    InvokeOutput,
+    OutputKind,
+    ToolOrigin,
+    ToolSpec,
+    sanitize_path_tool_arg,
+};
+use crate::cli::chat::CONTINUATION_LINE;
+use crate::cli::chat::consts::MAX_TOOL_RESPONSE_SIZE;
+use crate::cli::chat::util::truncate_safe;
+use crate::platform::Context;
+use crate::util::directories;
+
+const DEFAULT_TIMEOUT_SECONDS: u64 = 30;
+const MAX_TIMEOUT_SECONDS: u64 = 600;
+
+/// How the output of the command is passed to the model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginOutput {
+    #[default]
+    Text,
+    Json,
+}
+
+/// A plugin manifest, e.g.
+///
+/// ```json
+/// {
+///   "name": "jira_issue",
+///   "description": "Fetches a Jira issue by key",
+///   "command": "jira",
+///   "args": ["issue", "view", "--json"],
+///   "input_schema": {
+///     "type": "object",
+///     "properties": { "key": { "type": "string" } },
+///     "required": ["key"]
+///   },
+///   "output": "json"
+/// }
+/// ```
+///
+/// or, for a WASI module that can read the workspace:
+///
+/// ```json
+/// {
+///   "name": "license_check",
+///   "description": "Lists the licenses of the dependencies of the workspace",
+///   "wasm": "license_check.wasm",
+///   "preopens": [{ "host": ".", "guest": "/workspace" }],
+///   "input_schema": { "type": "object", "properties": {} }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginManifest {
+    pub name: String,
+    pub description: String,
+    /// The command run by the plugin, unless it is a WASI module.
+    #[serde(default)]
+    pub command: Option<String>,
+    /// A WASI module run instead of a command, relative to the manifest.
+    #[serde(default)]
+    pub wasm: Option<PathBuf>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Directories a WASI module can access, none by default.
+    #[serde(default)]
+    pub preopens: Vec<Preopen>,
+    #[serde(alias = "inputSchema")]
+    pub input_schema: Value,
+    /// Run without asking the user, only honored for the plugins of the home directory.
+    #[serde(default)]
+    pub trusted: bool,
+    #[serde(default)]
+    pub output: PluginOutput,
+    #[serde(default = "default_timeout_seconds")]
+    pub timeout_seconds: u64,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+fn default_timeout_seconds() -> u64 {
+    DEFAULT_TIMEOUT_SECONDS
+}
+
+/// A directory made available to a WASI module.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preopen {
+    /// The directory, relative to the workspace.
+    pub host: String,
+    /// Where the module sees the directory, an absolute path.
+    pub guest: String,
+    #[serde(default)]
+    pub writable: bool,
+}
+
+impl PluginManifest {
+    /// Parses a manifest. Manifests of the workspace are checked into repositories, so they can't
+    /// make themselves trusted.
+    pub fn parse(path: &Path, contents: &str, is_workspace: bool) -> Result<Self> {
+        let mut manifest = serde_json::from_str::<Self>(contents)?;
+        manifest.path = path.to_path_buf();
+        manifest.trusted &= !is_workspace;
+
+        let valid_name = regex::Regex::new(r"^[a-zA-Z][a-zA-Z0-9_]{0,63}$").expect("valid regex");
+        if !valid_name.is_match(&manifest.name) || manifest.name.contains("___") {
+            bail!(
+                "invalid name '{}', use up to 64 letters, digits and single underscores",
+                manifest.name
+            );
+        }
+        if manifest.description.trim().is_empty() {
+            bail!("the description is empty");
+        }
+        match (&manifest.command, &mut manifest.wasm) {
+            (Some(_), None) if manifest.preopens.is_empty() => (),
+            (Some(_), None) => bail!("preopens only apply to wasm plugins"),
+            (None, Some(module)) => {
+                if let Some(dir) = path.parent().filter(|_| module.is_relative()) {
+                    *module = dir.join(&*module);
+                }
+            },
+            _ => bail!("exactly one of command and wasm must be set"),
+        }
+        if let Some(preopen) = manifest.preopens.iter().find(|preopen| !preopen.guest.starts_with('/')) {
+            bail!("the guest path {} of a preopen must be absolute", preopen.guest);
+        }
+        if manifest.input_schema.get("type").and_then(Value::as_str) != Some("object") {
+            bail!("input_schema must be a JSON schema of type object");
+        }
+        if !(1..=MAX_TIMEOUT_SECONDS).contains(&manifest.timeout_seconds) {
+            bail!("timeout_seconds must be between 1 and {MAX_TIMEOUT_SECONDS}");
+        }
+        Ok(manifest)
+    }
+
+    /// Loads the manifests of the home directory, then of the workspace, which replace the ones
+    /// with the same name. Invalid manifests are reported to `output` and left out.
+    pub async fn load_all(ctx: &Context, output: &mut impl Write) -> Vec<Self> {
+        let mut plugins = BTreeMap::<String, Self>::new();
+        let dirs = [
+            (directories::chat_plugins_dir(ctx).ok(), false),
+            (
+                ctx.env()
+                    .current_dir()
+                    .ok()
+                    .map(|cwd| cwd.join(".amazonq").join("plugins")),
+                true,
+            ),
+        ];
+        for (dir, is_workspace) in dirs {
+            let Some(dir) = dir else {
+                continue;
+            };
+            let Ok(mut entries) = ctx.fs().read_dir(&dir).await else {
+                continue;
+            };
+            let mut paths = Vec::new();
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+                if path.extension().is_some_and(|extension| extension == "json") {
+                    paths.push(path);
+                }
+            }
+            paths.sort();
+
+            for path in paths {
+                let manifest = match ctx.fs().read_to_string(&path).await {
+                    Ok(contents) => Self::parse(&path, &contents, is_workspace),
+                    Err(err) => Err(err.into()),
+                };
+                match manifest {
+                    Ok(manifest) if manifest.wasm.is_some() && !wasm_runtime::is_available() => warn(
+                        output,
+                        format!(
+                            "Plugin {} is a WASI module, which this build can't run. Discarding.",
+                            path.display()
+                        ),
+                    ),
+                    Ok(manifest) => {
+                        let name = manifest.name.clone();
+                        if plugins.insert(name.clone(), manifest).is_some() {
+                            warn(
+                                output,
+                                format!("Plugin {name} is declared twice, using {}.", path.display()),
+                            );
+                        }
+                    },
+                    Err(err) => warn(
+                        output,
+                        format!("Error reading plugin {}: {err}. Discarding.", path.display()),
+                    ),
+                }
+            }
+        }
+        plugins.into_values().collect()
+    }
+
+    pub fn tool_spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            input_schema: InputSchema(self.input_schema.clone()),
+            tool_origin: ToolOrigin::Plugin,
+        }
+    }
+}
+
+fn warn(output: &mut impl Write, message: String) {
+    let _ = queue!(
+        output,
+        style::SetForegroundColor(Color::Yellow),
+        style::Print("WARNING: "),
+        style::ResetColor,
+        style::Print(message),
+        style::Print("\n"),
+    );
+}
+
+/// A use of a plugin tool.
+#[derive(Debug, Clone)]
+pub struct PluginTool {
+    pub manifest: Arc<PluginManifest>,
+    pub input: Value,
+}
+
+impl PluginTool {
+    pub fn requires_acceptance(&self) -> bool {
+        !self.manifest.trusted
+    }
+
+    pub async fn invoke(&self, ctx: &Context, _updates: &mut impl Write) -> Result<InvokeOutput> {
+        let name = &self.manifest.name;
+        let input = serde_json::to_vec(&self.input)?;
+        let (exit_status, stdout, stderr) = match (&self.manifest.command, &self.manifest.wasm) {
+            (Some(command), _) => self.run_command(ctx, command, input).await?,
+            (None, Some(module)) => {
+                let cwd = ctx.env().current_dir()?;
+                let preopens = self
+                    .manifest
+                    .preopens
+                    .iter()
+                    .map(|preopen| WasmPreopen {
+                        host: cwd.join(sanitize_path_tool_arg(ctx, &preopen.host)),
+                        guest: preopen.guest.clone(),
+                        writable: preopen.writable,
+                    })
+                    .collect();
+                let args = std::iter::once(name.clone())
+                    .chain(self.manifest.args.iter().cloned())
+                    .collect();
+                let output = wasm_runtime::run(
+                    module,
+                    args,
+                    input,
+                    preopens,
+                    Duration::from_secs(self.manifest.timeout_seconds),
+                    MAX_TOOL_RESPONSE_SIZE,
+                )
+                .await
+                .wrap_err_with(|| format!("{name} failed"))?;
+                let exit_status = (output.exit_code != 0).then(|| format!("exit code {}", output.exit_code));
+                (exit_status, output.stdout, output.stderr)
+            },
+            (None, None) => bail!("{name} has neither a command nor a wasm module"),
+        };
+
+        if let Some(exit_status) = exit_status {
+            let stderr = String::from_utf8_lossy(&stderr);
+            bail!(
+                "{name} failed with {exit_status}: {}",
+                truncate_safe(stderr.trim(), MAX_TOOL_RESPONSE_SIZE / 3)
+            );
+        }
+
+        Ok(InvokeOutput {
+            output: match self.manifest.output {
+                PluginOutput::Text => {
+                    let stdout = String::from_utf8_lossy(&stdout);
+                    OutputKind::Text(truncate_safe(&stdout, MAX_TOOL_RESPONSE_SIZE).to_string())
+                },
+                PluginOutput::Json => OutputKind::Json(
+                    serde_json::from_slice(&stdout).wrap_err_with(|| format!("{name} did not print valid JSON"))?,
+                ),
+            },
+        })
+    }
+
+    /// Runs the command of the plugin, returning how it failed if it did, and its output.
+    async fn run_command(
+        &self,
+        ctx: &Context,
+        command: &str,
+        input: Vec<u8>,
+    ) -> Result<(Option<String>, Vec<u8>, Vec<u8>)> {
+        let PluginManifest {
+            name,
+            args,
+            timeout_seconds,
+            ..
+        } = self.manifest.as_ref();
+        let mut child = tokio::process::Command::new(command)
+            .args(args)
+            .current_dir(ctx.env().current_dir()?)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .wrap_err_with(|| format!("Unable to run {command} for {name}"))?;
+
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let output = tokio::time::timeout(Duration::from_secs(*timeout_seconds), async move {
+            // Commands that don't read their input may exit before it is written.
+            stdin.write_all(&input).await.ok();
+            drop(stdin);
+            child.wait_with_output().await
+        })
+        .await;
+        let output = match output {
+            Ok(output) => output?,
+            Err(_) => bail!("{name} timed out after {timeout_seconds} seconds"),
+        };
+        let exit_status = (!output.status.success()).then(|| output.status.to_string());
+        Ok((exit_status, output.stdout, output.stderr))
+    }
+
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        let input = serde_json::to_string_pretty(&self.input)?
+            .lines()
+            .map(|line| format!("{CONTINUATION_LINE} {line}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let program = match (&self.manifest.command, &self.manifest.wasm) {
+            (Some(command), _) => command.clone(),
+            (None, module) => format!("{} in a sandbox", module.as_deref().unwrap_or(Path::new("")).display()),
+        };
+        let command_line = std::iter::once(program)
+            .chain(self.manifest.args.iter().cloned())
+            .chain(self.manifest.preopens.iter().map(|preopen| match preopen.writable {
+                true => format!("with {} writable", preopen.host),
+                false => format!("with {} read-only", preopen.host),
+            }))
+            .collect::<Vec<_>>()
+            .join(" ");
+        queue!(
+            updates,
+            style::Print("Running plugin "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.manifest.name),
+            style::ResetColor,
+            style::Print(" ("),
+            style::Print(command_line),
+            style::Print(") with the input:\n"),
+            style::Print(input),
+            style::Print("\n"),
+        )?;
+        Ok(())
+    }
+
+    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
+        let mut errors = Vec::new();
+        check_schema(&self.manifest.input_schema, &self.input, "input", &mut errors);
+        if !errors.is_empty() {
+            bail!("{}", errors.join(", "));
+        }
+        Ok(())
+    }
+}
+
+/// Checks the value against the subset of JSON schema used to describe tool inputs: `type`,
+/// `enum`, `properties`, `required`, `additionalProperties: false` and `items`.
+fn check_schema(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let types = match schema.get("type") {
+        Some(Value::String(name)) => vec![name.as_str()],
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|name| is_type(name, value)) {
+        errors.push(format!("{path} must be of type {}", types.join(" or ")));
+        return;
+    }
+    if let Some(Value::Array(variants)) = schema.get("enum") {
+        if !variants.contains(value) {
+            errors.push(format!(
+                "{path} must be one of {}",
+                variants.iter().map(Value::to_string).collect::<Vec<_>>().join(", ")
+            ));
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for name in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(name) {
+                        errors.push(format!("{path}.{name} is required"));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (name, value) in object {
+                match properties.and_then(|properties| properties.get(name)) {
+                    Some(property) => check_schema(property, value, &format!("{path}.{name}"), errors),
+                    None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                        errors.push(format!("{path}.{name} is not allowed"));
+                    },
+                    None => (),
+                }
+            }
+        },
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check_schema(item_schema, item, &format!("{path}[{i}]"), errors);
+                }
+            }
+        },
+        _ => (),
+    }
+}
+
+fn is_type(name: &str, value: &Value) -> bool {
+    match name {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const MANIFEST: &str = r#"{
+        "name": "word_count",
+        "description": "Counts the words of a file",
+        "command": "wc",
+        "args": ["-w"],
+        "input_schema": {
+            "type": "object",
+            "properties": {
+                "path": { "type": "string" },
+                "mode": { "type": "string", "enum": ["words", "lines"] },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["path"],
+            "additionalProperties": false
+        },
+        "trusted": true
+    }"#;
+
+    fn tool(input: Value) -> PluginTool {
+        PluginTool {
+            manifest: Arc::new(PluginManifest::parse(Path::new("word_count.json"), MANIFEST, false).unwrap()),
+            input,
+        }
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = PluginManifest::parse(Path::new("word_count.json"), MANIFEST, false).unwrap();
+        assert_eq!(manifest.args, vec!["-w"]);
+        assert_eq!(manifest.output, PluginOutput::Text);
+        assert_eq!(manifest.timeout_seconds, DEFAULT_TIMEOUT_SECONDS);
+        assert!(manifest.trusted);
+
+        let wasm = MANIFEST
+            .replace(r#""command": "wc","#, r#""wasm": "wc.wasm","#)
+            .replace(
+                r#""trusted": true"#,
+                r#""preopens": [{ "host": ".", "guest": "/workspace" }]"#,
+            );
+        let manifest = PluginManifest::parse(Path::new("/plugins/word_count.json"), &wasm, false).unwrap();
+        assert_eq!(manifest.wasm, Some(PathBuf::from("/plugins/wc.wasm")));
+        assert!(!manifest.preopens[0].writable);
+        let relative_guest = wasm.replace("/workspace", "workspace");
+        assert!(PluginManifest::parse(Path::new("/plugins/word_count.json"), &relative_guest, false).is_err());
+
+        // Workspace plugins are never trusted.
+        assert!(
+            !PluginManifest::parse(Path::new("word_count.json"), MANIFEST, true)
+                .unwrap()
+                .trusted
+        );
+
+        for invalid in [
+            MANIFEST.replace("word_count", "word-count"),
+            MANIFEST.replace("word_count", "word___count"),
+            MANIFEST.replace(r#""type": "object","#, r#""type": "string","#),
+            MANIFEST.replace(r#""trusted": true"#, r#""timeout_seconds": 0"#),
+            MANIFEST.replace(r#""trusted": true"#, r#""env": {}"#),
+            MANIFEST.replace(r#""command": "wc","#, ""),
+            MANIFEST.replace(r#""command": "wc","#, r#""command": "wc", "wasm": "wc.wasm","#),
+            MANIFEST.replace(
+                r#""trusted": true"#,
+                r#""preopens": [{ "host": ".", "guest": "/data" }]"#,
+            ),
+        ] {
+            assert!(PluginManifest::parse(Path::new("word_count.json"), &invalid, false).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        assert!(tool(json!({ "path": "README.md" })).validate(&ctx).await.is_ok());
+        assert!(
+            tool(json!({ "path": "README.md", "mode": "lines", "tags": ["a"] }))
+                .validate(&ctx)
+                .await
+                .is_ok()
+        );
+
+        for (input, error) in [
+            (json!({}), "input.path is required"),
+            (json!({ "path": 1 }), "input.path must be of type string"),
+            (
+                json!({ "path": "a", "mode": "bytes" }),
+                r#"input.mode must be one of "words", "lines""#,
+            ),
+            (
+                json!({ "path": "a", "tags": ["a", 2] }),
+                "input.tags[1] must be of type string",
+            ),
+            (json!({ "path": "a", "depth": 2 }), "input.depth is not allowed"),
+        ] {
+            assert_eq!(tool(input).validate(&ctx).await.unwrap_err().to_string(), error);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_invoke() {
+        let ctx = Context::new();
+        let mut manifest = PluginManifest::parse(Path::new("word_count.json"), MANIFEST, false).unwrap();
+        manifest.command = Some("cat".to_string());
+        manifest.args = Vec::new();
+        manifest.output = PluginOutput::Json;
+        let tool = PluginTool {
+            manifest: Arc::new(manifest.clone()),
+            input: json!({ "path": "README.md" }),
+        };
+        let output = tool.invoke(&ctx, &mut std::io::sink()).await.unwrap();
+        assert!(matches!(output.output, OutputKind::Json(value) if value == json!({ "path": "README.md" })));
+
+        manifest.command = Some("sleep".to_string());
+        manifest.args = vec!["5".to_string()];
+        manifest.timeout_seconds = 1;
+        let tool = PluginTool {
+            manifest: Arc::new(manifest),
+            input: json!({}),
+        };
+        let err = tool.invoke(&ctx, &mut std::io::sink()).await.unwrap_err();
+        assert_eq!(err.to_string(), "word_count timed out after 1 seconds");
+    }
+}
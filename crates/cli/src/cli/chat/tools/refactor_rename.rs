@@ -0,0 +1,332 @@
+//! Renames an identifier across the source files of a directory, using the tree-sitter parse of
+//! each file so that strings, comments and longer identifiers containing the name are left alone.
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Color,
+};
+use eyre::{
+    Result,
+    bail,
+};
+use regex::Regex;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use similar::{
+    ChangeTag,
+    TextDiff,
+};
+use tracing::debug;
+
+use super::code_symbols::{
+    Language,
+    identifier_ranges,
+    parse_symbols,
+    source_files,
+};
+use super::{
+    InvokeOutput,
+    OutputKind,
+    format_path,
+    sanitize_path_tool_arg,
+};
+use crate::cli::chat::accessibility;
+use crate::platform::Context;
+
+/// Renames touching more files than this are better done in steps or with an IDE.
+const MAX_CHANGED_FILES: usize = 200;
+
+/// Renames the identifiers named [RefactorRename::symbol] under [RefactorRename::path].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefactorRename {
+    /// A source file or a directory, every file under which is renamed in
+    pub path: String,
+    /// The current name of the symbol
+    pub symbol: String,
+    /// The new name of the symbol
+    pub new_name: String,
+}
+
+/// The new text of a file, with the lines where the symbol was renamed.
+#[derive(Debug, Clone)]
+struct RenamedFile {
+    path: PathBuf,
+    old_text: String,
+    new_text: String,
+    occurrences: usize,
+}
+
+impl RefactorRename {
+    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+        let cwd = ctx.env().current_dir()?;
+        let files = self.renamed_files(ctx).await?;
+        let mut summary = Vec::new();
+        for file in &files {
+            ctx.fs().write(&file.path, &file.new_text).await?;
+            summary.push(format!(
+                "{} ({} occurrences)",
+                format_path(ctx, &cwd, &file.path),
+                file.occurrences
+            ));
+        }
+
+        let occurrences = files.iter().map(|file| file.occurrences).sum::<usize>();
+        queue!(
+            updates,
+            style::Print(format!(
+                "Renamed {} occurrences of {} in {} files\n",
+                occurrences,
+                self.symbol,
+                files.len()
+            )),
+        )?;
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(format!(
+                "Renamed {} to {}, {occurrences} occurrences in {} files:\n{}",
+                self.symbol,
+                self.new_name,
+                files.len(),
+                summary.join("\n")
+            )),
+        })
+    }
+
+    /// The files where the symbol occurs, with the symbol renamed.
+    async fn renamed_files(&self, ctx: &Context) -> Result<Vec<RenamedFile>> {
+        let root = sanitize_path_tool_arg(ctx, &self.path);
+        let paths = if ctx.fs().symlink_metadata(&root).await?.is_dir() {
+            let (paths, truncated) = source_files(ctx, &root).await?;
+            if truncated {
+                bail!(
+                    "'{}' has too many source files, rename in a more specific path",
+                    self.path
+                );
+            }
+            paths
+        } else {
+            vec![root]
+        };
+
+        let mut is_defined = false;
+        let mut files = Vec::new();
+        for path in paths {
+            let Some(language) = Language::from_path(&path) else {
+                continue;
+            };
+            let Ok(old_text) = ctx.fs().read_to_string(&path).await else {
+                continue;
+            };
+            let ranges = match identifier_ranges(language, &old_text, &self.symbol) {
+                Ok(ranges) => ranges,
+                Err(err) => {
+                    debug!(?path, ?err, "Failed to parse");
+                    continue;
+                },
+            };
+            if ranges.is_empty() {
+                continue;
+            }
+            is_defined |= parse_symbols(language, &old_text)?
+                .iter()
+                .any(|symbol| symbol.name == self.symbol);
+
+            let mut new_text = String::with_capacity(old_text.len());
+            let mut end = 0;
+            for range in &ranges {
+                new_text.push_str(&old_text[end..range.start]);
+                new_text.push_str(&self.new_name);
+                end = range.end;
+            }
+            new_text.push_str(&old_text[end..]);
+            files.push(RenamedFile {
+                path,
+                old_text,
+                new_text,
+                occurrences: ranges.len(),
+            });
+        }
+
+        if files.is_empty() {
+            bail!("No occurrences of {} found in {}", self.symbol, self.path);
+        }
+        if !is_defined {
+            bail!(
+                "{} is not defined in {}, only symbols defined in the renamed files can be renamed",
+                self.symbol,
+                self.path
+            );
+        }
+        if files.len() > MAX_CHANGED_FILES {
+            bail!(
+                "Renaming {} would change {} files, more than the limit of {MAX_CHANGED_FILES}",
+                self.symbol,
+                files.len()
+            );
+        }
+        Ok(files)
+    }
+
+    /// Prints the changed lines of every file, for the user to review before accepting.
+    pub async fn queue_description(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
+        let cwd = ctx.env().current_dir()?;
+        queue!(
+            updates,
+            style::Print("Rename "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.symbol),
+            style::ResetColor,
+            style::Print(" to "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.new_name),
+            style::ResetColor,
+            style::Print(" in "),
+            style::SetForegroundColor(Color::Green),
+            style::Print(&self.path),
+            style::ResetColor,
+            style::Print("\n"),
+        )?;
+
+        for file in self.renamed_files(ctx).await? {
+            queue!(
+                updates,
+                style::Print("\n"),
+                style::SetForegroundColor(Color::Green),
+                style::Print(format_path(ctx, &cwd, &file.path)),
+                style::ResetColor,
+                style::Print("\n"),
+            )?;
+            let diff = TextDiff::from_lines(&file.old_text, &file.new_text);
+            for change in diff.iter_all_changes() {
+                let (sign, color, line) = match change.tag() {
+                    ChangeTag::Equal => continue,
+                    ChangeTag::Delete => ("-", Color::Red, change.old_index()),
+                    ChangeTag::Insert => ("+", Color::Green, change.new_index()),
+                };
+                let (line, text) = (line.map(|i| i + 1).unwrap_or_default(), change.value().trim_end());
+                let label = match accessibility::enabled() {
+                    true => format!("{} line {line}", accessibility::diff_label(change.tag())),
+                    false => format!("{sign} {line:>5}"),
+                };
+                queue!(
+                    updates,
+                    style::SetForegroundColor(color),
+                    style::Print(format!("{label}: {text}")),
+                    style::ResetColor,
+                    style::Print("\n"),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
+        let path = sanitize_path_tool_arg(ctx, &self.path);
+        if !path.exists() {
+            bail!("'{}' does not exist", self.path);
+        }
+        if ctx.fs().symlink_metadata(&path).await?.is_file() && Language::from_path(&path).is_none() {
+            bail!(
+                "'{}' is not in a supported language: Rust, Python, Go, JavaScript, TypeScript or Java",
+                self.path
+            );
+        }
+        let identifier = Regex::new(r"^[A-Za-z_$][A-Za-z0-9_$]*$").expect("valid regex");
+        for name in [&self.symbol, &self.new_name] {
+            if !identifier.is_match(name) {
+                bail!("'{name}' is not a valid identifier");
+            }
+        }
+        if self.symbol == self.new_name {
+            bail!("new_name must be different from symbol");
+        }
+        // Fail before asking the user to accept a rename that can't be done.
+        self.renamed_files(ctx).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIB_RS: &str = "\
+/// Parses the config, see parse_config.
+pub fn parse_config(input: &str) -> Config {
+    todo!(\"parse_config\")
+}
+";
+
+    const MAIN_RS: &str = "\
+fn main() {
+    let config = lib::parse_config(\"\");
+    let parse_config_count = 1;
+}
+";
+
+    async fn setup() -> std::sync::Arc<Context> {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().create_dir_all("/project/src").await.unwrap();
+        ctx.fs().write("/project/src/lib.rs", LIB_RS).await.unwrap();
+        ctx.fs().write("/project/src/main.rs", MAIN_RS).await.unwrap();
+        ctx.fs().write("/project/README.md", "parse_config").await.unwrap();
+        ctx
+    }
+
+    fn rename(symbol: &str, new_name: &str) -> RefactorRename {
+        RefactorRename {
+            path: "/project".to_string(),
+            symbol: symbol.to_string(),
+            new_name: new_name.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let ctx = setup().await;
+        assert!(rename("parse_config", "load_config").validate(&ctx).await.is_ok());
+        assert!(rename("parse_config", "load-config").validate(&ctx).await.is_err());
+        assert!(rename("parse_config", "parse_config").validate(&ctx).await.is_err());
+
+        let mut tool = rename("parse_config", "load_config");
+        tool.path = "/project/README.md".to_string();
+        assert!(tool.validate(&ctx).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rename() {
+        let ctx = setup().await;
+        rename("parse_config", "load_config")
+            .invoke(&ctx, &mut std::io::sink())
+            .await
+            .unwrap();
+
+        // Comments, strings and longer identifiers are left alone.
+        assert_eq!(
+            ctx.fs().read_to_string("/project/src/lib.rs").await.unwrap(),
+            LIB_RS.replace("pub fn parse_config", "pub fn load_config")
+        );
+        assert_eq!(
+            ctx.fs().read_to_string("/project/src/main.rs").await.unwrap(),
+            MAIN_RS.replace("lib::parse_config", "lib::load_config")
+        );
+        assert_eq!(
+            ctx.fs().read_to_string("/project/README.md").await.unwrap(),
+            "parse_config"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_undefined_symbol() {
+        let ctx = setup().await;
+        // Local variables are not definitions that can be renamed.
+        assert!(rename("config", "cfg").renamed_files(&ctx).await.is_err());
+        assert!(rename("missing", "found").renamed_files(&ctx).await.is_err());
+    }
+}
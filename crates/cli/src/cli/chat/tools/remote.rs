@@ -0,0 +1,412 @@
+//! Where execute_bash, fs_read and fs_write run: this machine, a host reached over SSH set with
+//! `chat.remoteHost` or `q chat --remote`, e.g. to debug on a dev box from a local chat, or a
+//! running container set with `chat.container` or `q chat --container`, e.g. to build and test in
+//! the dev container of the project.
+//!
+//! The `ssh` client of the system is used, so that its configuration, keys and agent apply. It
+//! runs in batch mode, failing rather than prompting for a password that nobody could type.
+//! Relative paths are resolved against the home directory of the user on the host.
+//!
+//! Commands run in a container as with `docker exec`. Paths on this machine under a bind mount of
+//! the container are mapped to the path of the mount in the container, and relative paths are
+//! resolved against the directory the current directory is mounted at.
+
+use std::fmt;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process::Stdio;
+use std::sync::Arc;
+
+use eyre::{
+    Result,
+    bail,
+};
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+
+use super::sanitize_path_tool_arg;
+use super::shell::Shell;
+use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::platform::Context;
+
+/// The name of `chat.container` that picks the dev container of the current directory.
+const DEVCONTAINER: &str = "devcontainer";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ExecutionTarget {
+    #[default]
+    Local,
+    /// A host as given to `ssh`, e.g. `user@devbox` or an alias of `~/.ssh/config`.
+    Ssh(String),
+    Container(Container),
+}
+
+/// A running container and where the directories of this machine are mounted in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Container {
+    pub name: String,
+    /// The bind mounts, from the directory on this machine to the directory in the container.
+    mounts: Vec<(PathBuf, String)>,
+    /// The directory in the container the current directory is mounted at.
+    workdir: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Mount {
+    source: PathBuf,
+    destination: String,
+}
+
+impl fmt::Display for ExecutionTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionTarget::Local => f.write_str("this machine"),
+            ExecutionTarget::Ssh(host) => write!(f, "{host} (ssh)"),
+            ExecutionTarget::Container(container) => write!(f, "container {} (docker)", container.name),
+        }
+    }
+}
+
+/// The context of a session whose tools run on `host` or `container`, given with `--remote` or
+/// `--container`, or else on the target of `chat.remoteHost` or `chat.container`.
+pub async fn configure(
+    ctx: Arc<Context>,
+    database: &Database,
+    host: Option<String>,
+    container: Option<String>,
+) -> Result<Arc<Context>> {
+    let setting = |setting| database.settings.get_string(setting).filter(|value| !value.is_empty());
+    let target = match (host, container) {
+        (Some(host), _) => ExecutionTarget::Ssh(host),
+        (None, Some(container)) => ExecutionTarget::Container(Container::inspect(&ctx, &container).await?),
+        (None, None) => match (setting(Setting::ChatRemoteHost), setting(Setting::ChatContainer)) {
+            (Some(host), _) => ExecutionTarget::Ssh(host),
+            (None, Some(container)) => ExecutionTarget::Container(Container::inspect(&ctx, &container).await?),
+            (None, None) => ExecutionTarget::Local,
+        },
+    };
+    Ok(ctx.with_execution_target(target))
+}
+
+impl ExecutionTarget {
+    pub fn is_local(&self) -> bool {
+        matches!(self, ExecutionTarget::Local)
+    }
+
+    /// What the model is told about where the tools run.
+    pub fn description(&self) -> Option<String> {
+        match self {
+            ExecutionTarget::Local => None,
+            ExecutionTarget::Ssh(host) => Some(format!(
+                "Runs on the remote host {host} over SSH, where relative paths start from the home directory."
+            )),
+            ExecutionTarget::Container(container) => Some(match &container.workdir {
+                Some(workdir) => format!(
+                    "Runs in the container {}, where the current directory is mounted at {workdir}.",
+                    container.name
+                ),
+                None => format!("Runs in the container {}.", container.name),
+            }),
+        }
+    }
+
+    /// The process running `command` with `shell` on the target, in the directory `cwd` when
+    /// given.
+    pub fn shell_command(&self, shell: &Shell, command: &str, cwd: Option<&str>) -> Result<tokio::process::Command> {
+        let (program, args) = shell.program();
+        match self {
+            ExecutionTarget::Local => Ok(shell.command(command)),
+            ExecutionTarget::Ssh(host) => {
+                let script = match cwd {
+                    Some(cwd) => format!("cd {} && {command}", quote(remote_path(cwd))?),
+                    None => command.to_string(),
+                };
+                let mut words = vec![program.to_string()];
+                words.extend(args.iter().map(|arg| (*arg).to_string()));
+                words.push(script);
+                let remote_command = words.iter().map(|word| quote(word)).collect::<Result<Vec<_>>>()?;
+                Ok(ssh(host, &remote_command.join(" ")))
+            },
+            ExecutionTarget::Container(container) => {
+                let cwd = cwd.map(|cwd| container.path(cwd));
+                let mut process = container.exec(cwd.as_deref());
+                process.arg(program).args(args).arg(command);
+                Ok(process)
+            },
+        }
+    }
+
+    /// The process running the `sh` script on the target.
+    fn script(&self, script: &str) -> tokio::process::Command {
+        match self {
+            ExecutionTarget::Local => {
+                let mut process = tokio::process::Command::new("sh");
+                process.args(["-c", script]);
+                process
+            },
+            ExecutionTarget::Ssh(host) => ssh(host, script),
+            ExecutionTarget::Container(container) => {
+                let mut process = container.exec(None);
+                process.args(["sh", "-c", script]);
+                process
+            },
+        }
+    }
+
+    /// The path of a file on the target.
+    fn path(&self, path: &str) -> String {
+        match self {
+            ExecutionTarget::Container(container) => container.path(path),
+            _ => remote_path(path).to_string(),
+        }
+    }
+}
+
+impl Container {
+    /// Finds where the directories of this machine are mounted in the running container `name`,
+    /// or in the dev container of the current directory when `name` is `devcontainer`.
+    async fn inspect(ctx: &Context, name: &str) -> Result<Self> {
+        let cwd = ctx.env().current_dir()?;
+        let name = match name {
+            DEVCONTAINER => {
+                let label = format!("label=devcontainer.local_folder={}", cwd.display());
+                let output = docker(&["ps", "--quiet", "--filter", &label]).await?;
+                match output.lines().next() {
+                    Some(id) => id.to_string(),
+                    None => bail!("No dev container is running for {}", cwd.display()),
+                }
+            },
+            _ => name.to_string(),
+        };
+
+        let output = docker(&["inspect", "--format", "{{.State.Running}} {{json .Mounts}}", &name]).await?;
+        let (running, mounts) = output.trim().split_once(' ').unwrap_or_default();
+        if running != "true" {
+            bail!("The container {name} isn't running");
+        }
+        let mounts = serde_json::from_str::<Vec<Mount>>(mounts)?
+            .into_iter()
+            .map(|mount| (mount.source, mount.destination))
+            .collect::<Vec<_>>();
+        let workdir = mapped_path(&mounts, &cwd);
+        Ok(Self { name, mounts, workdir })
+    }
+
+    /// `docker exec`, in `workdir` when given, or else in the directory the current directory is
+    /// mounted at.
+    fn exec(&self, workdir: Option<&str>) -> tokio::process::Command {
+        let mut process = tokio::process::Command::new("docker");
+        process.args(["exec", "--interactive"]);
+        if let Some(workdir) = workdir.or(self.workdir.as_deref()) {
+            process.args(["--workdir", workdir]);
+        }
+        process
+            .arg(&self.name)
+            // Stops the command when the tool times out.
+            .kill_on_drop(true);
+        process
+    }
+
+    /// The path in the container of a path on this machine, as given when it isn't mounted.
+    /// Relative paths start from the directory the current directory is mounted at.
+    fn path(&self, path: &str) -> String {
+        match &self.workdir {
+            Some(workdir) if Path::new(path).is_relative() => {
+                Path::new(workdir).join(path).to_string_lossy().to_string()
+            },
+            _ => mapped_path(&self.mounts, Path::new(path)).unwrap_or_else(|| path.to_string()),
+        }
+    }
+}
+
+/// The path in the container of `path`, when it is under one of the `mounts`.
+fn mapped_path(mounts: &[(PathBuf, String)], path: &Path) -> Option<String> {
+    mounts
+        .iter()
+        .filter(|(source, _)| path.starts_with(source))
+        .max_by_key(|(source, _)| source.components().count())
+        .and_then(|(source, destination)| {
+            let relative = path.strip_prefix(source).ok()?;
+            Some(Path::new(destination).join(relative).to_string_lossy().to_string())
+        })
+}
+
+/// Reads the file at `path`, on the target of the session.
+pub async fn read_to_string(ctx: &Context, path: &str) -> Result<String> {
+    let target = ctx.execution_target();
+    if target.is_local() {
+        return Ok(ctx.fs().read_to_string(sanitize_path_tool_arg(ctx, path)).await?);
+    }
+    let output = target
+        .script(&format!("cat -- {}", quote(&target.path(path))?))
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!(
+            "Failed to read {path} on {target}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Whether a file exists at `path`, on the target of the session.
+pub async fn exists(ctx: &Context, path: &str) -> bool {
+    let target = ctx.execution_target();
+    if target.is_local() {
+        return ctx.fs().exists(sanitize_path_tool_arg(ctx, path));
+    }
+    let Ok(path) = quote(&target.path(path)) else {
+        return false;
+    };
+    target
+        .script(&format!("test -e {path}"))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .is_ok_and(|status| status.success())
+}
+
+/// Writes `content` to the file at `path`, creating its parent directories, on the target of the
+/// session.
+pub async fn write(ctx: &Context, path: &str, content: &str) -> Result<()> {
+    let target = ctx.execution_target();
+    if target.is_local() {
+        let path = sanitize_path_tool_arg(ctx, path);
+        if let Some(parent) = path.parent() {
+            ctx.fs().create_dir_all(parent).await?;
+        }
+        return Ok(ctx.fs().write(path, content).await?);
+    }
+    let target_path = target.path(path);
+    let parent = Path::new(&target_path)
+        .parent()
+        .map(|parent| parent.to_string_lossy())
+        .filter(|parent| !parent.is_empty());
+    let write = format!("cat > {}", quote(&target_path)?);
+    let script = match parent {
+        Some(parent) => format!("mkdir -p -- {} && {write}", quote(&parent)?),
+        None => write,
+    };
+    let mut child = target
+        .script(&script)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(content.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        bail!(
+            "Failed to write {path} on {target}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn ssh(host: &str, remote_command: &str) -> tokio::process::Command {
+    let mut process = tokio::process::Command::new("ssh");
+    process
+        .args(["-o", "BatchMode=yes", "-T", host, "--", remote_command])
+        // Stops the command when the tool times out.
+        .kill_on_drop(true);
+    process
+}
+
+/// Runs `docker` with `args`, returning its stdout.
+async fn docker(args: &[&str]) -> Result<String> {
+    let output = tokio::process::Command::new("docker").args(args).output().await?;
+    if !output.status.success() {
+        bail!(
+            "docker {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// The path on the host, where `~/` is the home directory ssh starts in.
+fn remote_path(path: &str) -> &str {
+    match path {
+        "~" => ".",
+        _ => path.strip_prefix("~/").unwrap_or(path),
+    }
+}
+
+/// Quotes a word for the shell of the user on the host.
+fn quote(word: &str) -> Result<String> {
+    Ok(shlex::try_quote(word)?.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_command() {
+        let command = ExecutionTarget::Ssh("dev".to_string())
+            .shell_command(&Shell::default(), "ls -l | wc -l", Some("~/my project"))
+            .unwrap();
+        let args = command.as_std().get_args().collect::<Vec<_>>();
+        assert_eq!(args[..5], ["-o", "BatchMode=yes", "-T", "dev", "--"]);
+        assert_eq!(args[5], "bash -c \"cd 'my project' && ls -l | wc -l\"");
+        assert_eq!(remote_path("~/src/main.rs"), "src/main.rs");
+        assert_eq!(remote_path("/etc/hosts"), "/etc/hosts");
+    }
+
+    #[tokio::test]
+    async fn test_configure() {
+        let database = Database::new().await.unwrap();
+        let ctx = Context::new();
+        let remote_ctx = configure(ctx.clone(), &database, Some("dev".to_string()), None)
+            .await
+            .unwrap();
+        assert_eq!(remote_ctx.execution_target(), &ExecutionTarget::Ssh("dev".to_string()));
+        assert!(ctx.execution_target().is_local());
+    }
+
+    #[test]
+    fn test_container() {
+        let container = Container {
+            name: "app".to_string(),
+            mounts: vec![
+                (PathBuf::from("/home/me/app"), "/workspaces/app".to_string()),
+                (PathBuf::from("/home/me/app/cache"), "/cache".to_string()),
+            ],
+            workdir: Some("/workspaces/app".to_string()),
+        };
+        assert_eq!(
+            container.path("/home/me/app/src/main.rs"),
+            "/workspaces/app/src/main.rs"
+        );
+        assert_eq!(container.path("/home/me/app/cache/a"), "/cache/a");
+        assert_eq!(container.path("src/main.rs"), "/workspaces/app/src/main.rs");
+        assert_eq!(container.path("/usr/include/stdio.h"), "/usr/include/stdio.h");
+
+        let command = ExecutionTarget::Container(container)
+            .shell_command(&Shell::default(), "make test", Some("/home/me/app/lib"))
+            .unwrap();
+        let args = command.as_std().get_args().collect::<Vec<_>>();
+        assert_eq!(args, [
+            "exec",
+            "--interactive",
+            "--workdir",
+            "/workspaces/app/lib",
+            "app",
+            "bash",
+            "-c",
+            "make test"
+        ]);
+    }
+}
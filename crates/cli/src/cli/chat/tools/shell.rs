@@ -0,0 +1,93 @@
+//! The shell running the commands of execute_bash, `chat.shell`, and whether it sources the
+//! profile of the user first, `chat.shellSourceProfile`.
+//!
+//! Without the profile, the shell starts without reading any startup file, so that commands behave
+//! the same whatever the dotfiles of the user. Which variables of the environment the commands see
+//! is up to the [super::env_policy::EnvPolicy].
+
+use std::fmt;
+
+use crate::database::Database;
+use crate::database::settings::Setting;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShellKind {
+    #[default]
+    Bash,
+    Zsh,
+    Fish,
+    Pwsh,
+}
+
+impl fmt::Display for ShellKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ShellKind::Bash => "bash",
+            ShellKind::Zsh => "zsh",
+            ShellKind::Fish => "fish",
+            ShellKind::Pwsh => "pwsh",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Shell {
+    pub kind: ShellKind,
+    pub source_profile: bool,
+}
+
+impl Shell {
+    pub fn from_database(database: &Database) -> Self {
+        let kind = match database.settings.get_string(Setting::ChatShell).as_deref() {
+            Some("zsh") => ShellKind::Zsh,
+            Some("fish") => ShellKind::Fish,
+            Some("pwsh") => ShellKind::Pwsh,
+            _ => ShellKind::Bash,
+        };
+        Self {
+            kind,
+            source_profile: database.settings.get_bool_or_default(Setting::ChatShellSourceProfile),
+        }
+    }
+
+    /// The process running `command` in the shell.
+    pub fn command(&self, command: &str) -> tokio::process::Command {
+        let (program, args) = self.program();
+        let mut process = tokio::process::Command::new(program);
+        process.args(args).arg(command);
+        process
+    }
+
+    /// The program of the shell and its arguments before the command.
+    pub fn program(&self) -> (&'static str, &'static [&'static str]) {
+        match (self.kind, self.source_profile) {
+            // Non-interactive bash reads no startup file, unless it is a login shell.
+            (ShellKind::Bash, false) => ("bash", &["-c"]),
+            (ShellKind::Bash, true) => ("bash", &["-l", "-c"]),
+            // zsh always reads ~/.zshenv, unless started with -f.
+            (ShellKind::Zsh, false) => ("zsh", &["-f", "-c"]),
+            (ShellKind::Zsh, true) => ("zsh", &["-l", "-c"]),
+            (ShellKind::Fish, false) => ("fish", &["--no-config", "-c"]),
+            (ShellKind::Fish, true) => ("fish", &["-l", "-c"]),
+            (ShellKind::Pwsh, false) => ("pwsh", &["-NoProfile", "-NonInteractive", "-Command"]),
+            (ShellKind::Pwsh, true) => ("pwsh", &["-NonInteractive", "-Command"]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program() {
+        assert_eq!(Shell::default().program(), ("bash", &["-c"][..]));
+        let shell = |kind, source_profile| Shell { kind, source_profile };
+        assert_eq!(shell(ShellKind::Zsh, false).program(), ("zsh", &["-f", "-c"][..]));
+        assert_eq!(shell(ShellKind::Fish, true).program(), ("fish", &["-l", "-c"][..]));
+        assert_eq!(
+            shell(ShellKind::Pwsh, false).program(),
+            ("pwsh", &["-NoProfile", "-NonInteractive", "-Command"][..])
+        );
+    }
+}
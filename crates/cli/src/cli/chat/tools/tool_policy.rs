@@ -0,0 +1,286 @@
+//! Declarative rules allowing, denying or asking for the approval of tool uses.
+//!
+//! The policy is a YAML file, chat.policyFile or `~/.aws/amazonq/policy.yaml`, listing rules that
+//! match on the name of the tool, its arguments, the files it touches, the command it runs and
+//! where, and environment variables. The first rule matching a tool use decides, and tool uses no
+//! rule matches are approved as usual. Unlike trusted tools, the rules also apply with
+//! `--trust-all-tools`, so that an organization can set guardrails users can't lift.
+//!
+//! ```yaml
+//! rules:
+//!   - tool: execute_bash
+//!     command: '\brm\s+-rf?\b'
+//!     decision: deny
+//!     reason: Delete files yourself
+//!   - tool: fs_write
+//!     path: '/etc/**'
+//!     decision: deny
+//!   - tool: use_aws
+//!     env:
+//!       AWS_PROFILE: 'prod*'
+//!     decision: prompt
+//!   - tool: fs_read
+//!     decision: allow
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use eyre::{
+    Result,
+    WrapErr,
+};
+use globset::{
+    Glob,
+    GlobBuilder,
+    GlobMatcher,
+};
+use regex::Regex;
+use serde::Deserialize;
+
+use super::Tool;
+use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::platform::Context;
+use crate::util::directories;
+
+/// What a rule decides for the tool uses it matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyDecision {
+    /// Run without asking, unless the tool always asks, e.g. download_file.
+    Allow,
+    /// Refuse to run, the model is told why.
+    Deny,
+    /// Ask for approval even if the tool is trusted.
+    Prompt,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    tool: Option<String>,
+    args: Option<String>,
+    path: Option<String>,
+    command: Option<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    decision: PolicyDecision,
+    reason: Option<String>,
+}
+
+impl RuleConfig {
+    fn compile(self) -> Result<PolicyRule> {
+        let glob = |pattern: &str| Ok::<_, globset::Error>(Glob::new(pattern)?.compile_matcher());
+        Ok(PolicyRule {
+            tool: self.tool.as_deref().map(glob).transpose()?,
+            args: self.args.as_deref().map(Regex::new).transpose()?,
+            path: self
+                .path
+                .as_deref()
+                .map(|path| GlobBuilder::new(path).literal_separator(true).build())
+                .transpose()?
+                .map(|glob| glob.compile_matcher()),
+            command: self.command.as_deref().map(Regex::new).transpose()?,
+            env: self
+                .env
+                .into_iter()
+                .map(|(name, pattern)| Ok((name, glob(&pattern)?)))
+                .collect::<Result<_, globset::Error>>()?,
+            decision: self.decision,
+            reason: self.reason,
+        })
+    }
+}
+
+/// A rule of the policy. The conditions that are set must all match.
+#[derive(Debug, Clone)]
+pub struct PolicyRule {
+    /// Glob on the name of the tool.
+    tool: Option<GlobMatcher>,
+    /// Regex searched in the arguments of the tool, as JSON.
+    args: Option<Regex>,
+    /// Glob on the absolute paths the tool reads or writes, or the directory execute_bash runs in,
+    /// matching if any of them does.
+    path: Option<GlobMatcher>,
+    /// Regex searched in the command of execute_bash.
+    command: Option<Regex>,
+    /// Globs on environment variables, which don't match when the variable isn't set.
+    env: Vec<(String, GlobMatcher)>,
+    pub decision: PolicyDecision,
+    pub reason: Option<String>,
+}
+
+impl PolicyRule {
+    fn matches(&self, ctx: &Context, tool_name: &str, args: &serde_json::Value, tool: &Tool) -> bool {
+        if self.tool.as_ref().is_some_and(|glob| !glob.is_match(tool_name)) {
+            return false;
+        }
+        if self
+            .args
+            .as_ref()
+            .is_some_and(|regex| !regex.is_match(&args.to_string()))
+        {
+            return false;
+        }
+        if let Some(glob) = &self.path {
+            if !tool.policy_paths(ctx).iter().any(|path| glob.is_match(path)) {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.command {
+            match tool {
+                Tool::ExecuteBash(execute_bash) if regex.is_match(&execute_bash.command) => (),
+                _ => return false,
+            }
+        }
+        self.env
+            .iter()
+            .all(|(name, glob)| ctx.env().get(name).is_ok_and(|value| glob.is_match(value)))
+    }
+
+    /// The error returned to the model for a tool use the rule denies.
+    pub fn denial(&self, tool_name: &str) -> String {
+        match &self.reason {
+            Some(reason) => format!("The tool policy denies this use of {tool_name}: {reason}"),
+            None => format!("The tool policy denies this use of {tool_name}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ToolPolicy {
+    rules: Vec<PolicyRule>,
+}
+
+impl ToolPolicy {
+    /// The policy in chat.policyFile, or in the default location if there is one there. A policy
+    /// that can't be read is an error rather than being ignored.
+    pub fn from_database(ctx: &Context, database: &Database) -> Result<Self> {
+        let path = match database.settings.get_string(Setting::ChatPolicyFile) {
+            Some(path) => PathBuf::from(shellexpand::tilde(&path).as_ref()),
+            None => match directories::chat_policy_path(ctx) {
+                Ok(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let yaml = std::fs::read_to_string(&path)
+            .wrap_err_with(|| format!("Failed to read the tool policy {}", path.display()))?;
+        Self::parse(&yaml).wrap_err_with(|| format!("Invalid tool policy {}", path.display()))
+    }
+
+    pub fn parse(yaml: &str) -> Result<Self> {
+        let file: PolicyFile = serde_yaml::from_str(yaml)?;
+        let rules = file
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(index, rule)| rule.compile().wrap_err_with(|| format!("Invalid rule {}", index + 1)))
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    /// The first rule matching the tool use.
+    pub fn evaluate(
+        &self,
+        ctx: &Context,
+        tool_name: &str,
+        args: &serde_json::Value,
+        tool: &Tool,
+    ) -> Option<&PolicyRule> {
+        self.rules.iter().find(|rule| rule.matches(ctx, tool_name, args, tool))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::chat::tools::execute_bash::ExecuteBash;
+    use crate::cli::chat::tools::fs_write::FsWrite;
+
+    const POLICY: &str = r#"
+rules:
+  - tool: execute_bash
+    command: '\brm\s+-rf?\b'
+    decision: deny
+    reason: Delete files yourself
+  - tool: fs_write
+    path: '/etc/**'
+    decision: deny
+  - tool: execute_bash
+    path: '/etc/**'
+    decision: prompt
+  - tool: 'fs_*'
+    env:
+      Q_POLICY_TEST_ENV: 'prod*'
+    decision: prompt
+  - tool: '*'
+    args: '"ls'
+    decision: allow
+"#;
+
+    fn decision(ctx: &Context, policy: &ToolPolicy, name: &str, args: serde_json::Value) -> Option<PolicyDecision> {
+        let tool = match name {
+            "execute_bash" => Tool::ExecuteBash(serde_json::from_value::<ExecuteBash>(args.clone()).unwrap()),
+            _ => Tool::FsWrite(serde_json::from_value::<FsWrite>(args.clone()).unwrap()),
+        };
+        policy.evaluate(ctx, name, &args, &tool).map(|rule| rule.decision)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let ctx = Context::builder().with_env_var("HOME", "/home/testuser").build_fake();
+        let policy = ToolPolicy::parse(POLICY).unwrap();
+        let bash = |command: &str| serde_json::json!({ "command": command });
+        let write = |path: &str| serde_json::json!({ "command": "create", "path": path, "file_text": "a" });
+
+        assert_eq!(
+            decision(&ctx, &policy, "execute_bash", bash("rm -rf target")),
+            Some(PolicyDecision::Deny)
+        );
+        assert_eq!(
+            decision(&ctx, &policy, "execute_bash", bash("ls -la")),
+            Some(PolicyDecision::Allow)
+        );
+        assert_eq!(decision(&ctx, &policy, "execute_bash", bash("cargo build")), None);
+        assert_eq!(
+            decision(
+                &ctx,
+                &policy,
+                "execute_bash",
+                serde_json::json!({ "command": "cat nginx.conf", "cwd": "/etc/nginx" })
+            ),
+            Some(PolicyDecision::Prompt)
+        );
+        assert_eq!(
+            decision(&ctx, &policy, "fs_write", write("/etc/hosts")),
+            Some(PolicyDecision::Deny)
+        );
+        assert_eq!(decision(&ctx, &policy, "fs_write", write("/tmp/hosts")), None);
+    }
+
+    #[test]
+    fn test_evaluate_env() {
+        let ctx = Context::builder()
+            .with_env_var("Q_POLICY_TEST_ENV", "production")
+            .build_fake();
+        let policy = ToolPolicy::parse(POLICY).unwrap();
+        let write = serde_json::json!({ "command": "create", "path": "/tmp/a", "file_text": "a" });
+        assert_eq!(decision(&ctx, &policy, "fs_write", write), Some(PolicyDecision::Prompt));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(ToolPolicy::parse("rules:\n  - tool: fs_write\n").is_err());
+        assert!(ToolPolicy::parse("rules:\n  - command: '('\n    decision: deny\n").is_err());
+        assert!(ToolPolicy::parse("rules:\n  - tool: fs_write\n    decision: maybe\n").is_err());
+        assert!(ToolPolicy::parse("").unwrap().rules.is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+//! Which URLs the tools fetching web content may reach.
+//!
+//! Private, loopback and link-local addresses, which include cloud metadata endpoints such as
+//! `169.254.169.254`, are blocked unless `chat.web.allowPrivateNetwork` is set. Tools check the URL
+//! when validated, then [UrlPolicy::get] resolves the host of every request, redirects included,
+//! checks every address and pins the request to them so that DNS can't change its answer in
+//! between.
+
+use std::net::{
+    IpAddr,
+    Ipv4Addr,
+    Ipv6Addr,
+    SocketAddr,
+};
+use std::time::Duration;
+
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use reqwest::header::{
+    HeaderMap,
+    LOCATION,
+};
+use reqwest::redirect;
+use url::{
+    Host,
+    Url,
+};
+
+use crate::database::Database;
+use crate::database::settings::Setting;
+
+/// Redirects followed before giving up.
+const MAX_REDIRECTS: usize = 10;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UrlPolicy {
+    /// Domains that may be fetched, including their subdomains. Any domain when empty.
+    pub allowed_domains: Vec<String>,
+    /// Domains that may not be fetched, including their subdomains. Takes precedence over
+    /// [Self::allowed_domains].
+    pub denied_domains: Vec<String>,
+    pub allow_private_network: bool,
+}
+
+impl UrlPolicy {
+    pub fn from_database(database: &Database) -> Self {
+        Self {
+            allowed_domains: database
+                .settings
+                .get_string_list(Setting::ChatWebAllowedDomains)
+                .unwrap_or_default(),
+            denied_domains: database
+                .settings
+                .get_string_list(Setting::ChatWebDeniedDomains)
+                .unwrap_or_default(),
+            allow_private_network: database
+                .settings
+                .get_bool_or_default(Setting::ChatWebAllowPrivateNetwork),
+        }
+    }
+
+    /// Checks the URL without resolving its host: the scheme, the domain lists and literal IP
+    /// addresses.
+    pub fn check_url(&self, url: &Url) -> Result<()> {
+        if !matches!(url.scheme(), "http" | "https") {
+            bail!("Only HTTP and HTTPS URLs are supported");
+        }
+
+        match url.host() {
+            Some(Host::Domain(domain)) => {
+                let domain = domain.trim_end_matches('.').to_lowercase();
+                if self.denied_domains.iter().any(|d| domain_matches(&domain, d)) {
+                    bail!("{domain} is denied by chat.web.deniedDomains");
+                }
+                let allowed = self.allowed_domains.iter().any(|d| domain_matches(&domain, d));
+                if !self.allowed_domains.is_empty() && !allowed {
+                    bail!("{domain} is not in chat.web.allowedDomains");
+                }
+                if !self.allow_private_network && (domain == "localhost" || domain.ends_with(".localhost")) {
+                    bail!("{domain} is a local address, set chat.web.allowPrivateNetwork to allow it");
+                }
+            },
+            Some(Host::Ipv4(ip)) => self.check_ip(IpAddr::V4(ip))?,
+            Some(Host::Ipv6(ip)) => self.check_ip(IpAddr::V6(ip))?,
+            None => bail!("{url} has no host"),
+        }
+        Ok(())
+    }
+
+    /// Checks the URL and every address its host resolves to, returning the addresses.
+    pub async fn check(&self, url: &Url) -> Result<Vec<SocketAddr>> {
+        self.check_url(url)?;
+        let host = url.host_str().ok_or_else(|| eyre!("{url} has no host"))?;
+        let port = url.port_or_known_default().unwrap_or(443);
+        let addrs = tokio::net::lookup_host((host.trim_start_matches('[').trim_end_matches(']'), port))
+            .await
+            .map_err(|err| eyre!("Failed to resolve {host}: {err}"))?
+            .collect::<Vec<_>>();
+        if addrs.is_empty() {
+            bail!("{host} did not resolve to any address");
+        }
+        for addr in &addrs {
+            self.check_ip(addr.ip())
+                .map_err(|err| eyre!("{host} resolves to a blocked address: {err}"))?;
+        }
+        Ok(addrs)
+    }
+
+    fn check_ip(&self, ip: IpAddr) -> Result<()> {
+        if !self.allow_private_network && is_private(ip) {
+            bail!("{ip} is a private or local address, set chat.web.allowPrivateNetwork to allow it");
+        }
+        Ok(())
+    }
+
+    /// Sends a GET request, checking the URL and every redirect against the policy.
+    pub async fn get(&self, url: &str, timeout: Duration, headers: HeaderMap) -> Result<reqwest::Response> {
+        let mut url = Url::parse(url).map_err(|e| eyre!("Invalid URL '{}': {}", url, e))?;
+        for _ in 0..=MAX_REDIRECTS {
+            let addrs = self.check(&url).await?;
+            let mut builder = crate::request::client_builder()
+                .timeout(timeout)
+                .redirect(redirect::Policy::none());
+            if let Some(Host::Domain(domain)) = url.host() {
+                builder = builder.resolve_to_addrs(domain, &addrs);
+            }
+            let response = builder
+                .build()?
+                .get(url.clone())
+                .headers(headers.clone())
+                .send()
+                .await
+                .map_err(|e| eyre!("Failed to fetch URL: {}", e))?;
+
+            if !response.status().is_redirection() {
+                return Ok(response);
+            }
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or_else(|| eyre!("{url} redirected without a location"))?;
+            url = url
+                .join(location)
+                .map_err(|e| eyre!("Invalid redirect from {url} to '{location}': {e}"))?;
+        }
+        bail!("Too many redirects")
+    }
+}
+
+/// Whether `domain` is `pattern` or one of its subdomains.
+fn domain_matches(domain: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim_start_matches("*.").trim_start_matches('.').to_lowercase();
+    domain == pattern || domain.strip_suffix(&pattern).is_some_and(|rest| rest.ends_with('.'))
+}
+
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private_v4(ip),
+            None => is_private_v6(ip),
+        },
+    }
+}
+
+fn is_private_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        // 0.0.0.0/8
+        || a == 0
+        // Shared address space, 100.64.0.0/10
+        || (a == 100 && (b & 0b1100_0000) == 64)
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        // Unique local, fc00::/7, e.g. the EC2 metadata endpoint fd00:ec2::254
+        || (first & 0xfe00) == 0xfc00
+        // Link-local, fe80::/10
+        || (first & 0xffc0) == 0xfe80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn test_check_url_blocks_private_addresses() {
+        let policy = UrlPolicy::default();
+        for blocked in [
+            "http://169.254.169.254/latest/meta-data/",
+            "http://127.0.0.1:8080",
+            "http://10.0.0.1",
+            "http://192.168.1.1",
+            "http://100.64.0.1",
+            "http://0.0.0.0",
+            "http://[::1]/",
+            "http://[fd00:ec2::254]/",
+            "http://[::ffff:127.0.0.1]/",
+            "http://localhost:3000",
+            "http://app.localhost",
+            "file:///etc/passwd",
+        ] {
+            assert!(policy.check_url(&url(blocked)).is_err(), "{blocked} should be blocked");
+        }
+        assert!(policy.check_url(&url("https://8.8.8.8")).is_ok());
+        assert!(policy.check_url(&url("https://docs.aws.amazon.com")).is_ok());
+
+        let policy = UrlPolicy {
+            allow_private_network: true,
+            ..Default::default()
+        };
+        assert!(policy.check_url(&url("http://169.254.169.254/")).is_ok());
+    }
+
+    #[test]
+    fn test_check_url_domain_lists() {
+        let policy = UrlPolicy {
+            allowed_domains: vec!["amazon.com".to_string(), "*.rust-lang.org".to_string()],
+            denied_domains: vec!["internal.amazon.com".to_string()],
+            allow_private_network: false,
+        };
+        assert!(policy.check_url(&url("https://docs.aws.amazon.com")).is_ok());
+        assert!(policy.check_url(&url("https://amazon.com")).is_ok());
+        assert!(policy.check_url(&url("https://doc.rust-lang.org/std")).is_ok());
+        assert!(policy.check_url(&url("https://wiki.internal.amazon.com")).is_err());
+        assert!(policy.check_url(&url("https://notamazon.com")).is_err());
+        assert!(policy.check_url(&url("https://example.com")).is_err());
+    }
+}
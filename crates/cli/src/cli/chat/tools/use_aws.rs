@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use std::io::Write;
+use std::path::PathBuf;
 use std::process::Stdio;
+use std::time::Duration;
 
 use bstr::ByteSlice;
 use convert_case::{
@@ -14,24 +16,48 @@ use crossterm::{
 use eyre::{
     Result,
     WrapErr,
+    bail,
 };
 use serde::Deserialize;
+use tokio::io::{
+    AsyncBufReadExt,
+    AsyncRead,
+    AsyncReadExt,
+    BufReader,
+};
+use tokio::process::Command;
 
+use super::aws_policy::{
+    AwsPolicy,
+    OperationClass,
+};
+use super::aws_resources::ResourceLookup;
 use super::{
     InvokeOutput,
     MAX_TOOL_RESPONSE_SIZE,
     OutputKind,
+    aws_models,
+};
+use crate::cli::chat::util::truncate::{
+    Keep,
+    truncate_text,
 };
 use crate::platform::Context;
 
-const READONLY_OPS: [&str; 6] = ["get", "describe", "list", "ls", "search", "batch_get"];
-
 /// The environment variable name where we set additional metadata for the AWS CLI user agent.
 const USER_AGENT_ENV_VAR: &str = "AWS_EXECUTION_ENV";
 const USER_AGENT_APP_NAME: &str = "AmazonQ-For-CLI";
 const USER_AGENT_VERSION_KEY: &str = "Version";
 const USER_AGENT_VERSION_VALUE: &str = env!("CARGO_PKG_VERSION");
 
+/// Bounds of [LogsTail::duration_seconds] and [LogsTail::max_lines].
+const MAX_TAIL_DURATION_SECONDS: u64 = 300;
+const MAX_TAIL_LINES: usize = 1000;
+
+/// Services whose operations accept `--dry-run`, checking the permissions and parameters of a
+/// request without making it.
+const DRY_RUN_SERVICES: &[&str] = &["ec2"];
+
 // TODO: we should perhaps composite this struct with an interface that we can use to mock the
 // actual cli with. That will allow us to more thoroughly test it.
 #[derive(Debug, Clone, Deserialize)]
@@ -42,55 +68,105 @@ pub struct UseAws {
     pub region: String,
     pub profile_name: Option<String>,
     pub label: Option<String>,
+    /// Follows a CloudWatch Logs log group for a bounded time instead of running an operation,
+    /// used with the `logs` service and the `tail` operation.
+    #[serde(default)]
+    pub logs_tail: Option<LogsTail>,
+    /// Which services may be called, set from the settings before validation.
+    #[serde(skip)]
+    pub policy: AwsPolicy,
+    /// The parameters the user replaced when approving the call, with the values of the model,
+    /// which are reported with the output.
+    #[serde(skip)]
+    pub fixed_parameters: Vec<(String, serde_json::Value)>,
+    /// The current directory of the session once validated, which `file://` parameters are
+    /// relative to.
+    #[serde(skip)]
+    working_dir: Option<PathBuf>,
 }
 
-impl UseAws {
-    pub fn requires_acceptance(&self) -> bool {
-        !READONLY_OPS.iter().any(|op| self.operation_name.starts_with(op))
-    }
+/// A live tail of a log group, stopped after [LogsTail::duration_seconds] or
+/// [LogsTail::max_lines] lines, whichever comes first.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogsTail {
+    pub log_group_name: String,
+    /// Optional: Only tail these log streams of the group
+    #[serde(default)]
+    pub log_stream_names: Vec<String>,
+    /// Optional: A CloudWatch Logs filter pattern the lines must match
+    pub filter_pattern: Option<String>,
+    /// Optional: How far back to start, e.g. `5m` or `1h` (default: the AWS CLI default of 10m)
+    pub since: Option<String>,
+    /// Optional: How long to tail for in seconds (default: 30)
+    #[serde(default = "default_tail_duration_seconds")]
+    pub duration_seconds: u64,
+    /// Optional: The most lines to capture (default: 200)
+    #[serde(default = "default_tail_max_lines")]
+    pub max_lines: usize,
+}
 
-    pub async fn invoke(&self, _ctx: &Context, _updates: impl Write) -> Result<InvokeOutput> {
-        let mut command = tokio::process::Command::new("aws");
+fn default_tail_duration_seconds() -> u64 {
+    30
+}
 
-        // Set up environment variables
-        let mut env_vars: std::collections::HashMap<String, String> = std::env::vars().collect();
+fn default_tail_max_lines() -> usize {
+    200
+}
 
-        // Set up additional metadata for the AWS CLI user agent
-        let user_agent_metadata_value = format!(
-            "{} {}/{}",
-            USER_AGENT_APP_NAME, USER_AGENT_VERSION_KEY, USER_AGENT_VERSION_VALUE
-        );
+/// Why a live tail stopped capturing lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TailStop {
+    Duration,
+    MaxLines,
+    Exited,
+}
 
-        // If the user agent metadata env var already exists, append to it, otherwise set it
-        if let Some(existing_value) = env_vars.get(USER_AGENT_ENV_VAR) {
-            if !existing_value.is_empty() {
-                env_vars.insert(
-                    USER_AGENT_ENV_VAR.to_string(),
-                    format!("{} {}", existing_value, user_agent_metadata_value),
-                );
-            } else {
-                env_vars.insert(USER_AGENT_ENV_VAR.to_string(), user_agent_metadata_value);
-            }
-        } else {
-            env_vars.insert(USER_AGENT_ENV_VAR.to_string(), user_agent_metadata_value);
+impl TailStop {
+    fn description(&self) -> &'static str {
+        match self {
+            TailStop::Duration => "duration elapsed",
+            TailStop::MaxLines => "line limit reached",
+            TailStop::Exited => "tail exited",
         }
+    }
+}
+
+impl UseAws {
+    pub fn requires_acceptance(&self) -> bool {
+        self.operation_class() != OperationClass::Read
+    }
 
-        command.envs(env_vars).arg("--region").arg(&self.region);
-        if let Some(profile_name) = self.profile_name.as_deref() {
-            command.arg("--profile").arg(profile_name);
+    /// Whether the user must approve the call even if use_aws is trusted.
+    pub fn always_requires_acceptance(&self) -> bool {
+        self.policy.always_ask_cost_incurring && self.operation_class() == OperationClass::CostIncurring
+    }
+
+    pub fn operation_class(&self) -> OperationClass {
+        // Tailing logs only reads them.
+        if self.logs_tail.is_some() {
+            return OperationClass::Read;
         }
-        command.arg(&self.service_name).arg(&self.operation_name);
-        if let Some(parameters) = self.cli_parameters() {
-            for (name, val) in parameters {
-                command.arg(name);
-                if !val.is_empty() {
-                    command.arg(val);
-                }
-            }
+        OperationClass::classify(&self.service_name, &self.operation_name)
+    }
+
+    /// Runs the operation, keeping at most a third of `max_bytes` of stdout and of stderr.
+    pub async fn invoke(&self, _ctx: &Context, updates: impl Write, max_bytes: usize) -> Result<InvokeOutput> {
+        if let Some(logs_tail) = &self.logs_tail {
+            return logs_tail.invoke(self.command(), updates).await;
         }
-        let output = command
+        self.run(self.arguments(), max_bytes).await
+    }
+
+    /// Runs the AWS CLI with `arguments`, keeping at most a third of `max_bytes` of stdout and of
+    /// stderr.
+    async fn run(&self, arguments: Vec<String>, max_bytes: usize) -> Result<InvokeOutput> {
+        let output = self
+            .command()
+            .args(arguments)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            // Stops the AWS CLI when the tool times out.
+            .kill_on_drop(true)
             .spawn()
             .wrap_err_with(|| format!("Unable to spawn command '{:?}'", self))?
             .wait_with_output()
@@ -100,40 +176,165 @@ impl UseAws {
         let stdout = output.stdout.to_str_lossy();
         let stderr = output.stderr.to_str_lossy();
 
-        let stdout = format!(
-            "{}{}",
-            &stdout[0..stdout.len().min(MAX_TOOL_RESPONSE_SIZE / 3)],
-            if stdout.len() > MAX_TOOL_RESPONSE_SIZE / 3 {
-                " ... truncated"
-            } else {
-                ""
-            }
-        );
-
-        let stderr = format!(
-            "{}{}",
-            &stderr[0..stderr.len().min(MAX_TOOL_RESPONSE_SIZE / 3)],
-            if stderr.len() > MAX_TOOL_RESPONSE_SIZE / 3 {
-                " ... truncated"
-            } else {
-                ""
-            }
-        );
+        let stdout = truncate_text(&stdout, max_bytes / 3, Keep::HeadAndTail).into_owned();
+        let stderr = truncate_text(&stderr, max_bytes / 3, Keep::HeadAndTail).into_owned();
 
         if status.eq("0") {
+            let mut output = serde_json::json!({
+                "exit_status": status,
+                "stdout": stdout,
+                "stderr": stderr.clone()
+            });
+            if let Some(fixed) = self.fixed_parameters_note() {
+                output["note"] = serde_json::Value::String(fixed);
+            }
             Ok(InvokeOutput {
-                output: OutputKind::Json(serde_json::json!({
-                    "exit_status": status,
-                    "stdout": stdout,
-                    "stderr": stderr.clone()
-                })),
+                output: OutputKind::Json(output),
             })
         } else {
-            Err(eyre::eyre!(stderr))
+            match self.fixed_parameters_note() {
+                Some(fixed) => Err(eyre::eyre!("{stderr}\n{fixed}")),
+                None => Err(eyre::eyre!(stderr)),
+            }
         }
     }
 
+    /// The parameters naming a resource that can be looked up, with their values.
+    pub fn resource_parameters(&self) -> Vec<(String, String)> {
+        let mut parameters = self
+            .parameters
+            .iter()
+            .flatten()
+            .filter_map(|(name, value)| {
+                let cli_name = name.trim_start_matches("--").to_case(Case::Kebab);
+                ResourceLookup::find(&self.service_name, &cli_name)?;
+                Some((name.clone(), value.as_str()?.to_string()))
+            })
+            .collect::<Vec<_>>();
+        parameters.sort();
+        parameters
+    }
+
+    /// The lookup of the resources `parameter` can name.
+    pub fn resource_lookup(&self, parameter: &str) -> Option<&'static ResourceLookup> {
+        ResourceLookup::find(
+            &self.service_name,
+            &parameter.trim_start_matches("--").to_case(Case::Kebab),
+        )
+    }
+
+    /// Replaces the value of `parameter` with one the user picked.
+    pub fn fix_parameter(&mut self, parameter: &str, value: String) {
+        let previous = self
+            .parameters
+            .get_or_insert_default()
+            .insert(parameter.to_string(), serde_json::Value::String(value));
+        if let Some(previous) = previous {
+            if !self.fixed_parameters.iter().any(|(name, _)| name == parameter) {
+                self.fixed_parameters.push((parameter.to_string(), previous));
+            }
+        }
+    }
+
+    /// Tells the model which of its parameters the user replaced, so that it uses the new values.
+    fn fixed_parameters_note(&self) -> Option<String> {
+        if self.fixed_parameters.is_empty() {
+            return None;
+        }
+        let fixed = self
+            .fixed_parameters
+            .iter()
+            .map(|(name, previous)| {
+                let value = self.parameters.as_ref().and_then(|parameters| parameters.get(name));
+                format!(
+                    "{name}: {previous} -> {}",
+                    value.map(|value| value.to_string()).unwrap_or_default()
+                )
+            })
+            .collect::<Vec<_>>();
+        Some(format!(
+            "The user replaced parameters of the call with existing resources: {}",
+            fixed.join(", ")
+        ))
+    }
+
+    /// Runs the operation with `--dry-run` where the service supports it, for `q chat --dry-run`.
+    /// Otherwise only describes the command that would have run.
+    pub async fn dry_run(&self, max_bytes: usize) -> Result<InvokeOutput> {
+        if !DRY_RUN_SERVICES.contains(&self.service_name.as_str()) {
+            return Ok(InvokeOutput {
+                output: OutputKind::Text(format!(
+                    "Dry run: aws {} {} was not run, the service doesn't support --dry-run",
+                    self.service_name, self.operation_name
+                )),
+            });
+        }
+
+        match self.run(self.dry_run_arguments(), max_bytes).await {
+            // The AWS CLI reports a dry run that would have succeeded as an error.
+            Err(err) if err.to_string().contains("DryRunOperation") => Ok(InvokeOutput {
+                output: OutputKind::Text(format!(
+                    "Dry run: aws {} {} was checked with --dry-run and would have succeeded",
+                    self.service_name, self.operation_name
+                )),
+            }),
+            result => result,
+        }
+    }
+
+    fn command(&self) -> Command {
+        let mut command = aws_command(&self.region, self.profile_name.as_deref());
+        if let Some(dir) = &self.working_dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+
+    /// The arguments of the AWS CLI running the operation.
+    fn arguments(&self) -> Vec<String> {
+        let mut arguments = vec![self.service_name.clone(), self.operation_name.clone()];
+        for (name, val) in self.cli_parameters().unwrap_or_default() {
+            arguments.push(name);
+            if !val.is_empty() {
+                arguments.push(val);
+            }
+        }
+        arguments
+    }
+
+    /// The arguments of the AWS CLI checking the operation with `--dry-run`. The parameters of the
+    /// model turning the dry run on or off are dropped, and `--dry-run` is passed last so that
+    /// nothing can override it.
+    fn dry_run_arguments(&self) -> Vec<String> {
+        let mut use_aws = self.clone();
+        if let Some(parameters) = &mut use_aws.parameters {
+            parameters.retain(|name, _| !matches!(cli_parameter_name(name).as_str(), "--dry-run" | "--no-dry-run"));
+        }
+        let mut arguments = use_aws.arguments();
+        arguments.push("--dry-run".to_string());
+        arguments
+    }
+
     pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        let class = self.operation_class();
+        let color = match class {
+            OperationClass::Read => style::Color::Green,
+            OperationClass::Mutating => style::Color::Yellow,
+            OperationClass::CostIncurring => style::Color::Red,
+        };
+        queue!(
+            updates,
+            style::Print("Classification: "),
+            style::SetForegroundColor(color),
+            style::Print(class.description()),
+            style::ResetColor,
+            style::Print("\n"),
+        )?;
+
+        if let Some(logs_tail) = &self.logs_tail {
+            return logs_tail.queue_description(self, updates);
+        }
+
         queue!(
             updates,
             style::Print("Running aws cli command:\n\n"),
@@ -168,8 +369,39 @@ impl UseAws {
         Ok(())
     }
 
-    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
-        Ok(())
+    pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
+        self.policy.check_service(&self.service_name)?;
+        self.working_dir = ctx.env().current_dir().ok();
+        if self.logs_tail.is_none() {
+            let parameters = self
+                .cli_parameters()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>();
+            aws_models::check_call(
+                ctx,
+                self.policy.service_models_path.as_deref(),
+                &self.service_name,
+                &self.operation_name,
+                &parameters,
+            )
+            .await?;
+        }
+        let is_logs_tail = self.service_name == "logs" && self.operation_name == "tail";
+        match &self.logs_tail {
+            Some(logs_tail) if is_logs_tail => logs_tail.validate(),
+            Some(_) => bail!("logs_tail can only be used with the logs service and the tail operation"),
+            // `aws logs tail --follow` never exits on its own.
+            None if is_logs_tail
+                && self
+                    .cli_parameters()
+                    .is_some_and(|params| params.iter().any(|(name, _)| name == "--follow")) =>
+            {
+                bail!("Use logs_tail to follow a log group for a bounded time instead of the follow parameter")
+            },
+            None => Ok(()),
+        }
     }
 
     /// Returns the CLI arguments properly formatted as kebab case if parameters is
@@ -178,7 +410,7 @@ impl UseAws {
         if let Some(parameters) = &self.parameters {
             let mut params = vec![];
             for (param_name, val) in parameters {
-                let param_name = format!("--{}", param_name.trim_start_matches("--").to_case(Case::Kebab));
+                let param_name = cli_parameter_name(param_name);
                 let param_val = val.as_str().map(|s| s.to_string()).unwrap_or(val.to_string());
                 params.push((param_name, param_val));
             }
@@ -189,6 +421,190 @@ impl UseAws {
     }
 }
 
+/// The option of the AWS CLI for a parameter of the model, e.g. `--instance-ids` for `InstanceIds`.
+fn cli_parameter_name(name: &str) -> String {
+    format!("--{}", name.trim_start_matches("--").to_case(Case::Kebab))
+}
+
+/// The `aws` command with the user agent, region and profile set.
+pub fn aws_command(region: &str, profile_name: Option<&str>) -> Command {
+    let mut command = Command::new("aws");
+
+    // Set up environment variables
+    let mut env_vars: std::collections::HashMap<String, String> = std::env::vars().collect();
+
+    // Set up additional metadata for the AWS CLI user agent
+    let user_agent_metadata_value = format!(
+        "{} {}/{}",
+        USER_AGENT_APP_NAME, USER_AGENT_VERSION_KEY, USER_AGENT_VERSION_VALUE
+    );
+
+    // If the user agent metadata env var already exists, append to it, otherwise set it
+    if let Some(existing_value) = env_vars.get(USER_AGENT_ENV_VAR) {
+        if !existing_value.is_empty() {
+            env_vars.insert(
+                USER_AGENT_ENV_VAR.to_string(),
+                format!("{} {}", existing_value, user_agent_metadata_value),
+            );
+        } else {
+            env_vars.insert(USER_AGENT_ENV_VAR.to_string(), user_agent_metadata_value);
+        }
+    } else {
+        env_vars.insert(USER_AGENT_ENV_VAR.to_string(), user_agent_metadata_value);
+    }
+
+    command.envs(env_vars).arg("--region").arg(region);
+    if let Some(profile_name) = profile_name {
+        command.arg("--profile").arg(profile_name);
+    }
+    command
+}
+
+impl LogsTail {
+    async fn invoke(&self, mut command: Command, mut updates: impl Write) -> Result<InvokeOutput> {
+        command
+            .args(["logs", "tail"])
+            .arg(&self.log_group_name)
+            .args(["--follow", "--format", "short"]);
+        if !self.log_stream_names.is_empty() {
+            command.arg("--log-stream-names").args(&self.log_stream_names);
+        }
+        if let Some(filter_pattern) = &self.filter_pattern {
+            command.arg("--filter-pattern").arg(filter_pattern);
+        }
+        if let Some(since) = &self.since {
+            command.arg("--since").arg(since);
+        }
+
+        queue!(
+            updates,
+            style::Print(format!(
+                "Tailing {} for up to {}s or {} lines\n",
+                self.log_group_name, self.duration_seconds, self.max_lines
+            )),
+        )?;
+        updates.flush()?;
+
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .wrap_err_with(|| format!("Unable to spawn command '{:?}'", self))?;
+        let stdout = child.stdout.take().ok_or_else(|| eyre::eyre!("No stdout"))?;
+        let (lines, stop) = capture_lines(stdout, Duration::from_secs(self.duration_seconds), self.max_lines).await?;
+
+        if stop == TailStop::Exited {
+            let status = child.wait().await?;
+            if !status.success() {
+                let mut stderr = String::new();
+                if let Some(mut child_stderr) = child.stderr.take() {
+                    child_stderr.read_to_string(&mut stderr).await?;
+                }
+                bail!(stderr);
+            }
+        } else {
+            let _ = child.kill().await;
+        }
+
+        queue!(
+            updates,
+            style::Print(format!("Captured {} lines ({})\n", lines.len(), stop.description())),
+        )?;
+
+        Ok(InvokeOutput {
+            output: OutputKind::Json(serde_json::json!({
+                "log_group_name": self.log_group_name,
+                "stopped": stop.description(),
+                "line_count": lines.len(),
+                "lines": lines,
+            })),
+        })
+    }
+
+    fn queue_description(&self, use_aws: &UseAws, updates: &mut impl Write) -> Result<()> {
+        queue!(
+            updates,
+            style::Print("Tailing CloudWatch Logs:\n\n"),
+            style::Print(format!("Log group: {}\n", self.log_group_name)),
+        )?;
+        if !self.log_stream_names.is_empty() {
+            queue!(
+                updates,
+                style::Print(format!("Log streams: {}\n", self.log_stream_names.join(", ")))
+            )?;
+        }
+        if let Some(filter_pattern) = &self.filter_pattern {
+            queue!(updates, style::Print(format!("Filter pattern: {}\n", filter_pattern)))?;
+        }
+        if let Some(since) = &self.since {
+            queue!(updates, style::Print(format!("Since: {}\n", since)))?;
+        }
+        queue!(
+            updates,
+            style::Print(format!(
+                "Limits: {}s, {} lines\n",
+                self.duration_seconds, self.max_lines
+            )),
+            style::Print(format!(
+                "Profile name: {}\n",
+                use_aws.profile_name.as_deref().unwrap_or("default")
+            )),
+            style::Print(format!("Region: {}", use_aws.region)),
+        )?;
+        if let Some(ref label) = use_aws.label {
+            queue!(updates, style::Print(format!("\nLabel: {}", label)))?;
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.log_group_name.is_empty() {
+            bail!("log_group_name must not be empty");
+        }
+        if !(1..=MAX_TAIL_DURATION_SECONDS).contains(&self.duration_seconds) {
+            bail!("duration_seconds must be between 1 and {MAX_TAIL_DURATION_SECONDS}");
+        }
+        if !(1..=MAX_TAIL_LINES).contains(&self.max_lines) {
+            bail!("max_lines must be between 1 and {MAX_TAIL_LINES}");
+        }
+        Ok(())
+    }
+}
+
+/// Reads lines from `reader` until `duration` elapses, `max_lines` lines were read, the output
+/// reaches the tool response size limit, or the reader is closed.
+async fn capture_lines(
+    reader: impl AsyncRead + Unpin,
+    duration: Duration,
+    max_lines: usize,
+) -> Result<(Vec<String>, TailStop)> {
+    let mut segments = BufReader::new(reader).split(b'\n');
+    let deadline = tokio::time::sleep(duration);
+    tokio::pin!(deadline);
+
+    let mut lines = Vec::new();
+    let mut size = 0;
+    let stop = loop {
+        tokio::select! {
+            _ = &mut deadline => break TailStop::Duration,
+            segment = segments.next_segment() => {
+                let Some(segment) = segment? else {
+                    break TailStop::Exited;
+                };
+                let line = segment.to_str_lossy().trim_end().to_string();
+                size += line.len();
+                lines.push(line);
+                if lines.len() >= max_lines || size >= MAX_TOOL_RESPONSE_SIZE / 2 {
+                    break TailStop::MaxLines;
+                }
+            },
+        }
+    };
+    Ok((lines, stop))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -225,6 +641,32 @@ mod tests {
             "label": ""
         }};
         assert!(cmd.requires_acceptance());
+        assert!(!cmd.always_requires_acceptance());
+
+        let mut cmd = use_aws! {{
+            "service_name": "ec2",
+            "operation_name": "run-instances",
+            "region": "us-west-2",
+            "label": ""
+        }};
+        assert!(cmd.requires_acceptance());
+        assert!(cmd.always_requires_acceptance());
+        cmd.policy.always_ask_cost_incurring = false;
+        assert!(!cmd.always_requires_acceptance());
+    }
+
+    #[tokio::test]
+    async fn test_denied_service() {
+        let ctx = Context::new();
+        let mut cmd = use_aws! {{
+            "service_name": "s3api",
+            "operation_name": "list-buckets",
+            "region": "us-west-2",
+            "label": ""
+        }};
+        assert!(cmd.validate(&ctx).await.is_ok());
+        cmd.policy.denied_services = vec!["s3".to_string()];
+        assert!(cmd.validate(&ctx).await.is_err());
     }
 
     #[test]
@@ -255,6 +697,114 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_fix_parameter() {
+        let mut cmd = use_aws! {{
+            "service_name": "s3api",
+            "operation_name": "get-bucket-policy",
+            "parameters": {
+                "Bucket": "my-app-assets",
+                "ExpectedBucketOwner": "123456789012"
+            },
+            "region": "us-west-2"
+        }};
+        assert_eq!(cmd.resource_parameters(), vec![(
+            "Bucket".to_string(),
+            "my-app-assets".to_string()
+        )]);
+        assert!(cmd.resource_lookup("Bucket").is_some());
+        assert!(cmd.fixed_parameters_note().is_none());
+
+        cmd.fix_parameter("Bucket", "my-app-assets-prod".to_string());
+        cmd.fix_parameter("Bucket", "my-app-assets-dev".to_string());
+        let params = cmd.cli_parameters().unwrap();
+        assert!(params.contains(&("--bucket".to_string(), "my-app-assets-dev".to_string())));
+        assert_eq!(
+            cmd.fixed_parameters_note().unwrap(),
+            "The user replaced parameters of the call with existing resources: Bucket: \"my-app-assets\" -> \"my-app-assets-dev\""
+        );
+    }
+
+    #[test]
+    fn test_dry_run_arguments() {
+        let cmd = use_aws! {{
+            "service_name": "ec2",
+            "operation_name": "terminate-instances",
+            "parameters": {
+                "InstanceIds": "i-1234567890abcdef0",
+                "NoDryRun": "",
+                "DRY_RUN": "false",
+                "--no-dry-run": ""
+            },
+            "region": "us-west-2"
+        }};
+        let arguments = cmd.dry_run_arguments();
+        assert_eq!(&arguments[..2], ["ec2", "terminate-instances"]);
+        assert_eq!(arguments.last().unwrap(), "--dry-run");
+        assert_eq!(
+            arguments.iter().filter(|arg| arg.contains("dry-run")).count(),
+            1,
+            "{arguments:?}"
+        );
+        assert!(arguments.contains(&"--instance-ids".to_string()));
+        assert!(!arguments.contains(&"false".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_logs_tail_validate() {
+        let ctx = Context::new();
+        let mut cmd = use_aws! {{
+            "service_name": "logs",
+            "operation_name": "tail",
+            "region": "us-west-2",
+            "label": "",
+            "logs_tail": {
+                "log_group_name": "/aws/lambda/my-function",
+                "filter_pattern": "ERROR"
+            }
+        }};
+        let logs_tail = cmd.logs_tail.clone().unwrap();
+        assert_eq!(logs_tail.duration_seconds, 30);
+        assert_eq!(logs_tail.max_lines, 200);
+        assert!(!cmd.requires_acceptance());
+        assert!(cmd.validate(&ctx).await.is_ok());
+
+        cmd.logs_tail.as_mut().unwrap().duration_seconds = MAX_TAIL_DURATION_SECONDS + 1;
+        assert!(cmd.validate(&ctx).await.is_err());
+        cmd.logs_tail.as_mut().unwrap().duration_seconds = 10;
+
+        cmd.operation_name = "filter-log-events".to_string();
+        assert!(cmd.validate(&ctx).await.is_err());
+
+        // Following without logs_tail would never return.
+        let mut cmd = use_aws! {{
+            "service_name": "logs",
+            "operation_name": "tail",
+            "parameters": { "group-name": "/aws/lambda/my-function", "follow": "" },
+            "region": "us-west-2",
+            "label": ""
+        }};
+        assert!(cmd.validate(&ctx).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_capture_lines() {
+        let output = b"first\nsecond\r\nthird\n".as_slice();
+        let (lines, stop) = capture_lines(output, Duration::from_secs(10), 10).await.unwrap();
+        assert_eq!(lines, vec!["first", "second", "third"]);
+        assert_eq!(stop, TailStop::Exited);
+
+        let (lines, stop) = capture_lines(output, Duration::from_secs(10), 2).await.unwrap();
+        assert_eq!(lines, vec!["first", "second"]);
+        assert_eq!(stop, TailStop::MaxLines);
+
+        // A tail that is still following stops after the duration.
+        let (_writer, reader) = tokio::io::duplex(64);
+        let (lines, stop) = capture_lines(reader, Duration::from_millis(10), 10).await.unwrap();
+        assert!(lines.is_empty());
+        assert_eq!(stop, TailStop::Duration);
+    }
+
     #[tokio::test]
     #[ignore = "not in ci"]
     async fn test_aws_read_only() {
@@ -274,7 +824,7 @@ mod tests {
         assert!(
             serde_json::from_value::<UseAws>(v)
                 .unwrap()
-                .invoke(&ctx, &mut std::io::stdout())
+                .invoke(&ctx, &mut std::io::stdout(), MAX_TOOL_RESPONSE_SIZE)
                 .await
                 .is_err()
         );
@@ -295,7 +845,7 @@ mod tests {
         });
         let out = serde_json::from_value::<UseAws>(v)
             .unwrap()
-            .invoke(&ctx, &mut std::io::stdout())
+            .invoke(&ctx, &mut std::io::stdout(), MAX_TOOL_RESPONSE_SIZE)
             .await
             .unwrap();
 
@@ -0,0 +1,236 @@
+//! Runs plugin tools compiled to WASI (`wasm32-wasip1`) inside wasmtime. Needs the
+//! `wasm-plugins` feature.
+//!
+//! A module only sees the directories preopened by its manifest, gets no environment variables,
+//! and has no network access since WASI preview 1 has no sockets. Its memory is capped, and it is
+//! interrupted once its timeout is reached.
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::time::Duration;
+
+use eyre::Result;
+
+/// Largest linear memory of a module.
+#[cfg_attr(not(feature = "wasm-plugins"), allow(dead_code))]
+const MAX_MEMORY_BYTES: usize = 512 * 1024 * 1024;
+
+/// A directory of the host made available to a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WasmPreopen {
+    pub host: PathBuf,
+    /// The absolute path of the directory in the module.
+    pub guest: String,
+    pub writable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WasmOutput {
+    pub exit_code: i32,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Whether this build can run WASI modules.
+pub fn is_available() -> bool {
+    cfg!(feature = "wasm-plugins")
+}
+
+/// Runs the `_start` function of the module with `args`, `stdin` as its standard input, and the
+/// preopened directories, capturing up to `max_output` bytes of its standard output and error.
+#[cfg(feature = "wasm-plugins")]
+pub async fn run(
+    module: &Path,
+    args: Vec<String>,
+    stdin: Vec<u8>,
+    preopens: Vec<WasmPreopen>,
+    timeout: Duration,
+    max_output: usize,
+) -> Result<WasmOutput> {
+    let module = module.to_path_buf();
+    tokio::task::spawn_blocking(move || run_blocking(&module, &args, stdin, &preopens, timeout, max_output)).await?
+}
+
+#[cfg(feature = "wasm-plugins")]
+fn run_blocking(
+    module_path: &Path,
+    args: &[String],
+    stdin: Vec<u8>,
+    preopens: &[WasmPreopen],
+    timeout: Duration,
+    max_output: usize,
+) -> Result<WasmOutput> {
+    use std::sync::mpsc::{
+        RecvTimeoutError,
+        channel,
+    };
+
+    use eyre::{
+        bail,
+        eyre,
+    };
+    use wasmtime::{
+        Config,
+        Engine,
+        Linker,
+        Module,
+        Store,
+        StoreLimits,
+        StoreLimitsBuilder,
+        Trap,
+    };
+    use wasmtime_wasi::pipe::{
+        MemoryInputPipe,
+        MemoryOutputPipe,
+    };
+    use wasmtime_wasi::preview1::{
+        WasiP1Ctx,
+        add_to_linker_sync,
+    };
+    use wasmtime_wasi::{
+        DirPerms,
+        FilePerms,
+        I32Exit,
+        WasiCtxBuilder,
+    };
+
+    struct State {
+        wasi: WasiP1Ctx,
+        limits: StoreLimits,
+    }
+
+    // wasmtime reports errors with anyhow, which doesn't convert to eyre.
+    let report = |err: wasmtime::Error| eyre!("{err:#}");
+
+    let mut config = Config::new();
+    config.epoch_interruption(true);
+    let engine = Engine::new(&config).map_err(report)?;
+    let module = Module::from_file(&engine, module_path)
+        .map_err(|err| eyre!("Unable to load {}: {err:#}", module_path.display()))?;
+    let mut linker = Linker::<State>::new(&engine);
+    add_to_linker_sync(&mut linker, |state| &mut state.wasi).map_err(report)?;
+
+    let stdout = MemoryOutputPipe::new(max_output);
+    let stderr = MemoryOutputPipe::new(max_output);
+    let mut builder = WasiCtxBuilder::new();
+    builder
+        .args(args)
+        .stdin(MemoryInputPipe::new(stdin))
+        .stdout(stdout.clone())
+        .stderr(stderr.clone());
+    for preopen in preopens {
+        let (dir_perms, file_perms) = match preopen.writable {
+            true => (DirPerms::all(), FilePerms::all()),
+            false => (DirPerms::READ, FilePerms::READ),
+        };
+        builder
+            .preopened_dir(&preopen.host, &preopen.guest, dir_perms, file_perms)
+            .map_err(|err| eyre!("Unable to open {}: {err:#}", preopen.host.display()))?;
+    }
+
+    let mut store = Store::new(&engine, State {
+        wasi: builder.build_p1(),
+        limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).build(),
+    });
+    store.limiter(|state| &mut state.limits);
+    store.set_epoch_deadline(1);
+
+    // Interrupts the module once the timeout is reached, unless it finished before.
+    let (finished, timer) = channel::<()>();
+    let timer_engine = engine.clone();
+    std::thread::spawn(move || {
+        if let Err(RecvTimeoutError::Timeout) = timer.recv_timeout(timeout) {
+            timer_engine.increment_epoch();
+        }
+    });
+
+    linker.module(&mut store, "", &module).map_err(report)?;
+    let start = linker
+        .get_default(&mut store, "")
+        .and_then(|start| start.typed::<(), ()>(&store))
+        .map_err(report)?;
+    let result = start.call(&mut store, ());
+    drop(finished);
+
+    let exit_code = match result {
+        Ok(()) => 0,
+        Err(err) => {
+            if let Some(exit) = err.downcast_ref::<I32Exit>() {
+                exit.0
+            } else if err.downcast_ref::<Trap>() == Some(&Trap::Interrupt) {
+                bail!("timed out after {} seconds", timeout.as_secs());
+            } else {
+                return Err(report(err));
+            }
+        },
+    };
+    Ok(WasmOutput {
+        exit_code,
+        stdout: stdout.contents().to_vec(),
+        stderr: stderr.contents().to_vec(),
+    })
+}
+
+#[cfg(not(feature = "wasm-plugins"))]
+pub async fn run(
+    _module: &Path,
+    _args: Vec<String>,
+    _stdin: Vec<u8>,
+    _preopens: Vec<WasmPreopen>,
+    _timeout: Duration,
+    _max_output: usize,
+) -> Result<WasmOutput> {
+    eyre::bail!("This build can't run WASI plugins, it needs the wasm-plugins feature")
+}
+
+#[cfg(all(test, feature = "wasm-plugins"))]
+mod tests {
+    use super::*;
+
+    /// Writes its input back and exits with the code 3.
+    const ECHO_WAT: &str = r#"(module
+        (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+        (memory (export "memory") 1)
+        (func (export "_start")
+            ;; iovec at 0 pointing to a buffer of 1024 bytes at 64
+            (i32.store (i32.const 0) (i32.const 64))
+            (i32.store (i32.const 4) (i32.const 1024))
+            (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+            (i32.store (i32.const 4) (i32.load (i32.const 8)))
+            (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+            (call $proc_exit (i32.const 3))))"#;
+
+    const LOOP_WAT: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "_start") (loop $spin (br $spin))))"#;
+
+    #[tokio::test]
+    async fn test_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let echo = dir.path().join("echo.wat");
+        std::fs::write(&echo, ECHO_WAT).unwrap();
+        let output = run(
+            &echo,
+            vec!["echo".to_string()],
+            b"{\"path\":\"a\"}".to_vec(),
+            Vec::new(),
+            Duration::from_secs(10),
+            1024,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.exit_code, 3);
+        assert_eq!(output.stdout, b"{\"path\":\"a\"}");
+
+        let spin = dir.path().join("loop.wat");
+        std::fs::write(&spin, LOOP_WAT).unwrap();
+        let err = run(&spin, Vec::new(), Vec::new(), Vec::new(), Duration::from_secs(1), 1024)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "timed out after 1 seconds");
+    }
+}
@@ -1,12 +1,52 @@
 use std::io::Write;
 use std::time::Duration;
 
+use encoding_rs::{
+    Encoding,
+    UTF_8,
+};
 use eyre::Result;
-use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
-use serde::{Deserialize, Serialize};
+use futures::StreamExt;
+use reqwest::StatusCode;
+use reqwest::header::{
+    HeaderMap,
+    HeaderValue,
+    USER_AGENT,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
 use url::Url;
 
-use super::{InvokeOutput, OutputKind};
+use super::document::{
+    Document,
+    DocumentKind,
+    MAX_DOCUMENT_SIZE,
+};
+use super::url_policy::UrlPolicy;
+use super::web_cache::{
+    CachedPage,
+    WebCache,
+};
+use super::{
+    InvokeOutput,
+    OutputKind,
+    headless_browser,
+};
+use crate::api_client::model::{
+    ImageBlock,
+    ImageFormat,
+    ImageSource,
+};
+use crate::cli::chat::consts::MAX_IMAGE_SIZE;
+use crate::cli::chat::util::images::ImageMetadata;
+use crate::cli::chat::util::truncate::{
+    Keep,
+    truncate_text,
+};
+use crate::database::Database;
+use crate::database::settings::Setting;
 use crate::platform::Context;
 
 /// Tool for browsing web pages and extracting their content
@@ -23,6 +63,30 @@ pub struct WebBrowse {
     /// Optional: Timeout in seconds (default: 30)
     #[serde(default = "default_timeout")]
     pub timeout: u64,
+    /// Optional: Render the page in a headless browser so that its JavaScript runs (default: false)
+    #[serde(default)]
+    pub render_javascript: bool,
+    /// Optional: Return a screenshot of the rendered page instead of its text (default: false)
+    #[serde(default)]
+    pub screenshot: bool,
+    /// Optional: First page to read from PDF and Word documents (default: 1)
+    #[serde(default)]
+    pub start_page: Option<usize>,
+    /// Optional: Last page to read from PDF and Word documents (default: the last page)
+    #[serde(default)]
+    pub end_page: Option<usize>,
+    /// Optional: Use and update the cache of fetched pages (default: true)
+    #[serde(default = "default_cache")]
+    pub cache: bool,
+    /// Which URLs may be fetched, set from the settings before validation.
+    #[serde(skip)]
+    pub policy: UrlPolicy,
+    /// Whether the headless browser may be used, set from the settings before validation.
+    #[serde(skip)]
+    pub headless_browser: bool,
+    /// The cache of fetched pages, set from the settings before validation.
+    #[serde(skip)]
+    pub web_cache: Option<WebCache>,
 }
 
 fn default_max_length() -> usize {
@@ -33,46 +97,89 @@ fn default_timeout() -> u64 {
     30
 }
 
+fn default_cache() -> bool {
+    true
+}
+
 impl WebBrowse {
-    pub async fn invoke(&self, _ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+    /// Checks if the headless browser is enabled in settings and included in this build
+    pub fn is_headless_browser_enabled(database: &Database) -> bool {
+        headless_browser::is_available() && database.settings.get_bool_or_default(Setting::ChatWebHeadlessBrowser)
+    }
+
+    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
         writeln!(updates, "🌐 Browsing: {}", self.url)?;
-        
-        // Validate URL
-        let url = Url::parse(&self.url)
-            .map_err(|e| eyre::eyre!("Invalid URL '{}': {}", self.url, e))?;
-        
-        // Only allow HTTP and HTTPS schemes for security
-        if !matches!(url.scheme(), "http" | "https") {
-            return Err(eyre::eyre!("Only HTTP and HTTPS URLs are supported"));
+
+        if self.render_javascript || self.screenshot {
+            return self.invoke_headless(updates).await;
         }
 
-        // Create HTTP client with timeout and user agent
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(self.timeout))
-            .build()?;
+        let (content_type, body) = self.fetch(ctx, updates).await?;
+        writeln!(updates, "📄 Content-Type: {}", content_type)?;
+
+        // Documents are binary, so extract their text instead of returning the body. Servers often
+        // send them as a generic binary type, so check their content too.
+        if let Some(kind) = DocumentKind::detect(&body).or(DocumentKind::from_content_type(&content_type)) {
+            return self.document_output(kind, body, updates).await;
+        }
+
+        let body = Self::decode_text(&content_type, &body);
+
+        // Process content based on type and user preferences
+        let processed_content = if self.text_only || content_type.contains("text/html") {
+            Self::extract_text_content(&body)?
+        } else {
+            body
+        };
+
+        self.text_output(processed_content, updates)
+    }
+
+    /// Fetches the content type and body of the page, from the cache if it is fresh or the
+    /// server confirms that it did not change
+    async fn fetch(&self, ctx: &Context, updates: &mut impl Write) -> Result<(String, Vec<u8>)> {
+        // The page may have been cached while the policy allowed it, so check it before serving
+        // the cache
+        self.policy.check_url(&Url::parse(&self.url)?)?;
+
+        let cache = self.web_cache.filter(|_| self.cache);
+        let cached = match cache {
+            Some(cache) => cache.get(ctx, &self.url).await,
+            None => None,
+        };
+        if let (Some(cache), Some(cached)) = (cache, &cached) {
+            if cached.is_fresh(cache.ttl) {
+                writeln!(updates, "📦 Using the cached page")?;
+                return Ok((cached.entry.content_type.clone(), cached.body.clone()));
+            }
+        }
 
         // Set up headers
         let mut headers = HeaderMap::new();
-        headers.insert(
-            USER_AGENT,
-            HeaderValue::from_static("Amazon Q CLI Web Browser/1.0"),
-        );
+        headers.insert(USER_AGENT, HeaderValue::from_static("Amazon Q CLI Web Browser/1.0"));
+        if let Some(cached) = &cached {
+            cached.add_revalidation_headers(&mut headers);
+        }
 
-        // Make the request
+        // Make the request, checking the addresses of the host and of every redirect
         writeln!(updates, "📡 Fetching content...")?;
-        let response = client
-            .get(&self.url)
-            .headers(headers)
-            .send()
-            .await
-            .map_err(|e| eyre::eyre!("Failed to fetch URL: {}", e))?;
+        let response = self
+            .policy
+            .get(&self.url, Duration::from_secs(self.timeout), headers)
+            .await?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let (Some(cache), Some(cached)) = (cache, cached) {
+                writeln!(updates, "📦 The page did not change, using the cached page")?;
+                let cached = cached.revalidated();
+                cache.put(ctx, &cached).await;
+                return Ok((cached.entry.content_type, cached.body));
+            }
+        }
 
         // Check if the request was successful
         if !response.status().is_success() {
-            return Err(eyre::eyre!(
-                "HTTP request failed with status: {}",
-                response.status()
-            ));
+            return Err(eyre::eyre!("HTTP request failed with status: {}", response.status()));
         }
 
         // Get content type
@@ -83,21 +190,124 @@ impl WebBrowse {
             .unwrap_or("unknown")
             .to_string(); // Convert to owned String
 
-        writeln!(updates, "📄 Content-Type: {}", content_type)?;
-
         // Get the response body
-        let body = response
-            .text()
-            .await
-            .map_err(|e| eyre::eyre!("Failed to read response body: {}", e))?;
+        let response_headers = response.headers().clone();
+        let body = Self::read_body(response, MAX_DOCUMENT_SIZE).await?;
 
-        // Process content based on type and user preferences
-        let processed_content = if self.text_only || content_type.contains("text/html") {
-            self.extract_text_content(&body)?
-        } else {
-            body
-        };
+        if let Some(cache) = cache {
+            let page = CachedPage::from_response(&self.url, &content_type, &response_headers, body.clone());
+            if let Some(page) = page {
+                cache.put(ctx, &page).await;
+            }
+        }
+
+        Ok((content_type, body))
+    }
+
+    /// Reads the body of the response, failing once it exceeds `limit` bytes rather than holding
+    /// a body of any size in memory
+    async fn read_body(response: reqwest::Response, limit: u64) -> Result<Vec<u8>> {
+        let too_large = || eyre::eyre!("The page exceeds the size limit of {}MB", limit / (1024 * 1024));
+        if response.content_length().is_some_and(|length| length > limit) {
+            return Err(too_large());
+        }
+
+        let mut body = Vec::new();
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| eyre::eyre!("Failed to read response body: {}", e))?;
+            if (body.len() + chunk.len()) as u64 > limit {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /// Decodes a body with the charset of its content type, UTF-8 by default
+    fn decode_text(content_type: &str, body: &[u8]) -> String {
+        let encoding = content_type
+            .split(';')
+            .find_map(|param| param.trim().strip_prefix("charset="))
+            .and_then(|charset| Encoding::for_label(charset.trim_matches('"').as_bytes()))
+            .unwrap_or(UTF_8);
+        encoding.decode(body).0.into_owned()
+    }
+
+    /// Renders the page in the headless browser, returning its text or a screenshot
+    async fn invoke_headless(&self, updates: &mut impl Write) -> Result<InvokeOutput> {
+        // The browser makes its own requests, each of them is checked against the policy as it
+        // goes out
+        let url = Url::parse(&self.url)?;
+        self.policy.check(&url).await?;
+
+        writeln!(updates, "🖥️  Rendering in a headless browser...")?;
+        let page =
+            headless_browser::render(&url, Duration::from_secs(self.timeout), self.screenshot, &self.policy).await?;
+        if !page.blocked.is_empty() {
+            writeln!(updates, "⛔ Blocked {} requests of the page:", page.blocked.len())?;
+            for request in &page.blocked {
+                writeln!(updates, "  {request}")?;
+            }
+        }
+
+        match page.screenshot {
+            Some(screenshot) => {
+                if screenshot.len() > MAX_IMAGE_SIZE {
+                    return Err(eyre::eyre!(
+                        "The screenshot exceeds the size limit of {}MB",
+                        MAX_IMAGE_SIZE / (1024 * 1024)
+                    ));
+                }
+                writeln!(updates, "✅ Captured a screenshot of {}", page.url)?;
+                let metadata = ImageMetadata {
+                    filepath: page.url,
+                    size: screenshot.len() as u64,
+                    filename: "screenshot.png".to_string(),
+                };
+                let image = ImageBlock {
+                    format: ImageFormat::Png,
+                    source: ImageSource::Bytes(screenshot),
+                };
+                Ok(InvokeOutput {
+                    output: OutputKind::Images(vec![(image, metadata)]),
+                })
+            },
+            None => self.text_output(page.text, updates),
+        }
+    }
+
+    /// Extracts the text of the requested pages of a document, within `max_length`
+    async fn document_output(
+        &self,
+        kind: DocumentKind,
+        bytes: Vec<u8>,
+        updates: &mut impl Write,
+    ) -> Result<InvokeOutput> {
+        if bytes.len() as u64 > MAX_DOCUMENT_SIZE {
+            return Err(eyre::eyre!(
+                "The document exceeds the size limit of {}MB",
+                MAX_DOCUMENT_SIZE / (1024 * 1024)
+            ));
+        }
+
+        writeln!(updates, "📑 Extracting text from the {}...", kind.name())?;
+        let document = tokio::task::spawn_blocking(move || Document::parse(kind, &bytes)).await??;
+        let text = document.text(self.start_page.unwrap_or(1), self.end_page, self.max_length)?;
+
+        writeln!(
+            updates,
+            "✅ Extracted {} characters from {} pages",
+            text.len(),
+            document.pages.len()
+        )?;
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(text),
+        })
+    }
 
+    fn text_output(&self, processed_content: String, updates: &mut impl Write) -> Result<InvokeOutput> {
         // Truncate if necessary
         let final_content = if processed_content.len() > self.max_length {
             writeln!(
@@ -106,11 +316,7 @@ impl WebBrowse {
                 self.max_length,
                 processed_content.len()
             )?;
-            format!(
-                "{}\n\n[... Content truncated. Original length: {} characters ...]",
-                &processed_content[..self.max_length],
-                processed_content.len()
-            )
+            truncate_text(&processed_content, self.max_length, Keep::Head).into_owned()
         } else {
             processed_content
         };
@@ -123,7 +329,11 @@ impl WebBrowse {
     }
 
     pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
-        if self.text_only {
+        if self.screenshot {
+            writeln!(updates, "Take a screenshot of {}", self.url)?;
+        } else if self.render_javascript {
+            writeln!(updates, "Browse {} (rendered in a headless browser)", self.url)?;
+        } else if self.text_only {
             writeln!(updates, "Browse {} (text only)", self.url)?;
         } else {
             writeln!(updates, "Browse {}", self.url)?;
@@ -133,8 +343,10 @@ impl WebBrowse {
 
     pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
         // Validate URL format
-        Url::parse(&self.url)
-            .map_err(|e| eyre::eyre!("Invalid URL format '{}': {}", self.url, e))?;
+        let url = Url::parse(&self.url).map_err(|e| eyre::eyre!("Invalid URL format '{}': {}", self.url, e))?;
+
+        // Check the scheme, the domain lists and literal private addresses
+        self.policy.check_url(&url)?;
 
         // Validate max_length
         if self.max_length == 0 {
@@ -146,55 +358,65 @@ impl WebBrowse {
             return Err(eyre::eyre!("timeout must be greater than 0"));
         }
 
+        // Validate the page range
+        if self.start_page == Some(0) {
+            return Err(eyre::eyre!("start_page must be greater than 0"));
+        }
+
+        // Rendering runs a local browser, which the user must have enabled
+        if (self.render_javascript || self.screenshot) && !self.headless_browser {
+            return Err(eyre::eyre!(
+                "render_javascript and screenshot need the headless browser, which is not enabled"
+            ));
+        }
+
         Ok(())
     }
 
     /// Extract text content from HTML
-    fn extract_text_content(&self, html: &str) -> Result<String> {
+    pub fn extract_text_content(html: &str) -> Result<String> {
         let mut text = String::new();
         let mut in_tag = false;
         let mut in_script_or_style = false;
         let mut current_tag = String::new();
-        
+
         let chars: Vec<char> = html.chars().collect();
         let mut i = 0;
-        
+
         while i < chars.len() {
             let ch = chars[i];
-            
+
             if ch == '<' {
                 in_tag = true;
                 current_tag.clear();
-                
+
                 // Look ahead to determine tag type
                 let mut j = i + 1;
                 let mut is_closing = false;
-                
+
                 // Skip whitespace
                 while j < chars.len() && chars[j].is_whitespace() {
                     j += 1;
                 }
-                
+
                 // Check if it's a closing tag
                 if j < chars.len() && chars[j] == '/' {
                     is_closing = true;
                     j += 1;
                 }
-                
+
                 // Read tag name
                 while j < chars.len() && (chars[j].is_alphabetic() || chars[j].is_numeric()) {
                     current_tag.push(chars[j].to_ascii_lowercase());
                     j += 1;
                 }
-                
+
                 if is_closing {
                     if current_tag == "script" || current_tag == "style" {
                         in_script_or_style = false;
                     }
-                } else {
-                    if current_tag == "script" || current_tag == "style" {
-                        in_script_or_style = true;
-                    }
+                } else if current_tag == "script" || current_tag == "style" {
+                    in_script_or_style = true;
                 }
             } else if ch == '>' {
                 in_tag = false;
@@ -211,17 +433,17 @@ impl WebBrowse {
                     text.push(ch);
                 }
             }
-            
+
             i += 1;
         }
-        
+
         // Clean up extra whitespace
         let lines: Vec<&str> = text
             .lines()
             .map(|line| line.trim())
             .filter(|line| !line.is_empty())
             .collect();
-        
+
         Ok(lines.join("\n"))
     }
 }
@@ -232,13 +454,6 @@ mod tests {
 
     #[test]
     fn test_extract_text_content() {
-        let web_browse = WebBrowse {
-            url: "https://example.com".to_string(),
-            text_only: true,
-            max_length: 1000,
-            timeout: 30,
-        };
-
         let html = r#"
             <html>
                 <head>
@@ -256,8 +471,8 @@ mod tests {
             </html>
         "#;
 
-        let result = web_browse.extract_text_content(html).unwrap();
-        
+        let result = WebBrowse::extract_text_content(html).unwrap();
+
         // Should extract text content and exclude script/style content
         assert!(result.contains("Test Page"));
         assert!(result.contains("Hello World"));
@@ -274,10 +489,17 @@ mod tests {
             text_only: false,
             max_length: 1000,
             timeout: 30,
+            render_javascript: false,
+            screenshot: false,
+            start_page: None,
+            end_page: None,
+            policy: UrlPolicy::default(),
+            headless_browser: false,
+            cache: true,
+            web_cache: None,
         };
 
-        let ctx = Context::builder()
-            .build_fake();
+        let ctx = Context::builder().build_fake();
 
         // Should fail validation for invalid URL
         assert!(web_browse.validate(&ctx).await.is_err());
@@ -285,12 +507,18 @@ mod tests {
         // Should pass validation for valid URL
         web_browse.url = "https://example.com".to_string();
         assert!(web_browse.validate(&ctx).await.is_ok());
+
+        // Should fail validation for cloud metadata endpoints and denied domains
+        web_browse.url = "http://169.254.169.254/latest/meta-data/".to_string();
+        assert!(web_browse.validate(&ctx).await.is_err());
+        web_browse.url = "https://example.com".to_string();
+        web_browse.policy.denied_domains = vec!["example.com".to_string()];
+        assert!(web_browse.validate(&ctx).await.is_err());
     }
 
     #[tokio::test]
     async fn test_parameter_validation() {
-        let ctx = Context::builder()
-            .build_fake();
+        let ctx = Context::builder().build_fake();
 
         // Test max_length validation
         let mut web_browse = WebBrowse {
@@ -298,6 +526,14 @@ mod tests {
             text_only: false,
             max_length: 0,
             timeout: 30,
+            render_javascript: false,
+            screenshot: false,
+            start_page: None,
+            end_page: None,
+            policy: UrlPolicy::default(),
+            headless_browser: false,
+            cache: true,
+            web_cache: None,
         };
         assert!(web_browse.validate(&ctx).await.is_err());
 
@@ -309,5 +545,69 @@ mod tests {
         // Test valid parameters
         web_browse.timeout = 30;
         assert!(web_browse.validate(&ctx).await.is_ok());
+
+        // Rendering needs the headless browser to be enabled
+        web_browse.screenshot = true;
+        assert!(web_browse.validate(&ctx).await.is_err());
+        web_browse.headless_browser = true;
+        assert!(web_browse.validate(&ctx).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_cached_page_is_checked_against_the_policy() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let cache = WebCache {
+            ttl: Duration::from_secs(60),
+        };
+        let url = "https://example.com/docs";
+        let page = CachedPage::from_response(url, "text/html", &HeaderMap::new(), b"<p>docs</p>".to_vec()).unwrap();
+        cache.put(&ctx, &page).await;
+
+        let mut web_browse = WebBrowse {
+            url: url.to_string(),
+            text_only: false,
+            max_length: 1000,
+            timeout: 30,
+            render_javascript: false,
+            screenshot: false,
+            start_page: None,
+            end_page: None,
+            policy: UrlPolicy::default(),
+            headless_browser: false,
+            cache: true,
+            web_cache: Some(cache),
+        };
+        let (_, body) = web_browse.fetch(&ctx, &mut std::io::sink()).await.unwrap();
+        assert_eq!(body, b"<p>docs</p>");
+
+        // The domain was denied after the page was cached
+        web_browse.policy.denied_domains = vec!["example.com".to_string()];
+        assert!(web_browse.fetch(&ctx, &mut std::io::sink()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_body_stops_at_the_limit() {
+        let mut server = mockito::Server::new_async().await;
+        // Chunked, so the server sends no Content-Length
+        server
+            .mock("GET", "/endless")
+            .with_chunked_body(|w| {
+                for _ in 0..64 {
+                    w.write_all(&[b'a'; 1024])?;
+                }
+                Ok(())
+            })
+            .create_async()
+            .await;
+        server.mock("GET", "/small").with_body("small").create_async().await;
+
+        let client = reqwest::Client::new();
+        let response = client.get(format!("{}/endless", server.url())).send().await.unwrap();
+        assert!(response.content_length().is_none());
+        let err = WebBrowse::read_body(response, 16 * 1024).await.unwrap_err();
+        assert!(err.to_string().contains("size limit"), "{err}");
+
+        let response = client.get(format!("{}/small", server.url())).send().await.unwrap();
+        assert_eq!(WebBrowse::read_body(response, 16 * 1024).await.unwrap(), b"small");
     }
 }
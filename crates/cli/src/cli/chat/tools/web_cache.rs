@@ -0,0 +1,215 @@
+//! On-disk cache of the pages fetched by web_browse, so that browsing the same URL again in a
+//! conversation is served locally or revalidated with the server instead of downloaded again.
+
+use std::path::PathBuf;
+use std::time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+use eyre::Result;
+use reqwest::header::{
+    CACHE_CONTROL,
+    ETAG,
+    HeaderMap,
+    HeaderName,
+    HeaderValue,
+    IF_MODIFIED_SINCE,
+    IF_NONE_MATCH,
+    LAST_MODIFIED,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use tracing::warn;
+
+use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::platform::Context;
+use crate::util::directories;
+
+/// Where and for how long web_browse caches pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WebCache {
+    /// How long a cached page is used without revalidating it with the server.
+    pub ttl: Duration,
+}
+
+impl WebCache {
+    /// The cache configured in the settings, or `None` if it is disabled.
+    pub fn from_database(database: &Database) -> Option<Self> {
+        database
+            .settings
+            .get_int_or_default(Setting::ChatWebCacheTtl)
+            .filter(|ttl| *ttl > 0)
+            .map(|ttl| Self {
+                ttl: Duration::from_secs(ttl as u64),
+            })
+    }
+
+    /// The cached page of `url`, fresh or not.
+    pub async fn get(&self, ctx: &Context, url: &str) -> Option<CachedPage> {
+        let (entry_path, body_path) = Self::paths(ctx, url).ok()?;
+        let entry = ctx.fs().read_to_string(&entry_path).await.ok()?;
+        let entry = serde_json::from_str::<CacheEntry>(&entry).ok()?;
+        // Guard against hash collisions and entries of older formats.
+        if entry.url != url {
+            return None;
+        }
+        let body = ctx.fs().read(&body_path).await.ok()?;
+        Some(CachedPage { entry, body })
+    }
+
+    /// Saves `page`, logging rather than failing the tool if the cache can't be written.
+    pub async fn put(&self, ctx: &Context, page: &CachedPage) {
+        let result = async {
+            let (entry_path, body_path) = Self::paths(ctx, &page.entry.url)?;
+            ctx.fs().create_dir_all(directories::chat_web_cache_dir(ctx)?).await?;
+            ctx.fs().write(&body_path, &page.body).await?;
+            ctx.fs().write(&entry_path, serde_json::to_vec(&page.entry)?).await?;
+            Ok::<_, eyre::Report>(())
+        };
+        if let Err(err) = result.await {
+            warn!(?err, url = %page.entry.url, "Failed to cache the page");
+        }
+    }
+
+    fn paths(ctx: &Context, url: &str) -> Result<(PathBuf, PathBuf)> {
+        let key = format!("{:x}", Sha256::digest(url.as_bytes()));
+        let dir = directories::chat_web_cache_dir(ctx)?;
+        Ok((dir.join(format!("{key}.json")), dir.join(format!("{key}.body"))))
+    }
+}
+
+/// What is known about a cached page besides its body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub url: String,
+    pub content_type: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// When the page was last fetched or revalidated, in seconds since the Unix epoch.
+    pub fetched_at: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedPage {
+    pub entry: CacheEntry,
+    pub body: Vec<u8>,
+}
+
+impl CachedPage {
+    /// The page of a successful response, or `None` if the server asked for it not to be stored.
+    pub fn from_response(url: &str, content_type: &str, headers: &HeaderMap, body: Vec<u8>) -> Option<Self> {
+        let header = |name: HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+        if header(CACHE_CONTROL).is_some_and(|value| value.to_ascii_lowercase().contains("no-store")) {
+            return None;
+        }
+        Some(Self {
+            entry: CacheEntry {
+                url: url.to_string(),
+                content_type: content_type.to_string(),
+                etag: header(ETAG).map(String::from),
+                last_modified: header(LAST_MODIFIED).map(String::from),
+                fetched_at: now(),
+            },
+            body,
+        })
+    }
+
+    /// Whether the page can be used without asking the server whether it changed.
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        now().saturating_sub(self.entry.fetched_at) < ttl.as_secs()
+    }
+
+    /// Marks the page as fetched now, after the server confirmed that it did not change.
+    pub fn revalidated(mut self) -> Self {
+        self.entry.fetched_at = now();
+        self
+    }
+
+    /// Adds the headers that let the server answer 304 Not Modified if the page did not change.
+    pub fn add_revalidation_headers(&self, headers: &mut HeaderMap) {
+        if let Some(value) = self
+            .entry
+            .etag
+            .as_deref()
+            .and_then(|etag| HeaderValue::from_str(etag).ok())
+        {
+            headers.insert(IF_NONE_MATCH, value);
+        }
+        if let Some(value) = self
+            .entry
+            .last_modified
+            .as_deref()
+            .and_then(|last_modified| HeaderValue::from_str(last_modified).ok())
+        {
+            headers.insert(IF_MODIFIED_SINCE, value);
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://example.com/docs";
+
+    fn response_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"abc\""));
+        headers.insert(LAST_MODIFIED, HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_put_and_get() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let cache = WebCache {
+            ttl: Duration::from_secs(60),
+        };
+        assert!(cache.get(&ctx, URL).await.is_none());
+
+        let page = CachedPage::from_response(URL, "text/html", &response_headers(), b"<p>docs</p>".to_vec()).unwrap();
+        cache.put(&ctx, &page).await;
+
+        let cached = cache.get(&ctx, URL).await.unwrap();
+        assert_eq!(cached.entry, page.entry);
+        assert_eq!(cached.body, b"<p>docs</p>");
+        assert!(cached.is_fresh(cache.ttl));
+        assert!(cache.get(&ctx, "https://example.com/other").await.is_none());
+    }
+
+    #[test]
+    fn test_freshness_and_revalidation() {
+        let mut page = CachedPage::from_response(URL, "text/html", &response_headers(), Vec::new()).unwrap();
+        page.entry.fetched_at -= 120;
+        assert!(!page.is_fresh(Duration::from_secs(60)));
+        assert!(page.clone().revalidated().is_fresh(Duration::from_secs(60)));
+
+        let mut headers = HeaderMap::new();
+        page.add_revalidation_headers(&mut headers);
+        assert_eq!(headers.get(IF_NONE_MATCH).unwrap(), "\"abc\"");
+        assert_eq!(headers.get(IF_MODIFIED_SINCE).unwrap(), "Wed, 21 Oct 2015 07:28:00 GMT");
+    }
+
+    #[test]
+    fn test_no_store() {
+        let mut headers = response_headers();
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("private, No-Store"));
+        assert!(CachedPage::from_response(URL, "text/html", &headers, Vec::new()).is_none());
+    }
+}
@@ -0,0 +1,509 @@
+//! Crawls a few pages of a site, typically documentation, into a single digest.
+//!
+//! Only pages on the host of the seed URL and under [WebCrawl::path_prefix] are followed, in
+//! breadth first order, and the `Disallow` rules of the site's robots.txt are respected. Lines
+//! already seen on a previous page, such as navigation and footers, are left out of the digest.
+
+use std::collections::{
+    HashSet,
+    VecDeque,
+};
+use std::io::Write;
+use std::time::Duration;
+
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use reqwest::header::{
+    CONTENT_TYPE,
+    HeaderMap,
+    HeaderValue,
+    USER_AGENT,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use url::Url;
+
+use super::url_policy::UrlPolicy;
+use super::web_browse::WebBrowse;
+use super::{
+    InvokeOutput,
+    OutputKind,
+};
+use crate::cli::chat::util::truncate_safe;
+use crate::platform::Context;
+
+/// The name matched against the `User-agent` lines of robots.txt.
+const CRAWLER_NAME: &str = "AmazonQ-CLI-Crawler";
+const MAX_PAGES: usize = 50;
+const MAX_DEPTH: usize = 5;
+/// Links to files that are never worth fetching as a page.
+const SKIPPED_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "svg", "ico", "webp", "css", "js", "json", "xml", "zip", "gz", "tar", "pdf", "mp4",
+    "mp3", "woff", "woff2", "ttf",
+];
+
+/// Fetches a seed page and the same-site pages it links to, returning their text in one digest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebCrawl {
+    /// The URL to start crawling from
+    pub url: String,
+    /// Optional: Maximum number of pages to fetch (default: 10)
+    #[serde(default = "default_max_pages")]
+    pub max_pages: usize,
+    /// Optional: How many links away from the seed page to follow (default: 2)
+    #[serde(default = "default_max_depth")]
+    pub max_depth: usize,
+    /// Optional: Only follow links whose path starts with this prefix (default: the directory of
+    /// the seed URL)
+    #[serde(default)]
+    pub path_prefix: Option<String>,
+    /// Optional: Maximum length of the digest in characters (default: 50000)
+    #[serde(default = "default_max_length")]
+    pub max_length: usize,
+    /// Optional: Timeout of each request in seconds (default: 30)
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// Which URLs may be fetched, set from the settings before validation.
+    #[serde(skip)]
+    pub policy: UrlPolicy,
+}
+
+fn default_max_pages() -> usize {
+    10
+}
+
+fn default_max_depth() -> usize {
+    2
+}
+
+fn default_max_length() -> usize {
+    50000
+}
+
+fn default_timeout() -> u64 {
+    30
+}
+
+impl WebCrawl {
+    pub async fn invoke(&self, _ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+        let seed = Url::parse(&self.url)?;
+        let prefix = self.path_prefix();
+        writeln!(updates, "🕸️  Crawling {seed} (under {prefix})")?;
+
+        let robots = self.fetch_robots(&seed).await?;
+        let mut digest = Digest::new(self.max_length);
+        let mut visited = HashSet::from([normalize(&seed)]);
+        let mut queue = VecDeque::from([(seed, 0)]);
+        let mut fetched = 0;
+        let mut disallowed = 0;
+        let mut failed = 0;
+
+        while let Some((url, depth)) = queue.pop_front() {
+            if fetched == self.max_pages || digest.is_full() {
+                break;
+            }
+            if !robots.is_allowed(url.path()) {
+                disallowed += 1;
+                continue;
+            }
+
+            fetched += 1;
+            writeln!(updates, "📄 {url}")?;
+            let (text, links) = match self.fetch_page(&url).await {
+                Ok(page) => page,
+                Err(err) => {
+                    writeln!(updates, "⚠️  {err}")?;
+                    failed += 1;
+                    continue;
+                },
+            };
+            digest.add_page(&url, &text);
+
+            if depth < self.max_depth {
+                for link in links {
+                    if link.host_str() == url.host_str()
+                        && link.path().starts_with(&prefix)
+                        && !has_skipped_extension(&link)
+                        && visited.insert(normalize(&link))
+                    {
+                        queue.push_back((link, depth + 1));
+                    }
+                }
+            }
+        }
+
+        let mut summary = format!("Crawled {fetched} pages");
+        if digest.duplicates > 0 {
+            summary.push_str(&format!(", {} duplicate pages left out", digest.duplicates));
+        }
+        if disallowed > 0 {
+            summary.push_str(&format!(", {disallowed} pages disallowed by robots.txt"));
+        }
+        if failed > 0 {
+            summary.push_str(&format!(", {failed} pages failed"));
+        }
+        if digest.is_full() || !queue.is_empty() {
+            summary.push_str(", stopped at the page or length limit");
+        }
+        writeln!(updates, "✅ {summary}")?;
+
+        Ok(InvokeOutput {
+            output: OutputKind::Text(format!("{summary}\n\n{}", digest.content)),
+        })
+    }
+
+    /// Fetches the rules of the site's robots.txt, allowing everything if it has none.
+    async fn fetch_robots(&self, seed: &Url) -> Result<Robots> {
+        let url = seed.join("/robots.txt")?;
+        let response = self.policy.get(url.as_str(), self.timeout(), headers()).await?;
+        match response.status() {
+            status if status.is_success() => Ok(Robots::parse(&response.text().await?, CRAWLER_NAME)),
+            status if status.is_client_error() => Ok(Robots::default()),
+            status => bail!("Failed to fetch {url}: {status}"),
+        }
+    }
+
+    /// Fetches a page, returning its text and the links it contains.
+    async fn fetch_page(&self, url: &Url) -> Result<(String, Vec<Url>)> {
+        let response = self.policy.get(url.as_str(), self.timeout(), headers()).await?;
+        if !response.status().is_success() {
+            bail!("{url} returned {}", response.status());
+        }
+        // Redirects may lead elsewhere on the site, links are relative to the final page.
+        let url = response.url().clone();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let body = response.text().await?;
+
+        if content_type.contains("text/html") {
+            Ok((WebBrowse::extract_text_content(&body)?, extract_links(&url, &body)))
+        } else if content_type.starts_with("text/") {
+            Ok((body, Vec::new()))
+        } else {
+            bail!("{url} is not a text page ({content_type})")
+        }
+    }
+
+    fn path_prefix(&self) -> String {
+        match &self.path_prefix {
+            Some(prefix) => prefix.clone(),
+            None => Url::parse(&self.url).map_or_else(
+                |_err| "/".to_string(),
+                |url| {
+                    let path = url.path();
+                    path[..path.rfind('/').map_or(0, |i| i + 1)].to_string()
+                },
+            ),
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        writeln!(
+            updates,
+            "Crawl {} (up to {} pages, {} links deep)",
+            self.url, self.max_pages, self.max_depth
+        )?;
+        Ok(())
+    }
+
+    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
+        let url = Url::parse(&self.url).map_err(|e| eyre!("Invalid URL format '{}': {}", self.url, e))?;
+        self.policy.check_url(&url)?;
+
+        if !(1..=MAX_PAGES).contains(&self.max_pages) {
+            bail!("max_pages must be between 1 and {MAX_PAGES}");
+        }
+        if self.max_depth > MAX_DEPTH {
+            bail!("max_depth must be at most {MAX_DEPTH}");
+        }
+        if self.path_prefix.as_ref().is_some_and(|prefix| !prefix.starts_with('/')) {
+            bail!("path_prefix must start with '/'");
+        }
+        if self.max_length == 0 {
+            bail!("max_length must be greater than 0");
+        }
+        if self.timeout == 0 {
+            bail!("timeout must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+fn headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        USER_AGENT,
+        HeaderValue::from_str(&format!("{CRAWLER_NAME}/1.0")).expect("valid header value"),
+    );
+    headers
+}
+
+/// The URL without its fragment, so that anchors within a page are fetched once.
+fn normalize(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    url.to_string()
+}
+
+fn has_skipped_extension(url: &Url) -> bool {
+    url.path()
+        .rsplit_once('.')
+        .is_some_and(|(_, ext)| SKIPPED_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// The http(s) links in the `href` attributes of a page.
+fn extract_links(base: &Url, html: &str) -> Vec<Url> {
+    // ASCII lowercasing keeps byte offsets the same.
+    let lower = html.to_ascii_lowercase();
+    let mut links = Vec::new();
+    let mut pos = 0;
+    while let Some(i) = lower[pos..].find("href=") {
+        pos += i + "href=".len();
+        let rest = &html[pos..];
+        let value = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => rest[1..].split(quote).next(),
+            _ => rest.split(|c: char| c.is_whitespace() || c == '>').next(),
+        };
+        let Some(value) = value.map(|v| v.trim().replace("&amp;", "&")) else {
+            continue;
+        };
+        if let Ok(mut url) = base.join(&value) {
+            if matches!(url.scheme(), "http" | "https") {
+                url.set_fragment(None);
+                links.push(url);
+            }
+        }
+    }
+    links
+}
+
+/// The rules of a robots.txt that apply to the crawler.
+#[derive(Debug, Default)]
+struct Robots {
+    /// `(allow, pattern)`
+    rules: Vec<(bool, String)>,
+}
+
+impl Robots {
+    /// Parses the rules of the groups naming `agent`, or those of the `*` groups if none does.
+    fn parse(text: &str, agent: &str) -> Self {
+        let mut named = Vec::new();
+        let mut wildcard = Vec::new();
+        let (mut for_agent, mut for_wildcard) = (false, false);
+        let mut in_agent_lines = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    // Consecutive user-agent lines share a group.
+                    if !in_agent_lines {
+                        (for_agent, for_wildcard) = (false, false);
+                    }
+                    in_agent_lines = true;
+                    for_agent |= value.eq_ignore_ascii_case(agent);
+                    for_wildcard |= value == "*";
+                },
+                rule @ ("allow" | "disallow") => {
+                    in_agent_lines = false;
+                    // An empty disallow allows everything.
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let rule = (rule == "allow", value.to_string());
+                    if for_agent {
+                        named.push(rule.clone());
+                    }
+                    if for_wildcard {
+                        wildcard.push(rule);
+                    }
+                },
+                _ => in_agent_lines = false,
+            }
+        }
+
+        Self {
+            rules: if named.is_empty() { wildcard } else { named },
+        }
+    }
+
+    /// The longest matching rule wins, allow winning ties.
+    fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| robots_match(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .is_none_or(|(allow, _)| *allow)
+    }
+}
+
+/// Matches a robots.txt path pattern, supporting `*` wildcards and a `$` end anchor.
+fn robots_match(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let mut rest = rest;
+    let parts = parts.collect::<Vec<_>>();
+    for (i, part) in parts.iter().enumerate() {
+        // The last part of an anchored pattern must end the path.
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(j) => rest = &rest[j + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// The text of the crawled pages, within a length budget.
+#[derive(Debug)]
+struct Digest {
+    content: String,
+    max_length: usize,
+    seen_lines: HashSet<String>,
+    pages: usize,
+    duplicates: usize,
+}
+
+impl Digest {
+    fn new(max_length: usize) -> Self {
+        Self {
+            content: String::new(),
+            max_length,
+            seen_lines: HashSet::new(),
+            pages: 0,
+            duplicates: 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.content.len() >= self.max_length
+    }
+
+    /// Adds the lines of a page not seen on previous pages, returning whether any was.
+    fn add_page(&mut self, url: &Url, text: &str) -> bool {
+        let lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !self.seen_lines.contains(*line))
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            self.duplicates += 1;
+            return false;
+        }
+
+        let page = format!("## {url}\n\n{}\n\n", lines.join("\n"));
+        self.seen_lines.extend(lines.into_iter().map(str::to_string));
+        let remaining = self.max_length.saturating_sub(self.content.len());
+        if page.len() > remaining {
+            self.content.push_str(truncate_safe(&page, remaining));
+            self.content.push_str("\n\n[... Content truncated ...]");
+        } else {
+            self.content.push_str(&page);
+        }
+        self.pages += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robots() {
+        let robots = Robots::parse(
+            "# comment\n\
+             User-agent: *\n\
+             Disallow: /private/\n\
+             Allow: /private/public$\n\
+             Disallow: /*.cgi\n\
+             \n\
+             User-agent: OtherBot\n\
+             Disallow: /\n",
+            CRAWLER_NAME,
+        );
+        assert!(robots.is_allowed("/docs/index.html"));
+        assert!(!robots.is_allowed("/private/secret"));
+        assert!(robots.is_allowed("/private/public"));
+        assert!(!robots.is_allowed("/private/public/more"));
+        assert!(!robots.is_allowed("/bin/run.cgi"));
+
+        // A group naming the crawler replaces the `*` group.
+        let robots = Robots::parse(
+            "User-agent: *\nDisallow: /\n\nUser-agent: amazonq-cli-crawler\nUser-agent: Other\nDisallow: /admin\n",
+            CRAWLER_NAME,
+        );
+        assert!(robots.is_allowed("/docs"));
+        assert!(!robots.is_allowed("/admin/users"));
+
+        assert!(Robots::parse("User-agent: *\nDisallow:\n", CRAWLER_NAME).is_allowed("/"));
+    }
+
+    #[test]
+    fn test_extract_links() {
+        let base = Url::parse("https://docs.example.com/guide/intro.html").unwrap();
+        let html = r#"<a href="setup.html#install">Setup</a> <A HREF='/api/?a=1&amp;b=2'>API</a>
+            <a href=https://other.com/x>Other</a> <a href="mailto:me@example.com">Mail</a>"#;
+        let links = extract_links(&base, html)
+            .into_iter()
+            .map(|url| url.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(links, vec![
+            "https://docs.example.com/guide/setup.html",
+            "https://docs.example.com/api/?a=1&b=2",
+            "https://other.com/x",
+        ]);
+        assert!(has_skipped_extension(&Url::parse("https://a.com/logo.PNG").unwrap()));
+    }
+
+    #[test]
+    fn test_digest_skips_repeated_lines() {
+        let url = Url::parse("https://docs.example.com/").unwrap();
+        let mut digest = Digest::new(200);
+        assert!(digest.add_page(&url, "Home\nDocs\nInstalling the CLI"));
+        assert!(digest.add_page(&url, "Home\nDocs\nConfiguring the CLI"));
+        assert!(!digest.add_page(&url, "Home\nDocs"));
+        assert!(digest.content.contains("Configuring the CLI"));
+        assert_eq!(digest.content.matches("Home").count(), 1);
+        assert_eq!((digest.pages, digest.duplicates), (2, 1));
+
+        digest.add_page(&url, &"A long line ".repeat(50));
+        assert!(digest.is_full());
+        assert!(digest.content.ends_with("[... Content truncated ...]"));
+    }
+
+    #[test]
+    fn test_path_prefix() {
+        let crawl = serde_json::from_value::<WebCrawl>(serde_json::json!({
+            "url": "https://docs.example.com/guide/intro.html"
+        }))
+        .unwrap();
+        assert_eq!(crawl.path_prefix(), "/guide/");
+        assert_eq!(crawl.max_pages, 10);
+    }
+}
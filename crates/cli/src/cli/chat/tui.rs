@@ -0,0 +1,536 @@
+//! `q chat --tui`, a full-screen interface with panes for the transcript, the output of the
+//! running tool, and the context usage. Needs the `tui` feature.
+//!
+//! The chat writes to the TUI as it would to the terminal: its output is sent to the renderer
+//! thread, which drops the escape sequences and draws the panes, and lines typed in the input box
+//! are read by an [InputSource] backed by a channel.
+
+use std::io::{
+    self,
+    Write,
+};
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::sync::mpsc::Sender;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use eyre::Result;
+
+use super::input_source::InputSource;
+use super::util::shared_writer::SharedWriter;
+
+/// Width of the transcript pane, which the markdown of the responses is wrapped to.
+static TRANSCRIPT_WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Replaces the terminal width provider of the chat while the TUI is shown.
+pub fn transcript_width() -> Option<usize> {
+    match TRANSCRIPT_WIDTH.load(Ordering::Relaxed) {
+        0 => None,
+        width => Some(width),
+    }
+}
+
+/// What the chat sends to the TUI.
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+#[derive(Debug)]
+pub enum TuiEvent {
+    /// Output of the chat, as written to the terminal.
+    Transcript(Vec<u8>),
+    /// The chat waits for a line with this prompt.
+    Prompt(String),
+    /// Shown in place of the spinner until the next output.
+    Status(String),
+    ToolStarted(String),
+    ToolOutput(Vec<u8>),
+    ToolFinished {
+        success: bool,
+        duration: Duration,
+    },
+    Usage(TuiUsage),
+    Quit,
+}
+
+/// Shown in the sidebar, updated before every prompt.
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TuiUsage {
+    pub profile: Option<String>,
+    pub context_paths: Vec<String>,
+    pub tokens_used: usize,
+    pub context_window: usize,
+    pub turns: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct TuiHandle {
+    events: Sender<TuiEvent>,
+}
+
+impl TuiHandle {
+    /// Sends the event, ignored once the TUI is closed.
+    pub fn send(&self, event: TuiEvent) {
+        let _ = self.events.send(event);
+    }
+
+    pub fn transcript_writer(&self) -> SharedWriter {
+        SharedWriter::new(EventWriter {
+            events: self.events.clone(),
+            tool: false,
+        })
+    }
+
+    pub fn tool_output_writer(&self) -> SharedWriter {
+        SharedWriter::new(EventWriter {
+            events: self.events.clone(),
+            tool: true,
+        })
+    }
+}
+
+struct EventWriter {
+    events: Sender<TuiEvent>,
+    tool: bool,
+}
+
+impl Write for EventWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let event = match self.tool {
+            true => TuiEvent::ToolOutput(buf.to_vec()),
+            false => TuiEvent::Transcript(buf.to_vec()),
+        };
+        self.events.send(event).map_err(|_err| io::ErrorKind::BrokenPipe)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The TUI, drawn by its own thread until dropped.
+pub struct Tui {
+    handle: TuiHandle,
+    renderer: Option<JoinHandle<()>>,
+}
+
+impl Tui {
+    /// Switches the terminal to the TUI, returning it with the source of the lines typed in it.
+    #[cfg(feature = "tui")]
+    pub fn start() -> Result<(Self, InputSource)> {
+        let (events, events_receiver) = std::sync::mpsc::channel();
+        let (lines, lines_receiver) = std::sync::mpsc::channel();
+        let handle = TuiHandle { events };
+
+        // Ctrl+C is a key press in raw mode, the renderer raises SIGINT to interrupt the response.
+        // Registering a handler keeps SIGINT from killing the process with the terminal still in
+        // raw mode, and the handler stays registered once the stream is dropped.
+        #[cfg(unix)]
+        let _ = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())?;
+
+        let terminal = renderer::enter()?;
+        let renderer = std::thread::spawn(move || renderer::run(terminal, events_receiver, lines));
+        let input_source = InputSource::new_channel(handle.clone(), lines_receiver);
+        Ok((
+            Self {
+                handle,
+                renderer: Some(renderer),
+            },
+            input_source,
+        ))
+    }
+
+    #[cfg(not(feature = "tui"))]
+    pub fn start() -> Result<(Self, InputSource)> {
+        eyre::bail!("This build has no TUI, it needs the tui feature")
+    }
+
+    pub fn handle(&self) -> TuiHandle {
+        self.handle.clone()
+    }
+}
+
+impl Drop for Tui {
+    /// Restores the terminal once the renderer is done.
+    fn drop(&mut self) {
+        self.handle.send(TuiEvent::Quit);
+        if let Some(renderer) = self.renderer.take() {
+            let _ = renderer.join();
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+mod renderer {
+    use std::io::{
+        Stdout,
+        stdout,
+    };
+    use std::sync::atomic::Ordering;
+    use std::sync::mpsc::{
+        Receiver,
+        Sender,
+        TryRecvError,
+    };
+    use std::time::Duration;
+
+    use crossterm::event::{
+        self,
+        DisableBracketedPaste,
+        EnableBracketedPaste,
+        Event,
+        KeyCode,
+        KeyEvent,
+        KeyEventKind,
+        KeyModifiers,
+    };
+    use crossterm::execute;
+    use crossterm::terminal::{
+        EnterAlternateScreen,
+        LeaveAlternateScreen,
+        disable_raw_mode,
+        enable_raw_mode,
+    };
+    use eyre::Result;
+    use ratatui::Frame;
+    use ratatui::backend::CrosstermBackend;
+    use ratatui::layout::{
+        Constraint,
+        Layout,
+        Rect,
+    };
+    use ratatui::style::{
+        Color,
+        Style,
+        Stylize,
+    };
+    use ratatui::text::Line;
+    use ratatui::widgets::{
+        Block,
+        Gauge,
+        Paragraph,
+        Wrap,
+    };
+    use unicode_width::UnicodeWidthStr;
+
+    use super::super::scrollback::TextBuffer;
+    use super::{
+        TRANSCRIPT_WIDTH,
+        TuiEvent,
+        TuiUsage,
+    };
+
+    type Terminal = ratatui::Terminal<CrosstermBackend<Stdout>>;
+
+    /// Restores the terminal when dropped, including when the renderer panics.
+    struct TerminalGuard;
+
+    impl Drop for TerminalGuard {
+        fn drop(&mut self) {
+            let _ = disable_raw_mode();
+            let _ = execute!(
+                stdout(),
+                DisableBracketedPaste,
+                LeaveAlternateScreen,
+                crossterm::cursor::Show
+            );
+        }
+    }
+
+    pub fn enter() -> Result<Terminal> {
+        enable_raw_mode()?;
+        let terminal = (|| -> Result<Terminal> {
+            execute!(stdout(), EnterAlternateScreen, EnableBracketedPaste)?;
+            Ok(Terminal::new(CrosstermBackend::new(stdout()))?)
+        })();
+        if terminal.is_err() {
+            // The renderer restores the terminal once it runs, until then it is restored here.
+            drop(TerminalGuard);
+        }
+        terminal
+    }
+
+    pub fn run(mut terminal: Terminal, events: Receiver<TuiEvent>, lines: Sender<Option<String>>) {
+        let _guard = TerminalGuard;
+        let mut app = App {
+            lines: Some(lines),
+            ..Default::default()
+        };
+        let mut dirty = true;
+        loop {
+            loop {
+                match events.try_recv() {
+                    Ok(TuiEvent::Quit) | Err(TryRecvError::Disconnected) => return,
+                    Ok(event) => app.apply(event),
+                    Err(TryRecvError::Empty) => break,
+                }
+                dirty = true;
+            }
+            if dirty && terminal.draw(|frame| app.draw(frame)).is_err() {
+                return;
+            }
+            dirty = false;
+
+            match event::poll(Duration::from_millis(50)) {
+                Ok(true) => match event::read() {
+                    Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => app.on_key(key),
+                    Ok(Event::Paste(text)) => app.input.push_str(&text),
+                    Ok(_) => {},
+                    Err(_) => return,
+                },
+                Ok(false) => continue,
+                Err(_) => return,
+            }
+            dirty = true;
+        }
+    }
+
+    #[derive(Debug, Default)]
+    enum ToolState {
+        #[default]
+        Idle,
+        Running(String),
+        Finished {
+            name: String,
+            success: bool,
+            duration: Duration,
+        },
+    }
+
+    #[derive(Debug, Default)]
+    struct App {
+        transcript: TextBuffer,
+        /// Rows scrolled up from the bottom of the transcript, 0 follows the output.
+        scroll: usize,
+        transcript_height: usize,
+        tool: ToolState,
+        tool_output: TextBuffer,
+        tools_run: usize,
+        usage: TuiUsage,
+        status: Option<String>,
+        /// The prompt of the line the chat waits for.
+        prompt: Option<String>,
+        input: String,
+        /// Dropped with Ctrl+D, which ends the chat at its next prompt.
+        lines: Option<Sender<Option<String>>>,
+    }
+
+    impl App {
+        fn apply(&mut self, event: TuiEvent) {
+            match event {
+                TuiEvent::Transcript(bytes) => {
+                    self.status = None;
+                    self.transcript.push(&bytes);
+                },
+                TuiEvent::Prompt(prompt) => {
+                    let mut plain = TextBuffer::default();
+                    plain.push(prompt.as_bytes());
+                    self.prompt = Some(plain.lines().last().unwrap_or_default().into_owned());
+                },
+                TuiEvent::Status(status) => self.status = Some(status),
+                TuiEvent::ToolStarted(name) => {
+                    self.tool_output.clear();
+                    self.tool = ToolState::Running(name);
+                },
+                TuiEvent::ToolOutput(bytes) => self.tool_output.push(&bytes),
+                TuiEvent::ToolFinished { success, duration } => {
+                    if let ToolState::Running(name) = std::mem::take(&mut self.tool) {
+                        self.tool = ToolState::Finished {
+                            name,
+                            success,
+                            duration,
+                        };
+                    }
+                    self.tools_run += 1;
+                },
+                TuiEvent::Usage(usage) => self.usage = usage,
+                TuiEvent::Quit => {},
+            }
+        }
+
+        fn on_key(&mut self, key: KeyEvent) {
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                KeyCode::Char('c') if ctrl => {
+                    if !self.input.is_empty() {
+                        self.input.clear();
+                    } else if self.prompt.take().is_some() {
+                        self.send(None);
+                    } else {
+                        interrupt();
+                    }
+                },
+                KeyCode::Char('d') if ctrl => {
+                    self.prompt = None;
+                    self.lines = None;
+                },
+                KeyCode::Char('u') if ctrl => self.input.clear(),
+                KeyCode::Char(c) if !ctrl => self.input.push(c),
+                KeyCode::Backspace => {
+                    self.input.pop();
+                },
+                KeyCode::Enter => {
+                    if let Some(prompt) = self.prompt.take() {
+                        let line = std::mem::take(&mut self.input);
+                        self.transcript.push(format!("{prompt}{line}\n").as_bytes());
+                        self.scroll = 0;
+                        self.send(Some(line));
+                    }
+                },
+                KeyCode::Up => self.scroll += 1,
+                KeyCode::Down => self.scroll = self.scroll.saturating_sub(1),
+                KeyCode::PageUp => self.scroll += (self.transcript_height / 2).max(1),
+                KeyCode::PageDown => self.scroll = self.scroll.saturating_sub((self.transcript_height / 2).max(1)),
+                KeyCode::End => self.scroll = 0,
+                _ => {},
+            }
+        }
+
+        fn send(&mut self, line: Option<String>) {
+            if let Some(lines) = &self.lines {
+                let _ = lines.send(line);
+            }
+        }
+
+        fn draw(&mut self, frame: &mut Frame<'_>) {
+            let [main, input] = Layout::vertical([Constraint::Min(6), Constraint::Length(3)]).areas(frame.area());
+            let [left, sidebar] = Layout::horizontal([Constraint::Min(40), Constraint::Length(34)]).areas(main);
+            let [transcript, tools] =
+                Layout::vertical([Constraint::Percentage(70), Constraint::Percentage(30)]).areas(left);
+
+            self.draw_transcript(frame, transcript);
+            self.draw_tools(frame, tools);
+            self.draw_sidebar(frame, sidebar);
+            self.draw_input(frame, input);
+        }
+
+        fn draw_transcript(&mut self, frame: &mut Frame<'_>, area: Rect) {
+            let width = usize::from(area.width.saturating_sub(2)).max(1);
+            self.transcript_height = usize::from(area.height.saturating_sub(2));
+            TRANSCRIPT_WIDTH.store(width, Ordering::Relaxed);
+
+            let lines = self
+                .transcript
+                .lines()
+                .map(|line| line.into_owned())
+                .collect::<Vec<_>>();
+            let rows = lines
+                .iter()
+                .map(|line| line.width().div_ceil(width).max(1))
+                .sum::<usize>();
+            let max_scroll = rows.saturating_sub(self.transcript_height);
+            self.scroll = self.scroll.min(max_scroll);
+            let offset = u16::try_from(max_scroll - self.scroll).unwrap_or(u16::MAX);
+
+            let title = match self.scroll {
+                0 => " Transcript ".to_string(),
+                scroll => format!(" Transcript (scrolled up {scroll} lines, End to follow) "),
+            };
+            let paragraph = Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<_>>())
+                .block(Block::bordered().title(title))
+                .wrap(Wrap { trim: false })
+                .scroll((offset, 0));
+            frame.render_widget(paragraph, area);
+        }
+
+        fn draw_tools(&self, frame: &mut Frame<'_>, area: Rect) {
+            let header = match &self.tool {
+                ToolState::Idle => Line::from("No tool has run yet").dark_gray(),
+                ToolState::Running(name) => Line::from(format!("▶ {name} running")).yellow(),
+                ToolState::Finished {
+                    name,
+                    success: true,
+                    duration,
+                } => Line::from(format!("✓ {name} completed in {:.1}s", duration.as_secs_f32())).green(),
+                ToolState::Finished { name, duration, .. } => {
+                    Line::from(format!("✗ {name} failed after {:.1}s", duration.as_secs_f32())).red()
+                },
+            };
+            let visible = usize::from(area.height.saturating_sub(3));
+            let output = self.tool_output.lines().collect::<Vec<_>>();
+            let lines = std::iter::once(header)
+                .chain(
+                    output[output.len().saturating_sub(visible)..]
+                        .iter()
+                        .map(|line| Line::from(line.to_string())),
+                )
+                .collect::<Vec<_>>();
+            frame.render_widget(
+                Paragraph::new(lines).block(Block::bordered().title(" Tool activity ")),
+                area,
+            );
+        }
+
+        fn draw_sidebar(&self, frame: &mut Frame<'_>, area: Rect) {
+            let block = Block::bordered().title(" Context ");
+            let inner = block.inner(area);
+            frame.render_widget(block, area);
+            let [gauge, details] = Layout::vertical([Constraint::Length(2), Constraint::Min(0)]).areas(inner);
+
+            let usage = &self.usage;
+            let ratio = match usage.context_window {
+                0 => 0.0,
+                window => (usage.tokens_used as f64 / window as f64).min(1.0),
+            };
+            let color = match ratio {
+                r if r >= 0.9 => Color::Red,
+                r if r >= 0.7 => Color::Yellow,
+                _ => Color::Green,
+            };
+            frame.render_widget(
+                Gauge::default()
+                    .block(Block::new().title(format!(
+                        "{:.1}k of {}k tokens",
+                        usage.tokens_used as f64 / 1000.0,
+                        usage.context_window / 1000
+                    )))
+                    .gauge_style(Style::new().fg(color))
+                    .ratio(ratio),
+                gauge,
+            );
+
+            let mut lines = vec![
+                Line::from(""),
+                Line::from(format!("Profile: {}", usage.profile.as_deref().unwrap_or("default"))),
+                Line::from(format!("Turns: {}", usage.turns)),
+                Line::from(format!("Tools run: {}", self.tools_run)),
+                Line::from(""),
+                Line::from("Context files").bold(),
+            ];
+            match usage.context_paths.is_empty() {
+                true => lines.push(Line::from("none").dark_gray()),
+                false => lines.extend(usage.context_paths.iter().map(|path| Line::from(path.clone()))),
+            }
+            if let Some(status) = &self.status {
+                lines.push(Line::from(""));
+                lines.push(Line::from(status.clone()).magenta());
+            }
+            frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), details);
+        }
+
+        fn draw_input(&self, frame: &mut Frame<'_>, area: Rect) {
+            let (title, style) = match &self.prompt {
+                Some(_) => (" Message ", Style::new()),
+                None => (" Waiting for the model, Ctrl+C to interrupt ", Style::new().dark_gray()),
+            };
+            let block = Block::bordered()
+                .title(title)
+                .title_bottom(" PgUp/PgDn scroll · Ctrl+D quit ")
+                .border_style(style);
+            let prompt = self.prompt.as_deref().unwrap_or_default();
+            frame.render_widget(Paragraph::new(format!("{prompt}{}", self.input)).block(block), area);
+            if self.prompt.is_some() {
+                let x = area.x + 1 + u16::try_from(prompt.width() + self.input.width()).unwrap_or(u16::MAX);
+                frame.set_cursor_position((x.min(area.right().saturating_sub(2)), area.y + 1));
+            }
+        }
+    }
+
+    /// Interrupts the response like Ctrl+C in the line-based chat.
+    fn interrupt() {
+        #[cfg(unix)]
+        let _ = nix::sys::signal::raise(nix::sys::signal::Signal::SIGINT);
+    }
+}
@@ -0,0 +1,104 @@
+//! Limits on how long the model may keep using tools without the user typing a prompt, so that a
+//! model stuck retrying a failing command is stopped.
+
+use std::fmt;
+
+use crate::database::Database;
+use crate::database::settings::Setting;
+
+/// Counts the tool uses since the last prompt of the user, against the limits of
+/// `chat.maxToolCallsPerTurn` and `chat.maxTurnsPerRun`.
+#[derive(Debug, Clone, Default)]
+pub struct TurnBudget {
+    max_tool_calls: Option<usize>,
+    max_turns: Option<usize>,
+    /// Number of tools used since the last prompt.
+    tool_calls: usize,
+    /// Number of responses using tools since the last prompt.
+    turns: usize,
+}
+
+/// Which limit of a [TurnBudget] a response would exceed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetExhausted {
+    ToolCalls(usize),
+    Turns(usize),
+}
+
+impl fmt::Display for BudgetExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetExhausted::ToolCalls(max) => {
+                write!(f, "{max} tool calls since the last prompt (chat.maxToolCallsPerTurn)")
+            },
+            BudgetExhausted::Turns(max) => write!(f, "{max} responses using tools in a row (chat.maxTurnsPerRun)"),
+        }
+    }
+}
+
+impl TurnBudget {
+    pub fn new(max_tool_calls: Option<usize>, max_turns: Option<usize>) -> Self {
+        Self {
+            max_tool_calls,
+            max_turns,
+            ..Default::default()
+        }
+    }
+
+    /// Reads the limits from the settings, where 0 disables a limit.
+    pub fn from_database(database: &Database) -> Self {
+        let limit = |setting| {
+            database
+                .settings
+                .get_int_or_default(setting)
+                .filter(|max| *max > 0)
+                .map(|max| max as usize)
+        };
+        Self::new(
+            limit(Setting::ChatMaxToolCallsPerTurn),
+            limit(Setting::ChatMaxTurnsPerRun),
+        )
+    }
+
+    /// Starts counting again, when the user types a prompt or lets the model go on.
+    pub fn reset(&mut self) {
+        self.tool_calls = 0;
+        self.turns = 0;
+    }
+
+    /// Counts a response using `tool_calls` tools, unless it would exceed a limit.
+    pub fn spend(&mut self, tool_calls: usize) -> Result<(), BudgetExhausted> {
+        if let Some(max) = self.max_tool_calls.filter(|max| self.tool_calls + tool_calls > *max) {
+            return Err(BudgetExhausted::ToolCalls(max));
+        }
+        if let Some(max) = self.max_turns.filter(|max| self.turns + 1 > *max) {
+            return Err(BudgetExhausted::Turns(max));
+        }
+        self.tool_calls += tool_calls;
+        self.turns += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spend() {
+        let mut budget = TurnBudget::new(Some(5), Some(3));
+        assert_eq!(budget.spend(2), Ok(()));
+        assert_eq!(budget.spend(3), Ok(()));
+        assert_eq!(budget.spend(1), Err(BudgetExhausted::ToolCalls(5)));
+        budget.reset();
+        assert_eq!(budget.spend(1), Ok(()));
+        assert_eq!(budget.spend(1), Ok(()));
+        assert_eq!(budget.spend(1), Ok(()));
+        assert_eq!(budget.spend(1), Err(BudgetExhausted::Turns(3)));
+
+        let mut unlimited = TurnBudget::new(None, None);
+        for _ in 0..100 {
+            assert_eq!(unlimited.spend(10), Ok(()));
+        }
+    }
+}
@@ -118,13 +118,7 @@ pub fn handle_images_from_paths(output: &mut impl Write, paths: &[String]) -> Ri
         )
         .ok();
         for (_, metadata) in &images_exceeding_size_limit {
-            let image_size_str = if metadata.size > 1024 * 1024 {
-                format!("{:.2} MB", metadata.size as f64 / (1024.0 * 1024.0))
-            } else if metadata.size > 1024 {
-                format!("{:.2} KB", metadata.size as f64 / 1024.0)
-            } else {
-                format!("{} bytes", metadata.size)
-            };
+            let image_size_str = format_image_size(metadata.size);
             execute!(
                 &mut *output,
                 style::SetForegroundColor(Color::DarkYellow),
@@ -137,6 +131,125 @@ pub fn handle_images_from_paths(output: &mut impl Write, paths: &[String]) -> Ri
     valid_images
 }
 
+/// Formats an image size in bytes for display, e.g. `1.50 MB`.
+pub fn format_image_size(size: u64) -> String {
+    if size > 1024 * 1024 {
+        format!("{:.2} MB", size as f64 / (1024.0 * 1024.0))
+    } else if size > 1024 {
+        format!("{:.2} KB", size as f64 / 1024.0)
+    } else {
+        format!("{} bytes", size)
+    }
+}
+
+/// Loads a single image explicitly attached by the user, returning a message describing why the
+/// image cannot be attached on failure.
+pub fn load_image_attachment(path: &str) -> Result<RichImageBlock, String> {
+    if !is_supported_image_type(path) {
+        return Err(format!(
+            "'{}' is not a supported image type. Supported types are: jpg, jpeg, png, gif, webp",
+            path
+        ));
+    }
+
+    let metadata = fs::metadata(path).map_err(|err| format!("Unable to read '{}': {}", path, err))?;
+    if !metadata.is_file() {
+        return Err(format!("'{}' is not a file", path));
+    }
+    if metadata.len() as usize > MAX_IMAGE_SIZE {
+        return Err(format!(
+            "'{}' is {}, which exceeds the size limit of {}MB",
+            path,
+            format_image_size(metadata.len()),
+            MAX_IMAGE_SIZE / (1024 * 1024)
+        ));
+    }
+
+    let image_block = get_image_block_from_file_path(path).ok_or_else(|| format!("Unable to read '{}'", path))?;
+    let filename = Path::new(path)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+
+    Ok((image_block, ImageMetadata {
+        filepath: path.to_string(),
+        size: metadata.len(),
+        filename,
+    }))
+}
+
+/// Returns the path of an existing image if the user input consists of nothing but that path,
+/// as happens when a file is dragged into the terminal.
+///
+/// Terminals either quote dropped paths or escape their spaces with backslashes, so both forms
+/// are accepted.
+pub fn detect_pasted_image_path(input: &str) -> Option<String> {
+    let input = input.trim();
+    let path = match input
+        .strip_prefix(['\'', '"'])
+        .and_then(|s| s.strip_suffix(['\'', '"']))
+    {
+        Some(unquoted) => unquoted.to_string(),
+        None => input.replace("\\ ", " "),
+    };
+
+    (is_supported_image_type(&path) && Path::new(&path).is_file()).then_some(path)
+}
+
+/// Reads the width and height of an image from its header.
+pub fn image_dimensions(image: &ImageBlock) -> Option<(u32, u32)> {
+    let ImageSource::Bytes(bytes) = &image.source else {
+        return None;
+    };
+    let be_u16 = |i: usize| Some(u16::from_be_bytes(bytes.get(i..i + 2)?.try_into().ok()?) as u32);
+    let le_u16 = |i: usize| Some(u16::from_le_bytes(bytes.get(i..i + 2)?.try_into().ok()?) as u32);
+    let le_u24 = |i: usize| {
+        let b = bytes.get(i..i + 3)?;
+        Some(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16)
+    };
+
+    match image.format {
+        ImageFormat::Png => {
+            if bytes.get(12..16)? != b"IHDR" {
+                return None;
+            }
+            let width = u32::from_be_bytes(bytes.get(16..20)?.try_into().ok()?);
+            let height = u32::from_be_bytes(bytes.get(20..24)?.try_into().ok()?);
+            Some((width, height))
+        },
+        ImageFormat::Gif => Some((le_u16(6)?, le_u16(8)?)),
+        ImageFormat::Jpeg => {
+            // Walk the segments until a start of frame marker, which holds the dimensions.
+            let mut i = 2;
+            loop {
+                if *bytes.get(i)? != 0xff {
+                    return None;
+                }
+                let marker = *bytes.get(i + 1)?;
+                if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+                    return Some((be_u16(i + 7)?, be_u16(i + 5)?));
+                }
+                i += 2 + be_u16(i + 2)? as usize;
+            }
+        },
+        ImageFormat::Webp => {
+            if bytes.get(0..4)? != b"RIFF" || bytes.get(8..12)? != b"WEBP" {
+                return None;
+            }
+            match bytes.get(12..16)? {
+                b"VP8 " => Some((le_u16(26)? & 0x3fff, le_u16(28)? & 0x3fff)),
+                b"VP8L" => {
+                    let bits = u32::from_le_bytes(bytes.get(21..25)?.try_into().ok()?);
+                    Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+                },
+                b"VP8X" => Some((le_u24(24)? + 1, le_u24(27)? + 1)),
+                _ => None,
+            }
+        },
+    }
+}
+
 /// This function checks if the file path has a supported image type
 /// and returns true if it does, otherwise false.
 /// Supported image types are: jpg, jpeg, png, gif, webp
@@ -281,6 +394,70 @@ mod tests {
         assert!(images.is_empty());
     }
 
+    #[test]
+    fn test_image_dimensions() {
+        let image = |format, bytes: Vec<u8>| ImageBlock {
+            format,
+            source: ImageSource::Bytes(bytes),
+        };
+
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend(640u32.to_be_bytes());
+        png.extend(480u32.to_be_bytes());
+        assert_eq!(image_dimensions(&image(ImageFormat::Png, png)), Some((640, 480)));
+
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend(320u16.to_le_bytes());
+        gif.extend(200u16.to_le_bytes());
+        assert_eq!(image_dimensions(&image(ImageFormat::Gif, gif)), Some((320, 200)));
+
+        // SOI, an APP0 segment, then a baseline start of frame.
+        let mut jpeg = vec![
+            0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, 0xff, 0xc0, 0x00, 0x11, 0x08,
+        ];
+        jpeg.extend(100u16.to_be_bytes());
+        jpeg.extend(150u16.to_be_bytes());
+        assert_eq!(image_dimensions(&image(ImageFormat::Jpeg, jpeg)), Some((150, 100)));
+
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X\0\0\0\0\0\0\0\0".to_vec();
+        webp.extend([0xff, 0x03, 0x00, 0xdf, 0x01, 0x00]);
+        assert_eq!(image_dimensions(&image(ImageFormat::Webp, webp)), Some((1024, 480)));
+
+        assert_eq!(
+            image_dimensions(&image(ImageFormat::Png, b"fake_image_data".to_vec())),
+            None
+        );
+    }
+
+    #[test]
+    fn test_detect_pasted_image_path() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image_path = temp_dir.path().join("my image.png");
+        std::fs::write(&image_path, b"fake_image_data").unwrap();
+        let path = image_path.to_string_lossy().to_string();
+
+        assert_eq!(detect_pasted_image_path(&format!("'{}'", path)), Some(path.clone()));
+        assert_eq!(
+            detect_pasted_image_path(&format!("{} ", path.replace(' ', "\\ "))),
+            Some(path.clone())
+        );
+        assert_eq!(detect_pasted_image_path(&format!("describe {}", path)), None);
+        assert_eq!(detect_pasted_image_path("/does/not/exist.png"), None);
+    }
+
+    #[test]
+    fn test_load_image_attachment() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let image_path = temp_dir.path().join("test_image.gif");
+        std::fs::write(&image_path, b"fake_image_data").unwrap();
+
+        let (block, metadata) = load_image_attachment(&image_path.to_string_lossy()).unwrap();
+        assert_eq!(block.format, ImageFormat::Gif);
+        assert_eq!(metadata.filename, "test_image.gif");
+        assert!(load_image_attachment("notes.txt").is_err());
+        assert!(load_image_attachment(&temp_dir.path().join("missing.png").to_string_lossy()).is_err());
+    }
+
     #[test]
     fn test_handle_images_number_exceeded() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -11,6 +11,7 @@ use crate::util::system_info::is_remote;
 
 const TEMPLATE_NAME: &str = "1_bug_report_template.yml";
 
+#[derive(Debug, Clone)]
 pub struct IssueCreator {
     /// Issue title
     pub title: Option<String>,
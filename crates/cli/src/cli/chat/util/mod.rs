@@ -1,8 +1,11 @@
 pub mod images;
 pub mod issue;
+pub mod redact;
 pub mod shared_writer;
+pub mod truncate;
 pub mod ui;
 
+use std::collections::HashSet;
 use std::io::Write;
 use std::time::Duration;
 
@@ -12,8 +15,16 @@ use aws_smithy_types::{
 };
 use eyre::Result;
 
-use super::ChatError;
+use super::pruning::{
+    PruningPolicy,
+    context_file_candidate,
+    select_dropped,
+};
 use super::token_counter::TokenCounter;
+use super::{
+    ChatError,
+    accessibility,
+};
 use crate::util::system_info::in_cloudshell;
 
 const GOV_REGIONS: &[&str] = &["us-gov-east-1", "us-gov-west-1"];
@@ -48,9 +59,15 @@ pub fn truncate_safe(s: &str, max_bytes: usize) -> &str {
     &s[..byte_count]
 }
 
-pub fn animate_output(output: &mut impl Write, bytes: &[u8]) -> Result<(), ChatError> {
+/// Writes `bytes`, revealed a few at a time when `typewriter` is set, see `chat.typewriter`.
+pub fn animate_output(output: &mut impl Write, bytes: &[u8], typewriter: bool) -> Result<(), ChatError> {
+    if !typewriter || accessibility::enabled() {
+        output.write_all(bytes)?;
+        return Ok(output.flush()?);
+    }
     for b in bytes.chunks(12) {
         output.write_all(b)?;
+        output.flush()?;
         std::thread::sleep(Duration::from_millis(16));
     }
     Ok(())
@@ -107,28 +124,32 @@ fn should_play_bell() -> bool {
     false
 }
 
-/// This is a simple greedy algorithm that drops the largest files first
-/// until the total size is below the limit
+/// Drops the context files scored the lowest by `policy` until the total size is below the limit
 ///
 /// # Arguments
-/// * `files` - A mutable reference to a vector of tuples: (filename, content). This file will be
-///   sorted but the content will not be changed.
+/// * `files` - (filename, content) of the context files.
+/// * `pinned` - Files that are never dropped, the ones the user added by name.
+/// * `conversation` - Text of the conversation, files mentioned in it being more likely kept.
 ///
 /// Returns the dropped files
-pub fn drop_matched_context_files(files: &mut [(String, String)], limit: usize) -> Result<Vec<(String, String)>> {
-    files.sort_by(|a, b| TokenCounter::count_tokens(&b.1).cmp(&TokenCounter::count_tokens(&a.1)));
-    let mut total_size = 0;
-    let mut dropped_files = Vec::new();
-
-    for (filename, content) in files.iter() {
-        let size = TokenCounter::count_tokens(content);
-        if total_size + size > limit {
-            dropped_files.push((filename.clone(), content.clone()));
-        } else {
-            total_size += size;
-        }
-    }
-    Ok(dropped_files)
+pub fn drop_matched_context_files(
+    files: &[(String, String)],
+    limit: usize,
+    pinned: &HashSet<String>,
+    conversation: &[&str],
+    policy: &dyn PruningPolicy,
+) -> Result<Vec<(String, String)>> {
+    let candidates = files
+        .iter()
+        .map(|(filename, content)| {
+            let size = TokenCounter::count_tokens(content);
+            context_file_candidate(filename, size, pinned.contains(filename), conversation)
+        })
+        .collect::<Vec<_>>();
+    Ok(select_dropped(&candidates, limit, policy)
+        .into_iter()
+        .map(|i| files[i].clone())
+        .collect())
 }
 
 pub fn serde_value_to_document(value: serde_json::Value) -> Document {
@@ -186,6 +207,7 @@ pub fn document_to_serde_value(value: Document) -> serde_json::Value {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cli::chat::pruning::ImportancePolicy;
 
     #[test]
     fn test_truncate_safe() {
@@ -206,7 +228,7 @@ mod tests {
         ];
         let limit = 10;
 
-        let dropped_files = drop_matched_context_files(&mut files, limit).unwrap();
+        let dropped_files = drop_matched_context_files(&files, limit, &HashSet::new(), &[], &ImportancePolicy).unwrap();
         assert_eq!(dropped_files.len(), 1);
         assert_eq!(dropped_files[0].0, "file3");
         assert_eq!(files.len(), 2);
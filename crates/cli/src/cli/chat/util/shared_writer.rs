@@ -6,6 +6,17 @@ use std::sync::{
     Arc,
     Mutex,
 };
+use std::time::{
+    Duration,
+    Instant,
+};
+
+/// How often [SharedWriter::adaptive] writes out what it buffered, about the refresh interval of
+/// a terminal.
+const FRAME: Duration = Duration::from_millis(16);
+/// Size of the buffer of [AdaptiveWriter] from which it is written out without waiting for the
+/// end of the frame.
+const MAX_BUFFERED: usize = 64 * 1024;
 
 /// A thread-safe wrapper for any Write implementation.
 #[derive(Clone)]
@@ -34,6 +45,29 @@ impl SharedWriter {
     pub fn null() -> Self {
         Self::new(NullWriter {})
     }
+
+    /// A writer rendering to `writer` at most once per frame of the terminal, so that a long
+    /// response takes a few large writes rather than one per token. The clones share the buffer,
+    /// which keeps what tools print in order with the response.
+    pub fn adaptive<W>(writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        let shared = Self::new(AdaptiveWriter::new(writer));
+        // Writes out what is left at the end of each frame, e.g. the last tokens before the model
+        // pauses, until every clone is dropped.
+        let inner = Arc::downgrade(&shared.inner);
+        std::thread::spawn(move || {
+            while let Some(inner) = inner.upgrade() {
+                if let Ok(mut writer) = inner.lock() {
+                    let _ = writer.flush();
+                }
+                drop(inner);
+                std::thread::sleep(FRAME);
+            }
+        });
+        shared
+    }
 }
 
 impl std::fmt::Debug for SharedWriter {
@@ -52,6 +86,54 @@ impl Write for SharedWriter {
     }
 }
 
+/// Buffers what is written until a frame has passed since it last wrote, see
+/// [SharedWriter::adaptive].
+struct AdaptiveWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    last_write: Instant,
+}
+
+impl<W: Write> AdaptiveWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buffer: Vec::new(),
+            last_write: Instant::now(),
+        }
+    }
+
+    fn write_buffer(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.inner.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        self.last_write = Instant::now();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for AdaptiveWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= MAX_BUFFERED || self.last_write.elapsed() >= FRAME {
+            self.write_buffer()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_buffer()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for AdaptiveWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct NullWriter {}
 
@@ -87,3 +169,31 @@ impl Write for TestWriterWithSink {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_writer() {
+        let sink = TestWriterWithSink {
+            sink: Arc::new(Mutex::new(Vec::new())),
+        };
+        let mut writer = AdaptiveWriter::new(sink.clone());
+        // Keeps the end of the frame out of reach, however slow the test runs.
+        writer.last_write = Instant::now() + Duration::from_secs(60);
+        writer.write_all(b"first token").unwrap();
+        assert!(sink.get_content().is_empty());
+        writer.flush().unwrap();
+        assert_eq!(sink.get_content(), b"first token");
+
+        writer.last_write = Instant::now() + Duration::from_secs(60);
+
+        writer.write_all(&vec![b'a'; MAX_BUFFERED]).unwrap();
+        assert_eq!(sink.get_content().len(), b"first token".len() + MAX_BUFFERED);
+
+        writer.write_all(b"last").unwrap();
+        drop(writer);
+        assert!(sink.get_content().ends_with(b"last"));
+    }
+}
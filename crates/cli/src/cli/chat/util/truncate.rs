@@ -0,0 +1,209 @@
+//! Truncation of tool outputs at line boundaries, so that the model isn't sent half a line or a
+//! code block that never ends.
+
+use std::borrow::Cow;
+
+use super::truncate_safe;
+
+/// Which part of a text [truncate_text] keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    /// The first lines, e.g. for a page or a file read from the top.
+    Head,
+    /// The first and last lines, e.g. for the output of a command whose errors and summary come
+    /// last.
+    HeadAndTail,
+}
+
+/// The longest prefix of `s` made of whole lines that fits in `max_bytes`, which is empty when the
+/// first line alone doesn't fit.
+pub fn truncate_lines(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let len = fitting(s.split_inclusive('\n'), max_bytes)
+        .iter()
+        .map(|line| line.len())
+        .sum::<usize>();
+    &s[..len]
+}
+
+/// Truncates `s` to at most `max_bytes` at line boundaries, replacing the lines left out with a
+/// note saying how many there were.
+///
+/// A fenced code block cut by the truncation is closed before the note, and reopened after it when
+/// the tail is kept, so that the rest of the output isn't read as code.
+pub fn truncate_text(s: &str, max_bytes: usize, keep: Keep) -> Cow<'_, str> {
+    if s.len() <= max_bytes {
+        return Cow::Borrowed(s);
+    }
+
+    // The note and the fences come on top of the lines kept, so fewer lines are kept until all of
+    // it fits.
+    let mut budget = max_bytes;
+    loop {
+        let text = truncate_within(s, budget, keep);
+        if text.len() <= max_bytes {
+            return Cow::Owned(text);
+        }
+        if budget == 0 {
+            // Not even the note fits.
+            return Cow::Owned(truncate_safe(&text, max_bytes).to_string());
+        }
+        budget = budget.saturating_sub(text.len() - max_bytes);
+    }
+}
+
+/// [truncate_text] keeping `budget` bytes of lines, before the note and fences are added.
+fn truncate_within(s: &str, budget: usize, keep: Keep) -> String {
+    let lines = s.split_inclusive('\n').collect::<Vec<_>>();
+    let head_budget = match keep {
+        Keep::Head => budget,
+        Keep::HeadAndTail => budget / 2,
+    };
+    let head = fitting(lines.iter().copied(), head_budget);
+    let head_bytes = head.iter().map(|line| line.len()).sum::<usize>();
+    let tail_len = match keep {
+        Keep::Head => 0,
+        Keep::HeadAndTail => fitting(lines[head.len()..].iter().rev().copied(), budget - head_bytes).len(),
+    };
+    let middle = &lines[head.len()..lines.len() - tail_len];
+    let tail = &lines[lines.len() - tail_len..];
+
+    let mut text = head.concat();
+    if head.is_empty() {
+        // The first line alone doesn't fit, it is cut rather than sending nothing.
+        text.push_str(truncate_safe(lines[0], head_budget));
+    }
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+
+    let open_after_head = open_fence(head.iter().copied(), None);
+    let open_before_tail = open_fence(middle.iter().copied(), open_after_head);
+    if let Some(fence) = open_after_head {
+        text.push_str(closing_fence(fence));
+        text.push('\n');
+    }
+    let omitted = middle.len();
+    text.push_str(&format!(
+        "[... {omitted} {} truncated ...]\n",
+        if omitted == 1 { "line" } else { "lines" }
+    ));
+    if !tail.is_empty() {
+        if let Some(fence) = open_before_tail {
+            text.push_str(fence.trim_start());
+            text.push('\n');
+        }
+        text.push_str(&tail.concat());
+    }
+    text
+}
+
+/// The lines, taken in order, that fit in `max_bytes`.
+fn fitting<'a>(lines: impl Iterator<Item = &'a str>, max_bytes: usize) -> Vec<&'a str> {
+    let mut total = 0;
+    lines
+        .take_while(|line| {
+            total += line.len();
+            total <= max_bytes
+        })
+        .collect()
+}
+
+/// The opening line of the fenced code block still open after `lines`, starting in `open`.
+fn open_fence<'a>(lines: impl Iterator<Item = &'a str>, mut open: Option<&'a str>) -> Option<&'a str> {
+    for line in lines {
+        let line = line.trim_end();
+        let trimmed = line.trim_start();
+        if !trimmed.starts_with("```") && !trimmed.starts_with("~~~") {
+            continue;
+        }
+        match open {
+            // Only a fence without an info string, e.g. no language, closes a block.
+            Some(fence) if trimmed.starts_with(closing_fence(fence)) && trimmed.trim_matches(['`', '~']).is_empty() => {
+                open = None;
+            },
+            Some(_) => (),
+            None => open = Some(line),
+        }
+    }
+    open
+}
+
+/// The fence closing the block opened by `fence`, e.g. ```` ``` ```` for ```` ```rust ````.
+fn closing_fence(fence: &str) -> &str {
+    let fence = fence.trim_start();
+    let marker = fence.chars().next().unwrap_or('`');
+    &fence[..fence.len() - fence.trim_start_matches(marker).len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write as _;
+
+    use super::*;
+
+    #[test]
+    fn test_truncate_lines() {
+        assert_eq!(truncate_lines("one\ntwo\nthree\n", 10), "one\ntwo\n");
+        assert_eq!(truncate_lines("one\ntwo\nthree\n", 14), "one\ntwo\nthree\n");
+        assert_eq!(truncate_lines("a long first line\ntwo\n", 5), "");
+    }
+
+    #[test]
+    fn test_truncate_text() {
+        let text = (1..=10).fold(String::new(), |mut text, i| {
+            let _ = writeln!(text, "line {i}");
+            text
+        });
+        assert_eq!(truncate_text(&text, 1000, Keep::Head), text);
+        assert_eq!(
+            truncate_text(&text, 42, Keep::Head),
+            "line 1\nline 2\n[... 8 lines truncated ...]\n"
+        );
+        assert_eq!(
+            truncate_text(&text, 58, Keep::HeadAndTail),
+            "line 1\nline 2\n[... 6 lines truncated ...]\nline 9\nline 10\n"
+        );
+        assert_eq!(
+            truncate_text(&"é".repeat(30), 32, Keep::Head),
+            "éé\n[... 1 line truncated ...]\n"
+        );
+        assert_eq!(truncate_text(&text, 10, Keep::Head), "[... 10 li");
+    }
+
+    #[test]
+    fn test_truncate_text_fits() {
+        let long_line = "x".repeat(500);
+        let texts = [
+            format!("{long_line}\n{long_line}\n"),
+            format!("short\n{long_line}\nshort\n{long_line}"),
+            format!("```rust\n{long_line}\n{long_line}\n```\n{long_line}\n"),
+            "ab\n".repeat(1000),
+        ];
+        for text in &texts {
+            for max_bytes in [0, 10, 40, 100, 499, 501, 800, 1200] {
+                for keep in [Keep::Head, Keep::HeadAndTail] {
+                    let out = truncate_text(text, max_bytes, keep);
+                    assert!(out.len() <= max_bytes, "{} > {max_bytes}: {out:?}", out.len());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_truncate_text_fences() {
+        let text = "Example:\n```rust\nfn a() {}\nfn b() {}\nfn c() {}\nfn d() {}\nfn e() {}\nfn f() {}\n```\nDone\n";
+        assert_eq!(
+            truncate_text(text, 60, Keep::Head),
+            "Example:\n```rust\nfn a() {}\n```\n[... 7 lines truncated ...]\n"
+        );
+        assert_eq!(
+            truncate_text(text, 80, Keep::HeadAndTail),
+            "Example:\n```rust\n```\n[... 5 lines truncated ...]\n```rust\nfn f() {}\n```\nDone\n"
+        );
+        assert_eq!(open_fence(["````md\n", "```\n"].into_iter(), None), Some("````md"));
+        assert_eq!(open_fence(["~~~\n", "~~~\n"].into_iter(), None), None);
+    }
+}
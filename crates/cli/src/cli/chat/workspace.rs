@@ -0,0 +1,157 @@
+//! The roots of the workspace given with `q chat --workspace`, used instead of the directory `q`
+//! was started from, e.g. for a monorepo or when `q` is launched from a script.
+//!
+//! The roots are kept on the [Context] of the session, whose current directory is the first root:
+//! relative paths, context files and workspace settings are resolved against it. Tools using paths
+//! outside every root always ask for confirmation, and paths in the other roots are shown relative
+//! to the root they are in.
+
+use std::path::{
+    Component,
+    Path,
+    PathBuf,
+};
+use std::sync::Arc;
+
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+
+use crate::mcp_client::Root;
+use crate::platform::Context;
+
+/// The context of a session in the workspace of `paths`, `ctx` itself when there are none.
+pub async fn configure(ctx: Arc<Context>, paths: &[String]) -> Result<Arc<Context>> {
+    if paths.is_empty() {
+        return Ok(ctx);
+    }
+    let roots = canonical_roots(&ctx, paths).await?;
+    Ok(ctx.with_workspace(roots))
+}
+
+async fn canonical_roots(ctx: &Context, paths: &[String]) -> Result<Vec<PathBuf>> {
+    let mut roots = Vec::new();
+    for path in paths {
+        let root = ctx
+            .fs()
+            .canonicalize(path)
+            .await
+            .map_err(|err| eyre!("Workspace root {path}: {err}"))?;
+        if !ctx
+            .fs()
+            .symlink_metadata(&root)
+            .await
+            .is_ok_and(|metadata| metadata.is_dir())
+        {
+            bail!("Workspace root {path} is not a directory");
+        }
+        if !roots.contains(&root) {
+            roots.push(root);
+        }
+    }
+    Ok(roots)
+}
+
+/// The roots MCP servers are told to work in, the current directory without `--workspace`.
+pub fn mcp_roots(ctx: &Context) -> Vec<Root> {
+    match ctx.workspace_roots() {
+        [] => ctx
+            .env()
+            .current_dir()
+            .iter()
+            .filter_map(|dir| Root::from_path(dir))
+            .collect(),
+        roots => roots.iter().filter_map(|root| Root::from_path(root)).collect(),
+    }
+}
+
+/// Whether `path` is in a root of the workspace, always true without `--workspace`.
+pub async fn contains(ctx: &Context, path: &Path) -> bool {
+    let roots = ctx.workspace_roots();
+    if roots.is_empty() {
+        return true;
+    }
+    // Links are resolved so that a link in a root can't lead out of it.
+    is_within(roots, &resolve(ctx, path).await)
+}
+
+/// The root of the workspace `path` is in, the innermost one when roots are nested.
+pub fn root_of<'a>(ctx: &'a Context, path: &Path) -> Option<&'a Path> {
+    find_root(ctx.workspace_roots(), path)
+}
+
+fn is_within(roots: &[PathBuf], path: &Path) -> bool {
+    !path.components().any(|component| component == Component::ParentDir)
+        && roots.iter().any(|root| path.starts_with(root))
+}
+
+fn find_root<'a>(roots: &'a [PathBuf], path: &Path) -> Option<&'a Path> {
+    roots
+        .iter()
+        .filter(|root| path.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .map(PathBuf::as_path)
+}
+
+/// `path` with its links resolved, through its longest existing ancestor when it doesn't exist yet,
+/// e.g. a file about to be written.
+async fn resolve(ctx: &Context, path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(canonical) = ctx.fs().canonicalize(existing).await {
+            return rest.iter().rev().fold(canonical, |path, name| path.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name);
+                existing = parent;
+            },
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_workspace_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = std::fs::canonicalize(dir.path()).unwrap();
+        for name in ["api", "web", "web/app", "other"] {
+            std::fs::create_dir(base.join(name)).unwrap();
+        }
+        let process_dir = std::env::current_dir().unwrap();
+        let ctx = configure(Context::new(), &[
+            base.join("api").to_string_lossy().to_string(),
+            base.join("web").to_string_lossy().to_string(),
+            base.join("web/app").to_string_lossy().to_string(),
+        ])
+        .await
+        .unwrap();
+        assert_eq!(ctx.workspace_roots().len(), 3);
+        assert_eq!(ctx.env().current_dir().unwrap(), base.join("api"));
+        assert_eq!(std::env::current_dir().unwrap(), process_dir);
+        assert!(
+            configure(Context::new(), &[base.join("missing").to_string_lossy().to_string()])
+                .await
+                .is_err()
+        );
+
+        assert!(contains(&ctx, &base.join("api/src/main.rs")).await);
+        assert!(contains(&ctx, &base.join("web/new/file.ts")).await);
+        assert!(!contains(&ctx, &base.join("other/notes.md")).await);
+        assert!(!contains(&ctx, &base.join("api/missing/../../other/notes.md")).await);
+        assert!(contains(&Context::new(), &base.join("other/notes.md")).await);
+
+        assert_eq!(
+            root_of(&ctx, &base.join("web/app/index.ts")),
+            Some(base.join("web/app").as_path())
+        );
+        assert_eq!(root_of(&ctx, &base.join("other")), None);
+    }
+}
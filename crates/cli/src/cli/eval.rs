@@ -0,0 +1,770 @@
+//! `q eval`, which runs a suite of prompts against several providers and compares the results.
+//!
+//! Suites are YAML files listing the providers to compare and the cases to run, each a prompt with
+//! optional assertions on the response. The tools a case mocks are offered to the model and their
+//! uses are answered with the mocked results instead of being run. The `mock` provider replays the
+//! responses scripted in each case rather than asking a model, to check a suite offline.
+//!
+//! ```yaml
+//! providers:
+//!   - provider: amazon-q
+//!   - provider: openai
+//!     model: gpt-4o
+//!     api_key_env: OPENAI_API_KEY
+//! cases:
+//!   - name: todos
+//!     prompt: How many TODOs are in src/main.rs? Answer with {"count": <number>}
+//!     tools:
+//!       fs_read: "// TODO: parse the flags\nfn main() {}\n// TODO: exit code"
+//!     expect:
+//!       json: { "count": 2 }
+//!       tools: [fs_read]
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process::ExitCode;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use anstream::eprintln;
+use clap::Args;
+use crossterm::style::Stylize;
+use eyre::{
+    Result,
+    WrapErr,
+    bail,
+    eyre,
+};
+use regex::Regex;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::{
+    Value,
+    json,
+};
+
+use super::OutputFormat;
+use crate::api_client::StreamingClient;
+use crate::api_client::clients::backends::ToolCallPattern;
+use crate::api_client::model::{
+    AssistantResponseMessage,
+    ChatMessage,
+    ChatResponseStream,
+    ConversationState,
+    Tool,
+    ToolInputSchema,
+    ToolResult,
+    ToolResultContentBlock,
+    ToolResultStatus,
+    ToolSpecification,
+    ToolUse,
+    UserInputMessage,
+    UserInputMessageContext,
+};
+use crate::cli::chat::model_capabilities::ModelCapabilityRegistry;
+use crate::cli::chat::openai_config::{
+    ChatProvider,
+    OpenAiConfig,
+};
+use crate::cli::chat::token_counter::TokenCounter;
+use crate::cli::chat::util::serde_value_to_document;
+use crate::database::Database;
+
+/// Requests sent for a case before giving up on the model answering without using tools.
+const MAX_REQUESTS: usize = 10;
+
+#[derive(Debug, Args, PartialEq, Eq)]
+pub struct EvalArgs {
+    /// Path of the YAML suite to run
+    pub suite: PathBuf,
+    /// Run only the providers of the suite with this name, can be repeated
+    #[arg(long = "provider", value_name = "NAME")]
+    pub providers: Vec<String>,
+    /// Output format of the report
+    #[arg(long, short, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+impl EvalArgs {
+    pub async fn execute(self, database: &mut Database) -> Result<ExitCode> {
+        let suite = Suite::load(&self.suite)?;
+        let mut providers = match suite.providers.is_empty() {
+            true => vec![ProviderConfig::configured(database)],
+            false => suite.providers.clone(),
+        };
+        if !self.providers.is_empty() {
+            if let Some(unknown) = self
+                .providers
+                .iter()
+                .find(|name| !providers.iter().any(|p| p.name() == **name))
+            {
+                bail!("The suite has no provider named {unknown}");
+            }
+            providers.retain(|provider| self.providers.contains(&provider.name()));
+        }
+
+        let mut results = Vec::new();
+        for provider in &providers {
+            let client = match provider.is_mock() {
+                true => None,
+                false => Some(provider.client(database).await?),
+            };
+            for case in &suite.cases {
+                eprintln!("{} {} with {}", "Running".dim(), case.name, provider.name());
+                let result = match &client {
+                    Some(client) => run_case(client, case).await,
+                    None => run_case(&mock_client(&case.mock), case).await,
+                };
+                results.push(CaseResult::new(case, provider.name(), result));
+            }
+        }
+
+        let report = Report::new(results, providers.iter().map(ProviderConfig::name).collect());
+        self.format.print(|| report.plain(), || &report);
+        Ok(match report.passed() {
+            true => ExitCode::SUCCESS,
+            false => ExitCode::FAILURE,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Suite {
+    /// The provider configured with `q setup` when empty.
+    #[serde(default)]
+    providers: Vec<ProviderConfig>,
+    cases: Vec<Case>,
+}
+
+impl Suite {
+    fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&content).wrap_err_with(|| format!("Invalid suite {}", path.display()))
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let suite = serde_yaml::from_str::<Self>(content)?;
+        if suite.cases.is_empty() {
+            bail!("The suite has no cases");
+        }
+        for case in &suite.cases {
+            if let Some(regex) = &case.expect.regex {
+                Regex::new(regex).wrap_err_with(|| format!("Invalid regex for the {} case", case.name))?;
+            }
+        }
+        Ok(suite)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProviderConfig {
+    /// Name in the report, `provider/model` by default.
+    name: Option<String>,
+    /// `amazon-q`, `mock`, `openai` or the name of another OpenAI-compatible provider.
+    provider: String,
+    model: Option<String>,
+    /// Defaults to the OpenAI API for `openai`, required for other OpenAI-compatible providers.
+    base_url: Option<String>,
+    /// Environment variable holding the API key. Without it, the key configured with `q setup` is
+    /// used for the same base URL.
+    api_key_env: Option<String>,
+}
+
+impl ProviderConfig {
+    /// The provider configured with `q setup`.
+    fn configured(database: &Database) -> Self {
+        let config = OpenAiConfig::from_database(database);
+        match config.is_openai_compatible() {
+            true => Self {
+                name: None,
+                provider: config.provider.to_string(),
+                model: Some(config.model),
+                base_url: Some(config.base_url),
+                api_key_env: None,
+            },
+            false => Self {
+                name: None,
+                provider: ChatProvider::AmazonQ.to_string(),
+                model: None,
+                base_url: None,
+                api_key_env: None,
+            },
+        }
+    }
+
+    fn name(&self) -> String {
+        match (&self.name, &self.model) {
+            (Some(name), _) => name.clone(),
+            (None, Some(model)) => format!("{}/{model}", self.provider),
+            (None, None) => self.provider.clone(),
+        }
+    }
+
+    fn is_mock(&self) -> bool {
+        self.provider == "mock"
+    }
+
+    async fn client(&self, database: &mut Database) -> Result<StreamingClient> {
+        let provider = ChatProvider::from(self.provider.as_str());
+        if provider == ChatProvider::AmazonQ {
+            return Ok(StreamingClient::new_amazon_q_client(database).await?);
+        }
+
+        let base_url = match (&self.base_url, &provider) {
+            (Some(base_url), _) => base_url.clone(),
+            (None, ChatProvider::OpenAI) => OpenAiConfig::default().base_url,
+            (None, _) => bail!("The {} provider needs a base_url", self.name()),
+        };
+        let model = self
+            .model
+            .clone()
+            .ok_or_else(|| eyre!("The {} provider needs a model", self.name()))?;
+        let api_key = match &self.api_key_env {
+            Some(var) => Some(std::env::var(var).wrap_err_with(|| format!("{var} is not set"))?),
+            None => {
+                let configured = OpenAiConfig::from_database(database);
+                configured.api_key.filter(|_| configured.base_url == base_url)
+            },
+        };
+        let capabilities = ModelCapabilityRegistry::from_database(database).capabilities(&model);
+        let config = OpenAiConfig {
+            provider,
+            base_url,
+            api_key,
+            model,
+        };
+        let patterns = ToolCallPattern::from_database(database);
+        Ok(StreamingClient::new_openai_client(config, capabilities, patterns).await?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Case {
+    name: String,
+    prompt: String,
+    #[serde(default)]
+    expect: Expect,
+    /// Results returned for the uses of these tools, which are offered to the model.
+    #[serde(default)]
+    tools: BTreeMap<String, String>,
+    /// Responses replayed by the `mock` provider, one per request.
+    #[serde(default)]
+    mock: Vec<Vec<MockEvent>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Expect {
+    /// Regular expression the response must match.
+    regex: Option<String>,
+    /// JSON the response must be, where objects may have more fields than expected.
+    json: Option<Value>,
+    /// Tools the model must have used.
+    #[serde(default)]
+    tools: Vec<String>,
+}
+
+impl Expect {
+    /// The assertions the transcript fails.
+    fn failures(&self, transcript: &Transcript) -> Vec<String> {
+        let mut failures = Vec::new();
+        if let Some(pattern) = &self.regex {
+            match Regex::new(pattern) {
+                Ok(regex) if regex.is_match(&transcript.response) => {},
+                Ok(_) => failures.push(format!("the response doesn't match /{pattern}/")),
+                Err(err) => failures.push(err.to_string()),
+            }
+        }
+        if let Some(expected) = &self.json {
+            match json_document(&transcript.response) {
+                Some(actual) if json_contains(&actual, expected) => {},
+                Some(actual) => failures.push(format!("{actual} doesn't match {expected}")),
+                None => failures.push("the response is not a JSON document".to_string()),
+            }
+        }
+        for tool in &self.tools {
+            if !transcript.tools_used.contains(tool) {
+                failures.push(format!("{tool} wasn't used"));
+            }
+        }
+        failures
+    }
+}
+
+/// An event of a scripted response: text, or a tool use as in `Q_MOCK_CHAT_RESPONSE`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum MockEvent {
+    Text(String),
+    ToolUse {
+        tool_use_id: String,
+        name: String,
+        args: Value,
+    },
+}
+
+fn mock_client(responses: &[Vec<MockEvent>]) -> StreamingClient {
+    StreamingClient::mock(
+        responses
+            .iter()
+            .map(|events| {
+                events
+                    .iter()
+                    .flat_map(|event| match event {
+                        MockEvent::Text(content) => vec![ChatResponseStream::AssistantResponseEvent {
+                            content: content.clone(),
+                        }],
+                        MockEvent::ToolUse {
+                            tool_use_id,
+                            name,
+                            args,
+                        } => [None, Some(args.to_string())]
+                            .into_iter()
+                            .map(|input| ChatResponseStream::ToolUseEvent {
+                                tool_use_id: tool_use_id.clone(),
+                                name: name.clone(),
+                                input,
+                                stop: None,
+                            })
+                            .chain([ChatResponseStream::ToolUseEvent {
+                                tool_use_id: tool_use_id.clone(),
+                                name: name.clone(),
+                                input: None,
+                                stop: Some(true),
+                            }])
+                            .collect(),
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// How a provider answered a case.
+#[derive(Debug, Default)]
+struct Transcript {
+    response: String,
+    requests: usize,
+    tools_used: Vec<String>,
+    latency: Duration,
+    first_token: Option<Duration>,
+    /// Summed over the requests, for the providers reporting it.
+    input_tokens: Option<u64>,
+}
+
+/// Sends the prompt of the case, answering the tool uses with the mocked results until the model
+/// responds without using tools.
+async fn run_case(client: &StreamingClient, case: &Case) -> Result<Transcript> {
+    let tools = tool_specs(case.tools.keys());
+    let start = Instant::now();
+    let mut transcript = Transcript::default();
+    let mut history = Vec::new();
+    let mut message = user_message(case.prompt.clone(), None, &tools);
+
+    loop {
+        if transcript.requests == MAX_REQUESTS {
+            bail!("The model was still using tools after {MAX_REQUESTS} requests");
+        }
+        transcript.requests += 1;
+
+        let mut response = client
+            .send_message(ConversationState {
+                conversation_id: None,
+                user_input_message: message.clone(),
+                history: (!history.is_empty()).then(|| history.clone()),
+                response_schema: None,
+            })
+            .await?;
+        let mut content = String::new();
+        let mut tool_uses: Vec<(String, String, String)> = Vec::new();
+        while let Some(event) = response.recv().await? {
+            match event {
+                ChatResponseStream::AssistantResponseEvent { content: text }
+                | ChatResponseStream::CodeEvent { content: text } => {
+                    transcript.first_token.get_or_insert_with(|| start.elapsed());
+                    content.push_str(&text);
+                },
+                ChatResponseStream::ToolUseEvent {
+                    tool_use_id,
+                    name,
+                    input,
+                    ..
+                } => {
+                    transcript.first_token.get_or_insert_with(|| start.elapsed());
+                    let input = input.unwrap_or_default();
+                    match tool_uses.last_mut().filter(|(id, ..)| *id == tool_use_id) {
+                        Some((.., args)) => args.push_str(&input),
+                        None => tool_uses.push((tool_use_id, name, input)),
+                    }
+                },
+                ChatResponseStream::UsageEvent(usage) => {
+                    *transcript.input_tokens.get_or_insert(0) += usage.input_tokens;
+                },
+                ChatResponseStream::InvalidStateEvent { reason, message } => {
+                    bail!("Invalid state: {reason} - {message}");
+                },
+                _ => {},
+            }
+        }
+
+        if tool_uses.is_empty() {
+            transcript.response = content;
+            transcript.latency = start.elapsed();
+            return Ok(transcript);
+        }
+
+        let tool_uses = tool_uses
+            .into_iter()
+            .map(|(tool_use_id, name, args)| ToolUse {
+                tool_use_id,
+                name,
+                input: serde_value_to_document(serde_json::from_str(&args).unwrap_or_else(|_| json!({}))).into(),
+            })
+            .collect::<Vec<_>>();
+        let tool_results = tool_uses
+            .iter()
+            .map(|tool_use| {
+                let (result, status) = match case.tools.get(&tool_use.name) {
+                    Some(result) => (result.clone(), ToolResultStatus::Success),
+                    None => (
+                        format!("The {} tool is not available", tool_use.name),
+                        ToolResultStatus::Error,
+                    ),
+                };
+                ToolResult {
+                    tool_use_id: tool_use.tool_use_id.clone(),
+                    content: vec![ToolResultContentBlock::Text(result)],
+                    status,
+                }
+            })
+            .collect();
+        transcript
+            .tools_used
+            .extend(tool_uses.iter().map(|tool_use| tool_use.name.clone()));
+
+        history.push(ChatMessage::UserInputMessage(message));
+        history.push(ChatMessage::AssistantResponseMessage(AssistantResponseMessage {
+            message_id: None,
+            content,
+            tool_uses: Some(tool_uses),
+        }));
+        message = user_message(String::new(), Some(tool_results), &tools);
+    }
+}
+
+/// The specifications of the mocked tools: the ones of the built-in tools of the same name, or
+/// taking any arguments.
+fn tool_specs<'a>(names: impl Iterator<Item = &'a String>) -> Vec<Tool> {
+    let mut builtin = serde_json::from_str::<Value>(include_str!("chat/tools/tool_index.json")).unwrap_or_default();
+    names
+        .map(|name| {
+            let spec = builtin.get_mut(name).map(Value::take).unwrap_or_default();
+            Tool::ToolSpecification(ToolSpecification {
+                name: name.clone(),
+                description: spec["description"]
+                    .as_str()
+                    .map_or_else(|| format!("The {name} tool"), str::to_string),
+                input_schema: ToolInputSchema {
+                    json: Some(
+                        serde_value_to_document(match spec.get("input_schema") {
+                            Some(schema) => schema.clone(),
+                            None => json!({ "type": "object" }),
+                        })
+                        .into(),
+                    ),
+                },
+            })
+        })
+        .collect()
+}
+
+fn user_message(content: String, tool_results: Option<Vec<ToolResult>>, tools: &[Tool]) -> UserInputMessage {
+    UserInputMessage {
+        images: None,
+        content,
+        user_input_message_context: (tool_results.is_some() || !tools.is_empty()).then(|| UserInputMessageContext {
+            env_state: None,
+            git_state: None,
+            tool_results,
+            tools: (!tools.is_empty()).then(|| tools.to_vec()),
+        }),
+        user_intent: None,
+    }
+}
+
+/// The JSON document in the response, which may be wrapped in a markdown code block.
+fn json_document(response: &str) -> Option<Value> {
+    let response = response.trim();
+    serde_json::from_str(response).ok().or_else(|| {
+        let (_, fenced) = response.split_once("```")?;
+        let (_, fenced) = fenced.split_once('\n')?;
+        serde_json::from_str(fenced.split("```").next()?).ok()
+    })
+}
+
+/// Whether `actual` matches `expected`, objects being allowed to have more fields.
+fn json_contains(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Object(actual), Value::Object(expected)) => expected
+            .iter()
+            .all(|(key, expected)| actual.get(key).is_some_and(|actual| json_contains(actual, expected))),
+        (Value::Array(actual), Value::Array(expected)) => {
+            actual.len() == expected.len() && actual.iter().zip(expected).all(|(a, e)| json_contains(a, e))
+        },
+        (actual, expected) => actual == expected,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct CaseResult {
+    case: String,
+    provider: String,
+    passed: bool,
+    /// The assertions that failed, or the error of the requests.
+    failures: Vec<String>,
+    response: String,
+    requests: usize,
+    tools_used: Vec<String>,
+    latency_ms: u64,
+    first_token_ms: Option<u64>,
+    /// As reported by the provider.
+    input_tokens: Option<u64>,
+    /// Estimated from the response.
+    output_tokens: usize,
+}
+
+impl CaseResult {
+    fn new(case: &Case, provider: String, result: Result<Transcript>) -> Self {
+        let (transcript, failures) = match result {
+            Ok(transcript) => {
+                let failures = case.expect.failures(&transcript);
+                (transcript, failures)
+            },
+            Err(err) => (Transcript::default(), vec![format!("{err:#}")]),
+        };
+        Self {
+            case: case.name.clone(),
+            provider,
+            passed: failures.is_empty(),
+            failures,
+            output_tokens: TokenCounter::count_tokens(&transcript.response),
+            response: transcript.response,
+            requests: transcript.requests,
+            tools_used: transcript.tools_used,
+            latency_ms: transcript.latency.as_millis() as u64,
+            first_token_ms: transcript.first_token.map(|d| d.as_millis() as u64),
+            input_tokens: transcript.input_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct ProviderSummary {
+    provider: String,
+    passed: usize,
+    cases: usize,
+    median_latency_ms: u64,
+    input_tokens: Option<u64>,
+    output_tokens: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    results: Vec<CaseResult>,
+    providers: Vec<ProviderSummary>,
+}
+
+impl Report {
+    fn new(results: Vec<CaseResult>, providers: Vec<String>) -> Self {
+        let providers = providers
+            .into_iter()
+            .map(|provider| {
+                let results = results.iter().filter(|r| r.provider == provider).collect::<Vec<_>>();
+                let mut latencies = results.iter().map(|r| r.latency_ms).collect::<Vec<_>>();
+                latencies.sort_unstable();
+                ProviderSummary {
+                    passed: results.iter().filter(|r| r.passed).count(),
+                    cases: results.len(),
+                    median_latency_ms: latencies
+                        .get(latencies.len().saturating_sub(1) / 2)
+                        .copied()
+                        .unwrap_or_default(),
+                    input_tokens: results.iter().filter_map(|r| r.input_tokens).reduce(|a, b| a + b),
+                    output_tokens: results.iter().map(|r| r.output_tokens).sum(),
+                    provider,
+                }
+            })
+            .collect();
+        Self { results, providers }
+    }
+
+    fn passed(&self) -> bool {
+        self.results.iter().all(|r| r.passed)
+    }
+
+    /// A table of the cases and providers, the summary of each provider and the failures.
+    fn plain(&self) -> String {
+        let mut cases = Vec::<&str>::new();
+        for result in &self.results {
+            if !cases.contains(&result.case.as_str()) {
+                cases.push(&result.case);
+            }
+        }
+        let case_width = cases.iter().map(|case| case.len()).max().unwrap_or_default().max(4) + 2;
+        let widths = self
+            .providers
+            .iter()
+            .map(|p| p.provider.len().max(8) + 2)
+            .collect::<Vec<_>>();
+
+        let mut text = format!("{:case_width$}", "case");
+        for (summary, width) in self.providers.iter().zip(&widths) {
+            text.push_str(&format!("{:width$}", summary.provider));
+        }
+        text = format!("{}\n", text.trim_end().bold());
+        for case in &cases {
+            text.push_str(&format!("{case:case_width$}"));
+            for (summary, width) in self.providers.iter().zip(&widths) {
+                let cell = match self
+                    .results
+                    .iter()
+                    .find(|r| r.case == *case && r.provider == summary.provider)
+                {
+                    Some(r) if r.passed => format!("{:width$}", format!("✓ {}", seconds(r.latency_ms))).green(),
+                    Some(r) => format!("{:width$}", format!("✗ {}", seconds(r.latency_ms))).red(),
+                    None => format!("{:width$}", "-").dim(),
+                };
+                text.push_str(&cell.to_string());
+            }
+            text = format!("{}\n", text.trim_end());
+        }
+
+        text.push('\n');
+        for summary in &self.providers {
+            text.push_str(&format!(
+                "{}: {}/{} passed, median {}",
+                summary.provider.as_str().bold(),
+                summary.passed,
+                summary.cases,
+                seconds(summary.median_latency_ms)
+            ));
+            if let Some(input_tokens) = summary.input_tokens {
+                text.push_str(&format!(", {input_tokens} input tokens"));
+            }
+            text.push_str(&format!(", ~{} output tokens\n", summary.output_tokens));
+        }
+
+        let failed = self.results.iter().filter(|r| !r.passed).collect::<Vec<_>>();
+        if !failed.is_empty() {
+            text.push_str(&format!("\n{}\n", "Failures".bold()));
+            for r in failed {
+                text.push_str(&format!(
+                    "  {} with {}: {}\n",
+                    r.case,
+                    r.provider,
+                    r.failures.join("; ")
+                ));
+            }
+        }
+        text.trim_end().to_string()
+    }
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{:.1}s", ms as f64 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUITE: &str = r#"
+providers:
+  - provider: mock
+  - name: gpt
+    provider: openai
+    model: gpt-4o
+cases:
+  - name: todos
+    prompt: How many TODOs are in src/main.rs?
+    tools:
+      fs_read: "// TODO: parse the flags\nfn main() {}\n// TODO: exit code"
+    mock:
+      - - Let me read it.
+        - tool_use_id: "1"
+          name: fs_read
+          args: { operations: [{ mode: Line, path: src/main.rs }] }
+      - - '```json'
+        - "\n{\"count\": 2, \"lines\": [1, 3]}\n"
+        - '```'
+    expect:
+      json: { count: 2 }
+      tools: [fs_read]
+  - name: capital
+    prompt: What is the capital of France?
+    mock: [[Lyon]]
+    expect:
+      regex: (?i)paris
+"#;
+
+    #[test]
+    fn test_parse_suite() {
+        let suite = Suite::parse(SUITE).unwrap();
+        assert_eq!(suite.providers.len(), 2);
+        assert_eq!(suite.providers[0].name(), "mock");
+        assert_eq!(suite.providers[1].name(), "gpt");
+        assert_eq!(suite.cases[0].expect.json, Some(json!({ "count": 2 })));
+        assert_eq!(suite.cases[0].mock[0][1], MockEvent::ToolUse {
+            tool_use_id: "1".to_string(),
+            name: "fs_read".to_string(),
+            args: json!({ "operations": [{ "mode": "Line", "path": "src/main.rs" }] }),
+        });
+
+        assert!(Suite::parse("cases: []").is_err());
+        assert!(Suite::parse("cases: [{ name: a, prompt: b, expect: { regex: '(' } }]").is_err());
+        assert!(Suite::parse("cases: [{ name: a, prompt: b, expected: {} }]").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_mock_cases() {
+        let suite = Suite::parse(SUITE).unwrap();
+        let mut results = Vec::new();
+        for case in &suite.cases {
+            let transcript = run_case(&mock_client(&case.mock), case).await;
+            results.push(CaseResult::new(case, "mock".to_string(), transcript));
+        }
+
+        assert!(results[0].passed, "{:?}", results[0].failures);
+        assert_eq!(results[0].requests, 2);
+        assert_eq!(results[0].tools_used, vec!["fs_read"]);
+        assert!(!results[1].passed);
+        assert_eq!(results[1].failures, vec!["the response doesn't match /(?i)paris/"]);
+
+        let report = Report::new(results, vec!["mock".to_string()]);
+        assert!(!report.passed());
+        assert_eq!(report.providers[0].passed, 1);
+        assert_eq!(report.providers[0].cases, 2);
+    }
+
+    #[test]
+    fn test_json_contains() {
+        let actual = json!({ "count": 2, "items": [{ "line": 1, "text": "a" }] });
+        assert!(json_contains(&actual, &json!({ "count": 2 })));
+        assert!(json_contains(&actual, &json!({ "items": [{ "line": 1 }] })));
+        assert!(!json_contains(&actual, &json!({ "items": [] })));
+        assert!(!json_contains(&actual, &json!({ "count": "2" })));
+        assert_eq!(json_document("```json\n{\"a\": 1}\n```"), Some(json!({ "a": 1 })));
+        assert_eq!(json_document("no JSON here"), None);
+    }
+}
@@ -0,0 +1,195 @@
+//! Localization of the most frequent messages of the CLI, the approval prompt of tools and the
+//! errors of a chat, in the language set with `chat.language`.
+//!
+//! The messages are in the Fluent catalogs of `i18n/`, one per language. A language without a
+//! catalog, e.g. `ko`, or a message missing from one falls back to English, while the responses of
+//! the model still follow the setting.
+
+use std::io::Write;
+use std::sync::{
+    LazyLock,
+    OnceLock,
+};
+
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Color,
+};
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{
+    FluentArgs,
+    FluentResource,
+};
+use tracing::warn;
+use unic_langid::LanguageIdentifier;
+
+use crate::database::Database;
+use crate::database::settings::Setting;
+
+type Bundle = FluentBundle<FluentResource>;
+
+/// The catalogs, by language tag. English comes first and has every message.
+const CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("i18n/en.ftl")),
+    ("de", include_str!("i18n/de.ftl")),
+    ("es", include_str!("i18n/es.ftl")),
+    ("fr", include_str!("i18n/fr.ftl")),
+    ("ja", include_str!("i18n/ja.ftl")),
+    ("pt-BR", include_str!("i18n/pt-BR.ftl")),
+    ("zh-CN", include_str!("i18n/zh-CN.ftl")),
+];
+
+/// Separates the arguments from the text around them in [print_highlighted].
+const MARK: char = '\u{1f}';
+
+static ENGLISH: LazyLock<Bundle> = LazyLock::new(|| bundle(CATALOGS[0].0, CATALOGS[0].1));
+
+/// The catalog of `chat.language`, [None] for English or a language without one.
+static SELECTED: OnceLock<Option<Bundle>> = OnceLock::new();
+
+/// Picks the catalog of `chat.language`. Only the first call has an effect.
+pub fn configure(database: &Database) {
+    let selected = database
+        .settings
+        .get_string(Setting::ChatLanguage)
+        .and_then(|language| catalog(&language))
+        .filter(|(tag, _)| *tag != CATALOGS[0].0)
+        .map(|(tag, source)| bundle(tag, source));
+    let _ = SELECTED.set(selected);
+}
+
+/// The message `id` in the language of the CLI.
+pub fn message(id: &str) -> String {
+    message_with(id, &[])
+}
+
+/// The message `id` in the language of the CLI, with its `{ $name }` arguments replaced.
+pub fn message_with(id: &str, args: &[(&str, &str)]) -> String {
+    let selected = SELECTED.get().and_then(Option::as_ref);
+    format(selected.into_iter().chain([&*ENGLISH]), id, args)
+}
+
+/// Prints the message `id` in `color`, with its arguments in `highlight`, e.g. the keys of a
+/// prompt.
+pub fn print_highlighted(
+    output: &mut impl Write,
+    id: &str,
+    args: &[(&str, &str)],
+    color: Color,
+    highlight: Color,
+) -> std::io::Result<()> {
+    let marks = args
+        .iter()
+        .map(|(name, _)| format!("{MARK}{name}{MARK}"))
+        .collect::<Vec<_>>();
+    let marked = args
+        .iter()
+        .zip(&marks)
+        .map(|((name, _), mark)| (*name, mark.as_str()))
+        .collect::<Vec<_>>();
+    // The text alternates between what is around the arguments and their names.
+    for (index, part) in message_with(id, &marked).split(MARK).enumerate() {
+        match args.iter().find(|(name, _)| index % 2 == 1 && *name == part) {
+            Some((_, value)) => queue!(output, style::SetForegroundColor(highlight), style::Print(value))?,
+            None => queue!(output, style::SetForegroundColor(color), style::Print(part))?,
+        }
+    }
+    Ok(())
+}
+
+/// The catalog of `language`, the one of its primary language when there is none for the region,
+/// e.g. `es` for `es-MX`.
+fn catalog(language: &str) -> Option<(&'static str, &'static str)> {
+    let language = language.trim().replace('_', "-").to_ascii_lowercase();
+    let primary = |tag: &str| tag.split('-').next().unwrap_or_default().to_string();
+    CATALOGS
+        .iter()
+        .find(|(tag, _)| tag.to_ascii_lowercase() == language)
+        .or_else(|| CATALOGS.iter().find(|(tag, _)| primary(tag) == primary(&language)))
+        .copied()
+}
+
+fn bundle(tag: &str, source: &str) -> Bundle {
+    let language = tag.parse::<LanguageIdentifier>().unwrap_or_default();
+    let mut bundle = Bundle::new_concurrent(vec![language]);
+    // The isolation marks around arguments show up as garbage in some terminals.
+    bundle.set_use_isolating(false);
+    match FluentResource::try_new(source.to_string()) {
+        Ok(resource) => {
+            if let Err(errors) = bundle.add_resource(resource) {
+                warn!(?errors, "Duplicate messages in the {tag} catalog");
+            }
+        },
+        Err((_, errors)) => warn!(?errors, "Failed to parse the {tag} catalog"),
+    }
+    bundle
+}
+
+/// The message `id` from the first of `bundles` that has it, or `id` when none does.
+fn format<'a>(bundles: impl IntoIterator<Item = &'a Bundle>, id: &str, args: &[(&str, &str)]) -> String {
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, *value);
+    }
+    bundles
+        .into_iter()
+        .find_map(|bundle| {
+            let pattern = bundle.get_message(id)?.value()?;
+            let text = bundle.format_pattern(pattern, Some(&fluent_args), &mut Vec::new());
+            Some(text.into_owned())
+        })
+        .unwrap_or_else(|| id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalogs() {
+        let ids = |source: &str| {
+            source
+                .lines()
+                .filter(|line| line.starts_with(|c: char| c.is_ascii_lowercase()))
+                .filter_map(|line| line.split_once(" = ").map(|(id, _)| id.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let english = ids(CATALOGS[0].1);
+        assert!(!english.is_empty());
+        for (tag, source) in CATALOGS {
+            assert!(FluentResource::try_new((*source).to_string()).is_ok(), "{tag} parses");
+            assert_eq!(ids(source), english, "{tag}");
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(catalog("es_MX").map(|(tag, _)| tag), Some("es"));
+        assert_eq!(catalog("pt-br").map(|(tag, _)| tag), Some("pt-BR"));
+        assert_eq!(catalog("ko"), None);
+
+        let spanish = bundle("es", "error-export = No se pudo exportar a { $path }: { $error }\n");
+        assert_eq!(
+            format([&spanish, &*ENGLISH], "error-export", &[
+                ("path", "a.md"),
+                ("error", "denied")
+            ]),
+            "No se pudo exportar a a.md: denied"
+        );
+        assert_eq!(
+            format([&spanish, &*ENGLISH], "error-file-exists", &[("path", "a.md")]),
+            "File at a.md already exists. To overwrite, use -f or --force"
+        );
+        assert_eq!(format([&spanish], "missing", &[]), "missing");
+
+        let mut output = Vec::new();
+        let args = [("yes", "y"), ("no", "n"), ("trust", "t")];
+        print_highlighted(&mut output, "approval-prompt", &args, Color::DarkGrey, Color::Green).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("\u{1b}[38;5;8mAllow this action? Use '\u{1b}[38;5;10mt\u{1b}[38;5;8m' to trust"));
+        assert!(output.ends_with(
+            "[\u{1b}[38;5;10my\u{1b}[38;5;8m/\u{1b}[38;5;10mn\u{1b}[38;5;8m/\u{1b}[38;5;10mt\u{1b}[38;5;8m]:"
+        ));
+    }
+}
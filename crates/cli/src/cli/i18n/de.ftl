@@ -0,0 +1,17 @@
+### Meldungen der CLI auf Deutsch.
+
+## Freigabe von Tools
+
+approval-prompt = Diese Aktion erlauben? Mit '{ $trust }' wird diesem Tool für die Sitzung vertraut (immer erlauben). [{ $yes }/{ $no }/{ $trust }]:
+approval-fix-resource = Mit '{ $fix }' ersetzen Sie einen Ressourcennamen durch eine Ressource Ihres Kontos.
+error-tool-approval-non-interactive = Das Tool muss freigegeben werden, aber --no-interactive wurde angegeben. Mit --trust-all-tools werden Tools automatisch freigegeben.
+
+## Fehler eines Chats
+
+error-responding = Amazon Q kann gerade nicht antworten
+error-conversation-too-large = Die Unterhaltung ist zu lang, um fortzufahren.
+hint-usage = • { $command } zeigt, wie der Kontext genutzt wird
+hint-clear = • { $command } setzt die Unterhaltung zurück
+context-overflow = Das Kontextfenster ist übergelaufen, der Verlauf wird zusammengefasst...
+error-file-exists = Die Datei { $path } existiert bereits. Zum Überschreiben -f oder --force verwenden
+error-export = Export nach { $path } fehlgeschlagen: { $error }
@@ -0,0 +1,20 @@
+### Messages of the CLI in English, the fallback of the other catalogs, which have the same ids.
+
+## Tool approval
+
+# $yes, $no and $trust are the keys to type, which aren't translated.
+approval-prompt = Allow this action? Use '{ $trust }' to trust (always allow) this tool for the session. [{ $yes }/{ $no }/{ $trust }]:
+# $fix is the key to type, offered when use_aws names resources that can be listed.
+approval-fix-resource = Use '{ $fix }' to replace a resource name with one from your account.
+error-tool-approval-non-interactive = Tool approval required but --no-interactive was specified. Use --trust-all-tools to automatically approve tools.
+
+## Errors of a chat
+
+error-responding = Amazon Q is having trouble responding right now
+error-conversation-too-large = Your conversation is too large to continue.
+# $command is the slash command to run.
+hint-usage = • Run { $command } to analyze your context usage
+hint-clear = • Run { $command } to reset your conversation state
+context-overflow = The context window has overflowed, summarizing the history...
+error-file-exists = File at { $path } already exists. To overwrite, use -f or --force
+error-export = Failed to export to { $path }: { $error }
@@ -0,0 +1,17 @@
+### Mensajes de la CLI en español.
+
+## Aprobación de herramientas
+
+approval-prompt = ¿Permitir esta acción? Usa '{ $trust }' para confiar en esta herramienta (permitirla siempre) durante la sesión. [{ $yes }/{ $no }/{ $trust }]:
+approval-fix-resource = Usa '{ $fix }' para reemplazar el nombre de un recurso por uno de tu cuenta.
+error-tool-approval-non-interactive = La herramienta requiere aprobación, pero se indicó --no-interactive. Usa --trust-all-tools para aprobar las herramientas automáticamente.
+
+## Errores de un chat
+
+error-responding = Amazon Q tiene problemas para responder en este momento
+error-conversation-too-large = La conversación es demasiado larga para continuar.
+hint-usage = • Ejecuta { $command } para analizar el uso del contexto
+hint-clear = • Ejecuta { $command } para reiniciar la conversación
+context-overflow = La ventana de contexto se ha desbordado, resumiendo el historial...
+error-file-exists = El archivo { $path } ya existe. Para sobrescribirlo, usa -f o --force
+error-export = No se pudo exportar a { $path }: { $error }
@@ -0,0 +1,17 @@
+### Messages de la CLI en français.
+
+## Approbation des outils
+
+approval-prompt = Autoriser cette action ? Utilisez '{ $trust }' pour faire confiance à cet outil (toujours autoriser) pendant la session. [{ $yes }/{ $no }/{ $trust }] :
+approval-fix-resource = Utilisez '{ $fix }' pour remplacer le nom d'une ressource par une ressource de votre compte.
+error-tool-approval-non-interactive = L'outil doit être approuvé, mais --no-interactive a été indiqué. Utilisez --trust-all-tools pour approuver les outils automatiquement.
+
+## Erreurs d'un chat
+
+error-responding = Amazon Q a du mal à répondre pour le moment
+error-conversation-too-large = La conversation est trop longue pour continuer.
+hint-usage = • Lancez { $command } pour analyser l'utilisation du contexte
+hint-clear = • Lancez { $command } pour réinitialiser la conversation
+context-overflow = La fenêtre de contexte est pleine, résumé de l'historique...
+error-file-exists = Le fichier { $path } existe déjà. Pour l'écraser, utilisez -f ou --force
+error-export = Échec de l'export vers { $path } : { $error }
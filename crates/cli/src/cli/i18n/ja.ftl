@@ -0,0 +1,17 @@
+### CLI のメッセージ（日本語）。
+
+## ツールの承認
+
+approval-prompt = この操作を許可しますか？ '{ $trust }' でこのセッション中はこのツールを信頼します（常に許可）。 [{ $yes }/{ $no }/{ $trust }]:
+approval-fix-resource = '{ $fix }' でリソース名をアカウント内のリソースに置き換えます。
+error-tool-approval-non-interactive = ツールの承認が必要ですが、--no-interactive が指定されています。--trust-all-tools を使うとツールが自動的に承認されます。
+
+## チャットのエラー
+
+error-responding = Amazon Q は現在応答できません
+error-conversation-too-large = 会話が長すぎるため続行できません。
+hint-usage = • { $command } でコンテキストの使用状況を確認できます
+hint-clear = • { $command } で会話をリセットできます
+context-overflow = コンテキストウィンドウがあふれたため、履歴を要約しています...
+error-file-exists = ファイル { $path } は既に存在します。上書きするには -f または --force を使ってください
+error-export = { $path } へのエクスポートに失敗しました: { $error }
@@ -0,0 +1,17 @@
+### Mensagens da CLI em português do Brasil.
+
+## Aprovação de ferramentas
+
+approval-prompt = Permitir esta ação? Use '{ $trust }' para confiar nesta ferramenta (sempre permitir) durante a sessão. [{ $yes }/{ $no }/{ $trust }]:
+approval-fix-resource = Use '{ $fix }' para substituir o nome de um recurso por um da sua conta.
+error-tool-approval-non-interactive = A ferramenta precisa de aprovação, mas --no-interactive foi especificado. Use --trust-all-tools para aprovar as ferramentas automaticamente.
+
+## Erros de um chat
+
+error-responding = O Amazon Q está com dificuldades para responder no momento
+error-conversation-too-large = A conversa está longa demais para continuar.
+hint-usage = • Execute { $command } para analisar o uso do contexto
+hint-clear = • Execute { $command } para reiniciar a conversa
+context-overflow = A janela de contexto transbordou, resumindo o histórico...
+error-file-exists = O arquivo { $path } já existe. Para sobrescrever, use -f ou --force
+error-export = Falha ao exportar para { $path }: { $error }
@@ -0,0 +1,17 @@
+### CLI 的简体中文消息。
+
+## 工具审批
+
+approval-prompt = 允许此操作吗？输入 '{ $trust }' 可在本次会话中信任此工具（始终允许）。 [{ $yes }/{ $no }/{ $trust }]:
+approval-fix-resource = 输入 '{ $fix }' 可将资源名称替换为您账户中的资源。
+error-tool-approval-non-interactive = 需要审批工具，但指定了 --no-interactive。使用 --trust-all-tools 可自动批准工具。
+
+## 聊天错误
+
+error-responding = Amazon Q 暂时无法响应
+error-conversation-too-large = 对话过长，无法继续。
+hint-usage = • 运行 { $command } 查看上下文的使用情况
+hint-clear = • 运行 { $command } 重置对话
+context-overflow = 上下文窗口已溢出，正在总结历史记录...
+error-file-exists = 文件 { $path } 已存在。如需覆盖，请使用 -f 或 --force
+error-export = 导出到 { $path } 失败：{ $error }
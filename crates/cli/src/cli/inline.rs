@@ -0,0 +1,225 @@
+//! `q inline`, which completes the command line of the shell, or the buffer of an editor, with the
+//! code completions API.
+//!
+//! The shell integration asks for a completion of the command line when Alt-i is pressed. zsh shows
+//! it as ghost text after the cursor, accepted by pressing Alt-i again and dismissed by typing,
+//! while bash and fish, which can't show ghost text, insert it right away. Completions attributed
+//! to licensed code are recorded in the log of `q references`.
+
+use std::process::ExitCode;
+
+use clap::{
+    Args,
+    Subcommand,
+};
+use eyre::{
+    Result,
+    WrapErr,
+};
+use tracing::warn;
+
+use super::references::{
+    ReferenceEntry,
+    ReferenceLog,
+};
+use super::shell_hook::Shell;
+use crate::api_client::Client;
+use crate::api_client::model::{
+    FileContext,
+    LanguageName,
+    ProgrammingLanguage,
+    RecommendationsInput,
+};
+use crate::database::Database;
+use crate::util::CLI_BINARY_NAME;
+
+/// Name of the file sent with the command line, which the API needs to have one.
+const COMMAND_LINE_FILENAME: &str = "command-line.sh";
+
+const ZSH_INTEGRATION: &str = r#"__q_inline_suggestion=
+__q_inline_buffer=
+__q_inline_highlight=
+__q_inline_clear() {
+  __q_inline_suggestion=
+  POSTDISPLAY=
+  region_highlight=(${region_highlight:#$__q_inline_highlight})
+}
+__q_inline() {
+  if [[ -n $__q_inline_suggestion && $BUFFER == "$__q_inline_buffer" ]]; then
+    LBUFFER+=$__q_inline_suggestion
+    __q_inline_clear
+    return
+  fi
+  local suggestion
+  suggestion=$(command __BIN__ inline complete --left "$LBUFFER" --right "$RBUFFER" 2>/dev/null)
+  [[ -n $suggestion ]] || return
+  # Ghost text is only shown after the end of the buffer
+  if [[ -n $RBUFFER ]]; then
+    LBUFFER+=$suggestion
+    return
+  fi
+  __q_inline_suggestion=$suggestion
+  __q_inline_buffer=$BUFFER
+  POSTDISPLAY=$suggestion
+  __q_inline_highlight="${#BUFFER} $((${#BUFFER} + ${#POSTDISPLAY})) fg=8"
+  region_highlight+=("$__q_inline_highlight")
+}
+__q_inline_redraw() {
+  if [[ -n $__q_inline_suggestion && $BUFFER != "$__q_inline_buffer" ]]; then
+    __q_inline_clear
+  fi
+}
+__q_inline_finish() {
+  if [[ -n $__q_inline_suggestion ]]; then
+    __q_inline_clear
+  fi
+}
+zle -N __q_inline
+autoload -Uz add-zle-hook-widget
+add-zle-hook-widget line-pre-redraw __q_inline_redraw
+add-zle-hook-widget line-finish __q_inline_finish
+bindkey '^[i' __q_inline
+"#;
+
+const BASH_INTEGRATION: &str = r#"__q_inline() {
+  local left=${READLINE_LINE:0:READLINE_POINT} right=${READLINE_LINE:READLINE_POINT} suggestion
+  suggestion=$(command __BIN__ inline complete --left "$left" --right "$right" 2>/dev/null)
+  [[ -n $suggestion ]] || return
+  READLINE_LINE=$left$suggestion$right
+  READLINE_POINT=$((READLINE_POINT + ${#suggestion}))
+}
+bind -x '"\ei": __q_inline'
+"#;
+
+const FISH_INTEGRATION: &str = r#"function __q_inline
+    set -l buffer (commandline -b | string collect)
+    set -l left (commandline -c | string collect)
+    set -l right (string sub -s (math (string length -- "$left") + 1) -- "$buffer")
+    set -l suggestion (command __BIN__ inline complete --left "$left" --right "$right" 2>/dev/null | string collect)
+    test -n "$suggestion"; and commandline -i -- $suggestion
+end
+bind \ei __q_inline
+"#;
+
+#[derive(Debug, Subcommand, PartialEq, Eq)]
+pub enum InlineSubcommand {
+    /// Print the completion to insert at the cursor
+    Complete(CompleteArgs),
+    /// Print the shell integration, e.g. `eval "$(q inline shell-integration zsh)"` in ~/.zshrc
+    ShellIntegration {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(Debug, Args, PartialEq, Eq)]
+pub struct CompleteArgs {
+    /// Text before the cursor
+    #[arg(long, default_value = "")]
+    pub left: String,
+    /// Text after the cursor
+    #[arg(long, default_value = "")]
+    pub right: String,
+    /// File the text is from, which sets its language, e.g. main.rs. The text is a command line of
+    /// the shell without it, and plain text when the language isn't detected
+    #[arg(long)]
+    pub filename: Option<String>,
+}
+
+impl InlineSubcommand {
+    pub async fn execute(self, database: &mut Database) -> Result<ExitCode> {
+        match self {
+            InlineSubcommand::Complete(args) => args.execute(database).await,
+            InlineSubcommand::ShellIntegration { shell } => {
+                print!("{}", integration(shell));
+                Ok(ExitCode::SUCCESS)
+            },
+        }
+    }
+}
+
+impl CompleteArgs {
+    async fn execute(self, database: &mut Database) -> Result<ExitCode> {
+        let command_line = self.filename.is_none();
+        let filename = self.filename.clone();
+        let file_context = match self.filename {
+            Some(filename) => FileContext::new(filename, self.left, self.right),
+            None => FileContext {
+                left_file_content: self.left,
+                right_file_content: self.right,
+                filename: COMMAND_LINE_FILENAME.to_string(),
+                programming_language: ProgrammingLanguage {
+                    language_name: LanguageName::Shell,
+                },
+            },
+        };
+
+        let client = Client::new(database, None)
+            .await
+            .wrap_err("Failed to initialize the completions client")?;
+        let output = client
+            .generate_recommendations(RecommendationsInput {
+                file_context,
+                max_results: 1,
+                next_token: None,
+            })
+            .await?;
+
+        let Some((recommendation, suggestion)) = output
+            .recommendations
+            .iter()
+            .map(|recommendation| (recommendation, suggestion(&recommendation.content, command_line)))
+            .find(|(_, suggestion)| !suggestion.trim().is_empty())
+        else {
+            return Ok(ExitCode::FAILURE);
+        };
+
+        if !recommendation.references.is_empty() {
+            let entries = recommendation
+                .references
+                .iter()
+                .map(|reference| ReferenceEntry {
+                    files: filename.iter().cloned().collect(),
+                    ..ReferenceEntry::new(reference, &suggestion)
+                })
+                .collect::<Vec<_>>();
+            if let Err(err) = ReferenceLog::for_workspace(&std::env::current_dir()?).append(&entries) {
+                warn!(?err, "Failed to record the code references of the completion");
+            }
+        }
+
+        print!("{suggestion}");
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+fn integration(shell: Shell) -> String {
+    let template = match shell {
+        Shell::Bash => BASH_INTEGRATION,
+        Shell::Zsh => ZSH_INTEGRATION,
+        Shell::Fish => FISH_INTEGRATION,
+    };
+    template.replace("__BIN__", CLI_BINARY_NAME)
+}
+
+/// The text inserted for a completion. The command line only gets its first line, which would
+/// otherwise be run as soon as it's inserted.
+fn suggestion(content: &str, command_line: bool) -> String {
+    match command_line {
+        true => content.lines().next().unwrap_or_default().trim_end().to_string(),
+        false => content.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggestion() {
+        assert_eq!(suggestion("status --short\ngit diff\n", true), "status --short");
+        assert_eq!(suggestion("    x += 1\n    y += 1", false), "    x += 1\n    y += 1");
+        assert!(integration(Shell::Zsh).contains("command q inline complete"));
+        assert!(integration(Shell::Bash).contains("bind -x '\"\\ei\": __q_inline'"));
+    }
+}
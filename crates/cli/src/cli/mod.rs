@@ -1,10 +1,22 @@
+mod acp;
+mod audit;
 pub mod chat;
 mod debug;
 mod diagnostics;
+mod eval;
 mod feed;
+mod i18n;
+mod inline;
 mod issue;
+mod notify;
+mod references;
+mod schedule;
 mod server;
 mod settings;
+mod setup;
+mod shell_hook;
+mod translate;
+mod usage;
 mod user;
 
 use std::io::{
@@ -29,6 +41,8 @@ use clap::{
 use crossterm::style::Stylize;
 use eyre::Result;
 use feed::Feed;
+pub use schedule::Schedule;
+use schedule::ScheduleSubcommand;
 use serde::Serialize;
 use tracing::{
     Level,
@@ -36,8 +50,15 @@ use tracing::{
 };
 use user::UserSubcommand;
 
-use crate::cli::chat::cli::Mcp;
-use crate::cli::chat::mcp;
+use crate::cli::chat::cli::{
+    Config,
+    Mcp,
+};
+use crate::cli::chat::{
+    config,
+    mcp,
+};
+use crate::database::ReadOnlyReason;
 use crate::logging::{
     LogArgs,
     initialize_logging,
@@ -110,8 +131,36 @@ pub enum CliRootCommands {
     /// Model Context Protocol (MCP)
     #[command(subcommand)]
     Mcp(Mcp),
+    /// Export and import the CLI configuration
+    #[command(subcommand)]
+    Config(Config),
     /// Start OpenAI-compatible HTTP server
     Server(server::ServerArgs),
+    /// Serve the agent to editors over the Agent Client Protocol on stdio
+    Acp(acp::AcpArgs),
+    /// Recurring non-interactive agent tasks
+    #[command(subcommand)]
+    Schedule(ScheduleSubcommand),
+    /// Set up the model provider, trusted tools and MCP servers
+    Setup(setup::SetupArgs),
+    /// Turn a description into a shell command
+    Translate(translate::TranslateArgs),
+    /// Print the shell hook recording the last command for `q chat --last-command`
+    ShellHook(shell_hook::ShellHookArgs),
+    /// Complete the command line or the buffer of an editor
+    #[command(subcommand)]
+    Inline(inline::InlineSubcommand),
+    /// Run a suite of prompts against providers and compare the results
+    Eval(eval::EvalArgs),
+    /// Show or verify the log of the tools used in chat sessions
+    #[command(subcommand)]
+    Audit(audit::AuditSubcommand),
+    /// Report the tokens and estimated cost of chat sessions
+    #[command(subcommand)]
+    Usage(usage::UsageSubcommand),
+    /// List the generated code attributed to licensed repositories in this workspace
+    #[command(subcommand)]
+    References(references::ReferencesSubcommand),
 }
 
 impl CliRootCommands {
@@ -127,7 +176,18 @@ impl CliRootCommands {
             CliRootCommands::Version { .. } => "version",
             CliRootCommands::Chat { .. } => "chat",
             CliRootCommands::Mcp(_) => "mcp",
+            CliRootCommands::Config(_) => "config",
             CliRootCommands::Server(_) => "server",
+            CliRootCommands::Acp(_) => "acp",
+            CliRootCommands::Schedule(_) => "schedule",
+            CliRootCommands::Setup(_) => "setup",
+            CliRootCommands::Translate(_) => "translate",
+            CliRootCommands::ShellHook(_) => "shell-hook",
+            CliRootCommands::Inline(_) => "inline",
+            CliRootCommands::Eval(_) => "eval",
+            CliRootCommands::Audit(_) => "audit",
+            CliRootCommands::Usage(_) => "usage",
+            CliRootCommands::References(_) => "references",
         }
     }
 }
@@ -176,9 +236,14 @@ impl Cli {
                 ),
                 false => None,
             },
-            log_to_stdout: std::env::var_os("Q_LOG_STDOUT").is_some() || self.verbose > 0,
+            // stdout carries the protocol messages of `q acp` and `q mcp serve`
+            log_to_stdout: !matches!(
+                self.subcommand,
+                Some(CliRootCommands::Acp(_) | CliRootCommands::Mcp(Mcp::Serve(_)))
+            ) && (std::env::var_os("Q_LOG_STDOUT").is_some() || self.verbose > 0),
             log_file_path: match self.subcommand {
                 Some(CliRootCommands::Chat { .. }) => Some("chat.log".to_owned()),
+                Some(CliRootCommands::Acp(_)) => Some("acp.log".to_owned()),
                 _ => match crate::logging::get_log_level_max() >= Level::DEBUG {
                     true => Some("cli.log".to_owned()),
                     false => None,
@@ -192,6 +257,24 @@ impl Cli {
 
         let env = crate::platform::Env::new();
         let mut database = crate::database::Database::new().await?;
+        if let Some(reason) = database.read_only_reason() {
+            let message = match reason {
+                ReadOnlyReason::Locked => format!(
+                    "Another {CHAT_BINARY_NAME} process is holding the database, changes like saved conversations \
+                    won't be kept this time"
+                ),
+                ReadOnlyReason::NotWritable => {
+                    "The database isn't writable, check the permissions of its file and directory. Changes like \
+                    saved conversations won't be kept"
+                        .to_string()
+                },
+            };
+            eprintln!("{}", message.yellow());
+        }
+        // A bad network setting must not keep the user from fixing it with `q settings`
+        if let Err(err) = crate::request::configure(&database) {
+            eprintln!("{}", format!("Ignoring the network.* settings: {err}").yellow());
+        }
         let telemetry = crate::telemetry::TelemetryThread::new(&env, &mut database).await?;
 
         let _ = match &self.subcommand {
@@ -215,12 +298,35 @@ impl Cli {
                 CliRootCommands::Settings(settings_args) => settings_args.execute(&mut database, &cli_context).await,
                 CliRootCommands::Issue(args) => args.execute().await,
                 CliRootCommands::Version { changelog } => Self::print_version(changelog),
-                CliRootCommands::Chat(args) => chat::launch_chat(&mut database, &telemetry, args).await,
-                CliRootCommands::Mcp(args) => mcp::execute_mcp(args).await,
+                CliRootCommands::Chat(args) => {
+                    let args = chat::cli::Chat {
+                        verbose: self.verbose > 0,
+                        ..args
+                    };
+                    chat::launch_chat(&mut database, &telemetry, args).await
+                },
+                CliRootCommands::Mcp(args) => mcp::execute_mcp(&database, args).await,
+                CliRootCommands::Config(args) => config::execute_config(&mut database, args).await,
                 CliRootCommands::Server(args) => args.execute(&mut database, &cli_context).await,
+                CliRootCommands::Acp(args) => args.execute(&mut database).await,
+                CliRootCommands::Schedule(args) => args.execute(&mut database).await,
+                CliRootCommands::Setup(args) => args.execute(&mut database, &telemetry).await,
+                CliRootCommands::Translate(args) => args.execute(&mut database).await,
+                CliRootCommands::ShellHook(args) => args.execute(),
+                CliRootCommands::Inline(args) => args.execute(&mut database).await,
+                CliRootCommands::Eval(args) => args.execute(&mut database).await,
+                CliRootCommands::Audit(args) => args.execute(),
+                CliRootCommands::Usage(args) => args.execute(),
+                CliRootCommands::References(args) => args.execute(),
             },
             // Root command
-            None => chat::launch_chat(&mut database, &telemetry, chat::cli::Chat::default()).await,
+            None => {
+                let args = chat::cli::Chat {
+                    verbose: self.verbose > 0,
+                    ..Default::default()
+                };
+                chat::launch_chat(&mut database, &telemetry, args).await
+            },
         };
 
         let telemetry_result = telemetry.finish().await;
@@ -326,8 +432,10 @@ mod test {
         McpImport,
         McpList,
         McpRemove,
+        McpServe,
         Scope,
     };
+    use crate::cli::schedule::ScheduleRun;
 
     #[test]
     fn debug_assert() {
@@ -379,6 +487,7 @@ mod test {
                 profile: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                ..Default::default()
             })),
             verbose: 2,
             help_all: false,
@@ -399,6 +508,36 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_schedule() {
+        assert_parse!(
+            ["schedule", "run", "--daemon"],
+            CliRootCommands::Schedule(ScheduleSubcommand::Run(ScheduleRun {
+                daemon: true,
+                task: None,
+            }))
+        );
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "schedule", "run", "--daemon", "--task", "audit"]).is_err());
+    }
+
+    #[test]
+    fn test_chat_notify_requires_no_interactive() {
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--notify"]).is_err());
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--no-interactive", "--notify", "hi"]).is_ok());
+    }
+
+    #[test]
+    fn test_chat_tui_conflicts_with_no_interactive() {
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--tui", "--no-interactive"]).is_err());
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--tui"]).is_ok());
+    }
+
+    #[test]
+    fn test_chat_workspaces() {
+        let cli = Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--workspace", "api", "--workspace", "web"]).unwrap();
+        assert!(matches!(cli.subcommand, Some(CliRootCommands::Chat(chat)) if chat.workspaces == ["api", "web"]));
+    }
+
     #[test]
     fn test_version_changelog_specific() {
         assert_parse!(["version", "--changelog=1.8.0"], CliRootCommands::Version {
@@ -418,6 +557,7 @@ mod test {
                 profile: Some("my-profile".to_string()),
                 trust_all_tools: false,
                 trust_tools: None,
+                ..Default::default()
             })
         );
     }
@@ -434,6 +574,7 @@ mod test {
                 profile: Some("my-profile".to_string()),
                 trust_all_tools: false,
                 trust_tools: None,
+                ..Default::default()
             })
         );
     }
@@ -450,6 +591,7 @@ mod test {
                 profile: Some("my-profile".to_string()),
                 trust_all_tools: false,
                 trust_tools: None,
+                ..Default::default()
             })
         );
     }
@@ -466,6 +608,7 @@ mod test {
                 profile: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                ..Default::default()
             })
         );
         assert_parse!(
@@ -478,6 +621,7 @@ mod test {
                 profile: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                ..Default::default()
             })
         );
     }
@@ -494,6 +638,7 @@ mod test {
                 profile: None,
                 trust_all_tools: true,
                 trust_tools: None,
+                ..Default::default()
             })
         );
     }
@@ -510,6 +655,7 @@ mod test {
                 profile: None,
                 trust_all_tools: false,
                 trust_tools: Some(vec!["".to_string()]),
+                ..Default::default()
             })
         );
     }
@@ -526,6 +672,7 @@ mod test {
                 profile: None,
                 trust_all_tools: false,
                 trust_tools: Some(vec!["fs_read".to_string(), "fs_write".to_string()]),
+                ..Default::default()
             })
         );
     }
@@ -582,6 +729,17 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_mcp_subcommand_serve() {
+        assert_parse!(
+            ["mcp", "serve", "--trust-tools=fs_read,execute_bash"],
+            CliRootCommands::Mcp(Mcp::Serve(McpServe {
+                trust_all_tools: false,
+                trust_tools: Some(vec!["fs_read".to_string(), "execute_bash".to_string()]),
+            }))
+        );
+    }
+
     #[test]
     fn test_mcp_subcommand_status_simple() {
         assert_parse!(
@@ -600,4 +758,163 @@ mod test {
             }))
         );
     }
+
+    #[test]
+    fn test_setup() {
+        assert_parse!(["setup"], CliRootCommands::Setup(setup::SetupArgs {}));
+    }
+
+    #[test]
+    fn test_translate() {
+        assert_parse!(
+            ["translate", "find files modified last week"],
+            CliRootCommands::Translate(translate::TranslateArgs {
+                description: vec!["find files modified last week".to_string()],
+            })
+        );
+        assert_parse!(
+            ["translate", "list", "open", "ports"],
+            CliRootCommands::Translate(translate::TranslateArgs {
+                description: vec!["list".to_string(), "open".to_string(), "ports".to_string()],
+            })
+        );
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "translate"]).is_err());
+    }
+
+    #[test]
+    fn test_chat_last_command() {
+        let cli = Cli::parse_from([CHAT_BINARY_NAME, "chat", "--last-command", "is it flaky?"]);
+        match cli.subcommand {
+            Some(CliRootCommands::Chat(chat)) => {
+                assert!(chat.last_command);
+                assert_eq!(chat.input.as_deref(), Some("is it flaky?"));
+            },
+            other => panic!("unexpected subcommand: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_chat_recover() {
+        let cli = Cli::parse_from([CHAT_BINARY_NAME, "chat", "--recover"]);
+        match cli.subcommand {
+            Some(CliRootCommands::Chat(chat)) => assert!(chat.recover && !chat.resume),
+            other => panic!("unexpected subcommand: {other:?}"),
+        }
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--recover", "--resume"]).is_err());
+    }
+
+    #[test]
+    fn test_chat_schema() {
+        let cli = Cli::parse_from([CHAT_BINARY_NAME, "chat", "--schema", "todos.json", "-n", "TODOs"]);
+        match cli.subcommand {
+            Some(CliRootCommands::Chat(chat)) => {
+                assert_eq!(chat.schema.as_deref(), Some("todos.json"));
+                assert!(chat.no_interactive);
+            },
+            other => panic!("unexpected subcommand: {other:?}"),
+        }
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "chat", "--schema", "todos.json", "list the TODOs"]).is_err());
+    }
+
+    #[test]
+    fn test_chat_read_only() {
+        let cli = Cli::parse_from([CHAT_BINARY_NAME, "chat", "--read-only"]);
+        match cli.subcommand {
+            Some(CliRootCommands::Chat(chat)) => assert!(chat.read_only),
+            other => panic!("unexpected subcommand: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_chat_dry_run() {
+        let cli = Cli::parse_from([CHAT_BINARY_NAME, "chat", "--dry-run"]);
+        match cli.subcommand {
+            Some(CliRootCommands::Chat(chat)) => assert!(chat.dry_run && !chat.read_only),
+            other => panic!("unexpected subcommand: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_shell_hook() {
+        assert_parse!(
+            ["shell-hook", "zsh"],
+            CliRootCommands::ShellHook(shell_hook::ShellHookArgs {
+                shell: shell_hook::Shell::Zsh,
+            })
+        );
+        assert!(Cli::try_parse_from([CHAT_BINARY_NAME, "shell-hook", "powershell"]).is_err());
+    }
+
+    #[test]
+    fn test_inline() {
+        assert_parse!(
+            ["inline", "complete", "--left", "git st"],
+            CliRootCommands::Inline(inline::InlineSubcommand::Complete(inline::CompleteArgs {
+                left: "git st".to_string(),
+                right: String::new(),
+                filename: None,
+            }))
+        );
+        assert_parse!(
+            ["inline", "shell-integration", "bash"],
+            CliRootCommands::Inline(inline::InlineSubcommand::ShellIntegration {
+                shell: shell_hook::Shell::Bash,
+            })
+        );
+    }
+
+    #[test]
+    fn test_references() {
+        assert_parse!(
+            ["references", "list", "-f", "json"],
+            CliRootCommands::References(references::ReferencesSubcommand::List {
+                format: OutputFormat::Json,
+            })
+        );
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_parse!(
+            ["eval", "suite.yaml", "--provider", "gpt", "-f", "json"],
+            CliRootCommands::Eval(eval::EvalArgs {
+                suite: "suite.yaml".into(),
+                providers: vec!["gpt".to_string()],
+                format: OutputFormat::Json,
+            })
+        );
+    }
+
+    #[test]
+    fn test_audit() {
+        assert_parse!(
+            ["audit", "show", "-n", "5"],
+            CliRootCommands::Audit(audit::AuditSubcommand::Show {
+                limit: 5,
+                format: OutputFormat::Plain,
+            })
+        );
+        assert_parse!(
+            ["audit", "verify"],
+            CliRootCommands::Audit(audit::AuditSubcommand::Verify)
+        );
+    }
+
+    #[test]
+    fn test_usage() {
+        assert_parse!(
+            ["usage", "report"],
+            CliRootCommands::Usage(usage::UsageSubcommand::Report {
+                since: "7d".to_string(),
+                format: usage::ReportFormat::Table,
+            })
+        );
+        assert_parse!(
+            ["usage", "report", "--since", "4w", "--format", "csv"],
+            CliRootCommands::Usage(usage::UsageSubcommand::Report {
+                since: "4w".to_string(),
+                format: usage::ReportFormat::Csv,
+            })
+        );
+    }
 }
@@ -0,0 +1,239 @@
+//! `q references`, the log of generated code attributed to licensed repositories.
+//!
+//! When a chat response or an inline completion comes with code references, each of them is
+//! appended to `.amazonq/references.jsonl` of the workspace with the license, the repository and a
+//! hash of the code, so the code accepted in a project can be checked against its licenses later.
+
+use std::fs::{
+    File,
+    OpenOptions,
+};
+use std::io::{
+    BufRead,
+    BufReader,
+    Write as _,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process::ExitCode;
+
+use clap::Subcommand;
+use crossterm::style::Stylize;
+use eyre::{
+    Result,
+    WrapErr,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use time::OffsetDateTime;
+use time::macros::format_description;
+
+use super::OutputFormat;
+use crate::api_client::model::CodeReference;
+
+/// Path of the log, relative to the workspace.
+const REFERENCE_LOG_PATH: &str = ".amazonq/references.jsonl";
+
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum ReferencesSubcommand {
+    /// List the code references recorded in the current workspace
+    List {
+        /// Output format
+        #[arg(long, short, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+}
+
+impl ReferencesSubcommand {
+    pub fn execute(self) -> Result<ExitCode> {
+        let log = ReferenceLog::for_workspace(&std::env::current_dir()?);
+        match self {
+            ReferencesSubcommand::List { format } => {
+                let entries = &log.entries()?;
+                format.print(
+                    || {
+                        if entries.is_empty() {
+                            return format!("No code references were recorded in {}", log.path.display());
+                        }
+                        entries
+                            .iter()
+                            .map(ReferenceEntry::summary)
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    },
+                    || entries,
+                );
+                Ok(ExitCode::SUCCESS)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReferenceEntry {
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+    /// The chat the code was generated in, unset for inline completions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversation_id: Option<String>,
+    /// Files the code went to, e.g. the ones written by the response with the code.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    /// SHA-256 of the generated code.
+    pub snippet_hash: String,
+    pub license: Option<String>,
+    pub repository: Option<String>,
+    pub url: Option<String>,
+    /// Whether the code was withheld because of chat.blockCodeReferences.
+    #[serde(default)]
+    pub withheld: bool,
+}
+
+impl ReferenceEntry {
+    pub fn new(reference: &CodeReference, snippet: &str) -> Self {
+        Self {
+            time: OffsetDateTime::now_utc()
+                .replace_nanosecond(0)
+                .unwrap_or(OffsetDateTime::now_utc()),
+            conversation_id: None,
+            files: Vec::new(),
+            snippet_hash: format!("{:x}", Sha256::digest(snippet.as_bytes())),
+            license: reference.license_name.clone(),
+            repository: reference.repository.clone(),
+            url: reference.url.clone(),
+            withheld: false,
+        }
+    }
+
+    fn summary(&self) -> String {
+        let time = self
+            .time
+            .format(format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"))
+            .unwrap_or_default();
+        let mut summary = format!(
+            "{} {:<12} {}",
+            time.dark_grey(),
+            self.license.as_deref().unwrap_or("unknown"),
+            self.repository.as_deref().unwrap_or("unknown repository")
+        );
+        if let Some(url) = &self.url {
+            summary.push_str(&format!(" {}", url.as_str().dark_grey()));
+        }
+        if !self.files.is_empty() {
+            summary.push_str(&format!(" → {}", self.files.join(", ")));
+        }
+        if self.withheld {
+            summary.push_str(&format!(" {}", "(withheld)".yellow()));
+        }
+        summary
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReferenceLog {
+    path: PathBuf,
+}
+
+impl ReferenceLog {
+    /// The log of the workspace in `dir`.
+    pub fn for_workspace(dir: &Path) -> Self {
+        Self {
+            path: dir.join(REFERENCE_LOG_PATH),
+        }
+    }
+
+    pub fn append(&self, entries: &[ReferenceEntry]) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .wrap_err_with(|| format!("Failed to open {}", self.path.display()))?;
+        let mut lines = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut lines, entry)?;
+            lines.push(b'\n');
+        }
+        file.write_all(&lines)?;
+        Ok(())
+    }
+
+    /// The entries of the log, oldest first.
+    pub fn entries(&self) -> Result<Vec<ReferenceEntry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut entries = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line).wrap_err_with(|| format!("Invalid entry on line {}", index + 1))?);
+        }
+        Ok(entries)
+    }
+}
+
+/// The code of a response, its fenced code blocks or all of it when it has none.
+pub fn response_snippet(response: &str) -> String {
+    let blocks = response
+        .split("```")
+        .skip(1)
+        .step_by(2)
+        .map(|block| block.split_once('\n').map_or(block, |(_, code)| code))
+        .collect::<Vec<_>>();
+    match blocks.is_empty() {
+        true => response.trim().to_string(),
+        false => blocks.concat(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = ReferenceLog::for_workspace(dir.path());
+        assert!(log.entries().unwrap().is_empty());
+
+        let reference = CodeReference {
+            license_name: Some("MIT".to_string()),
+            repository: Some("octo/repo".to_string()),
+            url: Some("https://github.com/octo/repo".to_string()),
+        };
+        let mut entry = ReferenceEntry::new(&reference, "fn main() {}\n");
+        entry.files = vec!["src/main.rs".to_string()];
+        log.append(&[entry.clone()]).unwrap();
+        log.append(&[ReferenceEntry::new(&reference, "other")]).unwrap();
+
+        let entries = log.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], entry);
+        assert_ne!(entries[0].snippet_hash, entries[1].snippet_hash);
+        assert!(dir.path().join(".amazonq/references.jsonl").exists());
+    }
+
+    #[test]
+    fn test_response_snippet() {
+        assert_eq!(
+            response_snippet("Use this:\n```rust\nfn a() {}\n```\nand\n```\nb();\n```\n"),
+            "fn a() {}\nb();\n"
+        );
+        assert_eq!(response_snippet(" no code \n"), "no code");
+    }
+}
@@ -0,0 +1,649 @@
+//! `q schedule`, recurring non-interactive agent runs.
+//!
+//! Tasks are stored in the database with a cron expression. `q schedule run` runs the tasks that
+//! are due, and is meant to be called every few minutes from cron or a systemd timer, or left
+//! running with `--daemon`. Every run is a `q chat --no-interactive` in the directory of the task,
+//! whose output is appended to the output file of the task.
+
+use std::io::Write as _;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process::{
+    ExitCode,
+    Stdio,
+};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anstream::{
+    eprintln,
+    println,
+};
+use clap::{
+    Args,
+    Subcommand,
+};
+use crossterm::style::Stylize;
+use eyre::{
+    Result,
+    WrapErr,
+    bail,
+    eyre,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use time::macros::format_description;
+use time::{
+    OffsetDateTime,
+    UtcOffset,
+};
+use tracing::warn;
+
+use crate::cli::notify::{
+    Notification,
+    NotificationSink,
+    NotificationStatus,
+};
+use crate::database::Database;
+use crate::platform::Context;
+use crate::util::{
+    CLI_BINARY_NAME,
+    directories,
+};
+
+/// Minutes searched for the next run of a cron expression, a bit more than a leap year.
+const MAX_CRON_SEARCH_MINUTES: i64 = 367 * 24 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum ScheduleSubcommand {
+    /// Add or replace a recurring task
+    Add(ScheduleAdd),
+    /// List the recurring tasks with their next and last run
+    List,
+    /// Remove a recurring task
+    #[command(alias = "rm")]
+    Remove {
+        /// Name of the task
+        name: String,
+    },
+    /// Run the tasks that are due, from cron or a systemd timer
+    Run(ScheduleRun),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+pub struct ScheduleAdd {
+    /// Name of the task
+    pub name: String,
+    /// When to run, as a cron expression in local time, e.g. '0 2 * * *' for every night at 2:00,
+    /// or one of @hourly, @daily, @weekly and @monthly
+    #[arg(long)]
+    pub cron: String,
+    /// The prompt to run
+    #[arg(long)]
+    pub prompt: String,
+    /// Directory to run in, the current directory by default
+    #[arg(long)]
+    pub directory: Option<PathBuf>,
+    /// File the output of every run is appended to, ~/.aws/amazonq/schedules/<name>.md by default
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+    /// Allows the model to use any tool without asking for confirmation
+    #[arg(long)]
+    pub trust_all_tools: bool,
+    /// Trust only this set of tools, e.g. '--trust-tools=fs_read,fs_write'
+    #[arg(long, value_delimiter = ',', value_name = "TOOL_NAMES")]
+    pub trust_tools: Option<Vec<String>>,
+    /// Post the output of every run, or a failure alert, to the webhook set in
+    /// chat.notify.webhookUrl
+    #[arg(long)]
+    pub notify: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Args)]
+pub struct ScheduleRun {
+    /// Keep running and run the tasks when they are due
+    #[arg(long, conflicts_with = "task")]
+    pub daemon: bool,
+    /// Run this task now, whether or not it is due
+    #[arg(long)]
+    pub task: Option<String>,
+}
+
+/// A recurring task, stored in the database.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub name: String,
+    pub cron: String,
+    pub prompt: String,
+    pub directory: PathBuf,
+    pub output: Option<PathBuf>,
+    #[serde(default)]
+    pub trust_all_tools: bool,
+    #[serde(default)]
+    pub trust_tools: Option<Vec<String>>,
+    #[serde(default)]
+    pub notify: bool,
+    /// When the task was added, in seconds since the Unix epoch.
+    pub created_at: i64,
+    /// When the task last started, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub last_run: Option<i64>,
+    #[serde(default)]
+    pub last_exit_code: Option<i32>,
+}
+
+impl Schedule {
+    /// When the task should run next: the first time matching its cron expression after its last
+    /// run, or after it was added. A task that missed several runs runs once.
+    fn next_run(&self, offset: UtcOffset) -> Result<Option<OffsetDateTime>> {
+        let spec = CronSpec::from_str(&self.cron)?;
+        let since = OffsetDateTime::from_unix_timestamp(self.last_run.unwrap_or(self.created_at))?.to_offset(offset);
+        Ok(spec.next_after(since))
+    }
+
+    fn is_due(&self, now: OffsetDateTime) -> bool {
+        match self.next_run(now.offset()) {
+            Ok(next_run) => next_run.is_some_and(|next_run| next_run <= now),
+            Err(err) => {
+                warn!(?err, name = %self.name, "invalid schedule");
+                false
+            },
+        }
+    }
+
+    fn output_path(&self, ctx: &Context) -> Result<PathBuf> {
+        match &self.output {
+            Some(output) => Ok(output.clone()),
+            None => Ok(directories::schedule_outputs_dir(ctx)?.join(format!("{}.md", self.name))),
+        }
+    }
+
+    /// The arguments of the `q chat` running the task.
+    fn chat_args(&self) -> Vec<String> {
+        let mut args = vec!["chat".to_string(), "--no-interactive".to_string()];
+        if self.trust_all_tools {
+            args.push("--trust-all-tools".to_string());
+        } else if let Some(trust_tools) = &self.trust_tools {
+            args.push(format!("--trust-tools={}", trust_tools.join(",")));
+        }
+        args.push(self.prompt.clone());
+        args
+    }
+}
+
+impl ScheduleSubcommand {
+    pub async fn execute(self, database: &mut Database) -> Result<ExitCode> {
+        let ctx = Context::new();
+        match self {
+            ScheduleSubcommand::Add(args) => add_schedule(&ctx, database, args)?,
+            ScheduleSubcommand::List => list_schedules(&ctx, database)?,
+            ScheduleSubcommand::Remove { name } => {
+                if database.get_schedule(&name)?.is_none() {
+                    bail!("No task named {name}");
+                }
+                database.delete_schedule(&name)?;
+                println!("✓ Removed {name}");
+            },
+            ScheduleSubcommand::Run(ScheduleRun { task: Some(name), .. }) => {
+                let Some(mut schedule) = database.get_schedule(&name)? else {
+                    bail!("No task named {name}");
+                };
+                run_schedule(&ctx, database, &mut schedule).await?;
+            },
+            ScheduleSubcommand::Run(ScheduleRun { daemon: false, .. }) => run_due_schedules(&ctx, database).await?,
+            ScheduleSubcommand::Run(ScheduleRun { daemon: true, .. }) => loop {
+                run_due_schedules(&ctx, database).await?;
+                // Wake up at the start of the next minute, the resolution of cron expressions.
+                let second = OffsetDateTime::now_utc().second() as u64;
+                tokio::time::sleep(Duration::from_secs(60 - second)).await;
+            },
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+fn add_schedule(ctx: &Context, database: &mut Database, args: ScheduleAdd) -> Result<()> {
+    CronSpec::from_str(&args.cron)?;
+    if args.notify && NotificationSink::from_database(database).is_none() {
+        bail!(
+            "--notify needs a webhook, set one with {}",
+            format!("{CLI_BINARY_NAME} settings chat.notify.webhookUrl <URL>").bold()
+        );
+    }
+    if args.name.is_empty()
+        || !args
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("The name may only contain letters, digits, '-' and '_'");
+    }
+    let directory = match args.directory {
+        Some(directory) => directory,
+        None => ctx.env().current_dir()?,
+    };
+    let directory = directory
+        .canonicalize()
+        .wrap_err_with(|| format!("{} is not a directory", directory.display()))?;
+
+    let schedule = Schedule {
+        name: args.name,
+        cron: args.cron,
+        prompt: args.prompt,
+        directory,
+        output: args.output.map(|output| absolute_path(ctx, &output)).transpose()?,
+        trust_all_tools: args.trust_all_tools,
+        trust_tools: args.trust_tools,
+        notify: args.notify,
+        created_at: OffsetDateTime::now_utc().unix_timestamp(),
+        last_run: None,
+        last_exit_code: None,
+    };
+    database.set_schedule(&schedule)?;
+
+    let offset = local_offset();
+    println!(
+        "✓ Added {}, next run {}",
+        schedule.name,
+        format_next_run(&schedule, offset)
+    );
+    println!(
+        "\nTasks only run while {} is called, e.g. every minute from your crontab:\n\n  {}\n\nor keep {} running.",
+        format!("{CLI_BINARY_NAME} schedule run").green(),
+        format!("* * * * * {CLI_BINARY_NAME} schedule run").green(),
+        format!("{CLI_BINARY_NAME} schedule run --daemon").green(),
+    );
+    Ok(())
+}
+
+fn list_schedules(ctx: &Context, database: &Database) -> Result<()> {
+    let schedules = database.get_all_schedules()?;
+    if schedules.is_empty() {
+        println!(
+            "No scheduled tasks, add one with {}.",
+            format!("{CLI_BINARY_NAME} schedule add").green()
+        );
+        return Ok(());
+    }
+
+    let offset = local_offset();
+    println!();
+    for schedule in &schedules {
+        let last_run = match (schedule.last_run, schedule.last_exit_code) {
+            (Some(last_run), Some(0)) => format!("last run {} ✓", format_timestamp(last_run, offset)),
+            (Some(last_run), Some(code)) => format!("last run {} failed ({code})", format_timestamp(last_run, offset)),
+            (Some(last_run), None) => format!("last run {}", format_timestamp(last_run, offset)),
+            (None, _) => "never run".to_string(),
+        };
+        println!("  {}  {}", schedule.name.clone().bold(), schedule.cron.clone().cyan());
+        println!(
+            "    next run {} · {}",
+            format_next_run(schedule, offset),
+            last_run.dark_grey()
+        );
+        println!("    in {}", schedule.directory.display());
+        println!("    to {}", schedule.output_path(ctx)?.display());
+        println!("    {}", truncate(&schedule.prompt, 100).dark_grey());
+    }
+    println!();
+    Ok(())
+}
+
+async fn run_due_schedules(ctx: &Context, database: &mut Database) -> Result<()> {
+    for schedule in database.get_all_schedules()? {
+        let now = OffsetDateTime::now_utc().to_offset(local_offset());
+        // Read it again, another `q schedule run` may have started it in the meantime.
+        let Some(mut schedule) = database.get_schedule(&schedule.name)? else {
+            continue;
+        };
+        if !schedule.is_due(now) {
+            continue;
+        }
+        if let Err(err) = run_schedule(ctx, database, &mut schedule).await {
+            eprintln!("Failed to run {}: {err}", schedule.name);
+            warn!(?err, name = %schedule.name, "failed to run schedule");
+        }
+    }
+    Ok(())
+}
+
+async fn run_schedule(ctx: &Context, database: &mut Database, schedule: &mut Schedule) -> Result<()> {
+    let started_at = OffsetDateTime::now_utc();
+    // Mark the task as run before running it, so that overlapping `q schedule run`s don't run it
+    // twice.
+    schedule.last_run = Some(started_at.unix_timestamp());
+    schedule.last_exit_code = None;
+    database.set_schedule(schedule)?;
+    println!("Running {}", schedule.name);
+
+    let output = tokio::process::Command::new(std::env::current_exe()?)
+        .args(schedule.chat_args())
+        .current_dir(&schedule.directory)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .wrap_err_with(|| format!("Unable to run {}", schedule.name))?;
+
+    let mut report = format!(
+        "## {} at {}\n\n",
+        schedule.name,
+        format_timestamp(started_at.unix_timestamp(), local_offset())
+    );
+    report.push_str(strip_ansi_escapes::strip_str(String::from_utf8_lossy(&output.stdout)).trim());
+    let stderr = strip_ansi_escapes::strip_str(String::from_utf8_lossy(&output.stderr));
+    if !output.status.success() && !stderr.trim().is_empty() {
+        report.push_str("\n\nErrors:\n\n");
+        report.push_str(stderr.trim());
+    }
+    report.push_str("\n\n");
+
+    let output_path = schedule.output_path(ctx)?;
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&output_path)
+        .and_then(|mut file| file.write_all(report.as_bytes()))
+        .wrap_err_with(|| format!("Unable to write to {}", output_path.display()))?;
+
+    schedule.last_exit_code = Some(output.status.code().unwrap_or(-1));
+    database.set_schedule(schedule)?;
+    if let Some(sink) = NotificationSink::from_database(database).filter(|_| schedule.notify) {
+        let notification = Notification {
+            title: format!("Scheduled task {}", schedule.name),
+            status: match output.status.success() {
+                true => NotificationStatus::Success,
+                false => NotificationStatus::Failure,
+            },
+            message: report.trim().to_string(),
+        };
+        sink.send_or_warn(&notification).await;
+    }
+    if output.status.success() {
+        println!(
+            "✓ {} finished, output appended to {}",
+            schedule.name,
+            output_path.display()
+        );
+    } else {
+        println!(
+            "✗ {} failed, output appended to {}",
+            schedule.name,
+            output_path.display()
+        );
+    }
+    Ok(())
+}
+
+fn absolute_path(ctx: &Context, path: &Path) -> Result<PathBuf> {
+    Ok(match path.is_absolute() {
+        true => path.to_path_buf(),
+        false => ctx.env().current_dir()?.join(path),
+    })
+}
+
+/// The local time zone, or UTC if it can't be determined.
+fn local_offset() -> UtcOffset {
+    UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC)
+}
+
+fn format_timestamp(timestamp: i64, offset: UtcOffset) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|date| {
+            date.to_offset(offset)
+                .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+                .ok()
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn format_next_run(schedule: &Schedule, offset: UtcOffset) -> String {
+    match schedule.next_run(offset) {
+        Ok(Some(next_run)) => format_timestamp(next_run.unix_timestamp(), offset),
+        Ok(None) => "never".to_string(),
+        Err(err) => format!("invalid: {err}"),
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    match line.char_indices().nth(max_chars) {
+        Some((index, _)) => format!("{}…", &line[..index]),
+        None if line.len() < text.len() => format!("{line}…"),
+        None => line.to_string(),
+    }
+}
+
+/// A cron expression with the five standard fields: minute, hour, day of month, month and day of
+/// week. Every field is a bit set of the values it matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSpec {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// As in cron, a day matches if either the day of month or the day of week matches, unless
+    /// one of them is `*`.
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl FromStr for CronSpec {
+    type Err = eyre::Report;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let expanded = match spec.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            spec => spec,
+        };
+        let fields = expanded.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days_of_month, months, days_of_week] = fields.as_slice() else {
+            bail!("'{spec}' is not a cron expression, expected: minute hour day-of-month month day-of-week");
+        };
+
+        let mut days_of_week_bits = parse_cron_field(days_of_week, 0, 7)?;
+        // Both 0 and 7 are Sunday.
+        if days_of_week_bits & (1 << 7) != 0 {
+            days_of_week_bits = (days_of_week_bits | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_cron_field(minutes, 0, 59)?,
+            hours: parse_cron_field(hours, 0, 23)?,
+            days_of_month: parse_cron_field(days_of_month, 1, 31)?,
+            months: parse_cron_field(months, 1, 12)?,
+            days_of_week: days_of_week_bits,
+            any_day_of_month: *days_of_month == "*",
+            any_day_of_week: *days_of_week == "*",
+        })
+    }
+}
+
+impl CronSpec {
+    pub fn matches(&self, time: OffsetDateTime) -> bool {
+        let day_of_month = self.days_of_month & (1 << time.day()) != 0;
+        let day_of_week = self.days_of_week & (1 << time.weekday().number_days_from_sunday()) != 0;
+        let day = match (self.any_day_of_month, self.any_day_of_week) {
+            (true, true) => true,
+            (true, false) => day_of_week,
+            (false, true) => day_of_month,
+            (false, false) => day_of_month || day_of_week,
+        };
+        day && self.minutes & (1 << time.minute()) != 0
+            && self.hours & (1 << time.hour()) != 0
+            && self.months & (1 << time.month() as u8) != 0
+    }
+
+    /// The first minute strictly after `time` matching the expression, or `None` if there is none
+    /// within a year, e.g. for February 30th.
+    pub fn next_after(&self, time: OffsetDateTime) -> Option<OffsetDateTime> {
+        let start = time.replace_second(0).ok()?.replace_nanosecond(0).ok()?;
+        (1..=MAX_CRON_SEARCH_MINUTES)
+            .map(|minutes| start + time::Duration::minutes(minutes))
+            .find(|candidate| self.matches(*candidate))
+    }
+}
+
+/// Parses a comma separated list of values, `a-b` ranges and `*`, each optionally with a `/step`.
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let invalid = || eyre!("'{part}' is not a valid cron field, expected values between {min} and {max}");
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<u32>().map_err(|_err| invalid())?)),
+            None => (part, None),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (
+                start.parse::<u32>().map_err(|_err| invalid())?,
+                end.parse::<u32>().map_err(|_err| invalid())?,
+            ),
+            // `5/15` is every 15 from 5.
+            None if step.is_some() => (range.parse::<u32>().map_err(|_err| invalid())?, max),
+            None => {
+                let value = range.parse::<u32>().map_err(|_err| invalid())?;
+                (value, value)
+            },
+        };
+        if start < min || end > max || start > end || step == Some(0) {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_cron_field() {
+        assert_eq!(parse_cron_field("*", 0, 3).unwrap(), 0b1111);
+        assert_eq!(parse_cron_field("1,3", 0, 59).unwrap(), 0b1010);
+        assert_eq!(parse_cron_field("2-4", 0, 59).unwrap(), 0b11100);
+        assert_eq!(
+            parse_cron_field("*/15", 0, 59).unwrap(),
+            1 | 1 << 15 | 1 << 30 | 1 << 45
+        );
+        assert_eq!(parse_cron_field("10/20", 0, 59).unwrap(), 1 << 10 | 1 << 30 | 1 << 50);
+        for invalid in ["60", "5-2", "*/0", "a", "", "1-"] {
+            assert!(parse_cron_field(invalid, 0, 59).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_cron_spec() {
+        assert!(CronSpec::from_str("0 2 * *").is_err());
+        assert_eq!(
+            CronSpec::from_str("@daily").unwrap(),
+            CronSpec::from_str("0 0 * * *").unwrap()
+        );
+
+        // Every night at 2:30.
+        let spec = CronSpec::from_str("30 2 * * *").unwrap();
+        assert_eq!(
+            spec.next_after(datetime!(2025-03-10 02:30:15 UTC)),
+            Some(datetime!(2025-03-11 02:30 UTC))
+        );
+        assert_eq!(
+            spec.next_after(datetime!(2025-03-10 01:00 UTC)),
+            Some(datetime!(2025-03-10 02:30 UTC))
+        );
+
+        // Weekdays at 9:00, 2025-03-08 is a Saturday.
+        let spec = CronSpec::from_str("0 9 * * 1-5").unwrap();
+        assert_eq!(
+            spec.next_after(datetime!(2025-03-08 12:00 UTC)),
+            Some(datetime!(2025-03-10 09:00 UTC))
+        );
+
+        // Sundays as 7, and the day of month or the day of week when both are set.
+        let spec = CronSpec::from_str("0 0 1 * 7").unwrap();
+        assert_eq!(
+            spec.next_after(datetime!(2025-03-02 00:00 UTC)),
+            Some(datetime!(2025-03-09 00:00 UTC))
+        );
+        assert!(spec.matches(datetime!(2025-04-01 00:00 UTC)));
+
+        assert_eq!(
+            CronSpec::from_str("0 0 30 2 *")
+                .unwrap()
+                .next_after(datetime!(2025-01-01 00:00 UTC)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_schedule_is_due() {
+        let mut schedule = Schedule {
+            name: "audit".to_string(),
+            cron: "0 2 * * *".to_string(),
+            prompt: "Summarize the outdated dependencies".to_string(),
+            directory: PathBuf::from("/project"),
+            output: None,
+            trust_all_tools: false,
+            trust_tools: Some(vec!["fs_read".to_string()]),
+            notify: false,
+            created_at: datetime!(2025-03-10 12:00 UTC).unix_timestamp(),
+            last_run: None,
+            last_exit_code: None,
+        };
+        assert!(!schedule.is_due(datetime!(2025-03-11 01:59 UTC)));
+        assert!(schedule.is_due(datetime!(2025-03-11 02:00 UTC)));
+        // Runs once after missing several nights.
+        assert!(schedule.is_due(datetime!(2025-03-14 08:00 UTC)));
+
+        schedule.last_run = Some(datetime!(2025-03-14 08:00 UTC).unix_timestamp());
+        assert!(!schedule.is_due(datetime!(2025-03-14 23:00 UTC)));
+        assert!(schedule.is_due(datetime!(2025-03-15 02:00:30 UTC)));
+
+        assert_eq!(schedule.chat_args(), vec![
+            "chat",
+            "--no-interactive",
+            "--trust-tools=fs_read",
+            "Summarize the outdated dependencies"
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_database() {
+        let mut database = Database::new().await.unwrap();
+        let schedule = Schedule {
+            name: "audit".to_string(),
+            cron: "@daily".to_string(),
+            prompt: "Summarize the outdated dependencies".to_string(),
+            directory: PathBuf::from("/project"),
+            output: Some(PathBuf::from("/reports/audit.md")),
+            trust_all_tools: true,
+            trust_tools: None,
+            notify: true,
+            created_at: 0,
+            last_run: None,
+            last_exit_code: None,
+        };
+        database.set_schedule(&schedule).unwrap();
+        assert_eq!(database.get_schedule("audit").unwrap(), Some(schedule.clone()));
+        assert_eq!(database.get_all_schedules().unwrap(), vec![schedule]);
+        database.delete_schedule("audit").unwrap();
+        assert!(database.get_all_schedules().unwrap().is_empty());
+    }
+}
@@ -1,40 +1,136 @@
+use std::collections::HashMap;
+use std::future::Future;
 use std::net::SocketAddr;
 use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Args;
-use eyre::{Result, WrapErr};
+use eyre::{
+    Result,
+    WrapErr,
+};
+use futures::{
+    SinkExt,
+    StreamExt,
+};
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
-use hyper::{Method, Request, Response, StatusCode};
-use hyper_util::rt::TokioIo;
-use serde::{Deserialize, Serialize};
+use hyper::{
+    Method,
+    Request,
+    Response,
+    StatusCode,
+};
+use hyper_util::rt::{
+    TokioIo,
+    TokioTimer,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
 use serde_json::json;
 use tokio::net::TcpListener;
-use tokio::sync::Mutex;
-use tracing::{debug, error, info, warn};
+use tokio::sync::{
+    Mutex,
+    mpsc,
+};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::{
+    Message,
+    Role,
+};
+use tracing::{
+    debug,
+    error,
+    info,
+    warn,
+};
 
-use crate::api_client::{StreamingClient, model::ConversationState, model::UserInputMessage};
+use crate::api_client::clients::backends::ToolCallPattern;
+use crate::api_client::model::{
+    ConversationState,
+    UserInputMessage,
+};
+use crate::api_client::{
+    ApiClientError,
+    ErrorKind,
+    StreamingClient,
+    list_available_profiles,
+};
+use crate::cli::chat::agent_session::{
+    AgentEvent,
+    AgentSession,
+    ClientMessage,
+};
+use crate::cli::chat::event_schema;
+use crate::cli::chat::model_capabilities::ModelCapabilityRegistry;
+use crate::cli::chat::openai_config::OpenAiConfig;
 use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::platform::Context;
 use crate::util::CliContext;
 
+mod grpc;
+
 #[derive(Debug, Args, PartialEq, Eq)]
 pub struct ServerArgs {
     /// Port to bind the server to
     #[arg(long, short, default_value = "8080")]
     pub port: u16,
-    
+
     /// Host to bind the server to
     #[arg(long, default_value = "127.0.0.1")]
     pub host: String,
-    
+
     /// API key for authentication (optional)
     #[arg(long)]
     pub api_key: Option<String>,
-    
+
     /// Model name to report in API responses
     #[arg(long, default_value = "amazon-q")]
     pub model_name: String,
+
+    /// Additional model alias in the form ALIAS=MODEL (can be repeated)
+    #[arg(long = "model-alias", value_parser = parse_model_alias)]
+    pub model_aliases: Vec<(String, String)>,
+
+    /// Maximum size of a request body in bytes
+    #[arg(long, default_value_t = 10 * 1024 * 1024)]
+    pub max_body_bytes: usize,
+
+    /// Maximum time in seconds to handle a single request
+    #[arg(long, default_value_t = 300)]
+    pub request_timeout: u64,
+
+    /// Time in seconds to wait for in-flight requests to finish when shutting down
+    #[arg(long, default_value_t = 30)]
+    pub shutdown_timeout: u64,
+
+    /// Maximum number of requests of a batch sent upstream at the same time
+    #[arg(long, default_value_t = 4)]
+    pub batch_concurrency: usize,
+
+    /// Serve agentic chat sessions over a WebSocket on /ws. Tools run on this machine, and the
+    /// ones that would ask for confirmation in `q chat` wait for the client to approve them.
+    /// Needs --api-key unless the host is a loopback address
+    #[arg(long)]
+    pub agent: bool,
+
+    /// Also serve the agentic chat sessions over gRPC on this port, see proto/chat.proto
+    #[arg(long, requires = "agent")]
+    pub grpc_port: Option<u16>,
+}
+
+fn parse_model_alias(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((alias, model)) if !alias.trim().is_empty() && !model.trim().is_empty() => {
+            Ok((alias.trim().to_string(), model.trim().to_string()))
+        },
+        _ => Err(format!("invalid model alias '{}', expected ALIAS=MODEL", s)),
+    }
 }
 
 // OpenAI API compatible structures
@@ -42,7 +138,9 @@ pub struct ServerArgs {
 struct ChatCompletionRequest {
     model: String,
     messages: Vec<ChatMessage>,
+    #[allow(dead_code)]
     temperature: Option<f32>,
+    #[allow(dead_code)]
     max_tokens: Option<u32>,
     stream: Option<bool>,
 }
@@ -162,73 +260,303 @@ struct ErrorDetail {
     code: Option<String>,
 }
 
+/// A model listed by `/v1/models` together with the client that serves it.
+struct ModelRoute {
+    id: String,
+    owned_by: String,
+    client: StreamingClient,
+}
+
+const SYSTEM_CONTEXT_START_HEADER: &str =
+    "--- CONTEXT ENTRY BEGIN ---\nFollow these instructions for the rest of the conversation:\n";
+const SYSTEM_CONTEXT_END_HEADER: &str = "--- CONTEXT ENTRY END ---\n";
+const SYSTEM_CONTEXT_ACKNOWLEDGEMENT: &str = "I will follow these instructions when generating my responses.";
+
 struct ServerState {
     client: StreamingClient,
     model_name: String,
     api_key: Option<String>,
+    /// Models served by a backend other than the default client.
+    models: Vec<ModelRoute>,
+    /// Maps an alias to the id of a listed model.
+    aliases: HashMap<String, String>,
+    max_body_bytes: usize,
+    /// Deadline of a request, or of each request of a batch.
+    request_timeout: Duration,
+    batch_concurrency: usize,
+    agent: bool,
+    /// The address the server is bound to.
+    addr: SocketAddr,
+}
+
+impl ServerState {
+    /// Resolves a requested model id or alias to the model id reported back and the client that
+    /// serves it.
+    ///
+    /// Unknown models are served by the default client so that clients hard-coding a model name
+    /// keep working.
+    fn resolve_model(&self, requested: &str) -> (String, &StreamingClient) {
+        let target = self.aliases.get(requested).map_or(requested, String::as_str);
+        if let Some(route) = self.models.iter().find(|route| route.id == target) {
+            return (route.id.clone(), &route.client);
+        }
+        if target != self.model_name {
+            debug!(
+                "Unknown model '{}', routing to default model '{}'",
+                requested, self.model_name
+            );
+        }
+        (self.model_name.clone(), &self.client)
+    }
+
+    fn list_models(&self) -> Vec<ModelInfo> {
+        let created = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let model_info = |id: &str, owned_by: &str| ModelInfo {
+            id: id.to_string(),
+            object: "model".to_string(),
+            created,
+            owned_by: owned_by.to_string(),
+        };
+
+        let mut data = vec![model_info(&self.model_name, "amazon")];
+        for route in &self.models {
+            data.push(model_info(&route.id, &route.owned_by));
+        }
+
+        let mut aliases = self.aliases.iter().collect::<Vec<_>>();
+        aliases.sort();
+        for (alias, target) in aliases {
+            if data.iter().any(|m| &m.id == alias) {
+                continue;
+            }
+            let owned_by = self
+                .models
+                .iter()
+                .find(|route| &route.id == target)
+                .map_or("amazon", |route| route.owned_by.as_str());
+            data.push(model_info(alias, owned_by));
+        }
+
+        data
+    }
+}
+
+/// Builds the routes for every model the server can serve besides the default client: the Amazon Q
+/// profiles available to the signed-in user and the configured OpenAI-compatible provider model.
+async fn load_model_routes(database: &mut Database, default_model: &str) -> Vec<ModelRoute> {
+    let mut routes = Vec::new();
+
+    match list_available_profiles(database).await {
+        Ok(profiles) => {
+            for profile in profiles {
+                let id = format!("{}/{}", default_model, profile.profile_name);
+                match StreamingClient::new_codewhisperer_client_for_profile(database, profile).await {
+                    Ok(client) => routes.push(ModelRoute {
+                        id,
+                        owned_by: "amazon".to_string(),
+                        client,
+                    }),
+                    Err(err) => warn!("Failed to create client for model {}: {}", id, err),
+                }
+            }
+        },
+        Err(err) => debug!("Not listing Amazon Q profiles: {}", err),
+    }
+
+    let openai_config = OpenAiConfig::from_database(database);
+    if openai_config.is_openai_compatible() {
+        let id = openai_config.model.clone();
+        let owned_by = openai_config.provider.to_string();
+        let capabilities = ModelCapabilityRegistry::from_database(database).capabilities(&id);
+        let patterns = ToolCallPattern::from_database(database);
+        match StreamingClient::new_openai_client(openai_config, capabilities, patterns).await {
+            Ok(client) => routes.push(ModelRoute { id, owned_by, client }),
+            Err(err) => warn!("Failed to create client for OpenAI-compatible provider: {}", err),
+        }
+    }
+
+    routes
+}
+
+/// Reads the alias map from the `server.modelAliases` setting, with `--model-alias` arguments
+/// taking precedence.
+fn load_model_aliases(database: &Database, args: &[(String, String)]) -> HashMap<String, String> {
+    let mut aliases = HashMap::new();
+    if let Some(serde_json::Value::Object(map)) = database.settings.get(Setting::ServerModelAliases) {
+        for (alias, target) in map {
+            match target.as_str() {
+                Some(target) => {
+                    aliases.insert(alias.clone(), target.to_string());
+                },
+                None => warn!("Ignoring model alias '{}': target must be a string", alias),
+            }
+        }
+    }
+    aliases.extend(args.iter().cloned());
+    aliases
 }
 
 impl ServerArgs {
     pub async fn execute(&self, database: &mut Database, _cli_context: &CliContext) -> Result<ExitCode> {
         info!("Starting Amazon Q OpenAI-compatible server...");
-        
+
         // Initialize the streaming client
-        let client = StreamingClient::new(database).await
+        let client = StreamingClient::new(database)
+            .await
             .wrap_err("Failed to initialize Amazon Q client")?;
-        
+
+        let models = load_model_routes(database, &self.model_name).await;
+        let aliases = load_model_aliases(database, &self.model_aliases);
+
+        let addr: SocketAddr = format!("{}:{}", self.host, self.port)
+            .parse()
+            .wrap_err("Invalid host:port combination")?;
+        if self.agent {
+            check_agent_addr(&addr, self.api_key.is_some())?;
+        }
+
+        let request_timeout = Duration::from_secs(self.request_timeout);
         let state = Arc::new(Mutex::new(ServerState {
             client,
             model_name: self.model_name.clone(),
             api_key: self.api_key.clone(),
+            models,
+            aliases,
+            max_body_bytes: self.max_body_bytes,
+            request_timeout,
+            batch_concurrency: self.batch_concurrency.max(1),
+            agent: self.agent,
+            addr,
         }));
-        
-        let addr: SocketAddr = format!("{}:{}", self.host, self.port)
-            .parse()
-            .wrap_err("Invalid host:port combination")?;
-        
-        let listener = TcpListener::bind(addr).await
-            .wrap_err("Failed to bind to address")?;
-        
+
+        let listener = TcpListener::bind(addr).await.wrap_err("Failed to bind to address")?;
+
         info!("🚀 Amazon Q OpenAI-compatible server running on http://{}", addr);
         info!("📖 API Documentation:");
         info!("  • Chat Completions: POST /v1/chat/completions");
+        info!("    (send an array of requests to run them as a batch, results are returned as NDJSON)");
         info!("  • List Models: GET /v1/models");
         info!("  • Health Check: GET /health");
-        
+        if self.agent {
+            info!("  • Agentic Chat: WebSocket /ws (tools run on this machine)");
+        }
+
         if let Some(api_key) = &self.api_key {
             info!("🔐 API Key authentication enabled");
             info!("   Use 'Authorization: Bearer {}' header", api_key);
         } else {
             warn!("⚠️  No API key configured - authentication disabled");
         }
-        
+
         info!("💡 Example usage:");
         info!("   curl -X POST http://{}/v1/chat/completions \\", addr);
         info!("     -H 'Content-Type: application/json' \\");
         if self.api_key.is_some() {
             info!("     -H 'Authorization: Bearer YOUR_API_KEY' \\");
         }
-        info!("     -d '{{\"model\":\"{}\",\"messages\":[{{\"role\":\"user\",\"content\":\"Hello!\"}}]}}'", self.model_name);
-        
-        loop {
-            let (stream, _) = listener.accept().await
-                .wrap_err("Failed to accept connection")?;
-            
-            let io = TokioIo::new(stream);
-            let state = Arc::clone(&state);
-            
-            tokio::task::spawn(async move {
-                if let Err(err) = http1::Builder::new()
-                    .serve_connection(io, service_fn(move |req| {
-                        let state = Arc::clone(&state);
-                        handle_request(req, state)
-                    }))
-                    .await
-                {
-                    error!("Error serving connection: {:?}", err);
+        info!(
+            "     -d '{{\"model\":\"{}\",\"messages\":[{{\"role\":\"user\",\"content\":\"Hello!\"}}]}}'",
+            self.model_name
+        );
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        let mut connections = tokio::task::JoinSet::new();
+        if let Some(grpc_port) = self.grpc_port {
+            let grpc_addr = SocketAddr::new(addr.ip(), grpc_port);
+            let (state, shutdown_rx) = (Arc::clone(&state), shutdown_rx.clone());
+            connections.spawn(async move {
+                if let Err(err) = grpc::serve(grpc_addr, state, shutdown_rx).await {
+                    error!("gRPC server failed: {:?}", err);
                 }
             });
         }
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let stream = match accepted {
+                        Ok((stream, _)) => stream,
+                        Err(err) => {
+                            error!("Failed to accept connection: {:?}", err);
+                            continue;
+                        }
+                    };
+
+                    let io = TokioIo::new(stream);
+                    let state = Arc::clone(&state);
+                    let mut shutdown_rx = shutdown_rx.clone();
+
+                    connections.spawn(async move {
+                        let mut builder = http1::Builder::new();
+                        builder.timer(TokioTimer::new()).header_read_timeout(request_timeout);
+                        let conn = builder.serve_connection(io, service_fn(move |req| {
+                            let state = Arc::clone(&state);
+                            async move { handle_request(req, state).await }
+                        })).with_upgrades();
+                        tokio::pin!(conn);
+
+                        let result = tokio::select! {
+                            result = conn.as_mut() => result,
+                            _ = shutdown_rx.changed() => {
+                                // Let the in-flight request finish, then close the connection
+                                conn.as_mut().graceful_shutdown();
+                                conn.await
+                            }
+                        };
+                        if let Err(err) = result {
+                            error!("Error serving connection: {:?}", err);
+                        }
+                    });
+                },
+                Some(_) = connections.join_next(), if !connections.is_empty() => {},
+                _ = &mut ctrl_c => {
+                    info!("Shutting down, waiting for {} open connection(s)...", connections.len());
+                    break;
+                }
+            }
+        }
+
+        // Stop accepting new connections before draining the existing ones
+        drop(listener);
+        let _ = shutdown_tx.send(true);
+
+        let drain = async { while connections.join_next().await.is_some() {} };
+        if tokio::time::timeout(Duration::from_secs(self.shutdown_timeout), drain)
+            .await
+            .is_err()
+        {
+            warn!(
+                "Timed out waiting for connections to close, aborting {} connection(s)",
+                connections.len()
+            );
+            connections.abort_all();
+        }
+
+        info!("Server stopped");
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// Answers with a 504 when `response` isn't ready within `deadline`.
+async fn with_deadline<T>(
+    deadline: Duration,
+    response: impl Future<Output = Result<T, Response<String>>>,
+) -> Result<T, Response<String>> {
+    match tokio::time::timeout(deadline, response).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!("Request exceeded the {:?} deadline", deadline);
+            Err(create_error_response(
+                StatusCode::GATEWAY_TIMEOUT,
+                "Request timed out",
+                "timeout",
+            ))
+        },
     }
 }
 
@@ -238,23 +566,20 @@ async fn handle_request(
 ) -> Result<Response<String>, hyper::Error> {
     let method = req.method();
     let path = req.uri().path();
-    
+
     debug!("Handling {} {}", method, path);
-    
+
     // CORS headers
     let response_builder = Response::builder()
         .header("Access-Control-Allow-Origin", "*")
         .header("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
         .header("Access-Control-Allow-Headers", "Content-Type, Authorization");
-    
+
     // Handle preflight requests
     if method == Method::OPTIONS {
-        return Ok(response_builder
-            .status(StatusCode::OK)
-            .body(String::new())
-            .unwrap());
+        return Ok(response_builder.status(StatusCode::OK).body(String::new()).unwrap());
     }
-    
+
     // Check API key if configured
     if let Some(expected_key) = &state.lock().await.api_key {
         if let Some(auth_header) = req.headers().get("authorization") {
@@ -263,67 +588,55 @@ async fn handle_request(
                     return Ok(create_error_response(
                         StatusCode::UNAUTHORIZED,
                         "Invalid API key",
-                        "invalid_api_key"
+                        "invalid_api_key",
                     ));
                 }
             } else {
                 return Ok(create_error_response(
                     StatusCode::UNAUTHORIZED,
                     "Invalid authorization header",
-                    "invalid_request"
+                    "invalid_request",
                 ));
             }
         } else {
             return Ok(create_error_response(
                 StatusCode::UNAUTHORIZED,
                 "Missing authorization header",
-                "invalid_request"
+                "invalid_request",
             ));
         }
     }
-    
+
     match (method, path) {
-        (&Method::GET, "/health") => {
-            Ok(response_builder
-                .status(StatusCode::OK)
-                .header("content-type", "application/json")
-                .body(json!({"status": "healthy", "service": "amazon-q-openai-server"}).to_string())
-                .unwrap())
-        },
-        
+        (&Method::GET, "/health") => Ok(response_builder
+            .status(StatusCode::OK)
+            .header("content-type", "application/json")
+            .body(json!({"status": "healthy", "service": "amazon-q-openai-server"}).to_string())
+            .unwrap()),
+
         (&Method::GET, "/v1/models") => {
             let state = state.lock().await;
             let models = ModelsResponse {
                 object: "list".to_string(),
-                data: vec![ModelInfo {
-                    id: state.model_name.clone(),
-                    object: "model".to_string(),
-                    created: std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    owned_by: "amazon".to_string(),
-                }],
+                data: state.list_models(),
             };
-            
+
             Ok(response_builder
                 .status(StatusCode::OK)
                 .header("content-type", "application/json")
                 .body(serde_json::to_string(&models).unwrap())
                 .unwrap())
         },
-        
-        (&Method::POST, "/v1/chat/completions") => {
-            handle_chat_completion(req, state).await
-        },
-        
-        _ => {
-            Ok(create_error_response(
-                StatusCode::NOT_FOUND,
-                "Endpoint not found",
-                "not_found"
-            ))
-        }
+
+        (&Method::POST, "/v1/chat/completions") => handle_chat_completion(req, state).await,
+
+        (&Method::GET, "/ws") => handle_websocket(req, state).await,
+
+        _ => Ok(create_error_response(
+            StatusCode::NOT_FOUND,
+            "Endpoint not found",
+            "not_found",
+        )),
     }
 }
 
@@ -332,133 +645,225 @@ async fn handle_chat_completion(
     state: Arc<Mutex<ServerState>>,
 ) -> Result<Response<String>, hyper::Error> {
     // Parse request body
-    let body_bytes = match http_body_util::BodyExt::collect(req.into_body()).await {
-        Ok(collected) => collected.to_bytes(),
+    let (max_body_bytes, request_timeout) = {
+        let state = state.lock().await;
+        (state.max_body_bytes, state.request_timeout)
+    };
+    let body_bytes = match with_deadline(request_timeout, read_body(req.into_body(), max_body_bytes)).await {
+        Ok(body_bytes) => body_bytes,
+        Err(response) => return Ok(response),
+    };
+
+    let body: serde_json::Value = match serde_json::from_slice(&body_bytes) {
+        Ok(body) => body,
         Err(e) => {
-            error!("Failed to read request body: {}", e);
+            error!("Failed to parse JSON: {}", e);
             return Ok(create_error_response(
                 StatusCode::BAD_REQUEST,
-                "Failed to read request body",
-                "invalid_request"
+                &format!("Invalid JSON: {}", e),
+                "invalid_request",
             ));
-        }
+        },
+    };
+
+    // An array of requests is handled as a batch, with a deadline for each of them
+    let body = match body {
+        serde_json::Value::Array(requests) => return handle_batch_completion(requests, state).await,
+        body => body,
     };
-    
-    let chat_request: ChatCompletionRequest = match serde_json::from_slice(&body_bytes) {
+
+    let chat_request: ChatCompletionRequest = match serde_json::from_value(body) {
         Ok(req) => req,
         Err(e) => {
             error!("Failed to parse JSON: {}", e);
             return Ok(create_error_response(
                 StatusCode::BAD_REQUEST,
                 &format!("Invalid JSON: {}", e),
-                "invalid_request"
+                "invalid_request",
             ));
-        }
+        },
     };
-    
+
     debug!("Chat completion request: {:?}", chat_request);
-    
+
     // Check if streaming is requested
     let is_streaming = chat_request.stream.unwrap_or(false);
-    
-    if is_streaming {
-        handle_streaming_completion(chat_request, state).await
-    } else {
-        handle_non_streaming_completion(chat_request, state).await
+
+    with_deadline(request_timeout, async {
+        if is_streaming {
+            Ok(handle_streaming_completion(chat_request, state).await)
+        } else {
+            Ok(handle_non_streaming_completion(chat_request, state).await)
+        }
+    })
+    .await
+    .unwrap_or_else(Ok)
+}
+
+/// Reads a request body of at most `max_body_bytes`. Errors are returned as the response to send
+/// back.
+async fn read_body<B>(body: B, max_body_bytes: usize) -> Result<hyper::body::Bytes, Response<String>>
+where
+    B: hyper::body::Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let body = http_body_util::Limited::new(body, max_body_bytes);
+    match http_body_util::BodyExt::collect(body).await {
+        Ok(collected) => Ok(collected.to_bytes()),
+        Err(e) if e.is::<http_body_util::LengthLimitError>() => Err(create_error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!("Request body exceeds the limit of {} bytes", max_body_bytes),
+            "invalid_request",
+        )),
+        Err(e) => {
+            error!("Failed to read request body: {}", e);
+            Err(create_error_response(
+                StatusCode::BAD_REQUEST,
+                "Failed to read request body",
+                "invalid_request",
+            ))
+        },
     }
 }
 
-async fn handle_non_streaming_completion(
-    chat_request: ChatCompletionRequest,
+/// Runs the requests of a batch with at most `batch_concurrency` of them in flight, and returns one
+/// NDJSON line per request, in the order of the requests.
+async fn handle_batch_completion(
+    requests: Vec<serde_json::Value>,
     state: Arc<Mutex<ServerState>>,
 ) -> Result<Response<String>, hyper::Error> {
-    // Convert messages to Amazon Q format
-    let user_message = if let Some(last_message) = chat_request.messages.last() {
-        if last_message.role == "user" {
-            extract_text_content(&last_message.content)
-        } else {
-            return Ok(create_error_response(
-                StatusCode::BAD_REQUEST,
-                "Last message must be from user",
-                "invalid_request"
-            ));
-        }
-    } else {
+    if requests.is_empty() {
         return Ok(create_error_response(
             StatusCode::BAD_REQUEST,
-            "No messages provided",
-            "invalid_request"
+            "Batch contains no requests",
+            "invalid_request",
         ));
+    }
+
+    let (concurrency, request_timeout) = {
+        let state = state.lock().await;
+        (state.batch_concurrency, state.request_timeout)
     };
-    
-    debug!("Extracted user message: {}", user_message);
-    
-    // Build conversation history
-    let mut history = Vec::new();
-    for (i, msg) in chat_request.messages.iter().enumerate() {
-        if i == chat_request.messages.len() - 1 {
-            break; // Skip the last message as it's the current user input
-        }
-        
-        match msg.role.as_str() {
-            "user" => {
-                history.push(crate::api_client::model::ChatMessage::UserInputMessage(
-                    UserInputMessage {
-                        content: extract_text_content(&msg.content),
-                        user_input_message_context: None,
-                        user_intent: None,
-                        images: None,
-                    }
-                ));
-            },
-            "assistant" => {
-                history.push(crate::api_client::model::ChatMessage::AssistantResponseMessage(
-                    crate::api_client::model::AssistantResponseMessage {
-                        message_id: None,
-                        content: extract_text_content(&msg.content),
-                        tool_uses: None,
-                    }
-                ));
-            },
-            _ => {
-                warn!("Unsupported message role: {}", msg.role);
-            }
-        }
+    debug!(
+        "Handling batch of {} requests with concurrency {}",
+        requests.len(),
+        concurrency
+    );
+
+    let mut results = futures::stream::iter(requests.into_iter().enumerate())
+        .map(|(index, request)| {
+            let state = Arc::clone(&state);
+            async move { (index, complete_batch_item(index, request, state, request_timeout).await) }
+        })
+        .buffer_unordered(concurrency)
+        .collect::<Vec<_>>()
+        .await;
+    results.sort_by_key(|(index, _)| *index);
+
+    let mut body = String::new();
+    for (_, result) in results {
+        body.push_str(&result.to_string());
+        body.push('\n');
     }
-    
-    debug!("History length: {}", history.len());
-    
-    // Create conversation state
-    let conversation_state = ConversationState {
-        conversation_id: None,
-        user_input_message: UserInputMessage {
-            content: user_message,
-            user_input_message_context: None,
-            user_intent: None,
-            images: None,
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/x-ndjson")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(body)
+        .unwrap())
+}
+
+/// Returns the NDJSON line of a single request of a batch. Failed requests, including the ones not
+/// done within `request_timeout`, get an `error` in the same format as a failed
+/// `/v1/chat/completions` request, instead of failing the whole batch.
+async fn complete_batch_item(
+    index: usize,
+    request: serde_json::Value,
+    state: Arc<Mutex<ServerState>>,
+    request_timeout: Duration,
+) -> serde_json::Value {
+    let result = match serde_json::from_value::<ChatCompletionRequest>(request) {
+        Ok(request) if request.stream.unwrap_or(false) => Err(create_error_response(
+            StatusCode::BAD_REQUEST,
+            "Streaming is not supported in batch requests",
+            "invalid_request",
+        )),
+        Ok(request) => with_deadline(request_timeout, complete_chat(request, state)).await,
+        Err(e) => Err(create_error_response(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid request: {}", e),
+            "invalid_request",
+        )),
+    };
+
+    match result {
+        Ok(response) => json!({
+            "index": index,
+            "status": StatusCode::OK.as_u16(),
+            "response": response,
+        }),
+        Err(response) => {
+            let error = serde_json::from_str::<serde_json::Value>(response.body())
+                .ok()
+                .and_then(|body| body.get("error").cloned());
+            json!({
+                "index": index,
+                "status": response.status().as_u16(),
+                "error": error,
+            })
         },
-        history: if history.is_empty() { None } else { Some(history) },
+    }
+}
+
+async fn handle_non_streaming_completion(
+    chat_request: ChatCompletionRequest,
+    state: Arc<Mutex<ServerState>>,
+) -> Result<Response<String>, hyper::Error> {
+    let completion_response = match complete_chat(chat_request, state).await {
+        Ok(completion_response) => completion_response,
+        Err(response) => return Ok(response),
     };
-    
-    // Send to Amazon Q
-    let state_guard = state.lock().await;
-    let response = match state_guard.client.send_message(conversation_state).await {
+
+    let response_json = serde_json::to_string(&completion_response).unwrap();
+    debug!("Response JSON: {}", response_json);
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(response_json)
+        .unwrap())
+}
+
+/// Sends a chat request upstream and collects the whole response. Errors are returned as the
+/// response to send back.
+async fn complete_chat(
+    chat_request: ChatCompletionRequest,
+    state: Arc<Mutex<ServerState>>,
+) -> Result<ChatCompletionResponse, Response<String>> {
+    let conversation_state = build_conversation_state(&chat_request)?;
+
+    // Send to the backend serving the requested model, without holding the lock so that other
+    // requests are not blocked while this one is in flight
+    let (model_name, client) = {
+        let state = state.lock().await;
+        let (model_name, client) = state.resolve_model(&chat_request.model);
+        (model_name, client.clone())
+    };
+    let response = match client.send_message(conversation_state).await {
         Ok(response) => response,
         Err(e) => {
             error!("Amazon Q API error: {}", e);
-            return Ok(create_error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                &format!("Amazon Q API error: {}", e),
-                "api_error"
-            ));
-        }
+            return Err(create_api_error_response("Amazon Q API error", &e));
+        },
     };
-    
+
     // Collect the streaming response
     let mut content = String::new();
     let mut response = response;
     let mut has_content = false;
-    
+
     loop {
         match response.recv().await {
             Ok(Some(event)) => {
@@ -476,48 +881,48 @@ async fn handle_non_streaming_completion(
                     },
                     crate::api_client::model::ChatResponseStream::InvalidStateEvent { reason, message } => {
                         error!("Invalid state event: {} - {}", reason, message);
-                        return Ok(create_error_response(
+                        return Err(create_error_response(
                             StatusCode::BAD_REQUEST,
                             &format!("Invalid state: {} - {}", reason, message),
-                            "invalid_state"
+                            "invalid_state",
                         ));
                     },
                     _ => {
                         debug!("Received other event type: {:?}", event);
-                    }
+                    },
                 }
             },
             Ok(None) => {
                 // Stream ended
-                debug!("Stream ended, has_content: {}, content length: {}", has_content, content.len());
+                debug!(
+                    "Stream ended, has_content: {}, content length: {}",
+                    has_content,
+                    content.len()
+                );
                 break;
             },
             Err(e) => {
                 error!("Stream error: {}", e);
-                return Ok(create_error_response(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    &format!("Stream error: {}", e),
-                    "stream_error"
-                ));
-            }
+                return Err(create_api_error_response("Stream error", &e));
+            },
         }
     }
-    
+
     // Ensure we have some content to return
     if content.is_empty() {
         warn!("No content received from Amazon Q, providing default response");
         content = "I apologize, but I wasn't able to generate a response. Please try again.".to_string();
     }
-    
+
     // Create OpenAI-compatible response
     let completion_response = ChatCompletionResponse {
-        id: format!("chatcmpl-{}", uuid::Uuid::new_v4().simple().to_string()),
+        id: format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
         object: "chat.completion".to_string(),
         created: std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs(),
-        model: state_guard.model_name.clone(),
+        model: model_name,
         choices: vec![Choice {
             index: 0,
             message: ChatMessage {
@@ -540,120 +945,45 @@ async fn handle_non_streaming_completion(
         prompt_logprobs: None,
         kv_transfer_params: None,
     };
-    
+
     debug!("Sending response with content length: {}", content.len());
-    let response_json = serde_json::to_string(&completion_response).unwrap();
-    debug!("Response JSON: {}", response_json);
-    
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("content-type", "application/json")
-        .header("Access-Control-Allow-Origin", "*")
-        .body(response_json)
-        .unwrap())
+    Ok(completion_response)
 }
 
 async fn handle_streaming_completion(
     chat_request: ChatCompletionRequest,
     state: Arc<Mutex<ServerState>>,
 ) -> Result<Response<String>, hyper::Error> {
-    // Convert messages to Amazon Q format (same as non-streaming)
-    let user_message = if let Some(last_message) = chat_request.messages.last() {
-        if last_message.role == "user" {
-            extract_text_content(&last_message.content)
-        } else {
-            return Ok(create_error_response(
-                StatusCode::BAD_REQUEST,
-                "Last message must be from user",
-                "invalid_request"
-            ));
-        }
-    } else {
-        return Ok(create_error_response(
-            StatusCode::BAD_REQUEST,
-            "No messages provided",
-            "invalid_request"
-        ));
-    };
-    
-    debug!("Extracted user message for streaming: {}", user_message);
-    
-    // Build conversation history
-    let mut history = Vec::new();
-    for (i, msg) in chat_request.messages.iter().enumerate() {
-        if i == chat_request.messages.len() - 1 {
-            break; // Skip the last message as it's the current user input
-        }
-        
-        match msg.role.as_str() {
-            "user" => {
-                history.push(crate::api_client::model::ChatMessage::UserInputMessage(
-                    UserInputMessage {
-                        content: extract_text_content(&msg.content),
-                        user_input_message_context: None,
-                        user_intent: None,
-                        images: None,
-                    }
-                ));
-            },
-            "assistant" => {
-                history.push(crate::api_client::model::ChatMessage::AssistantResponseMessage(
-                    crate::api_client::model::AssistantResponseMessage {
-                        message_id: None,
-                        content: extract_text_content(&msg.content),
-                        tool_uses: None,
-                    }
-                ));
-            },
-            _ => {
-                warn!("Unsupported message role: {}", msg.role);
-            }
-        }
-    }
-    
-    debug!("History length for streaming: {}", history.len());
-    
-    // Create conversation state
-    let conversation_state = ConversationState {
-        conversation_id: None,
-        user_input_message: UserInputMessage {
-            content: user_message,
-            user_input_message_context: None,
-            user_intent: None,
-            images: None,
-        },
-        history: if history.is_empty() { None } else { Some(history) },
+    let conversation_state = match build_conversation_state(&chat_request) {
+        Ok(conversation_state) => conversation_state,
+        Err(response) => return Ok(response),
     };
-    
-    // Send to Amazon Q
+
+    // Send to the backend serving the requested model
     let state_guard = state.lock().await;
-    let response = match state_guard.client.send_message(conversation_state).await {
+    let (model_name, client) = state_guard.resolve_model(&chat_request.model);
+    let response = match client.send_message(conversation_state).await {
         Ok(response) => response,
         Err(e) => {
             error!("Amazon Q API error: {}", e);
-            return Ok(create_error_response(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                &format!("Amazon Q API error: {}", e),
-                "api_error"
-            ));
-        }
+            return Ok(create_api_error_response("Amazon Q API error", &e));
+        },
     };
-    
-    let model_name = state_guard.model_name.clone();
+
     drop(state_guard); // Release the lock
-    
+
     // Create streaming response
-    let chat_id = format!("chatcmpl-{}", uuid::Uuid::new_v4().simple().to_string());
+    let chat_id = format!("chatcmpl-{}", uuid::Uuid::new_v4().simple());
     let created = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_secs();
-    
+
     // Build the streaming response body
     let mut streaming_body = String::new();
     let mut response = response;
     let mut is_first_chunk = true;
-    
+
     loop {
         match response.recv().await {
             Ok(Some(event)) => {
@@ -661,7 +991,7 @@ async fn handle_streaming_completion(
                 match event {
                     crate::api_client::model::ChatResponseStream::AssistantResponseEvent { content: text } => {
                         debug!("Streaming assistant response: {}", text);
-                        
+
                         let chunk = if is_first_chunk {
                             is_first_chunk = false;
                             ChatCompletionChunk {
@@ -702,13 +1032,13 @@ async fn handle_streaming_completion(
                                 service_tier: None,
                             }
                         };
-                        
+
                         let chunk_json = serde_json::to_string(&chunk).unwrap();
                         streaming_body.push_str(&format!("data: {}\n\n", chunk_json));
                     },
                     crate::api_client::model::ChatResponseStream::CodeEvent { content: code } => {
                         debug!("Streaming code event: {}", code);
-                        
+
                         let chunk = ChatCompletionChunk {
                             id: chat_id.clone(),
                             object: "chat.completion.chunk".to_string(),
@@ -717,7 +1047,11 @@ async fn handle_streaming_completion(
                             choices: vec![ChunkChoice {
                                 index: 0,
                                 delta: ChunkDelta {
-                                    role: if is_first_chunk { Some("assistant".to_string()) } else { None },
+                                    role: if is_first_chunk {
+                                        Some("assistant".to_string())
+                                    } else {
+                                        None
+                                    },
                                     content: Some(code),
                                     tool_calls: None,
                                     function_call: None,
@@ -727,11 +1061,11 @@ async fn handle_streaming_completion(
                             system_fingerprint: None,
                             service_tier: None,
                         };
-                        
+
                         if is_first_chunk {
                             is_first_chunk = false;
                         }
-                        
+
                         let chunk_json = serde_json::to_string(&chunk).unwrap();
                         streaming_body.push_str(&format!("data: {}\n\n", chunk_json));
                     },
@@ -740,12 +1074,12 @@ async fn handle_streaming_completion(
                         return Ok(create_error_response(
                             StatusCode::BAD_REQUEST,
                             &format!("Invalid state: {} - {}", reason, message),
-                            "invalid_state"
+                            "invalid_state",
                         ));
                     },
                     _ => {
                         debug!("Received other streaming event type: {:?}", event);
-                    }
+                    },
                 }
             },
             Ok(None) => {
@@ -769,7 +1103,7 @@ async fn handle_streaming_completion(
                     system_fingerprint: None,
                     service_tier: None,
                 };
-                
+
                 let final_chunk_json = serde_json::to_string(&final_chunk).unwrap();
                 streaming_body.push_str(&format!("data: {}\n\n", final_chunk_json));
                 streaming_body.push_str("data: [DONE]\n\n");
@@ -777,15 +1111,11 @@ async fn handle_streaming_completion(
             },
             Err(e) => {
                 error!("Streaming error: {}", e);
-                return Ok(create_error_response(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    &format!("Stream error: {}", e),
-                    "stream_error"
-                ));
-            }
+                return Ok(create_api_error_response("Stream error", &e));
+            },
         }
     }
-    
+
     // If no content was generated, provide a default response
     if is_first_chunk {
         warn!("No content received from Amazon Q in streaming mode, providing default response");
@@ -798,7 +1128,9 @@ async fn handle_streaming_completion(
                 index: 0,
                 delta: ChunkDelta {
                     role: Some("assistant".to_string()),
-                    content: Some("I apologize, but I wasn't able to generate a response. Please try again.".to_string()),
+                    content: Some(
+                        "I apologize, but I wasn't able to generate a response. Please try again.".to_string(),
+                    ),
                     tool_calls: None,
                     function_call: None,
                 },
@@ -807,14 +1139,14 @@ async fn handle_streaming_completion(
             system_fingerprint: None,
             service_tier: None,
         };
-        
+
         let default_chunk_json = serde_json::to_string(&default_chunk).unwrap();
         streaming_body.push_str(&format!("data: {}\n\n", default_chunk_json));
         streaming_body.push_str("data: [DONE]\n\n");
     }
-    
+
     debug!("Sending streaming response with {} bytes", streaming_body.len());
-    
+
     Ok(Response::builder()
         .status(StatusCode::OK)
         .header("content-type", "text/event-stream")
@@ -825,34 +1157,348 @@ async fn handle_streaming_completion(
         .unwrap())
 }
 
+/// Translates an OpenAI chat completion request into an Amazon Q conversation.
+///
+/// The last message becomes the current user input and the rest become history. System (and
+/// developer) messages have no equivalent in the Amazon Q API, so their content is sent as a
+/// context entry at the start of the history, the same way the chat client sends context files.
+#[allow(clippy::result_large_err)]
+fn build_conversation_state(chat_request: &ChatCompletionRequest) -> Result<ConversationState, Response<String>> {
+    let Some((last_message, previous_messages)) = chat_request.messages.split_last() else {
+        return Err(create_error_response(
+            StatusCode::BAD_REQUEST,
+            "No messages provided",
+            "invalid_request",
+        ));
+    };
+    if last_message.role != "user" {
+        return Err(create_error_response(
+            StatusCode::BAD_REQUEST,
+            "Last message must be from user",
+            "invalid_request",
+        ));
+    }
+
+    let user_message = extract_text_content(&last_message.content);
+    debug!("Extracted user message: {}", user_message);
+
+    let system_prompt = previous_messages
+        .iter()
+        .filter(|msg| is_system_role(&msg.role))
+        .map(|msg| extract_text_content(&msg.content))
+        .filter(|content| !content.trim().is_empty())
+        .collect::<Vec<_>>();
+
+    // Build conversation history
+    let mut history = Vec::new();
+    if !system_prompt.is_empty() {
+        history.push(crate::api_client::model::ChatMessage::UserInputMessage(
+            UserInputMessage {
+                content: format!(
+                    "{}{}\n{}",
+                    SYSTEM_CONTEXT_START_HEADER,
+                    system_prompt.join("\n\n"),
+                    SYSTEM_CONTEXT_END_HEADER
+                ),
+                user_input_message_context: None,
+                user_intent: None,
+                images: None,
+            },
+        ));
+        history.push(crate::api_client::model::ChatMessage::AssistantResponseMessage(
+            crate::api_client::model::AssistantResponseMessage {
+                message_id: None,
+                content: SYSTEM_CONTEXT_ACKNOWLEDGEMENT.to_string(),
+                tool_uses: None,
+            },
+        ));
+    }
+
+    for msg in previous_messages {
+        match msg.role.as_str() {
+            "user" => {
+                history.push(crate::api_client::model::ChatMessage::UserInputMessage(
+                    UserInputMessage {
+                        content: extract_text_content(&msg.content),
+                        user_input_message_context: None,
+                        user_intent: None,
+                        images: None,
+                    },
+                ));
+            },
+            "assistant" => {
+                history.push(crate::api_client::model::ChatMessage::AssistantResponseMessage(
+                    crate::api_client::model::AssistantResponseMessage {
+                        message_id: None,
+                        content: extract_text_content(&msg.content),
+                        tool_uses: None,
+                    },
+                ));
+            },
+            role if is_system_role(role) => {},
+            _ => {
+                warn!("Unsupported message role: {}", msg.role);
+            },
+        }
+    }
+
+    debug!("History length: {}", history.len());
+
+    Ok(ConversationState {
+        conversation_id: None,
+        user_input_message: UserInputMessage {
+            content: user_message,
+            user_input_message_context: None,
+            user_intent: None,
+            images: None,
+        },
+        history: if history.is_empty() { None } else { Some(history) },
+        response_schema: None,
+    })
+}
+
+fn is_system_role(role: &str) -> bool {
+    matches!(role, "system" | "developer")
+}
+
 fn extract_text_content(content: &ChatMessageContent) -> String {
     match content {
         ChatMessageContent::Text(text) => text.clone(),
-        ChatMessageContent::Parts(parts) => {
-            parts.iter()
-                .filter_map(|part| {
-                    if part.part_type == "text" {
-                        part.text.as_ref()
-                    } else {
-                        None
-                    }
-                })
-                .cloned()
-                .collect::<Vec<_>>()
-                .join("\n")
+        ChatMessageContent::Parts(parts) => parts
+            .iter()
+            .filter_map(|part| {
+                if part.part_type == "text" {
+                    part.text.as_ref()
+                } else {
+                    None
+                }
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// Upgrades a request to `/ws` to a WebSocket running an [AgentSession]. Each text frame holds a
+/// JSON [ClientMessage] from the client or an event of the versioned [event_schema] from the
+/// server.
+///
+/// Requests from a browser page of another origin are rejected, so that websites can't drive the
+/// agent of a server running without an API key. Without an API key, requests must also name the
+/// server by a loopback or the bound address, as a website can point its own domain name at the
+/// server to pass as the same origin.
+async fn handle_websocket(
+    req: Request<hyper::body::Incoming>,
+    state: Arc<Mutex<ServerState>>,
+) -> Result<Response<String>, hyper::Error> {
+    let (agent, api_key, addr) = {
+        let state = state.lock().await;
+        (state.agent, state.api_key.is_some(), state.addr)
+    };
+    if !agent {
+        return Ok(create_error_response(
+            StatusCode::NOT_FOUND,
+            "Endpoint not found, start the server with --agent to enable it",
+            "not_found",
+        ));
+    }
+
+    let header = |name: hyper::header::HeaderName| req.headers().get(name).and_then(|value| value.to_str().ok());
+
+    if !header(hyper::header::UPGRADE).is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket")) {
+        return Ok(create_error_response(
+            StatusCode::UPGRADE_REQUIRED,
+            "Expected a WebSocket upgrade request",
+            "invalid_request",
+        ));
+    }
+    let Some(key) = header(hyper::header::SEC_WEBSOCKET_KEY) else {
+        return Ok(create_error_response(
+            StatusCode::BAD_REQUEST,
+            "Missing Sec-WebSocket-Key header",
+            "invalid_request",
+        ));
+    };
+    if !api_key && !is_local_host(header(hyper::header::HOST), &addr) {
+        warn!(
+            "Rejected a WebSocket request for host {}",
+            header(hyper::header::HOST).unwrap_or_default()
+        );
+        return Ok(create_error_response(
+            StatusCode::FORBIDDEN,
+            "WebSocket requests must address the server by IP address or localhost without an API key",
+            "invalid_request",
+        ));
+    }
+    if let Some(origin) = header(hyper::header::ORIGIN) {
+        if !is_same_origin(origin, header(hyper::header::HOST)) {
+            warn!("Rejected a WebSocket request from origin {}", origin);
+            return Ok(create_error_response(
+                StatusCode::FORBIDDEN,
+                "Cross-origin WebSocket requests are not allowed",
+                "invalid_request",
+            ));
         }
     }
+    let accept = derive_accept_key(key.as_bytes());
+
+    // `/ws?model=...` picks the model like the `model` field of a chat completion request
+    let model = req.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .filter_map(|kv| kv.split_once('='))
+            .find_map(|(key, value)| (key == "model").then(|| value.to_string()))
+    });
+    let client = {
+        let state = state.lock().await;
+        match model {
+            Some(model) => state.resolve_model(&model).1.clone(),
+            None => state.client.clone(),
+        }
+    };
+
+    tokio::spawn(async move {
+        let upgraded = match hyper::upgrade::on(req).await {
+            Ok(upgraded) => upgraded,
+            Err(err) => {
+                error!("WebSocket upgrade failed: {:?}", err);
+                return;
+            },
+        };
+        let socket = WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+        if let Err(err) = run_websocket_session(socket, client).await {
+            error!("WebSocket session failed: {:?}", err);
+        }
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(hyper::header::CONNECTION, "Upgrade")
+        .header(hyper::header::UPGRADE, "websocket")
+        .header(hyper::header::SEC_WEBSOCKET_ACCEPT, accept)
+        .body(String::new())
+        .unwrap())
+}
+
+fn is_same_origin(origin: &str, host: Option<&str>) -> bool {
+    let origin_host = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+        .unwrap_or(origin);
+    host.is_some_and(|host| host.eq_ignore_ascii_case(origin_host))
+}
+
+/// Refuses to serve the agent beyond this machine without an API key, as anyone who can reach it
+/// could run tools.
+fn check_agent_addr(addr: &SocketAddr, api_key: bool) -> Result<()> {
+    if !api_key && !addr.ip().is_loopback() {
+        eyre::bail!(
+            "--agent runs tools on this machine, set --api-key to serve it on {}",
+            addr.ip()
+        );
+    }
+    Ok(())
+}
+
+/// Whether `host`, the Host header of a request, names the server bound to `addr` by `localhost`,
+/// a loopback address or the bound address, rather than by a domain name that could resolve to
+/// anything. Any address names a server bound to all of them, so none is accepted for it beyond
+/// loopback ones.
+fn is_local_host(host: Option<&str>, addr: &SocketAddr) -> bool {
+    let Some(authority) = host.and_then(|host| host.parse::<hyper::http::uri::Authority>().ok()) else {
+        return false;
+    };
+    let hostname = authority.host().trim_start_matches('[').trim_end_matches(']');
+    if hostname.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    hostname
+        .parse::<std::net::IpAddr>()
+        .is_ok_and(|ip| ip.is_loopback() || (ip == addr.ip() && !ip.is_unspecified()))
+}
+
+/// Pumps messages between the socket and the session until either side goes away.
+async fn run_websocket_session<S>(socket: WebSocketStream<S>, client: StreamingClient) -> Result<()>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let database = Database::new().await?;
+    let session = AgentSession::new(Context::new(), &database, client).await?;
+    let (mut sink, mut stream) = socket.split();
+    let (message_tx, message_rx) = mpsc::channel(16);
+    let (event_tx, mut event_rx) = mpsc::channel(64);
+
+    let read = {
+        let event_tx = event_tx.clone();
+        async move {
+            while let Some(frame) = stream.next().await {
+                match frame? {
+                    Message::Text(text) => match serde_json::from_str::<ClientMessage>(text.as_str()) {
+                        Ok(message) => {
+                            if message_tx.send(message).await.is_err() {
+                                break;
+                            }
+                        },
+                        Err(err) => {
+                            let message = format!("Invalid message: {}", err);
+                            let _ = event_tx.send(AgentEvent::Error { message, code: None }).await;
+                        },
+                    },
+                    Message::Close(_) => break,
+                    // Pings are answered by tungstenite
+                    _ => {},
+                }
+            }
+            Ok::<_, eyre::Report>(())
+        }
+    };
+    let write = async move {
+        while let Some(event) = event_rx.recv().await {
+            for event in event_schema::versioned(event) {
+                sink.send(Message::text(serde_json::to_string(&event)?)).await?;
+            }
+        }
+        sink.close().await?;
+        Ok::<_, eyre::Report>(())
+    };
+
+    let (read, write, ()) = tokio::join!(read, write, session.run(message_rx, event_tx));
+    if let Err(err) = read.and(write) {
+        debug!("WebSocket closed: {:?}", err);
+    }
+    Ok(())
 }
 
 fn create_error_response(status: StatusCode, message: &str, error_type: &str) -> Response<String> {
+    error_response(status, message, error_type, None)
+}
+
+/// Creates the response for an error of the backend, with the machine-readable code of its
+/// [ErrorKind] and the hint telling the user how to fix it.
+fn create_api_error_response(prefix: &str, err: &ApiClientError) -> Response<String> {
+    let kind = err.kind();
+    let status = match kind {
+        ErrorKind::QuotaExceeded => StatusCode::TOO_MANY_REQUESTS,
+        ErrorKind::ContextOverflow => StatusCode::BAD_REQUEST,
+        ErrorKind::AuthExpired | ErrorKind::Network | ErrorKind::ProviderMisconfigured => StatusCode::BAD_GATEWAY,
+        ErrorKind::Service => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let message = match kind.hint() {
+        Some(hint) => format!("{}: {} ({})", prefix, err, hint),
+        None => format!("{}: {}", prefix, err),
+    };
+    error_response(status, &message, "api_error", Some(kind.code()))
+}
+
+fn error_response(status: StatusCode, message: &str, error_type: &str, code: Option<&str>) -> Response<String> {
     let error_response = ErrorResponse {
         error: ErrorDetail {
             message: message.to_string(),
             error_type: error_type.to_string(),
-            code: None,
+            code: code.map(str::to_string),
         },
     };
-    
+
     Response::builder()
         .status(status)
         .header("content-type", "application/json")
@@ -860,3 +1506,241 @@ fn create_error_response(status: StatusCode, message: &str, error_type: &str) ->
         .body(serde_json::to_string(&error_response).unwrap())
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_state() -> ServerState {
+        ServerState {
+            client: StreamingClient::mock(vec![]),
+            model_name: "amazon-q".to_string(),
+            api_key: None,
+            models: vec![ModelRoute {
+                id: "gpt-4o".to_string(),
+                owned_by: "openai".to_string(),
+                client: StreamingClient::mock(vec![]),
+            }],
+            aliases: HashMap::from([
+                ("fast".to_string(), "gpt-4o".to_string()),
+                ("default".to_string(), "amazon-q".to_string()),
+            ]),
+            max_body_bytes: 1024,
+            request_timeout: Duration::from_secs(10),
+            batch_concurrency: 2,
+            agent: false,
+            addr: SocketAddr::from(([127, 0, 0, 1], 8080)),
+        }
+    }
+
+    #[test]
+    fn test_parse_model_alias() {
+        assert_eq!(
+            parse_model_alias("fast=gpt-4o").unwrap(),
+            ("fast".to_string(), "gpt-4o".to_string())
+        );
+        assert!(parse_model_alias("fast").is_err());
+        assert!(parse_model_alias("=gpt-4o").is_err());
+    }
+
+    #[test]
+    fn test_resolve_model() {
+        let state = test_state();
+        assert_eq!(state.resolve_model("gpt-4o").0, "gpt-4o");
+        assert_eq!(state.resolve_model("fast").0, "gpt-4o");
+        assert_eq!(state.resolve_model("default").0, "amazon-q");
+        assert_eq!(state.resolve_model("unknown").0, "amazon-q");
+    }
+
+    #[test]
+    fn test_list_models() {
+        let models = test_state().list_models();
+        let ids = models
+            .iter()
+            .map(|m| (m.id.as_str(), m.owned_by.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![
+            ("amazon-q", "amazon"),
+            ("gpt-4o", "openai"),
+            ("default", "amazon"),
+            ("fast", "openai"),
+        ]);
+    }
+
+    #[test]
+    fn test_build_conversation_state_system_prompt() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "amazon-q",
+            "messages": [
+                {"role": "system", "content": "You are a pirate."},
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Ahoy!"},
+                {"role": "user", "content": "Where is the treasure?"}
+            ]
+        }))
+        .unwrap();
+
+        let state = build_conversation_state(&request).unwrap();
+        assert_eq!(state.user_input_message.content, "Where is the treasure?");
+        let history = state.history.unwrap();
+        assert_eq!(history.len(), 4);
+        match &history[0] {
+            crate::api_client::model::ChatMessage::UserInputMessage(msg) => {
+                assert!(msg.content.starts_with(SYSTEM_CONTEXT_START_HEADER));
+                assert!(msg.content.contains("You are a pirate."));
+            },
+            other @ crate::api_client::model::ChatMessage::AssistantResponseMessage(_) => {
+                panic!("expected system context message, found {:?}", other)
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_completion() {
+        let mut state = test_state();
+        state.client = StreamingClient::mock(vec![
+            vec![crate::api_client::model::ChatResponseStream::AssistantResponseEvent {
+                content: "one".to_string(),
+            }],
+            vec![crate::api_client::model::ChatResponseStream::AssistantResponseEvent {
+                content: "two".to_string(),
+            }],
+        ]);
+        let requests = vec![
+            json!({"model": "amazon-q", "messages": [{"role": "user", "content": "1"}]}),
+            json!({"model": "amazon-q", "messages": []}),
+            json!({"model": "amazon-q", "messages": [{"role": "user", "content": "2"}], "stream": true}),
+            json!({"model": "amazon-q", "messages": [{"role": "user", "content": "3"}]}),
+        ];
+
+        let response = handle_batch_completion(requests, Arc::new(Mutex::new(state)))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+        let lines = response
+            .body()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        for (index, line) in lines.iter().enumerate() {
+            assert_eq!(line["index"], index, "results are in request order");
+        }
+        assert_eq!(lines[0]["status"], 200);
+        assert_eq!(lines[0]["response"]["object"], "chat.completion");
+        assert_eq!(lines[1]["status"], 400);
+        assert_eq!(
+            lines[2]["error"]["message"],
+            "Streaming is not supported in batch requests"
+        );
+        assert_eq!(lines[3]["status"], 200);
+    }
+
+    #[tokio::test]
+    async fn test_empty_batch_is_rejected() {
+        let response = handle_batch_completion(vec![], Arc::new(Mutex::new(test_state())))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body_is_rejected() {
+        let body = http_body_util::Full::new(hyper::body::Bytes::from(vec![b'a'; 2048]));
+        let response = read_body(body, 1024).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let body = http_body_util::Full::new(hyper::body::Bytes::from_static(b"{}"));
+        assert_eq!(read_body(body, 1024).await.unwrap(), "{}");
+    }
+
+    #[tokio::test]
+    async fn test_slow_request_times_out() {
+        let response = with_deadline::<()>(Duration::from_millis(10), std::future::pending())
+            .await
+            .unwrap_err();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        assert_eq!(
+            with_deadline(Duration::from_secs(10), async { Ok(1) }).await.unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_item_times_out() {
+        let state = Arc::new(Mutex::new(test_state()));
+        let request = json!({"model": "amazon-q", "messages": [{"role": "user", "content": "1"}]});
+
+        // The request can't get hold of the client while the state is locked
+        let _guard = state.lock().await;
+        let line = complete_batch_item(3, request, Arc::clone(&state), Duration::from_millis(10)).await;
+        assert_eq!(line["index"], 3);
+        assert_eq!(line["status"], 504);
+        assert_eq!(line["error"]["type"], "timeout");
+    }
+
+    #[test]
+    fn test_is_same_origin() {
+        assert!(is_same_origin("http://127.0.0.1:8080", Some("127.0.0.1:8080")));
+        assert!(is_same_origin("https://q.example.com", Some("Q.example.com")));
+        assert!(!is_same_origin("https://evil.example.com", Some("127.0.0.1:8080")));
+        assert!(!is_same_origin("http://127.0.0.1:8080", None));
+    }
+
+    #[test]
+    fn test_is_local_host() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
+        assert!(is_local_host(Some("127.0.0.1:8080"), &addr));
+        assert!(is_local_host(Some("localhost:8080"), &addr));
+        assert!(is_local_host(Some("[::1]:8080"), &addr));
+        assert!(!is_local_host(Some("192.168.1.5:8080"), &addr));
+        assert!(!is_local_host(None, &addr));
+
+        // A page of a domain rebound to the server is of the same origin, but names a domain
+        let (origin, host) = ("http://rebind.attacker.example:8080", "rebind.attacker.example:8080");
+        assert!(is_same_origin(origin, Some(host)));
+        assert!(!is_local_host(Some(host), &addr));
+
+        let addr = SocketAddr::from(([192, 168, 1, 5], 8080));
+        assert!(is_local_host(Some("192.168.1.5:8080"), &addr));
+        assert!(!is_local_host(Some(host), &addr));
+
+        let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
+        assert!(is_local_host(Some("127.0.0.1:8080"), &addr));
+        assert!(!is_local_host(Some("192.168.1.5:8080"), &addr));
+        assert!(!is_local_host(Some("0.0.0.0:8080"), &addr));
+    }
+
+    #[test]
+    fn test_check_agent_addr() {
+        assert!(check_agent_addr(&SocketAddr::from(([127, 0, 0, 1], 8080)), false).is_ok());
+        assert!(check_agent_addr(&"[::1]:8080".parse().unwrap(), false).is_ok());
+        assert!(check_agent_addr(&SocketAddr::from(([0, 0, 0, 0], 8080)), false).is_err());
+        assert!(check_agent_addr(&"[::]:8080".parse().unwrap(), false).is_err());
+        assert!(check_agent_addr(&SocketAddr::from(([192, 168, 1, 5], 8080)), false).is_err());
+        assert!(check_agent_addr(&SocketAddr::from(([0, 0, 0, 0], 8080)), true).is_ok());
+    }
+
+    #[test]
+    fn test_api_error_response_has_code() {
+        let response = create_api_error_response("Amazon Q API error", &ApiClientError::ContextWindowOverflow);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(body["error"]["code"], "context_overflow");
+        assert!(body["error"]["message"].as_str().unwrap().contains("/compact"));
+    }
+
+    #[test]
+    fn test_build_conversation_state_requires_user_message() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "amazon-q",
+            "messages": [{"role": "system", "content": "You are a pirate."}]
+        }))
+        .unwrap();
+        assert_eq!(
+            build_conversation_state(&request).unwrap_err().status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
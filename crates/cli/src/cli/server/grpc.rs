@@ -0,0 +1,410 @@
+//! gRPC interface of `q server`, serving the agentic chat sessions of
+//! [crate::cli::chat::agent_session] to IDE plugins and other local processes. See
+//! `proto/chat.proto` for the services.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use tokio::sync::{
+    Mutex,
+    mpsc,
+    watch,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{
+    Request,
+    Response,
+    Status,
+};
+use tracing::info;
+
+use super::ServerState;
+use crate::cli::chat::agent_session::{
+    AgentEvent,
+    AgentSession,
+    ClientMessage,
+};
+use crate::database::Database;
+use crate::platform::Context;
+
+mod proto {
+    tonic::include_proto!("q.chat.v1");
+}
+
+use proto::chat_event::Event;
+use proto::chat_server::{
+    Chat,
+    ChatServer,
+};
+use proto::session_management_server::{
+    SessionManagement,
+    SessionManagementServer,
+};
+use proto::tool_approval_server::{
+    ToolApproval,
+    ToolApprovalServer,
+};
+
+/// Approvals sent ahead of the tool use waiting for them.
+const MAX_PENDING_APPROVALS: usize = 16;
+
+/// Sessions open at the same time, as clients may go away without closing theirs.
+const MAX_SESSIONS: usize = 32;
+
+/// Sessions without a turn for this long are closed.
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+struct SessionHandle {
+    model: String,
+    approvals: mpsc::Sender<ClientMessage>,
+    /// Locked while a turn runs.
+    inner: Arc<Mutex<SessionInner>>,
+}
+
+struct SessionInner {
+    agent: AgentSession,
+    approvals: mpsc::Receiver<ClientMessage>,
+    /// When the session was created or its last turn ended.
+    last_used: Instant,
+}
+
+#[derive(Clone)]
+struct GrpcService {
+    state: Arc<Mutex<ServerState>>,
+    sessions: Arc<Mutex<HashMap<String, SessionHandle>>>,
+}
+
+impl GrpcService {
+    async fn session(&self, id: &str) -> Result<(mpsc::Sender<ClientMessage>, Arc<Mutex<SessionInner>>), Status> {
+        let sessions = self.sessions.lock().await;
+        let handle = sessions
+            .get(id)
+            .ok_or_else(|| Status::not_found(format!("No session with id '{id}'")))?;
+        Ok((handle.approvals.clone(), Arc::clone(&handle.inner)))
+    }
+}
+
+/// Closes the sessions idle for longer than `timeout`, leaving the ones running a turn.
+fn evict_idle(sessions: &mut HashMap<String, SessionHandle>, timeout: Duration) {
+    sessions.retain(|id, handle| match handle.inner.try_lock() {
+        Ok(inner) if inner.last_used.elapsed() >= timeout => {
+            info!("Closing the idle gRPC session {}", id);
+            false
+        },
+        _ => true,
+    });
+}
+
+/// Serves the gRPC services on `addr` until `shutdown` changes.
+pub async fn serve(
+    addr: SocketAddr,
+    state: Arc<Mutex<ServerState>>,
+    mut shutdown: watch::Receiver<bool>,
+) -> eyre::Result<()> {
+    let api_key = state.lock().await.api_key.clone();
+    let service = GrpcService {
+        state,
+        sessions: Default::default(),
+    };
+    let sweep = {
+        let sessions = Arc::clone(&service.sessions);
+        async move {
+            let mut interval = tokio::time::interval(SESSION_IDLE_TIMEOUT / 4);
+            loop {
+                interval.tick().await;
+                evict_idle(&mut *sessions.lock().await, SESSION_IDLE_TIMEOUT);
+            }
+        }
+    };
+
+    info!("🔌 gRPC interface running on {}", addr);
+    let server = tonic::transport::Server::builder()
+        .add_service(SessionManagementServer::with_interceptor(
+            service.clone(),
+            check_api_key(api_key.clone()),
+        ))
+        .add_service(ChatServer::with_interceptor(
+            service.clone(),
+            check_api_key(api_key.clone()),
+        ))
+        .add_service(ToolApprovalServer::with_interceptor(service, check_api_key(api_key)))
+        .serve_with_shutdown(addr, async move {
+            let _ = shutdown.changed().await;
+        });
+    tokio::select! {
+        result = server => result?,
+        () = sweep => {},
+    }
+    Ok(())
+}
+
+/// Requires the `authorization: Bearer <api key>` metadata when the server has an API key.
+fn check_api_key(api_key: Option<String>) -> impl Fn(Request<()>) -> Result<Request<()>, Status> + Clone {
+    move |request| {
+        let Some(api_key) = &api_key else {
+            return Ok(request);
+        };
+        match request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(value) if value.strip_prefix("Bearer ") == Some(api_key.as_str()) => Ok(request),
+            Some(_) => Err(Status::unauthenticated("Invalid API key")),
+            None => Err(Status::unauthenticated("Missing authorization metadata")),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl SessionManagement for GrpcService {
+    async fn create_session(
+        &self,
+        request: Request<proto::CreateSessionRequest>,
+    ) -> Result<Response<proto::Session>, Status> {
+        let (model, client) = {
+            let state = self.state.lock().await;
+            let requested = request.into_inner().model.unwrap_or_else(|| state.model_name.clone());
+            let (model, client) = state.resolve_model(&requested);
+            (model, client.clone())
+        };
+
+        let database = Database::new()
+            .await
+            .map_err(|err| Status::internal(format!("Failed to open the database: {err}")))?;
+        let agent = AgentSession::new(Context::new(), &database, client)
+            .await
+            .map_err(|err| Status::internal(format!("Failed to create the session: {err}")))?;
+
+        let id = agent.conversation_id().to_string();
+        let (approvals_tx, approvals_rx) = mpsc::channel(MAX_PENDING_APPROVALS);
+        let mut sessions = self.sessions.lock().await;
+        evict_idle(&mut sessions, SESSION_IDLE_TIMEOUT);
+        if sessions.len() >= MAX_SESSIONS {
+            return Err(Status::resource_exhausted(format!(
+                "Too many open sessions, close one first (at most {MAX_SESSIONS})"
+            )));
+        }
+        sessions.insert(id.clone(), SessionHandle {
+            model: model.clone(),
+            approvals: approvals_tx,
+            inner: Arc::new(Mutex::new(SessionInner {
+                agent,
+                approvals: approvals_rx,
+                last_used: Instant::now(),
+            })),
+        });
+
+        Ok(Response::new(proto::Session { id, model, busy: false }))
+    }
+
+    async fn list_sessions(
+        &self,
+        _request: Request<proto::ListSessionsRequest>,
+    ) -> Result<Response<proto::ListSessionsResponse>, Status> {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .await
+            .iter()
+            .map(|(id, handle)| proto::Session {
+                id: id.clone(),
+                model: handle.model.clone(),
+                busy: handle.inner.try_lock().is_err(),
+            })
+            .collect::<Vec<_>>();
+        sessions.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(Response::new(proto::ListSessionsResponse { sessions }))
+    }
+
+    async fn close_session(
+        &self,
+        request: Request<proto::CloseSessionRequest>,
+    ) -> Result<Response<proto::CloseSessionResponse>, Status> {
+        let id = request.into_inner().session_id;
+        match self.sessions.lock().await.remove(&id) {
+            Some(_) => Ok(Response::new(proto::CloseSessionResponse {})),
+            None => Err(Status::not_found(format!("No session with id '{id}'"))),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Chat for GrpcService {
+    type SendMessageStream = ReceiverStream<Result<proto::ChatEvent, Status>>;
+
+    async fn send_message(
+        &self,
+        request: Request<proto::SendMessageRequest>,
+    ) -> Result<Response<Self::SendMessageStream>, Status> {
+        let request = request.into_inner();
+        let (_, inner) = self.session(&request.session_id).await?;
+        let mut inner = inner
+            .try_lock_owned()
+            .map_err(|_err| Status::failed_precondition("A turn is already running in this session"))?;
+
+        let (tx, rx) = mpsc::channel(64);
+        tokio::spawn(async move {
+            let SessionInner {
+                agent,
+                approvals,
+                last_used,
+            } = &mut *inner;
+            // Approvals sent while no turn was running can't be for this turn.
+            while approvals.try_recv().is_ok() {}
+
+            let (event_tx, mut event_rx) = mpsc::channel(64);
+            let turn = async move { agent.run_turn(request.content, approvals, &event_tx).await };
+            let forward = async {
+                while let Some(event) = event_rx.recv().await {
+                    if let Some(event) = to_proto(event) {
+                        if tx.send(Ok(event)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            };
+
+            tokio::select! {
+                (result, ()) = async { tokio::join!(turn, forward) } => {
+                    if let Err(err) = result {
+                        let event = proto::ChatEvent {
+                            event: Some(Event::Error(err.to_string())),
+                        };
+                        let _ = tx.send(Ok(event)).await;
+                    }
+                },
+                // The client dropped the stream, e.g. it disconnected, so no one can approve the
+                // tools of the turn or read its answer.
+                () = tx.closed() => {},
+            }
+            *last_used = Instant::now();
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+#[tonic::async_trait]
+impl ToolApproval for GrpcService {
+    async fn approve(
+        &self,
+        request: Request<proto::ToolApprovalRequest>,
+    ) -> Result<Response<proto::ToolApprovalResponse>, Status> {
+        let request = request.into_inner();
+        let (approvals, _) = self.session(&request.session_id).await?;
+        approvals
+            .try_send(ClientMessage::ToolApproval {
+                id: request.tool_use_id,
+                approved: request.approved,
+            })
+            .map_err(|_err| Status::resource_exhausted("Too many pending approvals"))?;
+        Ok(Response::new(proto::ToolApprovalResponse {}))
+    }
+}
+
+/// Converts an event of a turn, returning [None] for the events that only make sense for the
+/// WebSocket protocol, where the end of a turn isn't the end of a stream.
+fn to_proto(event: AgentEvent) -> Option<proto::ChatEvent> {
+    let event = match event {
+        AgentEvent::Text { content } => Event::Text(content),
+        AgentEvent::ToolUse {
+            id,
+            name,
+            input,
+            requires_approval,
+            ..
+        } => Event::ToolUse(proto::ToolUse {
+            id,
+            name,
+            input_json: input.to_string(),
+            requires_approval,
+        }),
+        AgentEvent::ToolResult { id, success, content } => Event::ToolResult(proto::ToolResult {
+            tool_use_id: id,
+            status: match success {
+                true => proto::ToolResultStatus::Success,
+                false => proto::ToolResultStatus::Error,
+            }
+            .into(),
+            content,
+        }),
+        AgentEvent::Error { message, .. } => Event::Error(message),
+        AgentEvent::TurnStart { .. } | AgentEvent::Done => return None,
+    };
+    Some(proto::ChatEvent { event: Some(event) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_client::StreamingClient;
+
+    #[test]
+    fn test_to_proto() {
+        let event = to_proto(AgentEvent::ToolResult {
+            id: "1".to_string(),
+            success: false,
+            content: "denied".to_string(),
+        })
+        .unwrap();
+        let Some(Event::ToolResult(result)) = event.event else {
+            panic!("expected a tool result, got {:?}", event);
+        };
+        assert_eq!(result.status(), proto::ToolResultStatus::Error);
+        assert_eq!(to_proto(AgentEvent::Done), None);
+    }
+
+    #[tokio::test]
+    async fn test_evict_idle() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let database = Database::new().await.unwrap();
+        let mut sessions = HashMap::new();
+        for id in ["idle", "busy", "recent"] {
+            let agent = AgentSession::new(Arc::clone(&ctx), &database, StreamingClient::mock(vec![]))
+                .await
+                .unwrap();
+            let (approvals, approvals_rx) = mpsc::channel(1);
+            let last_used = match id {
+                "recent" => Instant::now(),
+                _ => Instant::now() - Duration::from_secs(120),
+            };
+            sessions.insert(id.to_string(), SessionHandle {
+                model: "amazon-q".to_string(),
+                approvals,
+                inner: Arc::new(Mutex::new(SessionInner {
+                    agent,
+                    approvals: approvals_rx,
+                    last_used,
+                })),
+            });
+        }
+
+        let busy = Arc::clone(&sessions["busy"].inner);
+        let _turn = busy.lock().await;
+        evict_idle(&mut sessions, Duration::from_secs(60));
+        let mut ids = sessions.keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec!["busy", "recent"]);
+    }
+
+    #[test]
+    fn test_check_api_key() {
+        let check = check_api_key(Some("secret".to_string()));
+        let mut request = Request::new(());
+        assert_eq!(
+            check(Request::new(())).unwrap_err().code(),
+            tonic::Code::Unauthenticated
+        );
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        assert!(check(request).is_ok());
+        assert!(check_api_key(None)(Request::new(())).is_ok());
+    }
+}
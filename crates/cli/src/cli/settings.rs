@@ -1,18 +1,26 @@
 use std::process::ExitCode;
 
-use anstream::println;
+use anstream::{
+    eprintln,
+    println,
+};
 use clap::{
     ArgGroup,
     Args,
     Subcommand,
 };
+use crossterm::style::Stylize;
 use eyre::{
     Result,
     WrapErr,
     bail,
 };
 use globset::Glob;
-use serde_json::json;
+use serde_json::{
+    Value,
+    json,
+};
+use strum::IntoEnumIterator;
 
 use super::OutputFormat;
 use crate::database::Database;
@@ -26,6 +34,12 @@ use crate::util::{
 pub enum SettingsSubcommands {
     /// Open the settings file
     Open,
+    /// List the available settings with their type, default, current value and origin
+    List {
+        /// Format of the output
+        #[arg(long, short, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
     /// List all the settings
     All {
         /// Format of the output
@@ -68,10 +82,14 @@ impl SettingsArgs {
                     bail!("The EDITOR environment variable is not set")
                 }
             },
+            Some(SettingsSubcommands::List { format }) => {
+                list_settings(database, format)?;
+                Ok(ExitCode::SUCCESS)
+            },
             Some(SettingsSubcommands::All { format, state }) => {
                 let settings = match state {
                     true => database.get_all_entries()?,
-                    false => database.settings.map().clone(),
+                    false => database.settings.effective_map(),
                 };
 
                 match format {
@@ -93,7 +111,12 @@ impl SettingsArgs {
                     return Ok(ExitCode::SUCCESS);
                 };
 
-                let key = Setting::try_from(key.as_str())?;
+                let name = key;
+                let key = Setting::try_from(name.as_str())?;
+                if name != key.as_ref() {
+                    eprintln!("`{name}` is deprecated, use `{key}` instead");
+                }
+
                 match (&self.value, self.delete) {
                     (None, false) => match database.settings.get(key) {
                         Some(value) => {
@@ -116,7 +139,7 @@ impl SettingsArgs {
                         },
                     },
                     (Some(value_str), false) => {
-                        let value = serde_json::from_str(value_str).unwrap_or_else(|_| json!(value_str));
+                        let value = key.parse_value(value_str)?;
                         database.settings.set(key, value).await?;
                         Ok(ExitCode::SUCCESS)
                     },
@@ -156,3 +179,61 @@ impl SettingsArgs {
         }
     }
 }
+
+fn list_settings(database: &Database, format: OutputFormat) -> Result<()> {
+    let settings = Setting::iter().filter(|setting| !setting.is_internal());
+
+    match format {
+        OutputFormat::Plain => {
+            for setting in settings {
+                let value = match database.settings.get(setting) {
+                    Some(value) => {
+                        let source = database.settings.source(setting);
+                        format!(
+                            "{} {}",
+                            display_value(setting, value),
+                            format!("({source})").dark_grey()
+                        )
+                    },
+                    None => "not set".dark_grey().to_string(),
+                };
+                println!("{} = {value}", setting.as_ref().bold());
+                println!("    {}", setting.description());
+                match setting.default_value() {
+                    Some(default) => println!("    {}, default {default}", setting.value_type()),
+                    None => println!("    {}", setting.value_type()),
+                }
+                println!();
+            }
+        },
+        OutputFormat::Json | OutputFormat::JsonPretty => {
+            let settings = settings
+                .map(|setting| {
+                    json!({
+                        "key": setting.as_ref(),
+                        "description": setting.description(),
+                        "type": setting.value_type().to_string(),
+                        "default": setting.default_value(),
+                        "value": database.settings.get(setting).map(|value| display_value(setting, value)),
+                        "source": database.settings.source(setting).to_string(),
+                        "deprecatedAliases": setting.deprecated_aliases(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string(&settings)?),
+                _ => println!("{}", serde_json::to_string_pretty(&settings)?),
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Returns the value to show for a setting, masking secrets.
+fn display_value(setting: Setting, value: &Value) -> Value {
+    match setting.is_secret() {
+        true => json!("********"),
+        false => value.clone(),
+    }
+}
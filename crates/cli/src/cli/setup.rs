@@ -0,0 +1,344 @@
+//! `q setup`, a guided first run that picks the chat provider, checks it can be reached, and
+//! configures the trusted tools and MCP servers.
+//!
+//! Nothing is written until the last step, so cancelling the wizard leaves the configuration as it
+//! was. Logging in to Amazon Q is the exception since it happens in its own flow.
+
+use std::collections::HashMap;
+use std::io::{
+    IsTerminal,
+    Write as _,
+    stdout,
+};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use anstream::println;
+use clap::Args;
+use crossterm::style::Stylize;
+use dialoguer::{
+    Confirm,
+    MultiSelect,
+};
+use eyre::{
+    Result,
+    WrapErr,
+    bail,
+};
+use serde_json::Value;
+
+use super::user::{
+    LoginArgs,
+    login_interactive,
+};
+use crate::cli::chat::cli::{
+    McpAdd,
+    Scope,
+};
+use crate::cli::chat::mcp::add_mcp_server;
+use crate::cli::chat::util::shared_writer::SharedWriter;
+use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::platform::Context;
+use crate::telemetry::TelemetryThread;
+use crate::util::{
+    CLI_BINARY_NAME,
+    choose,
+    dialoguer_theme,
+    input,
+};
+
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+const OLLAMA_BASE_URL: &str = "http://localhost:11434/v1";
+const OLLAMA_MODEL: &str = "llama3.2";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Provider {
+    AmazonQ,
+    OpenAi,
+    Ollama,
+}
+
+impl Provider {
+    const ALL: [Provider; 3] = [Provider::AmazonQ, Provider::OpenAi, Provider::Ollama];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Provider::AmazonQ => "Amazon Q (Builder ID or IAM Identity Center)",
+            Provider::OpenAi => "An OpenAI-compatible API",
+            Provider::Ollama => "Ollama running on this machine",
+        }
+    }
+}
+
+/// The choices made in the wizard, written at the end.
+#[derive(Debug, Default)]
+struct Plan {
+    settings: Vec<(Setting, Value)>,
+    mcp_servers: Vec<McpAdd>,
+}
+
+#[derive(Debug, Args, PartialEq, Eq)]
+pub struct SetupArgs {}
+
+impl SetupArgs {
+    pub async fn execute(self, database: &mut Database, telemetry: &TelemetryThread) -> Result<ExitCode> {
+        if !stdout().is_terminal() {
+            bail!("{CLI_BINARY_NAME} setup needs an interactive terminal");
+        }
+
+        println!("\n{}\n", "Welcome to Amazon Q! Let's get you set up.".bold());
+        let mut plan = Plan::default();
+
+        let Some(provider) = choose(
+            "Which model provider do you want to chat with?",
+            &Provider::ALL.map(|p| p.label()),
+        )?
+        else {
+            return cancelled();
+        };
+        let provider = Provider::ALL[provider];
+        if !configure_provider(database, telemetry, provider, &mut plan).await? {
+            return cancelled();
+        }
+
+        let trusted = choose_trusted_tools(database)?;
+        plan.settings.push((Setting::ChatTrustedTools, Value::from(trusted)));
+
+        choose_mcp_servers(&mut plan)?;
+
+        println!("\n{}", "Summary".bold());
+        for line in plan.summary() {
+            println!("  {line}");
+        }
+        if !confirm("Save these settings?", true)? {
+            return cancelled();
+        }
+
+        for (setting, value) in plan.settings {
+            database
+                .settings
+                .set(setting, value)
+                .await
+                .wrap_err_with(|| format!("Failed to save {setting}"))?;
+        }
+        if !plan.mcp_servers.is_empty() {
+            let ctx = Context::new();
+            let mut output = SharedWriter::stdout();
+            for server in plan.mcp_servers {
+                add_mcp_server(&ctx, &mut output, server).await?;
+            }
+            output.flush()?;
+        }
+
+        println!(
+            "\nAll set! Start chatting with {}.\n",
+            match provider {
+                Provider::Ollama => format!("{CLI_BINARY_NAME} chat --offline"),
+                _ => format!("{CLI_BINARY_NAME} chat"),
+            }
+            .bold()
+        );
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+impl Plan {
+    fn set(&mut self, setting: Setting, value: impl Into<Value>) {
+        self.settings.push((setting, value.into()));
+    }
+
+    /// One line per setting and server, with secrets hidden.
+    fn summary(&self) -> Vec<String> {
+        let settings = self
+            .settings
+            .iter()
+            .map(|(setting, value)| match (setting.is_secret(), value) {
+                (true, _) => format!("{setting} = ********"),
+                (false, Value::String(value)) => format!("{setting} = {value}"),
+                (false, value) => format!("{setting} = {value}"),
+            });
+        let servers = self
+            .mcp_servers
+            .iter()
+            .map(|server| format!("MCP server {} = {}", server.name, server.command));
+        settings.chain(servers).collect()
+    }
+}
+
+/// Asks for the details of the provider and checks it can be reached. Returns `false` if the user
+/// gives up.
+async fn configure_provider(
+    database: &mut Database,
+    telemetry: &TelemetryThread,
+    provider: Provider,
+    plan: &mut Plan,
+) -> Result<bool> {
+    match provider {
+        Provider::AmazonQ => {
+            plan.set(Setting::OpenAiProvider, "amazon-q");
+            if crate::auth::is_logged_in(database).await {
+                println!("{} You are logged in to Amazon Q", "✓".green());
+                return Ok(true);
+            }
+            if let Err(err) = login_interactive(database, telemetry, LoginArgs::default()).await {
+                println!("{} Unable to log in: {err}", "✗".red());
+                return confirm("Continue anyway?", false);
+            }
+            Ok(true)
+        },
+        Provider::OpenAi => {
+            let base_url = input("Base URL of the API", Some(OPENAI_BASE_URL))?;
+            let api_key = dialoguer::Password::with_theme(&dialoguer_theme())
+                .with_prompt("API key (leave empty if the API doesn't need one)")
+                .allow_empty_password(true)
+                .interact()?;
+            let model = input("Model", Some("gpt-4o"))?;
+            let api_key = (!api_key.is_empty()).then_some(api_key);
+
+            if !check_connectivity(&base_url, api_key.as_deref()).await? {
+                return Ok(false);
+            }
+            plan.set(Setting::OpenAiProvider, "openai");
+            plan.set(Setting::OpenAiApiBaseUrl, base_url);
+            if let Some(api_key) = api_key {
+                plan.set(Setting::OpenAiApiKey, api_key);
+            }
+            plan.set(Setting::OpenAiModel, model);
+            Ok(true)
+        },
+        Provider::Ollama => {
+            let base_url = input("Base URL of Ollama", Some(OLLAMA_BASE_URL))?;
+            let model = input("Model", Some(OLLAMA_MODEL))?;
+
+            if !check_connectivity(&base_url, None).await? {
+                return Ok(false);
+            }
+            plan.set(Setting::ChatOfflineBaseUrl, base_url);
+            plan.set(Setting::ChatOfflineModel, model);
+            Ok(true)
+        },
+    }
+}
+
+/// Lists the models of an OpenAI-compatible API. Returns `false` if it can't be reached and the
+/// user doesn't want to continue.
+async fn check_connectivity(base_url: &str, api_key: Option<&str>) -> Result<bool> {
+    let mut request = crate::request::client_builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(models_url(base_url));
+    if let Some(api_key) = api_key {
+        request = request.bearer_auth(api_key);
+    }
+
+    let error = match request.send().await {
+        Ok(response) if response.status().is_success() => {
+            println!("{} Connected to {base_url}", "✓".green());
+            return Ok(true);
+        },
+        Ok(response) => format!("it responded with {}", response.status()),
+        Err(err) => err.to_string(),
+    };
+    println!("{} Unable to reach {base_url}: {error}", "✗".red());
+    confirm("Continue anyway?", false)
+}
+
+fn models_url(base_url: &str) -> String {
+    format!("{}/models", base_url.trim_end_matches('/'))
+}
+
+/// Asks which built-in tools run without asking for confirmation, starting from the current ones.
+fn choose_trusted_tools(database: &Database) -> Result<Vec<String>> {
+    let specs = serde_json::from_str::<HashMap<String, Value>>(include_str!("chat/tools/tool_index.json"))?;
+    let mut tools = specs.into_keys().collect::<Vec<_>>();
+    tools.sort();
+    let current = database
+        .settings
+        .get_string_list(Setting::ChatTrustedTools)
+        .unwrap_or_default();
+    let defaults = tools.iter().map(|tool| current.contains(tool)).collect::<Vec<_>>();
+
+    println!(
+        "\n{}",
+        "Trusted tools run without asking you first. You can change them later with /tools.".dark_grey()
+    );
+    let chosen = MultiSelect::with_theme(&dialoguer_theme())
+        .with_prompt("Which tools do you trust? (space to select, enter to confirm)")
+        .items(&tools)
+        .defaults(&defaults)
+        .interact()?;
+    Ok(chosen.into_iter().map(|i| tools[i].clone()).collect())
+}
+
+fn choose_mcp_servers(plan: &mut Plan) -> Result<()> {
+    let mut prompt = "Do you want to add an MCP server?";
+    while confirm(prompt, false)? {
+        let name = input("Name of the server", None)?;
+        let command = input("Command launching the server", None)?;
+        if name.trim().is_empty() || command.trim().is_empty() {
+            println!("{}", "The name and the command are required.".red());
+            continue;
+        }
+        plan.mcp_servers.push(McpAdd {
+            name: name.trim().to_string(),
+            command: command.trim().to_string(),
+            scope: Some(Scope::Global),
+            env: Vec::new(),
+            timeout: None,
+            force: false,
+        });
+        prompt = "Do you want to add another MCP server?";
+    }
+    Ok(())
+}
+
+fn confirm(prompt: &str, default: bool) -> Result<bool> {
+    Ok(Confirm::with_theme(&dialoguer_theme())
+        .with_prompt(prompt)
+        .default(default)
+        .interact_opt()?
+        .unwrap_or(false))
+}
+
+fn cancelled() -> Result<ExitCode> {
+    println!("\nSetup cancelled, nothing was saved.");
+    Ok(ExitCode::FAILURE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_models_url() {
+        assert_eq!(
+            models_url("http://localhost:11434/v1"),
+            "http://localhost:11434/v1/models"
+        );
+        assert_eq!(
+            models_url("https://api.openai.com/v1/"),
+            "https://api.openai.com/v1/models"
+        );
+    }
+
+    #[test]
+    fn test_summary_hides_secrets() {
+        let mut plan = Plan::default();
+        plan.set(Setting::OpenAiApiKey, "sk-123");
+        plan.set(Setting::ChatTrustedTools, vec!["fs_write".to_string()]);
+        plan.mcp_servers.push(McpAdd {
+            name: "git".to_string(),
+            command: "uvx mcp-server-git".to_string(),
+            scope: Some(Scope::Global),
+            env: Vec::new(),
+            timeout: None,
+            force: false,
+        });
+        assert_eq!(plan.summary(), vec![
+            "openai.api.key = ********".to_string(),
+            "chat.trustedTools = [\"fs_write\"]".to_string(),
+            "MCP server git = uvx mcp-server-git".to_string(),
+        ]);
+    }
+}
@@ -0,0 +1,239 @@
+//! `q shell-hook`, which prints a hook recording the last command of the shell, and the record it
+//! leaves for `q chat --last-command`.
+//!
+//! The hook writes the command, its exit status and, inside tmux, the end of the pane to files in
+//! the runtime directory once the command finishes. Recording it only then means that running
+//! `q chat --last-command` doesn't replace the command the user is asking about.
+
+use std::fmt::Write as _;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process::ExitCode;
+
+use clap::{
+    Args,
+    ValueEnum,
+};
+use eyre::{
+    Result,
+    WrapErr,
+    bail,
+};
+
+use crate::util::CLI_BINARY_NAME;
+
+/// Lines of the tmux pane that are kept by the hook.
+const CAPTURED_LINES: usize = 200;
+/// Lines of output sent to the model.
+const MAX_OUTPUT_LINES: usize = 100;
+
+const BASH_HOOK: &str = r#"__q_last_command_dir=__DIR__
+__q_precmd() {
+  local exit_code=$? command
+  command=$(HISTTIMEFORMAT= builtin history 1)
+  command=${command#*[0-9]  }
+  [[ -n $command && $command != "$__q_last_command" ]] || return $exit_code
+  __q_last_command=$command
+  mkdir -p -m 700 -- "$__q_last_command_dir"
+  printf '%s\n' "$command" >| "$__q_last_command_dir/command"
+  printf '%s\n' "$exit_code" >| "$__q_last_command_dir/status"
+  if [[ -n $TMUX ]]; then
+    tmux capture-pane -p -J -S -__LINES__ >| "$__q_last_command_dir/output" 2>/dev/null
+  else
+    : >| "$__q_last_command_dir/output"
+  fi
+  return $exit_code
+}
+PROMPT_COMMAND="__q_precmd${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+"#;
+
+const ZSH_HOOK: &str = r#"__q_last_command_dir=__DIR__
+__q_preexec() { __q_last_command=$1 }
+__q_precmd() {
+  local exit_code=$?
+  [[ -n $__q_last_command ]] || return
+  mkdir -p -m 700 -- "$__q_last_command_dir"
+  print -r -- "$__q_last_command" >| "$__q_last_command_dir/command"
+  print -r -- "$exit_code" >| "$__q_last_command_dir/status"
+  if [[ -n $TMUX ]]; then
+    tmux capture-pane -p -J -S -__LINES__ >| "$__q_last_command_dir/output" 2>/dev/null
+  else
+    : >| "$__q_last_command_dir/output"
+  fi
+  __q_last_command=
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook preexec __q_preexec
+# First, so that $? is still the status of the command
+precmd_functions=(__q_precmd ${precmd_functions:#__q_precmd})
+"#;
+
+const FISH_HOOK: &str = r#"set -g __q_last_command_dir __DIR__
+function __q_postexec --on-event fish_postexec
+    set -l exit_code $status
+    mkdir -p -m 700 -- $__q_last_command_dir
+    printf '%s\n' $argv[1] > $__q_last_command_dir/command
+    printf '%s\n' $exit_code > $__q_last_command_dir/status
+    if set -q TMUX
+        tmux capture-pane -p -J -S -__LINES__ > $__q_last_command_dir/output 2>/dev/null
+    else
+        true > $__q_last_command_dir/output
+    end
+end
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Debug, Args, PartialEq, Eq)]
+pub struct ShellHookArgs {
+    /// Shell to print the hook for, e.g. `eval "$(q shell-hook zsh)"` in ~/.zshrc
+    #[arg(value_enum)]
+    pub shell: Shell,
+}
+
+impl ShellHookArgs {
+    pub fn execute(self) -> Result<ExitCode> {
+        print!("{}", hook(self.shell, &last_command_dir()?));
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+fn hook(shell: Shell, dir: &Path) -> String {
+    let dir = dir.to_string_lossy();
+    let (template, dir) = match shell {
+        Shell::Bash => (BASH_HOOK, format!("'{}'", dir.replace('\'', r"'\''"))),
+        Shell::Zsh => (ZSH_HOOK, format!("'{}'", dir.replace('\'', r"'\''"))),
+        Shell::Fish => (
+            FISH_HOOK,
+            format!("'{}'", dir.replace('\\', r"\\").replace('\'', r"\'")),
+        ),
+    };
+    template
+        .replace("__DIR__", &dir)
+        .replace("__LINES__", &CAPTURED_LINES.to_string())
+}
+
+fn last_command_dir() -> Result<PathBuf> {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            Ok(crate::util::directories::runtime_dir()?.join("q-last-command"))
+        } else {
+            bail!("Recording the last command isn't supported on Windows")
+        }
+    }
+}
+
+/// The last command recorded by the hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastCommand {
+    pub command: String,
+    pub exit_code: Option<i32>,
+    /// The end of the terminal, only captured inside tmux.
+    pub output: Option<String>,
+}
+
+impl LastCommand {
+    pub fn load() -> Result<Self> {
+        let dir = last_command_dir()?;
+        let command = match std::fs::read_to_string(dir.join("command")) {
+            Ok(command) if !command.trim().is_empty() => command.trim_end().to_string(),
+            Ok(_) => bail!("No command was recorded yet"),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => bail!(
+                "No command was recorded. Add the hook to your shell configuration first, e.g. \
+                `eval \"$({CLI_BINARY_NAME} shell-hook zsh)\"` in ~/.zshrc"
+            ),
+            Err(err) => return Err(err).wrap_err("Failed to read the last command"),
+        };
+        let exit_code = std::fs::read_to_string(dir.join("status"))
+            .ok()
+            .and_then(|status| status.trim().parse().ok());
+        let output = std::fs::read_to_string(dir.join("output"))
+            .ok()
+            .map(|output| tail(&output, MAX_OUTPUT_LINES))
+            .filter(|output| !output.is_empty());
+        Ok(Self {
+            command,
+            exit_code,
+            output,
+        })
+    }
+
+    /// The first message of the chat, ending with `question` or one that fits the exit status.
+    pub fn prompt(&self, question: Option<&str>) -> String {
+        let mut prompt = match self.exit_code {
+            Some(code) => format!("I ran this command in my shell and it exited with status {code}:\n\n"),
+            None => "I ran this command in my shell:\n\n".to_string(),
+        };
+        let _ = write!(prompt, "```\n$ {}\n```\n\n", self.command);
+        if let Some(output) = &self.output {
+            let _ = write!(
+                prompt,
+                "The end of the terminal after it ran:\n\n```\n{output}\n```\n\n"
+            );
+        }
+        let question = match (question, self.exit_code) {
+            (Some(question), _) => question,
+            (None, Some(0)) => "Explain what it did.",
+            (None, Some(_)) => "Why did it fail, and how do I fix it?",
+            (None, None) => "What happened?",
+        };
+        prompt.push_str(question);
+        prompt
+    }
+}
+
+/// The last `lines` lines of the output, without the blank lines at the end.
+fn tail(output: &str, lines: usize) -> String {
+    let all = output.trim_end().lines().collect::<Vec<_>>();
+    all[all.len().saturating_sub(lines)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_quotes_dir() {
+        let dir = Path::new("/tmp/it's here");
+        assert!(hook(Shell::Bash, dir).starts_with(r"__q_last_command_dir='/tmp/it'\''s here'"));
+        assert!(hook(Shell::Zsh, dir).starts_with(r"__q_last_command_dir='/tmp/it'\''s here'"));
+        assert!(hook(Shell::Fish, dir).starts_with(r"set -g __q_last_command_dir '/tmp/it\'s here'"));
+        assert!(hook(Shell::Zsh, dir).contains("capture-pane -p -J -S -200"));
+    }
+
+    #[test]
+    fn test_tail() {
+        assert_eq!(tail("a\nb\nc\n\n\n", 2), "b\nc");
+        assert_eq!(tail("a\n", 5), "a");
+        assert_eq!(tail("", 5), "");
+    }
+
+    #[test]
+    fn test_prompt() {
+        let mut last_command = LastCommand {
+            command: "cargo build".to_string(),
+            exit_code: Some(101),
+            output: Some("error[E0425]: cannot find value `x`".to_string()),
+        };
+        assert_eq!(
+            last_command.prompt(None),
+            "I ran this command in my shell and it exited with status 101:\n\n```\n$ cargo build\n```\n\n\
+            The end of the terminal after it ran:\n\n```\nerror[E0425]: cannot find value `x`\n```\n\n\
+            Why did it fail, and how do I fix it?"
+        );
+
+        last_command.exit_code = None;
+        last_command.output = None;
+        assert_eq!(
+            last_command.prompt(Some("Is it slow?")),
+            "I ran this command in my shell:\n\n```\n$ cargo build\n```\n\nIs it slow?"
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! `q translate` and `/translate`, which turn a description into a single shell command.
+//!
+//! The command is shown with a menu to run, explain or edit it, or to ask the model for changes,
+//! which keeps the earlier requests in the conversation. Commands that are run are added to the
+//! history file of the user's shell so they can be recalled later.
+
+use std::io::{
+    Write as _,
+    stderr,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process::ExitCode;
+
+use anstream::println;
+use clap::Args;
+use crossterm::style::Stylize;
+use crossterm::{
+    cursor,
+    execute,
+    terminal,
+};
+use eyre::{
+    Result,
+    WrapErr,
+    bail,
+};
+use spinners::{
+    Spinner,
+    Spinners,
+};
+use tracing::warn;
+
+use crate::api_client::StreamingClient;
+use crate::api_client::model::{
+    AssistantResponseMessage,
+    ChatMessage,
+    ChatResponseStream,
+    ConversationState,
+    UserInputMessage,
+};
+use crate::database::Database;
+use crate::util::{
+    choose,
+    input,
+};
+
+#[derive(Debug, Args, PartialEq, Eq)]
+pub struct TranslateArgs {
+    /// What the command should do, e.g. "find files modified last week"
+    #[arg(required = true, num_args = 1..)]
+    pub description: Vec<String>,
+}
+
+impl TranslateArgs {
+    pub async fn execute(self, database: &mut Database) -> Result<ExitCode> {
+        let client = StreamingClient::new(database)
+            .await
+            .wrap_err("Failed to initialize the chat client")?;
+        Ok(match run(&client, &self.description.join(" ")).await? {
+            Some(_) => ExitCode::SUCCESS,
+            None => ExitCode::FAILURE,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Run,
+    Explain,
+    Edit,
+    Change,
+    Cancel,
+}
+
+impl Action {
+    const ALL: [Action; 5] = [
+        Action::Run,
+        Action::Explain,
+        Action::Edit,
+        Action::Change,
+        Action::Cancel,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Action::Run => "Run it",
+            Action::Explain => "Explain it",
+            Action::Edit => "Edit it",
+            Action::Change => "Ask for changes",
+            Action::Cancel => "Cancel",
+        }
+    }
+}
+
+/// Asks the model for a command doing `description` and shows the menu until the command is run
+/// or the user cancels. Returns the command that was run.
+pub async fn run(client: &StreamingClient, description: &str) -> Result<Option<String>> {
+    let shell = shell();
+    let mut translator = Translator::new(client);
+    let mut command = translator.ask(&initial_request(&shell, description)).await?;
+
+    loop {
+        println!("\n  {} {}\n", "❯".magenta(), command.as_str().bold());
+        let action = match choose("What do you want to do?", &Action::ALL.map(|a| a.label()))? {
+            Some(i) => Action::ALL[i],
+            None => Action::Cancel,
+        };
+
+        match action {
+            Action::Run => {
+                let status = std::process::Command::new(&shell)
+                    .arg("-c")
+                    .arg(&command)
+                    .status()
+                    .wrap_err_with(|| format!("Failed to run {shell}"))?;
+                if let Err(err) = add_to_shell_history(&shell, &command) {
+                    warn!(?err, "Failed to add the command to the shell history");
+                }
+                if !status.success() {
+                    println!("{}", format!("\nThe command exited with {status}").red());
+                }
+                return Ok(Some(command));
+            },
+            Action::Explain => {
+                let explanation = translator.explain(&command).await?;
+                println!("\n{}", explanation.trim());
+            },
+            Action::Edit => command = input("Command", Some(&command))?,
+            Action::Change => {
+                let change = input("What should change?", None)?;
+                if !change.trim().is_empty() {
+                    command = translator.ask(&change_request(&change)).await?;
+                }
+            },
+            Action::Cancel => return Ok(None),
+        }
+    }
+}
+
+/// The conversation about the command, so that changes can refer to earlier requests.
+struct Translator<'a> {
+    client: &'a StreamingClient,
+    history: Vec<ChatMessage>,
+}
+
+impl<'a> Translator<'a> {
+    fn new(client: &'a StreamingClient) -> Self {
+        Self {
+            client,
+            history: Vec::new(),
+        }
+    }
+
+    /// Sends the request and returns the command in the response.
+    async fn ask(&mut self, request: &str) -> Result<String> {
+        let response = self.send(request, "Translating...").await?;
+        let command = extract_command(&response);
+        if command.is_empty() {
+            bail!("The model didn't answer with a command: {}", response.trim());
+        }
+
+        self.history.push(ChatMessage::UserInputMessage(user_message(request)));
+        self.history
+            .push(ChatMessage::AssistantResponseMessage(AssistantResponseMessage {
+                message_id: None,
+                content: command.clone(),
+                tool_uses: None,
+            }));
+        Ok(command)
+    }
+
+    /// Asks what the command does, without adding the answer to the conversation.
+    async fn explain(&self, command: &str) -> Result<String> {
+        let request = format!(
+            "Explain briefly what this command does, one line per part of it. Do not suggest other commands.\n\n{command}"
+        );
+        self.send(&request, "Explaining...").await
+    }
+
+    async fn send(&self, request: &str, spinner_message: &str) -> Result<String> {
+        let mut spinner = Spinner::new(Spinners::Dots, spinner_message.to_string());
+        let result = async {
+            let mut response = self
+                .client
+                .send_message(ConversationState {
+                    conversation_id: None,
+                    user_input_message: user_message(request),
+                    history: (!self.history.is_empty()).then(|| self.history.clone()),
+                    response_schema: None,
+                })
+                .await?;
+            let mut text = String::new();
+            while let Some(event) = response.recv().await? {
+                if let ChatResponseStream::AssistantResponseEvent { content } = event {
+                    text.push_str(&content);
+                }
+            }
+            Ok::<_, eyre::Report>(text)
+        }
+        .await;
+
+        spinner.stop();
+        let _ = execute!(
+            stderr(),
+            cursor::MoveToColumn(0),
+            terminal::Clear(terminal::ClearType::CurrentLine)
+        );
+        result
+    }
+}
+
+fn user_message(content: &str) -> UserInputMessage {
+    UserInputMessage {
+        images: None,
+        content: content.to_string(),
+        user_input_message_context: None,
+        user_intent: None,
+    }
+}
+
+fn initial_request(shell: &str, description: &str) -> String {
+    let shell_name = Path::new(shell)
+        .file_name()
+        .map_or(shell.into(), |name| name.to_string_lossy());
+    format!(
+        "Write a single {shell_name} command for {} that does the following. Reply with only the command, \
+        without explanation and without markdown. Chain steps with pipes or && if needed.\n\n{description}",
+        std::env::consts::OS
+    )
+}
+
+fn change_request(change: &str) -> String {
+    format!(
+        "Change the command: {change}\n\nReply with only the updated command, without explanation and without markdown."
+    )
+}
+
+/// The command in a response, without the code fence or backticks models tend to add anyway.
+fn extract_command(response: &str) -> String {
+    let response = response.trim();
+    let command = match response.split_once("```") {
+        Some((_, fenced)) => {
+            let fenced = fenced.split("```").next().unwrap_or_default();
+            // Skip the language of the fence, e.g. ```bash
+            match fenced.split_once('\n') {
+                Some((first, rest)) if !first.trim().contains(' ') => rest,
+                _ => fenced,
+            }
+        },
+        None => response.trim_matches('`'),
+    };
+    let command = command.trim();
+    command.strip_prefix("$ ").unwrap_or(command).trim().to_string()
+}
+
+fn shell() -> String {
+    std::env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| "sh".to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HistoryFormat {
+    Plain,
+    /// zsh with `EXTENDED_HISTORY`, which records the time of each command.
+    ZshExtended,
+    Fish,
+}
+
+/// The history file of the shell, or `None` for shells whose history isn't supported.
+fn history_file(shell: &str) -> Option<(PathBuf, HistoryFormat)> {
+    let home = dirs::home_dir()?;
+    let histfile = std::env::var_os("HISTFILE").map(PathBuf::from);
+    match Path::new(shell).file_name()?.to_str()? {
+        "bash" => Some((
+            histfile.unwrap_or_else(|| home.join(".bash_history")),
+            HistoryFormat::Plain,
+        )),
+        "zsh" => {
+            let path = histfile.unwrap_or_else(|| home.join(".zsh_history"));
+            let contents = std::fs::read(&path).unwrap_or_default();
+            let format = match String::from_utf8_lossy(&contents)
+                .lines()
+                .rev()
+                .find(|line| !line.is_empty())
+            {
+                Some(line) if line.starts_with(": ") => HistoryFormat::ZshExtended,
+                _ => HistoryFormat::Plain,
+            };
+            Some((path, format))
+        },
+        "fish" => {
+            let data_dir = std::env::var_os("XDG_DATA_HOME").map_or_else(|| home.join(".local/share"), PathBuf::from);
+            Some((data_dir.join("fish").join("fish_history"), HistoryFormat::Fish))
+        },
+        _ => None,
+    }
+}
+
+fn history_entry(format: HistoryFormat, command: &str, timestamp: u64) -> String {
+    match format {
+        HistoryFormat::Plain => format!("{command}\n"),
+        HistoryFormat::ZshExtended => format!(": {timestamp}:0;{}\n", command.replace('\n', "\\\n")),
+        HistoryFormat::Fish => format!(
+            "- cmd: {}\n  when: {timestamp}\n",
+            command.replace('\\', "\\\\").replace('\n', "\\n")
+        ),
+    }
+}
+
+/// Appends the command to the history file, where new shells and `history -r` pick it up.
+fn add_to_shell_history(shell: &str, command: &str) -> Result<()> {
+    let Some((path, format)) = history_file(shell) else {
+        return Ok(());
+    };
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    file.write_all(history_entry(format, command, timestamp).as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_command() {
+        assert_eq!(extract_command("find . -mtime -7\n"), "find . -mtime -7");
+        assert_eq!(extract_command("`ls -la`"), "ls -la");
+        assert_eq!(
+            extract_command("```bash\n$ du -sh * | sort -h\n```"),
+            "du -sh * | sort -h"
+        );
+        assert_eq!(
+            extract_command("Here is the command:\n```\ngit log --since='1 week ago'\n```\nIt lists commits."),
+            "git log --since='1 week ago'"
+        );
+    }
+
+    #[test]
+    fn test_history_entry() {
+        assert_eq!(history_entry(HistoryFormat::Plain, "ls", 1), "ls\n");
+        assert_eq!(
+            history_entry(HistoryFormat::ZshExtended, "ls", 1700000000),
+            ": 1700000000:0;ls\n"
+        );
+        assert_eq!(
+            history_entry(HistoryFormat::Fish, "echo a\\b", 1700000000),
+            "- cmd: echo a\\\\b\n  when: 1700000000\n"
+        );
+    }
+}
@@ -0,0 +1,505 @@
+//! `q usage`, the ledger of the tokens spent by chat sessions and what they cost.
+//!
+//! When chat.usageLedger is enabled, which it is by default, every response of a provider that
+//! reports usage is appended to a JSON lines file with its tokens, the provider and model, the
+//! workspace of the session and an estimate of its cost in USD. `q usage report` rolls the ledger
+//! up per week and workspace. Prices are estimates from the public price lists of well known
+//! models, which chat.modelPricing can override or extend; the Amazon Q backend doesn't report
+//! usage, so its sessions aren't recorded.
+
+use std::collections::{
+    BTreeMap,
+    HashSet,
+};
+use std::fs::{
+    File,
+    OpenOptions,
+};
+use std::io::{
+    BufRead,
+    BufReader,
+    Write as _,
+};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anstream::{
+    print,
+    println,
+};
+use clap::{
+    Subcommand,
+    ValueEnum,
+};
+use crossterm::style::Stylize;
+use eyre::{
+    Result,
+    WrapErr,
+    bail,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use time::macros::format_description;
+use time::{
+    Date,
+    OffsetDateTime,
+};
+use tracing::warn;
+
+use crate::api_client::model::TokenUsage;
+use crate::cli::chat::model_capabilities::longest_prefix_match;
+use crate::cli::chat::openai_config::OpenAiConfig;
+use crate::database::Database;
+use crate::database::settings::Setting;
+use crate::platform::Context;
+use crate::util::directories;
+
+/// Prices of well known models in USD per million tokens, matched by model id prefix.
+const BUILTIN_PRICES: &[(&str, ModelPrice)] = &[
+    ("gpt-4.1", ModelPrice::new(2.0, Some(0.5), 8.0)),
+    ("gpt-4.1-mini", ModelPrice::new(0.4, Some(0.1), 1.6)),
+    ("gpt-4.1-nano", ModelPrice::new(0.1, Some(0.025), 0.4)),
+    ("gpt-4o", ModelPrice::new(2.5, Some(1.25), 10.0)),
+    ("gpt-4o-mini", ModelPrice::new(0.15, Some(0.075), 0.6)),
+    ("gpt-4-turbo", ModelPrice::new(10.0, None, 30.0)),
+    ("gpt-4", ModelPrice::new(30.0, None, 60.0)),
+    ("gpt-3.5-turbo", ModelPrice::new(0.5, None, 1.5)),
+    ("o1", ModelPrice::new(15.0, Some(7.5), 60.0)),
+    ("o3", ModelPrice::new(2.0, Some(0.5), 8.0)),
+    ("o3-mini", ModelPrice::new(1.1, Some(0.55), 4.4)),
+    ("o4-mini", ModelPrice::new(1.1, Some(0.275), 4.4)),
+    ("claude-opus-4", ModelPrice::new(15.0, Some(1.5), 75.0)),
+    ("claude-sonnet-4", ModelPrice::new(3.0, Some(0.3), 15.0)),
+    ("claude-3-7-sonnet", ModelPrice::new(3.0, Some(0.3), 15.0)),
+    ("claude-3-5-sonnet", ModelPrice::new(3.0, Some(0.3), 15.0)),
+    ("claude-3-5-haiku", ModelPrice::new(0.8, Some(0.08), 4.0)),
+    ("deepseek-chat", ModelPrice::new(0.27, Some(0.07), 1.1)),
+    ("deepseek-reasoner", ModelPrice::new(0.55, Some(0.14), 2.19)),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum UsageSubcommand {
+    /// Show the tokens and estimated cost of chat sessions per week and workspace
+    Report {
+        /// How far back to report, e.g. 12h, 7d or 4w, or a date such as 2025-06-01
+        #[arg(long, default_value = "7d")]
+        since: String,
+        /// Output format
+        #[arg(long, short, value_enum, default_value_t)]
+        format: ReportFormat,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// A table for the terminal
+    #[default]
+    Table,
+    /// Comma separated values, e.g. for a spreadsheet
+    Csv,
+    /// Outputs the rows as JSON
+    Json,
+}
+
+impl UsageSubcommand {
+    pub fn execute(self) -> Result<ExitCode> {
+        let ledger = UsageLedger::new(directories::usage_ledger_path(&Context::new())?);
+        match self {
+            UsageSubcommand::Report { since, format } => {
+                let since = parse_since(&since, OffsetDateTime::now_utc())?;
+                let rows = report(&ledger.entries()?, since);
+                match format {
+                    ReportFormat::Table if rows.is_empty() => println!(
+                        "No usage was recorded since {}, the ledger is enabled with `q settings chat.usageLedger true`",
+                        since.date()
+                    ),
+                    ReportFormat::Table => println!("{}", table(&rows)),
+                    ReportFormat::Csv => print!("{}", csv(&rows)),
+                    ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+                }
+                Ok(ExitCode::SUCCESS)
+            },
+        }
+    }
+}
+
+/// Price of a model in USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPrice {
+    pub input: f64,
+    /// Price of the input tokens read from the prompt cache, the input price if unset.
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+    pub output: f64,
+}
+
+impl ModelPrice {
+    const fn new(input: f64, cached_input: Option<f64>, output: f64) -> Self {
+        Self {
+            input,
+            cached_input,
+            output,
+        }
+    }
+
+    /// The estimated cost of a request in USD.
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let cached = usage.cached_input_tokens.min(usage.input_tokens);
+        let uncached = usage.input_tokens - cached;
+        (uncached as f64 * self.input
+            + cached as f64 * self.cached_input.unwrap_or(self.input)
+            + usage.output_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// The price of `model`, from chat.modelPricing or else [BUILTIN_PRICES].
+pub fn model_price(database: &Database, model: &str) -> Option<ModelPrice> {
+    let mut overrides = Vec::new();
+    if let Some(serde_json::Value::Object(map)) = database.settings.get(Setting::ChatModelPricing) {
+        for (prefix, value) in map {
+            match serde_json::from_value::<ModelPrice>(value.clone()) {
+                Ok(price) => overrides.push((prefix.to_lowercase(), price)),
+                Err(err) => warn!("Ignoring invalid price for model '{}': {}", prefix, err),
+            }
+        }
+    }
+    longest_prefix_match(model, overrides.iter().map(|(prefix, price)| (prefix.as_str(), *price)))
+        .or_else(|| longest_prefix_match(model, BUILTIN_PRICES.iter().copied()))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageEntry {
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+    pub conversation_id: String,
+    /// The workspace root of the session, or its working directory outside of a workspace.
+    pub workspace: PathBuf,
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub cached_input_tokens: u64,
+    pub output_tokens: u64,
+    /// Estimated cost in USD, unset if the price of the model isn't known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+/// The ledger a chat session appends the usage of its requests to.
+#[derive(Debug, Clone)]
+pub struct UsageLedger {
+    path: PathBuf,
+    workspace: PathBuf,
+    provider: String,
+    model: String,
+    price: Option<ModelPrice>,
+}
+
+impl UsageLedger {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            workspace: PathBuf::new(),
+            provider: String::new(),
+            model: String::new(),
+            price: None,
+        }
+    }
+
+    /// The ledger in the default location for sessions using `config`, if chat.usageLedger is
+    /// enabled and the provider reports usage.
+    pub fn from_database(ctx: &Context, database: &Database, config: &OpenAiConfig) -> Option<Self> {
+        if !config.is_openai_compatible() || !database.settings.get_bool_or_default(Setting::ChatUsageLedger) {
+            return None;
+        }
+        let path = match directories::usage_ledger_path(ctx) {
+            Ok(path) => path,
+            Err(err) => {
+                warn!(?err, "Failed to find the path of the usage ledger");
+                return None;
+            },
+        };
+        let workspace = match database.settings.workspace_root() {
+            Some(root) => root.to_path_buf(),
+            None => ctx.env().current_dir().unwrap_or_default(),
+        };
+        Some(Self {
+            path,
+            workspace,
+            provider: config.provider.to_string(),
+            model: config.model.clone(),
+            price: model_price(database, &config.model),
+        })
+    }
+
+    /// Appends the usage of a request of the conversation.
+    pub fn record(&self, conversation_id: &str, usage: &TokenUsage) -> Result<()> {
+        self.append(&UsageEntry {
+            time: OffsetDateTime::now_utc()
+                .replace_nanosecond(0)
+                .unwrap_or(OffsetDateTime::now_utc()),
+            conversation_id: conversation_id.to_string(),
+            workspace: self.workspace.clone(),
+            provider: self.provider.clone(),
+            model: self.model.clone(),
+            input_tokens: usage.input_tokens,
+            cached_input_tokens: usage.cached_input_tokens,
+            output_tokens: usage.output_tokens,
+            cost: self.price.map(|price| price.cost(usage)),
+        })
+    }
+
+    fn append(&self, entry: &UsageEntry) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .wrap_err_with(|| format!("Failed to open {}", self.path.display()))?;
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        Ok(())
+    }
+
+    /// The entries of the ledger, oldest first. Invalid lines are skipped.
+    pub fn entries(&self) -> Result<Vec<UsageEntry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut entries = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(err) => warn!("Skipping invalid usage entry on line {}: {}", index + 1, err),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Parses `--since`, a number of hours, days or weeks before `now`, or a date.
+fn parse_since(since: &str, now: OffsetDateTime) -> Result<OffsetDateTime> {
+    if let Ok(date) = Date::parse(since, format_description!("[year]-[month]-[day]")) {
+        return Ok(date.midnight().assume_utc());
+    }
+    let (amount, unit) = since.split_at(since.len().saturating_sub(1));
+    let Ok(amount) = amount.parse::<i64>() else {
+        bail!("Invalid duration '{since}', expected e.g. 12h, 7d, 4w or a date such as 2025-06-01");
+    };
+    let duration = match unit {
+        "h" => time::Duration::hours(amount),
+        "d" => time::Duration::days(amount),
+        "w" => time::Duration::weeks(amount),
+        _ => bail!("Invalid duration '{since}', expected e.g. 12h, 7d, 4w or a date such as 2025-06-01"),
+    };
+    Ok(now - duration)
+}
+
+/// The usage of a week in a workspace with a provider.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportRow {
+    /// The Monday starting the week.
+    pub week: String,
+    pub workspace: PathBuf,
+    pub provider: String,
+    pub sessions: usize,
+    pub requests: usize,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Estimated cost in USD of the requests whose price is known, unset if there are none.
+    pub cost: Option<f64>,
+}
+
+fn report(entries: &[UsageEntry], since: OffsetDateTime) -> Vec<ReportRow> {
+    let mut rows = BTreeMap::new();
+    let mut sessions = HashSet::new();
+    for entry in entries.iter().filter(|entry| entry.time >= since) {
+        let date = entry.time.date();
+        let monday = date - time::Duration::days(date.weekday().number_days_from_monday().into());
+        let key = (monday, entry.workspace.clone(), entry.provider.clone());
+        let row = rows.entry(key.clone()).or_insert_with(|| ReportRow {
+            week: monday.to_string(),
+            workspace: entry.workspace.clone(),
+            provider: entry.provider.clone(),
+            sessions: 0,
+            requests: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            cost: None,
+        });
+        if sessions.insert((key, entry.conversation_id.as_str())) {
+            row.sessions += 1;
+        }
+        row.requests += 1;
+        row.input_tokens += entry.input_tokens;
+        row.output_tokens += entry.output_tokens;
+        if let Some(cost) = entry.cost {
+            *row.cost.get_or_insert(0.0) += cost;
+        }
+    }
+    rows.into_values().collect()
+}
+
+fn table(rows: &[ReportRow]) -> String {
+    let workspace_width = rows
+        .iter()
+        .map(|row| row.workspace.display().to_string().chars().count())
+        .max()
+        .unwrap_or_default()
+        .max(9)
+        + 2;
+    let provider_width = rows
+        .iter()
+        .map(|row| row.provider.len())
+        .max()
+        .unwrap_or_default()
+        .max(8)
+        + 2;
+
+    let mut text = format!(
+        "{:12}{:workspace_width$}{:provider_width$}{:>10}{:>14}{:>14}{:>12}",
+        "week", "workspace", "provider", "sessions", "input tokens", "output tokens", "cost"
+    )
+    .bold()
+    .to_string();
+    text.push('\n');
+    for row in rows {
+        text.push_str(&format!(
+            "{:12}{:workspace_width$}{:provider_width$}{:>10}{:>14}{:>14}{:>12}\n",
+            row.week,
+            row.workspace.display().to_string(),
+            row.provider,
+            row.sessions,
+            row.input_tokens,
+            row.output_tokens,
+            dollars(row.cost)
+        ));
+    }
+
+    let total = rows.iter().filter_map(|row| row.cost).reduce(|a, b| a + b);
+    text.push_str(&format!(
+        "\n{} {} input and {} output tokens, {} estimated",
+        "Total:".bold(),
+        rows.iter().map(|row| row.input_tokens).sum::<u64>(),
+        rows.iter().map(|row| row.output_tokens).sum::<u64>(),
+        dollars(total)
+    ));
+    text
+}
+
+fn csv(rows: &[ReportRow]) -> String {
+    let mut text = "week,workspace,provider,sessions,requests,input_tokens,output_tokens,cost_usd\n".to_string();
+    for row in rows {
+        text.push_str(&format!(
+            "{},{},{},{},{},{},{},{}\n",
+            row.week,
+            csv_field(&row.workspace.display().to_string()),
+            csv_field(&row.provider),
+            row.sessions,
+            row.requests,
+            row.input_tokens,
+            row.output_tokens,
+            row.cost.map_or(String::new(), |cost| format!("{cost:.4}"))
+        ));
+    }
+    text
+}
+
+/// Quotes a field holding commas, quotes or line breaks.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn dollars(cost: Option<f64>) -> String {
+    cost.map_or("-".to_string(), |cost| format!("${cost:.2}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn entry(time: OffsetDateTime, conversation_id: &str, workspace: &str, cost: Option<f64>) -> UsageEntry {
+        UsageEntry {
+            time,
+            conversation_id: conversation_id.to_string(),
+            workspace: PathBuf::from(workspace),
+            provider: "openai".to_string(),
+            model: "gpt-4o".to_string(),
+            input_tokens: 1000,
+            cached_input_tokens: 0,
+            output_tokens: 100,
+            cost,
+        }
+    }
+
+    #[test]
+    fn test_model_price() {
+        let price = longest_prefix_match("openai/gpt-4o-mini", BUILTIN_PRICES.iter().copied()).unwrap();
+        assert_eq!(price.input, 0.15);
+        let usage = TokenUsage {
+            input_tokens: 1_000_000,
+            cached_input_tokens: 500_000,
+            cache_write_tokens: 0,
+            output_tokens: 100_000,
+        };
+        assert!((price.cost(&usage) - (0.075 + 0.0375 + 0.06)).abs() < 1e-9);
+        assert!(longest_prefix_match("llama3.2", BUILTIN_PRICES.iter().copied()).is_none());
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let now = datetime!(2025-06-11 12:00 UTC);
+        assert_eq!(parse_since("7d", now).unwrap(), datetime!(2025-06-04 12:00 UTC));
+        assert_eq!(parse_since("12h", now).unwrap(), datetime!(2025-06-11 00:00 UTC));
+        assert_eq!(parse_since("2w", now).unwrap(), datetime!(2025-05-28 12:00 UTC));
+        assert_eq!(parse_since("2025-06-01", now).unwrap(), datetime!(2025-06-01 00:00 UTC));
+        assert!(parse_since("7", now).is_err());
+        assert!(parse_since("soon", now).is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        let entries = [
+            entry(datetime!(2025-05-20 09:00 UTC), "old", "/src/a", Some(1.0)),
+            entry(datetime!(2025-06-02 09:00 UTC), "1", "/src/a", Some(0.25)),
+            entry(datetime!(2025-06-03 09:00 UTC), "1", "/src/a", Some(0.25)),
+            entry(datetime!(2025-06-08 09:00 UTC), "2", "/src/a", None),
+            entry(datetime!(2025-06-09 09:00 UTC), "3", "/src/a", None),
+            entry(datetime!(2025-06-10 09:00 UTC), "4", "/src/b,c", Some(0.5)),
+        ];
+        let rows = report(&entries, datetime!(2025-06-01 00:00 UTC));
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].week, "2025-06-02");
+        assert_eq!(rows[0].sessions, 2);
+        assert_eq!(rows[0].requests, 3);
+        assert_eq!(rows[0].input_tokens, 3000);
+        assert_eq!(rows[0].cost, Some(0.5));
+        assert_eq!(rows[1].week, "2025-06-09");
+        assert_eq!(rows[1].cost, None);
+        assert_eq!(rows[2].workspace, PathBuf::from("/src/b,c"));
+
+        let csv = csv(&rows);
+        assert!(csv.contains("2025-06-02,/src/a,openai,2,3,3000,300,0.5000\n"));
+        assert!(csv.contains("2025-06-09,/src/a,openai,1,1,1000,100,\n"));
+        assert!(csv.contains(",\"/src/b,c\","));
+    }
+}
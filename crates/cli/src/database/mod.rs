@@ -4,6 +4,7 @@ use std::ops::Deref;
 use std::path::Path;
 use std::str::FromStr;
 use std::sync::PoisonError;
+use std::time::Duration;
 
 use aws_sdk_cognitoidentity::primitives::DateTimeFormat;
 use aws_sdk_cognitoidentity::types::Credentials;
@@ -13,6 +14,8 @@ use rusqlite::types::FromSql;
 use rusqlite::{
     Connection,
     Error,
+    ErrorCode,
+    OpenFlags,
     ToSql,
     params,
 };
@@ -30,10 +33,14 @@ use thiserror::Error;
 use tracing::{
     info,
     trace,
+    warn,
 };
 use uuid::Uuid;
 
-use crate::cli::ConversationState;
+use crate::cli::{
+    ConversationState,
+    Schedule,
+};
 use crate::util::directories::{
     DirectoryError,
     database_path,
@@ -61,6 +68,12 @@ const IDC_REGION_KEY: &str = "auth.idc.region";
 const CUSTOMIZATION_STATE_KEY: &str = "api.selectedCustomization";
 const ROTATING_TIP_KEY: &str = "chat.greeting.rotating_tips_current_index";
 
+/// How long a connection waits for other q processes to finish writing before giving up.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long opening the database waits for the write lock before falling back to read-only, kept
+/// short since every command opens the database.
+const LOCK_PROBE_TIMEOUT: Duration = Duration::from_millis(250);
+
 const MIGRATIONS: &[Migration] = migrations![
     "000_migration_table",
     "001_history_table",
@@ -69,7 +82,8 @@ const MIGRATIONS: &[Migration] = migrations![
     "004_state_table",
     "005_auth_table",
     "006_make_state_blob",
-    "007_conversations_table"
+    "007_conversations_table",
+    "008_schedules_table"
 ];
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -141,8 +155,27 @@ pub enum DatabaseError {
     StringFromUtf8(#[from] std::string::FromUtf8Error),
     #[error(transparent)]
     StrFromUtf8(#[from] std::str::Utf8Error),
+    #[error(transparent)]
+    TomlError(#[from] toml::de::Error),
     #[error("`{}` is not a valid setting", .0)]
     InvalidSetting(String),
+    #[error("invalid value {value} for `{key}`, expected {expected}")]
+    InvalidSettingValue {
+        key: String,
+        value: String,
+        expected: String,
+    },
+    #[error("the database is open read-only")]
+    ReadOnly,
+}
+
+/// Why the database was opened read-only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadOnlyReason {
+    /// Another process kept the database locked.
+    Locked,
+    /// The database file or its filesystem isn't writable.
+    NotWritable,
 }
 
 impl<T> From<PoisonError<T>> for DatabaseError {
@@ -159,6 +192,8 @@ pub enum Table {
     Conversations,
     /// The auth table contains SSO and Builder ID credentials.
     Auth,
+    /// The schedules table contains the recurring tasks of `q schedule`.
+    Schedules,
 }
 
 impl std::fmt::Display for Table {
@@ -167,6 +202,7 @@ impl std::fmt::Display for Table {
             Table::State => write!(f, "state"),
             Table::Conversations => write!(f, "conversations"),
             Table::Auth => write!(f, "auth_kv"),
+            Table::Schedules => write!(f, "schedules"),
         }
     }
 }
@@ -181,6 +217,8 @@ struct Migration {
 pub struct Database {
     pool: Pool<SqliteConnectionManager>,
     pub settings: Settings,
+    /// Set when the database couldn't be opened for writing, in which case nothing is written.
+    read_only: Option<ReadOnlyReason>,
 }
 
 impl Database {
@@ -190,6 +228,7 @@ impl Database {
                 return Self {
                     pool: Pool::builder().build(SqliteConnectionManager::memory()).unwrap(),
                     settings: Settings::new().await?,
+                    read_only: None,
                 }
                 .migrate();
             },
@@ -203,7 +242,28 @@ impl Database {
             }
         }
 
-        let conn = SqliteConnectionManager::file(&path);
+        // Other q processes may be writing, fall back to reading the database rather than failing
+        // if they keep it locked or it can't be written at all.
+        let read_only = match check_writable(&path, LOCK_PROBE_TIMEOUT) {
+            Ok(()) => None,
+            Err(err) => match read_only_reason(&err) {
+                Some(reason) => {
+                    warn!(
+                        ?err,
+                        ?path,
+                        ?reason,
+                        "The database isn't writable, opening it read-only"
+                    );
+                    Some(reason)
+                },
+                None => return Err(DbOpenError(err.to_string()).into()),
+            },
+        };
+        let conn = match read_only {
+            Some(_) => SqliteConnectionManager::file(&path).with_flags(OpenFlags::SQLITE_OPEN_READ_ONLY),
+            None => SqliteConnectionManager::file(&path),
+        }
+        .with_init(|conn| conn.busy_timeout(BUSY_TIMEOUT));
         let pool = Pool::builder().build(conn)?;
 
         // Check the unix permissions of the database file, set them to 0600 if they are not
@@ -219,12 +279,20 @@ impl Database {
             }
         }
 
-        Ok(Self {
+        let database = Self {
             pool,
             settings: Settings::new().await?,
+            read_only,
+        };
+        match read_only {
+            Some(_) => Ok(database),
+            None => Ok(database.migrate().map_err(|e| DbOpenError(e.to_string()))?),
         }
-        .migrate()
-        .map_err(|e| DbOpenError(e.to_string()))?)
+    }
+
+    /// Why the database was opened read-only, if it was.
+    pub fn read_only_reason(&self) -> Option<ReadOnlyReason> {
+        self.read_only
     }
 
     /// Get all entries for dumping the persistent application state.
@@ -336,6 +404,54 @@ impl Database {
         self.set_json_entry(Table::Conversations, path, state)
     }
 
+    /// Get every persisted chat conversation, keyed by the path it belongs to. Conversations that
+    /// fail to deserialize are skipped.
+    pub fn get_all_conversations(&self) -> Result<Vec<(String, ConversationState)>, DatabaseError> {
+        let mut conversations = Vec::new();
+        for (path, value) in self.all_entries(Table::Conversations)? {
+            let Value::String(json) = value else { continue };
+            match serde_json::from_str(&json) {
+                Ok(state) => conversations.push((path, state)),
+                Err(err) => warn!(?err, path, "failed to deserialize conversation"),
+            }
+        }
+        Ok(conversations)
+    }
+
+    /// Delete the chat conversation belonging to a path.
+    pub fn delete_conversation_by_path(&mut self, path: impl AsRef<str>) -> Result<(), DatabaseError> {
+        self.delete_entry(Table::Conversations, path)
+    }
+
+    /// Get a scheduled task by name.
+    pub fn get_schedule(&self, name: &str) -> Result<Option<Schedule>, DatabaseError> {
+        self.get_json_entry(Table::Schedules, name)
+    }
+
+    /// Add or replace a scheduled task.
+    pub fn set_schedule(&mut self, schedule: &Schedule) -> Result<usize, DatabaseError> {
+        self.set_json_entry(Table::Schedules, &schedule.name, schedule)
+    }
+
+    /// Get every scheduled task, sorted by name. Tasks that fail to deserialize are skipped.
+    pub fn get_all_schedules(&self) -> Result<Vec<Schedule>, DatabaseError> {
+        let mut schedules: Vec<Schedule> = Vec::new();
+        for (name, value) in self.all_entries(Table::Schedules)? {
+            let Value::String(json) = value else { continue };
+            match serde_json::from_str(&json) {
+                Ok(schedule) => schedules.push(schedule),
+                Err(err) => warn!(?err, name, "failed to deserialize schedule"),
+            }
+        }
+        schedules.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(schedules)
+    }
+
+    /// Delete a scheduled task by name.
+    pub fn delete_schedule(&mut self, name: &str) -> Result<(), DatabaseError> {
+        self.delete_entry(Table::Schedules, name)
+    }
+
     pub async fn get_secret(&self, key: &str) -> Result<Option<Secret>, DatabaseError> {
         trace!(key, "getting secret");
         Ok(self.get_entry::<String>(Table::Auth, key)?.map(Into::into))
@@ -394,6 +510,9 @@ impl Database {
     }
 
     fn set_entry(&self, table: Table, key: impl AsRef<str>, value: impl ToSql) -> Result<usize, DatabaseError> {
+        if self.read_only.is_some() {
+            return Err(DatabaseError::ReadOnly);
+        }
         Ok(self.pool.get()?.execute(
             &format!("INSERT OR REPLACE INTO {table} (key, value) VALUES (?1, ?2)"),
             params![key.as_ref(), value],
@@ -421,6 +540,9 @@ impl Database {
     }
 
     fn delete_entry(&self, table: Table, key: impl AsRef<str>) -> Result<(), DatabaseError> {
+        if self.read_only.is_some() {
+            return Err(DatabaseError::ReadOnly);
+        }
         self.pool
             .get()?
             .execute(&format!("DELETE FROM {table} WHERE key = ?1"), [key.as_ref()])?;
@@ -446,6 +568,24 @@ impl Database {
     }
 }
 
+/// Opens the database and takes the write lock, waiting up to `timeout` for other processes to
+/// release it.
+fn check_writable(path: &Path, timeout: Duration) -> Result<(), rusqlite::Error> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(timeout)?;
+    conn.execute_batch("BEGIN IMMEDIATE; COMMIT;")
+}
+
+/// Whether opening the database for writing failed because of another process or the permissions
+/// of the file, rather than because it is corrupted.
+fn read_only_reason(err: &rusqlite::Error) -> Option<ReadOnlyReason> {
+    match err.sqlite_error_code()? {
+        ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked => Some(ReadOnlyReason::Locked),
+        ErrorCode::ReadOnly => Some(ReadOnlyReason::NotWritable),
+        _ => None,
+    }
+}
+
 fn max_migration_version<C: Deref<Target = Connection>>(conn: &C) -> Option<i64> {
     let mut stmt = conn.prepare("SELECT MAX(version) FROM migrations").ok()?;
     stmt.query_row([], |row| row.get(0)).ok()
@@ -568,6 +708,52 @@ mod tests {
         assert!(db.get_entry::<bool>(Table::State, "bool").unwrap().is_some());
     }
 
+    #[test]
+    fn test_check_writable_while_locked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.sqlite3");
+        assert!(check_writable(&path, Duration::from_millis(10)).is_ok());
+
+        let other = Connection::open(&path).unwrap();
+        other.execute_batch("BEGIN EXCLUSIVE;").unwrap();
+        let err = check_writable(&path, Duration::from_millis(10)).unwrap_err();
+        assert_eq!(read_only_reason(&err), Some(ReadOnlyReason::Locked), "{err}");
+
+        other.execute_batch("COMMIT;").unwrap();
+        assert!(check_writable(&path, Duration::from_millis(10)).is_ok());
+    }
+
+    #[test]
+    fn test_read_only_reason() {
+        let err = |code| rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(code), None);
+        assert_eq!(
+            read_only_reason(&err(rusqlite::ffi::SQLITE_BUSY)),
+            Some(ReadOnlyReason::Locked)
+        );
+        assert_eq!(
+            read_only_reason(&err(rusqlite::ffi::SQLITE_READONLY)),
+            Some(ReadOnlyReason::NotWritable)
+        );
+        assert_eq!(read_only_reason(&err(rusqlite::ffi::SQLITE_CORRUPT)), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_only_refuses_writes() {
+        let db = Database {
+            read_only: Some(ReadOnlyReason::Locked),
+            ..Database::new().await.unwrap()
+        };
+        assert!(matches!(
+            db.set_entry(Table::State, "test", "test"),
+            Err(DatabaseError::ReadOnly)
+        ));
+        assert!(matches!(
+            db.delete_entry(Table::State, "test"),
+            Err(DatabaseError::ReadOnly)
+        ));
+        assert!(db.get_entry::<String>(Table::State, "test").unwrap().is_none());
+    }
+
     #[tokio::test]
     #[ignore = "not on ci"]
     async fn test_set_password() {
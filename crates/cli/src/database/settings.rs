@@ -1,21 +1,27 @@
 use std::fmt::Display;
 use std::io::SeekFrom;
+use std::path::{
+    Path,
+    PathBuf,
+};
 
 use fd_lock::RwLock;
 use serde_json::{
     Map,
     Value,
 };
+use strum::IntoEnumIterator;
 use tokio::fs::File;
 use tokio::io::{
     AsyncReadExt,
     AsyncSeekExt,
     AsyncWriteExt,
 };
+use tracing::warn;
 
 use super::DatabaseError;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum::EnumIter)]
 pub enum Setting {
     TelemetryEnabled,
     OldClientId,
@@ -36,6 +42,56 @@ pub enum Setting {
     OpenAiApiKey,
     OpenAiModel,
     OpenAiProvider,
+    ServerModelAliases,
+    ChatModelCapabilities,
+    ChatWatchFiles,
+    ChatSpeechRecordCommand,
+    ChatSpeechTranscribeCommand,
+    ChatSpeechEndpoint,
+    ChatSpeechModel,
+    ChatTrustedTools,
+    ChatContextPaths,
+    ChatOfflineBaseUrl,
+    ChatOfflineModel,
+    ChatCompressToolResultsAfter,
+    NetworkProxy,
+    NetworkNoProxy,
+    NetworkCaBundle,
+    ChatWebAllowedDomains,
+    ChatWebDeniedDomains,
+    ChatWebAllowPrivateNetwork,
+    ChatWebHeadlessBrowser,
+    ChatWebCacheTtl,
+    ChatUseAwsDeniedServices,
+    ChatUseAwsAlwaysAskCostIncurring,
+    ChatNotifyWebhookUrl,
+    ChatNotifyWebhookFormat,
+    ChatNotifyWebhookTemplate,
+    ChatBlockCodeReferences,
+    ChatToolTimeouts,
+    ChatToolMaxOutputBytes,
+    ChatAuditLog,
+    ChatPolicyFile,
+    ChatToolEnvAllowlist,
+    ChatUsageLedger,
+    ChatModelPricing,
+    ChatGitContext,
+    ChatMaxToolCallsPerTurn,
+    ChatMaxTurnsPerRun,
+    ChatToolLoopThreshold,
+    ChatShell,
+    ChatShellSourceProfile,
+    ChatRemoteHost,
+    ChatContainer,
+    ChatLanguage,
+    ChatAccessibilityMode,
+    ChatTypewriter,
+    ChatShareTarget,
+    ChatToolCallPatterns,
+    McpDisabledScopes,
+    McpSampling,
+    McpSamplingTokenBudget,
+    ChatUseAwsServiceModelsPath,
 }
 
 impl AsRef<str> for Setting {
@@ -59,6 +115,469 @@ impl AsRef<str> for Setting {
             Self::OpenAiApiKey => "openai.api.key",
             Self::OpenAiModel => "openai.model",
             Self::OpenAiProvider => "openai.provider",
+            Self::ServerModelAliases => "server.modelAliases",
+            Self::ChatModelCapabilities => "chat.modelCapabilities",
+            Self::ChatWatchFiles => "chat.watchFiles",
+            Self::ChatSpeechRecordCommand => "chat.speech.recordCommand",
+            Self::ChatSpeechTranscribeCommand => "chat.speech.transcribeCommand",
+            Self::ChatSpeechEndpoint => "chat.speech.endpoint",
+            Self::ChatSpeechModel => "chat.speech.model",
+            Self::ChatTrustedTools => "chat.trustedTools",
+            Self::ChatContextPaths => "chat.contextPaths",
+            Self::ChatOfflineBaseUrl => "chat.offline.baseUrl",
+            Self::ChatOfflineModel => "chat.offline.model",
+            Self::ChatCompressToolResultsAfter => "chat.compressToolResultsAfter",
+            Self::NetworkProxy => "network.proxy",
+            Self::NetworkNoProxy => "network.noProxy",
+            Self::NetworkCaBundle => "network.caBundle",
+            Self::ChatWebAllowedDomains => "chat.web.allowedDomains",
+            Self::ChatWebDeniedDomains => "chat.web.deniedDomains",
+            Self::ChatWebAllowPrivateNetwork => "chat.web.allowPrivateNetwork",
+            Self::ChatWebHeadlessBrowser => "chat.web.headlessBrowser",
+            Self::ChatWebCacheTtl => "chat.web.cacheTtl",
+            Self::ChatUseAwsDeniedServices => "chat.useAws.deniedServices",
+            Self::ChatUseAwsAlwaysAskCostIncurring => "chat.useAws.alwaysAskCostIncurring",
+            Self::ChatNotifyWebhookUrl => "chat.notify.webhookUrl",
+            Self::ChatNotifyWebhookFormat => "chat.notify.webhookFormat",
+            Self::ChatNotifyWebhookTemplate => "chat.notify.webhookTemplate",
+            Self::ChatBlockCodeReferences => "chat.blockCodeReferences",
+            Self::ChatToolTimeouts => "chat.toolTimeouts",
+            Self::ChatToolMaxOutputBytes => "chat.toolMaxOutputBytes",
+            Self::ChatAuditLog => "chat.auditLog",
+            Self::ChatPolicyFile => "chat.policyFile",
+            Self::ChatToolEnvAllowlist => "chat.toolEnvAllowlist",
+            Self::ChatUsageLedger => "chat.usageLedger",
+            Self::ChatModelPricing => "chat.modelPricing",
+            Self::ChatGitContext => "chat.gitContext",
+            Self::ChatMaxToolCallsPerTurn => "chat.maxToolCallsPerTurn",
+            Self::ChatMaxTurnsPerRun => "chat.maxTurnsPerRun",
+            Self::ChatToolLoopThreshold => "chat.toolLoopThreshold",
+            Self::ChatShell => "chat.shell",
+            Self::ChatShellSourceProfile => "chat.shellSourceProfile",
+            Self::ChatRemoteHost => "chat.remoteHost",
+            Self::ChatContainer => "chat.container",
+            Self::ChatLanguage => "chat.language",
+            Self::ChatAccessibilityMode => "chat.accessibilityMode",
+            Self::ChatTypewriter => "chat.typewriter",
+            Self::ChatShareTarget => "chat.shareTarget",
+            Self::ChatToolCallPatterns => "chat.toolCallPatterns",
+            Self::McpDisabledScopes => "mcp.disabledScopes",
+            Self::McpSampling => "mcp.sampling",
+            Self::McpSamplingTokenBudget => "mcp.samplingTokenBudget",
+            Self::ChatUseAwsServiceModelsPath => "chat.useAws.serviceModelsPath",
+        }
+    }
+}
+
+impl Setting {
+    /// Whether the setting can be overridden by the workspace settings file. The file is checked
+    /// into repositories, so settings that could leak credentials to another endpoint or run
+    /// arbitrary commands are only read from the global settings.
+    pub fn is_workspace_overridable(&self) -> bool {
+        matches!(
+            self,
+            Self::EnabledThinking
+                | Self::ChatGreetingEnabled
+                | Self::ApiTimeout
+                | Self::ChatEditMode
+                | Self::ChatEnableNotifications
+                | Self::McpInitTimeout
+                | Self::OpenAiProvider
+                | Self::OpenAiModel
+                | Self::ChatModelCapabilities
+                | Self::ChatModelPricing
+                | Self::ChatWatchFiles
+                | Self::ChatTrustedTools
+                | Self::ChatContextPaths
+                | Self::ChatOfflineModel
+                | Self::ChatCompressToolResultsAfter
+                | Self::ChatWebCacheTtl
+                | Self::ChatBlockCodeReferences
+                | Self::ChatToolTimeouts
+                | Self::ChatToolMaxOutputBytes
+                | Self::ChatGitContext
+                | Self::ChatMaxToolCallsPerTurn
+                | Self::ChatMaxTurnsPerRun
+                | Self::ChatToolLoopThreshold
+        )
+    }
+
+    pub fn value_type(&self) -> SettingType {
+        match self {
+            Self::TelemetryEnabled
+            | Self::ShareCodeWhispererContent
+            | Self::EnabledThinking
+            | Self::ChatGreetingEnabled
+            | Self::ChatEnableNotifications
+            | Self::McpLoadedBefore
+            | Self::ChatWatchFiles
+            | Self::ChatWebAllowPrivateNetwork
+            | Self::ChatWebHeadlessBrowser
+            | Self::ChatUseAwsAlwaysAskCostIncurring
+            | Self::ChatBlockCodeReferences
+            | Self::ChatAuditLog
+            | Self::ChatUsageLedger
+            | Self::ChatGitContext
+            | Self::ChatShellSourceProfile
+            | Self::ChatAccessibilityMode
+            | Self::ChatTypewriter => SettingType::Bool,
+            Self::ApiTimeout
+            | Self::McpInitTimeout
+            | Self::McpNoInteractiveTimeout
+            | Self::ChatCompressToolResultsAfter
+            | Self::ChatWebCacheTtl
+            | Self::ChatMaxToolCallsPerTurn
+            | Self::ChatMaxTurnsPerRun
+            | Self::ChatToolLoopThreshold
+            | Self::McpSamplingTokenBudget => SettingType::Integer,
+            Self::ChatEditMode => SettingType::Enum(&["emacs", "vi", "vim"]),
+            Self::ChatShell => SettingType::Enum(&["bash", "zsh", "fish", "pwsh"]),
+            Self::ChatNotifyWebhookFormat => SettingType::Enum(&["slack", "teams", "generic"]),
+            Self::McpSampling => SettingType::Enum(&["ask", "allow", "deny"]),
+            Self::OpenAiApiBaseUrl
+            | Self::ChatSpeechEndpoint
+            | Self::ChatOfflineBaseUrl
+            | Self::ChatNotifyWebhookUrl => SettingType::Url,
+            Self::ApiCodeWhispererService
+            | Self::ApiQService
+            | Self::ServerModelAliases
+            | Self::ChatModelCapabilities
+            | Self::ChatModelPricing
+            | Self::ChatToolTimeouts
+            | Self::ChatToolMaxOutputBytes => SettingType::Object,
+            Self::ChatTrustedTools
+            | Self::ChatContextPaths
+            | Self::NetworkNoProxy
+            | Self::ChatWebAllowedDomains
+            | Self::ChatWebDeniedDomains
+            | Self::ChatUseAwsDeniedServices
+            | Self::ChatToolEnvAllowlist
+            | Self::ChatToolCallPatterns
+            | Self::McpDisabledScopes => SettingType::StringList,
+            Self::OldClientId
+            | Self::SkimCommandKey
+            | Self::OpenAiApiKey
+            | Self::OpenAiModel
+            | Self::OpenAiProvider
+            | Self::ChatSpeechRecordCommand
+            | Self::ChatSpeechTranscribeCommand
+            | Self::ChatSpeechModel
+            | Self::ChatOfflineModel
+            | Self::NetworkProxy
+            | Self::NetworkCaBundle
+            | Self::ChatNotifyWebhookTemplate
+            | Self::ChatPolicyFile
+            | Self::ChatRemoteHost
+            | Self::ChatContainer
+            | Self::ChatLanguage
+            | Self::ChatShareTarget
+            | Self::ChatUseAwsServiceModelsPath => SettingType::String,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::TelemetryEnabled => "Send usage telemetry",
+            Self::OldClientId => "Legacy telemetry client id",
+            Self::ShareCodeWhispererContent => "Share content with AWS to improve the service",
+            Self::EnabledThinking => "Enable the thinking tool for complex reasoning",
+            Self::SkimCommandKey => "Key used with Ctrl to open the fuzzy command search",
+            Self::ChatGreetingEnabled => "Show the greeting when starting a chat",
+            Self::ApiTimeout => "API request timeout, in milliseconds",
+            Self::ChatEditMode => "Line editing mode of the chat prompt",
+            Self::ChatEnableNotifications => "Ring the terminal bell when a response or approval is pending",
+            Self::ApiCodeWhispererService => "Override of the CodeWhisperer service endpoint and region",
+            Self::ApiQService => "Override of the Q service endpoint and region",
+            Self::McpInitTimeout => "Time to wait for MCP servers to load in interactive mode, in milliseconds",
+            Self::McpNoInteractiveTimeout => {
+                "Time to wait for MCP servers to load in non-interactive mode, in milliseconds"
+            },
+            Self::McpLoadedBefore => "Whether MCP servers were loaded before",
+            Self::OpenAiApiBaseUrl => "Base URL of the OpenAI compatible API",
+            Self::OpenAiApiKey => "API key of the OpenAI compatible API",
+            Self::OpenAiModel => "Model used with OpenAI compatible providers",
+            Self::OpenAiProvider => "Chat provider: amazon-q, openai or a custom provider name",
+            Self::ServerModelAliases => "Model aliases accepted by q server, mapped to provider models",
+            Self::ChatModelCapabilities => "Context window and feature overrides per model",
+            Self::ChatWatchFiles => "Tell the model about external edits to files used in the conversation",
+            Self::ChatSpeechRecordCommand => "Command recording the microphone to {output} for /speak",
+            Self::ChatSpeechTranscribeCommand => "Command printing the transcription of {input} for /speak",
+            Self::ChatSpeechEndpoint => "OpenAI compatible transcription endpoint used instead of the command",
+            Self::ChatSpeechModel => "Model used with the transcription endpoint",
+            Self::ChatTrustedTools => "Tools trusted without --trust-tools",
+            Self::ChatContextPaths => "Paths or globs always added to the chat context",
+            Self::ChatOfflineBaseUrl => "Base URL of the local OpenAI compatible API used by q chat --offline",
+            Self::ChatOfflineModel => "Local model used by q chat --offline",
+            Self::ChatCompressToolResultsAfter => {
+                "Number of exchanges after which large tool results are replaced by a digest, 0 to disable"
+            },
+            Self::NetworkProxy => "Proxy for all requests, e.g. http://proxy:8080, instead of HTTPS_PROXY",
+            Self::NetworkNoProxy => "Hosts reached without network.proxy, e.g. localhost or .internal.example.com",
+            Self::NetworkCaBundle => "PEM file of CA certificates to trust in addition to the system ones",
+            Self::ChatWebAllowedDomains => "Domains web tools may fetch, including subdomains; any domain if unset",
+            Self::ChatWebDeniedDomains => "Domains web tools may not fetch, including subdomains",
+            Self::ChatWebAllowPrivateNetwork => {
+                "Let web tools fetch private, loopback and link-local addresses such as cloud metadata endpoints"
+            },
+            Self::ChatWebHeadlessBrowser => {
+                "Let web_browse render JavaScript and take screenshots with a local Chrome, set CHROME to its path"
+            },
+            Self::ChatWebCacheTtl => {
+                "Seconds web_browse reuses a cached page before revalidating it, 0 to disable the cache"
+            },
+            Self::ChatUseAwsDeniedServices => "AWS CLI services use_aws may not call, e.g. iam or s3",
+            Self::ChatUseAwsAlwaysAskCostIncurring => {
+                "Ask before use_aws operations that may incur cost, even if use_aws is trusted"
+            },
+            Self::ChatNotifyWebhookUrl => "Webhook posted the answer of q chat --notify and of scheduled tasks",
+            Self::ChatNotifyWebhookFormat => "Payload format of the notification webhook",
+            Self::ChatNotifyWebhookTemplate => {
+                "Text of notifications, with {{title}}, {{status}} and {{message}} replaced"
+            },
+            Self::ChatBlockCodeReferences => "Withhold code in responses that matches licensed code from a repository",
+            Self::ChatToolTimeouts => {
+                "Seconds each tool may run before it is stopped, e.g. {\"execute_bash\": 300, \"default\": 120}"
+            },
+            Self::ChatToolMaxOutputBytes => {
+                "Bytes of the output of each tool sent to the model, e.g. {\"fs_read\": 100000}"
+            },
+            Self::ChatAuditLog => "Record every tool invocation in a hash-chained log, see q audit",
+            Self::ChatPolicyFile => {
+                "YAML rules allowing, denying or prompting for tool uses, ~/.aws/amazonq/policy.yaml by default"
+            },
+            Self::ChatToolEnvAllowlist => {
+                "Environment variables passed to execute_bash and MCP servers besides PATH, HOME and the like, * globs allowed"
+            },
+            Self::ChatUsageLedger => "Record the tokens and estimated cost of chat sessions, see q usage",
+            Self::ChatModelPricing => {
+                "Price overrides per model in USD per million tokens, e.g. {\"my-model\": {\"input\": 1, \"output\": 4}}"
+            },
+            Self::ChatGitContext => {
+                "Send the git branch, its divergence from upstream and a diffstat of uncommitted changes with each message"
+            },
+            Self::ChatMaxToolCallsPerTurn => {
+                "Number of tool calls after which to ask before the model goes on without a new prompt, 0 to disable"
+            },
+            Self::ChatMaxTurnsPerRun => {
+                "Number of responses using tools in a row after which to ask before the model goes on, 0 to disable"
+            },
+            Self::ChatToolLoopThreshold => {
+                "Number of identical consecutive tool calls after which the model is stopped and the user asked, 0 to disable"
+            },
+            Self::ChatShell => "Shell running the commands of execute_bash",
+            Self::ChatShellSourceProfile => {
+                "Source your shell profile before the commands of execute_bash, rather than running them in a clean shell"
+            },
+            Self::ChatRemoteHost => {
+                "Host to run execute_bash, fs_read and fs_write on over SSH, e.g. user@devbox, instead of this machine"
+            },
+            Self::ChatContainer => {
+                "Running container to run execute_bash, fs_read and fs_write in, or devcontainer for the dev container of the current directory"
+            },
+            Self::ChatLanguage => "Language of the responses and of the messages of the CLI, as a tag like es or pt-BR",
+            Self::ChatAccessibilityMode => {
+                "Screen-reader-friendly chat output: no colors, emoji, spinners or animations, and text markers for tools and prompts"
+            },
+            Self::ChatTypewriter => "Reveal responses progressively, like a typewriter, when printed to a terminal",
+            Self::ChatShareTarget => {
+                "Where /share uploads transcripts: gist for a secret GitHub gist, or a presigned S3 URL to PUT them to"
+            },
+            Self::McpDisabledScopes => "MCP config scopes whose servers aren't started: global or workspace",
+            Self::ChatToolCallPatterns => {
+                "How models without function calling write tool calls in their responses: tags like <tool_call> or fences like ```json. Once set, the responses of the other models are checked too"
+            },
+            Self::McpSampling => "Whether MCP servers can ask the model for completions: ask, allow or deny",
+            Self::McpSamplingTokenBudget => "Tokens the sampling requests of MCP servers can use per chat session",
+            Self::ChatUseAwsServiceModelsPath => {
+                "Directory of the botocore service models use_aws calls are checked against, by default the ones of the AWS CLI"
+            },
+        }
+    }
+
+    pub fn default_value(&self) -> Option<Value> {
+        Some(match self {
+            Self::TelemetryEnabled
+            | Self::ShareCodeWhispererContent
+            | Self::ChatGreetingEnabled
+            | Self::ChatUseAwsAlwaysAskCostIncurring
+            | Self::ChatUsageLedger => true.into(),
+            Self::EnabledThinking
+            | Self::ChatEnableNotifications
+            | Self::McpLoadedBefore
+            | Self::ChatWatchFiles
+            | Self::ChatWebAllowPrivateNetwork
+            | Self::ChatWebHeadlessBrowser
+            | Self::ChatBlockCodeReferences
+            | Self::ChatAuditLog
+            | Self::ChatGitContext
+            | Self::ChatShellSourceProfile => false.into(),
+            Self::SkimCommandKey => "s".into(),
+            Self::ApiTimeout => 300_000.into(),
+            Self::ChatEditMode => "emacs".into(),
+            Self::McpInitTimeout => 5000.into(),
+            Self::McpNoInteractiveTimeout => 30_000.into(),
+            Self::OpenAiApiBaseUrl => "https://api.openai.com/v1".into(),
+            Self::OpenAiModel => "gpt-3.5-turbo".into(),
+            Self::OpenAiProvider => "amazon-q".into(),
+            Self::ChatSpeechRecordCommand => "sox -q -d -r 16000 -c 1 -b 16 {output}".into(),
+            Self::ChatSpeechTranscribeCommand => "whisper-cli -np -nt -f {input}".into(),
+            Self::ChatSpeechModel => "whisper-1".into(),
+            Self::ChatOfflineBaseUrl => "http://localhost:11434/v1".into(),
+            Self::ChatOfflineModel => "llama3.2".into(),
+            Self::ChatCompressToolResultsAfter => 10.into(),
+            Self::ChatWebCacheTtl => 3600.into(),
+            Self::ChatMaxToolCallsPerTurn => 50.into(),
+            Self::ChatMaxTurnsPerRun => 25.into(),
+            Self::ChatToolLoopThreshold => 3.into(),
+            Self::ChatShell => "bash".into(),
+            Self::ChatNotifyWebhookFormat => "generic".into(),
+            Self::ChatNotifyWebhookTemplate => "{{title}} {{status}}\n\n{{message}}".into(),
+            Self::ChatShareTarget => "gist".into(),
+            Self::ChatToolCallPatterns => vec!["<tool_call>", "<function_call>", "```json"].into(),
+            Self::McpSampling => "ask".into(),
+            Self::McpSamplingTokenBudget => 50_000.into(),
+            Self::OldClientId
+            | Self::ApiCodeWhispererService
+            | Self::ApiQService
+            | Self::OpenAiApiKey
+            | Self::ServerModelAliases
+            | Self::ChatModelCapabilities
+            | Self::ChatModelPricing
+            | Self::ChatToolTimeouts
+            | Self::ChatToolMaxOutputBytes
+            | Self::ChatSpeechEndpoint
+            | Self::ChatTrustedTools
+            | Self::ChatContextPaths
+            | Self::NetworkProxy
+            | Self::NetworkNoProxy
+            | Self::NetworkCaBundle
+            | Self::ChatWebAllowedDomains
+            | Self::ChatWebDeniedDomains
+            | Self::ChatUseAwsDeniedServices
+            | Self::ChatNotifyWebhookUrl
+            | Self::ChatPolicyFile
+            | Self::ChatToolEnvAllowlist
+            | Self::ChatRemoteHost
+            | Self::ChatContainer
+            | Self::ChatLanguage
+            | Self::ChatAccessibilityMode
+            | Self::ChatTypewriter
+            | Self::McpDisabledScopes
+            | Self::ChatUseAwsServiceModelsPath => return None,
+        })
+    }
+
+    /// Settings holding state rather than configuration, hidden from `q settings list`.
+    pub fn is_internal(&self) -> bool {
+        matches!(self, Self::OldClientId | Self::McpLoadedBefore)
+    }
+
+    /// Settings whose value is masked when listed.
+    pub fn is_secret(&self) -> bool {
+        // Proxy URLs can hold credentials, and anyone with a webhook or presigned URL can post to it.
+        matches!(
+            self,
+            Self::OpenAiApiKey | Self::NetworkProxy | Self::ChatNotifyWebhookUrl | Self::ChatShareTarget
+        )
+    }
+
+    /// Previous names of the setting, still read from settings files and accepted by
+    /// `q settings`.
+    pub fn deprecated_aliases(&self) -> &'static [&'static str] {
+        match self {
+            Self::OpenAiApiBaseUrl => &["openai.baseUrl"],
+            Self::OpenAiApiKey => &["openai.apiKey"],
+            _ => &[],
+        }
+    }
+
+    pub fn validate(&self, value: &Value) -> Result<(), DatabaseError> {
+        let valid = match self.value_type() {
+            SettingType::Bool => value.is_boolean(),
+            SettingType::Integer => value.as_i64().is_some_and(|i| i >= 0),
+            SettingType::String => value.is_string(),
+            SettingType::Url => value
+                .as_str()
+                .and_then(|s| url::Url::parse(s).ok())
+                .is_some_and(|url| matches!(url.scheme(), "http" | "https")),
+            SettingType::Enum(variants) => value.as_str().is_some_and(|s| variants.contains(&s)),
+            SettingType::StringList => value
+                .as_array()
+                .is_some_and(|values| values.iter().all(Value::is_string)),
+            SettingType::Object => value.is_object(),
+        };
+
+        match valid {
+            true => Ok(()),
+            false => Err(DatabaseError::InvalidSettingValue {
+                key: self.to_string(),
+                value: value.to_string(),
+                expected: self.value_type().to_string(),
+            }),
+        }
+    }
+
+    /// Parses a value given on the command line. Strings are taken as is for string settings, so
+    /// that e.g. a model named `4` is not turned into a number, otherwise the input is parsed as
+    /// JSON. Comma separated values are accepted for lists.
+    pub fn parse_value(&self, input: &str) -> Result<Value, DatabaseError> {
+        let value = match self.value_type() {
+            SettingType::String | SettingType::Url | SettingType::Enum(_) => Value::String(input.to_string()),
+            SettingType::StringList if !input.trim_start().starts_with('[') => input
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| Value::String(s.to_string()))
+                .collect(),
+            _ => serde_json::from_str(input).unwrap_or_else(|_| Value::String(input.to_string())),
+        };
+        self.validate(&value)?;
+        Ok(value)
+    }
+}
+
+/// The type of the value of a [Setting].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingType {
+    Bool,
+    /// A non-negative integer.
+    Integer,
+    String,
+    /// An http or https URL.
+    Url,
+    Enum(&'static [&'static str]),
+    StringList,
+    Object,
+}
+
+impl Display for SettingType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bool => f.write_str("boolean"),
+            Self::Integer => f.write_str("integer"),
+            Self::String => f.write_str("string"),
+            Self::Url => f.write_str("url"),
+            Self::Enum(variants) => write!(f, "one of {}", variants.join(", ")),
+            Self::StringList => f.write_str("list of strings"),
+            Self::Object => f.write_str("object"),
+        }
+    }
+}
+
+/// Where the value of a setting comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingSource {
+    Workspace,
+    Global,
+    Default,
+}
+
+impl Display for SettingSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Workspace => f.write_str("workspace"),
+            Self::Global => f.write_str("global"),
+            Self::Default => f.write_str("default"),
         }
     }
 }
@@ -92,20 +611,99 @@ impl TryFrom<&str> for Setting {
             "openai.api.key" => Ok(Self::OpenAiApiKey),
             "openai.model" => Ok(Self::OpenAiModel),
             "openai.provider" => Ok(Self::OpenAiProvider),
-            _ => Err(DatabaseError::InvalidSetting(value.to_string())),
+            "server.modelAliases" => Ok(Self::ServerModelAliases),
+            "chat.modelCapabilities" => Ok(Self::ChatModelCapabilities),
+            "chat.watchFiles" => Ok(Self::ChatWatchFiles),
+            "chat.speech.recordCommand" => Ok(Self::ChatSpeechRecordCommand),
+            "chat.speech.transcribeCommand" => Ok(Self::ChatSpeechTranscribeCommand),
+            "chat.speech.endpoint" => Ok(Self::ChatSpeechEndpoint),
+            "chat.speech.model" => Ok(Self::ChatSpeechModel),
+            "chat.trustedTools" => Ok(Self::ChatTrustedTools),
+            "chat.contextPaths" => Ok(Self::ChatContextPaths),
+            "chat.offline.baseUrl" => Ok(Self::ChatOfflineBaseUrl),
+            "chat.offline.model" => Ok(Self::ChatOfflineModel),
+            "chat.compressToolResultsAfter" => Ok(Self::ChatCompressToolResultsAfter),
+            "network.proxy" => Ok(Self::NetworkProxy),
+            "network.noProxy" => Ok(Self::NetworkNoProxy),
+            "network.caBundle" => Ok(Self::NetworkCaBundle),
+            "chat.web.allowedDomains" => Ok(Self::ChatWebAllowedDomains),
+            "chat.web.deniedDomains" => Ok(Self::ChatWebDeniedDomains),
+            "chat.web.allowPrivateNetwork" => Ok(Self::ChatWebAllowPrivateNetwork),
+            "chat.web.headlessBrowser" => Ok(Self::ChatWebHeadlessBrowser),
+            "chat.web.cacheTtl" => Ok(Self::ChatWebCacheTtl),
+            "chat.useAws.deniedServices" => Ok(Self::ChatUseAwsDeniedServices),
+            "chat.useAws.alwaysAskCostIncurring" => Ok(Self::ChatUseAwsAlwaysAskCostIncurring),
+            "chat.notify.webhookUrl" => Ok(Self::ChatNotifyWebhookUrl),
+            "chat.notify.webhookFormat" => Ok(Self::ChatNotifyWebhookFormat),
+            "chat.notify.webhookTemplate" => Ok(Self::ChatNotifyWebhookTemplate),
+            "chat.blockCodeReferences" => Ok(Self::ChatBlockCodeReferences),
+            "chat.toolTimeouts" => Ok(Self::ChatToolTimeouts),
+            "chat.toolMaxOutputBytes" => Ok(Self::ChatToolMaxOutputBytes),
+            "chat.auditLog" => Ok(Self::ChatAuditLog),
+            "chat.policyFile" => Ok(Self::ChatPolicyFile),
+            "chat.toolEnvAllowlist" => Ok(Self::ChatToolEnvAllowlist),
+            "chat.usageLedger" => Ok(Self::ChatUsageLedger),
+            "chat.modelPricing" => Ok(Self::ChatModelPricing),
+            "chat.gitContext" => Ok(Self::ChatGitContext),
+            "chat.maxToolCallsPerTurn" => Ok(Self::ChatMaxToolCallsPerTurn),
+            "chat.maxTurnsPerRun" => Ok(Self::ChatMaxTurnsPerRun),
+            "chat.toolLoopThreshold" => Ok(Self::ChatToolLoopThreshold),
+            "chat.shell" => Ok(Self::ChatShell),
+            "chat.shellSourceProfile" => Ok(Self::ChatShellSourceProfile),
+            "chat.remoteHost" => Ok(Self::ChatRemoteHost),
+            "chat.container" => Ok(Self::ChatContainer),
+            "chat.language" => Ok(Self::ChatLanguage),
+            "chat.accessibilityMode" => Ok(Self::ChatAccessibilityMode),
+            "chat.typewriter" => Ok(Self::ChatTypewriter),
+            "chat.shareTarget" => Ok(Self::ChatShareTarget),
+            "chat.toolCallPatterns" => Ok(Self::ChatToolCallPatterns),
+            "mcp.disabledScopes" => Ok(Self::McpDisabledScopes),
+            "mcp.sampling" => Ok(Self::McpSampling),
+            "mcp.samplingTokenBudget" => Ok(Self::McpSamplingTokenBudget),
+            "chat.useAws.serviceModelsPath" => Ok(Self::ChatUseAwsServiceModelsPath),
+            _ => Self::iter()
+                .find(|setting| setting.deprecated_aliases().contains(&value))
+                .ok_or_else(|| DatabaseError::InvalidSetting(value.to_string())),
         }
     }
 }
 
+/// The settings file of a workspace, relative to the workspace root.
+pub const WORKSPACE_SETTINGS_PATH: &str = ".amazonq/settings.toml";
+
+/// Settings resolved from the global settings file, overridden by the settings file of the
+/// current workspace if there is one.
 #[derive(Debug, Clone, Default)]
-pub struct Settings(Map<String, Value>);
+pub struct Settings {
+    global: Map<String, Value>,
+    workspace: Map<String, Value>,
+    /// Root directory of the workspace the overrides were loaded from.
+    workspace_root: Option<PathBuf>,
+}
 
 impl Settings {
     pub async fn new() -> Result<Self, DatabaseError> {
+        Self::in_dir(std::env::current_dir().ok().as_deref()).await
+    }
+
+    /// The settings with the overrides of the workspace `dir` is in, e.g. the first root of
+    /// `q chat --workspace`.
+    pub async fn in_dir(dir: Option<&Path>) -> Result<Self, DatabaseError> {
         if cfg!(test) {
             return Ok(Self::default());
         }
 
+        let (workspace_root, workspace) = match dir.and_then(find_workspace_settings) {
+            Some((root, path)) => match load_workspace_settings(&path).await {
+                Ok(workspace) => (Some(root), workspace),
+                Err(err) => {
+                    warn!(?err, ?path, "failed to load workspace settings");
+                    (None, Map::new())
+                },
+            },
+            None => (None, Map::new()),
+        };
+
         let path = crate::util::directories::settings_path()?;
 
         // If the folder doesn't exist, create it.
@@ -115,7 +713,7 @@ impl Settings {
             }
         }
 
-        Ok(Self(match path.exists() {
+        let global = match path.exists() {
             true => {
                 let mut file = RwLock::new(File::open(&path).await?);
                 let mut buf = Vec::new();
@@ -127,26 +725,80 @@ impl Settings {
                 file.write()?.write_all(b"{}").await?;
                 serde_json::Map::new()
             },
-        }))
+        };
+
+        Ok(Self {
+            global,
+            workspace,
+            workspace_root,
+        })
     }
 
+    /// The global settings, without workspace overrides.
     pub fn map(&self) -> &'_ Map<String, Value> {
-        &self.0
+        &self.global
+    }
+
+    /// The global settings merged with the workspace overrides.
+    pub fn effective_map(&self) -> Map<String, Value> {
+        let mut map = self.global.clone();
+        map.extend(self.workspace.clone());
+        map
+    }
+
+    pub fn workspace_root(&self) -> Option<&Path> {
+        self.workspace_root.as_deref()
+    }
+
+    /// Whether the value of `key` comes from the workspace settings file.
+    pub fn is_workspace_override(&self, key: Setting) -> bool {
+        self.workspace.contains_key(key.as_ref())
+    }
+
+    pub fn source(&self, key: Setting) -> SettingSource {
+        if self.is_workspace_override(key) {
+            SettingSource::Workspace
+        } else if Self::lookup(&self.global, key).is_some() {
+            SettingSource::Global
+        } else {
+            SettingSource::Default
+        }
     }
 
     pub fn get(&self, key: Setting) -> Option<&Value> {
-        self.0.get(key.as_ref())
+        self.workspace
+            .get(key.as_ref())
+            .or_else(|| Self::lookup(&self.global, key))
+    }
+
+    /// Looks up `key` in `map`, falling back to its deprecated aliases.
+    fn lookup(map: &Map<String, Value>, key: Setting) -> Option<&Value> {
+        std::iter::once(key.as_ref())
+            .chain(key.deprecated_aliases().iter().copied())
+            .find_map(|key| map.get(key))
     }
 
+    /// Sets a global setting. Workspace overrides are only edited through the workspace file.
     pub async fn set(&mut self, key: Setting, value: impl Into<serde_json::Value>) -> Result<(), DatabaseError> {
-        self.0.insert(key.to_string(), value.into());
-        self.save_to_file().await
+        let value = value.into();
+        self.update(|global| {
+            for alias in key.deprecated_aliases() {
+                global.remove(*alias);
+            }
+            global.insert(key.to_string(), value);
+        })
+        .await
     }
 
     pub async fn remove(&mut self, key: Setting) -> Result<Option<Value>, DatabaseError> {
-        let key = self.0.remove(key.as_ref());
-        self.save_to_file().await?;
-        Ok(key)
+        self.update(|global| {
+            let mut removed = global.remove(key.as_ref());
+            for alias in key.deprecated_aliases() {
+                removed = removed.or(global.remove(*alias));
+            }
+            removed
+        })
+        .await
     }
 
     pub fn get_bool(&self, key: Setting) -> Option<bool> {
@@ -161,9 +813,34 @@ impl Settings {
         self.get(key).and_then(|value| value.as_i64())
     }
 
-    async fn save_to_file(&self) -> Result<(), DatabaseError> {
+    pub fn get_bool_or_default(&self, key: Setting) -> bool {
+        self.get_bool(key)
+            .or_else(|| key.default_value()?.as_bool())
+            .unwrap_or_default()
+    }
+
+    pub fn get_string_or_default(&self, key: Setting) -> Option<String> {
+        self.get_string(key)
+            .or_else(|| key.default_value()?.as_str().map(String::from))
+    }
+
+    pub fn get_int_or_default(&self, key: Setting) -> Option<i64> {
+        self.get_int(key).or_else(|| key.default_value()?.as_i64())
+    }
+
+    pub fn get_string_list(&self, key: Setting) -> Option<Vec<String>> {
+        self.get(key).and_then(|value| {
+            value
+                .as_array()
+                .map(|values| values.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+        })
+    }
+
+    /// Applies `change` to the global settings and saves them. The file is read again first, so
+    /// that settings changed by other q processes since this one started are kept.
+    async fn update<T>(&mut self, change: impl FnOnce(&mut Map<String, Value>) -> T) -> Result<T, DatabaseError> {
         if cfg!(test) {
-            return Ok(());
+            return Ok(change(&mut self.global));
         }
 
         let path = crate::util::directories::settings_path()?;
@@ -175,25 +852,94 @@ impl Settings {
             }
         }
 
-        let mut file_opts = File::options();
-        file_opts.create(true).write(true).truncate(true);
+        let (global, result) = update_settings_file(&path, &self.global, change).await?;
+        self.global = global;
+        Ok(result)
+    }
+}
+
+/// Applies `change` to the settings file while holding an exclusive lock on it, so that concurrent
+/// q processes don't overwrite each other's settings. A corrupt file is replaced starting from
+/// `loaded`, the settings this process already has. Returns the settings that were written.
+async fn update_settings_file<T>(
+    path: &Path,
+    loaded: &Map<String, Value>,
+    change: impl FnOnce(&mut Map<String, Value>) -> T,
+) -> Result<(Map<String, Value>, T), DatabaseError> {
+    let mut file_opts = File::options();
+    file_opts.create(true).read(true).write(true);
 
-        #[cfg(unix)]
-        file_opts.mode(0o600);
-        let mut file = RwLock::new(file_opts.open(&path).await?);
-        let mut lock = file.write()?;
+    #[cfg(unix)]
+    file_opts.mode(0o600);
+    let mut file = RwLock::new(file_opts.open(path).await?);
+    let mut lock = file.write()?;
 
-        match serde_json::to_string_pretty(&self.0) {
-            Ok(json) => lock.write_all(json.as_bytes()).await?,
-            Err(_err) => {
-                lock.seek(SeekFrom::Start(0)).await?;
-                lock.set_len(0).await?;
-                lock.write_all(b"{}").await?;
+    let mut buf = Vec::new();
+    lock.read_to_end(&mut buf).await?;
+    let mut global = match buf.iter().all(u8::is_ascii_whitespace) {
+        true => Map::new(),
+        false => match serde_json::from_slice(&buf) {
+            Ok(global) => global,
+            Err(err) => {
+                warn!(?err, ?path, "settings file is invalid, overwriting it");
+                loaded.clone()
             },
-        }
-        lock.flush().await?;
+        },
+    };
+    let result = change(&mut global);
+
+    let json = serde_json::to_string_pretty(&global)?;
+    lock.seek(SeekFrom::Start(0)).await?;
+    lock.set_len(0).await?;
+    lock.write_all(json.as_bytes()).await?;
+    lock.flush().await?;
 
-        Ok(())
+    Ok((global, result))
+}
+
+/// Finds the closest workspace settings file in `dir` or its ancestors, returning the workspace
+/// root along with the path of the file.
+fn find_workspace_settings(dir: &Path) -> Option<(PathBuf, PathBuf)> {
+    dir.ancestors().find_map(|root| {
+        let path = root.join(WORKSPACE_SETTINGS_PATH);
+        path.is_file().then(|| (root.to_path_buf(), path))
+    })
+}
+
+async fn load_workspace_settings(path: &Path) -> Result<Map<String, Value>, DatabaseError> {
+    let contents = tokio::fs::read_to_string(path).await?;
+    let table = toml::from_str::<toml::Table>(&contents)?;
+    let mut settings = Map::new();
+    flatten_workspace_settings(None, table, &mut settings);
+    Ok(settings)
+}
+
+/// Converts the workspace settings to dotted keys, so that both `[chat] editMode = "vi"` and
+/// `"chat.editMode" = "vi"` are accepted. Tables are only flattened until they name a setting,
+/// since some settings are objects themselves.
+fn flatten_workspace_settings(prefix: Option<&str>, table: toml::Table, settings: &mut Map<String, Value>) {
+    for (key, value) in table {
+        let key = match prefix {
+            Some(prefix) => format!("{prefix}.{key}"),
+            None => key,
+        };
+
+        match Setting::try_from(key.as_str()) {
+            Ok(setting) if setting.is_workspace_overridable() => {
+                let value = serde_json::to_value(value).map_err(DatabaseError::from);
+                match value.and_then(|value| setting.validate(&value).map(|_| value)) {
+                    Ok(value) => {
+                        settings.insert(setting.to_string(), value);
+                    },
+                    Err(err) => warn!(?err, key, "invalid workspace setting value"),
+                }
+            },
+            Ok(_) => warn!(key, "setting cannot be overridden by workspace settings"),
+            Err(_) => match value {
+                toml::Value::Table(table) => flatten_workspace_settings(Some(&key), table, settings),
+                _ => warn!(key, "unknown workspace setting"),
+            },
+        }
     }
 }
 
@@ -237,4 +983,179 @@ mod test {
         assert_eq!(settings.get(Setting::ShareCodeWhispererContent), None);
         assert_eq!(settings.get(Setting::McpLoadedBefore), None);
     }
+
+    #[test]
+    fn test_setting_schema() {
+        for setting in Setting::iter() {
+            assert_eq!(Setting::try_from(setting.as_ref()).unwrap(), setting);
+            if let Some(default) = setting.default_value() {
+                setting.validate(&default).unwrap();
+            }
+        }
+
+        assert_eq!(Setting::try_from("openai.baseUrl").unwrap(), Setting::OpenAiApiBaseUrl);
+        assert!(Setting::try_from("openai.unknown").is_err());
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(Setting::ChatEditMode.parse_value("vi").unwrap(), Value::from("vi"));
+        assert!(Setting::ChatEditMode.parse_value("nano").is_err());
+        assert_eq!(Setting::OpenAiModel.parse_value("4").unwrap(), Value::from("4"));
+        assert_eq!(Setting::ApiTimeout.parse_value("1000").unwrap(), Value::from(1000));
+        assert!(Setting::ApiTimeout.parse_value("soon").is_err());
+        assert!(Setting::ApiTimeout.parse_value("-1").is_err());
+        assert!(Setting::ChatGreetingEnabled.parse_value("false").is_ok());
+        assert!(Setting::ChatGreetingEnabled.parse_value("no").is_err());
+        assert!(
+            Setting::OpenAiApiBaseUrl
+                .parse_value("http://localhost:11434/v1")
+                .is_ok()
+        );
+        assert!(Setting::OpenAiApiBaseUrl.parse_value("localhost:11434").is_err());
+        assert_eq!(
+            Setting::ChatTrustedTools.parse_value("fs_read, fs_write").unwrap(),
+            serde_json::json!(["fs_read", "fs_write"])
+        );
+        assert_eq!(
+            Setting::ChatTrustedTools.parse_value(r#"["fs_read"]"#).unwrap(),
+            serde_json::json!(["fs_read"])
+        );
+        assert!(Setting::ServerModelAliases.parse_value("gpt-4").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_defaults_and_deprecated_aliases() {
+        let mut settings = Settings::new().await.unwrap();
+        assert!(settings.get_bool_or_default(Setting::ChatGreetingEnabled));
+        assert_eq!(settings.get_int_or_default(Setting::McpInitTimeout), Some(5000));
+        assert_eq!(settings.source(Setting::McpInitTimeout), SettingSource::Default);
+
+        settings
+            .global
+            .insert("openai.baseUrl".to_string(), "http://old".into());
+        assert_eq!(
+            settings.get_string(Setting::OpenAiApiBaseUrl).as_deref(),
+            Some("http://old")
+        );
+        assert_eq!(settings.source(Setting::OpenAiApiBaseUrl), SettingSource::Global);
+
+        settings.set(Setting::OpenAiApiBaseUrl, "http://new").await.unwrap();
+        assert_eq!(
+            settings.map().get("openai.baseUrl"),
+            None,
+            "aliases are migrated on set"
+        );
+        assert_eq!(
+            settings.get_string(Setting::OpenAiApiBaseUrl).as_deref(),
+            Some("http://new")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_workspace_settings_override_global() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("crates/app");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir_all(dir.path().join(".amazonq")).unwrap();
+        std::fs::write(dir.path().join(WORKSPACE_SETTINGS_PATH), indoc::indoc! {r#"
+            "chat.editMode" = "vi"
+            trustedTools = ["ignored"]
+
+            [openai]
+            model = "gpt-4o"
+            provider = "ollama"
+            api.key = "secret"
+
+            [chat]
+            trustedTools = ["fs_read", "fs_write"]
+            modelCapabilities = { "gpt-4o" = { contextWindowTokens = 128000 } }
+        "#})
+        .unwrap();
+
+        let (root, path) = find_workspace_settings(&nested).unwrap();
+        assert_eq!(root, dir.path());
+        let workspace = load_workspace_settings(&path).await.unwrap();
+
+        let mut settings = Settings::new().await.unwrap();
+        settings.set(Setting::OpenAiModel, "gpt-3.5-turbo").await.unwrap();
+        settings.set(Setting::OpenAiApiKey, "global-key").await.unwrap();
+        settings.workspace = workspace;
+        settings.workspace_root = Some(root);
+
+        assert_eq!(settings.get_string(Setting::OpenAiModel).as_deref(), Some("gpt-4o"));
+        assert_eq!(settings.get_string(Setting::OpenAiProvider).as_deref(), Some("ollama"));
+        assert_eq!(settings.get_string(Setting::ChatEditMode).as_deref(), Some("vi"));
+        assert_eq!(
+            settings.get_string(Setting::OpenAiApiKey).as_deref(),
+            Some("global-key"),
+            "credentials cannot be overridden by the workspace"
+        );
+        assert_eq!(
+            settings.get_string_list(Setting::ChatTrustedTools),
+            Some(vec!["fs_read".to_string(), "fs_write".to_string()])
+        );
+        assert!(settings.get(Setting::ChatModelCapabilities).unwrap()["gpt-4o"].is_object());
+        assert!(settings.is_workspace_override(Setting::OpenAiModel));
+        assert!(!settings.is_workspace_override(Setting::OpenAiApiKey));
+
+        assert_eq!(
+            settings.map().get("openai.model"),
+            Some(&Value::String("gpt-3.5-turbo".to_string())),
+            "the global settings are left untouched"
+        );
+        assert_eq!(
+            settings.effective_map().get("openai.model"),
+            Some(&Value::String("gpt-4o".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_settings_file_keeps_other_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        std::fs::write(&path, r#"{"chat.editMode": "vi", "openai.model": "gpt-4o"}"#).unwrap();
+
+        // Another process changed the file after this one loaded it.
+        let (global, removed) = update_settings_file(&path, &Map::new(), |global| {
+            global.insert("chat.greeting.enabled".to_string(), false.into());
+            global.remove("openai.model")
+        })
+        .await
+        .unwrap();
+        assert_eq!(removed, Some(Value::String("gpt-4o".to_string())));
+        assert_eq!(global.get("chat.editMode"), Some(&Value::String("vi".to_string())));
+
+        let written = serde_json::from_str::<Map<String, Value>>(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written, global);
+        assert_eq!(written.len(), 2);
+
+        let empty = dir.path().join("empty.json");
+        let (global, _) = update_settings_file(&empty, &Map::new(), |global| {
+            global.insert("chat.editMode".to_string(), "vi".into())
+        })
+        .await
+        .unwrap();
+        assert_eq!(global.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_settings_file_recovers_from_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        std::fs::write(&path, r#"{"chat.editMode": "vi""#).unwrap();
+
+        let mut loaded = Map::new();
+        loaded.insert("openai.model".to_string(), "gpt-4o".into());
+        let (global, _) = update_settings_file(&path, &loaded, |global| {
+            global.insert("chat.greeting.enabled".to_string(), false.into())
+        })
+        .await
+        .unwrap();
+        assert_eq!(global.get("openai.model"), Some(&Value::String("gpt-4o".to_string())));
+        assert_eq!(global.len(), 2);
+
+        let written = serde_json::from_str::<Map<String, Value>>(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written, global);
+    }
 }
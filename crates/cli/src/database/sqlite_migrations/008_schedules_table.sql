@@ -0,0 +1,4 @@
+CREATE TABLE schedules (
+    key TEXT PRIMARY KEY,
+    value TEXT
+);
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::process::Stdio;
 use std::sync::atomic::{
     AtomicBool,
@@ -41,8 +42,11 @@ use super::{
     PromptsListResult,
     ResourceTemplatesListResult,
     ResourcesListResult,
+    Roots,
+    Sampler,
     ServerCapabilities,
     ToolsListResult,
+    handle_server_request,
 };
 use crate::util::process::{
     Pid,
@@ -81,7 +85,14 @@ pub struct ClientConfig {
     pub args: Vec<String>,
     pub timeout: u64,
     pub client_info: serde_json::Value,
+    /// Variables of the environment the server inherits, replaced by [Self::env] where both set
+    /// one.
+    #[serde(default)]
+    pub inherited_env: HashMap<String, String>,
     pub env: Option<HashMap<String, String>>,
+    /// Directory the server runs in, the current directory of `q` if not set.
+    #[serde(default)]
+    pub current_dir: Option<PathBuf>,
 }
 
 #[allow(dead_code)]
@@ -128,6 +139,11 @@ pub struct Client<T: Transport> {
     client_info: serde_json::Value,
     current_id: Arc<AtomicU64>,
     pub messenger: Option<Box<dyn Messenger>>,
+    /// Answers the sampling requests of the server, which is told the client supports sampling
+    /// when there is one.
+    pub sampler: Option<Arc<dyn Sampler>>,
+    /// The directories the server is told to work in, the server being notified when they change.
+    pub roots: Option<Roots>,
     // TODO: move this to tool manager that way all the assets are treated equally
     pub prompt_gets: Arc<SyncRwLock<HashMap<String, PromptGet>>>,
     pub is_prompts_out_of_date: Arc<AtomicBool>,
@@ -145,6 +161,8 @@ impl<T: Transport> Clone for Client<T> {
             client_info: self.client_info.clone(),
             current_id: self.current_id.clone(),
             messenger: None,
+            sampler: self.sampler.clone(),
+            roots: self.roots.clone(),
             prompt_gets: self.prompt_gets.clone(),
             is_prompts_out_of_date: self.is_prompts_out_of_date.clone(),
         }
@@ -159,7 +177,9 @@ impl Client<StdioTransport> {
             args,
             timeout,
             client_info,
+            inherited_env,
             env,
+            current_dir,
         } = config;
         let child = {
             let mut command = tokio::process::Command::new(bin_path);
@@ -167,11 +187,16 @@ impl Client<StdioTransport> {
                 .stdin(Stdio::piped())
                 .stdout(Stdio::piped())
                 .stderr(Stdio::piped())
-                .envs(std::env::vars());
+                .env_clear()
+                .envs(inherited_env);
 
             #[cfg(not(windows))]
             command.process_group(0);
 
+            if let Some(dir) = current_dir {
+                command.current_dir(dir);
+            }
+
             if let Some(env) = env {
                 for (env_name, env_value) in env {
                     command.env(env_name, env_value);
@@ -192,6 +217,8 @@ impl Client<StdioTransport> {
             client_info,
             current_id: Arc::new(AtomicU64::new(0)),
             messenger: None,
+            sampler: None,
+            roots: None,
             prompt_gets: Arc::new(SyncRwLock::new(HashMap::new())),
             is_prompts_out_of_date: Arc::new(AtomicBool::new(false)),
         })
@@ -245,7 +272,17 @@ where
         });
 
         let init_params = Some({
-            let client_cap = ClientCapabilities::from(self.client_info.clone());
+            let mut client_cap = ClientCapabilities::from(self.client_info.clone());
+            if self.sampler.is_some() {
+                client_cap
+                    .capabilities
+                    .insert("sampling".to_string(), serde_json::json!({}));
+            }
+            if self.roots.is_some() {
+                client_cap
+                    .capabilities
+                    .insert("roots".to_string(), serde_json::json!({}));
+            }
             serde_json::json!(client_cap)
         });
         let init_resp = self.request("initialize", init_params).await?;
@@ -294,6 +331,8 @@ where
         let server_name = self.server_name.clone();
         let messenger_ref = self.messenger.as_ref().map(|m| m.duplicate());
         let client_ref = (*self).clone();
+        let sampler_ref = self.sampler.clone();
+        let roots_ref = self.roots.clone();
 
         let prompts_list_changed_supported = cap.prompts.as_ref().is_some_and(|p| p.get("listChanged").is_some());
         let tools_list_changed_supported = cap.tools.as_ref().is_some_and(|t| t.get("listChanged").is_some());
@@ -303,7 +342,26 @@ where
                 match listener.recv().await {
                     Ok(msg) => {
                         match msg {
-                            JsonRpcMessage::Request(_req) => {},
+                            JsonRpcMessage::Request(req) => {
+                                // Answered in their own task, since sampling waits for the user
+                                // and the model.
+                                let transport_ref = transport_ref.clone();
+                                let server_name = server_name.clone();
+                                let sampler_ref = sampler_ref.clone();
+                                let roots_ref = roots_ref.clone();
+                                tokio::spawn(async move {
+                                    let resp = handle_server_request(
+                                        &server_name,
+                                        sampler_ref.as_deref(),
+                                        roots_ref.as_ref(),
+                                        req,
+                                    )
+                                    .await;
+                                    if let Err(e) = transport_ref.send(&JsonRpcMessage::Response(resp)).await {
+                                        tracing::error!("Failed to answer the request of {}: {:?}", server_name, e);
+                                    }
+                                });
+                            },
                             JsonRpcMessage::Notification(notif) => {
                                 let JsonRpcNotification { method, params, .. } = notif;
                                 match method.as_str() {
@@ -360,6 +418,10 @@ where
                             },
                         }
                     },
+                    Err(TransportError::RecvError(tokio::sync::broadcast::error::RecvError::Closed)) => {
+                        tracing::error!("Server {} closed its output, ending the listening thread", server_name);
+                        break;
+                    },
                     Err(e) => {
                         tracing::error!("Background listening thread for client {}: {:?}", server_name, e);
                     },
@@ -376,6 +438,18 @@ where
         &self,
         method: &str,
         params: Option<serde_json::Value>,
+    ) -> Result<JsonRpcResponse, ClientError> {
+        self.request_with_timeout(method, params, Duration::from_millis(self.timeout))
+            .await
+    }
+
+    /// Sends a request like [Self::request], waiting for `timeout` instead of the timeout of the
+    /// server.
+    pub async fn request_with_timeout(
+        &self,
+        method: &str,
+        params: Option<serde_json::Value>,
+        timeout: Duration,
     ) -> Result<JsonRpcResponse, ClientError> {
         let send_map_err = |e: Elapsed| (e, method.to_string());
         let recv_map_err = |e: Elapsed| (e, format!("recv for {method}"));
@@ -388,11 +462,11 @@ where
         };
         tracing::trace!(target: "mcp", "To {}:\n{:#?}", self.server_name, request);
         let msg = JsonRpcMessage::Request(request);
-        time::timeout(Duration::from_millis(self.timeout), self.transport.send(&msg))
+        time::timeout(timeout, self.transport.send(&msg))
             .await
             .map_err(send_map_err)??;
         let mut listener = self.transport.get_listener();
-        let mut resp = time::timeout(Duration::from_millis(self.timeout), async {
+        let mut resp = time::timeout(timeout, async {
             // we want to ignore all other messages sent by the server at this point and let the
             // background loop handle them
             // We also want to ignore all messages emitted by the server to its stdout that does
@@ -461,10 +535,10 @@ where
                         })),
                     };
                     let msg = JsonRpcMessage::Request(next_request);
-                    time::timeout(Duration::from_millis(self.timeout), self.transport.send(&msg))
+                    time::timeout(timeout, self.transport.send(&msg))
                         .await
                         .map_err(send_map_err)??;
-                    let resp = time::timeout(Duration::from_millis(self.timeout), async {
+                    let resp = time::timeout(timeout, async {
                         loop {
                             if let Ok(JsonRpcMessage::Response(resp)) = listener.recv().await {
                                 if resp.id == id {
@@ -654,12 +728,14 @@ mod tests {
             args: ["1".to_owned()].to_vec(),
             timeout: 120 * 1000,
             client_info: client_info_one.clone(),
+            inherited_env: std::env::vars().collect(),
             env: {
                 let mut map = HashMap::<String, String>::new();
                 map.insert("ENV_ONE".to_owned(), "1".to_owned());
                 map.insert("ENV_TWO".to_owned(), "2".to_owned());
                 Some(map)
             },
+            current_dir: None,
         };
         let client_info_two = serde_json::json!({
           "name": "TestClientTwo",
@@ -671,12 +747,14 @@ mod tests {
             args: ["2".to_owned()].to_vec(),
             timeout: 120 * 1000,
             client_info: client_info_two.clone(),
+            inherited_env: std::env::vars().collect(),
             env: {
                 let mut map = HashMap::<String, String>::new();
                 map.insert("ENV_ONE".to_owned(), "1".to_owned());
                 map.insert("ENV_TWO".to_owned(), "2".to_owned());
                 Some(map)
             },
+            current_dir: None,
         };
         let mut client_one = Client::<StdioTransport>::from_config(client_config_one).expect("Failed to create client");
         let mut client_two = Client::<StdioTransport>::from_config(client_config_two).expect("Failed to create client");
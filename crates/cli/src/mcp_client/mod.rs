@@ -2,12 +2,18 @@ pub mod client;
 pub mod error;
 pub mod facilitator_types;
 pub mod messenger;
+pub mod roots;
+pub mod sampler;
 pub mod server;
+pub mod server_requests;
 pub mod transport;
 
 pub use client::*;
 pub use facilitator_types::*;
 pub use messenger::*;
+pub use roots::*;
+pub use sampler::*;
 #[allow(unused_imports)]
 pub use server::*;
+pub use server_requests::*;
 pub use transport::*;
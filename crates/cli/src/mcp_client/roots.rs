@@ -0,0 +1,70 @@
+//! Referencing https://spec.modelcontextprotocol.io/specification/2024-11-05/client/roots/
+//!
+//! Servers can ask the client for the directories they should work in with `roots/list`, which
+//! the client declares support of in its capabilities when it has [Roots]. The roots are the ones
+//! of the session and don't change, so `listChanged` isn't declared.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use url::Url;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Root {
+    /// A `file://` URI.
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl Root {
+    /// The root of the directory `path`, which has to be absolute, named after it.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Some(Self {
+            uri: Url::from_file_path(path).ok()?.to_string(),
+            name: path.file_name().map(|name| name.to_string_lossy().into_owned()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListRootsResult {
+    pub roots: Vec<Root>,
+}
+
+/// The roots shared by the clients of all servers.
+#[derive(Debug, Clone)]
+pub struct Roots(Arc<Vec<Root>>);
+
+impl Roots {
+    pub fn new(roots: Vec<Root>) -> Self {
+        Self(Arc::new(roots))
+    }
+
+    pub fn get(&self) -> Vec<Root> {
+        self.0.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Root::from_path(dir.path()).unwrap();
+        assert!(root.uri.starts_with("file:///"));
+        assert_eq!(
+            root.name,
+            dir.path().file_name().map(|name| name.to_string_lossy().into_owned())
+        );
+        assert_eq!(Root::from_path(Path::new("relative")), None);
+
+        assert_eq!(Roots::new(vec![root.clone()]).get(), vec![root]);
+    }
+}
@@ -0,0 +1,73 @@
+//! Referencing https://spec.modelcontextprotocol.io/specification/2024-11-05/client/sampling/
+//!
+//! Servers can ask the client for a completion of its model with `sampling/createMessage`, which
+//! the client declares support of in its capabilities when it has a [Sampler].
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use thiserror::Error;
+
+/// Answers the sampling requests of servers with a completion of the model in use.
+#[async_trait::async_trait]
+pub trait Sampler: std::fmt::Debug + Send + Sync + 'static {
+    async fn create_message(
+        &self,
+        server_name: &str,
+        params: CreateMessageParams,
+    ) -> Result<CreateMessageResult, SamplingError>;
+}
+
+#[derive(Debug, Error)]
+pub enum SamplingError {
+    #[error("User rejected sampling request")]
+    Rejected,
+    #[error("{0}")]
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageParams {
+    pub messages: Vec<SamplingMessage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    pub max_tokens: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SamplingMessage {
+    pub role: SamplingRole,
+    pub content: SamplingContent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplingRole {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SamplingContent {
+    Text {
+        text: String,
+    },
+    Image {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateMessageResult {
+    pub role: SamplingRole,
+    pub content: SamplingContent,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+}
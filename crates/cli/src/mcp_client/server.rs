@@ -33,6 +33,14 @@ use super::transport::{
     TransportError,
 };
 
+/// Whether the transport closed, after which no more messages can be received.
+fn is_closed(error: &TransportError) -> bool {
+    matches!(
+        error,
+        TransportError::RecvError(tokio::sync::broadcast::error::RecvError::Closed)
+    )
+}
+
 pub type Request = serde_json::Value;
 pub type Response = Option<serde_json::Value>;
 pub type InitializedServer = JoinHandle<Result<(), ServerError>>;
@@ -86,6 +94,8 @@ pub enum ServerError {
     MissingTransport,
     #[error("Failed to initialize server. Missing handler")]
     MissingHandler,
+    #[error("Method not found: {0}")]
+    MethodNotFound(String),
 }
 
 impl<H> Server<StdioTransport, H>
@@ -166,6 +176,9 @@ where
             let mut listener = transport.get_listener();
             loop {
                 let request = listener.recv().await;
+                if request.as_ref().is_err_and(is_closed) {
+                    break;
+                }
                 let transport_clone = transport.clone();
                 let has_init_clone = has_initialized.clone();
                 let handler_clone = handler.clone();
@@ -173,6 +186,7 @@ where
                     process_request(has_init_clone, transport_clone, handler_clone, request).await;
                 });
             }
+            Ok(())
         });
         Ok(listener)
     }
@@ -257,8 +271,12 @@ async fn process_request<T, H>(
                 } = req;
                 let resp = handler.handle_incoming(method, params).await.map_or_else(
                     |error| {
+                        let code = match error {
+                            ServerError::MethodNotFound(_) => ErrorCode::MethodNotFound,
+                            _ => ErrorCode::InternalError,
+                        };
                         let err = JsonRpcError {
-                            code: ErrorCode::InternalError.into(),
+                            code: code.into(),
                             message: error.to_string(),
                             data: None,
                         };
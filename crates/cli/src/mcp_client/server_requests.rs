@@ -0,0 +1,166 @@
+//! The requests servers send to the client, answered by the listener of
+//! [Client::init](super::Client::init).
+
+use super::transport::base_protocol::{
+    JsonRpcError,
+    JsonRpcRequest,
+    JsonRpcResponse,
+    JsonRpcVersion,
+};
+use super::{
+    ListRootsResult,
+    Roots,
+    Sampler,
+    SamplingError,
+};
+
+/// Error code of a request the user rejected, as specified for sampling.
+const USER_REJECTED: i32 = -1;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const INTERNAL_ERROR: i32 = -32603;
+
+/// The response to a request of a server, the only ones supported being `ping`, with a `sampler`,
+/// `sampling/createMessage` and, with `roots`, `roots/list`.
+pub async fn handle_server_request(
+    server_name: &str,
+    sampler: Option<&dyn Sampler>,
+    roots: Option<&Roots>,
+    request: JsonRpcRequest,
+) -> JsonRpcResponse {
+    let JsonRpcRequest { id, method, params, .. } = request;
+    let error = |code: i32, message: String| JsonRpcResponse {
+        jsonrpc: JsonRpcVersion::default(),
+        id,
+        result: None,
+        error: Some(JsonRpcError {
+            code,
+            message,
+            data: None,
+        }),
+    };
+    let result = match (method.as_str(), sampler, roots) {
+        ("ping", ..) => serde_json::json!({}),
+        ("roots/list", _, Some(roots)) => {
+            serde_json::to_value(ListRootsResult { roots: roots.get() }).unwrap_or_default()
+        },
+        ("sampling/createMessage", Some(sampler), _) => {
+            let params = match serde_json::from_value(params.unwrap_or_default()) {
+                Ok(params) => params,
+                Err(err) => return error(INVALID_PARAMS, format!("Invalid sampling request: {err}")),
+            };
+            match sampler.create_message(server_name, params).await {
+                Ok(result) => serde_json::to_value(result).unwrap_or_default(),
+                Err(err @ SamplingError::Rejected) => return error(USER_REJECTED, err.to_string()),
+                Err(err) => return error(INTERNAL_ERROR, err.to_string()),
+            }
+        },
+        _ => return error(METHOD_NOT_FOUND, format!("Method not found: {method}")),
+    };
+    JsonRpcResponse {
+        jsonrpc: JsonRpcVersion::default(),
+        id,
+        result: Some(result),
+        error: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::mcp_client::{
+        CreateMessageParams,
+        CreateMessageResult,
+        Root,
+        SamplingContent,
+        SamplingRole,
+    };
+
+    #[derive(Debug)]
+    struct EchoSampler;
+
+    #[async_trait::async_trait]
+    impl Sampler for EchoSampler {
+        async fn create_message(
+            &self,
+            server_name: &str,
+            params: CreateMessageParams,
+        ) -> Result<CreateMessageResult, SamplingError> {
+            match params.messages.last().map(|message| &message.content) {
+                Some(SamplingContent::Text { text }) if text != "no" => Ok(CreateMessageResult {
+                    role: SamplingRole::Assistant,
+                    content: SamplingContent::Text {
+                        text: format!("{server_name}: {text}"),
+                    },
+                    model: "test".to_string(),
+                    stop_reason: Some("endTurn".to_string()),
+                }),
+                _ => Err(SamplingError::Rejected),
+            }
+        }
+    }
+
+    fn request(method: &str, params: serde_json::Value) -> JsonRpcRequest {
+        JsonRpcRequest {
+            jsonrpc: JsonRpcVersion::default(),
+            id: 7,
+            method: method.to_string(),
+            params: Some(params),
+        }
+    }
+
+    fn sampling_request(text: &str) -> JsonRpcRequest {
+        request(
+            "sampling/createMessage",
+            json!({
+                "messages": [{"role": "user", "content": {"type": "text", "text": text}}],
+                "modelPreferences": {"hints": [{"name": "claude"}]},
+                "maxTokens": 100
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_handle_server_request() {
+        let response = handle_server_request("git", Some(&EchoSampler), None, sampling_request("hi")).await;
+        assert_eq!(response.id, 7);
+        assert_eq!(
+            response.result,
+            Some(json!({
+                "role": "assistant",
+                "content": {"type": "text", "text": "git: hi"},
+                "model": "test",
+                "stopReason": "endTurn"
+            }))
+        );
+
+        let response = handle_server_request("git", Some(&EchoSampler), None, sampling_request("no")).await;
+        assert_eq!(response.error.unwrap().code, USER_REJECTED);
+        let response = handle_server_request("git", None, None, sampling_request("hi")).await;
+        assert_eq!(response.error.unwrap().code, METHOD_NOT_FOUND);
+        let response = handle_server_request(
+            "git",
+            Some(&EchoSampler),
+            None,
+            request("sampling/createMessage", json!({})),
+        )
+        .await;
+        assert_eq!(response.error.unwrap().code, INVALID_PARAMS);
+        let response = handle_server_request("git", None, None, request("ping", json!({}))).await;
+        assert_eq!(response.result, Some(json!({})));
+
+        let roots = Roots::new(vec![Root {
+            uri: "file:///home/user/repo".to_string(),
+            name: Some("repo".to_string()),
+        }]);
+        let response = handle_server_request("git", None, Some(&roots), request("roots/list", json!({}))).await;
+        assert_eq!(
+            response.result,
+            Some(json!({"roots": [{"uri": "file:///home/user/repo", "name": "repo"}]}))
+        );
+        let response = handle_server_request("git", None, None, request("roots/list", json!({}))).await;
+        assert_eq!(response.error.unwrap().code, METHOD_NOT_FOUND);
+    }
+}
@@ -51,7 +51,8 @@ impl JsonRpcStdioTransport {
                 // Messages are delimited by newlines and assumed to contain no embedded newlines
                 // See https://spec.modelcontextprotocol.io/specification/2024-11-05/basic/transports/#stdio
                 match buf_reader.read_until(b'\n', &mut buffer).await {
-                    Ok(0) => continue,
+                    // The other end closed the pipe, which closes the channel of the listeners.
+                    Ok(0) => break,
                     Ok(_) => match serde_json::from_slice::<JsonRpcMessage>(buffer.as_slice()) {
                         Ok(msg) => {
                             let _ = tx.send(Ok(msg));
@@ -29,6 +29,8 @@ mod inner {
     pub(super) enum Inner {
         #[default]
         Real,
+        /// The real environment, in another directory than the current directory of the process.
+        RealIn(PathBuf),
         Fake(Arc<Mutex<Fake>>),
     }
 
@@ -68,7 +70,7 @@ impl Env {
     pub fn get<K: AsRef<str>>(&self, key: K) -> Result<String, VarError> {
         use inner::Inner;
         match &self.0 {
-            Inner::Real => env::var(key.as_ref()),
+            Inner::Real | Inner::RealIn(_) => env::var(key.as_ref()),
             Inner::Fake(fake) => fake
                 .lock()
                 .unwrap()
@@ -82,7 +84,7 @@ impl Env {
     pub fn get_os<K: AsRef<OsStr>>(&self, key: K) -> Option<OsString> {
         use inner::Inner;
         match &self.0 {
-            Inner::Real => env::var_os(key.as_ref()),
+            Inner::Real | Inner::RealIn(_) => env::var_os(key.as_ref()),
             Inner::Fake(fake) => fake
                 .lock()
                 .unwrap()
@@ -102,7 +104,7 @@ impl Env {
     pub unsafe fn set_var(&self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) {
         use inner::Inner;
         match &self.0 {
-            Inner::Real => std::env::set_var(key, value),
+            Inner::Real | Inner::RealIn(_) => std::env::set_var(key, value),
             Inner::Fake(fake) => {
                 fake.lock().unwrap().vars.insert(
                     key.as_ref().to_str().expect("key must be valid str").to_string(),
@@ -114,7 +116,7 @@ impl Env {
 
     pub fn home(&self) -> Option<PathBuf> {
         match &self.0 {
-            inner::Inner::Real => dirs::home_dir(),
+            inner::Inner::Real | inner::Inner::RealIn(_) => dirs::home_dir(),
             inner::Inner::Fake(fake) => fake.lock().unwrap().vars.get("HOME").map(PathBuf::from),
         }
     }
@@ -123,14 +125,28 @@ impl Env {
         use inner::Inner;
         match &self.0 {
             Inner::Real => std::env::current_dir(),
+            Inner::RealIn(dir) => Ok(dir.clone()),
             Inner::Fake(fake) => Ok(fake.lock().unwrap().cwd.clone()),
         }
     }
 
+    /// The same environment in the directory `dir`, leaving the current directory of the process
+    /// alone.
+    pub fn with_current_dir(&self, dir: PathBuf) -> Self {
+        use inner::Inner;
+        match &self.0 {
+            Inner::Real | Inner::RealIn(_) => Self(Inner::RealIn(dir)),
+            Inner::Fake(fake) => {
+                let fake = fake.lock().unwrap().clone();
+                Self(Inner::Fake(Arc::new(Mutex::new(inner::Fake { cwd: dir, ..fake }))))
+            },
+        }
+    }
+
     pub fn current_exe(&self) -> Result<PathBuf, io::Error> {
         use inner::Inner;
         match &self.0 {
-            Inner::Real => std::env::current_exe(),
+            Inner::Real | Inner::RealIn(_) => std::env::current_exe(),
             Inner::Fake(fake) => Ok(fake.lock().unwrap().current_exe.clone()),
         }
     }
@@ -213,4 +229,18 @@ mod tests {
         let env = Env::from_slice(&[]);
         assert_eq!(env.current_dir().unwrap(), PathBuf::from("/"));
     }
+
+    #[test]
+    fn test_with_current_dir() {
+        let process_dir = std::env::current_dir().unwrap();
+        let env = Env::new().with_current_dir(PathBuf::from("/project"));
+        assert_eq!(env.current_dir().unwrap(), PathBuf::from("/project"));
+        assert_eq!(std::env::current_dir().unwrap(), process_dir);
+
+        let fake = Env::from_slice(&[("HOME", "/home/user")]);
+        let env = fake.with_current_dir(PathBuf::from("/project"));
+        assert_eq!(env.current_dir().unwrap(), PathBuf::from("/project"));
+        assert_eq!(env.get("HOME").unwrap(), "/home/user");
+        assert_eq!(fake.current_dir().unwrap(), PathBuf::from("/"));
+    }
 }
@@ -8,6 +8,7 @@ mod os;
 mod providers;
 mod sysinfo;
 
+use std::path::PathBuf;
 use std::sync::Arc;
 
 pub use env::Env;
@@ -23,6 +24,8 @@ pub use providers::{
 };
 pub use sysinfo::SysInfo;
 
+use crate::cli::chat::ExecutionTarget;
+
 /// Struct that contains the interface to every system related IO operation.
 ///
 /// Every operation that accesses the file system, environment, or other related platform
@@ -34,6 +37,11 @@ pub struct Context {
     env: Env,
     sysinfo: SysInfo,
     platform: Platform,
+    /// The roots given with `q chat --workspace`, see [Self::with_workspace].
+    workspace_roots: Vec<PathBuf>,
+    /// Where the tools of `q chat --remote` or `--container` run, see
+    /// [Self::with_execution_target].
+    execution_target: ExecutionTarget,
 }
 
 impl Context {
@@ -45,12 +53,16 @@ impl Context {
                 env: Env::new(),
                 sysinfo: SysInfo::new(),
                 platform: Platform::new(),
+                workspace_roots: Vec::new(),
+                execution_target: ExecutionTarget::Local,
             }),
             false => Arc::new_cyclic(|_| Self {
                 fs: Default::default(),
                 env: Default::default(),
                 sysinfo: SysInfo::default(),
                 platform: Platform::new(),
+                workspace_roots: Vec::new(),
+                execution_target: ExecutionTarget::Local,
             }),
         }
     }
@@ -74,6 +86,45 @@ impl Context {
     pub fn platform(&self) -> &Platform {
         &self.platform
     }
+
+    /// The roots of the workspace, empty when the workspace is the current directory.
+    pub fn workspace_roots(&self) -> &[PathBuf] {
+        &self.workspace_roots
+    }
+
+    /// Where execute_bash, fs_read and fs_write run, this machine unless set.
+    pub fn execution_target(&self) -> &ExecutionTarget {
+        &self.execution_target
+    }
+
+    /// The same context whose tools run on `target`, e.g. a host reached over SSH.
+    pub fn with_execution_target(&self, target: ExecutionTarget) -> Arc<Self> {
+        Arc::new(Self {
+            execution_target: target,
+            ..self.clone()
+        })
+    }
+
+    /// The same context in the directory `dir`, e.g. the one of a session of `q acp`.
+    pub fn with_current_dir(&self, dir: PathBuf) -> Arc<Self> {
+        Arc::new(Self {
+            env: self.env.with_current_dir(dir),
+            ..self.clone()
+        })
+    }
+
+    /// The same context in the workspace of `roots`, in the directory of the first one.
+    pub fn with_workspace(&self, roots: Vec<PathBuf>) -> Arc<Self> {
+        let env = match roots.first() {
+            Some(first) => self.env.with_current_dir(first.clone()),
+            None => self.env.clone(),
+        };
+        Arc::new(Self {
+            env,
+            workspace_roots: roots,
+            ..self.clone()
+        })
+    }
 }
 
 #[derive(Default, Debug)]
@@ -100,6 +151,8 @@ impl ContextBuilder {
             env,
             sysinfo,
             platform,
+            workspace_roots: Vec::new(),
+            execution_target: ExecutionTarget::Local,
         })
     }
 
@@ -114,6 +167,8 @@ impl ContextBuilder {
             env,
             sysinfo,
             platform,
+            workspace_roots: Vec::new(),
+            execution_target: ExecutionTarget::Local,
         })
     }
 
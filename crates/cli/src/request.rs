@@ -1,17 +1,30 @@
 use std::env::current_exe;
+use std::path::PathBuf;
 use std::sync::{
     Arc,
     LazyLock,
+    OnceLock,
 };
 
-use reqwest::Client;
+use reqwest::{
+    Client,
+    ClientBuilder,
+    NoProxy,
+    Proxy,
+};
+use rustls::pki_types::CertificateDer;
+use rustls::pki_types::pem::PemObject;
 use rustls::{
     ClientConfig,
     RootCertStore,
 };
 use thiserror::Error;
+use tracing::warn;
 use url::ParseError;
 
+use crate::database::Database;
+use crate::database::settings::Setting;
+
 #[derive(Debug, Error)]
 pub enum RequestError {
     #[error(transparent)]
@@ -26,14 +39,105 @@ pub enum RequestError {
     Settings(#[from] crate::database::DatabaseError),
     #[error(transparent)]
     UrlParseError(#[from] ParseError),
+    #[error("Failed to load the CA bundle {}: {}", .0.display(), .1)]
+    CaBundle(PathBuf, String),
+}
+
+/// The proxy and CA settings applied to every client, see [configure].
+#[derive(Debug, Clone, Default)]
+struct NetworkConfig {
+    proxy: Option<Proxy>,
+    /// The URL of [Self::proxy] and the hosts reached without it, for the headless browser.
+    proxy_server: Option<(String, Vec<String>)>,
+    ca_certs: Vec<CertificateDer<'static>>,
+}
+
+static NETWORK_CONFIG: OnceLock<NetworkConfig> = OnceLock::new();
+
+/// Applies the `network.*` settings to every client created afterwards, for both reqwest and the
+/// AWS SDK. Only the first call has an effect.
+///
+/// Without `network.proxy`, clients use the `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY`
+/// environment variables.
+pub fn configure(database: &Database) -> Result<(), RequestError> {
+    let config = network_config(
+        database.settings.get_string(Setting::NetworkProxy).as_deref(),
+        &database
+            .settings
+            .get_string_list(Setting::NetworkNoProxy)
+            .unwrap_or_default(),
+        database
+            .settings
+            .get_string(Setting::NetworkCaBundle)
+            .map(PathBuf::from),
+    )?;
+    let _ = NETWORK_CONFIG.set(config);
+    Ok(())
+}
+
+fn network_config(
+    proxy: Option<&str>,
+    no_proxy: &[String],
+    ca_bundle: Option<PathBuf>,
+) -> Result<NetworkConfig, RequestError> {
+    let proxy = proxy.filter(|proxy| !proxy.is_empty());
+    let proxy_server = proxy.map(|proxy| (proxy.to_string(), no_proxy.to_vec()));
+    let proxy = match proxy {
+        Some(proxy) => Some(Proxy::all(proxy)?.no_proxy(NoProxy::from_string(&no_proxy.join(",")))),
+        None => None,
+    };
+
+    let ca_certs = match ca_bundle {
+        Some(path) => {
+            let certs = CertificateDer::pem_file_iter(&path)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .map_err(|err| RequestError::CaBundle(path.clone(), err.to_string()))?;
+            if certs.is_empty() {
+                return Err(RequestError::CaBundle(path, "no certificates found".to_string()));
+            }
+            certs
+        },
+        None => Vec::new(),
+    };
+
+    Ok(NetworkConfig {
+        proxy,
+        proxy_server,
+        ca_certs,
+    })
+}
+
+/// The URL of `network.proxy` and the hosts of `network.noProxy`, for clients that can't be built
+/// with [client_builder], e.g. the headless browser.
+#[cfg_attr(not(feature = "headless-browser"), allow(dead_code))]
+pub fn proxy_server() -> Option<(String, Vec<String>)> {
+    NETWORK_CONFIG.get().and_then(|config| config.proxy_server.clone())
+}
+
+/// The certificates of `network.caBundle`, for clients that can't be built with [client_builder].
+#[cfg_attr(not(feature = "headless-browser"), allow(dead_code))]
+pub fn extra_ca_certs() -> Vec<CertificateDer<'static>> {
+    NETWORK_CONFIG
+        .get()
+        .map(|config| config.ca_certs.clone())
+        .unwrap_or_default()
 }
 
 pub fn new_client() -> Result<Client, RequestError> {
-    Ok(Client::builder()
-        .use_preconfigured_tls(client_config())
+    Ok(client_builder().build()?)
+}
+
+/// A builder honoring the network settings, for clients that need more options, e.g. a timeout.
+pub fn client_builder() -> ClientBuilder {
+    let config = NETWORK_CONFIG.get().cloned().unwrap_or_default();
+    let builder = Client::builder()
+        .use_preconfigured_tls(client_config(&config.ca_certs))
         .user_agent(USER_AGENT.chars().filter(|c| c.is_ascii_graphic()).collect::<String>())
-        .cookie_store(true)
-        .build()?)
+        .cookie_store(true);
+    match config.proxy {
+        Some(proxy) => builder.proxy(proxy),
+        None => builder,
+    }
 }
 
 pub fn create_default_root_cert_store() -> RootCertStore {
@@ -49,15 +153,22 @@ pub fn create_default_root_cert_store() -> RootCertStore {
     root_cert_store
 }
 
-fn client_config() -> ClientConfig {
+fn client_config(extra_certs: &[CertificateDer<'static>]) -> ClientConfig {
     let provider = rustls::crypto::CryptoProvider::get_default()
         .cloned()
         .unwrap_or_else(|| Arc::new(rustls::crypto::ring::default_provider()));
 
+    let mut root_cert_store = create_default_root_cert_store();
+    for cert in extra_certs {
+        if let Err(err) = root_cert_store.add(cert.clone()) {
+            warn!(?err, "Skipping an invalid certificate of network.caBundle");
+        }
+    }
+
     ClientConfig::builder_with_provider(provider)
         .with_protocol_versions(rustls::DEFAULT_VERSIONS)
         .expect("Failed to set supported TLS versions")
-        .with_root_certificates(create_default_root_cert_store())
+        .with_root_certificates(root_cert_store)
         .with_no_client_auth()
 }
 
@@ -83,6 +194,31 @@ mod tests {
         new_client().unwrap();
     }
 
+    #[test]
+    fn test_network_config() {
+        let config = network_config(Some("http://proxy:8080"), &["localhost".to_string()], None).unwrap();
+        assert!(config.proxy.is_some());
+        assert_eq!(
+            config.proxy_server,
+            Some(("http://proxy:8080".to_string(), vec!["localhost".to_string()]))
+        );
+        assert!(config.ca_certs.is_empty());
+        assert!(network_config(None, &[], None).unwrap().proxy.is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.pem");
+        assert!(matches!(
+            network_config(None, &[], Some(missing)),
+            Err(RequestError::CaBundle(..))
+        ));
+        let empty = dir.path().join("empty.pem");
+        std::fs::write(&empty, "not a certificate").unwrap();
+        assert!(matches!(
+            network_config(None, &[], Some(empty)),
+            Err(RequestError::CaBundle(..))
+        ));
+    }
+
     #[tokio::test]
     async fn request_test() {
         let mut server = mockito::Server::new_async().await;
@@ -21,12 +21,17 @@ use crate::telemetry::definitions::metrics::{
     CodewhispererterminalUserLoggedIn,
 };
 use crate::telemetry::definitions::types::{
+    CodewhispererterminalCachedInputTokens,
     CodewhispererterminalCustomToolInputTokenSize,
     CodewhispererterminalCustomToolLatency,
     CodewhispererterminalCustomToolOutputTokenSize,
     CodewhispererterminalInCloudshell,
+    CodewhispererterminalInputTokens,
     CodewhispererterminalIsToolValid,
     CodewhispererterminalMcpServerInitFailureReason,
+    CodewhispererterminalResponseStreamDuration,
+    CodewhispererterminalTimeToFirstChunk,
+    CodewhispererterminalToolExecutionDuration,
     CodewhispererterminalToolName,
     CodewhispererterminalToolUseId,
     CodewhispererterminalToolUseIsSuccess,
@@ -116,6 +121,11 @@ impl Event {
             EventType::ChatAddedMessage {
                 conversation_id,
                 context_file_length,
+                request_id,
+                time_to_first_chunk_ms,
+                stream_duration_ms,
+                input_tokens,
+                cached_input_tokens,
                 ..
             } => Some(
                 CodewhispererterminalAddChatMessage {
@@ -125,6 +135,15 @@ impl Event {
                     credential_start_url: self.credential_start_url.map(Into::into),
                     codewhispererterminal_in_cloudshell: in_cloudshell(),
                     codewhispererterminal_context_file_length: context_file_length.map(|l| l as i64).map(Into::into),
+                    request_id: request_id.map(Into::into),
+                    codewhispererterminal_time_to_first_chunk: time_to_first_chunk_ms
+                        .map(|ms| CodewhispererterminalTimeToFirstChunk(ms as i64)),
+                    codewhispererterminal_response_stream_duration: stream_duration_ms
+                        .map(|ms| CodewhispererterminalResponseStreamDuration(ms as i64)),
+                    codewhispererterminal_input_tokens: input_tokens
+                        .map(|t| CodewhispererterminalInputTokens(t as i64)),
+                    codewhispererterminal_cached_input_tokens: cached_input_tokens
+                        .map(|t| CodewhispererterminalCachedInputTokens(t as i64)),
                 }
                 .into_metric_datum(),
             ),
@@ -141,6 +160,7 @@ impl Event {
                 input_token_size,
                 output_token_size,
                 custom_tool_call_latency,
+                execution_duration_ms,
             } => Some(
                 CodewhispererterminalToolUseSuggested {
                     create_time: self.created_time,
@@ -161,6 +181,8 @@ impl Event {
                         .map(|s| CodewhispererterminalCustomToolOutputTokenSize(s as i64)),
                     codewhispererterminal_custom_tool_latency: custom_tool_call_latency
                         .map(|l| CodewhispererterminalCustomToolLatency(l as i64)),
+                    codewhispererterminal_tool_execution_duration: execution_duration_ms
+                        .map(|ms| CodewhispererterminalToolExecutionDuration(ms as i64)),
                 }
                 .into_metric_datum(),
             ),
@@ -246,6 +268,11 @@ pub enum EventType {
         conversation_id: String,
         message_id: String,
         context_file_length: Option<usize>,
+        request_id: Option<String>,
+        time_to_first_chunk_ms: Option<u64>,
+        stream_duration_ms: Option<u64>,
+        input_tokens: Option<u64>,
+        cached_input_tokens: Option<u64>,
     },
     ToolUseSuggested {
         conversation_id: String,
@@ -260,6 +287,7 @@ pub enum EventType {
         input_token_size: Option<usize>,
         output_token_size: Option<usize>,
         custom_tool_call_latency: Option<usize>,
+        execution_duration_ms: Option<u64>,
     },
     McpServerInit {
         conversation_id: String,
@@ -295,6 +323,8 @@ pub struct ToolUseEventBuilder {
     pub input_token_size: Option<usize>,
     pub output_token_size: Option<usize>,
     pub custom_tool_call_latency: Option<usize>,
+    /// Milliseconds spent executing the tool, for any tool.
+    pub execution_duration_ms: Option<u64>,
 }
 
 impl ToolUseEventBuilder {
@@ -312,6 +342,7 @@ impl ToolUseEventBuilder {
             input_token_size: None,
             output_token_size: None,
             custom_tool_call_latency: None,
+            execution_duration_ms: None,
         }
     }
 
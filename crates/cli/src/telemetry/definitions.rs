@@ -24,6 +24,11 @@ mod tests {
             value: None,
             credential_start_url: Some("https://example.com".to_owned().into()),
             codewhispererterminal_in_cloudshell: Some(false.into()),
+            request_id: None,
+            codewhispererterminal_time_to_first_chunk: None,
+            codewhispererterminal_response_stream_duration: None,
+            codewhispererterminal_input_tokens: None,
+            codewhispererterminal_cached_input_tokens: None,
         });
 
         let s = serde_json::to_string_pretty(&metric_datum_init).unwrap();
@@ -6,6 +6,7 @@ mod install_method;
 
 use core::ToolUseEventBuilder;
 use std::str::FromStr;
+use std::time::Duration;
 
 use amzn_codewhisperer_client::types::{
     ChatAddMessageEvent,
@@ -48,6 +49,7 @@ use uuid::{
 };
 
 use crate::api_client::Client as CodewhispererClient;
+use crate::api_client::model::TokenUsage;
 use crate::aws_common::app_name;
 use crate::cli::CliRootCommands;
 use crate::database::settings::Setting;
@@ -162,7 +164,7 @@ impl TelemetryThread {
     pub async fn finish(self) -> Result<(), TelemetryError> {
         drop(self.tx);
         if let Some(handle) = self.handle {
-            match tokio::time::timeout(std::time::Duration::from_millis(1000), handle).await {
+            match tokio::time::timeout(Duration::from_millis(1000), handle).await {
                 Ok(result) => {
                     if let Err(e) = result {
                         return Err(TelemetryError::Join(e));
@@ -193,16 +195,26 @@ impl TelemetryThread {
             .send(Event::new(EventType::CliSubcommandExecuted { subcommand }))?)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn send_chat_added_message(
         &self,
         conversation_id: String,
         message_id: String,
         context_file_length: Option<usize>,
+        request_id: Option<String>,
+        time_to_first_chunk: Option<Duration>,
+        stream_duration: Option<Duration>,
+        usage: Option<TokenUsage>,
     ) -> Result<(), TelemetryError> {
         Ok(self.tx.send(Event::new(EventType::ChatAddedMessage {
             conversation_id,
             message_id,
             context_file_length,
+            request_id,
+            time_to_first_chunk_ms: time_to_first_chunk.map(|d| d.as_millis() as u64),
+            stream_duration_ms: stream_duration.map(|d| d.as_millis() as u64),
+            input_tokens: usage.map(|u| u.input_tokens),
+            cached_input_tokens: usage.map(|u| u.cached_input_tokens),
         }))?)
     }
 
@@ -220,6 +232,7 @@ impl TelemetryThread {
             input_token_size: event.input_token_size,
             output_token_size: event.output_token_size,
             custom_tool_call_latency: event.custom_tool_call_latency,
+            execution_duration_ms: event.execution_duration_ms,
         }))?)
     }
 
@@ -281,7 +294,7 @@ impl TelemetryClient {
     async fn new(env: &Env, database: &mut Database) -> Result<Self, TelemetryError> {
         let telemetry_enabled = !cfg!(test)
             && env.get_os("Q_DISABLE_TELEMETRY").is_none()
-            && database.settings.get_bool(Setting::TelemetryEnabled).unwrap_or(true);
+            && database.settings.get_bool_or_default(Setting::TelemetryEnabled);
 
         // If telemetry is disabled we do not emit using toolkit_telemetry
         let toolkit_telemetry_client = match telemetry_enabled {
@@ -495,7 +508,20 @@ mod test {
             .send_cli_subcommand_executed(Some(&CliRootCommands::Version { changelog: None }))
             .ok();
         thread
-            .send_chat_added_message("version".to_owned(), "version".to_owned(), Some(123))
+            .send_chat_added_message(
+                "version".to_owned(),
+                "version".to_owned(),
+                Some(123),
+                Some("request".to_owned()),
+                Some(Duration::from_millis(800)),
+                Some(Duration::from_secs(3)),
+                Some(TokenUsage {
+                    input_tokens: 2048,
+                    cached_input_tokens: 1920,
+                    cache_write_tokens: 0,
+                    output_tokens: 12,
+                }),
+            )
             .ok();
 
         drop(thread);
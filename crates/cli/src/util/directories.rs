@@ -123,6 +123,53 @@ pub fn chat_profiles_dir(ctx: &Context) -> Result<PathBuf> {
     Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("profiles"))
 }
 
+/// The directory where large tool results are saved once they are compressed out of the chat
+/// history, so that the model can read them again.
+pub fn chat_tool_results_dir(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("tool_results"))
+}
+
+/// The directory of the journals of running chat sessions, left behind by the ones that were
+/// interrupted for `q chat --recover`.
+pub fn chat_journal_dir(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("journal"))
+}
+
+/// The directory where web_browse caches the pages it fetched.
+pub fn chat_web_cache_dir(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("web_cache"))
+}
+
+/// The tool policy applied when chat.policyFile isn't set.
+pub fn chat_policy_path(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("policy.yaml"))
+}
+
+/// The directory of the manifests of plugin tools available in every workspace.
+pub fn chat_plugins_dir(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("plugins"))
+}
+
+/// The hash-chained log of tool invocations, written when chat.auditLog is enabled.
+pub fn audit_log_path(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("audit.jsonl"))
+}
+
+/// The tokens and estimated cost of chat requests, written when chat.usageLedger is enabled.
+pub fn usage_ledger_path(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("usage.jsonl"))
+}
+
+/// The directory of the prompt snippets available in every workspace.
+pub fn chat_snippets_dir(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("snippets"))
+}
+
+/// The directory where `q schedule run` writes the output of scheduled tasks by default.
+pub fn schedule_outputs_dir(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("schedules"))
+}
+
 /// The path to the fig settings file
 pub fn settings_path() -> Result<PathBuf> {
     Ok(fig_data_dir()?.join("settings.json"))